@@ -0,0 +1,247 @@
+use std::future::Future;
+use std::time::Duration;
+
+use graph::blockchain::BlockPtr;
+use graph::futures03::stream::BoxStream;
+use graph::futures03::StreamExt as _;
+use graph::prelude::web3::{transports::WebSocket, Web3};
+use graph::prelude::{anyhow::anyhow, info, tokio, warn, Error, Logger};
+use graph::tokio::sync::watch;
+use graph::util::backoff::ExponentialBackoff;
+
+/// The heads that a subscription delivers
+type Heads = BoxStream<'static, Result<BlockPtr, Error>>;
+
+/// Tracks the chain head with an `eth_subscribe("newHeads")` subscription
+/// over a WebSocket. The subscription is reestablished with exponential
+/// backoff whenever it fails or stops delivering headers; while it is down,
+/// the ingestor falls back to polling
+pub(crate) struct HeadSubscription {
+    /// The latest head we heard about, or `None` if the subscription is
+    /// currently not working
+    latest: watch::Receiver<Option<BlockPtr>>,
+}
+
+impl HeadSubscription {
+    pub fn start(logger: Logger, url: String, timeout: Duration) -> Self {
+        Self::start_with(logger, timeout, move || new_heads(url.clone()))
+    }
+
+    /// Start a subscription that gets its heads by calling `connect`
+    /// whenever it needs to (re)connect
+    pub(crate) fn start_with<C, F>(logger: Logger, timeout: Duration, connect: C) -> Self
+    where
+        C: Fn() -> F + Send + Sync + 'static,
+        F: Future<Output = Result<Heads, Error>> + Send,
+    {
+        let (sender, latest) = watch::channel(None);
+        graph::spawn(Self::run(logger, connect, timeout, sender));
+        HeadSubscription { latest }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.latest.borrow().is_some()
+    }
+
+    /// Wait for the subscription to deliver a head that we have not seen
+    /// yet, but not longer than `timeout`. Return `None` if there was no
+    /// new head in that time or if the subscription stopped working
+    pub async fn next(&mut self, timeout: Duration) -> Option<BlockPtr> {
+        match tokio::time::timeout(timeout, self.latest.changed()).await {
+            Ok(Ok(())) => self.latest.borrow_and_update().clone(),
+            Ok(Err(_)) | Err(_) => None,
+        }
+    }
+
+    async fn run<C, F>(
+        logger: Logger,
+        connect: C,
+        timeout: Duration,
+        sender: watch::Sender<Option<BlockPtr>>,
+    ) where
+        C: Fn() -> F,
+        F: Future<Output = Result<Heads, Error>>,
+    {
+        let mut backoff =
+            ExponentialBackoff::new(Duration::from_millis(250), Duration::from_secs(30));
+
+        loop {
+            if let Err(e) = Self::subscribe(&logger, &connect, timeout, &sender, &mut backoff).await
+            {
+                warn!(logger, "Subscription to new block headers failed, polling until it is reestablished";
+                      "error" => e.to_string());
+            }
+            // The ingestor is gone, and there is nobody to tell about new heads
+            if sender.send(None).is_err() {
+                return;
+            }
+            backoff.sleep_async().await;
+        }
+    }
+
+    async fn subscribe<C, F>(
+        logger: &Logger,
+        connect: &C,
+        timeout: Duration,
+        sender: &watch::Sender<Option<BlockPtr>>,
+        backoff: &mut ExponentialBackoff,
+    ) -> Result<(), Error>
+    where
+        C: Fn() -> F,
+        F: Future<Output = Result<Heads, Error>>,
+    {
+        let mut heads = connect().await?;
+        info!(logger, "Subscribed to new block headers");
+
+        loop {
+            let ptr = tokio::time::timeout(timeout, heads.next())
+                .await
+                .map_err(|_| anyhow!("no new block header for {}s", timeout.as_secs()))?
+                .ok_or_else(|| anyhow!("the subscription was closed"))??;
+            backoff.reset();
+            if sender.send(Some(ptr)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Subscribe to new block headers from the node at `url`. Headers for
+/// pending blocks, which have no hash or number, are skipped
+async fn new_heads(url: String) -> Result<Heads, Error> {
+    let web3 = Web3::new(WebSocket::new(&url).await?);
+    let heads = web3.eth_subscribe().subscribe_new_heads().await?;
+    let heads = heads.filter_map(|header| async move {
+        match header {
+            Ok(header) => match (header.hash, header.number) {
+                (Some(hash), Some(number)) => Some(Ok(BlockPtr::from((hash, number.as_u64())))),
+                _ => None,
+            },
+            Err(e) => Some(Err(e.into())),
+        }
+    });
+    Ok(heads.boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use graph::futures03::future::{self, Ready};
+    use graph::futures03::{stream, StreamExt as _};
+    use graph::prelude::web3::types::H256;
+    use graph::tokio;
+
+    use super::*;
+
+    /// What happens when the subscription connects
+    enum Session {
+        /// Connecting fails
+        Fail,
+        /// The subscription delivers these heads and then closes
+        Close(Vec<u64>),
+        /// The subscription delivers these heads and then goes quiet
+        Stall(Vec<u64>),
+    }
+
+    fn ptr(number: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(number), number))
+    }
+
+    /// Hand out `sessions` one after the other on each connection
+    /// attempt; once they are used up, connecting fails. Also return a
+    /// counter for the connection attempts
+    fn connector(
+        sessions: Vec<Session>,
+    ) -> (
+        impl Fn() -> Ready<Result<Heads, Error>> + Send + Sync + 'static,
+        Arc<AtomicUsize>,
+    ) {
+        let sessions = Mutex::new(VecDeque::from(sessions));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let connect = move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let heads = |numbers: Vec<u64>| stream::iter(numbers.into_iter().map(|n| Ok(ptr(n))));
+            let res = match sessions.lock().unwrap().pop_front() {
+                Some(Session::Close(numbers)) => Ok(heads(numbers).boxed()),
+                Some(Session::Stall(numbers)) => {
+                    Ok(heads(numbers).chain(stream::pending()).boxed())
+                }
+                Some(Session::Fail) | None => Err(anyhow!("connection refused")),
+            };
+            future::ready(res)
+        };
+        (connect, attempts)
+    }
+
+    /// Wait for `cond` to become true, giving up after 60s
+    async fn eventually(mut cond: impl FnMut() -> bool) {
+        for _ in 0..6000 {
+            if cond() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition did not become true in time");
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test(start_paused = true)]
+    async fn delivers_new_heads() {
+        let (connect, attempts) = connector(vec![Session::Stall(vec![1, 2])]);
+        let mut subscription =
+            HeadSubscription::start_with(graph::log::discard(), TIMEOUT, connect);
+
+        eventually(|| *subscription.latest.borrow() == Some(ptr(2))).await;
+        assert!(subscription.is_connected());
+        assert_eq!(Some(ptr(2)), subscription.next(TIMEOUT).await);
+        // We've seen the latest head, and no new one arrives
+        assert_eq!(None, subscription.next(Duration::from_secs(1)).await);
+        assert!(subscription.is_connected());
+        assert_eq!(1, attempts.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnects_after_failure() {
+        let (connect, attempts) = connector(vec![
+            Session::Close(vec![1]),
+            Session::Fail,
+            Session::Stall(vec![2]),
+        ]);
+        let subscription = HeadSubscription::start_with(graph::log::discard(), TIMEOUT, connect);
+
+        eventually(|| *subscription.latest.borrow() == Some(ptr(2))).await;
+        assert_eq!(3, attempts.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_subscription_falls_back_to_polling() {
+        let (connect, attempts) = connector(vec![Session::Stall(vec![1])]);
+        let mut subscription =
+            HeadSubscription::start_with(graph::log::discard(), TIMEOUT, connect);
+
+        eventually(|| subscription.is_connected()).await;
+        // Without new heads for `TIMEOUT`, the subscription is considered
+        // broken, and the ingestor polls until it is reestablished
+        assert_eq!(Some(ptr(1)), subscription.next(TIMEOUT).await);
+        assert_eq!(None, subscription.next(2 * TIMEOUT).await);
+        assert!(!subscription.is_connected());
+        eventually(|| attempts.load(Ordering::SeqCst) >= 3).await;
+        assert!(!subscription.is_connected());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn never_connected() {
+        let (connect, attempts) = connector(vec![]);
+        let mut subscription =
+            HeadSubscription::start_with(graph::log::discard(), TIMEOUT, connect);
+
+        eventually(|| attempts.load(Ordering::SeqCst) >= 2).await;
+        assert!(!subscription.is_connected());
+        assert_eq!(None, subscription.next(TIMEOUT).await);
+    }
+}
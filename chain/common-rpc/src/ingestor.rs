@@ -0,0 +1,780 @@
+use std::{sync::Arc, time::Duration};
+
+use graph::blockchain::{BlockHash, BlockIngestor, BlockPtr, BlockchainKind, IngestorError};
+use graph::cheap_clone::CheapClone;
+use graph::components::adapter::ChainId;
+use graph::prelude::{
+    async_trait, error, info, tokio, trace, warn, BlockNumber, ChainStore, LogCode, Logger,
+};
+use graph::slog::o;
+use graph::util::backoff::ExponentialBackoff;
+
+use crate::head::HeadSubscription;
+use crate::{RpcAdapter, RpcAdapterSelector};
+
+/// Settings for the `PollingBlockIngestor`
+#[derive(Clone, Debug)]
+pub struct IngestorConfig {
+    /// How many blocks below the chain head to keep in the block cache
+    pub ancestor_count: BlockNumber,
+    /// How often to look for a new chain head when there is no working
+    /// head subscription
+    pub polling_interval: Duration,
+    /// How long to wait for a new head from the head subscription before
+    /// polling anyway
+    pub new_heads_timeout: Duration,
+    /// Whether to remove blocks that are more than `ancestor_count` blocks
+    /// below the chain head from the block cache
+    pub cleanup_blocks: bool,
+}
+
+/// A block ingestor that keeps the chain store up to date by polling a
+/// JSON-RPC provider for the chain head, or by listening for new heads if
+/// the provider supports subscriptions
+pub struct PollingBlockIngestor<S: RpcAdapterSelector> {
+    logger: Logger,
+    config: IngestorConfig,
+    adapters: S,
+    chain_store: Arc<dyn ChainStore>,
+    network_name: ChainId,
+    kind: BlockchainKind,
+}
+
+impl<S: RpcAdapterSelector> PollingBlockIngestor<S> {
+    pub fn new(
+        logger: Logger,
+        config: IngestorConfig,
+        adapters: S,
+        chain_store: Arc<dyn ChainStore>,
+        network_name: ChainId,
+        kind: BlockchainKind,
+    ) -> Self {
+        PollingBlockIngestor {
+            logger,
+            config,
+            adapters,
+            chain_store,
+            network_name,
+            kind,
+        }
+    }
+
+    fn cleanup_cached_blocks(&self) {
+        match self
+            .chain_store
+            .cleanup_cached_blocks(self.config.ancestor_count)
+        {
+            Ok(Some((min_block, count))) => {
+                if count > 0 {
+                    info!(
+                        self.logger,
+                        "Cleaned {} blocks from the block cache. \
+                                 Only blocks with number greater than {} remain",
+                        count,
+                        min_block
+                    );
+                }
+            }
+            Ok(None) => { /* nothing was cleaned, ignore */ }
+            Err(e) => warn!(
+                self.logger,
+                "Failed to clean blocks from block cache: {}", e
+            ),
+        }
+    }
+
+    async fn do_poll(
+        &self,
+        logger: &Logger,
+        adapter: &S::Adapter,
+        latest_block: Option<BlockPtr>,
+    ) -> Result<(), IngestorError> {
+        trace!(&logger, "BlockIngestor::do_poll");
+
+        // Get chain head ptr from store
+        let head_block_ptr_opt = self.chain_store.cheap_clone().chain_head_ptr().await?;
+
+        // If the head subscription told us about the latest block, use that. Otherwise, to
+        // check if there is a new block or not, fetch only the block header since that's cheaper
+        // than the full block. This is worthwhile because most of the time there won't be a new
+        // block, as we expect the poll interval to be much shorter than the block time.
+        let latest_block = match latest_block {
+            Some(latest_block) => latest_block,
+            None => adapter.latest_block_ptr(logger).await?,
+        };
+
+        if let Some(head_block) = head_block_ptr_opt.as_ref() {
+            // If latest block matches head block in store, nothing needs to be done
+            if &latest_block == head_block {
+                return Ok(());
+            }
+
+            if latest_block.number < head_block.number {
+                // An ingestor might wait or move forward, but it never
+                // wavers and goes back. More seriously, this keeps us from
+                // later trying to ingest a block with the same number again
+                warn!(&logger,
+                    "Provider went backwards - ignoring this latest block";
+                    "current_block_head" => head_block.number,
+                    "latest_block_head" => latest_block.number);
+                return Ok(());
+            }
+        }
+
+        // Compare latest block with head ptr, alert user if far behind
+        match head_block_ptr_opt {
+            None => {
+                info!(
+                    &logger,
+                    "Downloading latest blocks from {}, this may take a few minutes...",
+                    self.network_name
+                );
+            }
+            Some(head_block_ptr) => {
+                let latest_number = latest_block.number;
+                let head_number = head_block_ptr.number;
+                let distance = latest_number - head_number;
+                let blocks_needed = (distance).min(self.config.ancestor_count);
+                let code = if distance >= 15 {
+                    LogCode::BlockIngestionLagging
+                } else {
+                    LogCode::BlockIngestionStatus
+                };
+                if distance > 0 {
+                    info!(
+                    &logger,
+                        "Syncing {} blocks from {}",
+                        blocks_needed,
+                        self.network_name;
+                        "current_block_head" => head_number,
+                        "latest_block_head" => latest_number,
+                        "blocks_behind" => distance,
+                        "blocks_needed" => blocks_needed,
+                        "code" => code,
+                    );
+                }
+            }
+        }
+
+        // Store latest block in block store.
+        // Might be a no-op if latest block is one that we have seen.
+        // ingest_blocks will return a (potentially incomplete) list of blocks that are
+        // missing.
+        let mut missing_block_hash = self
+            .ingest_block(&logger, adapter, &latest_block.hash)
+            .await?;
+
+        // Repeatedly fetch missing parent blocks, and ingest them.
+        // ingest_blocks will continue to tell us about more missing parent
+        // blocks until we have filled in all missing pieces of the
+        // blockchain in the block number range we care about.
+        //
+        // Loop will terminate because:
+        // - The number of blocks in the ChainStore in the block number
+        //   range [latest - ancestor_count, latest] is finite.
+        // - The missing parents in the first iteration have at most block
+        //   number latest-1.
+        // - Each iteration loads parents of all blocks in the range whose
+        //   parent blocks are not already in the ChainStore, so blocks
+        //   with missing parents in one iteration will not have missing
+        //   parents in the next.
+        // - Therefore, if the missing parents in one iteration have at
+        //   most block number N, then the missing parents in the next
+        //   iteration will have at most block number N-1.
+        // - Therefore, the loop will iterate at most ancestor_count times.
+        while let Some(hash) = missing_block_hash {
+            missing_block_hash = self.ingest_block(&logger, adapter, &hash).await?;
+        }
+        Ok(())
+    }
+
+    async fn ingest_block(
+        &self,
+        logger: &Logger,
+        adapter: &S::Adapter,
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockHash>, IngestorError> {
+        let block = adapter.block_by_hash(logger, block_hash).await?;
+
+        // Store it in the database and try to advance the chain head pointer
+        self.chain_store.upsert_block(block).await?;
+
+        self.chain_store
+            .cheap_clone()
+            .attempt_chain_head_update(self.config.ancestor_count)
+            .await
+            .map(|missing| missing.map(|h256| h256.into()))
+            .map_err(|e| {
+                error!(logger, "failed to update chain head");
+                IngestorError::Unknown(e)
+            })
+    }
+
+    /// Remember the latest finalized block in the store if the provider
+    /// reports a newer one than `finalized`. Return `false` if the provider
+    /// does not know about finalized blocks so that we stop asking; block
+    /// streams then rely on the reorg threshold alone
+    async fn update_finalized_block(
+        &self,
+        logger: &Logger,
+        adapter: &S::Adapter,
+        finalized: &mut Option<BlockPtr>,
+    ) -> bool {
+        match adapter.latest_finalized_block(logger).await {
+            Ok(Some(ptr)) => {
+                if finalized.as_ref() != Some(&ptr) {
+                    match self
+                        .chain_store
+                        .cheap_clone()
+                        .set_finalized_block(ptr.clone())
+                        .await
+                    {
+                        Ok(()) => *finalized = Some(ptr),
+                        Err(e) => warn!(logger, "Failed to store finalized block";
+                                        "block" => ptr.to_string(),
+                                        "error" => e.to_string()),
+                    }
+                }
+                true
+            }
+            Ok(None) => {
+                info!(
+                    logger,
+                    "Provider does not report finalized blocks, relying on the reorg threshold"
+                );
+                false
+            }
+            Err(e) => {
+                warn!(logger, "Failed to get finalized block"; "error" => e.to_string());
+                true
+            }
+        }
+    }
+
+    /// Subscribe to new block headers if the provider we use for ingesting
+    /// blocks supports that
+    async fn head_subscription(&self) -> Option<HeadSubscription> {
+        let adapter = self.adapters.cheapest().await.ok()?;
+        let url = adapter.subscription_url()?;
+        let logger = self
+            .logger
+            .new(o!("provider" => adapter.provider().to_string(),
+                                        "component" => "HeadSubscription"));
+        Some(HeadSubscription::start(
+            logger,
+            url.to_string(),
+            self.config.new_heads_timeout,
+        ))
+    }
+
+    /// Wait until it is time to look for a new chain head. If the head
+    /// subscription works, wait for it to deliver a new head, but not longer
+    /// than `new_heads_timeout`, and return that head. Otherwise, wait for
+    /// the polling interval
+    async fn next_head(&self, subscription: &mut Option<HeadSubscription>) -> Option<BlockPtr> {
+        match subscription {
+            Some(subscription) if subscription.is_connected() => {
+                subscription.next(self.config.new_heads_timeout).await
+            }
+            _ => {
+                tokio::time::sleep(self.config.polling_interval).await;
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RpcAdapterSelector> BlockIngestor for PollingBlockIngestor<S> {
+    async fn run(self: Box<Self>) {
+        let mut backoff =
+            ExponentialBackoff::new(Duration::from_millis(250), Duration::from_secs(30));
+        let mut subscription = self.head_subscription().await;
+        let mut latest_block = None;
+        let mut track_finality = true;
+        let mut finalized = None;
+
+        loop {
+            let adapter = match self.adapters.cheapest().await {
+                Ok(adapter) => {
+                    backoff.reset();
+                    adapter
+                }
+                Err(err) => {
+                    error!(
+                        &self.logger,
+                        "unable to get an adapter, backing off... error: {}",
+                        err.to_string()
+                    );
+                    backoff.sleep_async().await;
+                    continue;
+                }
+            };
+            let logger = self
+                .logger
+                .new(o!("provider" => adapter.provider().to_string()));
+
+            match self
+                .do_poll(&logger, adapter.as_ref(), latest_block.take())
+                .await
+            {
+                // Some polls will fail due to transient issues
+                Err(err) => {
+                    error!(logger, "Trying again after block polling failed: {}", err);
+                }
+                Ok(()) => (),
+            }
+
+            if track_finality {
+                track_finality = self
+                    .update_finalized_block(&logger, adapter.as_ref(), &mut finalized)
+                    .await;
+            }
+
+            if self.config.cleanup_blocks {
+                self.cleanup_cached_blocks()
+            }
+
+            latest_block = self.next_head(&mut subscription).await;
+        }
+    }
+
+    fn network_name(&self) -> ChainId {
+        self.network_name.clone()
+    }
+
+    fn kind(&self) -> BlockchainKind {
+        self.kind
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use graph::blockchain::polling_block_stream::StreamCheckpoint;
+    use graph::blockchain::{Block, BlockTime, ChainIdentifier};
+    use graph::components::transaction_receipt::LightTransactionReceipt;
+    use graph::futures03::{future, stream, StreamExt as _};
+    use graph::prelude::web3::types::H256;
+    use graph::prelude::{anyhow::anyhow, serde_json, DeploymentHash, Error, StoreError};
+
+    use super::*;
+
+    fn hash(number: BlockNumber) -> BlockHash {
+        H256::from_low_u64_be(number as u64 + 1).into()
+    }
+
+    fn ptr(number: BlockNumber) -> BlockPtr {
+        BlockPtr::new(hash(number), number)
+    }
+
+    struct TestBlock(BlockNumber);
+
+    impl Block for TestBlock {
+        fn ptr(&self) -> BlockPtr {
+            ptr(self.0)
+        }
+
+        fn parent_ptr(&self) -> Option<BlockPtr> {
+            (self.0 > 0).then(|| ptr(self.0 - 1))
+        }
+
+        fn timestamp(&self) -> BlockTime {
+            BlockTime::NONE
+        }
+    }
+
+    /// A provider for a chain where block `n` has hash `hash(n)`
+    #[derive(Default)]
+    struct TestAdapter {
+        latest: Mutex<BlockNumber>,
+        finalized: Option<BlockPtr>,
+        /// How often the ingestor asked for the latest block
+        latest_calls: AtomicUsize,
+        /// The blocks the ingestor fetched, in order
+        fetched: Mutex<Vec<BlockNumber>>,
+    }
+
+    impl TestAdapter {
+        fn at(latest: BlockNumber) -> Self {
+            TestAdapter {
+                latest: Mutex::new(latest),
+                ..Default::default()
+            }
+        }
+
+        fn fetched(&self) -> Vec<BlockNumber> {
+            std::mem::take(&mut *self.fetched.lock().unwrap())
+        }
+    }
+
+    #[async_trait]
+    impl RpcAdapter for TestAdapter {
+        fn provider(&self) -> &str {
+            "test"
+        }
+
+        fn subscription_url(&self) -> Option<&str> {
+            None
+        }
+
+        async fn latest_block_ptr(&self, _: &Logger) -> Result<BlockPtr, IngestorError> {
+            self.latest_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(ptr(*self.latest.lock().unwrap()))
+        }
+
+        async fn block_by_hash(
+            &self,
+            _: &Logger,
+            block_hash: &BlockHash,
+        ) -> Result<Arc<dyn Block>, IngestorError> {
+            let latest = *self.latest.lock().unwrap();
+            match (0..=latest).find(|number| &hash(*number) == block_hash) {
+                Some(number) => {
+                    self.fetched.lock().unwrap().push(number);
+                    Ok(Arc::new(TestBlock(number)))
+                }
+                None => Err(IngestorError::BlockUnavailable(H256::from_slice(
+                    block_hash.as_slice(),
+                ))),
+            }
+        }
+
+        async fn latest_finalized_block(&self, _: &Logger) -> Result<Option<BlockPtr>, Error> {
+            Ok(self.finalized.clone())
+        }
+    }
+
+    struct TestSelector(Arc<TestAdapter>);
+
+    #[async_trait]
+    impl RpcAdapterSelector for TestSelector {
+        type Adapter = TestAdapter;
+
+        async fn cheapest(&self) -> Result<Arc<TestAdapter>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    /// A chain store that only keeps track of the blocks it was given, the
+    /// chain head and the finalized block
+    #[derive(Default)]
+    struct TestStore {
+        /// Maps each block to its parent
+        blocks: Mutex<HashMap<BlockHash, (BlockNumber, Option<BlockHash>)>>,
+        head: Mutex<Option<BlockPtr>>,
+        finalized: Mutex<Option<BlockPtr>>,
+    }
+
+    #[async_trait]
+    impl ChainStore for TestStore {
+        fn genesis_block_ptr(&self) -> Result<BlockPtr, Error> {
+            Ok(ptr(0))
+        }
+
+        async fn upsert_block(&self, block: Arc<dyn Block>) -> Result<(), Error> {
+            self.blocks
+                .lock()
+                .unwrap()
+                .insert(block.hash(), (block.number(), block.parent_hash()));
+            Ok(())
+        }
+
+        fn upsert_light_blocks(&self, _: &[&dyn Block]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        /// Move the head to the highest block once its `ancestor_count`
+        /// ancestors are all present, and otherwise return the first
+        /// missing one
+        async fn attempt_chain_head_update(
+            self: Arc<Self>,
+            ancestor_count: BlockNumber,
+        ) -> Result<Option<H256>, Error> {
+            let blocks = self.blocks.lock().unwrap();
+            let mut head = self.head.lock().unwrap();
+            let (candidate, (number, _)) = match blocks.iter().max_by_key(|(_, (n, _))| *n) {
+                Some(candidate) => candidate,
+                None => return Ok(None),
+            };
+            if let Some(head) = head.as_ref() {
+                if head.number >= *number {
+                    return Ok(None);
+                }
+            }
+            let mut block = candidate;
+            for _ in 0..ancestor_count {
+                match &blocks[block].1 {
+                    Some(parent) if blocks.contains_key(parent) => block = parent,
+                    Some(parent) => return Ok(Some(H256::from_slice(parent.as_slice()))),
+                    None => break,
+                }
+            }
+            *head = Some(BlockPtr::new(candidate.clone(), *number));
+            Ok(None)
+        }
+
+        async fn chain_head_ptr(self: Arc<Self>) -> Result<Option<BlockPtr>, Error> {
+            Ok(self.head.lock().unwrap().clone())
+        }
+
+        fn chain_head_cursor(&self) -> Result<Option<String>, Error> {
+            unimplemented!()
+        }
+
+        async fn set_chain_head(
+            self: Arc<Self>,
+            _: Arc<dyn Block>,
+            _: String,
+        ) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        async fn finalized_block_ptr(self: Arc<Self>) -> Result<Option<BlockPtr>, Error> {
+            Ok(self.finalized.lock().unwrap().clone())
+        }
+
+        async fn set_finalized_block(self: Arc<Self>, ptr: BlockPtr) -> Result<(), Error> {
+            *self.finalized.lock().unwrap() = Some(ptr);
+            Ok(())
+        }
+
+        async fn stream_checkpoint(
+            self: Arc<Self>,
+            _: &DeploymentHash,
+        ) -> Result<Option<StreamCheckpoint>, Error> {
+            unimplemented!()
+        }
+
+        async fn set_stream_checkpoint(
+            self: Arc<Self>,
+            _: &DeploymentHash,
+            _: StreamCheckpoint,
+        ) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        async fn blocks(
+            self: Arc<Self>,
+            _: Vec<BlockHash>,
+        ) -> Result<Vec<serde_json::Value>, Error> {
+            unimplemented!()
+        }
+
+        async fn ancestor_block(
+            self: Arc<Self>,
+            _: BlockPtr,
+            _: BlockNumber,
+            _: Option<BlockHash>,
+        ) -> Result<Option<serde_json::Value>, Error> {
+            unimplemented!()
+        }
+
+        fn cleanup_cached_blocks(
+            &self,
+            _: BlockNumber,
+        ) -> Result<Option<(BlockNumber, usize)>, Error> {
+            Ok(None)
+        }
+
+        fn block_hashes_by_block_number(&self, _: BlockNumber) -> Result<Vec<BlockHash>, Error> {
+            unimplemented!()
+        }
+
+        fn confirm_block_hash(&self, _: BlockNumber, _: &BlockHash) -> Result<usize, Error> {
+            unimplemented!()
+        }
+
+        async fn block_number(
+            &self,
+            _: &BlockHash,
+        ) -> Result<Option<(String, BlockNumber, Option<u64>, Option<BlockHash>)>, StoreError>
+        {
+            unimplemented!()
+        }
+
+        async fn block_numbers(
+            &self,
+            _: Vec<BlockHash>,
+        ) -> Result<HashMap<BlockHash, BlockNumber>, StoreError> {
+            unimplemented!()
+        }
+
+        async fn transaction_receipts_in_block(
+            &self,
+            _: &H256,
+        ) -> Result<Vec<LightTransactionReceipt>, StoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_call_cache(&self, _: BlockNumber, _: BlockNumber) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn chain_identifier(&self) -> Result<ChainIdentifier, Error> {
+            unimplemented!()
+        }
+
+        fn set_chain_identifier(&self, _: &ChainIdentifier) -> Result<(), Error> {
+            unimplemented!()
+        }
+    }
+
+    const POLLING_INTERVAL: Duration = Duration::from_secs(1);
+
+    fn ingestor(
+        adapter: Arc<TestAdapter>,
+        store: Arc<TestStore>,
+    ) -> PollingBlockIngestor<TestSelector> {
+        let config = IngestorConfig {
+            ancestor_count: 3,
+            polling_interval: POLLING_INTERVAL,
+            new_heads_timeout: Duration::from_secs(30),
+            cleanup_blocks: false,
+        };
+        PollingBlockIngestor::new(
+            graph::log::discard(),
+            config,
+            TestSelector(adapter),
+            store,
+            "test".into(),
+            BlockchainKind::Ethereum,
+        )
+    }
+
+    async fn poll(
+        ingestor: &PollingBlockIngestor<TestSelector>,
+        adapter: &TestAdapter,
+        latest_block: Option<BlockPtr>,
+    ) {
+        ingestor
+            .do_poll(&ingestor.logger, adapter, latest_block)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn poll_fills_in_ancestors() {
+        let adapter = Arc::new(TestAdapter::at(5));
+        let store = Arc::new(TestStore::default());
+        let ingestor = ingestor(adapter.clone(), store.clone());
+
+        // Starting from an empty store, we fetch the latest block and then
+        // `ancestor_count` parents before the head moves
+        poll(&ingestor, &adapter, None).await;
+        assert_eq!(vec![5, 4, 3, 2], adapter.fetched());
+        assert_eq!(Some(ptr(5)), *store.head.lock().unwrap());
+
+        // Without a new block, nothing happens
+        poll(&ingestor, &adapter, None).await;
+        assert!(adapter.fetched().is_empty());
+
+        // A new block only needs that block
+        *adapter.latest.lock().unwrap() = 6;
+        poll(&ingestor, &adapter, None).await;
+        assert_eq!(vec![6], adapter.fetched());
+        assert_eq!(Some(ptr(6)), *store.head.lock().unwrap());
+        assert_eq!(3, adapter.latest_calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn poll_uses_head_from_subscription() {
+        let adapter = Arc::new(TestAdapter::at(7));
+        let store = Arc::new(TestStore::default());
+        let ingestor = ingestor(adapter.clone(), store.clone());
+
+        poll(&ingestor, &adapter, Some(ptr(7))).await;
+        assert_eq!(Some(ptr(7)), *store.head.lock().unwrap());
+        assert_eq!(0, adapter.latest_calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn poll_ignores_provider_going_backwards() {
+        let adapter = Arc::new(TestAdapter::at(5));
+        let store = Arc::new(TestStore::default());
+        let ingestor = ingestor(adapter.clone(), store.clone());
+
+        poll(&ingestor, &adapter, None).await;
+        adapter.fetched();
+
+        *adapter.latest.lock().unwrap() = 3;
+        poll(&ingestor, &adapter, None).await;
+        assert!(adapter.fetched().is_empty());
+        assert_eq!(Some(ptr(5)), *store.head.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn finalized_block() {
+        let adapter = Arc::new(TestAdapter {
+            finalized: Some(ptr(2)),
+            ..TestAdapter::at(5)
+        });
+        let store = Arc::new(TestStore::default());
+        let ingestor = ingestor(adapter.clone(), store.clone());
+
+        let mut finalized = None;
+        assert!(
+            ingestor
+                .update_finalized_block(&ingestor.logger, &adapter, &mut finalized)
+                .await
+        );
+        assert_eq!(Some(ptr(2)), finalized);
+        assert_eq!(Some(ptr(2)), *store.finalized.lock().unwrap());
+
+        // A provider that does not know about finality makes us stop asking
+        let adapter = TestAdapter::at(5);
+        assert!(
+            !ingestor
+                .update_finalized_block(&ingestor.logger, &adapter, &mut finalized)
+                .await
+        );
+        assert_eq!(Some(ptr(2)), finalized);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn next_head_polls_without_subscription() {
+        let adapter = Arc::new(TestAdapter::at(5));
+        let ingestor = ingestor(adapter, Arc::new(TestStore::default()));
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(None, ingestor.next_head(&mut None).await);
+        assert!(start.elapsed() >= POLLING_INTERVAL);
+
+        // While the subscription can not connect, we poll instead of
+        // waiting for new heads
+        let mut subscription = Some(HeadSubscription::start_with(
+            graph::log::discard(),
+            Duration::from_secs(30),
+            || future::ready(Err(anyhow!("connection refused"))),
+        ));
+        let start = tokio::time::Instant::now();
+        assert_eq!(None, ingestor.next_head(&mut subscription).await);
+        assert!(start.elapsed() >= POLLING_INTERVAL);
+        assert!(start.elapsed() < ingestor.config.new_heads_timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn next_head_from_subscription() {
+        let adapter = Arc::new(TestAdapter::at(5));
+        let ingestor = ingestor(adapter, Arc::new(TestStore::default()));
+
+        let subscription =
+            HeadSubscription::start_with(graph::log::discard(), Duration::from_secs(30), || {
+                let heads = stream::iter([Ok(ptr(6))]).chain(stream::pending());
+                future::ready(Ok(heads.boxed()))
+            });
+        let mut subscription = Some(subscription);
+        while !subscription.as_ref().unwrap().is_connected() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // The head is already there, and we do not wait for the polling
+        // interval
+        let start = tokio::time::Instant::now();
+        assert_eq!(Some(ptr(6)), ingestor.next_head(&mut subscription).await);
+        assert!(start.elapsed() < POLLING_INTERVAL);
+    }
+}
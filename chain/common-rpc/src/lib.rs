@@ -0,0 +1,55 @@
+//! Building blocks for chains whose blocks are read through an
+//! Ethereum-style JSON-RPC API. A chain only needs to implement
+//! [`RpcAdapter`] for its adapter and [`RpcAdapterSelector`] for picking
+//! the adapter to use, and can then use the [`PollingBlockIngestor`] and
+//! its head tracking instead of writing its own.
+//!
+//! Trigger filters, and picking adapters by their capabilities for block
+//! streams and runtime calls, differ too much between chains and remain
+//! the job of each chain crate
+mod head;
+mod ingestor;
+
+use std::sync::Arc;
+
+use graph::blockchain::{Block, BlockHash, BlockPtr, IngestorError};
+use graph::prelude::{async_trait, Error, Logger};
+
+pub use ingestor::{IngestorConfig, PollingBlockIngestor};
+
+/// What the block ingestor needs from a JSON-RPC provider
+#[async_trait]
+pub trait RpcAdapter: Send + Sync + 'static {
+    /// The name of the provider, used for logging
+    fn provider(&self) -> &str;
+
+    /// The WebSocket URL on which the provider supports
+    /// `eth_subscribe("newHeads")`, if it has one
+    fn subscription_url(&self) -> Option<&str>;
+
+    /// The pointer to the latest block. Since this is called on every poll,
+    /// implementations should only fetch the block header
+    async fn latest_block_ptr(&self, logger: &Logger) -> Result<BlockPtr, IngestorError>;
+
+    /// The block with `hash` in the form in which it should be stored in
+    /// the chain store
+    async fn block_by_hash(
+        &self,
+        logger: &Logger,
+        hash: &BlockHash,
+    ) -> Result<Arc<dyn Block>, IngestorError>;
+
+    /// The latest finalized block, or `None` if the provider does not know
+    /// about finality
+    async fn latest_finalized_block(&self, logger: &Logger) -> Result<Option<BlockPtr>, Error>;
+}
+
+/// Picks the adapter that the block ingestor uses. It is asked for an
+/// adapter on every poll so that the ingestor moves to another provider
+/// when the current one becomes unavailable
+#[async_trait]
+pub trait RpcAdapterSelector: Send + Sync + 'static {
+    type Adapter: RpcAdapter;
+
+    async fn cheapest(&self) -> Result<Arc<Self::Adapter>, Error>;
+}
@@ -34,6 +34,7 @@ const COMBINED_FILTER_TYPE_URL: &str =
 
 use crate::capabilities::NodeCapabilities;
 use crate::data_source::{BlockHandlerFilter, DataSource};
+use crate::trigger::{EthereumTrigger, TransferDirection};
 use crate::{Chain, Mapping, ENV_VARS};
 
 pub type EventSignature = H256;
@@ -241,11 +242,12 @@ pub struct TriggerFilter {
     pub(crate) log: EthereumLogFilter,
     pub(crate) call: EthereumCallFilter,
     pub(crate) block: EthereumBlockFilter,
+    pub(crate) transfer: EthereumTransferFilter,
 }
 
 impl TriggerFilter {
     pub(crate) fn requires_traces(&self) -> bool {
-        !self.call.is_empty() || self.block.requires_traces()
+        !self.call.is_empty() || self.block.requires_traces() || !self.transfer.is_empty()
     }
 
     #[cfg(debug_assertions)]
@@ -271,7 +273,9 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
         self.call
             .extend(EthereumCallFilter::from_data_sources(data_sources.clone()));
         self.block
-            .extend(EthereumBlockFilter::from_data_sources(data_sources));
+            .extend(EthereumBlockFilter::from_data_sources(data_sources.clone()));
+        self.transfer
+            .extend(EthereumTransferFilter::from_data_sources(data_sources));
     }
 
     fn node_capabilities(&self) -> NodeCapabilities {
@@ -294,6 +298,9 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
 
             self.block
                 .extend(EthereumBlockFilter::from_mapping(&data_source.mapping));
+
+            self.transfer
+                .extend(EthereumTransferFilter::from_mapping(&data_source.mapping));
         }
     }
 
@@ -309,6 +316,13 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
         // initialization handlers
         let has_initilization_triggers_only = polling_intervals.iter().all(|(_, i)| *i == 0);
 
+        // Firehose can only filter calls by their recipient, but transfer
+        // handlers also need the transfers that are sent by the addresses
+        // they are interested in, and therefore need all blocks
+        if !self.transfer.is_empty() {
+            return Vec::new();
+        }
+
         let log_filters: Vec<LogFilter> = self.log.into();
         let mut call_filters: Vec<CallToFilter> = self.call.into();
         call_filters.extend(Into::<Vec<CallToFilter>>::into(self.block));
@@ -330,6 +344,44 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
             value: combined_filter.encode_to_vec(),
         }]
     }
+
+    fn fingerprint(&self) -> Option<String> {
+        // The filters are made of hash maps and sets whose iteration order
+        // is not stable, so we sort everything before hashing it
+        fn sorted<T: fmt::Debug>(items: impl Iterator<Item = T>) -> Vec<String> {
+            items.map(|item| format!("{:?}", item)).sorted().collect()
+        }
+
+        let log_edges = sorted(self.log.contracts_and_events_graph.all_edges().map(
+            |(a, b, receipt)| {
+                let (a, b) = (format!("{:?}", a), format!("{:?}", b));
+                match a <= b {
+                    true => (a, b, *receipt),
+                    false => (b, a, *receipt),
+                }
+            },
+        ));
+        let calls = sorted(
+            self.call
+                .contract_addresses_function_signatures
+                .iter()
+                .map(|(address, (start, sigs))| (address, start, sorted(sigs.iter()))),
+        );
+
+        let parts = (
+            log_edges,
+            sorted(self.log.wildcard_events.iter()),
+            sorted(self.log.events_with_topic_filters.iter()),
+            calls,
+            sorted(self.call.wildcard_signatures.iter()),
+            sorted(self.block.polling_intervals.iter()),
+            sorted(self.block.contract_addresses.iter()),
+            self.block.trigger_every_block,
+            sorted(self.transfer.addresses.iter()),
+            self.transfer.wildcard,
+        );
+        Some(hex::encode(keccak256(format!("{:?}", parts).as_bytes())))
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -798,6 +850,96 @@ impl FromIterator<(BlockNumber, Address, FunctionSelector)> for EthereumCallFilt
     }
 }
 
+/// Filter for internal calls that transfer ETH to or from the addresses of
+/// data sources with transfer handlers
+#[derive(Clone, Debug, Default)]
+pub struct EthereumTransferFilter {
+    /// The addresses we are interested in, with the first block at which
+    /// we are interested in each of them
+    pub addresses: HashMap<Address, BlockNumber>,
+    /// Set when a template has transfer handlers. Since we do not know the
+    /// addresses of data sources created from it until they are created,
+    /// all transfers match
+    pub wildcard: bool,
+}
+
+impl EthereumTransferFilter {
+    fn matches_address(&self, address: &Address) -> bool {
+        self.wildcard || self.addresses.contains_key(address)
+    }
+
+    /// The triggers for `call` if it is a transfer this filter is
+    /// interested in. There is one trigger for each of the recipient and
+    /// the sender that match
+    pub fn triggers(&self, call: &Arc<EthereumCall>) -> Vec<EthereumTrigger> {
+        if !call.internal_transfer {
+            return vec![];
+        }
+
+        let mut triggers = Vec::new();
+        if self.matches_address(&call.to) {
+            triggers.push(EthereumTrigger::Transfer(
+                call.cheap_clone(),
+                TransferDirection::Incoming,
+            ));
+        }
+        if self.matches_address(&call.from) {
+            triggers.push(EthereumTrigger::Transfer(
+                call.cheap_clone(),
+                TransferDirection::Outgoing,
+            ));
+        }
+        triggers
+    }
+
+    pub fn from_mapping(mapping: &Mapping) -> Self {
+        Self {
+            addresses: HashMap::new(),
+            wildcard: mapping.has_transfer_handler(),
+        }
+    }
+
+    pub fn from_data_sources<'a>(iter: impl IntoIterator<Item = &'a DataSource>) -> Self {
+        let mut filter = Self::default();
+        for data_source in iter {
+            if !data_source.mapping.has_transfer_handler() {
+                continue;
+            }
+            if let Some(address) = data_source.address {
+                let start_block = filter
+                    .addresses
+                    .entry(address)
+                    .or_insert(data_source.start_block);
+                *start_block = cmp::min(*start_block, data_source.start_block);
+            }
+        }
+        filter
+    }
+
+    /// Extends this transfer filter with another one.
+    pub fn extend(&mut self, other: EthereumTransferFilter) {
+        let EthereumTransferFilter {
+            addresses,
+            wildcard,
+        } = other;
+
+        for (address, start_block) in addresses {
+            let existing = self.addresses.entry(address).or_insert(start_block);
+            *existing = cmp::min(*existing, start_block);
+        }
+        self.wildcard |= wildcard;
+    }
+
+    /// An empty filter is one that never matches.
+    pub fn is_empty(&self) -> bool {
+        let EthereumTransferFilter {
+            addresses,
+            wildcard,
+        } = self;
+        addresses.is_empty() && !wildcard
+    }
+}
+
 impl From<&EthereumBlockFilter> for EthereumCallFilter {
     fn from(ethereum_block_filter: &EthereumBlockFilter) -> Self {
         Self {
@@ -1206,8 +1348,10 @@ pub trait EthereumAdapter: Send + Sync + 'static {
 mod tests {
     use crate::adapter::{FunctionSelector, COMBINED_FILTER_TYPE_URL};
 
+    use crate::trigger::{EthereumTrigger, TransferDirection};
+
     use super::{EthereumBlockFilter, LogFilterNode};
-    use super::{EthereumCallFilter, EthereumLogFilter, TriggerFilter};
+    use super::{EthereumCallFilter, EthereumLogFilter, EthereumTransferFilter, TriggerFilter};
 
     use base64::prelude::*;
     use graph::blockchain::TriggerFilter as _;
@@ -1225,6 +1369,7 @@ mod tests {
     use std::collections::{HashMap, HashSet};
     use std::iter::FromIterator;
     use std::str::FromStr;
+    use std::sync::Arc;
 
     #[test]
     fn ethereum_log_filter_codec() {
@@ -1333,6 +1478,7 @@ mod tests {
                 ]),
                 trigger_every_block: false,
             },
+            transfer: EthereumTransferFilter::default(),
         };
 
         let expected_call_filters = vec![
@@ -1449,6 +1595,7 @@ mod tests {
                 contract_addresses: HashSet::new(),
                 trigger_every_block: true,
             },
+            transfer: EthereumTransferFilter::default(),
         };
 
         filter.log.contracts_and_events_graph.add_edge(
@@ -1596,6 +1743,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn matching_ethereum_transfer_filter() {
+        let transfer = |from: Address, to: Address, internal_transfer: bool| {
+            Arc::new(EthereumCall {
+                from,
+                to,
+                internal_transfer,
+                ..Default::default()
+            })
+        };
+        let directions = |triggers: Vec<EthereumTrigger>| -> Vec<TransferDirection> {
+            triggers
+                .into_iter()
+                .map(|trigger| match trigger {
+                    EthereumTrigger::Transfer(_, direction) => direction,
+                    _ => panic!("expected a transfer trigger"),
+                })
+                .collect()
+        };
+
+        let mut filter = EthereumTransferFilter {
+            addresses: HashMap::from_iter(vec![(address(1), 10)]),
+            wildcard: false,
+        };
+        assert!(!filter.is_empty());
+
+        assert_eq!(
+            vec![TransferDirection::Incoming],
+            directions(filter.triggers(&transfer(address(2), address(1), true)))
+        );
+        assert_eq!(
+            vec![TransferDirection::Outgoing],
+            directions(filter.triggers(&transfer(address(1), address(2), true)))
+        );
+        assert!(filter
+            .triggers(&transfer(address(2), address(3), true))
+            .is_empty());
+        assert!(
+            filter
+                .triggers(&transfer(address(2), address(1), false))
+                .is_empty(),
+            "calls that are not internal transfers never match"
+        );
+
+        filter.extend(EthereumTransferFilter {
+            addresses: HashMap::from_iter(vec![(address(1), 5)]),
+            wildcard: true,
+        });
+        assert_eq!(Some(&5), filter.addresses.get(&address(1)));
+        assert_eq!(
+            vec![TransferDirection::Incoming, TransferDirection::Outgoing],
+            directions(filter.triggers(&transfer(address(2), address(3), true)))
+        );
+
+        assert!(EthereumTransferFilter::default().is_empty());
+    }
+
     #[test]
     fn extending_ethereum_block_filter_no_found() {
         let mut base = EthereumBlockFilter {
@@ -1792,6 +1996,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ethereum_trigger_filter_fingerprint() {
+        let filter = |addresses: Vec<u64>| TriggerFilter {
+            call: EthereumCallFilter {
+                contract_addresses_function_signatures: HashMap::from_iter(
+                    addresses.into_iter().map(|id| {
+                        (
+                            address(id),
+                            (0, HashSet::from_iter(vec![[0u8; 4], [1u8; 4]])),
+                        )
+                    }),
+                ),
+                wildcard_signatures: HashSet::new(),
+            },
+            ..Default::default()
+        };
+
+        let fingerprint = filter(vec![1, 2, 3]).fingerprint();
+        assert!(fingerprint.is_some());
+        assert_eq!(fingerprint, filter(vec![3, 1, 2]).fingerprint());
+        assert_ne!(fingerprint, filter(vec![1, 2]).fingerprint());
+        assert_ne!(fingerprint, TriggerFilter::default().fingerprint());
+    }
+
     fn address(id: u64) -> Address {
         Address::from_low_u64_be(id)
     }
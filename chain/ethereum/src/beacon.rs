@@ -0,0 +1,254 @@
+//! Access to the blobs that EIP-4844 transactions carry. Execution clients
+//! only know the versioned hashes of the blobs of a transaction; the blobs
+//! themselves are kept by the consensus client, which serves them through
+//! the beacon API as blob sidecars of the beacon block for a slot. Beacon
+//! nodes prune sidecars after about 18 days unless they are archival.
+//!
+//! The Engine API's `engine_getBlobsV1` can also return blobs by versioned
+//! hash, but only while they are in the transaction pool, which makes it
+//! useless for transactions that have already been included in a block
+use graph::prelude::reqwest::{Client, StatusCode};
+use graph::prelude::web3::types::{Bytes, H256};
+use graph::prelude::{anyhow, info, warn, Deserialize, Error, Logger};
+use graph::tokio::sync::RwLock;
+use sha2::{Digest, Sha256};
+
+use crate::ENV_VARS;
+
+/// The version byte of versioned hashes that are derived from a KZG
+/// commitment
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// What we need to know about a transaction to find its blobs. `web3`'s
+/// `Transaction` does not have the `blobVersionedHashes` of blob
+/// transactions, and we therefore deserialize the response to
+/// `eth_getTransactionByHash` into this
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlobTransaction {
+    #[serde(default)]
+    pub block_hash: Option<H256>,
+    #[serde(default)]
+    pub blob_versioned_hashes: Vec<H256>,
+}
+
+/// The beacon API wraps all responses in an object with a `data` field
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct Genesis {
+    #[serde(with = "string_u64")]
+    genesis_time: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Spec {
+    #[serde(rename = "SECONDS_PER_SLOT", with = "string_u64")]
+    seconds_per_slot: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlobSidecar {
+    blob: Bytes,
+    kzg_commitment: Bytes,
+}
+
+/// The beacon API encodes all numbers as strings
+mod string_u64 {
+    use graph::prelude::serde::{de::Error, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+/// The parameters of the beacon chain that we need to map an execution
+/// block to its slot
+#[derive(Clone, Copy, Debug)]
+struct Timing {
+    genesis_time: u64,
+    seconds_per_slot: u64,
+}
+
+impl Timing {
+    fn slot(&self, timestamp: u64) -> Result<u64, Error> {
+        if timestamp < self.genesis_time || self.seconds_per_slot == 0 {
+            return Err(anyhow!(
+                "block timestamp {} is before the beacon chain genesis at {}",
+                timestamp,
+                self.genesis_time
+            ));
+        }
+        Ok((timestamp - self.genesis_time) / self.seconds_per_slot)
+    }
+}
+
+/// A client for the beacon API of a consensus client
+#[derive(Debug)]
+pub struct BeaconClient {
+    url: String,
+    client: Client,
+    /// The genesis time and slot length of the beacon chain. We get them
+    /// the first time we need them, which also tells us whether the beacon
+    /// API is usable at all; `Some(None)` means that it is not
+    timing: RwLock<Option<Option<Timing>>>,
+}
+
+impl BeaconClient {
+    pub fn new(url: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: Client::new(),
+            timing: RwLock::new(None),
+        }
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<Option<T>, Error> {
+        let res = self
+            .client
+            .get(format!("{}{}", self.url, path))
+            .timeout(ENV_VARS.json_rpc_timeout)
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let res: Response<T> = res.error_for_status()?.json().await?;
+        Ok(Some(res.data))
+    }
+
+    async fn fetch_timing(&self) -> Result<Timing, Error> {
+        let genesis: Genesis = self
+            .get("/eth/v1/beacon/genesis")
+            .await?
+            .ok_or_else(|| anyhow!("the beacon node does not know the genesis"))?;
+        let spec: Spec = self
+            .get("/eth/v1/config/spec")
+            .await?
+            .ok_or_else(|| anyhow!("the beacon node does not serve its spec"))?;
+        Ok(Timing {
+            genesis_time: genesis.genesis_time,
+            seconds_per_slot: spec.seconds_per_slot,
+        })
+    }
+
+    /// Check whether the beacon API can be used. This is only checked once
+    /// and the result is cached
+    async fn timing(&self, logger: &Logger) -> Option<Timing> {
+        if let Some(timing) = *self.timing.read().await {
+            return timing;
+        }
+
+        let timing = match self.fetch_timing().await {
+            Ok(timing) => {
+                info!(logger, "Beacon API is available for blob sidecars";
+                      "url" => &self.url);
+                Some(timing)
+            }
+            Err(e) => {
+                warn!(logger, "Beacon API is not usable, blobs can not be retrieved";
+                      "url" => &self.url, "error" => e.to_string());
+                None
+            }
+        };
+        *self.timing.write().await = Some(timing);
+        timing
+    }
+
+    pub async fn is_available(&self, logger: &Logger) -> bool {
+        self.timing(logger).await.is_some()
+    }
+
+    /// The blobs with the given versioned hashes from the beacon block for
+    /// the execution block with `timestamp`, in the order of `hashes`
+    pub async fn blobs(
+        &self,
+        logger: &Logger,
+        timestamp: u64,
+        hashes: &[H256],
+    ) -> Result<Vec<Bytes>, Error> {
+        let timing = self
+            .timing(logger)
+            .await
+            .ok_or_else(|| anyhow!("the beacon API at {} is not usable", self.url))?;
+        let slot = timing.slot(timestamp)?;
+
+        let sidecars: Vec<BlobSidecar> = self
+            .get(&format!("/eth/v1/beacon/blob_sidecars/{}", slot))
+            .await?
+            .unwrap_or_default();
+
+        hashes
+            .iter()
+            .map(|hash| {
+                sidecars
+                    .iter()
+                    .find(|sidecar| &versioned_hash(&sidecar.kzg_commitment.0) == hash)
+                    .map(|sidecar| sidecar.blob.clone())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "the beacon node has no blob with versioned hash {:?} for slot {}; \
+                             it might have been pruned",
+                            hash,
+                            slot
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+/// The versioned hash for a KZG commitment as defined in EIP-4844
+fn versioned_hash(commitment: &[u8]) -> H256 {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    H256(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::serde_json::{self as json, json};
+
+    use super::*;
+
+    #[test]
+    fn computes_versioned_hash() {
+        let commitment = [0xab; 48];
+        let hash = versioned_hash(&commitment);
+        assert_eq!(VERSIONED_HASH_VERSION_KZG, hash.0[0]);
+        assert_eq!(&Sha256::digest(&commitment)[1..], &hash.0[1..]);
+    }
+
+    #[test]
+    fn maps_timestamps_to_slots() {
+        let timing = Timing {
+            genesis_time: 1_606_824_023,
+            seconds_per_slot: 12,
+        };
+        assert_eq!(0, timing.slot(1_606_824_023).unwrap());
+        assert_eq!(8_626_178, timing.slot(1_710_338_159).unwrap());
+        assert!(timing.slot(1_600_000_000).is_err());
+    }
+
+    #[test]
+    fn parses_beacon_responses() {
+        let res: Response<Spec> = json::from_value(json!({
+            "data": { "SECONDS_PER_SLOT": "12", "SLOTS_PER_EPOCH": "32" }
+        }))
+        .unwrap();
+        assert_eq!(12, res.data.seconds_per_slot);
+
+        let tx: BlobTransaction = json::from_value(json!({
+            "blockHash": format!("{:?}", H256::from_low_u64_be(1)),
+            "type": "0x3",
+            "blobVersionedHashes": [format!("{:?}", H256::from_low_u64_be(2))]
+        }))
+        .unwrap();
+        assert_eq!(Some(H256::from_low_u64_be(1)), tx.block_hash);
+        assert_eq!(vec![H256::from_low_u64_be(2)], tx.blob_versioned_hashes);
+    }
+}
@@ -8,6 +8,7 @@ use graph::blockchain::{
 use graph::components::adapter::ChainId;
 use graph::components::store::DeploymentCursorTracker;
 use graph::data::subgraph::UnifiedMappingApiVersion;
+use graph::endpoint::RequestClass;
 use graph::firehose::{FirehoseEndpoint, ForkStep};
 use graph::futures03::compat::Future01CompatExt;
 use graph::prelude::{
@@ -35,6 +36,7 @@ use graph::{
         Logger, LoggerFactory, NodeId,
     },
 };
+use graph_chain_common_rpc::{IngestorConfig, PollingBlockIngestor};
 use prost::Message;
 use std::collections::HashSet;
 use std::iter::FromIterator;
@@ -44,7 +46,7 @@ use std::time::Duration;
 use crate::codec::HeaderOnlyBlock;
 use crate::data_source::DataSourceTemplate;
 use crate::data_source::UnresolvedDataSourceTemplate;
-use crate::ingestor::PollingBlockIngestor;
+use crate::ingestor::IngestorAdapters;
 use crate::network::EthereumNetworkAdapters;
 use crate::runtime::runtime_adapter::eth_call_gas;
 use crate::{
@@ -53,7 +55,7 @@ use crate::{
     data_source::{DataSource, UnresolvedDataSource},
     ethereum_adapter::{
         blocks_with_triggers, get_calls, parse_block_triggers, parse_call_triggers,
-        parse_log_triggers,
+        parse_log_triggers, parse_transfer_triggers,
     },
     SubgraphEthRpcMetrics, TriggerFilter, ENV_VARS,
 };
@@ -181,6 +183,7 @@ impl BlockStreamBuilder<Chain> for EthereumStreamBuilder {
             logger,
             ENV_VARS.max_block_range_size,
             ENV_VARS.target_triggers_per_block_range,
+            ENV_VARS.polling_scan_concurrency(&chain.name),
             unified_api_version,
             subgraph_current_block,
         )))
@@ -546,14 +549,20 @@ impl Blockchain for Chain {
                 // The block ingestor must be configured to keep at least REORG_THRESHOLD ancestors,
                 // because the json-rpc BlockStream expects blocks after the reorg threshold to be
                 // present in the DB.
+                let config = IngestorConfig {
+                    ancestor_count: graph::env::ENV_VARS.reorg_threshold,
+                    polling_interval: self.polling_ingestor_interval,
+                    new_heads_timeout: ENV_VARS.new_heads_timeout,
+                    cleanup_blocks: ENV_VARS.cleanup_blocks,
+                };
                 Box::new(PollingBlockIngestor::new(
                     logger,
-                    graph::env::ENV_VARS.reorg_threshold,
-                    self.chain_client(),
+                    config,
+                    IngestorAdapters(self.chain_client()),
                     self.chain_store().cheap_clone(),
-                    self.polling_ingestor_interval,
                     self.name.clone(),
-                )?)
+                    BlockchainKind::Ethereum,
+                ))
             }
         };
 
@@ -652,6 +661,45 @@ impl Block for BlockFinality {
         let ts = i64::try_from(ts.as_u64()).unwrap();
         BlockTime::since_epoch(ts, 0)
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        use graph::prelude::web3::types::{Log, Transaction, TransactionReceipt};
+        use graph::prelude::EthereumCall;
+        use std::mem::size_of;
+
+        // Count the fixed size of transactions, receipts, logs and calls
+        // and the variable size of their data, which is what dominates
+        // the size of a block
+        let (block, receipts, calls) = match self {
+            BlockFinality::Final(block) => (block.as_ref(), &[][..], &[][..]),
+            BlockFinality::NonFinal(block) => (
+                block.ethereum_block.block.as_ref(),
+                &block.ethereum_block.transaction_receipts[..],
+                block.calls.as_deref().unwrap_or_default(),
+            ),
+        };
+        let txs: usize = block
+            .transactions
+            .iter()
+            .map(|tx| size_of::<Transaction>() + tx.input.0.len())
+            .sum();
+        let receipts: usize = receipts
+            .iter()
+            .map(|receipt| {
+                size_of::<TransactionReceipt>()
+                    + receipt
+                        .logs
+                        .iter()
+                        .map(|log| size_of::<Log>() + log.data.0.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        let calls: usize = calls
+            .iter()
+            .map(|call| size_of::<EthereumCall>() + call.input.0.len() + call.output.0.len())
+            .sum();
+        Some(size_of::<LightEthereumBlock>() + txs + receipts + calls)
+    }
 }
 
 pub struct DummyDataSourceTemplate;
@@ -676,7 +724,7 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
         blocks_with_triggers(
             self.chain_client
                 .rpc()?
-                .cheapest_with(&self.capabilities)
+                .cheapest_with(&self.capabilities, RequestClass::Logs)
                 .await?,
             self.logger.clone(),
             self.chain_store.clone(),
@@ -710,7 +758,7 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
                 let adapter = self
                     .chain_client
                     .rpc()?
-                    .cheapest_with(&self.capabilities)
+                    .cheapest_with(&self.capabilities, RequestClass::Logs)
                     .await?;
                 let block_number = block.number() as BlockNumber;
                 let (blocks, _) = blocks_with_triggers(
@@ -734,6 +782,7 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
                     &full_block.ethereum_block,
                 ));
                 triggers.append(&mut parse_call_triggers(&filter.call, full_block)?);
+                triggers.append(&mut parse_transfer_triggers(&filter.transfer, full_block)?);
                 triggers.append(&mut parse_block_triggers(&filter.block, full_block));
                 Ok(BlockWithTriggers::new(block, triggers, logger))
             }
@@ -782,7 +831,7 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
             }),
             ChainClient::Rpc(adapters) => {
                 let blocks = adapters
-                    .cheapest_with(&self.capabilities)
+                    .cheapest_with(&self.capabilities, RequestClass::Blocks)
                     .await?
                     .load_blocks(
                         self.logger.cheap_clone(),
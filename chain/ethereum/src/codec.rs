@@ -60,14 +60,18 @@ impl<'a> TryInto<EthereumCall> for CallAt<'a> {
     type Error = Error;
 
     fn try_into(self) -> Result<EthereumCall, Self::Error> {
+        let value = self
+            .call
+            .value
+            .as_ref()
+            .map_or_else(|| U256::from(0), |v| v.into());
+        let internal_transfer =
+            self.call.depth > 0 && self.call.call_type == CallType::Call as i32 && !value.is_zero();
+
         Ok(EthereumCall {
             from: self.call.caller.try_decode_proto("call from address")?,
             to: self.call.address.try_decode_proto("call to address")?,
-            value: self
-                .call
-                .value
-                .as_ref()
-                .map_or_else(|| U256::from(0), |v| v.into()),
+            value,
             gas_used: U256::from(self.call.gas_consumed),
             input: Bytes(self.call.input.clone()),
             output: Bytes(self.call.return_data.clone()),
@@ -75,6 +79,7 @@ impl<'a> TryInto<EthereumCall> for CallAt<'a> {
             block_number: self.block.number as i32,
             transaction_hash: Some(self.trace.hash.try_decode_proto("call transaction hash")?),
             transaction_index: self.trace.index as u64,
+            internal_transfer,
         })
     }
 }
@@ -42,8 +42,8 @@ use graph::{
 };
 
 use graph::data::subgraph::{
-    calls_host_fn, DataSourceContext, Source, MIN_SPEC_VERSION, SPEC_VERSION_0_0_8,
-    SPEC_VERSION_1_2_0,
+    calls_host_fn, DataSourceContext, Source, API_VERSION_0_0_9, MIN_SPEC_VERSION,
+    SPEC_VERSION_0_0_8, SPEC_VERSION_1_2_0,
 };
 
 use crate::adapter::EthereumAdapter as _;
@@ -57,6 +57,7 @@ const ETHEREUM_KINDS: &[&str] = &["ethereum/contract", "ethereum"];
 const EVENT_HANDLER_KIND: &str = "event";
 const CALL_HANDLER_KIND: &str = "call";
 const BLOCK_HANDLER_KIND: &str = "block";
+const TRANSFER_HANDLER_KIND: &str = "transfer";
 
 /// Runtime representation of a data source.
 // Note: Not great for memory usage that this needs to be `Clone`, considering how there may be tens
@@ -150,6 +151,7 @@ impl blockchain::DataSource<Chain> for DataSource {
             event_handlers,
             call_handlers,
             block_handlers,
+            transfer_handlers,
             ..
         } = &self.mapping;
 
@@ -159,6 +161,9 @@ impl blockchain::DataSource<Chain> for DataSource {
         if !call_handlers.is_empty() {
             kinds.insert(CALL_HANDLER_KIND);
         }
+        if !transfer_handlers.is_empty() {
+            kinds.insert(TRANSFER_HANDLER_KIND);
+        }
         for handler in block_handlers.iter() {
             kinds.insert(handler.kind());
         }
@@ -233,6 +238,7 @@ impl blockchain::DataSource<Chain> for DataSource {
             && mapping.event_handlers == other.mapping.event_handlers
             && mapping.call_handlers == other.mapping.call_handlers
             && mapping.block_handlers == other.mapping.block_handlers
+            && mapping.transfer_handlers == other.mapping.transfer_handlers
             && context == &other.context
     }
 
@@ -302,14 +308,20 @@ impl blockchain::DataSource<Chain> for DataSource {
             ))
         }
 
-        // Validate that there is a `source` address if there are call or block handlers
+        // Validate that there is a `source` address if there are call, block
+        // or transfer handlers
         let no_source_address = self.address().is_none();
         let has_call_handlers = !self.mapping.call_handlers.is_empty();
         let has_block_handlers = !self.mapping.block_handlers.is_empty();
-        if no_source_address && (has_call_handlers || has_block_handlers) {
+        let has_transfer_handlers = !self.mapping.transfer_handlers.is_empty();
+        if no_source_address && (has_call_handlers || has_block_handlers || has_transfer_handlers) {
             errors.push(SubgraphManifestValidationError::SourceAddressRequired.into());
         };
 
+        if self.mapping.transfer_handlers.len() > 1 {
+            errors.push(anyhow!("data source has more than one transfer handler"));
+        }
+
         // Ensure that there is at most one instance of each type of block handler
         // and that a combination of a non-filtered block handler and a filtered block handler is not allowed.
 
@@ -371,6 +383,13 @@ impl blockchain::DataSource<Chain> for DataSource {
             }
         }
 
+        if has_transfer_handlers && api_version < API_VERSION_0_0_9 {
+            errors.push(anyhow!(
+                "data source has transfer handlers, but they are only supported for \
+                 apiVersion >= 0.0.9"
+            ));
+        }
+
         if spec_version < &SPEC_VERSION_1_2_0 {
             for handler in &self.mapping.event_handlers {
                 if !handler.calls.decls.is_empty() {
@@ -693,6 +712,10 @@ impl DataSource {
                 Ok(Some(TriggerWithHandler::<Chain>::new(
                     MappingTrigger::Block {
                         block: block.cheap_clone(),
+                        rollup: self
+                            .network
+                            .as_deref()
+                            .and_then(|network| crate::ENV_VARS.rollup(network)),
                     },
                     handler.handler.clone(),
                     block.block_ptr(),
@@ -929,6 +952,34 @@ impl DataSource {
                     logging_extras,
                 )))
             }
+            EthereumTrigger::Transfer(transfer, _) => {
+                let handler = match self.mapping.transfer_handlers.first() {
+                    Some(handler) => handler,
+                    None => return Ok(None),
+                };
+
+                let transaction = Arc::new(
+                    block
+                        .transaction_for_call(transfer)
+                        .context("Found no transaction for transfer")?,
+                );
+                let logging_extras = Arc::new(o! {
+                    "from" => format!("{}", &transfer.from),
+                    "to" => format!("{}", &transfer.to),
+                    "transaction" => format!("{}", &transaction.hash),
+                });
+                Ok(Some(TriggerWithHandler::<Chain>::new_with_logging_extras(
+                    MappingTrigger::Transfer {
+                        block: block.cheap_clone(),
+                        transaction,
+                        transfer: transfer.cheap_clone(),
+                    },
+                    handler.handler.clone(),
+                    block.block_ptr(),
+                    block.timestamp(),
+                    logging_extras,
+                )))
+            }
         }
     }
 }
@@ -1178,7 +1229,9 @@ impl blockchain::DecoderHook<Chain> for DecoderHook {
                         .into_iter()
                         .map(move |call| (metrics.cheap_clone(), call)),
                 ),
-                MappingTrigger::Block { .. } | MappingTrigger::Call { .. } => None,
+                MappingTrigger::Block { .. }
+                | MappingTrigger::Call { .. }
+                | MappingTrigger::Transfer { .. } => None,
             })
             .flatten()
             .collect();
@@ -1341,6 +1394,8 @@ pub struct UnresolvedMapping {
     pub call_handlers: Vec<MappingCallHandler>,
     #[serde(default)]
     pub event_handlers: Vec<MappingEventHandler>,
+    #[serde(default)]
+    pub transfer_handlers: Vec<MappingTransferHandler>,
     pub file: Link,
 }
 
@@ -1354,6 +1409,7 @@ pub struct Mapping {
     pub block_handlers: Vec<MappingBlockHandler>,
     pub call_handlers: Vec<MappingCallHandler>,
     pub event_handlers: Vec<MappingEventHandler>,
+    pub transfer_handlers: Vec<MappingTransferHandler>,
     pub runtime: Arc<Vec<u8>>,
     pub link: Link,
 }
@@ -1367,6 +1423,10 @@ impl Mapping {
         !self.call_handlers.is_empty()
     }
 
+    pub fn has_transfer_handler(&self) -> bool {
+        !self.transfer_handlers.is_empty()
+    }
+
     pub fn has_block_handler_with_call_filter(&self) -> bool {
         self.block_handlers
             .iter()
@@ -1398,6 +1458,7 @@ impl UnresolvedMapping {
             block_handlers,
             call_handlers,
             event_handlers,
+            transfer_handlers,
             file: link,
         } = self;
 
@@ -1430,6 +1491,7 @@ impl UnresolvedMapping {
             block_handlers: block_handlers.clone(),
             call_handlers: call_handlers.clone(),
             event_handlers: event_handlers.clone(),
+            transfer_handlers: transfer_handlers.clone(),
             runtime,
             link,
         })
@@ -1549,6 +1611,13 @@ pub struct MappingCallHandler {
     pub handler: String,
 }
 
+/// A handler for internal calls that transfer ETH to or from the address
+/// of the data source
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
+pub struct MappingTransferHandler {
+    pub handler: String,
+}
+
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
 pub struct MappingEventHandler {
     pub event: String,
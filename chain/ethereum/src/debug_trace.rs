@@ -0,0 +1,262 @@
+//! Support for getting call traces with `debug_traceBlockByNumber` and
+//! Geth's `callTracer`, which Geth and Erigon support, as an alternative to
+//! OpenEthereum-style `trace_filter`. The nested call frames that the call
+//! tracer produces are flattened into the same `Trace`s that `trace_filter`
+//! returns so that the rest of the code does not need to know where the
+//! traces came from
+use graph::prelude::serde_json::{self as json, json};
+use graph::prelude::web3::types::{
+    Action, ActionType, Address, Bytes, Call, CallResult, CallType, Res, Trace, H256, U256,
+};
+use graph::prelude::{anyhow, Deserialize, Error};
+
+/// The parameters for `debug_traceBlockByNumber` that select the call tracer
+pub(crate) fn tracer_config() -> json::Value {
+    json!({ "tracer": "callTracer" })
+}
+
+/// A call frame as produced by Geth's call tracer
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    #[serde(rename = "type")]
+    call_type: String,
+    from: Address,
+    #[serde(default)]
+    to: Option<Address>,
+    #[serde(default)]
+    value: Option<U256>,
+    #[serde(default)]
+    gas: U256,
+    #[serde(default)]
+    gas_used: U256,
+    #[serde(default)]
+    input: Bytes,
+    #[serde(default)]
+    output: Option<Bytes>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    calls: Vec<CallFrame>,
+}
+
+/// The trace of one transaction in the response to `debug_traceBlockByNumber`.
+/// Older versions of Geth do not include the transaction hash, and
+/// transactions that could not be traced have an `error` instead of a
+/// `result`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionTrace {
+    #[serde(default)]
+    tx_hash: Option<H256>,
+    #[serde(default)]
+    result: Option<CallFrame>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+fn call_type(call_type: &str) -> Option<CallType> {
+    match call_type {
+        "CALL" => Some(CallType::Call),
+        "CALLCODE" => Some(CallType::CallCode),
+        "DELEGATECALL" => Some(CallType::DelegateCall),
+        "STATICCALL" => Some(CallType::StaticCall),
+        _ => None,
+    }
+}
+
+/// Turn the response to `debug_traceBlockByNumber` for the block with the
+/// given hash and number into traces. `transactions` are the hashes of the
+/// transactions in the block, in order
+pub(crate) fn block_traces(
+    response: json::Value,
+    block_hash: H256,
+    block_number: u64,
+    transactions: &[H256],
+) -> Result<Vec<Trace>, Error> {
+    let txs: Vec<TransactionTrace> = json::from_value(response)?;
+    if txs.len() != transactions.len() {
+        return Err(anyhow!(
+            "debug_traceBlockByNumber returned {} traces for block {} with {} transactions",
+            txs.len(),
+            block_number,
+            transactions.len()
+        ));
+    }
+
+    let mut traces = Vec::new();
+    for (position, (tx, tx_hash)) in txs.into_iter().zip(transactions).enumerate() {
+        if let Some(hash) = tx.tx_hash {
+            if &hash != tx_hash {
+                return Err(anyhow!(
+                    "debug_traceBlockByNumber returned a trace for transaction {:?} \
+                     where {:?} was expected in block {}",
+                    hash,
+                    tx_hash,
+                    block_number
+                ));
+            }
+        }
+        let frame = match (tx.result, tx.error) {
+            (Some(frame), _) => frame,
+            (None, error) => {
+                return Err(anyhow!(
+                    "failed to trace transaction {:?} in block {}: {}",
+                    tx_hash,
+                    block_number,
+                    error.unwrap_or_else(|| "no result".to_string())
+                ))
+            }
+        };
+        let tx = TxInfo {
+            block_hash,
+            block_number,
+            position,
+            hash: *tx_hash,
+        };
+        flatten(frame, &tx, Vec::new(), None, &mut traces);
+    }
+    Ok(traces)
+}
+
+struct TxInfo {
+    block_hash: H256,
+    block_number: u64,
+    position: usize,
+    hash: H256,
+}
+
+/// Add a trace for `frame` and all the frames nested in it to `traces`.
+/// Frames that are not calls, like contract creations, do not produce a
+/// trace, but the calls nested in them do. Calls nested in a frame that
+/// failed inherit its error since their effects were reverted
+fn flatten(
+    frame: CallFrame,
+    tx: &TxInfo,
+    trace_address: Vec<usize>,
+    parent_error: Option<&str>,
+    traces: &mut Vec<Trace>,
+) {
+    let error = frame.error.as_deref().or(parent_error).map(str::to_string);
+
+    if let (Some(call_type), Some(to)) = (call_type(&frame.call_type), frame.to) {
+        traces.push(Trace {
+            action: Action::Call(Call {
+                from: frame.from,
+                to,
+                value: frame.value.unwrap_or_default(),
+                gas: frame.gas,
+                input: frame.input.clone(),
+                call_type,
+            }),
+            result: Some(Res::Call(CallResult {
+                gas_used: frame.gas_used,
+                output: frame.output.clone().unwrap_or_default(),
+            })),
+            trace_address: trace_address.clone(),
+            subtraces: frame.calls.len(),
+            transaction_position: Some(tx.position),
+            transaction_hash: Some(tx.hash),
+            block_number: tx.block_number,
+            block_hash: tx.block_hash,
+            action_type: ActionType::Call,
+            error: error.clone(),
+        });
+    }
+
+    for (idx, call) in frame.calls.into_iter().enumerate() {
+        let mut address = trace_address.clone();
+        address.push(idx);
+        flatten(call, tx, address, error.as_deref(), traces);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::components::ethereum::EthereumCall;
+
+    use super::*;
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    #[test]
+    fn flattens_call_frames() {
+        let tx = H256::from_low_u64_be(7);
+        let block = H256::from_low_u64_be(100);
+        let response = json!([{
+            "txHash": format!("{:?}", tx),
+            "result": {
+                "type": "CALL",
+                "from": format!("{:?}", address(1)),
+                "to": format!("{:?}", address(2)),
+                "value": "0x0",
+                "gas": "0x100",
+                "gasUsed": "0x80",
+                "input": "0x12345678",
+                "output": "0x",
+                "calls": [{
+                    "type": "CREATE",
+                    "from": format!("{:?}", address(2)),
+                    "to": format!("{:?}", address(3)),
+                    "gas": "0x10",
+                    "gasUsed": "0x8",
+                    "input": "0x00",
+                    "calls": [{
+                        "type": "STATICCALL",
+                        "from": format!("{:?}", address(3)),
+                        "to": format!("{:?}", address(4)),
+                        "gas": "0x4",
+                        "gasUsed": "0x2",
+                        "input": "0xdeadbeef",
+                        "output": "0x01"
+                    }]
+                }, {
+                    "type": "DELEGATECALL",
+                    "from": format!("{:?}", address(2)),
+                    "to": format!("{:?}", address(5)),
+                    "gas": "0x10",
+                    "gasUsed": "0x8",
+                    "input": "0xcafebabe",
+                    "error": "execution reverted"
+                }]
+            }
+        }]);
+
+        let traces = block_traces(response, block, 12, &[tx]).unwrap();
+        let addresses: Vec<_> = traces
+            .iter()
+            .map(|trace| trace.trace_address.clone())
+            .collect();
+        assert_eq!(vec![vec![], vec![0, 0], vec![1]], addresses);
+
+        let calls: Vec<_> = traces
+            .iter()
+            .filter_map(EthereumCall::try_from_trace)
+            .collect();
+        assert_eq!(2, calls.len());
+        assert_eq!(address(2), calls[0].to);
+        assert_eq!(address(4), calls[1].to);
+        assert_eq!(block, calls[1].block_hash);
+        assert_eq!(12, calls[1].block_number);
+        assert_eq!(Some(tx), calls[1].transaction_hash);
+    }
+
+    #[test]
+    fn rejects_mismatched_transactions() {
+        let response = json!([{
+            "txHash": format!("{:?}", H256::from_low_u64_be(1)),
+            "result": {
+                "type": "CALL",
+                "from": format!("{:?}", address(1)),
+                "to": format!("{:?}", address(2)),
+                "input": "0x"
+            }
+        }]);
+
+        let block = H256::from_low_u64_be(100);
+        assert!(block_traces(response.clone(), block, 1, &[]).is_err());
+        assert!(block_traces(response, block, 1, &[H256::from_low_u64_be(2)]).is_err());
+    }
+}
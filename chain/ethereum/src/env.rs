@@ -1,9 +1,12 @@
 use envconfig::Envconfig;
 use graph::env::EnvVarBoolean;
+use graph::prelude::web3::types::Address;
 use graph::prelude::{envconfig, lazy_static, BlockNumber};
 use std::fmt;
 use std::time::Duration;
 
+use crate::rollup::Rollup;
+
 lazy_static! {
     pub static ref ENV_VARS: EnvVars = EnvVars::from_env().unwrap();
 }
@@ -50,6 +53,23 @@ pub struct EnvVars {
     /// Set by the environment variable `GRAPH_ETHEREUM_BLOCK_RECEIPTS_CHECK_TIMEOUT`
     /// (expressed in seconds). The default value is 10s.
     pub block_receipts_check_timeout: Duration,
+    /// When building triggers for a block that has at least this many
+    /// transactions whose receipts we need, get all receipts of the block
+    /// with one `eth_getBlockReceipts` call if the provider supports that.
+    /// Setting this to 0 turns that off.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_ETHEREUM_BLOCK_RECEIPTS_MIN_TRANSACTIONS`. The default value is 10.
+    pub block_receipts_min_transactions: usize,
+    /// The address of the Multicall3 contract. Declared calls for the same
+    /// block are combined into one call to that contract at blocks where it
+    /// exists.
+    ///
+    /// Set by the environment variable `GRAPH_ETHEREUM_MULTICALL3_ADDRESS`.
+    /// The default value is the address at which Multicall3 is deployed on
+    /// most chains, `0xcA11bde05977b3631167028862bE2a173976CA11`. Setting it
+    /// to an empty string turns combining calls off.
+    pub multicall3_address: Option<Address>,
     /// This is used for requests that will not fail the subgraph if the limit
     /// is reached, but will simply restart the syncing step, so it can be low.
     /// This limit guards against scenarios such as requesting a block hash that
@@ -66,6 +86,30 @@ pub struct EnvVars {
     /// by default on macOS (to avoid DNS issues) and disabled by default on all
     /// other systems.
     pub fetch_receipts_in_batches: bool,
+    /// The default for the `batch.size` of providers in the configuration
+    /// file, i.e., the maximum number of requests for logs, blocks,
+    /// receipts and `eth_call`s that the transport of a provider combines
+    /// into one JSON-RPC batch request. Batching is turned off if this is
+    /// 0 or 1.
+    ///
+    /// Set by the environment variable `GRAPH_ETHEREUM_JSON_RPC_BATCH_SIZE`.
+    /// The default value is 0.
+    pub json_rpc_batch_size: usize,
+    /// The default for the `batch.concurrency` of providers in the
+    /// configuration file, i.e., the maximum number of batch requests that
+    /// are in flight at the same time for each provider.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_ETHEREUM_JSON_RPC_MAX_BATCHES_IN_FLIGHT`. The default value is 4.
+    pub json_rpc_max_batches_in_flight: usize,
+    /// When the block ingestor tracks the chain head with an `eth_subscribe`
+    /// subscription, poll for the latest block if the subscription has not
+    /// delivered a new block header for this long, and reconnect the
+    /// subscription.
+    ///
+    /// Set by the environment variable `GRAPH_ETHEREUM_NEW_HEADS_TIMEOUT`
+    /// (expressed in seconds). The default value is 30s.
+    pub new_heads_timeout: Duration,
     /// `graph_node::config` disallows setting this in a store with multiple
     /// shards. See 8b6ad0c64e244023ac20ced7897fe666 for the reason.
     ///
@@ -88,6 +132,20 @@ pub struct EnvVars {
     /// This is a comma separated list of chain ids for which the gas field will not be set
     /// when calling `eth_call`.
     pub eth_call_no_gas: Vec<String>,
+    /// Set by the environment variable `GRAPH_ETHEREUM_ROLLUP_CHAINS`.
+    /// This is a comma separated list of `network=rollup` entries, where
+    /// `rollup` is `arbitrum` or `op-stack`. Block handlers for data sources
+    /// on these networks see the L1 block of each block.
+    pub rollup_chains: Vec<(String, Rollup)>,
+    /// Set by the environment variable
+    /// `GRAPH_ETHEREUM_POLLING_SCAN_CONCURRENCY`. This is a comma separated
+    /// list of `network=count` entries and at most one plain `count` that
+    /// applies to all other networks. The polling block stream for a
+    /// network scans up to `count` block ranges for triggers at the same
+    /// time. The default is 1.
+    pub polling_scan_concurrency: usize,
+    /// The per-network entries of `GRAPH_ETHEREUM_POLLING_SCAN_CONCURRENCY`
+    pub polling_scan_concurrency_by_chain: Vec<(String, usize)>,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -101,6 +159,34 @@ impl EnvVars {
     pub fn from_env() -> Result<Self, envconfig::Error> {
         Ok(Inner::init_from_env()?.into())
     }
+
+    /// The kind of rollup `network` is, if it is one
+    pub fn rollup(&self, network: &str) -> Option<Rollup> {
+        self.rollup_chains
+            .iter()
+            .find(|(name, _)| name == network)
+            .map(|(_, rollup)| *rollup)
+    }
+
+    /// How many block ranges the polling block stream for `network` scans
+    /// at the same time
+    pub fn polling_scan_concurrency(&self, network: &str) -> usize {
+        self.polling_scan_concurrency_by_chain
+            .iter()
+            .find(|(name, _)| name == network)
+            .map(|(_, count)| *count)
+            .unwrap_or(self.polling_scan_concurrency)
+            .max(1)
+    }
+}
+
+fn parse_scan_concurrency(count: &str) -> usize {
+    count.trim().parse().unwrap_or_else(|_| {
+        panic!(
+            "GRAPH_ETHEREUM_POLLING_SCAN_CONCURRENCY: `{}` is not a number",
+            count
+        )
+    })
 }
 
 impl From<Inner> for EnvVars {
@@ -121,6 +207,13 @@ impl From<Inner> for EnvVars {
             block_receipts_check_timeout: Duration::from_secs(
                 x.block_receipts_check_timeout_in_seccs,
             ),
+            block_receipts_min_transactions: x.block_receipts_min_transactions,
+            multicall3_address: Some(x.multicall3_address.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.parse()
+                        .expect("GRAPH_ETHEREUM_MULTICALL3_ADDRESS must be an address")
+                }),
             request_retries: x.request_retries,
             block_ingestor_max_concurrent_json_rpc_calls: x
                 .block_ingestor_max_concurrent_json_rpc_calls,
@@ -128,6 +221,9 @@ impl From<Inner> for EnvVars {
                 .fetch_receipts_in_batches
                 .map(|b| b.0)
                 .unwrap_or(cfg!(target_os = "macos")),
+            json_rpc_batch_size: x.json_rpc_batch_size,
+            json_rpc_max_batches_in_flight: x.json_rpc_max_batches_in_flight,
+            new_heads_timeout: Duration::from_secs(x.new_heads_timeout_in_secs),
             cleanup_blocks: x.cleanup_blocks.0,
             target_triggers_per_block_range: x.target_triggers_per_block_range,
             genesis_block_number: x.genesis_block_number,
@@ -137,6 +233,37 @@ impl From<Inner> for EnvVars {
                 .filter(|s| !s.is_empty())
                 .map(str::to_string)
                 .collect(),
+            rollup_chains: x
+                .rollup_chains
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|entry| {
+                    let (network, rollup) = entry.split_once('=').unwrap_or_else(|| {
+                        panic!(
+                            "GRAPH_ETHEREUM_ROLLUP_CHAINS: `{}` must have the form `network=rollup`",
+                            entry
+                        )
+                    });
+                    let rollup = rollup
+                        .trim()
+                        .parse()
+                        .unwrap_or_else(|e| panic!("GRAPH_ETHEREUM_ROLLUP_CHAINS: {}", e));
+                    (network.trim().to_string(), rollup)
+                })
+                .collect(),
+            polling_scan_concurrency: x
+                .polling_scan_concurrency
+                .split(',')
+                .filter(|s| !s.trim().is_empty() && !s.contains('='))
+                .last()
+                .map(parse_scan_concurrency)
+                .unwrap_or(1),
+            polling_scan_concurrency_by_chain: x
+                .polling_scan_concurrency
+                .split(',')
+                .filter_map(|entry| entry.split_once('='))
+                .map(|(network, count)| (network.trim().to_string(), parse_scan_concurrency(count)))
+                .collect(),
         }
     }
 }
@@ -166,6 +293,16 @@ struct Inner {
     json_rpc_timeout_in_secs: u64,
     #[envconfig(from = "GRAPH_ETHEREUM_BLOCK_RECEIPTS_CHECK_TIMEOUT", default = "10")]
     block_receipts_check_timeout_in_seccs: u64,
+    #[envconfig(
+        from = "GRAPH_ETHEREUM_BLOCK_RECEIPTS_MIN_TRANSACTIONS",
+        default = "10"
+    )]
+    block_receipts_min_transactions: usize,
+    #[envconfig(
+        from = "GRAPH_ETHEREUM_MULTICALL3_ADDRESS",
+        default = "0xcA11bde05977b3631167028862bE2a173976CA11"
+    )]
+    multicall3_address: String,
     #[envconfig(from = "GRAPH_ETHEREUM_REQUEST_RETRIES", default = "10")]
     request_retries: usize,
     #[envconfig(
@@ -175,6 +312,12 @@ struct Inner {
     block_ingestor_max_concurrent_json_rpc_calls: usize,
     #[envconfig(from = "GRAPH_ETHEREUM_FETCH_TXN_RECEIPTS_IN_BATCHES")]
     fetch_receipts_in_batches: Option<EnvVarBoolean>,
+    #[envconfig(from = "GRAPH_ETHEREUM_JSON_RPC_BATCH_SIZE", default = "0")]
+    json_rpc_batch_size: usize,
+    #[envconfig(from = "GRAPH_ETHEREUM_JSON_RPC_MAX_BATCHES_IN_FLIGHT", default = "4")]
+    json_rpc_max_batches_in_flight: usize,
+    #[envconfig(from = "GRAPH_ETHEREUM_NEW_HEADS_TIMEOUT", default = "30")]
+    new_heads_timeout_in_secs: u64,
     #[envconfig(from = "GRAPH_ETHEREUM_CLEANUP_BLOCKS", default = "false")]
     cleanup_blocks: EnvVarBoolean,
     #[envconfig(
@@ -186,4 +329,8 @@ struct Inner {
     genesis_block_number: u64,
     #[envconfig(from = "GRAPH_ETH_CALL_NO_GAS", default = "421613")]
     eth_call_no_gas: String,
+    #[envconfig(from = "GRAPH_ETHEREUM_ROLLUP_CHAINS", default = "")]
+    rollup_chains: String,
+    #[envconfig(from = "GRAPH_ETHEREUM_POLLING_SCAN_CONCURRENCY", default = "1")]
+    polling_scan_concurrency: String,
 }
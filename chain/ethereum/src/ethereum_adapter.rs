@@ -7,10 +7,11 @@ use graph::data::store::ethereum::call;
 use graph::data::store::scalar;
 use graph::data::subgraph::UnifiedMappingApiVersion;
 use graph::data::subgraph::API_VERSION_0_0_7;
+use graph::endpoint::RequestClass;
 use graph::futures01::stream;
 use graph::futures01::Future;
 use graph::futures01::Stream;
-use graph::futures03::future::try_join_all;
+use graph::futures03::future::{join_all, try_join_all};
 use graph::futures03::{
     self, compat::Future01CompatExt, FutureExt, StreamExt, TryFutureExt, TryStreamExt,
 };
@@ -42,7 +43,8 @@ use graph::{
     components::ethereum::*,
     prelude::web3::api::Web3,
     prelude::web3::transports::Batch,
-    prelude::web3::types::{Trace, TraceFilter, TraceFilterBuilder, H160},
+    prelude::web3::types::{Action, Trace, TraceFilter, TraceFilterBuilder, H160},
+    prelude::web3::Transport as _,
 };
 use itertools::Itertools;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
@@ -54,15 +56,18 @@ use std::time::Instant;
 
 use crate::adapter::EthereumRpcError;
 use crate::adapter::ProviderStatus;
+use crate::beacon::{BeaconClient, BlobTransaction};
 use crate::chain::BlockFinality;
+use crate::debug_trace;
+use crate::multicall;
 use crate::trigger::LogRef;
 use crate::Chain;
 use crate::NodeCapabilities;
 use crate::{
     adapter::{
         ContractCall, ContractCallError, EthGetLogsFilter, EthereumAdapter as EthereumAdapterTrait,
-        EthereumBlockFilter, EthereumCallFilter, EthereumLogFilter, ProviderEthRpcMetrics,
-        SubgraphEthRpcMetrics,
+        EthereumBlockFilter, EthereumCallFilter, EthereumLogFilter, EthereumTransferFilter,
+        ProviderEthRpcMetrics, SubgraphEthRpcMetrics,
     },
     transport::Transport,
     trigger::{EthereumBlockTriggerType, EthereumTrigger},
@@ -78,6 +83,26 @@ pub struct EthereumAdapter {
     supports_eip_1898: bool,
     call_only: bool,
     supports_block_receipts: Arc<RwLock<Option<bool>>>,
+    /// A WebSocket URL for the provider that can be used to subscribe to
+    /// new block headers
+    subscription_url: Option<String>,
+    trace_method: TraceMethod,
+    /// The blocks at which the Multicall3 contract exists on this chain
+    multicall3: Arc<RwLock<multicall::Deployment>>,
+    /// The beacon API of the consensus client paired with this provider,
+    /// which we need to get the blobs of blob transactions
+    beacon: Option<Arc<BeaconClient>>,
+}
+
+/// The JSON-RPC method used to get call traces from a provider
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceMethod {
+    /// OpenEthereum-style `trace_filter`
+    #[default]
+    TraceFilter,
+    /// `debug_traceBlockByNumber` with the call tracer, as supported by
+    /// Geth and Erigon
+    DebugTraceBlock,
 }
 
 impl CheapClone for EthereumAdapter {
@@ -90,6 +115,10 @@ impl CheapClone for EthereumAdapter {
             supports_eip_1898: self.supports_eip_1898,
             call_only: self.call_only,
             supports_block_receipts: self.supports_block_receipts.cheap_clone(),
+            subscription_url: self.subscription_url.clone(),
+            trace_method: self.trace_method,
+            multicall3: self.multicall3.cheap_clone(),
+            beacon: self.beacon.clone(),
         }
     }
 }
@@ -126,6 +155,112 @@ impl EthereumAdapter {
             supports_eip_1898: supports_eip_1898 && !is_ganache,
             call_only,
             supports_block_receipts: Arc::new(RwLock::new(None)),
+            subscription_url: None,
+            trace_method: TraceMethod::default(),
+            multicall3: Arc::new(RwLock::new(multicall::Deployment::default())),
+            beacon: None,
+        }
+    }
+
+    /// Use `url` to subscribe to new block headers from this provider
+    /// instead of polling for them
+    pub fn with_subscription_url(mut self, url: Option<String>) -> Self {
+        self.subscription_url = url;
+        self
+    }
+
+    pub fn subscription_url(&self) -> Option<&str> {
+        self.subscription_url.as_deref()
+    }
+
+    /// Use `method` to get call traces from this provider
+    pub fn with_trace_method(mut self, method: TraceMethod) -> Self {
+        self.trace_method = method;
+        self
+    }
+
+    /// Use the beacon API at `url` to get the blobs of blob transactions
+    pub fn with_beacon_url(mut self, url: Option<String>) -> Self {
+        self.beacon = url.map(|url| Arc::new(BeaconClient::new(url)));
+        self
+    }
+
+    /// Whether this provider has a beacon API that can be used to get
+    /// blobs. Only checks the configuration, not whether the beacon API
+    /// actually works
+    pub fn has_beacon(&self) -> bool {
+        self.beacon.is_some()
+    }
+
+    /// Whether the beacon API of this provider can be used to get blobs
+    pub async fn supports_blobs(&self, logger: &Logger) -> bool {
+        match &self.beacon {
+            Some(beacon) => beacon.is_available(logger).await,
+            None => false,
+        }
+    }
+
+    /// The blobs carried by the transaction with hash `hash`, in the order
+    /// of its `blobVersionedHashes`. Returns `None` if the provider does
+    /// not know the transaction or it has not been included in a block
+    /// yet, and an empty list if it does not carry any blobs
+    pub async fn blobs(&self, logger: &Logger, hash: H256) -> Result<Option<Vec<Bytes>>, Error> {
+        let beacon = self.beacon.clone().ok_or_else(|| {
+            anyhow!(
+                "provider {} has no beacon API to get blobs from",
+                self.provider
+            )
+        })?;
+
+        let web3 = self.web3.cheap_clone();
+        let location = retry("eth_getTransactionByHash RPC call", logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                let web3 = web3.cheap_clone();
+                async move {
+                    let value = web3
+                        .transport()
+                        .execute("eth_getTransactionByHash", vec![json::to_value(hash)?])
+                        .await?;
+                    if value.is_null() {
+                        return Ok(None);
+                    }
+                    let tx: BlobTransaction = json::from_value(value)?;
+                    let block_hash = match tx.block_hash {
+                        Some(block_hash) => block_hash,
+                        None => return Ok(None),
+                    };
+                    if tx.blob_versioned_hashes.is_empty() {
+                        return Ok(Some((0, vec![])));
+                    }
+                    let block = web3
+                        .eth()
+                        .block(BlockId::Hash(block_hash))
+                        .await?
+                        .ok_or_else(|| {
+                            anyhow!("block {:?} of transaction {:?} not found", block_hash, hash)
+                        })?;
+                    Ok::<_, Error>(Some((block.timestamp.as_u64(), tx.blob_versioned_hashes)))
+                }
+            })
+            .await
+            .map_err(|e| {
+                e.into_inner().unwrap_or_else(|| {
+                    anyhow!(
+                        "Ethereum node took too long to return transaction {:?}",
+                        hash
+                    )
+                })
+            })?;
+
+        match location {
+            None => Ok(None),
+            Some((_, versioned_hashes)) if versioned_hashes.is_empty() => Ok(Some(vec![])),
+            Some((timestamp, versioned_hashes)) => beacon
+                .blobs(logger, timestamp, &versioned_hashes)
+                .await
+                .map(Some),
         }
     }
 
@@ -139,6 +274,12 @@ impl EthereumAdapter {
     ) -> Result<Vec<Trace>, Error> {
         assert!(!self.call_only);
 
+        if self.trace_method == TraceMethod::DebugTraceBlock {
+            return self
+                .debug_traces(logger, subgraph_metrics, from, to, addresses)
+                .await;
+        }
+
         let eth = self.clone();
         let retry_log_message =
             format!("trace_filter RPC call for block range: [{}..{}]", from, to);
@@ -222,6 +363,148 @@ impl EthereumAdapter {
             .await
     }
 
+    /// Get the call traces for the blocks `from..=to` with
+    /// `debug_traceBlockByNumber` and the call tracer. Unlike `trace_filter`,
+    /// that method can not filter by address, and the traces it returns are
+    /// filtered here instead
+    async fn debug_traces(
+        &self,
+        logger: Logger,
+        subgraph_metrics: Arc<SubgraphEthRpcMetrics>,
+        from: BlockNumber,
+        to: BlockNumber,
+        addresses: Vec<H160>,
+    ) -> Result<Vec<Trace>, Error> {
+        let eth = self.cheap_clone();
+        let retry_log_message = format!(
+            "debug_traceBlockByNumber RPC call for block range: [{}..{}]",
+            from, to
+        );
+        let traces = retry(retry_log_message, &logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                let eth = eth.cheap_clone();
+                let logger = logger.clone();
+                let subgraph_metrics = subgraph_metrics.clone();
+
+                async move {
+                    let start = Instant::now();
+                    let result =
+                        try_join_all((from..=to).map(|number| eth.debug_trace_block(number)))
+                            .await
+                            .map(|traces| traces.into_iter().flatten().collect::<Vec<_>>());
+
+                    let elapsed = start.elapsed().as_secs_f64();
+                    let method = "debug_traceBlockByNumber";
+                    eth.metrics.observe_request(elapsed, method, &eth.provider);
+                    subgraph_metrics.observe_request(elapsed, method, &eth.provider);
+                    if let Err(e) = &result {
+                        eth.metrics.add_error(method, &eth.provider);
+                        subgraph_metrics.add_error(method, &eth.provider);
+                        debug!(
+                            logger,
+                            "Error querying traces error = {:#} from = {} to = {}", e, from, to
+                        );
+                    }
+                    result
+                }
+            })
+            .await
+            .map_err(move |e| {
+                e.into_inner().unwrap_or_else(move || {
+                    anyhow!(
+                        "Ethereum node took too long to respond to debug_traceBlockByNumber \
+                         (from block {}, to block {})",
+                        from,
+                        to
+                    )
+                })
+            })?;
+
+        if addresses.is_empty() {
+            return Ok(traces);
+        }
+        let addresses: HashSet<_> = addresses.into_iter().collect();
+        Ok(traces
+            .into_iter()
+            .filter(|trace| match &trace.action {
+                Action::Call(call) => addresses.contains(&call.to),
+                _ => false,
+            })
+            .collect())
+    }
+
+    /// Trace all transactions in the block with the given number
+    async fn debug_trace_block(&self, number: BlockNumber) -> Result<Vec<Trace>, Error> {
+        let block = self
+            .web3
+            .eth()
+            .block(BlockId::Number(Web3BlockNumber::Number(number.into())))
+            .await?
+            .ok_or_else(|| anyhow!("Ethereum node could not find block {}", number))?;
+        let hash = block
+            .hash
+            .ok_or_else(|| anyhow!("Ethereum node returned block {} without a hash", number))?;
+
+        let response = self
+            .web3
+            .transport()
+            .execute(
+                "debug_traceBlockByNumber",
+                vec![
+                    json::Value::String(format!("{:#x}", number)),
+                    debug_trace::tracer_config(),
+                ],
+            )
+            .await?;
+        debug_trace::block_traces(response, hash, number as u64, &block.transactions)
+    }
+
+    /// Get the latest finalized block with
+    /// `eth_getBlockByNumber("finalized")`. Return `None` if the provider
+    /// does not know about finalized blocks, e.g., because the chain does
+    /// not use proof-of-stake
+    pub async fn latest_finalized_block(&self, logger: &Logger) -> Result<Option<BlockPtr>, Error> {
+        let web3 = self.web3.cheap_clone();
+        retry("eth_getBlockByNumber(finalized) RPC call", logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                let web3 = web3.cheap_clone();
+                async move {
+                    let params = vec![
+                        json::Value::String("finalized".to_string()),
+                        json::Value::Bool(false),
+                    ];
+                    match web3
+                        .transport()
+                        .execute("eth_getBlockByNumber", params)
+                        .await
+                    {
+                        Ok(json::Value::Null) => Ok(None),
+                        Ok(value) => {
+                            let block: web3::types::Block<H256> = json::from_value(value)?;
+                            Ok(block
+                                .hash
+                                .zip(block.number)
+                                .map(|(hash, number)| BlockPtr::from((hash, number.as_u64()))))
+                        }
+                        // Nodes that do not support the `finalized` tag
+                        // reject it as an invalid argument
+                        Err(web3::Error::Rpc(_)) => Ok(None),
+                        Err(e) => Err(Error::from(e)),
+                    }
+                }
+            })
+            .await
+            .map_err(|e| {
+                e.into_inner().unwrap_or_else(|| {
+                    anyhow!("Ethereum node took too long to return the finalized block")
+                })
+            })
+    }
+
     // This is a lazy check for block receipt support. It is only called once and then the result is
     // cached. The result is not used for anything critical, so it is fine to be lazy.
     async fn check_block_receipt_support_and_update_cache(
@@ -277,6 +560,53 @@ impl EthereumAdapter {
         result
     }
 
+    /// Fetch the receipts for the transactions `hashes` in the block
+    /// `block_hash` with one `eth_getBlockReceipts` call. Return `None` if
+    /// the provider does not support that or did not return all the
+    /// receipts, in which case the receipts need to be fetched one by one.
+    /// If the provider reports that it does not know the method, we
+    /// remember that and stop using it
+    async fn receipts_from_block(
+        &self,
+        logger: &Logger,
+        block_hash: H256,
+        hashes: &HashSet<H256>,
+    ) -> Option<Vec<Arc<TransactionReceipt>>> {
+        let supported = self
+            .check_block_receipt_support_and_update_cache(
+                self.web3.cheap_clone(),
+                block_hash,
+                self.supports_eip_1898,
+                self.call_only,
+                logger.cheap_clone(),
+            )
+            .await;
+        if !supported {
+            return None;
+        }
+
+        let receipts = timeout(
+            ENV_VARS.json_rpc_timeout,
+            block_receipts(&*self.web3, block_hash, hashes),
+        )
+        .await;
+        match receipts {
+            Ok(Ok(receipts)) => receipts,
+            Ok(Err(e)) if is_method_not_found(&e) => {
+                warn!(logger, "Provider does not support eth_getBlockReceipts anymore, fetching receipts individually";
+                      "error" => e.to_string());
+                *self.supports_block_receipts.write().await = Some(false);
+                None
+            }
+            Ok(Err(e)) => {
+                debug!(logger, "eth_getBlockReceipts failed, fetching receipts individually";
+                       "block_hash" => format!("{:?}", block_hash), "error" => e.to_string());
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
     async fn logs_with_sigs(
         &self,
         logger: Logger,
@@ -308,18 +638,7 @@ impl EthereumAdapter {
 
                 async move {
                     let start = Instant::now();
-                    // Create a log filter
-                    let log_filter: Filter = FilterBuilder::default()
-                        .from_block(from.into())
-                        .to_block(to.into())
-                        .address(filter.contracts.clone())
-                        .topics(
-                            Some(filter.event_signatures.clone()),
-                            filter.topic1.clone(),
-                            filter.topic2.clone(),
-                            filter.topic3.clone(),
-                        )
-                        .build();
+                    let log_filter = get_logs_filter(from, to, &filter);
 
                     // Request logs from client
                     let result = eth_adapter.web3.eth().logs(log_filter).boxed().await;
@@ -570,11 +889,6 @@ impl EthereumAdapter {
         block_ptr: BlockPtr,
         gas: Option<u32>,
     ) -> Result<call::Retval, ContractCallError> {
-        fn reverted(logger: &Logger, reason: &str) -> Result<call::Retval, ContractCallError> {
-            info!(logger, "Contract call reverted"; "reason" => reason);
-            Ok(call::Retval::Null)
-        }
-
         let web3 = self.web3.clone();
         let logger = Logger::new(&logger, o!("provider" => self.provider.clone()));
 
@@ -584,132 +898,12 @@ impl EthereumAdapter {
             .limit(ENV_VARS.request_retries)
             .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
             .run(move || {
-                let call_data = call_data.clone();
+                let req = call_request(&call_data, gas);
                 let web3 = web3.cheap_clone();
                 let logger = logger.cheap_clone();
                 async move {
-                    let req = CallRequest {
-                        to: Some(call_data.address),
-                        gas: gas.map(|val| web3::types::U256::from(val)),
-                        data: Some(Bytes::from(call_data.encoded_call.to_vec())),
-                        from: None,
-                        gas_price: None,
-                        value: None,
-                        access_list: None,
-                        max_fee_per_gas: None,
-                        max_priority_fee_per_gas: None,
-                        transaction_type: None,
-                    };
                     let result = web3.eth().call(req, Some(block_id)).boxed().await;
-
-                    // Try to check if the call was reverted. The JSON-RPC response for reverts is
-                    // not standardized, so we have ad-hoc checks for each Ethereum client.
-
-                    // 0xfe is the "designated bad instruction" of the EVM, and Solidity uses it for
-                    // asserts.
-                    const PARITY_BAD_INSTRUCTION_FE: &str = "Bad instruction fe";
-
-                    // 0xfd is REVERT, but on some contracts, and only on older blocks,
-                    // this happens. Makes sense to consider it a revert as well.
-                    const PARITY_BAD_INSTRUCTION_FD: &str = "Bad instruction fd";
-
-                    const PARITY_BAD_JUMP_PREFIX: &str = "Bad jump";
-                    const PARITY_STACK_LIMIT_PREFIX: &str = "Out of stack";
-
-                    // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61.
-                    const PARITY_OUT_OF_GAS: &str = "Out of gas";
-
-                    const PARITY_VM_EXECUTION_ERROR: i64 = -32015;
-                    const PARITY_REVERT_PREFIX: &str = "Reverted 0x";
-                    const XDAI_REVERT: &str = "revert";
-
-                    // Deterministic Geth execution errors. We might need to expand this as
-                    // subgraphs come across other errors. See
-                    // https://github.com/ethereum/go-ethereum/blob/cd57d5cd38ef692de8fbedaa56598b4e9fbfbabc/core/vm/errors.go
-                    const GETH_EXECUTION_ERRORS: &[&str] = &[
-                        // The "revert" substring covers a few known error messages, including:
-                        // Hardhat: "error: transaction reverted",
-                        // Ganache and Moonbeam: "vm exception while processing transaction: revert",
-                        // Geth: "execution reverted"
-                        // And others.
-                        "revert",
-                        "invalid jump destination",
-                        "invalid opcode",
-                        // Ethereum says 1024 is the stack sizes limit, so this is deterministic.
-                        "stack limit reached 1024",
-                        // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61 for why the gas limit is considered deterministic.
-                        "out of gas",
-                        "stack underflow",
-                    ];
-
-                    let env_geth_call_errors = ENV_VARS.geth_eth_call_errors.iter();
-                    let mut geth_execution_errors = GETH_EXECUTION_ERRORS
-                        .iter()
-                        .copied()
-                        .chain(env_geth_call_errors.map(|s| s.as_str()));
-
-                    let as_solidity_revert_with_reason = |bytes: &[u8]| {
-                        let solidity_revert_function_selector =
-                            &tiny_keccak::keccak256(b"Error(string)")[..4];
-
-                        match bytes.len() >= 4 && &bytes[..4] == solidity_revert_function_selector {
-                            false => None,
-                            true => ethabi::decode(&[ParamType::String], &bytes[4..])
-                                .ok()
-                                .and_then(|tokens| tokens[0].clone().into_string()),
-                        }
-                    };
-
-                    match result {
-                        // A successful response.
-                        Ok(bytes) => Ok(call::Retval::Value(scalar::Bytes::from(bytes))),
-
-                        // Check for Geth revert.
-                        Err(web3::Error::Rpc(rpc_error))
-                            if geth_execution_errors
-                                .any(|e| rpc_error.message.to_lowercase().contains(e)) =>
-                        {
-                            reverted(&logger, &rpc_error.message)
-                        }
-
-                        // Check for Parity revert.
-                        Err(web3::Error::Rpc(ref rpc_error))
-                            if rpc_error.code.code() == PARITY_VM_EXECUTION_ERROR =>
-                        {
-                            match rpc_error.data.as_ref().and_then(|d| d.as_str()) {
-                                Some(data)
-                                    if data.starts_with(PARITY_REVERT_PREFIX)
-                                        || data.starts_with(PARITY_BAD_JUMP_PREFIX)
-                                        || data.starts_with(PARITY_STACK_LIMIT_PREFIX)
-                                        || data == PARITY_BAD_INSTRUCTION_FE
-                                        || data == PARITY_BAD_INSTRUCTION_FD
-                                        || data == PARITY_OUT_OF_GAS
-                                        || data == XDAI_REVERT =>
-                                {
-                                    let reason = if data == PARITY_BAD_INSTRUCTION_FE {
-                                        PARITY_BAD_INSTRUCTION_FE.to_owned()
-                                    } else {
-                                        let payload = data.trim_start_matches(PARITY_REVERT_PREFIX);
-                                        hex::decode(payload)
-                                            .ok()
-                                            .and_then(|payload| {
-                                                as_solidity_revert_with_reason(&payload)
-                                            })
-                                            .unwrap_or("no reason".to_owned())
-                                    };
-                                    reverted(&logger, &reason)
-                                }
-
-                                // The VM execution error was not identified as a revert.
-                                _ => Err(ContractCallError::Web3Error(web3::Error::Rpc(
-                                    rpc_error.clone(),
-                                ))),
-                            }
-                        }
-
-                        // The error was not identified as a revert.
-                        Err(err) => Err(ContractCallError::Web3Error(err)),
-                    }
+                    call_retval(&logger, result)
                 }
             })
             .map_err(|e| e.into_inner().unwrap_or(ContractCallError::Timeout))
@@ -732,21 +926,103 @@ impl EthereumAdapter {
                 call.gas,
             )
             .await?;
-        let _ = cache
-            .set_call(
-                &logger,
-                req.cheap_clone(),
-                call.block_ptr.cheap_clone(),
-                result.clone(),
-            )
-            .map_err(|e| {
-                error!(logger, "EthereumAdapter: call cache set error";
-                        "contract_address" => format!("{:?}", req.address),
-                        "error" => e.to_string())
-            });
+        Ok(cache_call(
+            logger,
+            cache.as_ref(),
+            req,
+            &call.block_ptr,
+            result,
+        ))
+    }
 
-        Ok(req.response(result, call::Source::Rpc))
+    /// Whether the Multicall3 contract at `address` exists at `block_ptr`
+    async fn multicall3_exists(
+        &self,
+        logger: &Logger,
+        address: Address,
+        block_ptr: &BlockPtr,
+    ) -> bool {
+        if let Some(exists) = self.multicall3.read().await.exists_at(block_ptr.number) {
+            return exists;
+        }
+
+        match self.code(logger, address, block_ptr.clone()).compat().await {
+            Ok(code) => {
+                let exists = !code.0.is_empty();
+                self.multicall3
+                    .write()
+                    .await
+                    .record(block_ptr.number, exists);
+                exists
+            }
+            Err(e) => {
+                debug!(logger, "Could not check whether Multicall3 exists";
+                       "block" => block_ptr.to_string(),
+                       "error" => e.to_string());
+                false
+            }
+        }
     }
+
+    /// Make the calls for `reqs` with one call to Multicall3 and cache
+    /// their results. All calls must be for `block_ptr`. Return the
+    /// responses and the requests for calls that need to be made
+    /// individually because Multicall3 does not exist at that block or the
+    /// call to it failed
+    async fn calls_in_multicall(
+        &self,
+        logger: &Logger,
+        calls: &[&ContractCall],
+        reqs: Vec<call::Request>,
+        block_ptr: &BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> (Vec<call::Response>, Vec<call::Request>) {
+        let address = match ENV_VARS.multicall3_address {
+            Some(address) => address,
+            None => return (Vec::new(), reqs),
+        };
+        if !self.multicall3_exists(logger, address, block_ptr).await {
+            return (Vec::new(), reqs);
+        }
+
+        let gas = reqs.iter().try_fold(0u32, |gas, req| {
+            calls[req.index as usize]
+                .gas
+                .map(|call_gas| gas.saturating_add(call_gas))
+        });
+        let req = call::Request::new(address, multicall::encode(&reqs), 0);
+        let output = match self.call(logger.clone(), req, block_ptr.clone(), gas).await {
+            Ok(call::Retval::Value(output)) => output,
+            Ok(_) => {
+                debug!(
+                    logger,
+                    "Call to Multicall3 reverted, making calls individually"
+                );
+                return (Vec::new(), reqs);
+            }
+            Err(e) => {
+                debug!(logger, "Call to Multicall3 failed, making calls individually";
+                       "error" => e.to_string());
+                return (Vec::new(), reqs);
+            }
+        };
+        let retvals = match multicall::decode(output.as_slice(), reqs.len()) {
+            Ok(retvals) => retvals,
+            Err(e) => {
+                debug!(logger, "Could not decode Multicall3 result, making calls individually";
+                       "error" => e.to_string());
+                return (Vec::new(), reqs);
+            }
+        };
+
+        let resps = reqs
+            .into_iter()
+            .zip(retvals)
+            .map(|(req, retval)| cache_call(logger, cache.as_ref(), req, block_ptr, retval))
+            .collect();
+        (resps, Vec::new())
+    }
+
     /// Request blocks by hash through JSON-RPC.
     fn load_blocks_rpc(
         &self,
@@ -858,6 +1134,8 @@ impl EthereumAdapter {
         let eth: Self = self.cheap_clone();
         let logger = logger.clone();
 
+        // The transport combines the requests for the filters into batch
+        // requests if batching is enabled for the provider
         futures03::stream::iter(log_filter.eth_get_logs_filters().map(move |filter| {
             eth.cheap_clone().log_stream(
                 logger.cheap_clone(),
@@ -922,6 +1200,38 @@ impl EthereumAdapter {
         )
     }
 
+    /// The triggers for internal ETH transfers to or from the addresses in
+    /// `transfer_filter` in the given block range
+    pub(crate) fn transfers_in_block_range<'a>(
+        &self,
+        logger: &Logger,
+        subgraph_metrics: Arc<SubgraphEthRpcMetrics>,
+        from: BlockNumber,
+        to: BlockNumber,
+        transfer_filter: &'a EthereumTransferFilter,
+    ) -> Box<dyn Stream<Item = EthereumTrigger, Error = Error> + Send + 'a> {
+        let started = transfer_filter
+            .addresses
+            .values()
+            .any(|start_block| start_block <= &to);
+        if !started && !transfer_filter.wildcard {
+            // No data source with transfer handlers has started in the
+            // requested range
+            return Box::new(stream::empty());
+        }
+
+        // `trace_filter` can not select traces by their sender and their
+        // recipient at the same time, so we need to get all the traces
+        Box::new(
+            self.trace_stream(logger, subgraph_metrics, from, to, vec![])
+                .filter_map(|trace| EthereumCall::try_from_trace(&trace))
+                .map(move |call| {
+                    stream::iter_ok::<_, Error>(transfer_filter.triggers(&Arc::new(call)))
+                })
+                .flatten(),
+        )
+    }
+
     // Used to get the block triggers with a `polling` or `once` filter
     /// `polling_filter_type` is used to differentiate between `polling` and `once` filters
     /// A `polling_filter_type` value of  `BlockPollingFilterType::Once` is the case for
@@ -1575,6 +1885,14 @@ impl EthereumAdapterTrait for EthereumAdapter {
                     info!(logger, "Contract call reverted"; "reason" => "empty response");
                     (None, call::Source::Rpc)
                 }
+                Reverted(reason) => {
+                    // The call was logged as reverted when we made it; only
+                    // log reverts that we remembered from earlier calls
+                    if source != call::Source::Rpc {
+                        info!(logger, "Contract call reverted"; "reason" => reason, "source" => source.to_string());
+                    }
+                    (None, source)
+                }
             }
         }
 
@@ -1614,6 +1932,16 @@ impl EthereumAdapterTrait for EthereumAdapter {
             .map_err(|e| error!(logger, "call cache get error"; "error" => e.to_string()))
             .unwrap_or_else(|_| (Vec::new(), reqs));
 
+        let missing = if missing.len() > 1 {
+            let (combined, missing) = self
+                .calls_in_multicall(logger, calls, missing, &block_ptr, cache.clone())
+                .await;
+            resps.extend(combined);
+            missing
+        } else {
+            missing
+        };
+
         let futs = missing.into_iter().map(|req| {
             let cache = cache.clone();
             async move {
@@ -1789,6 +2117,22 @@ pub(crate) async fn blocks_with_triggers(
         trigger_futs.push(calls_future)
     }
 
+    // Scan for internal ETH transfers
+    if !filter.transfer.is_empty() {
+        let transfers_future = eth
+            .transfers_in_block_range(
+                &logger,
+                subgraph_metrics.clone(),
+                from,
+                to,
+                &filter.transfer,
+            )
+            .collect()
+            .compat()
+            .boxed();
+        trigger_futs.push(transfers_future)
+    }
+
     if !filter.block.contract_addresses.is_empty() {
         // To determine which blocks include a call to addresses
         // in the block filter, transform the `block_filter` into
@@ -1905,7 +2249,7 @@ pub(crate) async fn get_calls(
             } else {
                 client
                     .rpc()?
-                    .cheapest_with(capabilities)
+                    .cheapest_with(capabilities, RequestClass::Traces)
                     .await?
                     .calls_in_block(
                         &logger,
@@ -1968,6 +2312,24 @@ pub(crate) fn parse_call_triggers(
     }
 }
 
+pub(crate) fn parse_transfer_triggers(
+    transfer_filter: &EthereumTransferFilter,
+    block: &EthereumBlockWithCalls,
+) -> anyhow::Result<Vec<EthereumTrigger>> {
+    if transfer_filter.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut triggers = Vec::new();
+    for call in block.calls.iter().flatten() {
+        if !call.internal_transfer || !block.transaction_for_call_succeeded(call)? {
+            continue;
+        }
+        triggers.extend(transfer_filter.triggers(&Arc::new(call.clone())));
+    }
+    Ok(triggers)
+}
+
 /// This method does not parse block triggers with `once` filters.
 /// This is because it is to be run before any other triggers are run.
 /// So we have `parse_initialization_triggers` for that.
@@ -2076,7 +2438,9 @@ async fn filter_call_triggers_from_unsuccessful_transactions(
         .trigger_data
         .iter()
         .filter_map(|trigger| match trigger {
-            EthereumTrigger::Call(call_trigger) => Some(call_trigger.transaction_hash),
+            EthereumTrigger::Call(call_trigger) | EthereumTrigger::Transfer(call_trigger, _) => {
+                Some(call_trigger.transaction_hash)
+            }
             _ => None,
         })
         .collect::<Option<BTreeSet<H256>>>()
@@ -2167,7 +2531,9 @@ async fn filter_call_triggers_from_unsuccessful_transactions(
 
     // Filter call triggers from unsuccessful transactions
     block.trigger_data.retain(|trigger| {
-        if let EthereumTrigger::Call(call_trigger) = trigger {
+        if let EthereumTrigger::Call(call_trigger) | EthereumTrigger::Transfer(call_trigger, _) =
+            trigger
+        {
             // Unwrap: We already checked that those values exist
             transaction_success[&call_trigger.transaction_hash.unwrap()]
         } else {
@@ -2194,6 +2560,194 @@ async fn filter_call_triggers_from_unsuccessful_transactions(
     Ok(block)
 }
 
+/// Remember the result of the call for `req` in `cache` and turn it into a
+/// response
+fn cache_call(
+    logger: &Logger,
+    cache: &dyn EthereumCallCache,
+    req: call::Request,
+    block_ptr: &BlockPtr,
+    retval: call::Retval,
+) -> call::Response {
+    let _ = cache
+        .set_call(
+            logger,
+            req.cheap_clone(),
+            block_ptr.cheap_clone(),
+            retval.clone(),
+        )
+        .map_err(|e| {
+            error!(logger, "EthereumAdapter: call cache set error";
+                    "contract_address" => format!("{:?}", req.address),
+                    "error" => e.to_string())
+        });
+
+    req.response(retval, call::Source::Rpc)
+}
+
+/// Whether `error` means that the provider does not know the method we
+/// called
+fn is_method_not_found(error: &web3::Error) -> bool {
+    const METHOD_NOT_FOUND: i64 = -32601;
+
+    match error {
+        web3::Error::Rpc(e) => {
+            let message = e.message.to_lowercase();
+            e.code.code() == METHOD_NOT_FOUND
+                || message.contains("method not found")
+                || message.contains("does not exist")
+                || message.contains("not supported")
+        }
+        _ => false,
+    }
+}
+
+fn get_logs_filter(from: BlockNumber, to: BlockNumber, filter: &EthGetLogsFilter) -> Filter {
+    FilterBuilder::default()
+        .from_block(from.into())
+        .to_block(to.into())
+        .address(filter.contracts.clone())
+        .topics(
+            Some(filter.event_signatures.clone()),
+            filter.topic1.clone(),
+            filter.topic2.clone(),
+            filter.topic3.clone(),
+        )
+        .build()
+}
+
+fn call_request(call_data: &call::Request, gas: Option<u32>) -> CallRequest {
+    CallRequest {
+        to: Some(call_data.address),
+        gas: gas.map(|val| web3::types::U256::from(val)),
+        data: Some(Bytes::from(call_data.encoded_call.to_vec())),
+        from: None,
+        gas_price: None,
+        value: None,
+        access_list: None,
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+        transaction_type: None,
+    }
+}
+
+/// Turn the response to an `eth_call` into a return value, treating the
+/// errors that clients return for reverted calls as reverts
+fn call_retval(
+    logger: &Logger,
+    result: Result<Bytes, web3::Error>,
+) -> Result<call::Retval, ContractCallError> {
+    fn reverted(logger: &Logger, reason: &str) -> Result<call::Retval, ContractCallError> {
+        info!(logger, "Contract call reverted"; "reason" => reason);
+        Ok(call::Retval::Reverted(reason.to_string()))
+    }
+
+    // Try to check if the call was reverted. The JSON-RPC response for reverts is
+    // not standardized, so we have ad-hoc checks for each Ethereum client.
+
+    // 0xfe is the "designated bad instruction" of the EVM, and Solidity uses it for
+    // asserts.
+    const PARITY_BAD_INSTRUCTION_FE: &str = "Bad instruction fe";
+
+    // 0xfd is REVERT, but on some contracts, and only on older blocks,
+    // this happens. Makes sense to consider it a revert as well.
+    const PARITY_BAD_INSTRUCTION_FD: &str = "Bad instruction fd";
+
+    const PARITY_BAD_JUMP_PREFIX: &str = "Bad jump";
+    const PARITY_STACK_LIMIT_PREFIX: &str = "Out of stack";
+
+    // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61.
+    const PARITY_OUT_OF_GAS: &str = "Out of gas";
+
+    const PARITY_VM_EXECUTION_ERROR: i64 = -32015;
+    const PARITY_REVERT_PREFIX: &str = "Reverted 0x";
+    const XDAI_REVERT: &str = "revert";
+
+    // Deterministic Geth execution errors. We might need to expand this as
+    // subgraphs come across other errors. See
+    // https://github.com/ethereum/go-ethereum/blob/cd57d5cd38ef692de8fbedaa56598b4e9fbfbabc/core/vm/errors.go
+    const GETH_EXECUTION_ERRORS: &[&str] = &[
+        // The "revert" substring covers a few known error messages, including:
+        // Hardhat: "error: transaction reverted",
+        // Ganache and Moonbeam: "vm exception while processing transaction: revert",
+        // Geth: "execution reverted"
+        // And others.
+        "revert",
+        "invalid jump destination",
+        "invalid opcode",
+        // Ethereum says 1024 is the stack sizes limit, so this is deterministic.
+        "stack limit reached 1024",
+        // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61 for why the gas limit is considered deterministic.
+        "out of gas",
+        "stack underflow",
+    ];
+
+    let env_geth_call_errors = ENV_VARS.geth_eth_call_errors.iter();
+    let mut geth_execution_errors = GETH_EXECUTION_ERRORS
+        .iter()
+        .copied()
+        .chain(env_geth_call_errors.map(|s| s.as_str()));
+
+    let as_solidity_revert_with_reason = |bytes: &[u8]| {
+        let solidity_revert_function_selector = &tiny_keccak::keccak256(b"Error(string)")[..4];
+
+        match bytes.len() >= 4 && &bytes[..4] == solidity_revert_function_selector {
+            false => None,
+            true => ethabi::decode(&[ParamType::String], &bytes[4..])
+                .ok()
+                .and_then(|tokens| tokens[0].clone().into_string()),
+        }
+    };
+
+    match result {
+        // A successful response.
+        Ok(bytes) => Ok(call::Retval::Value(scalar::Bytes::from(bytes))),
+
+        // Check for Geth revert.
+        Err(web3::Error::Rpc(rpc_error))
+            if geth_execution_errors.any(|e| rpc_error.message.to_lowercase().contains(e)) =>
+        {
+            reverted(logger, &rpc_error.message)
+        }
+
+        // Check for Parity revert.
+        Err(web3::Error::Rpc(ref rpc_error))
+            if rpc_error.code.code() == PARITY_VM_EXECUTION_ERROR =>
+        {
+            match rpc_error.data.as_ref().and_then(|d| d.as_str()) {
+                Some(data)
+                    if data.starts_with(PARITY_REVERT_PREFIX)
+                        || data.starts_with(PARITY_BAD_JUMP_PREFIX)
+                        || data.starts_with(PARITY_STACK_LIMIT_PREFIX)
+                        || data == PARITY_BAD_INSTRUCTION_FE
+                        || data == PARITY_BAD_INSTRUCTION_FD
+                        || data == PARITY_OUT_OF_GAS
+                        || data == XDAI_REVERT =>
+                {
+                    let reason = if data == PARITY_BAD_INSTRUCTION_FE {
+                        PARITY_BAD_INSTRUCTION_FE.to_owned()
+                    } else {
+                        let payload = data.trim_start_matches(PARITY_REVERT_PREFIX);
+                        hex::decode(payload)
+                            .ok()
+                            .and_then(|payload| as_solidity_revert_with_reason(&payload))
+                            .unwrap_or("no reason".to_owned())
+                    };
+                    reverted(logger, &reason)
+                }
+
+                // The VM execution error was not identified as a revert.
+                _ => Err(ContractCallError::Web3Error(web3::Error::Rpc(
+                    rpc_error.clone(),
+                ))),
+            }
+        }
+
+        // The error was not identified as a revert.
+        Err(err) => Err(ContractCallError::Web3Error(err)),
+    }
+}
+
 /// Deprecated. Wraps the [`fetch_transaction_receipts_in_batch`] in a retry loop.
 async fn fetch_transaction_receipts_in_batch_with_retry(
     web3: Arc<Web3<Transport>>,
@@ -2274,6 +2828,33 @@ pub(crate) async fn check_block_receipt_support(
     }
 }
 
+/// Get the receipts for the transactions `hashes` in the block
+/// `block_hash` with one `eth_getBlockReceipts` call. Return `None` if the
+/// provider does not know the block or did not return a receipt from that
+/// block for each of `hashes`
+async fn block_receipts(
+    web3: &Web3<impl web3::Transport>,
+    block_hash: H256,
+    hashes: &HashSet<H256>,
+) -> Result<Option<Vec<Arc<TransactionReceipt>>>, web3::Error> {
+    let receipts = match web3.eth().block_receipts(BlockId::Hash(block_hash)).await? {
+        Some(receipts) => receipts,
+        None => return Ok(None),
+    };
+
+    let receipts: Vec<_> = receipts
+        .into_iter()
+        .filter(|receipt| hashes.contains(&receipt.transaction_hash))
+        .filter(|receipt| receipt.block_hash == Some(block_hash))
+        .map(Arc::new)
+        .collect();
+    if receipts.len() == hashes.len() {
+        Ok(Some(receipts))
+    } else {
+        Ok(None)
+    }
+}
+
 // Fetches transaction receipts with retries. This function acts as a dispatcher
 // based on whether block receipts are supported or individual transaction receipts
 // need to be fetched.
@@ -2535,26 +3116,67 @@ async fn get_transaction_receipts_for_transaction_hashes(
     // later use this to check if we have collected the receipts from all required transactions.
     let mut unique_transaction_hashes: HashSet<&H256> = HashSet::new();
 
-    // Request transaction receipts concurrently
-    let receipt_futures = FuturesUnordered::new();
+    // For blocks with many transactions that we need receipts for, get all
+    // the receipts with one eth_getBlockReceipts call if the provider
+    // supports that
+    let start = Instant::now();
+    let min_transactions = ENV_VARS.block_receipts_min_transactions;
+    let logger_ref = &logger;
+    let block_receipts = join_all(
+        transaction_hashes_by_block
+            .iter()
+            .filter(|(_, transaction_hashes)| {
+                min_transactions > 0 && transaction_hashes.len() >= min_transactions
+            })
+            .map(|(block_hash, transaction_hashes)| async move {
+                let receipts = adapter
+                    .receipts_from_block(logger_ref, *block_hash, transaction_hashes)
+                    .await;
+                (*block_hash, receipts)
+            }),
+    )
+    .await;
+    let mut from_blocks = Vec::new();
+    let mut done = HashSet::new();
+    for (block_hash, receipts) in block_receipts {
+        if let Some(receipts) = receipts {
+            from_blocks.extend(receipts);
+            done.insert(block_hash);
+        }
+    }
 
-    let web3 = Arc::clone(&adapter.web3);
+    let mut hashes = Vec::new();
     for (block_hash, transaction_hashes) in transaction_hashes_by_block {
         for transaction_hash in transaction_hashes {
             unique_transaction_hashes.insert(transaction_hash);
-            let receipt_future = fetch_transaction_receipt_with_retry(
-                web3.cheap_clone(),
-                *transaction_hash,
-                *block_hash,
-                logger.cheap_clone(),
-            );
-            receipt_futures.push(receipt_future)
+            if !done.contains(block_hash) {
+                hashes.push((*block_hash, *transaction_hash));
+            }
         }
     }
 
-    // Execute futures while monitoring elapsed time
-    let start = Instant::now();
-    let receipts: Vec<_> = match receipt_futures.try_collect().await {
+    // Request the remaining transaction receipts concurrently while
+    // monitoring elapsed time. The transport combines these requests into
+    // batch requests if batching is enabled for the provider
+    let receipts = if hashes.is_empty() {
+        Ok(Vec::new())
+    } else {
+        let web3 = Arc::clone(&adapter.web3);
+        hashes
+            .into_iter()
+            .map(|(block_hash, transaction_hash)| {
+                fetch_transaction_receipt_with_retry(
+                    web3.cheap_clone(),
+                    transaction_hash,
+                    block_hash,
+                    logger.cheap_clone(),
+                )
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_collect()
+            .await
+    };
+    let receipts: Vec<_> = match receipts {
         Ok(receipts) => {
             let elapsed = start.elapsed().as_secs_f64();
             subgraph_metrics.observe_request(
@@ -2575,7 +3197,7 @@ async fn get_transaction_receipts_for_transaction_hashes(
     };
 
     // Build a map between transaction hashes and their receipts
-    for receipt in receipts.into_iter() {
+    for receipt in from_blocks.into_iter().chain(receipts) {
         if !unique_transaction_hashes.remove(&receipt.transaction_hash) {
             bail!("Received a receipt for a different transaction hash")
         }
@@ -2601,8 +3223,8 @@ mod tests {
     use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger};
 
     use super::{
-        check_block_receipt_support, parse_block_triggers, EthereumBlock, EthereumBlockFilter,
-        EthereumBlockWithCalls,
+        block_receipts, check_block_receipt_support, is_method_not_found, parse_block_triggers,
+        EthereumBlock, EthereumBlockFilter, EthereumBlockWithCalls,
     };
     use graph::blockchain::BlockPtr;
     use graph::prelude::ethabi::ethereum_types::U64;
@@ -2782,6 +3404,103 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn method_not_found() {
+        use graph::prelude::web3;
+        use jsonrpc_core::{Error as RpcError, ErrorCode};
+
+        fn rpc_error(code: ErrorCode, message: &str) -> web3::Error {
+            web3::Error::Rpc(RpcError {
+                code,
+                message: message.to_string(),
+                data: None,
+            })
+        }
+
+        assert!(is_method_not_found(&rpc_error(
+            ErrorCode::MethodNotFound,
+            "Method not found"
+        )));
+        // Some providers use a generic error code with a telling message
+        for message in [
+            "the method eth_getBlockReceipts does not exist/is not available",
+            "Method Not Found",
+            "eth_getBlockReceipts is not supported",
+        ] {
+            assert!(
+                is_method_not_found(&rpc_error(ErrorCode::ServerError(-32000), message)),
+                "`{}` means the method is not known",
+                message
+            );
+        }
+
+        assert!(!is_method_not_found(&rpc_error(
+            ErrorCode::ServerError(-32000),
+            "header not found"
+        )));
+        assert!(!is_method_not_found(&rpc_error(
+            ErrorCode::InternalError,
+            "request timed out"
+        )));
+        assert!(!is_method_not_found(&web3::Error::Unreachable));
+    }
+
+    #[tokio::test]
+    async fn block_receipts_for_transactions() {
+        fn receipt(block: u8, transaction: u8) -> Value {
+            serde_json::json!({
+                "blockHash": hash(block),
+                "blockNumber": "0x1",
+                "contractAddress": null,
+                "cumulativeGasUsed": "0x26f66",
+                "effectiveGasPrice": "0x140a1bd03",
+                "from": address(1),
+                "gasUsed": "0x26f66",
+                "logs": [],
+                "logsBloom": format!("0x{}", "0".repeat(512)),
+                "status": "0x1",
+                "to": address(2),
+                "transactionHash": hash(transaction),
+                "transactionIndex": format!("0x{:x}", transaction),
+                "type": "0x2"
+            })
+        }
+
+        let mut transport = TestTransport::default();
+        let web3 = Web3::new(transport.clone());
+        let all = serde_json::json!([receipt(1, 10), receipt(1, 11), receipt(1, 12)]);
+
+        // Only the receipts for the transactions we asked for are returned
+        transport.set_response(all.clone());
+        let wanted = HashSet::from_iter([hash(10), hash(12)]);
+        let receipts = block_receipts(&web3, hash(1), &wanted)
+            .await
+            .unwrap()
+            .expect("all receipts were found");
+        let found: HashSet<_> = receipts
+            .iter()
+            .map(|receipt| receipt.transaction_hash)
+            .collect();
+        assert_eq!(wanted, found);
+
+        // A transaction that is not in the block
+        transport.set_response(all);
+        let wanted = HashSet::from_iter([hash(10), hash(13)]);
+        let receipts = block_receipts(&web3, hash(1), &wanted).await.unwrap();
+        assert!(receipts.is_none());
+
+        // The provider returned receipts for a different block
+        transport.set_response(serde_json::json!([receipt(2, 10)]));
+        let wanted = HashSet::from_iter([hash(10)]);
+        let receipts = block_receipts(&web3, hash(1), &wanted).await.unwrap();
+        assert!(receipts.is_none());
+
+        // The provider does not know the block
+        transport.set_response(Value::Null);
+        let receipts = block_receipts(&web3, hash(1), &wanted).await.unwrap();
+        assert!(receipts.is_none());
+    }
+
     #[test]
     fn parse_block_triggers_specific_call_not_found() {
         let block = EthereumBlockWithCalls {
@@ -1,16 +1,21 @@
 mod adapter;
+mod beacon;
 mod buffered_call_cache;
 mod capabilities;
 pub mod codec;
 mod data_source;
+mod debug_trace;
 mod env;
 mod ethereum_adapter;
 mod ingestor;
+mod multicall;
+mod rollup;
 pub mod runtime;
 mod transport;
 
 pub use self::capabilities::NodeCapabilities;
-pub use self::ethereum_adapter::EthereumAdapter;
+pub use self::ethereum_adapter::{EthereumAdapter, TraceMethod};
+pub use self::rollup::{Rollup, RollupBlockData};
 pub use self::runtime::RuntimeAdapter;
 pub use self::transport::Transport;
 pub use env::ENV_VARS;
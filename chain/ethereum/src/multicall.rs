@@ -0,0 +1,189 @@
+//! Combining several `eth_call`s for the same block into one call to the
+//! `aggregate3` function of the [Multicall3](https://www.multicall3.com)
+//! contract. Multicall3 is deployed at the same address on most chains, but
+//! we can not rely on it being there, and only use it for blocks at which
+//! the contract exists
+use graph::data::store::ethereum::call;
+use graph::data::store::scalar;
+use graph::prelude::ethabi::{self, ParamType, Token};
+use graph::prelude::{anyhow, tiny_keccak, BlockNumber, Error};
+
+const AGGREGATE3: &[u8] = b"aggregate3((address,bool,bytes)[])";
+
+fn result_type() -> ParamType {
+    ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Bool,
+        ParamType::Bytes,
+    ])))
+}
+
+/// What we know about the blocks at which Multicall3 exists on a chain.
+/// Once deployed, the contract stays in place, so that it exists at all
+/// blocks after one where we found it, and did not exist at any block
+/// before one where we did not find it
+#[derive(Debug, Default)]
+pub(crate) struct Deployment {
+    absent_at: Option<BlockNumber>,
+    present_at: Option<BlockNumber>,
+}
+
+impl Deployment {
+    /// Whether Multicall3 exists at block `number`, or `None` if we need to
+    /// check with the provider
+    pub fn exists_at(&self, number: BlockNumber) -> Option<bool> {
+        match (self.absent_at, self.present_at) {
+            (_, Some(present)) if number >= present => Some(true),
+            (Some(absent), _) if number <= absent => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn record(&mut self, number: BlockNumber, exists: bool) {
+        if exists {
+            self.present_at = Some(self.present_at.map_or(number, |n| n.min(number)));
+        } else {
+            self.absent_at = Some(self.absent_at.map_or(number, |n| n.max(number)));
+        }
+    }
+}
+
+/// Encode a call to `aggregate3` that makes all the calls in `reqs`,
+/// allowing each of them to fail
+pub(crate) fn encode(reqs: &[call::Request]) -> Vec<u8> {
+    let calls = reqs
+        .iter()
+        .map(|req| {
+            Token::Tuple(vec![
+                Token::Address(req.address),
+                Token::Bool(true),
+                Token::Bytes(req.encoded_call.to_vec()),
+            ])
+        })
+        .collect();
+
+    let mut data = tiny_keccak::keccak256(AGGREGATE3)[..4].to_vec();
+    data.extend(ethabi::encode(&[Token::Array(calls)]));
+    data
+}
+
+/// Decode the output of a call to `aggregate3` into the return values of
+/// the `count` individual calls. Calls that failed are treated as reverts
+pub(crate) fn decode(output: &[u8], count: usize) -> Result<Vec<call::Retval>, Error> {
+    let results = ethabi::decode(&[result_type()], output)?
+        .pop()
+        .and_then(Token::into_array)
+        .ok_or_else(|| anyhow!("Multicall3 returned an unexpected result"))?;
+    if results.len() != count {
+        return Err(anyhow!(
+            "Multicall3 returned {} results for {} calls",
+            results.len(),
+            count
+        ));
+    }
+
+    results
+        .into_iter()
+        .map(|result| {
+            let (success, data) = result
+                .into_tuple()
+                .and_then(|mut fields| {
+                    let data = fields.pop()?.into_bytes()?;
+                    let success = fields.pop()?.into_bool()?;
+                    Some((success, data))
+                })
+                .ok_or_else(|| anyhow!("Multicall3 returned an unexpected result"))?;
+            if success {
+                Ok(call::Retval::Value(scalar::Bytes::from(data)))
+            } else {
+                Ok(call::Retval::Reverted(revert_reason(&data)))
+            }
+        })
+        .collect()
+}
+
+/// The reason for a revert from the data the call returned, which is the
+/// message for Solidity's `Error(string)` and the hex-encoded data otherwise
+fn revert_reason(data: &[u8]) -> String {
+    let selector = &tiny_keccak::keccak256(b"Error(string)")[..4];
+    if data.len() >= 4 && &data[..4] == selector {
+        if let Some(reason) = ethabi::decode(&[ParamType::String], &data[4..])
+            .ok()
+            .and_then(|mut tokens| tokens.pop())
+            .and_then(Token::into_string)
+        {
+            return reason;
+        }
+    }
+    format!("0x{}", hex::encode(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::web3::types::Address;
+
+    use super::*;
+
+    #[test]
+    fn encodes_aggregate3() {
+        let reqs = vec![
+            call::Request::new(Address::from_low_u64_be(1), vec![1, 2, 3, 4], 0),
+            call::Request::new(Address::from_low_u64_be(2), vec![5, 6, 7, 8], 1),
+        ];
+        let data = encode(&reqs);
+        assert_eq!(hex::decode("82ad56cb").unwrap(), data[..4]);
+
+        let param = ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Address,
+            ParamType::Bool,
+            ParamType::Bytes,
+        ])));
+        let calls = ethabi::decode(&[param], &data[4..])
+            .unwrap()
+            .pop()
+            .and_then(Token::into_array)
+            .unwrap();
+        assert_eq!(
+            Token::Tuple(vec![
+                Token::Address(Address::from_low_u64_be(2)),
+                Token::Bool(true),
+                Token::Bytes(vec![5, 6, 7, 8]),
+            ]),
+            calls[1]
+        );
+    }
+
+    #[test]
+    fn decodes_results() {
+        let mut reason = tiny_keccak::keccak256(b"Error(string)")[..4].to_vec();
+        reason.extend(ethabi::encode(&[Token::String("no".to_string())]));
+        let output = ethabi::encode(&[Token::Array(vec![
+            Token::Tuple(vec![Token::Bool(true), Token::Bytes(vec![1, 2])]),
+            Token::Tuple(vec![Token::Bool(false), Token::Bytes(reason)]),
+            Token::Tuple(vec![Token::Bool(false), Token::Bytes(vec![0xab])]),
+        ])]);
+
+        let retvals = decode(&output, 3).unwrap();
+        assert_eq!(
+            vec![
+                call::Retval::Value(scalar::Bytes::from(vec![1, 2])),
+                call::Retval::Reverted("no".to_string()),
+                call::Retval::Reverted("0xab".to_string()),
+            ],
+            retvals
+        );
+        assert!(decode(&output, 2).is_err());
+    }
+
+    #[test]
+    fn tracks_deployment() {
+        let mut deployment = Deployment::default();
+        assert_eq!(None, deployment.exists_at(10));
+        deployment.record(10, false);
+        deployment.record(20, true);
+        assert_eq!(Some(false), deployment.exists_at(5));
+        assert_eq!(Some(false), deployment.exists_at(10));
+        assert_eq!(None, deployment.exists_at(15));
+        assert_eq!(Some(true), deployment.exists_at(20));
+        assert_eq!(Some(true), deployment.exists_at(30));
+    }
+}
@@ -1,9 +1,9 @@
 use anyhow::{anyhow, bail};
 use graph::blockchain::ChainIdentifier;
 use graph::components::adapter::{ChainId, NetIdentifiable, ProviderManager, ProviderName};
-use graph::endpoint::EndpointMetrics;
+use graph::endpoint::{EndpointMetrics, RequestClass};
 use graph::firehose::{AvailableCapacity, SubgraphLimit};
-use graph::prelude::rand::seq::IteratorRandom;
+use graph::prelude::rand::seq::SliceRandom;
 use graph::prelude::rand::{self, Rng};
 use std::sync::Arc;
 
@@ -65,6 +65,12 @@ impl EthereumNetworkAdapter {
     pub fn current_error_count(&self) -> u64 {
         self.endpoint_metrics.get_count(&self.provider().into())
     }
+
+    /// The current score of this adapter for requests of class `class`;
+    /// lower is better
+    pub fn score(&self, class: RequestClass) -> f64 {
+        self.endpoint_metrics.score(&self.provider().into(), class)
+    }
     pub fn provider(&self) -> &str {
         self.adapter.provider()
     }
@@ -183,30 +189,32 @@ impl EthereumNetworkAdapters {
     // handle adapter selection from a list, implements the availability checking with an abstracted
     // source of the adapter list.
     fn cheapest_from(
-        input: Vec<&EthereumNetworkAdapter>,
+        mut input: Vec<&EthereumNetworkAdapter>,
         required_capabilities: &NodeCapabilities,
+        class: RequestClass,
         retest_percent: f64,
     ) -> Result<Arc<EthereumAdapter>, Error> {
         let retest_rng: f64 = (&mut rand::thread_rng()).gen();
 
-        let cheapest = input
-            .into_iter()
-            .choose_multiple(&mut rand::thread_rng(), 3);
-        let cheapest = cheapest.iter();
+        // Shuffle so that load is spread across adapters with the same score
+        input.shuffle(&mut rand::thread_rng());
+        let by_score = |a: &&EthereumNetworkAdapter, b: &&EthereumNetworkAdapter| {
+            a.score(class).total_cmp(&b.score(class))
+        };
 
         // If request falls below the retest threshold, use this request to try and
-        // reset the failed adapter. If a request succeeds the adapter will be more
-        // likely to be selected afterwards.
+        // reset a badly scored adapter. If a request succeeds the adapter's score
+        // improves and it will be more likely to be selected afterwards.
         if retest_rng < retest_percent {
-            cheapest.max_by_key(|adapter| adapter.current_error_count())
+            input.into_iter().take(3).max_by(by_score)
         } else {
             // The assumption here is that most RPC endpoints will not have limits
             // which makes the check for low/high available capacity less relevant.
             // So we essentially assume if it had available capacity when calling
             // `all_cheapest_with` then it prolly maintains that state and so we
-            // just select whichever adapter is working better according to
-            // the number of errors.
-            cheapest.min_by_key(|adapter| adapter.current_error_count())
+            // just select whichever adapter currently has the best score for
+            // this class of requests.
+            input.into_iter().min_by(by_score)
         }
         .map(|adapter| adapter.adapter.clone())
         .ok_or(anyhow!(
@@ -218,27 +226,62 @@ impl EthereumNetworkAdapters {
     pub(crate) fn unverified_cheapest_with(
         &self,
         required_capabilities: &NodeCapabilities,
+        class: RequestClass,
     ) -> Result<Arc<EthereumAdapter>, Error> {
         let cheapest = self.all_unverified_cheapest_with(required_capabilities);
 
         Self::cheapest_from(
-            cheapest.choose_multiple(&mut rand::thread_rng(), 3),
+            cheapest.collect(),
             required_capabilities,
+            class,
+            self.retest_percent,
+        )
+    }
+
+    /// The best adapter among the ones that have a beacon API and can
+    /// therefore get the blobs of blob transactions. Like
+    /// `unverified_cheapest_with`, this does not validate providers
+    pub(crate) fn unverified_cheapest_with_blobs(&self) -> Result<Arc<EthereumAdapter>, Error> {
+        let with_beacon: Vec<_> = self
+            .manager
+            .get_all_unverified(&self.chain_id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|adapter| adapter.adapter.has_beacon())
+            .filter(|adapter| adapter.get_capacity() > AvailableCapacity::Unavailable)
+            .collect();
+        if with_beacon.is_empty() {
+            bail!(
+                "no available provider for network {} has a beacon API to get blobs from",
+                self.chain_id
+            );
+        }
+
+        Self::cheapest_from(
+            with_beacon,
+            &NodeCapabilities {
+                archive: false,
+                traces: false,
+            },
+            RequestClass::Calls,
             self.retest_percent,
         )
     }
 
-    /// This is the public entry point and should always use verified adapters
+    /// This is the public entry point and should always use verified adapters.
+    /// Among the adapters with the required capabilities, it returns the one
+    /// with the best score for requests of class `class`
     pub async fn cheapest_with(
         &self,
         required_capabilities: &NodeCapabilities,
+        class: RequestClass,
     ) -> Result<Arc<EthereumAdapter>, Error> {
         let cheapest = self
             .all_cheapest_with(required_capabilities)
             .await
-            .choose_multiple(&mut rand::thread_rng(), 3);
+            .collect();
 
-        Self::cheapest_from(cheapest, required_capabilities, self.retest_percent)
+        Self::cheapest_from(cheapest, required_capabilities, class, self.retest_percent)
     }
 
     pub async fn cheapest(&self) -> Option<Arc<EthereumAdapter>> {
@@ -258,18 +301,21 @@ impl EthereumNetworkAdapters {
         &self,
         capabilities: Option<&NodeCapabilities>,
     ) -> anyhow::Result<Arc<EthereumAdapter>> {
+        // We only use this to make calls, so pick the adapter that is best
+        // at those
         // call_only_adapter can fail if we're out of capcity, this is fine since
         // we would want to fallback onto a full adapter
         // so we will ignore this error and return whatever comes out of `cheapest_with`
         match self.call_only_adapter() {
             Ok(Some(adapter)) => Ok(adapter),
-            _ => {
-                self.unverified_cheapest_with(capabilities.unwrap_or(&NodeCapabilities {
+            _ => self.unverified_cheapest_with(
+                capabilities.unwrap_or(&NodeCapabilities {
                     // Archive is required for call_only
                     archive: true,
                     traces: false,
-                }))
-            }
+                }),
+                RequestClass::Calls,
+            ),
         }
     }
 
@@ -304,6 +350,7 @@ mod tests {
     use graph::cheap_clone::CheapClone;
     use graph::components::adapter::{MockIdentValidator, ProviderManager, ProviderName};
     use graph::data::value::Word;
+    use graph::endpoint::RequestClass;
     use graph::http::HeaderMap;
     use graph::{
         endpoint::EndpointMetrics,
@@ -440,19 +487,25 @@ mod tests {
         {
             // Not Found
             assert!(adapters
-                .cheapest_with(&NodeCapabilities {
-                    archive: false,
-                    traces: true,
-                })
+                .cheapest_with(
+                    &NodeCapabilities {
+                        archive: false,
+                        traces: true,
+                    },
+                    RequestClass::Other
+                )
                 .await
                 .is_err());
 
             // Check cheapest is not call only
             let adapter = adapters
-                .cheapest_with(&NodeCapabilities {
-                    archive: true,
-                    traces: false,
-                })
+                .cheapest_with(
+                    &NodeCapabilities {
+                        archive: true,
+                        traces: false,
+                    },
+                    RequestClass::Other,
+                )
                 .await
                 .unwrap();
             assert_eq!(adapter.is_call_only(), false);
@@ -764,10 +817,13 @@ mod tests {
 
         assert_eq!(
             no_retest_adapters
-                .cheapest_with(&NodeCapabilities {
-                    archive: true,
-                    traces: false,
-                })
+                .cheapest_with(
+                    &NodeCapabilities {
+                        archive: true,
+                        traces: false,
+                    },
+                    RequestClass::Other
+                )
                 .await
                 .unwrap()
                 .provider(),
@@ -775,10 +831,13 @@ mod tests {
         );
         assert_eq!(
             always_retest_adapters
-                .cheapest_with(&NodeCapabilities {
-                    archive: true,
-                    traces: false,
-                })
+                .cheapest_with(
+                    &NodeCapabilities {
+                        archive: true,
+                        traces: false,
+                    },
+                    RequestClass::Other
+                )
                 .await
                 .unwrap()
                 .provider(),
@@ -857,10 +916,13 @@ mod tests {
         );
         assert_eq!(
             always_retest_adapters
-                .cheapest_with(&NodeCapabilities {
-                    archive: true,
-                    traces: false,
-                })
+                .cheapest_with(
+                    &NodeCapabilities {
+                        archive: true,
+                        traces: false,
+                    },
+                    RequestClass::Other
+                )
                 .await
                 .unwrap()
                 .provider(),
@@ -881,10 +943,13 @@ mod tests {
             EthereumNetworkAdapters::new(chain_id.clone(), manager, vec![], Some(0f64));
         assert_eq!(
             no_retest_adapters
-                .cheapest_with(&NodeCapabilities {
-                    archive: true,
-                    traces: false,
-                })
+                .cheapest_with(
+                    &NodeCapabilities {
+                        archive: true,
+                        traces: false,
+                    },
+                    RequestClass::Other
+                )
                 .await
                 .unwrap()
                 .provider(),
@@ -921,10 +986,13 @@ mod tests {
 
         let no_available_adapter = EthereumNetworkAdapters::new(chain_id, manager, vec![], None);
         let res = no_available_adapter
-            .cheapest_with(&NodeCapabilities {
-                archive: true,
-                traces: false,
-            })
+            .cheapest_with(
+                &NodeCapabilities {
+                    archive: true,
+                    traces: false,
+                },
+                RequestClass::Other,
+            )
             .await;
         assert!(res.is_err(), "{:?}", res);
     }
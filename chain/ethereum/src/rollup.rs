@@ -0,0 +1,268 @@
+//! Rollup-specific information about L2 blocks. Both Arbitrum and OP-stack
+//! chains start every block with a system transaction that records which L1
+//! block the L2 block was built on; we decode that transaction so that
+//! mappings can see the L1 block without making any calls
+use std::str::FromStr;
+
+use graph::prelude::ethabi::{self, ParamType};
+use graph::prelude::web3::types::{Block, Transaction, H160, H256};
+use graph::prelude::{anyhow, tiny_keccak, Error};
+
+/// The kind of rollup a chain is. Which chains are rollups is set with
+/// `GRAPH_ETHEREUM_ROLLUP_CHAINS`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rollup {
+    Arbitrum,
+    OpStack,
+}
+
+impl FromStr for Rollup {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "arbitrum" => Ok(Rollup::Arbitrum),
+            "op-stack" => Ok(Rollup::OpStack),
+            _ => Err(anyhow!(
+                "unknown rollup `{}`, must be one of `arbitrum` or `op-stack`",
+                s
+            )),
+        }
+    }
+}
+
+/// The L1 information for an L2 block. Arbitrum only tells us the L1 block
+/// number; OP-stack chains also include the timestamp and hash of the L1
+/// block and the position of the L2 block in the sequencing epoch for that
+/// L1 block
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RollupBlockData {
+    pub l1_block_number: u64,
+    pub l1_timestamp: Option<u64>,
+    pub l1_block_hash: Option<H256>,
+    pub sequence_number: Option<u64>,
+}
+
+/// ArbOS, which sends the `startBlock` transaction at the start of every
+/// Arbitrum block
+const ARBOS_ADDRESS: H160 = H160([
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x0a, 0x4b, 0x05,
+]);
+const ARBITRUM_START_BLOCK: &[u8] = b"startBlock(uint256,uint64,uint64,uint64)";
+
+/// The `L1Block` predeploy that the first transaction of every OP-stack
+/// block updates
+const OP_L1_BLOCK_ADDRESS: H160 = H160([
+    0x42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x15,
+]);
+const OP_SET_L1_BLOCK_VALUES: &[u8] =
+    b"setL1BlockValues(uint64,uint64,uint256,bytes32,uint64,bytes32,uint256,uint256)";
+/// Since the Ecotone upgrade, the L1 values are tightly packed after the
+/// function selector
+const OP_PACKED_LEN: usize = 4 + 160;
+
+impl Rollup {
+    /// Decode the L1 information from the system transaction at the start
+    /// of `block`. Return `None` if the block does not start with such a
+    /// transaction
+    pub fn block_data(&self, block: &Block<Transaction>) -> Option<RollupBlockData> {
+        let tx = block.transactions.first()?;
+        match self {
+            Rollup::Arbitrum => arbitrum_block_data(tx),
+            Rollup::OpStack => op_stack_block_data(tx),
+        }
+    }
+}
+
+fn selector(signature: &[u8]) -> [u8; 4] {
+    let hash = tiny_keccak::keccak256(signature);
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn arbitrum_block_data(tx: &Transaction) -> Option<RollupBlockData> {
+    let input = &tx.input.0;
+    if tx.to != Some(ARBOS_ADDRESS)
+        || input.len() < 4
+        || input[..4] != selector(ARBITRUM_START_BLOCK)
+    {
+        return None;
+    }
+
+    let params = [
+        ParamType::Uint(256),
+        ParamType::Uint(64),
+        ParamType::Uint(64),
+        ParamType::Uint(64),
+    ];
+    let l1_block_number = ethabi::decode(&params, &input[4..])
+        .ok()?
+        .into_iter()
+        .nth(1)?
+        .into_uint()?;
+    Some(RollupBlockData {
+        l1_block_number: l1_block_number.low_u64(),
+        ..Default::default()
+    })
+}
+
+fn op_stack_block_data(tx: &Transaction) -> Option<RollupBlockData> {
+    let input = &tx.input.0;
+    if tx.to != Some(OP_L1_BLOCK_ADDRESS) || input.len() < 4 {
+        return None;
+    }
+
+    if input[..4] == selector(OP_SET_L1_BLOCK_VALUES) {
+        let params = [
+            ParamType::Uint(64),
+            ParamType::Uint(64),
+            ParamType::Uint(256),
+            ParamType::FixedBytes(32),
+            ParamType::Uint(64),
+        ];
+        let mut tokens = ethabi::decode(&params, &input[4..]).ok()?.into_iter();
+        let number = tokens.next()?.into_uint()?;
+        let timestamp = tokens.next()?.into_uint()?;
+        let hash = tokens.nth(1)?.into_fixed_bytes()?;
+        let sequence_number = tokens.next()?.into_uint()?;
+        return Some(RollupBlockData {
+            l1_block_number: number.low_u64(),
+            l1_timestamp: Some(timestamp.low_u64()),
+            l1_block_hash: Some(H256::from_slice(&hash)),
+            sequence_number: Some(sequence_number.low_u64()),
+        });
+    }
+
+    // The packed layout is `baseFeeScalar (uint32)`, `blobBaseFeeScalar
+    // (uint32)`, `sequenceNumber (uint64)`, `timestamp (uint64)`, `number
+    // (uint64)`, `basefee (uint256)`, `blobBaseFee (uint256)`, `hash
+    // (bytes32)` and `batcherHash (bytes32)`. Later upgrades only add
+    // fields at the end
+    if input.len() < OP_PACKED_LEN {
+        return None;
+    }
+    let data = &input[4..];
+    let u64_at = |offset: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&data[offset..offset + 8]);
+        u64::from_be_bytes(bytes)
+    };
+    Some(RollupBlockData {
+        l1_block_number: u64_at(24),
+        l1_timestamp: Some(u64_at(16)),
+        l1_block_hash: Some(H256::from_slice(&data[96..128])),
+        sequence_number: Some(u64_at(8)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::ethabi::Token;
+    use graph::prelude::web3::types::{Bytes, U256};
+
+    use super::*;
+
+    fn encode_call(signature: &[u8], tokens: &[Token]) -> Vec<u8> {
+        let mut input = selector(signature).to_vec();
+        input.extend(ethabi::encode(tokens));
+        input
+    }
+
+    fn block(to: H160, input: Vec<u8>) -> Block<Transaction> {
+        Block {
+            transactions: vec![Transaction {
+                to: Some(to),
+                input: Bytes(input),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn arbitrum() {
+        let input = encode_call(
+            ARBITRUM_START_BLOCK,
+            &[
+                Token::Uint(U256::from(7)),
+                Token::Uint(U256::from(19_000_000)),
+                Token::Uint(U256::from(200_000_000)),
+                Token::Uint(U256::from(12)),
+            ],
+        );
+        let data = Rollup::Arbitrum
+            .block_data(&block(ARBOS_ADDRESS, input.clone()))
+            .unwrap();
+        assert_eq!(19_000_000, data.l1_block_number);
+        assert_eq!(None, data.l1_timestamp);
+
+        assert_eq!(
+            None,
+            Rollup::Arbitrum.block_data(&block(OP_L1_BLOCK_ADDRESS, input))
+        );
+        assert_eq!(None, Rollup::Arbitrum.block_data(&Block::default()));
+    }
+
+    #[test]
+    fn op_stack_bedrock() {
+        let hash = H256::from_low_u64_be(99);
+        let input = encode_call(
+            OP_SET_L1_BLOCK_VALUES,
+            &[
+                Token::Uint(U256::from(18_000_000)),
+                Token::Uint(U256::from(1_700_000_000)),
+                Token::Uint(U256::from(30)),
+                Token::FixedBytes(hash.as_bytes().to_vec()),
+                Token::Uint(U256::from(3)),
+                Token::FixedBytes(vec![0; 32]),
+                Token::Uint(U256::from(188)),
+                Token::Uint(U256::from(684000)),
+            ],
+        );
+        let data = Rollup::OpStack
+            .block_data(&block(OP_L1_BLOCK_ADDRESS, input))
+            .unwrap();
+        assert_eq!(
+            RollupBlockData {
+                l1_block_number: 18_000_000,
+                l1_timestamp: Some(1_700_000_000),
+                l1_block_hash: Some(hash),
+                sequence_number: Some(3),
+            },
+            data
+        );
+    }
+
+    #[test]
+    fn op_stack_packed() {
+        let hash = H256::from_low_u64_be(42);
+        let mut input = hex::decode("440a5e20").unwrap();
+        input.extend(1368u32.to_be_bytes());
+        input.extend(810949u32.to_be_bytes());
+        input.extend(5u64.to_be_bytes());
+        input.extend(1_710_000_000u64.to_be_bytes());
+        input.extend(19_400_000u64.to_be_bytes());
+        input.extend([0u8; 32]);
+        input.extend([0u8; 32]);
+        input.extend(hash.as_bytes());
+        input.extend([0u8; 32]);
+
+        let data = Rollup::OpStack
+            .block_data(&block(OP_L1_BLOCK_ADDRESS, input.clone()))
+            .unwrap();
+        assert_eq!(
+            RollupBlockData {
+                l1_block_number: 19_400_000,
+                l1_timestamp: Some(1_710_000_000),
+                l1_block_hash: Some(hash),
+                sequence_number: Some(5),
+            },
+            data
+        );
+
+        input.truncate(100);
+        assert_eq!(
+            None,
+            Rollup::OpStack.block_data(&block(OP_L1_BLOCK_ADDRESS, input))
+        );
+    }
+}
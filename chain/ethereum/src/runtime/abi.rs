@@ -1,6 +1,7 @@
 use super::runtime_adapter::UnresolvedContractCall;
 use crate::trigger::{
     EthereumBlockData, EthereumCallData, EthereumEventData, EthereumTransactionData,
+    EthereumTransferData,
 };
 use graph::{
     prelude::{
@@ -223,6 +224,34 @@ impl AscIndexId for AscEthereumBlock_0_0_6 {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumBlock;
 }
 
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumBlock_0_0_9 {
+    pub hash: AscPtr<AscH256>,
+    pub parent_hash: AscPtr<AscH256>,
+    pub uncles_hash: AscPtr<AscH256>,
+    pub author: AscPtr<AscH160>,
+    pub state_root: AscPtr<AscH256>,
+    pub transactions_root: AscPtr<AscH256>,
+    pub receipts_root: AscPtr<AscH256>,
+    pub number: AscPtr<AscBigInt>,
+    pub gas_used: AscPtr<AscBigInt>,
+    pub gas_limit: AscPtr<AscBigInt>,
+    pub timestamp: AscPtr<AscBigInt>,
+    pub difficulty: AscPtr<AscBigInt>,
+    pub total_difficulty: AscPtr<AscBigInt>,
+    pub size: AscPtr<AscBigInt>,
+    pub base_fee_per_block: AscPtr<AscBigInt>,
+    pub l1_block_number: AscPtr<AscBigInt>,
+    pub l1_timestamp: AscPtr<AscBigInt>,
+    pub l1_block_hash: AscPtr<AscH256>,
+    pub sequence_number: AscPtr<AscBigInt>,
+}
+
+impl AscIndexId for AscEthereumBlock_0_0_9 {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumBlock;
+}
+
 #[repr(C)]
 #[derive(AscType)]
 pub(crate) struct AscEthereumTransaction_0_0_1 {
@@ -274,6 +303,28 @@ impl AscIndexId for AscEthereumTransaction_0_0_6 {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumTransaction;
 }
 
+/// Introduced in API Version 0.0.9, this is the same as
+/// [`AscEthereumTransaction_0_0_6`] with an added `transaction_type` field
+/// so that mappings can recognize blob transactions
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumTransaction_0_0_9 {
+    pub hash: AscPtr<AscH256>,
+    pub index: AscPtr<AscBigInt>,
+    pub from: AscPtr<AscH160>,
+    pub to: AscPtr<AscH160>,
+    pub value: AscPtr<AscBigInt>,
+    pub gas_limit: AscPtr<AscBigInt>,
+    pub gas_price: AscPtr<AscBigInt>,
+    pub input: AscPtr<Uint8Array>,
+    pub nonce: AscPtr<AscBigInt>,
+    pub transaction_type: AscPtr<AscBigInt>,
+}
+
+impl AscIndexId for AscEthereumTransaction_0_0_9 {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumTransaction;
+}
+
 #[repr(C)]
 #[derive(AscType)]
 pub(crate) struct AscEthereumEvent<T, B>
@@ -365,6 +416,10 @@ impl AscIndexId for AscEthereumEvent_0_0_7<AscEthereumTransaction_0_0_6, AscEthe
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumEvent;
 }
 
+impl AscIndexId for AscEthereumEvent_0_0_7<AscEthereumTransaction_0_0_9, AscEthereumBlock_0_0_6> {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumEvent;
+}
+
 #[repr(C)]
 #[derive(AscType)]
 pub(crate) struct AscLogParam {
@@ -413,6 +468,20 @@ where
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumCall;
 }
 
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumTransfer {
+    pub from: AscPtr<AscAddress>,
+    pub to: AscPtr<AscAddress>,
+    pub value: AscPtr<AscBigInt>,
+    pub block: AscPtr<AscEthereumBlock_0_0_6>,
+    pub transaction: AscPtr<AscEthereumTransaction_0_0_9>,
+}
+
+impl AscIndexId for AscEthereumTransfer {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumTransfer;
+}
+
 impl ToAscObj<AscEthereumBlock> for EthereumBlockData {
     fn to_asc_obj<H: AscHeap + ?Sized>(
         &self,
@@ -481,6 +550,66 @@ impl ToAscObj<AscEthereumBlock_0_0_6> for EthereumBlockData {
     }
 }
 
+impl ToAscObj<AscEthereumBlock_0_0_9> for EthereumBlockData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+        gas: &GasCounter,
+    ) -> Result<AscEthereumBlock_0_0_9, HostExportError> {
+        let AscEthereumBlock_0_0_6 {
+            hash,
+            parent_hash,
+            uncles_hash,
+            author,
+            state_root,
+            transactions_root,
+            receipts_root,
+            number,
+            gas_used,
+            gas_limit,
+            timestamp,
+            difficulty,
+            total_difficulty,
+            size,
+            base_fee_per_block,
+        } = ToAscObj::<AscEthereumBlock_0_0_6>::to_asc_obj(self, heap, gas)?;
+        let rollup = self.rollup.as_ref();
+
+        Ok(AscEthereumBlock_0_0_9 {
+            hash,
+            parent_hash,
+            uncles_hash,
+            author,
+            state_root,
+            transactions_root,
+            receipts_root,
+            number,
+            gas_used,
+            gas_limit,
+            timestamp,
+            difficulty,
+            total_difficulty,
+            size,
+            base_fee_per_block,
+            l1_block_number: rollup
+                .map(|rollup| asc_new(heap, &BigInt::from(rollup.l1_block_number), gas))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            l1_timestamp: rollup
+                .and_then(|rollup| rollup.l1_timestamp)
+                .map(|timestamp| asc_new(heap, &BigInt::from(timestamp), gas))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            l1_block_hash: rollup
+                .and_then(|rollup| rollup.l1_block_hash)
+                .map(|hash| asc_new(heap, &hash, gas))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            sequence_number: rollup
+                .and_then(|rollup| rollup.sequence_number)
+                .map(|number| asc_new(heap, &BigInt::from(number), gas))
+                .unwrap_or(Ok(AscPtr::null()))?,
+        })
+    }
+}
+
 impl ToAscObj<AscEthereumTransaction_0_0_1> for EthereumTransactionData {
     fn to_asc_obj<H: AscHeap + ?Sized>(
         &self,
@@ -547,6 +676,42 @@ impl ToAscObj<AscEthereumTransaction_0_0_6> for EthereumTransactionData {
     }
 }
 
+impl ToAscObj<AscEthereumTransaction_0_0_9> for EthereumTransactionData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+        gas: &GasCounter,
+    ) -> Result<AscEthereumTransaction_0_0_9, HostExportError> {
+        let AscEthereumTransaction_0_0_6 {
+            hash,
+            index,
+            from,
+            to,
+            value,
+            gas_limit,
+            gas_price,
+            input,
+            nonce,
+        } = ToAscObj::<AscEthereumTransaction_0_0_6>::to_asc_obj(self, heap, gas)?;
+
+        Ok(AscEthereumTransaction_0_0_9 {
+            hash,
+            index,
+            from,
+            to,
+            value,
+            gas_limit,
+            gas_price,
+            input,
+            nonce,
+            transaction_type: self
+                .transaction_type
+                .map(|tx_type| asc_new(heap, &BigInt::from(tx_type), gas))
+                .unwrap_or(Ok(AscPtr::null()))?,
+        })
+    }
+}
+
 impl<T, B> ToAscObj<AscEthereumEvent<T, B>> for EthereumEventData
 where
     T: AscType + AscIndexId,
@@ -771,6 +936,44 @@ impl ToAscObj<AscEthereumCall_0_0_3<AscEthereumTransaction_0_0_6, AscEthereumBlo
     }
 }
 
+impl ToAscObj<AscEthereumCall_0_0_3<AscEthereumTransaction_0_0_9, AscEthereumBlock_0_0_6>>
+    for EthereumCallData
+{
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+        gas: &GasCounter,
+    ) -> Result<
+        AscEthereumCall_0_0_3<AscEthereumTransaction_0_0_9, AscEthereumBlock_0_0_6>,
+        HostExportError,
+    > {
+        Ok(AscEthereumCall_0_0_3 {
+            to: asc_new(heap, &self.to, gas)?,
+            from: asc_new(heap, &self.from, gas)?,
+            block: asc_new(heap, &self.block, gas)?,
+            transaction: asc_new(heap, &self.transaction, gas)?,
+            inputs: asc_new(heap, &self.inputs, gas)?,
+            outputs: asc_new(heap, &self.outputs, gas)?,
+        })
+    }
+}
+
+impl ToAscObj<AscEthereumTransfer> for EthereumTransferData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+        gas: &GasCounter,
+    ) -> Result<AscEthereumTransfer, HostExportError> {
+        Ok(AscEthereumTransfer {
+            from: asc_new(heap, &self.from, gas)?,
+            to: asc_new(heap, &self.to, gas)?,
+            value: asc_new(heap, &BigInt::from_unsigned_u256(&self.value), gas)?,
+            block: asc_new(heap, &self.block, gas)?,
+            transaction: asc_new(heap, &self.transaction, gas)?,
+        })
+    }
+}
+
 impl ToAscObj<AscLogParam> for ethabi::LogParam {
     fn to_asc_obj<H: AscHeap + ?Sized>(
         &self,
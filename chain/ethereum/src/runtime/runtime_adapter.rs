@@ -13,8 +13,9 @@ use graph::components::subgraph::HostMetrics;
 use graph::data::store::ethereum::call;
 use graph::data::store::scalar::BigInt;
 use graph::data::subgraph::API_VERSION_0_0_9;
+use graph::endpoint::RequestClass;
 use graph::futures03::compat::Future01CompatExt;
-use graph::prelude::web3::types::H160;
+use graph::prelude::web3::types::{H160, H256};
 use graph::runtime::gas::Gas;
 use graph::runtime::{AscIndexId, IndexForAscTypeId};
 use graph::slog::debug;
@@ -29,7 +30,9 @@ use graph::{
     semver::Version,
     slog::Logger,
 };
-use graph_runtime_wasm::asc_abi::class::{AscBigInt, AscEnumArray, AscWrapped, EthereumValueKind};
+use graph_runtime_wasm::asc_abi::class::{
+    Array, AscBigInt, AscEnumArray, AscWrapped, EthereumValueKind, Uint8Array,
+};
 use itertools::Itertools;
 
 use super::abi::{AscUnresolvedContractCall, AscUnresolvedContractCall_0_0_4};
@@ -60,6 +63,9 @@ pub const ETH_GET_BALANCE: Gas = Gas::new(5_000_000_000);
 // TODO: Determine the appropriate gas cost for `ETH_HAS_CODE`, initially aligned with `ETHEREUM_CALL`.
 pub const ETH_HAS_CODE: Gas = Gas::new(5_000_000_000);
 
+// TODO: Determine the appropriate gas cost for `ETH_GET_BLOBS`, initially aligned with `ETHEREUM_CALL`.
+pub const ETH_GET_BLOBS: Gas = Gas::new(5_000_000_000);
+
 pub struct RuntimeAdapter {
     pub eth_adapters: Arc<EthereumNetworkAdapters>,
     pub call_cache: Arc<dyn EthereumCallCache>,
@@ -111,10 +117,13 @@ impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
         let ethereum_get_balance = HostFn {
             name: "ethereum.getBalance",
             func: Arc::new(move |ctx, wasm_ptr| {
-                let eth_adapter = eth_adapters.unverified_cheapest_with(&NodeCapabilities {
-                    archive,
-                    traces: false,
-                })?;
+                let eth_adapter = eth_adapters.unverified_cheapest_with(
+                    &NodeCapabilities {
+                        archive,
+                        traces: false,
+                    },
+                    RequestClass::Calls,
+                )?;
                 eth_get_balance(&eth_adapter, ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
             }),
         };
@@ -123,15 +132,32 @@ impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
         let ethereum_get_code = HostFn {
             name: "ethereum.hasCode",
             func: Arc::new(move |ctx, wasm_ptr| {
-                let eth_adapter = eth_adapters.unverified_cheapest_with(&NodeCapabilities {
-                    archive,
-                    traces: false,
-                })?;
+                let eth_adapter = eth_adapters.unverified_cheapest_with(
+                    &NodeCapabilities {
+                        archive,
+                        traces: false,
+                    },
+                    RequestClass::Calls,
+                )?;
                 eth_has_code(&eth_adapter, ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
             }),
         };
 
-        Ok(vec![ethereum_call, ethereum_get_balance, ethereum_get_code])
+        let eth_adapters = self.eth_adapters.cheap_clone();
+        let ethereum_get_blobs = HostFn {
+            name: "ethereum.getBlobs",
+            func: Arc::new(move |ctx, wasm_ptr| {
+                let eth_adapter = eth_adapters.unverified_cheapest_with_blobs()?;
+                eth_get_blobs(&eth_adapter, ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
+            }),
+        };
+
+        Ok(vec![
+            ethereum_call,
+            ethereum_get_balance,
+            ethereum_get_code,
+            ethereum_get_blobs,
+        ])
     }
 }
 
@@ -246,6 +272,35 @@ fn eth_has_code(
     }
 }
 
+/// function ethereum.getBlobs(txHash: Bytes): Array<Bytes> | null
+///
+/// Returns `null` if the transaction is not known
+fn eth_get_blobs(
+    eth_adapter: &EthereumAdapter,
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<Array<AscPtr<Uint8Array>>>, HostExportError> {
+    ctx.gas
+        .consume_host_fn_with_metrics(ETH_GET_BLOBS, "eth_get_blobs")?;
+
+    if ctx.heap.api_version() < API_VERSION_0_0_9 {
+        return Err(HostExportError::Deterministic(anyhow!(
+            "ethereum.getBlobs call is not supported before API version 0.0.9"
+        )));
+    }
+
+    let hash: H256 = asc_get(ctx.heap, wasm_ptr.into(), &ctx.gas, 0)?;
+
+    let result = graph::block_on(eth_adapter.blobs(&ctx.logger, hash));
+
+    match result {
+        Ok(Some(blobs)) => Ok(asc_new(ctx.heap, blobs.as_slice(), &ctx.gas)?),
+        Ok(None) => Ok(AscPtr::null()),
+        // Retry on any kind of error
+        Err(e) => Err(HostExportError::PossibleReorg(e)),
+    }
+}
+
 /// Returns `Ok(None)` if the call was reverted.
 fn eth_call(
     eth_adapter: &EthereumAdapter,
@@ -3,12 +3,32 @@ use graph::endpoint::{EndpointMetrics, RequestLabels};
 use jsonrpc_core::types::Call;
 use jsonrpc_core::Value;
 
+use web3::error::{Error as Web3Error, TransportError};
 use web3::transports::{http, ipc, ws};
-use web3::RequestId;
+use web3::{BatchTransport as _, RequestId, Transport as _};
 
+use graph::futures03::future::join_all;
 use graph::prelude::*;
+use graph::tokio::sync::{mpsc, oneshot, Semaphore};
 use graph::url::Url;
+use std::fmt;
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// The JSON-RPC methods whose requests can be combined into batch
+/// requests. These are the requests that block ingestion, log scanning and
+/// handlers make in large numbers
+const BATCHED_METHODS: [&str; 6] = [
+    "eth_call",
+    "eth_getLogs",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getTransactionReceipt",
+    "eth_getBlockReceipts",
+];
+
+type RpcResult = Result<Value, Web3Error>;
 
 /// Abstraction over the different web3 transports.
 #[derive(Clone, Debug)]
@@ -17,6 +37,7 @@ pub enum Transport {
         client: http::Http,
         metrics: Arc<EndpointMetrics>,
         provider: ProviderName,
+        batcher: Option<Arc<Batcher>>,
     },
     IPC(ipc::Ipc),
     WS(ws::WebSocket),
@@ -60,20 +81,306 @@ impl Transport {
             client: http::Http::with_client(client, rpc),
             metrics,
             provider: provider.as_ref().into(),
+            batcher: None,
+        }
+    }
+
+    /// Combine requests for the methods in `BATCHED_METHODS` into batch
+    /// requests of at most `max_size` requests, with at most `concurrency`
+    /// batch requests in flight at any time. This only has an effect for
+    /// JSON-RPC over HTTP and if `max_size` is bigger than 1
+    pub fn with_batching(self, logger: &Logger, max_size: usize, concurrency: usize) -> Self {
+        match self {
+            Transport::RPC {
+                client,
+                metrics,
+                provider,
+                batcher: _,
+            } if max_size > 1 => {
+                let batcher = Batcher::new(
+                    logger.cheap_clone(),
+                    client.clone(),
+                    metrics.cheap_clone(),
+                    provider.clone(),
+                    max_size,
+                    concurrency.max(1),
+                );
+                Transport::RPC {
+                    client,
+                    metrics,
+                    provider,
+                    batcher: Some(Arc::new(batcher)),
+                }
+            }
+            transport => transport,
+        }
+    }
+}
+
+fn method_name(call: &Call) -> &str {
+    match call {
+        Call::MethodCall(m) => m.method.as_str(),
+        _ => "unknown",
+    }
+}
+
+/// Send a single request over HTTP and record its outcome in `metrics`
+fn send_rpc(
+    client: &http::Http,
+    metrics: &Arc<EndpointMetrics>,
+    provider: &ProviderName,
+    id: RequestId,
+    request: Call,
+) -> impl Future<Output = RpcResult> + Send + 'static {
+    let metrics = metrics.cheap_clone();
+    let client = client.clone();
+    let labels = RequestLabels {
+        provider: provider.clone(),
+        req_type: method_name(&request).into(),
+        conn_type: graph::endpoint::ConnectionType::Rpc,
+    };
+    async move {
+        let start = Instant::now();
+        let out = client.send(id, request).await;
+        record(&metrics, &labels, &out, start.elapsed());
+
+        out
+    }
+}
+
+/// Record the outcome of a request in `metrics`
+fn record(
+    metrics: &EndpointMetrics,
+    labels: &RequestLabels,
+    result: &RpcResult,
+    elapsed: Duration,
+) {
+    metrics.latency(labels, elapsed);
+    match result {
+        Ok(_) => metrics.success(labels),
+        Err(e) => {
+            metrics.failure(labels);
+            if is_rate_limited(e) {
+                metrics.rate_limited(labels);
+            }
+        }
+    }
+}
+
+/// Whether `error` indicates that the provider rejected the request
+/// because of rate limiting
+fn is_rate_limited(error: &Web3Error) -> bool {
+    match error {
+        Web3Error::Transport(TransportError::Code(429)) => true,
+        Web3Error::Rpc(e) => {
+            let message = e.message.to_lowercase();
+            message.contains("rate limit") || message.contains("too many requests")
+        }
+        _ => false,
+    }
+}
+
+/// A request that is waiting to be sent as part of a batch
+struct PendingCall {
+    id: RequestId,
+    call: Call,
+    sender: oneshot::Sender<RpcResult>,
+}
+
+/// Combines requests into JSON-RPC batch requests. Requests are queued and
+/// a background task sends them in batches of at most `max_size` requests
+/// with at most `concurrency` batches in flight; requests that arrive
+/// while all batches are in flight go out with the next batch. If the
+/// provider rejects a batch request, batching is turned off and all
+/// requests are sent individually from then on
+pub struct Batcher {
+    queue: mpsc::UnboundedSender<PendingCall>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for Batcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Batcher")
+            .field("enabled", &self.enabled.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+/// The state the background task of a `Batcher` needs to send requests
+#[derive(Clone)]
+struct BatchSender {
+    logger: Logger,
+    client: http::Http,
+    metrics: Arc<EndpointMetrics>,
+    provider: ProviderName,
+    enabled: Arc<AtomicBool>,
+}
+
+impl Batcher {
+    fn new(
+        logger: Logger,
+        client: http::Http,
+        metrics: Arc<EndpointMetrics>,
+        provider: ProviderName,
+        max_size: usize,
+        concurrency: usize,
+    ) -> Self {
+        let (queue, receiver) = mpsc::unbounded_channel();
+        let enabled = Arc::new(AtomicBool::new(true));
+        let sender = BatchSender {
+            logger,
+            client,
+            metrics,
+            provider,
+            enabled: enabled.cheap_clone(),
+        };
+        graph::spawn(sender.run(receiver, max_size, concurrency));
+        Batcher { queue, enabled }
+    }
+
+    /// Whether `call` should be sent as part of a batch
+    fn accepts(&self, call: &Call) -> bool {
+        self.enabled.load(Ordering::SeqCst) && BATCHED_METHODS.contains(&method_name(call))
+    }
+
+    fn send(&self, id: RequestId, call: Call) -> impl Future<Output = RpcResult> + Send + 'static {
+        let (sender, receiver) = oneshot::channel();
+        // If the background task is gone, `sender` is dropped and the
+        // request fails below
+        let _ = self.queue.send(PendingCall { id, call, sender });
+        async move {
+            receiver.await.unwrap_or_else(|_| {
+                Err(Web3Error::InvalidResponse(
+                    "batch request did not produce a response".to_string(),
+                ))
+            })
         }
     }
 }
 
+impl BatchSender {
+    async fn run(
+        self,
+        mut receiver: mpsc::UnboundedReceiver<PendingCall>,
+        max_size: usize,
+        concurrency: usize,
+    ) {
+        let slots = Arc::new(Semaphore::new(concurrency));
+        while let Some(first) = receiver.recv().await {
+            // Wait for a free slot before collecting the batch so that
+            // requests that arrive in the meantime are part of it.
+            // Unwrap: we never close the semaphore
+            let permit = slots.clone().acquire_owned().await.unwrap();
+            let mut calls = vec![first];
+            while calls.len() < max_size {
+                match receiver.try_recv() {
+                    Ok(call) => calls.push(call),
+                    Err(_) => break,
+                }
+            }
+            let sender = self.clone();
+            graph::spawn(async move {
+                sender.send_batch(calls).await;
+                drop(permit);
+            });
+        }
+    }
+
+    async fn send_batch(&self, calls: Vec<PendingCall>) {
+        if calls.len() == 1 || !self.enabled.load(Ordering::SeqCst) {
+            return self.send_each(calls).await;
+        }
+
+        let requests: Vec<_> = calls
+            .iter()
+            .map(|pending| (pending.id, pending.call.clone()))
+            .collect();
+        let start = Instant::now();
+        match self.client.send_batch(requests).await {
+            Ok(results) => {
+                let elapsed = start.elapsed();
+                for (pending, result) in calls.into_iter().zip(results) {
+                    self.record(&pending.call, &result, elapsed);
+                    let _ = pending.sender.send(result);
+                }
+            }
+            Err(e) if rejects_batches(&e) => {
+                warn!(self.logger, "Provider rejected a batch request, sending requests individually from now on";
+                      "error" => e.to_string());
+                self.enabled.store(false, Ordering::SeqCst);
+                self.send_each(calls).await;
+            }
+            Err(e) => {
+                let elapsed = start.elapsed();
+                for pending in calls {
+                    let result = Err(e.clone());
+                    self.record(&pending.call, &result, elapsed);
+                    let _ = pending.sender.send(result);
+                }
+            }
+        }
+    }
+
+    /// Send each of `calls` as an individual request
+    async fn send_each(&self, calls: Vec<PendingCall>) {
+        join_all(calls.into_iter().map(|pending| {
+            let out = send_rpc(
+                &self.client,
+                &self.metrics,
+                &self.provider,
+                pending.id,
+                pending.call,
+            );
+            async move {
+                let _ = pending.sender.send(out.await);
+            }
+        }))
+        .await;
+    }
+
+    fn record(&self, call: &Call, result: &RpcResult, elapsed: Duration) {
+        let labels = RequestLabels {
+            provider: self.provider.clone(),
+            req_type: method_name(call).into(),
+            conn_type: graph::endpoint::ConnectionType::Rpc,
+        };
+        record(&self.metrics, &labels, result, elapsed);
+    }
+}
+
+/// Phrases providers use to say that they do not accept batch requests
+const BATCHES_UNSUPPORTED: [&str; 5] = [
+    "not supported",
+    "unsupported",
+    "not allowed",
+    "not enabled",
+    "disabled",
+];
+
+/// Whether `error` says explicitly that the provider does not accept batch
+/// requests. Anything else, like rate limiting with a 429 or a timeout, is
+/// treated as transient, and the requests in the batch fail with the error
+/// so that they can be retried
+fn rejects_batches(error: &Web3Error) -> bool {
+    let message = match error {
+        Web3Error::Rpc(e) => e.message.to_lowercase(),
+        Web3Error::Transport(TransportError::Message(msg)) | Web3Error::InvalidResponse(msg) => {
+            msg.to_lowercase()
+        }
+        _ => return false,
+    };
+    message.contains("batch")
+        && BATCHES_UNSUPPORTED
+            .iter()
+            .any(|phrase| message.contains(phrase))
+}
+
 impl web3::Transport for Transport {
     type Out = Pin<Box<dyn Future<Output = Result<Value, web3::error::Error>> + Send + 'static>>;
 
     fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
         match self {
-            Transport::RPC {
-                client,
-                metrics: _,
-                provider: _,
-            } => client.prepare(method, params),
+            Transport::RPC { client, .. } => client.prepare(method, params),
             Transport::IPC(ipc) => ipc.prepare(method, params),
             Transport::WS(ws) => ws.prepare(method, params),
         }
@@ -85,31 +392,11 @@ impl web3::Transport for Transport {
                 client,
                 metrics,
                 provider,
-            } => {
-                let metrics = metrics.cheap_clone();
-                let client = client.clone();
-                let method = match request {
-                    Call::MethodCall(ref m) => m.method.as_str(),
-                    _ => "unknown",
-                };
-
-                let labels = RequestLabels {
-                    provider: provider.clone(),
-                    req_type: method.into(),
-                    conn_type: graph::endpoint::ConnectionType::Rpc,
-                };
-                let out = async move {
-                    let out = client.send(id, request).await;
-                    match out {
-                        Ok(_) => metrics.success(&labels),
-                        Err(_) => metrics.failure(&labels),
-                    }
-
-                    out
-                };
-
-                Box::pin(out)
-            }
+                batcher,
+            } => match batcher {
+                Some(batcher) if batcher.accepts(&request) => Box::pin(batcher.send(id, request)),
+                _ => Box::pin(send_rpc(client, metrics, provider, id, request)),
+            },
             Transport::IPC(ipc) => Box::pin(ipc.send(id, request)),
             Transport::WS(ws) => Box::pin(ws.send(id, request)),
         }
@@ -128,13 +415,151 @@ impl web3::BatchTransport for Transport {
         T: IntoIterator<Item = (RequestId, Call)>,
     {
         match self {
-            Transport::RPC {
-                client,
-                metrics: _,
-                provider: _,
-            } => Box::new(client.send_batch(requests)),
+            Transport::RPC { client, .. } => Box::new(client.send_batch(requests)),
             Transport::IPC(ipc) => Box::new(ipc.send_batch(requests)),
             Transport::WS(ws) => Box::new(ws.send_batch(requests)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graph::endpoint::EndpointMetrics;
+    use graph::futures03::future::join_all;
+    use graph::http::HeaderMap;
+    use graph::prelude::serde_json::{self, json, Value};
+    use graph::tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use graph::url::Url;
+    use std::sync::{Arc, Mutex};
+    use web3::error::{Error as Web3Error, TransportError};
+    use web3::Transport as _;
+
+    use super::{rejects_batches, Transport};
+
+    /// Answer one HTTP request on `stream` with a JSON-RPC response that
+    /// returns `0x` for every call, and remember the request body
+    async fn answer(mut stream: TcpStream, bodies: Arc<Mutex<Vec<Value>>>) {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let (header_len, body_len) = loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before the request was complete");
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&buf[..pos]).to_lowercase();
+                let body_len = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|len| len.trim().parse::<usize>().unwrap())
+                    .unwrap_or(0);
+                break (pos + 4, body_len);
+            }
+        };
+        while buf.len() < header_len + body_len {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before the request was complete");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+
+        let body: Value = serde_json::from_slice(&buf[header_len..header_len + body_len]).unwrap();
+        let respond = |call: &Value| json!({ "jsonrpc": "2.0", "id": call["id"], "result": "0x" });
+        let response = match &body {
+            Value::Array(calls) => Value::Array(calls.iter().map(respond).collect()),
+            call => respond(call),
+        };
+        bodies.lock().unwrap().push(body);
+
+        let response = response.to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            response.len(),
+            response
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_eth_calls_are_batched() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let server_bodies = bodies.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(answer(stream, server_bodies.clone()));
+            }
+        });
+
+        let transport = Transport::new_rpc(
+            url,
+            HeaderMap::new(),
+            Arc::new(EndpointMetrics::mock()),
+            "test",
+        )
+        .with_batching(&graph::log::discard(), 10, 1);
+
+        let call = |i: usize| {
+            let params = vec![
+                json!({ "to": "0x0000000000000000000000000000000000000001", "data": format!("0x{:02x}", i) }),
+                json!("latest"),
+            ];
+            transport.execute("eth_call", params)
+        };
+        let calls: Vec<_> = (0..5).map(call).collect();
+        // Methods that are not batched are still sent on their own
+        let version = transport.execute("net_version", vec![]);
+
+        for result in join_all(calls).await {
+            assert_eq!(json!("0x"), result.unwrap());
+        }
+        assert_eq!(json!("0x"), version.await.unwrap());
+
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(2, bodies.len());
+        let batch = bodies
+            .iter()
+            .find_map(|body| body.as_array())
+            .expect("the eth_calls were sent as a batch request");
+        assert_eq!(5, batch.len());
+        assert!(batch.iter().all(|call| call["method"] == "eth_call"));
+        assert!(bodies.iter().any(|body| body["method"] == "net_version"));
+    }
+
+    #[test]
+    fn only_explicit_rejections_disable_batching() {
+        let rpc = |message: &str| {
+            Web3Error::Rpc(jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(-32600),
+                message: message.to_string(),
+                data: None,
+            })
+        };
+
+        assert!(rejects_batches(&rpc("Batch requests are not supported")));
+        assert!(rejects_batches(&Web3Error::InvalidResponse(
+            "batch requests disabled".to_string()
+        )));
+        assert!(rejects_batches(&Web3Error::Transport(
+            TransportError::Message("JSON-RPC batching is not allowed".to_string())
+        )));
+
+        // Rate limiting and other transient errors must not disable batching
+        assert!(!rejects_batches(&Web3Error::Transport(
+            TransportError::Code(429)
+        )));
+        assert!(!rejects_batches(&Web3Error::Transport(
+            TransportError::Code(400)
+        )));
+        assert!(!rejects_batches(&Web3Error::Unreachable));
+        assert!(!rejects_batches(&rpc("too many requests")));
+        assert!(!rejects_batches(&Web3Error::InvalidResponse(
+            "expected a batch response".to_string()
+        )));
+    }
+}
@@ -3,6 +3,7 @@ use graph::blockchain::TriggerData;
 use graph::data::subgraph::API_VERSION_0_0_2;
 use graph::data::subgraph::API_VERSION_0_0_6;
 use graph::data::subgraph::API_VERSION_0_0_7;
+use graph::data::subgraph::API_VERSION_0_0_9;
 use graph::prelude::ethabi::ethereum_types::H160;
 use graph::prelude::ethabi::ethereum_types::H256;
 use graph::prelude::ethabi::ethereum_types::U128;
@@ -29,8 +30,10 @@ use std::ops::Deref;
 use std::{cmp::Ordering, sync::Arc};
 
 use crate::data_source::DeclaredCall;
+use crate::rollup::{Rollup, RollupBlockData};
 use crate::runtime::abi::AscEthereumBlock;
 use crate::runtime::abi::AscEthereumBlock_0_0_6;
+use crate::runtime::abi::AscEthereumBlock_0_0_9;
 use crate::runtime::abi::AscEthereumCall;
 use crate::runtime::abi::AscEthereumCall_0_0_3;
 use crate::runtime::abi::AscEthereumEvent;
@@ -38,6 +41,8 @@ use crate::runtime::abi::AscEthereumEvent_0_0_7;
 use crate::runtime::abi::AscEthereumTransaction_0_0_1;
 use crate::runtime::abi::AscEthereumTransaction_0_0_2;
 use crate::runtime::abi::AscEthereumTransaction_0_0_6;
+use crate::runtime::abi::AscEthereumTransaction_0_0_9;
+use crate::runtime::abi::AscEthereumTransfer;
 
 // ETHDEP: This should be defined in only one place.
 type LightEthereumBlock = Block<Transaction>;
@@ -60,6 +65,13 @@ pub enum MappingTrigger {
     },
     Block {
         block: Arc<LightEthereumBlock>,
+        /// The kind of rollup the chain is, if it is one
+        rollup: Option<Rollup>,
+    },
+    Transfer {
+        block: Arc<LightEthereumBlock>,
+        transaction: Arc<Transaction>,
+        transfer: Arc<EthereumCall>,
     },
 }
 
@@ -68,6 +80,7 @@ impl MappingTriggerTrait for MappingTrigger {
         let transaction_id = match self {
             MappingTrigger::Log { log, .. } => log.transaction_hash,
             MappingTrigger::Call { call, .. } => call.transaction_hash,
+            MappingTrigger::Transfer { transfer, .. } => transfer.transaction_hash,
             MappingTrigger::Block { .. } => None,
         };
 
@@ -95,6 +108,10 @@ impl std::fmt::Debug for MappingTrigger {
                 _outputs: Vec<LogParam>,
             },
             Block,
+            Transfer {
+                _transaction: Arc<Transaction>,
+                _transfer: Arc<EthereumCall>,
+            },
         }
 
         let trigger_without_block = match self {
@@ -122,7 +139,15 @@ impl std::fmt::Debug for MappingTrigger {
                 _inputs: inputs.clone(),
                 _outputs: outputs.clone(),
             },
-            MappingTrigger::Block { block: _ } => MappingTriggerWithoutBlock::Block,
+            MappingTrigger::Block { .. } => MappingTriggerWithoutBlock::Block,
+            MappingTrigger::Transfer {
+                block: _,
+                transaction,
+                transfer,
+            } => MappingTriggerWithoutBlock::Transfer {
+                _transaction: transaction.cheap_clone(),
+                _transfer: transfer.cheap_clone(),
+            },
         };
 
         write!(f, "{:?}", trigger_without_block)
@@ -154,7 +179,17 @@ impl ToAscPtr for MappingTrigger {
                     log_type: log.log_type.clone(),
                     params,
                 };
-                if api_version >= API_VERSION_0_0_7 {
+                if api_version >= API_VERSION_0_0_9 {
+                    asc_new::<
+                        AscEthereumEvent_0_0_7<
+                            AscEthereumTransaction_0_0_9,
+                            AscEthereumBlock_0_0_6,
+                        >,
+                        _,
+                        _,
+                    >(heap, &(ethereum_event_data, receipt.as_deref()), gas)?
+                    .erase()
+                } else if api_version >= API_VERSION_0_0_7 {
                     asc_new::<
                         AscEthereumEvent_0_0_7<
                             AscEthereumTransaction_0_0_6,
@@ -202,7 +237,14 @@ impl ToAscPtr for MappingTrigger {
                     inputs,
                     outputs,
                 };
-                if heap.api_version() >= Version::new(0, 0, 6) {
+                if heap.api_version() >= API_VERSION_0_0_9 {
+                    asc_new::<
+                        AscEthereumCall_0_0_3<AscEthereumTransaction_0_0_9, AscEthereumBlock_0_0_6>,
+                        _,
+                        _,
+                    >(heap, &call, gas)?
+                    .erase()
+                } else if heap.api_version() >= Version::new(0, 0, 6) {
                     asc_new::<
                         AscEthereumCall_0_0_3<AscEthereumTransaction_0_0_6, AscEthereumBlock_0_0_6>,
                         _,
@@ -220,14 +262,31 @@ impl ToAscPtr for MappingTrigger {
                     asc_new::<AscEthereumCall, _, _>(heap, &call, gas)?.erase()
                 }
             }
-            MappingTrigger::Block { block } => {
-                let block = EthereumBlockData::from(block.as_ref());
-                if heap.api_version() >= Version::new(0, 0, 6) {
-                    asc_new::<AscEthereumBlock_0_0_6, _, _>(heap, &block, gas)?.erase()
+            MappingTrigger::Block { block, rollup } => {
+                let mut block_data = EthereumBlockData::from(block.as_ref());
+                if heap.api_version() >= API_VERSION_0_0_9 {
+                    block_data.rollup = rollup.and_then(|rollup| rollup.block_data(&block));
+                    asc_new::<AscEthereumBlock_0_0_9, _, _>(heap, &block_data, gas)?.erase()
+                } else if heap.api_version() >= Version::new(0, 0, 6) {
+                    asc_new::<AscEthereumBlock_0_0_6, _, _>(heap, &block_data, gas)?.erase()
                 } else {
-                    asc_new::<AscEthereumBlock, _, _>(heap, &block, gas)?.erase()
+                    asc_new::<AscEthereumBlock, _, _>(heap, &block_data, gas)?.erase()
                 }
             }
+            MappingTrigger::Transfer {
+                block,
+                transaction,
+                transfer,
+            } => {
+                let transfer = EthereumTransferData {
+                    from: transfer.from,
+                    to: transfer.to,
+                    value: transfer.value,
+                    block: EthereumBlockData::from(block.as_ref()),
+                    transaction: EthereumTransactionData::from(transaction.deref()),
+                };
+                asc_new::<AscEthereumTransfer, _, _>(heap, &transfer, gas)?.erase()
+            }
         })
     }
 }
@@ -283,6 +342,9 @@ pub enum EthereumTrigger {
     Block(BlockPtr, EthereumBlockTriggerType),
     Call(Arc<EthereumCall>),
     Log(LogRef),
+    /// An internal call that transfers ETH. A transfer between two
+    /// addresses we are interested in produces a trigger for each side
+    Transfer(Arc<EthereumCall>, TransferDirection),
 }
 
 impl PartialEq for EthereumTrigger {
@@ -294,6 +356,8 @@ impl PartialEq for EthereumTrigger {
 
             (Self::Call(a), Self::Call(b)) => a == b,
 
+            (Self::Transfer(a, a_dir), Self::Transfer(b, b_dir)) => a == b && a_dir == b_dir,
+
             (Self::Log(a), Self::Log(b)) => {
                 a.transaction_hash() == b.transaction_hash() && a.log_index() == b.log_index()
             }
@@ -311,11 +375,19 @@ pub enum EthereumBlockTriggerType {
     WithCallTo(Address),
 }
 
+/// Whether a `Transfer` trigger is for the recipient or the sender of the
+/// ETH
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferDirection {
+    Incoming,
+    Outgoing,
+}
+
 impl EthereumTrigger {
     pub fn block_number(&self) -> BlockNumber {
         match self {
             EthereumTrigger::Block(block_ptr, _) => block_ptr.number,
-            EthereumTrigger::Call(call) => call.block_number,
+            EthereumTrigger::Call(call) | EthereumTrigger::Transfer(call, _) => call.block_number,
             EthereumTrigger::Log(log_ref) => {
                 i32::try_from(log_ref.block_number().unwrap().as_u64()).unwrap()
             }
@@ -325,7 +397,7 @@ impl EthereumTrigger {
     pub fn block_hash(&self) -> H256 {
         match self {
             EthereumTrigger::Block(block_ptr, _) => block_ptr.hash_as_h256(),
-            EthereumTrigger::Call(call) => call.block_hash,
+            EthereumTrigger::Call(call) | EthereumTrigger::Transfer(call, _) => call.block_hash,
             EthereumTrigger::Log(log_ref) => log_ref.block_hash().unwrap(),
         }
     }
@@ -337,6 +409,8 @@ impl EthereumTrigger {
                 Some(address)
             }
             EthereumTrigger::Call(call) => Some(&call.to),
+            EthereumTrigger::Transfer(call, TransferDirection::Incoming) => Some(&call.to),
+            EthereumTrigger::Transfer(call, TransferDirection::Outgoing) => Some(&call.from),
             EthereumTrigger::Log(log_ref) => Some(&log_ref.address()),
             // Unfiltered block triggers match any data source address.
             EthereumTrigger::Block(_, EthereumBlockTriggerType::End) => None,
@@ -359,28 +433,30 @@ impl Ord for EthereumTrigger {
             (Self::Block(..), _) => Ordering::Greater,
             (_, Self::Block(..)) => Ordering::Less,
 
-            // Calls are ordered by their tx indexes
-            (Self::Call(a), Self::Call(b)) => a.transaction_index.cmp(&b.transaction_index),
+            // Calls and transfers are ordered by their tx indexes
+            (Self::Call(a) | Self::Transfer(a, _), Self::Call(b) | Self::Transfer(b, _)) => {
+                a.transaction_index.cmp(&b.transaction_index)
+            }
 
             // Events are ordered by their log index
             (Self::Log(a), Self::Log(b)) => a.log_index().cmp(&b.log_index()),
 
-            // Calls vs. events are logged by their tx index;
+            // Calls and transfers vs. events are logged by their tx index;
             // if they are from the same transaction, events come first
-            (Self::Call(a), Self::Log(b))
+            (Self::Call(a) | Self::Transfer(a, _), Self::Log(b))
                 if a.transaction_index == b.transaction_index().unwrap().as_u64() =>
             {
                 Ordering::Greater
             }
-            (Self::Log(a), Self::Call(b))
+            (Self::Log(a), Self::Call(b) | Self::Transfer(b, _))
                 if a.transaction_index().unwrap().as_u64() == b.transaction_index =>
             {
                 Ordering::Less
             }
-            (Self::Call(a), Self::Log(b)) => a
+            (Self::Call(a) | Self::Transfer(a, _), Self::Log(b)) => a
                 .transaction_index
                 .cmp(&b.transaction_index().unwrap().as_u64()),
-            (Self::Log(a), Self::Call(b)) => a
+            (Self::Log(a), Self::Call(b) | Self::Transfer(b, _)) => a
                 .transaction_index()
                 .unwrap()
                 .as_u64()
@@ -399,7 +475,9 @@ impl TriggerData for EthereumTrigger {
     fn error_context(&self) -> std::string::String {
         let transaction_id = match self {
             EthereumTrigger::Log(log) => log.transaction_hash(),
-            EthereumTrigger::Call(call) => call.transaction_hash,
+            EthereumTrigger::Call(call) | EthereumTrigger::Transfer(call, _) => {
+                call.transaction_hash
+            }
             EthereumTrigger::Block(..) => None,
         };
 
@@ -437,6 +515,8 @@ pub struct EthereumBlockData {
     pub total_difficulty: U256,
     pub size: Option<U256>,
     pub base_fee_per_gas: Option<U256>,
+    /// The L1 block for blocks on rollups
+    pub rollup: Option<RollupBlockData>,
 }
 
 impl<'a, T> From<&'a Block<T>> for EthereumBlockData {
@@ -457,6 +537,7 @@ impl<'a, T> From<&'a Block<T>> for EthereumBlockData {
             total_difficulty: block.total_difficulty.unwrap_or_default(),
             size: block.size,
             base_fee_per_gas: block.base_fee_per_gas,
+            rollup: None,
         }
     }
 }
@@ -473,6 +554,8 @@ pub struct EthereumTransactionData {
     pub gas_price: U256,
     pub input: Bytes,
     pub nonce: U256,
+    /// The EIP-2718 type of the transaction; blob transactions have type 3
+    pub transaction_type: Option<u64>,
 }
 
 impl From<&'_ Transaction> for EthereumTransactionData {
@@ -490,6 +573,7 @@ impl From<&'_ Transaction> for EthereumTransactionData {
             gas_price: tx.gas_price.unwrap_or(U256::zero()), // EIP-1559 made this optional.
             input: tx.input.0.clone(),
             nonce: tx.nonce,
+            transaction_type: tx.transaction_type.map(|tx_type| tx_type.as_u64()),
         }
     }
 }
@@ -516,3 +600,13 @@ pub struct EthereumCallData {
     pub inputs: Vec<LogParam>,
     pub outputs: Vec<LogParam>,
 }
+
+/// ETH transferred by an internal call within a transaction.
+#[derive(Debug, Clone)]
+pub struct EthereumTransferData {
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+    pub block: EthereumBlockData,
+    pub transaction: EthereumTransactionData,
+}
@@ -101,6 +101,7 @@ impl BlockStreamBuilder<Chain> for NearStreamBuilder {
             block_cursor.clone(),
             mapper,
             package.modules.unwrap_or_default(),
+            vec![],
             NEAR_FILTER_MODULE_NAME.to_string(),
             vec![start_block],
             vec![],
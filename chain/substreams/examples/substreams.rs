@@ -75,6 +75,7 @@ async fn main() -> Result<(), Error> {
                 skip_empty_blocks: false,
             }),
             package.modules.clone().unwrap_or_default(),
+            vec![],
             module_name.to_string(),
             vec![12369621],
             vec![],
@@ -160,6 +160,7 @@ impl BlockIngestor for SubstreamsBlockIngestor {
                 latest_cursor.clone(),
                 mapper.cheap_clone(),
                 package.modules.clone().unwrap_or_default(),
+                vec![],
                 "map_blocks".to_string(),
                 vec![-1],
                 vec![],
@@ -58,6 +58,7 @@ impl BlockStreamBuilderTrait<Chain> for BlockStreamBuilder {
                     handler: handler.clone(),
                 }),
                 filter.modules.clone().unwrap_or_default(),
+                filter.params_updates.clone(),
                 filter.module_name.clone(),
                 filter.start_block.map(|x| vec![x]).unwrap_or_default(),
                 vec![],
@@ -75,6 +76,7 @@ impl BlockStreamBuilderTrait<Chain> for BlockStreamBuilder {
                     skip_empty_blocks: true,
                 }),
                 filter.modules.clone().unwrap_or_default(),
+                filter.params_updates.clone(),
                 filter.module_name.clone(),
                 filter.start_block.map(|x| vec![x]).unwrap_or_default(),
                 vec![],
@@ -1,4 +1,7 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Context, Error};
 use graph::{
@@ -7,6 +10,7 @@ use graph::{
     components::{link_resolver::LinkResolver, subgraph::InstanceDSTemplateInfo},
     prelude::{async_trait, BlockNumber, Link},
     slog::Logger,
+    substreams::Modules,
 };
 
 use prost::Message;
@@ -129,6 +133,18 @@ impl blockchain::DataSource<Chain> for DataSource {
             ))
         }
 
+        let mut previous_block = self.start_block();
+        for (start_block, _) in &self.source.params_updates {
+            if *start_block <= previous_block {
+                errs.push(anyhow!(
+                    "params update at block {} must come after block {}",
+                    start_block,
+                    previous_block
+                ));
+            }
+            previous_block = *start_block;
+        }
+
         errs
     }
 
@@ -145,6 +161,9 @@ impl blockchain::DataSource<Chain> for DataSource {
 pub struct Source {
     pub module_name: String,
     pub package: graph::substreams::Package,
+    /// The modules with updated params and the block from which on they
+    /// are used instead of the modules in `package`, in block order
+    pub params_updates: Vec<(BlockNumber, Modules)>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -191,20 +210,14 @@ impl blockchain::UnresolvedDataSource<Chain> for UnresolvedDataSource {
         let content = resolver.cat(logger, &self.source.package.file).await?;
 
         let mut package = graph::substreams::Package::decode(content.as_ref())?;
+        let module_name = &self.source.package.module_name;
 
-        let module = match package.modules.as_mut() {
-            Some(modules) => modules
+        let module = package.modules.as_ref().and_then(|modules| {
+            modules
                 .modules
-                .iter_mut()
-                .find(|module| module.name == self.source.package.module_name)
-                .map(|module| {
-                    if let Some(params) = self.source.package.params {
-                        graph::substreams::patch_module_params(params, module);
-                    }
-                    module
-                }),
-            None => None,
-        };
+                .iter()
+                .find(|module| &module.name == module_name)
+        });
 
         let initial_block: Option<u64> = match module {
             Some(module) => match &module.kind {
@@ -228,6 +241,18 @@ impl blockchain::UnresolvedDataSource<Chain> for UnresolvedDataSource {
             .map_or(Ok(None), |x: u64| TryInto::<i32>::try_into(x).map(Some))
             .map_err(anyhow::Error::from)?;
 
+        if let (Some(params), Some(modules)) = (&self.source.package.params, &mut package.modules) {
+            params.apply(modules, module_name)?;
+        }
+
+        // Each update changes the params that were in effect before it
+        let mut modules = package.modules.clone().unwrap_or_default();
+        let mut params_updates = Vec::new();
+        for update in &self.source.package.params_updates {
+            update.params.apply(&mut modules, module_name)?;
+            params_updates.push((update.start_block, modules.clone()));
+        }
+
         let handler = match (self.mapping.handler, self.mapping.file) {
             (Some(handler), Some(file)) => {
                 let module_bytes = resolver
@@ -250,6 +275,7 @@ impl blockchain::UnresolvedDataSource<Chain> for UnresolvedDataSource {
             source: Source {
                 module_name: self.source.package.module_name,
                 package,
+                params_updates,
             },
             mapping: Mapping {
                 api_version: semver::Version::parse(&self.mapping.api_version)?,
@@ -277,7 +303,55 @@ pub struct UnresolvedSource {
 pub struct UnresolvedPackage {
     pub module_name: String,
     pub file: Link,
-    pub params: Option<String>,
+    pub params: Option<UnresolvedParams>,
+    #[serde(default)]
+    pub params_updates: Vec<UnresolvedParamsUpdate>,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
+#[serde(untagged)]
+/// Module params from the manifest. A plain string is the params for the
+/// output module; a map gives the params for each of the modules it names.
+pub enum UnresolvedParams {
+    Output(String),
+    Modules(BTreeMap<String, String>),
+}
+
+impl UnresolvedParams {
+    /// Set the params of the modules in `modules` that these params are
+    /// for. `output_module` is the module that a plain string is for
+    fn apply(&self, modules: &mut Modules, output_module: &str) -> Result<(), Error> {
+        let mut patch = |name: &str, params: &String| -> Result<(), Error> {
+            let module = modules
+                .modules
+                .iter_mut()
+                .find(|module| module.name == name)
+                .ok_or_else(|| anyhow!("params given for unknown module {}", name))?;
+            graph::substreams::patch_module_params(params.clone(), module);
+            Ok(())
+        };
+
+        match self {
+            UnresolvedParams::Output(params) => patch(output_module, params),
+            UnresolvedParams::Modules(params) => params
+                .iter()
+                .try_for_each(|(name, params)| patch(name.as_str(), params)),
+        }
+    }
+}
+
+impl From<&str> for UnresolvedParams {
+    fn from(params: &str) -> Self {
+        UnresolvedParams::Output(params.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// New params for the modules that take effect at `start_block`.
+pub struct UnresolvedParamsUpdate {
+    pub start_block: BlockNumber,
+    pub params: UnresolvedParams,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -356,6 +430,7 @@ mod test {
                         link: "/ipfs/QmbHnhUFZa6qqqRyubUYhXntox1TCBxqryaBM1iNGqVJzT".into(),
                     },
                     params: None,
+                    params_updates: vec![],
                 },
                 start_block: None,
             },
@@ -384,6 +459,7 @@ mod test {
                         link: "/ipfs/QmbHnhUFZa6qqqRyubUYhXntox1TCBxqryaBM1iNGqVJzT".into(),
                     },
                     params: Some("x\ny\n123\n".into()),
+                    params_updates: vec![],
                 },
                 start_block: None,
             },
@@ -410,6 +486,7 @@ mod test {
             source: crate::Source {
                 module_name: "output".into(),
                 package: gen_package(),
+                params_updates: vec![],
             },
             mapping: Mapping {
                 api_version: semver::Version::from_str("0.0.7").unwrap(),
@@ -447,6 +524,7 @@ mod test {
             source: crate::Source {
                 module_name: "output".into(),
                 package,
+                params_updates: vec![],
             },
             mapping: Mapping {
                 api_version: semver::Version::from_str("0.0.7").unwrap(),
@@ -459,6 +537,45 @@ mod test {
         assert_eq!(ds, expected);
     }
 
+    #[tokio::test]
+    async fn data_source_conversion_params_updates() {
+        let params = |modules: &Modules, name: &str| -> Vec<String> {
+            modules
+                .modules
+                .iter()
+                .find(|module| module.name == name)
+                .unwrap()
+                .inputs
+                .iter()
+                .filter_map(|input| match &input.input {
+                    Some(Input::Params(params)) => Some(params.value.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let ds: UnresolvedDataSource =
+            serde_yaml::from_str(TEMPLATE_DATA_SOURCE_WITH_PARAMS_UPDATES).unwrap();
+        let link_resolver: Arc<dyn LinkResolver> = Arc::new(NoopLinkResolver {});
+        let logger = Logger::root(Discard, o!());
+        let mut ds: DataSource = ds.resolve(&link_resolver, &logger, 0).await.unwrap();
+
+        let modules = ds.source.package.modules.as_ref().unwrap();
+        assert_eq!(vec!["a"], params(modules, "output"));
+        assert_eq!(vec!["b"], params(modules, "map_mod"));
+
+        assert_eq!(1, ds.source.params_updates.len());
+        let (start_block, modules) = &ds.source.params_updates[0];
+        assert_eq!(200, *start_block);
+        assert_eq!(vec!["c"], params(modules, "output"));
+        assert_eq!(vec!["b"], params(modules, "map_mod"));
+        assert!(ds.validate(LATEST_VERSION).is_empty());
+
+        // Updates have to come after the start block
+        ds.source.params_updates[0].0 = 100;
+        assert_eq!(1, ds.validate(LATEST_VERSION).len());
+    }
+
     #[test]
     fn data_source_validation() {
         let mut ds = gen_data_source();
@@ -501,6 +618,7 @@ mod test {
                         link: "/ipfs/QmbHnhUFZa6qqqRyubUYhXntox1TCBxqryaBM1iNGqVJzT".into(),
                     },
                     params: Some("x\ny\n123\n".into()),
+                    params_updates: vec![],
                 },
                 start_block: None,
             },
@@ -578,6 +696,7 @@ mod test {
             source: crate::Source {
                 module_name: "".to_string(),
                 package: gen_package(),
+                params_updates: vec![],
             },
             mapping: Mapping {
                 api_version: semver::Version::from_str("0.0.7").unwrap(),
@@ -645,6 +764,26 @@ mod test {
           apiVersion: 0.0.7
     "#;
 
+    const TEMPLATE_DATA_SOURCE_WITH_PARAMS_UPDATES: &str = r#"
+        kind: substreams
+        name: Uniswap
+        network: mainnet
+        source:
+          package:
+            moduleName: output
+            file:
+              /: /ipfs/QmbHnhUFZa6qqqRyubUYhXntox1TCBxqryaBM1iNGqVJzT
+            params:
+              output: a
+              map_mod: b
+            paramsUpdates:
+              - startBlock: 200
+                params: c
+        mapping:
+          kind: substreams/graph-entities
+          apiVersion: 0.0.7
+    "#;
+
     #[derive(Debug)]
     struct NoopLinkResolver {}
 
@@ -662,6 +801,19 @@ mod test {
             Ok(gen_package().encode_to_vec())
         }
 
+        async fn cat_bounded(
+            &self,
+            _logger: &Logger,
+            _link: &Link,
+            _max_bytes: usize,
+        ) -> Result<Option<Vec<u8>>, Error> {
+            unimplemented!()
+        }
+
+        async fn exists(&self, _logger: &Logger, _link: &Link) -> Result<bool, Error> {
+            unimplemented!()
+        }
+
         async fn get_block(&self, _logger: &Logger, _link: &Link) -> Result<Vec<u8>, Error> {
             unimplemented!()
         }
@@ -54,6 +54,8 @@ impl ToAscPtr for TriggerData {
 #[derive(Debug, Clone, Default)]
 pub struct TriggerFilter {
     pub(crate) modules: Option<Modules>,
+    // modules with updated params and the block at which they take over
+    pub(crate) params_updates: Vec<(BlockNumber, Modules)>,
     pub(crate) module_name: String,
     pub(crate) start_block: Option<BlockNumber>,
     pub(crate) data_sources_len: u8,
@@ -95,6 +97,7 @@ impl blockchain::TriggerFilter<Chain> for TriggerFilter {
     ) {
         let Self {
             modules,
+            params_updates,
             module_name,
             start_block,
             data_sources_len,
@@ -108,6 +111,7 @@ impl blockchain::TriggerFilter<Chain> for TriggerFilter {
         if let Some(ds) = data_sources.next() {
             *data_sources_len = 1;
             *modules = ds.source.package.modules.clone();
+            *params_updates = ds.source.params_updates.clone();
             *module_name = ds.source.module_name.clone();
             *start_block = ds.initial_block;
             *mapping_handler = ds.mapping.handler.as_ref().map(|h| h.handler.clone());
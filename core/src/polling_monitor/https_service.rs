@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Error};
+use bytes::{Bytes, BytesMut};
+use graph::futures03::future::BoxFuture;
+use graph::futures03::TryStreamExt;
+use graph::{
+    data_source::offchain::PinnedUrl,
+    derive::CheapClone,
+    prelude::{hex, reqwest, CheapClone},
+};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tower::{buffer::Buffer, ServiceBuilder, ServiceExt};
+
+const NOT_FOUND: u16 = 404;
+const CLOUDFLARE_TIMEOUT: u16 = 524;
+const GATEWAY_TIMEOUT: u16 = 504;
+
+pub type HttpsService = Buffer<PinnedUrl, BoxFuture<'static, Result<Option<Bytes>, Error>>>;
+
+pub fn https_service(max_file_size: usize, timeout: Duration, rate_limit: u16) -> HttpsService {
+    let https = HttpsServiceInner {
+        client: reqwest::Client::new(),
+        max_file_size,
+        timeout,
+    };
+
+    let svc = ServiceBuilder::new()
+        .rate_limit(rate_limit.into(), Duration::from_secs(1))
+        .service_fn(move |req| https.cheap_clone().call_inner(req))
+        .boxed();
+
+    // The `Buffer` makes it so the rate limit is shared among clones.
+    // Make it unbounded to avoid any risk of starvation.
+    Buffer::new(svc, u32::MAX as usize)
+}
+
+#[derive(Clone, CheapClone)]
+struct HttpsServiceInner {
+    // reqwest::Client doesn't need to be `Arc` because it has one internally
+    // already.
+    client: reqwest::Client,
+    max_file_size: usize,
+    timeout: Duration,
+}
+
+impl HttpsServiceInner {
+    async fn call_inner(self, req: PinnedUrl) -> Result<Option<Bytes>, Error> {
+        let res = match self.client.get(&req.url).timeout(self.timeout).send().await {
+            Ok(res) => res,
+            Err(e) if e.is_timeout() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // The file might just not have been published yet
+        match res.status().as_u16() {
+            NOT_FOUND | GATEWAY_TIMEOUT | CLOUDFLARE_TIMEOUT => return Ok(None),
+            _ => {}
+        }
+
+        let max_file_size = self.max_file_size;
+        let url = &req.url;
+        let bytes = res
+            .error_for_status()?
+            .bytes_stream()
+            .err_into::<Error>()
+            .try_fold(BytesMut::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                if acc.len() > max_file_size {
+                    return Err(anyhow!(
+                        "file {} is too large. It can be at most {} bytes",
+                        url,
+                        max_file_size
+                    ));
+                }
+                Ok(acc)
+            })
+            .await?;
+
+        verify(&req, bytes.into()).map(Some)
+    }
+}
+
+/// Only accept `data` for `url` if it has the hash that `url` is pinned
+/// to. Otherwise, fail so that the file is requested again after a backoff
+fn verify(url: &PinnedUrl, data: Bytes) -> Result<Bytes, Error> {
+    let hash: [u8; 32] = Sha256::digest(&data).into();
+    if hash != url.sha256 {
+        return Err(anyhow!(
+            "the content of {} has sha256 hash {} instead of {}",
+            url.url,
+            hex::encode(hash),
+            hex::encode(url.sha256)
+        ));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use graph::data_source::offchain::PinnedUrl;
+    use std::str::FromStr;
+
+    #[test]
+    fn verify_hash() {
+        // The sha256 hash of `hello`
+        let url = PinnedUrl::from_str(
+            "https://example.com/hello.txt#sha256=\
+             2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        )
+        .unwrap();
+
+        let data = super::verify(&url, Bytes::from_static(b"hello")).unwrap();
+        assert_eq!(b"hello".as_slice(), &data[..]);
+        assert!(super::verify(&url, Bytes::from_static(b"goodbye")).is_err());
+    }
+}
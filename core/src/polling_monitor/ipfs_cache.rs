@@ -0,0 +1,106 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Error;
+use bytes::Bytes;
+use graph::derive::CheapClone;
+use graph::prelude::tokio;
+use sha2::{Digest, Sha256};
+
+/// Used to give temporary files unique names
+static TMP_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A cache of IPFS files on local disk, keyed by the CID and path of the
+/// file. Since the content of a CID never changes, entries never become
+/// stale and the cache can be shared by all deployments on a node, and by
+/// several nodes if the directory is on a shared filesystem.
+///
+/// Each file is stored under the hex encoded sha256 hash of its key, fanned
+/// out into subdirectories by the first two characters of the hash. Files
+/// are written to a temporary file first and then renamed so that readers
+/// never see partially written files. Nothing is ever removed from the
+/// cache.
+#[derive(Clone, CheapClone)]
+pub struct IpfsDiskCache {
+    dir: Arc<PathBuf>,
+}
+
+impl IpfsDiskCache {
+    /// Use `dir` for the cache, creating it if it does not exist yet
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(IpfsDiskCache { dir: Arc::new(dir) })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+        self.dir.join(&hash[0..2]).join(hash)
+    }
+
+    /// Return the cached content for `key`, or `None` if there is no entry
+    /// for it. Errors reading the entry are treated like a missing entry so
+    /// that the file is fetched again.
+    pub async fn get(&self, key: &str) -> Option<Bytes> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(data) => Some(Bytes::from(data)),
+            Err(_) => None,
+        }
+    }
+
+    pub async fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.path(key);
+        // Unwrap: `path` is always in a subdirectory of `self.dir`
+        let parent = path.parent().unwrap();
+        match tokio::fs::create_dir(parent).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let tmp = path.with_extension(format!(
+            "tmp-{}-{}",
+            std::process::id(),
+            TMP_SEQ.fetch_add(1, Ordering::SeqCst)
+        ));
+        tokio::fs::write(&tmp, data).await?;
+        if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use graph::prelude::tokio;
+
+    use super::IpfsDiskCache;
+
+    #[tokio::test]
+    async fn put_and_get() {
+        let dir = std::env::temp_dir().join(format!("ipfs-cache-{}", uuid::Uuid::new_v4()));
+        let cache = IpfsDiskCache::new(&dir).unwrap();
+
+        let key = "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn/file.json";
+        assert_eq!(None, cache.get(key).await);
+
+        cache.put(key, b"hello").await.unwrap();
+        assert_eq!(Some(&b"hello"[..]), cache.get(key).await.as_deref());
+
+        // Another cache on the same directory sees the entry
+        let other = IpfsDiskCache::new(&dir).unwrap();
+        assert_eq!(Some(&b"hello"[..]), other.get(key).await.as_deref());
+        assert_eq!(
+            None,
+            other
+                .get("QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn")
+                .await
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
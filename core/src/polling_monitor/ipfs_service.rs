@@ -3,27 +3,38 @@ use bytes::Bytes;
 use graph::futures03::future::BoxFuture;
 use graph::{
     derive::CheapClone,
-    ipfs_client::{CidFile, IpfsClient},
+    ipfs_client::{available_clients, CidFile, IpfsClient, IpfsError},
     prelude::CheapClone,
 };
+use std::sync::Arc;
 use std::time::Duration;
 use tower::{buffer::Buffer, ServiceBuilder, ServiceExt};
 
+use super::IpfsDiskCache;
+
 const CLOUDFLARE_TIMEOUT: u16 = 524;
 const GATEWAY_TIMEOUT: u16 = 504;
 
 pub type IpfsService = Buffer<CidFile, BoxFuture<'static, Result<Option<Bytes>, Error>>>;
 
+/// Fetch files from the endpoints of `clients`, in the order in which they
+/// are given. Endpoints that are down are skipped, and a file that one
+/// endpoint does not have or fails to serve is requested from the next one
+/// as long as that endpoint has retry budget left. With a `cache`, files
+/// are looked up there before they are fetched, and fetched files are added
+/// to it.
 pub fn ipfs_service(
-    client: IpfsClient,
+    clients: Vec<IpfsClient>,
     max_file_size: usize,
     timeout: Duration,
     rate_limit: u16,
+    cache: Option<IpfsDiskCache>,
 ) -> IpfsService {
     let ipfs = IpfsServiceInner {
-        client,
+        clients: Arc::new(clients),
         max_file_size,
         timeout,
+        cache,
     };
 
     let svc = ServiceBuilder::new()
@@ -38,9 +49,10 @@ pub fn ipfs_service(
 
 #[derive(Clone, CheapClone)]
 struct IpfsServiceInner {
-    client: IpfsClient,
+    clients: Arc<Vec<IpfsClient>>,
     max_file_size: usize,
     timeout: Duration,
+    cache: Option<IpfsDiskCache>,
 }
 
 impl IpfsServiceInner {
@@ -56,18 +68,54 @@ impl IpfsServiceInner {
             None => cid.to_string(),
         };
 
-        let res = self
-            .client
-            .cat_all(&cid_str, Some(self.timeout), self.max_file_size)
+        if let Some(cache) = &self.cache {
+            // The cache might have been filled with a larger file size limit
+            match cache.get(&cid_str).await {
+                Some(data) if data.len() <= self.max_file_size => return Ok(Some(data)),
+                Some(_) => return Err(IpfsError::FileTooLarge(cid_str, self.max_file_size).into()),
+                None => {}
+            }
+        }
+
+        let mut not_found = false;
+        let mut err = None;
+        for (i, client) in available_clients(&self.clients).into_iter().enumerate() {
+            if i > 0 && !client.try_retry() {
+                continue;
+            }
+            match self.cat(&client, &cid_str).await {
+                Ok(Some(file_bytes)) => {
+                    if let Some(cache) = &self.cache {
+                        // Failing to cache the file is not a reason to fail
+                        // the request; it will be fetched again next time
+                        let _ = cache.put(&cid_str, &file_bytes).await;
+                    }
+                    return Ok(Some(file_bytes));
+                }
+                Ok(None) => not_found = true,
+                Err(e @ IpfsError::FileTooLarge(..)) => return Err(e.into()),
+                Err(e) => err = Some(e),
+            }
+        }
+
+        match err {
+            Some(e) if !not_found => Err(e.into()),
+            _ => Ok(None),
+        }
+    }
+
+    async fn cat(&self, client: &IpfsClient, cid_str: &str) -> Result<Option<Bytes>, IpfsError> {
+        let res = client
+            .cat_all(cid_str, Some(self.timeout), self.max_file_size)
             .await;
 
         match res {
             Ok(file_bytes) => Ok(Some(file_bytes)),
             Err(e) => match e.status().map(|e| e.as_u16()) {
                 // Timeouts in IPFS mean the file is not available, so we return `None`
-                Some(GATEWAY_TIMEOUT) | Some(CLOUDFLARE_TIMEOUT) => return Ok(None),
-                _ if e.is_timeout() => return Ok(None),
-                _ => return Err(e.into()),
+                Some(GATEWAY_TIMEOUT) | Some(CLOUDFLARE_TIMEOUT) => Ok(None),
+                _ if e.is_timeout() => Ok(None),
+                _ => Err(e),
             },
         }
     }
@@ -127,7 +175,7 @@ mod test {
         let cid = Cid::from_str(&ipfs_folder.hash).unwrap();
         let file = "random.txt".to_string();
 
-        let svc = super::ipfs_service(local, 100000, Duration::from_secs(5), 10);
+        let svc = super::ipfs_service(vec![local], 100000, Duration::from_secs(5), 10, None);
 
         let content = svc
             .oneshot(super::CidFile {
@@ -140,6 +188,26 @@ mod test {
         assert_eq!(content.to_vec(), uid.as_bytes().to_vec());
     }
 
+    #[tokio::test]
+    async fn failover_to_next_endpoint() {
+        let uid = Uuid::new_v4().to_string();
+        let cl: ipfs::IpfsClient = ipfs::IpfsClient::default();
+        let rsp = cl.add(std::io::Cursor::new(uid.clone())).await.unwrap();
+        let cid = Cid::from_str(&rsp.hash).unwrap();
+
+        // Nothing listens on the first endpoint
+        let down = IpfsClient::new("http://localhost:1").unwrap();
+        let local = IpfsClient::localhost();
+        let svc = super::ipfs_service(vec![down, local], 100000, Duration::from_secs(5), 10, None);
+
+        let content = svc
+            .oneshot(super::CidFile { cid, path: None })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(content.to_vec(), uid.as_bytes().to_vec());
+    }
+
     #[tokio::test]
     async fn arweave_get() {
         const ID: &str = "8APeQ5lW0-csTcBaGdPBDLAL2ci2AT9pTn2tppGPU_8";
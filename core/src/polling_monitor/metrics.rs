@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use graph::{
     prelude::{DeploymentHash, MetricsRegistry},
-    prometheus::{Counter, Gauge},
+    prometheus::{Counter, Gauge, Histogram, HistogramOpts},
 };
 
 #[derive(Clone)]
@@ -11,6 +11,7 @@ pub struct PollingMonitorMetrics {
     pub errors: Counter,
     pub not_found: Counter,
     pub queue_depth: Gauge,
+    pub fetch_duration: Histogram,
 }
 
 impl PollingMonitorMetrics {
@@ -43,11 +44,20 @@ impl PollingMonitorMetrics {
                 subgraph_hash.as_str(),
             )
             .unwrap();
+        let fetch_duration = *registry
+            .new_deployment_histogram(
+                "polling_monitor_fetch_duration",
+                "measures the duration of requests made to the service being polled",
+                subgraph_hash.as_str(),
+                vec![0.05, 0.2, 0.5, 1.0, 2.5, 5.0, 15.0, 30.0, 60.0],
+            )
+            .unwrap();
         Self {
             requests,
             errors,
             not_found,
             queue_depth,
+            fetch_duration,
         }
     }
 
@@ -58,6 +68,7 @@ impl PollingMonitorMetrics {
             errors: Counter::new("y", " ").unwrap(),
             not_found: Counter::new("z", " ").unwrap(),
             queue_depth: Gauge::new("w", " ").unwrap(),
+            fetch_duration: Histogram::with_opts(HistogramOpts::new("v", " ")).unwrap(),
         }
     }
 }
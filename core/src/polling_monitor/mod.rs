@@ -1,4 +1,6 @@
 mod arweave_service;
+mod https_service;
+mod ipfs_cache;
 mod ipfs_service;
 mod metrics;
 
@@ -7,7 +9,7 @@ use std::fmt::Display;
 use std::hash::Hash;
 use std::sync::Arc;
 use std::task::Poll;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use graph::cheap_clone::CheapClone;
 use graph::futures03::future::BoxFuture;
@@ -15,7 +17,7 @@ use graph::futures03::stream::StreamExt;
 use graph::futures03::{stream, Future, FutureExt, TryFutureExt};
 use graph::parking_lot::Mutex;
 use graph::prelude::tokio;
-use graph::prometheus::{Counter, Gauge};
+use graph::prometheus::{Counter, Gauge, Histogram};
 use graph::slog::{debug, Logger};
 use graph::util::monitored::MonitoredVecDeque as VecDeque;
 use tokio::sync::{mpsc, watch};
@@ -25,6 +27,8 @@ use tower::{Service, ServiceExt};
 
 pub use self::metrics::PollingMonitorMetrics;
 pub use arweave_service::{arweave_service, ArweaveService};
+pub use https_service::{https_service, HttpsService};
+pub use ipfs_cache::IpfsDiskCache;
 pub use ipfs_service::{ipfs_service, IpfsService};
 
 const MIN_BACKOFF: Duration = Duration::from_secs(5);
@@ -110,7 +114,10 @@ where
     E: Display + Send + 'static,
     S::Future: Send,
 {
-    let service = ReturnRequest { service };
+    let service = ReturnRequest {
+        service,
+        fetch_duration: metrics.fetch_duration.clone(),
+    };
     let (queue, queue_woken) = Queue::new(metrics.queue_depth.clone(), metrics.requests.clone());
 
     let cancel_check = response_sender.clone();
@@ -212,6 +219,7 @@ impl<ID> PollingMonitor<ID> {
 
 struct ReturnRequest<S> {
     service: S,
+    fetch_duration: Histogram,
 }
 
 impl<S, Req> Service<Req> for ReturnRequest<S>
@@ -232,8 +240,11 @@ where
 
     fn call(&mut self, req: Req) -> Self::Future {
         let req1 = req.clone();
+        let fetch_duration = self.fetch_duration.clone();
+        let start = Instant::now();
         self.service
             .call(req.clone())
+            .inspect(move |_| fetch_duration.observe(start.elapsed().as_secs_f64()))
             .map_ok(move |x| (req, x))
             .map_err(move |e| (req1, e))
             .boxed()
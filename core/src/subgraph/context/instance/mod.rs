@@ -4,6 +4,7 @@ use anyhow::ensure;
 use graph::futures01::sync::mpsc::Sender;
 use graph::{
     blockchain::{Blockchain, TriggerData as _},
+    data::subgraph::HandlerLimits,
     data_source::{
         causality_region::CausalityRegionSeq, offchain, CausalityRegion, DataSource,
         DataSourceTemplate, TriggerData,
@@ -11,7 +12,7 @@ use graph::{
     prelude::*,
 };
 use hosts::{OffchainHosts, OnchainHosts};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub(crate) struct SubgraphInstance<C: Blockchain, T: RuntimeHostBuilder<C>> {
     subgraph_id: DeploymentHash,
@@ -21,6 +22,13 @@ pub(crate) struct SubgraphInstance<C: Blockchain, T: RuntimeHostBuilder<C>> {
     /// The data sources declared in the subgraph manifest. This does not include dynamic data sources.
     pub(super) static_data_sources: Arc<Vec<DataSource<C>>>,
     host_metrics: Arc<HostMetrics>,
+    /// The limits for individual handlers from the manifest
+    handler_limits: Arc<BTreeMap<String, HandlerLimits>>,
+    /// The fuel that each invocation of a handler may use, if handlers
+    /// are metered with fuel
+    fuel: Option<u64>,
+    /// The number of threads that run the mappings of offchain data sources
+    offchain_workers: usize,
 
     /// The hosts represent the data sources in the subgraph. There is one host per data source.
     /// Data sources with no mappings (e.g. direct substreams) have no host.
@@ -31,8 +39,14 @@ pub(crate) struct SubgraphInstance<C: Blockchain, T: RuntimeHostBuilder<C>> {
 
     offchain_hosts: OffchainHosts<C, T>,
 
-    /// Maps the hash of a module to a channel to the thread in which the module is instantiated.
-    module_cache: HashMap<[u8; 32], Sender<T::Req>>,
+    /// Hosts for subgraph data sources. These can only be declared in the manifest and are
+    /// therefore never reverted.
+    subgraph_hosts: Vec<Arc<T::Host>>,
+
+    /// Maps the hash of a module and the number of threads running it to a channel to the
+    /// threads in which the module is instantiated. Offchain data sources get their own
+    /// threads when they use more than one.
+    module_cache: HashMap<([u8; 32], usize), Sender<T::Req>>,
 
     /// This manages the sequence of causality regions for the subgraph.
     causality_region_seq: CausalityRegionSeq,
@@ -68,9 +82,12 @@ where
         host_builder: T,
         host_metrics: Arc<HostMetrics>,
         causality_region_seq: CausalityRegionSeq,
+        offchain_workers: usize,
     ) -> Self {
         let subgraph_id = manifest.id.clone();
         let network = manifest.network_name();
+        let handler_limits = Arc::new(manifest.handler_limits());
+        let fuel = manifest.fuel();
         let templates = Arc::new(manifest.templates);
 
         SubgraphInstance {
@@ -80,9 +97,13 @@ where
             static_data_sources: Arc::new(manifest.data_sources),
             onchain_hosts: OnchainHosts::new(),
             offchain_hosts: OffchainHosts::new(),
+            subgraph_hosts: Vec::new(),
             module_cache: HashMap::new(),
             templates,
             host_metrics,
+            handler_limits,
+            fuel,
+            offchain_workers,
             causality_region_seq,
         }
     }
@@ -98,9 +119,15 @@ where
             Some(ref module_bytes) => module_bytes.cheap_clone(),
         };
 
+        let workers = match data_source.as_offchain() {
+            Some(_) => self.offchain_workers,
+            None => 1,
+        };
+
         let mapping_request_sender = {
             let module_hash = tiny_keccak::keccak256(module_bytes.as_ref());
-            if let Some(sender) = self.module_cache.get(&module_hash) {
+            let key = (module_hash, workers);
+            if let Some(sender) = self.module_cache.get(&key) {
                 sender.clone()
             } else {
                 let sender = T::spawn_mapping(
@@ -108,8 +135,11 @@ where
                     logger,
                     self.subgraph_id.clone(),
                     self.host_metrics.cheap_clone(),
+                    self.handler_limits.cheap_clone(),
+                    self.fuel,
+                    workers,
                 )?;
-                self.module_cache.insert(module_hash, sender.clone());
+                self.module_cache.insert(key, sender.clone());
                 sender
             }
         };
@@ -139,12 +169,20 @@ where
         }
 
         let is_onchain = data_source.is_onchain();
+        let is_subgraph = data_source.as_subgraph().is_some();
         let Some(host) = self.new_host(logger.clone(), data_source)? else {
             return Ok(None);
         };
 
         // Check for duplicates and add the host.
-        if is_onchain {
+        if is_subgraph {
+            if self.subgraph_hosts.contains(&host) {
+                Ok(None)
+            } else {
+                self.subgraph_hosts.push(host.cheap_clone());
+                Ok(Some(host))
+            }
+        } else if is_onchain {
             // `onchain_hosts` will remain ordered by the creation block.
             // See also 8f1bca33-d3b7-4035-affc-fd6161a12448.
             ensure!(
@@ -226,6 +264,19 @@ where
             TriggerData::Offchain(trigger) => self
                 .offchain_hosts
                 .matches_by_address(trigger.source.address().as_ref().map(|a| a.as_slice())),
+            TriggerData::Subgraph(trigger) => {
+                let source = trigger.source.clone();
+                Box::new(
+                    self.subgraph_hosts
+                        .iter()
+                        .filter(move |host| {
+                            host.data_source()
+                                .as_subgraph()
+                                .map_or(false, |ds| ds.source.address == source)
+                        })
+                        .map(|host| host.as_ref()),
+                )
+            }
         }
     }
 
@@ -234,7 +285,7 @@ where
     }
 
     pub fn hosts_len(&self) -> usize {
-        self.onchain_hosts.len() + self.offchain_hosts.len()
+        self.onchain_hosts.len() + self.offchain_hosts.len() + self.subgraph_hosts.len()
     }
 
     pub fn first_host(&self) -> Option<&Arc<T::Host>> {
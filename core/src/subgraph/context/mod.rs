@@ -1,12 +1,12 @@
 mod instance;
 
 use crate::polling_monitor::{
-    spawn_monitor, ArweaveService, IpfsService, PollingMonitor, PollingMonitorMetrics,
+    spawn_monitor, ArweaveService, HttpsService, IpfsService, PollingMonitor, PollingMonitorMetrics,
 };
 use anyhow::{self, Error};
 use bytes::Bytes;
 use graph::{
-    blockchain::{BlockTime, Blockchain},
+    blockchain::{block_stream::FilterExtender, BlockTime, Blockchain},
     components::{
         store::{DeploymentId, SubgraphFork},
         subgraph::{HostMetrics, MappingError, RuntimeHost as _, SharedProofOfIndexing},
@@ -14,7 +14,7 @@ use graph::{
     data::subgraph::SubgraphManifest,
     data_source::{
         causality_region::CausalityRegionSeq,
-        offchain::{self, Base64},
+        offchain::{self, Base64, PinnedUrl},
         CausalityRegion, DataSource, DataSourceTemplate,
     },
     derive::CheapClone,
@@ -74,6 +74,12 @@ where
     pub instances: SubgraphKeepAlive,
     pub offchain_monitor: OffchainMonitor,
     pub filter: Option<C::TriggerFilter>,
+    /// Extends the filter of the running block stream with the data
+    /// sources created while it runs, if the stream supports that
+    pub filter_extender: Option<Arc<dyn FilterExtender<C>>>,
+    /// The filters added to the running block stream, each with the first
+    /// block that the stream scans with it
+    pub extended_filters: Vec<(C::TriggerFilter, BlockNumber)>,
     pub(crate) trigger_processor: Box<dyn TriggerProcessor<C, T>>,
     pub(crate) decoder: Box<Decoder<C, T>>,
 }
@@ -88,12 +94,14 @@ impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
         offchain_monitor: OffchainMonitor,
         trigger_processor: Box<dyn TriggerProcessor<C, T>>,
         decoder: Box<Decoder<C, T>>,
+        offchain_workers: usize,
     ) -> Self {
         let instance = SubgraphInstance::new(
             manifest,
             host_builder,
             host_metrics.clone(),
             causality_region_seq,
+            offchain_workers,
         );
 
         Self {
@@ -101,6 +109,8 @@ impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
             instances,
             offchain_monitor,
             filter: None,
+            filter_extender: None,
+            extended_filters: Vec::new(),
             trigger_processor,
             decoder,
         }
@@ -232,6 +242,8 @@ pub struct OffchainMonitor {
     ipfs_monitor_rx: mpsc::UnboundedReceiver<(CidFile, Bytes)>,
     arweave_monitor: PollingMonitor<Base64>,
     arweave_monitor_rx: mpsc::UnboundedReceiver<(Base64, Bytes)>,
+    https_monitor: PollingMonitor<PinnedUrl>,
+    https_monitor_rx: mpsc::UnboundedReceiver<(PinnedUrl, Bytes)>,
 }
 
 impl OffchainMonitor {
@@ -241,12 +253,14 @@ impl OffchainMonitor {
         subgraph_hash: &DeploymentHash,
         ipfs_service: IpfsService,
         arweave_service: ArweaveService,
+        https_service: HttpsService,
     ) -> Self {
         let metrics = Arc::new(PollingMonitorMetrics::new(registry, subgraph_hash));
         // The channel is unbounded, as it is expected that `fn ready_offchain_events` is called
         // frequently, or at least with the same frequency that requests are sent.
         let (ipfs_monitor_tx, ipfs_monitor_rx) = mpsc::unbounded_channel();
         let (arweave_monitor_tx, arweave_monitor_rx) = mpsc::unbounded_channel();
+        let (https_monitor_tx, https_monitor_rx) = mpsc::unbounded_channel();
 
         let ipfs_monitor = spawn_monitor(
             ipfs_service,
@@ -255,12 +269,20 @@ impl OffchainMonitor {
             metrics.cheap_clone(),
         );
 
-        let arweave_monitor = spawn_monitor(arweave_service, arweave_monitor_tx, logger, metrics);
+        let arweave_monitor = spawn_monitor(
+            arweave_service,
+            arweave_monitor_tx,
+            logger.cheap_clone(),
+            metrics.cheap_clone(),
+        );
+        let https_monitor = spawn_monitor(https_service, https_monitor_tx, logger, metrics);
         Self {
             ipfs_monitor,
             ipfs_monitor_rx,
             arweave_monitor,
             arweave_monitor_rx,
+            https_monitor,
+            https_monitor_rx,
         }
     }
 
@@ -268,6 +290,7 @@ impl OffchainMonitor {
         match source {
             offchain::Source::Ipfs(cid_file) => self.ipfs_monitor.monitor(cid_file),
             offchain::Source::Arweave(base64) => self.arweave_monitor.monitor(base64),
+            offchain::Source::Https(url) => self.https_monitor.monitor(url),
         };
         Ok(())
     }
@@ -302,6 +325,19 @@ impl OffchainMonitor {
             }
         }
 
+        loop {
+            match self.https_monitor_rx.try_recv() {
+                Ok((url, data)) => triggers.push(offchain::TriggerData {
+                    source: offchain::Source::Https(url),
+                    data: Arc::new(data),
+                }),
+                Err(TryRecvError::Disconnected) => {
+                    anyhow::bail!("https monitor unexpectedly terminated")
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
         Ok(triggers)
     }
 }
@@ -1,12 +1,15 @@
 use graph::{
     blockchain::{Blockchain, TriggersAdapter},
     components::{
-        store::{DeploymentLocator, SubgraphFork, WritableStore},
-        subgraph::ProofOfIndexingVersion,
+        store::{
+            DeploymentLocator, DeploymentPriority, SubgraphFork, SubgraphStore, WritableStore,
+        },
+        subgraph::{DeploymentDependencies, ProofOfIndexingVersion},
     },
     data::subgraph::{SubgraphFeature, UnifiedMappingApiVersion},
     data_source::DataSourceTemplate,
     prelude::BlockNumber,
+    schema::EntityType,
 };
 use std::collections::BTreeSet;
 use std::sync::Arc;
@@ -30,6 +33,27 @@ pub struct IndexingInputs<C: Blockchain> {
     /// Whether to instrument trigger processing and log additional,
     /// possibly expensive and noisy, information
     pub instrument: bool,
+
+    /// The entity types whose most recently written entities are loaded
+    /// into the entity cache when the runner starts
+    pub warmup_entity_types: Vec<EntityType>,
+    /// How many entities of each of the `warmup_entity_types` to load
+    pub warmup_entity_count: usize,
+
+    /// The handlers whose deterministic errors are recorded as skipped
+    /// triggers instead of becoming subgraph errors
+    pub skip_error_handlers: BTreeSet<String>,
+
+    /// The share of the node's resources the deployment gets compared to
+    /// other deployments
+    pub priority: DeploymentPriority,
+
+    /// Used to read the entity changes of the deployments that subgraph
+    /// data sources take their triggers from
+    pub subgraph_store: Arc<dyn SubgraphStore>,
+
+    /// The deployments that the mappings read from with `store.loadFrom`
+    pub dependencies: Arc<DeploymentDependencies>,
 }
 
 impl<C: Blockchain> IndexingInputs<C> {
@@ -50,6 +74,12 @@ impl<C: Blockchain> IndexingInputs<C> {
             poi_version,
             network,
             instrument,
+            warmup_entity_types,
+            warmup_entity_count,
+            skip_error_handlers,
+            priority,
+            subgraph_store,
+            dependencies,
         } = self;
         IndexingInputs {
             deployment: deployment.clone(),
@@ -67,6 +97,12 @@ impl<C: Blockchain> IndexingInputs<C> {
             poi_version: *poi_version,
             network: network.clone(),
             instrument: *instrument,
+            warmup_entity_types: warmup_entity_types.clone(),
+            warmup_entity_count: *warmup_entity_count,
+            skip_error_handlers: skip_error_handlers.clone(),
+            priority: *priority,
+            subgraph_store: subgraph_store.clone(),
+            dependencies: dependencies.clone(),
         }
     }
 }
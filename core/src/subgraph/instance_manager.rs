@@ -1,7 +1,8 @@
-use crate::polling_monitor::{ArweaveService, IpfsService};
+use crate::polling_monitor::{ArweaveService, HttpsService, IpfsService};
 use crate::subgraph::context::{IndexingContext, SubgraphKeepAlive};
 use crate::subgraph::inputs::IndexingInputs;
 use crate::subgraph::loader::load_dynamic_data_sources;
+use crate::subgraph::quota::QuotaScheduler;
 use crate::subgraph::Decoder;
 use std::collections::BTreeSet;
 
@@ -9,7 +10,7 @@ use crate::subgraph::runner::SubgraphRunner;
 use graph::blockchain::block_stream::BlockStreamMetrics;
 use graph::blockchain::{Blockchain, BlockchainKind, DataSource, NodeCapabilities};
 use graph::components::metrics::gas::GasMetrics;
-use graph::components::subgraph::ProofOfIndexingVersion;
+use graph::components::subgraph::{DeploymentDependencies, ProofOfIndexingVersion, HANDLER_STATS};
 use graph::data::subgraph::{UnresolvedSubgraphManifest, SPEC_VERSION_0_0_6};
 use graph::data::value::Word;
 use graph::data_source::causality_region::CausalityRegionSeq;
@@ -23,6 +24,10 @@ use tokio::task;
 use super::context::OffchainMonitor;
 use super::SubgraphTriggerProcessor;
 
+/// How many entities of each entity type listed under
+/// `indexerHints.warmup` to load when `GRAPH_ENTITY_CACHE_WARMUP` is not set
+const DEFAULT_WARMUP_COUNT: usize = 1000;
+
 #[derive(Clone)]
 pub struct SubgraphInstanceManager<S: SubgraphStore> {
     logger_factory: LoggerFactory,
@@ -33,8 +38,10 @@ pub struct SubgraphInstanceManager<S: SubgraphStore> {
     link_resolver: Arc<dyn LinkResolver>,
     ipfs_service: IpfsService,
     arweave_service: ArweaveService,
+    https_service: HttpsService,
     static_filters: bool,
     env_vars: Arc<EnvVars>,
+    quotas: Arc<QuotaScheduler>,
 }
 
 #[async_trait]
@@ -167,6 +174,7 @@ impl<S: SubgraphStore> SubgraphInstanceManagerTrait for SubgraphInstanceManager<
         }
 
         self.instances.remove(&loc.id);
+        HANDLER_STATS.remove(loc.hash.as_str());
 
         info!(logger, "Stopped subgraph");
     }
@@ -183,10 +191,15 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
         link_resolver: Arc<dyn LinkResolver>,
         ipfs_service: IpfsService,
         arweave_service: ArweaveService,
+        https_service: HttpsService,
         static_filters: bool,
     ) -> Self {
         let logger = logger_factory.component_logger("SubgraphInstanceManager", None);
         let logger_factory = logger_factory.with_parent(logger.clone());
+        let quotas = Arc::new(QuotaScheduler::new(
+            env_vars.deployment_cpu_quota,
+            env_vars.deployment_write_quota,
+        ));
 
         SubgraphInstanceManager {
             logger_factory,
@@ -199,6 +212,8 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             static_filters,
             env_vars,
             arweave_service,
+            https_service,
+            quotas,
         }
     }
 
@@ -318,7 +333,10 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
 
         let start_blocks: Vec<BlockNumber> = data_sources
             .iter()
-            .filter_map(|d| d.as_onchain().map(|d: &C::DataSource| d.start_block()))
+            .filter_map(|d| match d.as_subgraph() {
+                Some(d) => Some(d.start_block()),
+                None => d.as_onchain().map(|d: &C::DataSource| d.start_block()),
+            })
             .collect();
 
         let end_blocks: BTreeSet<BlockNumber> = manifest
@@ -384,6 +402,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             &manifest.id,
             self.ipfs_service.clone(),
             self.arweave_service.clone(),
+            self.https_service.clone(),
         );
 
         // Initialize deployment_head with current deployment head. Any sort of trouble in
@@ -392,10 +411,14 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
         block_stream_metrics.deployment_head.set(deployment_head);
 
         let (runtime_adapter, decoder_hook) = chain.runtime()?;
+        let dependencies = Arc::new(DeploymentDependencies::default());
         let host_builder = graph_runtime_wasm::RuntimeHostBuilder::new(
             runtime_adapter,
             self.link_resolver.cheap_clone(),
             subgraph_store.ens_lookup(),
+            subgraph_store.aux_lookup(),
+            subgraph_store.deployment_reader(),
+            dependencies.cheap_clone(),
         );
 
         let features = manifest.features.clone();
@@ -411,6 +434,36 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
 
         let instrument = self.subgraph_store.instrument(&deployment)?;
 
+        // Entity types the subgraph author listed are warmed up even if
+        // the warm-up is turned off for all other subgraphs
+        let warmup_count = ENV_VARS.mappings.entity_cache_warmup;
+        let (warmup_entity_types, warmup_entity_count) = match manifest.warmup_entity_types() {
+            Some(names) => {
+                let entity_types = names
+                    .iter()
+                    .filter_map(|name| match manifest.schema.entity_type(name) {
+                        Ok(entity_type) if entity_type.is_object_type() => Some(entity_type),
+                        _ => {
+                            warn!(logger, "Ignoring unknown entity type in `indexerHints.warmup`";
+                                  "entity_type" => name);
+                            None
+                        }
+                    })
+                    .collect();
+                let count = if warmup_count == 0 {
+                    DEFAULT_WARMUP_COUNT
+                } else {
+                    warmup_count
+                };
+                (entity_types, count)
+            }
+            None if warmup_count == 0 => (vec![], 0),
+            None => (manifest.schema.entity_types(), warmup_count),
+        };
+
+        let skip_error_handlers = manifest.skip_error_handlers();
+        let priority = self.subgraph_store.priority(&deployment)?;
+
         let decoder = Box::new(Decoder::new(decoder_hook));
 
         let inputs = IndexingInputs {
@@ -429,6 +482,12 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             poi_version,
             network: network.to_string(),
             instrument,
+            warmup_entity_types,
+            warmup_entity_count,
+            skip_error_handlers,
+            priority,
+            subgraph_store: self.subgraph_store.cheap_clone(),
+            dependencies,
         };
 
         // Initialize the indexing context, including both static and dynamic data sources.
@@ -444,6 +503,8 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
                 offchain_monitor,
                 tp,
                 decoder,
+                priority
+                    .file_data_source_concurrency(ENV_VARS.mappings.file_data_source_concurrency),
             );
             for data_source in data_sources {
                 ctx.add_dynamic_data_source(&logger, data_source)?;
@@ -456,12 +517,16 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             host: host_metrics,
             stream: block_stream_metrics,
         };
+        let quota = self
+            .quotas
+            .register(deployment.id, priority, metrics.subgraph.cheap_clone());
 
         Ok(SubgraphRunner::new(
             inputs,
             ctx,
             logger.cheap_clone(),
             metrics,
+            quota,
             env_vars,
         ))
     }
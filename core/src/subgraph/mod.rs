@@ -4,6 +4,7 @@ mod inputs;
 mod instance_manager;
 mod loader;
 mod provider;
+mod quota;
 mod registrar;
 mod runner;
 mod state;
@@ -0,0 +1,230 @@
+use graph::components::store::{DeploymentId, DeploymentPriority};
+use graph::prelude::{CheapClone, SubgraphInstanceMetrics};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many seconds worth of its quota a deployment may use up before it
+/// gets throttled. This lets deployments absorb bursts like a block with
+/// an unusual number of triggers without being held back
+const BURST_SECS: f64 = 10.0;
+
+/// The longest we hold back a deployment before processing a block
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// How much of a quota a deployment has used beyond what the quota
+/// allowed. Debts drain at the rate of the quota
+#[derive(Clone, Debug)]
+struct Usage {
+    cpu_ms: f64,
+    writes: f64,
+    updated: Instant,
+    /// The number of runners for the deployment; while a runner is being
+    /// replaced, the old and the new runner can exist at the same time
+    refs: usize,
+    priority: DeploymentPriority,
+}
+
+impl Usage {
+    fn new(now: Instant, priority: DeploymentPriority) -> Self {
+        Usage {
+            cpu_ms: 0.0,
+            writes: 0.0,
+            updated: now,
+            refs: 0,
+            priority,
+        }
+    }
+
+    fn charge(&mut self, now: Instant, cpu_ms_per_sec: f64, writes_per_sec: f64) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.cpu_ms = (self.cpu_ms - elapsed * cpu_ms_per_sec).max(0.0);
+        self.writes = (self.writes - elapsed * writes_per_sec).max(0.0);
+        self.updated = now;
+    }
+
+    /// How long the deployment needs to wait until its debts are back
+    /// within the burst allowance. A rate of 0 means there is no quota.
+    /// High priority deployments are never held back, and low priority
+    /// deployments get only half the burst allowance
+    fn delay(&self, cpu_ms_per_sec: f64, writes_per_sec: f64) -> Duration {
+        let burst = match self.priority {
+            DeploymentPriority::High => return Duration::ZERO,
+            DeploymentPriority::Normal => BURST_SECS,
+            DeploymentPriority::Low => BURST_SECS / 2.0,
+        };
+        let excess = |debt: f64, rate: f64| -> f64 {
+            if rate <= 0.0 {
+                return 0.0;
+            }
+            ((debt - burst * rate) / rate).max(0.0)
+        };
+
+        let secs = excess(self.cpu_ms, cpu_ms_per_sec).max(excess(self.writes, writes_per_sec));
+        Duration::from_secs_f64(secs).min(MAX_DELAY)
+    }
+}
+
+/// Keeps track of how much mapping time and how many entity writes each
+/// deployment on this node uses, and holds back deployments that use more
+/// than their quota so that the other deployments get a larger share of
+/// the node. Quotas are set with `GRAPH_DEPLOYMENT_CPU_QUOTA` and
+/// `GRAPH_DEPLOYMENT_WRITE_QUOTA`
+pub struct QuotaScheduler {
+    cpu_ms_per_sec: f64,
+    writes_per_sec: f64,
+    usage: Mutex<HashMap<DeploymentId, Usage>>,
+}
+
+impl QuotaScheduler {
+    pub fn new(cpu_ms_per_sec: f64, writes_per_sec: f64) -> Self {
+        QuotaScheduler {
+            cpu_ms_per_sec,
+            writes_per_sec,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.cpu_ms_per_sec > 0.0 || self.writes_per_sec > 0.0
+    }
+
+    /// Start tracking the usage of `id`. Usage is tracked until the
+    /// returned `DeploymentQuota` is dropped
+    pub fn register(
+        self: &Arc<Self>,
+        id: DeploymentId,
+        priority: DeploymentPriority,
+        metrics: Arc<SubgraphInstanceMetrics>,
+    ) -> DeploymentQuota {
+        let mut deployments = self.usage.lock().unwrap();
+        let usage = deployments
+            .entry(id)
+            .or_insert_with(|| Usage::new(Instant::now(), priority));
+        usage.priority = priority;
+        usage.refs += 1;
+        DeploymentQuota {
+            id,
+            scheduler: self.cheap_clone(),
+            metrics,
+        }
+    }
+
+    fn record(&self, id: DeploymentId, cpu: Duration, writes: usize) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(usage) = usage.get_mut(&id) {
+            usage.charge(Instant::now(), self.cpu_ms_per_sec, self.writes_per_sec);
+            usage.cpu_ms += cpu.as_secs_f64() * 1000.0;
+            usage.writes += writes as f64;
+        }
+    }
+
+    fn delay(&self, id: DeploymentId) -> Duration {
+        let mut usage = self.usage.lock().unwrap();
+        // A deployment that has the node to itself can use all of it
+        if !self.enabled() || usage.len() < 2 {
+            return Duration::ZERO;
+        }
+        match usage.get_mut(&id) {
+            Some(usage) => {
+                usage.charge(Instant::now(), self.cpu_ms_per_sec, self.writes_per_sec);
+                usage.delay(self.cpu_ms_per_sec, self.writes_per_sec)
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    fn unregister(&self, id: DeploymentId) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(entry) = usage.get_mut(&id) {
+            entry.refs -= 1;
+            if entry.refs == 0 {
+                usage.remove(&id);
+            }
+        }
+    }
+}
+
+/// The quota of one deployment. The subgraph runner reports the resources
+/// it used for each block with `record` and calls `throttle` before it
+/// processes the next block
+pub struct DeploymentQuota {
+    id: DeploymentId,
+    scheduler: Arc<QuotaScheduler>,
+    metrics: Arc<SubgraphInstanceMetrics>,
+}
+
+impl DeploymentQuota {
+    pub fn record(&self, cpu: Duration, writes: usize) {
+        self.metrics.quota_cpu_ms.inc_by(cpu.as_secs_f64() * 1000.0);
+        self.metrics.quota_writes.inc_by(writes as f64);
+        self.scheduler.record(self.id, cpu, writes);
+    }
+
+    /// Wait if the deployment has used more than its quota
+    pub async fn throttle(&self) {
+        let delay = self.scheduler.delay(self.id);
+        if delay > Duration::ZERO {
+            self.metrics
+                .quota_throttled_secs
+                .inc_by(delay.as_secs_f64());
+            graph::tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+impl Drop for DeploymentQuota {
+    fn drop(&mut self) {
+        self.scheduler.unregister(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_drains_at_quota_rate() {
+        let start = Instant::now();
+        let mut usage = Usage::new(start, DeploymentPriority::Normal);
+        usage.cpu_ms = 5000.0;
+        usage.writes = 300.0;
+
+        usage.charge(start + Duration::from_secs(2), 1000.0, 100.0);
+        assert_eq!(3000.0, usage.cpu_ms);
+        assert_eq!(100.0, usage.writes);
+
+        usage.charge(start + Duration::from_secs(10), 1000.0, 100.0);
+        assert_eq!(0.0, usage.cpu_ms);
+        assert_eq!(0.0, usage.writes);
+    }
+
+    #[test]
+    fn delay_beyond_burst() {
+        let mut usage = Usage::new(Instant::now(), DeploymentPriority::Normal);
+
+        // Within the burst allowance
+        usage.cpu_ms = BURST_SECS * 100.0;
+        assert_eq!(Duration::ZERO, usage.delay(100.0, 0.0));
+
+        // Two seconds worth of cpu time beyond the burst allowance
+        usage.cpu_ms = BURST_SECS * 100.0 + 200.0;
+        assert_eq!(Duration::from_secs(2), usage.delay(100.0, 0.0));
+
+        // No quota means no delay
+        assert_eq!(Duration::ZERO, usage.delay(0.0, 0.0));
+
+        // The longer of the two delays wins, capped at MAX_DELAY
+        usage.writes = BURST_SECS * 10.0 + 30.0;
+        assert_eq!(Duration::from_secs(3), usage.delay(100.0, 10.0));
+        usage.writes = BURST_SECS * 10.0 + 1000.0;
+        assert_eq!(MAX_DELAY, usage.delay(100.0, 10.0));
+
+        // Priority changes how much a deployment gets held back
+        usage.writes = 0.0;
+        usage.priority = DeploymentPriority::High;
+        assert_eq!(Duration::ZERO, usage.delay(100.0, 0.0));
+        usage.priority = DeploymentPriority::Low;
+        assert_eq!(Duration::from_secs(7), usage.delay(100.0, 0.0));
+    }
+}
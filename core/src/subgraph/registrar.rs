@@ -5,8 +5,11 @@ use async_trait::async_trait;
 use graph::blockchain::Blockchain;
 use graph::blockchain::BlockchainKind;
 use graph::blockchain::BlockchainMap;
-use graph::components::store::{DeploymentId, DeploymentLocator, SubscriptionManager};
+use graph::components::store::{
+    DeploymentId, DeploymentLocator, DeploymentPriority, SubscriptionManager,
+};
 use graph::components::subgraph::Settings;
+use graph::data::subgraph::capabilities;
 use graph::data::subgraph::schema::DeploymentCreate;
 use graph::data::subgraph::Graft;
 use graph::data::value::Word;
@@ -318,6 +321,8 @@ where
                 .map_err(|e| SubgraphRegistrarError::ResolveError(e.into()))?
         };
 
+        check_capabilities(&logger, &self.resolver, &self.chains, &raw).await?;
+
         let kind = BlockchainKind::from_manifest(&raw).map_err(|e| {
             SubgraphRegistrarError::ResolveError(SubgraphManifestResolveError::ResolveError(e))
         })?;
@@ -492,6 +497,20 @@ where
 
         Ok(())
     }
+
+    async fn set_subgraph_priority(
+        &self,
+        hash: &DeploymentHash,
+        priority: DeploymentPriority,
+    ) -> Result<(), SubgraphRegistrarError> {
+        let locator = self.store.active_locator(hash)?;
+        let deployment =
+            locator.ok_or_else(|| SubgraphRegistrarError::DeploymentNotFound(hash.to_string()))?;
+
+        self.store.set_priority(&deployment, priority)?;
+
+        Ok(())
+    }
 }
 
 async fn handle_assignment_event(
@@ -604,6 +623,45 @@ async fn resolve_graft_block(
         })
 }
 
+/// Check the raw manifest and its schema against what this node supports
+/// so that the deployer gets a list of everything that is not supported
+/// rather than an error for the first of them
+async fn check_capabilities(
+    logger: &Logger,
+    resolver: &Arc<dyn LinkResolver>,
+    chains: &BlockchainMap,
+    raw: &serde_yaml::Mapping,
+) -> Result<(), SubgraphRegistrarError> {
+    let mut unsupported = capabilities::check_capabilities(raw, &ENV_VARS.max_spec_version, chains);
+
+    let spec_version = raw
+        .get(&serde_yaml::Value::from("specVersion"))
+        .and_then(serde_yaml::Value::as_str)
+        .and_then(|version| Version::parse(version).ok());
+    let schema_link = raw
+        .get(&serde_yaml::Value::from("schema"))
+        .and_then(|schema| schema.get("file"))
+        .and_then(|file| file.get("/"))
+        .and_then(serde_yaml::Value::as_str);
+    if let (Some(spec_version), Some(schema_link)) = (spec_version, schema_link) {
+        // A schema that can not be fetched is reported when the manifest
+        // is resolved
+        if let Ok(schema) = resolver.cat(logger, &Link::from(schema_link)).await {
+            let schema = String::from_utf8_lossy(&schema);
+            unsupported.extend(capabilities::check_schema_capabilities(
+                &schema,
+                &spec_version,
+            ));
+        }
+    }
+
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(SubgraphRegistrarError::UnsupportedFeatures(unsupported))
+    }
+}
+
 async fn create_subgraph_version<C: Blockchain, S: SubgraphStore>(
     logger: &Logger,
     store: Arc<S>,
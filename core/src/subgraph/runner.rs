@@ -1,8 +1,9 @@
 use crate::subgraph::context::IndexingContext;
 use crate::subgraph::error::BlockProcessingError;
 use crate::subgraph::inputs::IndexingInputs;
+use crate::subgraph::quota::DeploymentQuota;
 use crate::subgraph::state::IndexingState;
-use crate::subgraph::stream::new_block_stream;
+use crate::subgraph::stream::{BlockStreamSupervisor, StreamErrorAction};
 use atomic_refcell::AtomicRefCell;
 use graph::blockchain::block_stream::{
     BlockStreamError, BlockStreamEvent, BlockWithTriggers, FirehoseCursor,
@@ -10,6 +11,7 @@ use graph::blockchain::block_stream::{
 use graph::blockchain::{Block, BlockTime, Blockchain, DataSource as _, TriggerFilter as _};
 use graph::components::store::{EmptyStore, GetScope, ReadStore, StoredDynamicDataSource};
 use graph::components::subgraph::InstanceDSTemplate;
+use graph::components::trigger_processor::{HostedTrigger, RunnableTriggers};
 use graph::components::{
     store::ModificationsAndCache,
     subgraph::{MappingError, PoICausalityRegion, ProofOfIndexing, SharedProofOfIndexing},
@@ -20,19 +22,25 @@ use graph::data::subgraph::{
     SubgraphFeature,
 };
 use graph::data_source::{
-    offchain, CausalityRegion, DataSource, DataSourceCreationError, TriggerData,
+    offchain, subgraph, CausalityRegion, DataSource, DataSourceCreationError, TriggerData,
 };
 use graph::env::EnvVars;
+use graph::futures03::future::join_all;
 use graph::futures03::stream::StreamExt;
 use graph::futures03::TryStreamExt;
 use graph::prelude::*;
 use graph::schema::EntityKey;
 use graph::util::{backoff::ExponentialBackoff, lfu_cache::LfuCache};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 const MINUTE: Duration = Duration::from_secs(60);
 
+/// How often to check whether the source of a subgraph data source has
+/// caught up with the block this subgraph is processing
+const SOURCE_SUBGRAPH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 const SKIP_PTR_UPDATES_THRESHOLD: Duration = Duration::from_secs(60 * 5);
 const HANDLE_REVERT_SECTION_NAME: &str = "handle_revert";
 const PROCESS_BLOCK_SECTION_NAME: &str = "process_block";
@@ -49,6 +57,11 @@ where
     state: IndexingState,
     inputs: Arc<IndexingInputs<C>>,
     logger: Logger,
+    stream_supervisor: BlockStreamSupervisor,
+    quota: DeploymentQuota,
+    /// How many triggers of a block to run concurrently for a deployment
+    /// of normal priority
+    trigger_concurrency: usize,
     pub metrics: RunnerMetrics,
 }
 
@@ -62,6 +75,7 @@ where
         ctx: IndexingContext<C, T>,
         logger: Logger,
         metrics: RunnerMetrics,
+        quota: DeploymentQuota,
         env_vars: Arc<EnvVars>,
     ) -> Self {
         Self {
@@ -77,7 +91,15 @@ where
                 ),
                 entity_lfu_cache: LfuCache::new(),
                 cached_head_ptr: None,
+                indexes_deferred: None,
+                entity_cache_checkpointed: Instant::now(),
             },
+            stream_supervisor: BlockStreamSupervisor::new(
+                logger.cheap_clone(),
+                metrics.subgraph.cheap_clone(),
+            ),
+            quota,
+            trigger_concurrency: env_vars.mappings.trigger_concurrency,
             logger,
             metrics,
         }
@@ -102,6 +124,81 @@ where
         Ok(())
     }
 
+    /// Load the entities that were in the entity cache when the subgraph
+    /// last saved a checkpoint of it, and the most recently written
+    /// entities of the types in `warmup_entity_types` into the entity
+    /// cache. Without this, the first blocks after a restart have to load
+    /// every entity they touch from the store, one handler at a time
+    async fn warm_up_entity_cache(&mut self) -> Result<(), StoreError> {
+        let start = Instant::now();
+        let mut keys: BTreeSet<_> = self
+            .inputs
+            .store
+            .entity_cache_checkpoint()?
+            .into_iter()
+            .collect();
+        let count = self.inputs.warmup_entity_count;
+        if count > 0 {
+            for entity_type in &self.inputs.warmup_entity_types {
+                keys.extend(
+                    self.inputs
+                        .store
+                        .recent_entity_keys(entity_type, count)
+                        .await?,
+                );
+            }
+        }
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut entities = self.inputs.store.get_many(keys.clone())?;
+
+        let cache = &mut self.state.entity_lfu_cache;
+        for key in keys {
+            let entity = entities.remove(&key).map(Arc::new);
+            cache.insert(key, entity);
+        }
+        cache.evict(ENV_VARS.mappings.entity_cache_size);
+
+        info!(self.logger, "Warmed up entity cache";
+              "entities" => cache.len(),
+              "time_ms" => start.elapsed().as_millis());
+        Ok(())
+    }
+
+    /// Save the keys of the entities in the entity cache so that a
+    /// restarted subgraph can warm up its cache with them. With `force`
+    /// unset, only save them if `GRAPH_ENTITY_CACHE_CHECKPOINT_INTERVAL`
+    /// has passed since they were last saved
+    async fn checkpoint_entity_cache(&mut self, force: bool) {
+        let due = ENV_VARS
+            .mappings
+            .entity_cache_checkpoint_interval
+            .map_or(false, |interval| {
+                self.state.entity_cache_checkpointed.elapsed() >= interval
+            });
+        if !force && !due {
+            return;
+        }
+        self.state.entity_cache_checkpointed = Instant::now();
+
+        let keys: Vec<_> = self
+            .state
+            .entity_lfu_cache
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect();
+        let count = keys.len();
+        // A missing checkpoint only makes the first blocks after a restart
+        // slower
+        match self.inputs.store.checkpoint_entity_cache(keys).await {
+            Ok(()) => debug!(self.logger, "Saved entity cache checkpoint"; "entities" => count),
+            Err(e) => warn!(self.logger, "Failed to save entity cache checkpoint";
+                            "error" => e.to_string()),
+        }
+    }
+
     #[cfg(debug_assertions)]
     pub fn context(&self) -> &IndexingContext<C, T> {
         &self.ctx
@@ -199,6 +296,11 @@ where
             }
         }
 
+        // Failing to warm up the cache only makes the first blocks slower
+        if let Err(e) = self.warm_up_entity_cache().await {
+            warn!(self.logger, "Failed to warm up entity cache"; "error" => e.to_string());
+        }
+
         loop {
             debug!(self.logger, "Starting or restarting subgraph");
 
@@ -207,21 +309,38 @@ where
             // TriggerFilter needs to be rebuilt eveytime the blockstream is restarted
             self.ctx.filter = Some(self.build_filter());
 
-            let mut block_stream = new_block_stream(
-                &self.inputs,
-                self.ctx.filter.as_ref().unwrap(), // Safe to unwrap as we just called `build_filter` in the previous line
-                &self.metrics.subgraph,
-            )
-            .await?
-            .map_err(CancelableError::from)
-            .cancelable(&block_stream_canceler, || Err(CancelableError::Cancel));
-
             // Keep the stream's cancel guard around to be able to shut it down when the subgraph
             // deployment is unassigned
             self.ctx
                 .instances
                 .insert(self.inputs.deployment.id, block_stream_canceler);
 
+            let block_stream = self
+                .stream_supervisor
+                .start(
+                    &self.inputs,
+                    self.ctx.filter.as_ref().unwrap(), // Safe to unwrap as we just called `build_filter` in the previous line
+                    &block_stream_cancel_handle,
+                )
+                .await?;
+            let mut block_stream = match block_stream {
+                Some(block_stream) => {
+                    // The new stream was built with the filters of all data
+                    // sources, including the ones added to the old stream
+                    self.ctx.filter_extender = block_stream.filter_extender();
+                    self.ctx.extended_filters.clear();
+                    block_stream
+                        .map_err(CancelableError::from)
+                        .cancelable(&block_stream_cancel_handle, || Err(CancelableError::Cancel))
+                }
+                None => {
+                    info!(self.logger, "Stopping subgraph");
+                    self.inputs.store.flush().await?;
+                    self.checkpoint_entity_cache(true).await;
+                    return Ok(self);
+                }
+            };
+
             debug!(self.logger, "Starting block stream");
 
             // Process events from the stream as long as no restart is needed
@@ -231,6 +350,9 @@ where
 
                     block_stream.next().await
                 };
+                if let Some(Ok(_)) = event {
+                    self.stream_supervisor.on_event();
+                }
 
                 // TODO: move cancel handle to the Context
                 // This will require some code refactor in how the BlockStream is created
@@ -244,10 +366,14 @@ where
                             .observe_block_processed(block_start.elapsed(), res.block_finished());
                         res
                     })? {
-                    Action::Continue => continue,
+                    Action::Continue => {
+                        self.checkpoint_entity_cache(false).await;
+                        continue;
+                    }
                     Action::Stop => {
                         info!(self.logger, "Stopping subgraph");
                         self.inputs.store.flush().await?;
+                        self.checkpoint_entity_cache(true).await;
                         return Ok(self);
                     }
                     Action::Restart if break_on_restart => {
@@ -272,6 +398,202 @@ where
         }
     }
 
+    /// Add the triggers of data sources that were added to the filter of
+    /// the running block stream to `block`. The stream might have scanned
+    /// the block before they were added
+    async fn add_extended_filter_triggers(
+        &mut self,
+        block: BlockWithTriggers<C>,
+    ) -> Result<BlockWithTriggers<C>, BlockProcessingError> {
+        let number = block.block.number();
+
+        // The stream scans blocks in order, so once it produces a block
+        // that it scanned with an extended filter, all later blocks have
+        // been scanned with it, too
+        self.ctx
+            .extended_filters
+            .retain(|(_, first_block)| number < *first_block);
+        if self.ctx.extended_filters.is_empty() {
+            return Ok(block);
+        }
+
+        let mut trigger_data = block.trigger_data;
+        for (filter, _) in &self.ctx.extended_filters {
+            let extra = self
+                .inputs
+                .triggers_adapter
+                .triggers_in_block(&self.logger, block.block.clone(), filter)
+                .await?;
+            trigger_data.extend(extra.trigger_data);
+        }
+        Ok(BlockWithTriggers::new(
+            block.block,
+            trigger_data,
+            &self.logger,
+        ))
+    }
+
+    /// Run the triggers in `runnables` in order and return the resulting
+    /// state.
+    ///
+    /// With `GRAPH_MAPPING_TRIGGER_CONCURRENCY` larger than 1, a window of
+    /// that many triggers is run concurrently, each trigger in a fork of
+    /// `state` and with its own recording PoI. The forks are then merged
+    /// in trigger order until we reach one that can't be merged because
+    /// its outcome might depend on the triggers before it in the window.
+    /// That trigger is run again against the merged state, and the next
+    /// window starts right after it.
+    async fn process_triggers<'a>(
+        &'a self,
+        logger: &Logger,
+        block: &Arc<C::Block>,
+        runnables: Vec<RunnableTriggers<'a, C>>,
+        mut state: BlockState,
+        proof_of_indexing: &SharedProofOfIndexing,
+        causality_region: &str,
+    ) -> Result<BlockState, MappingError> {
+        let concurrency = self
+            .inputs
+            .priority
+            .trigger_concurrency(self.trigger_concurrency);
+        let mut pending = VecDeque::from(runnables);
+
+        while !pending.is_empty() {
+            if concurrency <= 1 || pending.len() == 1 {
+                let RunnableTriggers {
+                    trigger,
+                    hosted_triggers,
+                } = pending.pop_front().unwrap();
+                state = self
+                    .process_hosted_triggers(
+                        &trigger,
+                        hosted_triggers,
+                        block,
+                        state,
+                        proof_of_indexing,
+                        causality_region,
+                    )
+                    .await?;
+                continue;
+            }
+
+            let window = pending.drain(..concurrency.min(pending.len()));
+            let forks = state.forks(window.len());
+            let mut triggers = Vec::with_capacity(forks.len());
+            let mut runs = Vec::with_capacity(forks.len());
+            for (runnable, fork) in window.zip(forks) {
+                let RunnableTriggers {
+                    trigger,
+                    hosted_triggers,
+                } = runnable;
+                let hosts: Vec<_> = hosted_triggers.iter().map(|hosted| hosted.host).collect();
+                let recorder = proof_of_indexing.as_ref().map(|_| {
+                    Arc::new(AtomicRefCell::new(ProofOfIndexing::recorder(
+                        block.number(),
+                        self.inputs.poi_version,
+                    )))
+                });
+                triggers.push((trigger, hosts, recorder.cheap_clone()));
+                runs.push((hosted_triggers, fork, recorder));
+            }
+
+            let results = join_all(runs.into_iter().zip(triggers.iter()).map(
+                |((hosted_triggers, fork, recorder), (trigger, _, _))| async move {
+                    self.process_hosted_triggers(
+                        trigger,
+                        hosted_triggers,
+                        block,
+                        fork,
+                        &recorder,
+                        causality_region,
+                    )
+                    .await
+                    .map(|mut fork| {
+                        fork.entity_cache.detach();
+                        fork
+                    })
+                },
+            ))
+            .await;
+
+            // The entities changed by the triggers merged so far
+            let mut changed = HashSet::new();
+            let mut results = triggers.into_iter().zip(results);
+            while let Some(((trigger, hosts, recorder), result)) = results.next() {
+                let fork = match result {
+                    Ok(fork) if can_merge(&fork, &changed) => fork,
+                    _ => {
+                        // Run the trigger again against the state of all
+                        // the triggers before it; the results of the
+                        // triggers after it in the window are stale
+                        self.metrics.subgraph.trigger_conflicts.inc();
+                        let runnable = redecode(logger, block, trigger, hosts)?;
+                        state = self
+                            .process_hosted_triggers(
+                                &runnable.trigger,
+                                runnable.hosted_triggers,
+                                block,
+                                state,
+                                proof_of_indexing,
+                                causality_region,
+                            )
+                            .await?;
+                        break;
+                    }
+                };
+
+                changed.extend(fork.entity_cache.footprint().writes);
+                if let (Some(proof_of_indexing), Some(recorder)) = (proof_of_indexing, recorder) {
+                    proof_of_indexing
+                        .borrow_mut()
+                        .replay(logger, &mut recorder.borrow_mut());
+                }
+                state.extend(fork);
+            }
+
+            let stale: Vec<_> = results.collect();
+            for ((trigger, hosts, _), _) in stale.into_iter().rev() {
+                pending.push_front(redecode(logger, block, trigger, hosts)?);
+            }
+        }
+
+        Ok(state)
+    }
+
+    async fn process_hosted_triggers<'a>(
+        &'a self,
+        trigger: &TriggerData<C>,
+        hosted_triggers: Vec<HostedTrigger<'a, C>>,
+        block: &Arc<C::Block>,
+        state: BlockState,
+        proof_of_indexing: &SharedProofOfIndexing,
+        causality_region: &str,
+    ) -> Result<BlockState, MappingError> {
+        let first_error = state.deterministic_errors.len();
+        let mut state = self
+            .ctx
+            .trigger_processor
+            .process_trigger(
+                &self.logger,
+                hosted_triggers,
+                block,
+                state,
+                proof_of_indexing,
+                causality_region,
+                &self.inputs.debug_fork,
+                &self.metrics.subgraph,
+                self.inputs.instrument,
+            )
+            .await
+            .map_err(|e| e.add_trigger_context(trigger))?;
+        state.skip_errors(
+            first_error,
+            &self.inputs.skip_error_handlers,
+            &trigger.error_context(),
+        );
+        Ok(state)
+    }
+
     /// Processes a block and returns the updated context and a boolean flag indicating
     /// whether new dynamic data sources have been added to the subgraph.
     async fn process_block(
@@ -280,6 +602,7 @@ where
         block: BlockWithTriggers<C>,
         firehose_cursor: FirehoseCursor,
     ) -> Result<Action, BlockProcessingError> {
+        let block = self.add_extended_filter_triggers(block).await?;
         let triggers = block.trigger_data;
         let block = Arc::new(block.block);
         let block_ptr = block.ptr();
@@ -289,8 +612,26 @@ where
                 "block_hash" => format!("{}", block_ptr.hash)
         ));
 
+        // Mappings that read other deployments with `store.loadFrom` need
+        // those deployments to have processed this block
+        for deployment in self.inputs.dependencies.deployments() {
+            self.wait_for_source(
+                &logger,
+                &deployment,
+                block_ptr.number,
+                block_stream_cancel_handle,
+            )
+            .await?;
+            self.check_source_block(&deployment, &block_ptr).await?;
+        }
+
+        let subgraph_triggers = self
+            .subgraph_triggers(&logger, block_ptr.number, block_stream_cancel_handle)
+            .await?;
+
         debug!(logger, "Start processing block";
-               "triggers" => triggers.len());
+               "triggers" => triggers.len(),
+               "subgraph_triggers" => subgraph_triggers.len());
 
         let proof_of_indexing = if self.inputs.store.supports_proof_of_indexing().await? {
             Some(Arc::new(AtomicRefCell::new(ProofOfIndexing::new(
@@ -315,6 +656,9 @@ where
             .stopwatch
             .start_section(PROCESS_TRIGGERS_SECTION_NAME);
 
+        // Mapping time counts against the deployment's CPU quota
+        let mapping_start = Instant::now();
+
         // Match and decode all triggers in the block
         let hosts_filter = |trigger: &TriggerData<C>| self.ctx.instance.hosts_for_trigger(trigger);
         let match_res = self
@@ -323,46 +667,30 @@ where
             .match_and_decode_many(
                 &logger,
                 &block,
-                triggers.into_iter().map(TriggerData::Onchain),
+                triggers
+                    .into_iter()
+                    .map(TriggerData::Onchain)
+                    .chain(subgraph_triggers.into_iter().map(TriggerData::Subgraph)),
                 hosts_filter,
                 &self.metrics.subgraph,
             )
             .await;
 
-        // Process events one after the other, passing in entity operations
-        // collected previously to every new event being processed
-        let mut res = Ok(block_state);
-        match match_res {
+        // Process events, passing in entity operations collected
+        // previously to every new event being processed
+        let res = match match_res {
             Ok(runnables) => {
-                for runnable in runnables {
-                    let process_res = self
-                        .ctx
-                        .trigger_processor
-                        .process_trigger(
-                            &self.logger,
-                            runnable.hosted_triggers,
-                            &block,
-                            res.unwrap(),
-                            &proof_of_indexing,
-                            &causality_region,
-                            &self.inputs.debug_fork,
-                            &self.metrics.subgraph,
-                            self.inputs.instrument,
-                        )
-                        .await
-                        .map_err(|e| e.add_trigger_context(&runnable.trigger));
-                    match process_res {
-                        Ok(state) => res = Ok(state),
-                        Err(e) => {
-                            res = Err(e);
-                            break;
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                res = Err(e);
+                self.process_triggers(
+                    &logger,
+                    &block,
+                    runnables,
+                    block_state,
+                    &proof_of_indexing,
+                    &causality_region,
+                )
+                .await
             }
+            Err(e) => Err(e),
         };
 
         match res {
@@ -392,10 +720,13 @@ where
         // of that data source is equal to the block number of the current block.
         let has_expired_data_sources = self.inputs.end_blocks.contains(&block_ptr.number);
 
-        // If new onchain data sources have been created, and static filters are not in use, it is necessary
-        // to restart the block stream with the new filters.
-        let created_data_sources_needs_restart =
-            !self.is_static_filters_enabled() && block_state.has_created_on_chain_data_sources();
+        // If new onchain data sources have been created, and static filters are not in use, the
+        // block stream needs the filters of the new data sources. If the stream can not extend its
+        // filter while it runs, it is necessary to restart it with the new filters.
+        let extend_filter = !self.is_static_filters_enabled();
+        let created_data_sources_needs_restart = extend_filter
+            && self.ctx.filter_extender.is_none()
+            && block_state.has_created_on_chain_data_sources();
 
         // Determine if the block stream needs to be restarted due to newly created on-chain data sources
         // or data sources that have reached their end block.
@@ -425,6 +756,21 @@ where
                     data_sources.iter().filter_map(DataSource::as_onchain),
                 );
 
+                if let Some(extender) = self.ctx.filter_extender.as_ref().filter(|_| extend_filter)
+                {
+                    let onchain: Vec<_> = data_sources
+                        .iter()
+                        .filter_map(DataSource::as_onchain)
+                        .cloned()
+                        .collect();
+                    if !onchain.is_empty() {
+                        let first_block = extender.extend(&onchain);
+                        self.ctx
+                            .extended_filters
+                            .push((filter.clone(), first_block));
+                    }
+                }
+
                 let block: Arc<C::Block> = if self.inputs.chain.is_refetch_block_required() {
                     let cur = firehose_cursor.clone();
                     let log = logger.cheap_clone();
@@ -536,6 +882,7 @@ where
             }
         }
 
+        let mapping_time = mapping_start.elapsed();
         let has_errors = block_state.has_errors();
         let is_non_fatal_errors_active = self
             .inputs
@@ -629,6 +976,7 @@ where
 
         let BlockState {
             deterministic_errors,
+            skipped_triggers,
             mut persisted_data_sources,
             metrics: block_state_metrics,
             ..
@@ -637,7 +985,28 @@ where
         let first_error = deterministic_errors.first().cloned();
 
         let is_caught_up = self.is_caught_up(&block_ptr).await?;
+        self.maintain_deferred_indexes(&block_ptr).await?;
+
+        if !skipped_triggers.is_empty() {
+            for skipped in &skipped_triggers {
+                warn!(&logger, "Skipped trigger because of the handler's error policy";
+                    "handler" => &skipped.handler,
+                    "trigger" => &skipped.trigger,
+                    "error" => &skipped.message
+                );
+            }
+            self.metrics
+                .subgraph
+                .skipped_triggers
+                .inc_by(skipped_triggers.len() as f64);
+            self.inputs
+                .store
+                .record_skipped_triggers(skipped_triggers)
+                .await
+                .context("Failed to record skipped triggers")?;
+        }
 
+        let writes = mods.len();
         persisted_data_sources.extend(persisted_off_chain_data_sources);
         self.inputs
             .store
@@ -655,6 +1024,7 @@ where
             )
             .await
             .context("Failed to transact block operations")?;
+        self.quota.record(mapping_time, writes);
 
         // For subgraphs with `nonFatalErrors` feature disabled, we consider
         // any error as fatal.
@@ -806,6 +1176,7 @@ where
         &mut self,
         start: Instant,
         block_ptr: BlockPtr,
+        parent_ptr: Option<BlockPtr>,
         action: Result<Action, BlockProcessingError>,
     ) -> Result<Action, Error> {
         self.state.skip_ptr_updates_timer = Instant::now();
@@ -879,11 +1250,20 @@ where
                         // Fail subgraph:
                         // - Change status/health.
                         // - Save the error to the database.
+                        // - Remember the block before the failing block as
+                        //   the point to graft a fixed deployment onto.
                         self.inputs
                             .store
                             .fail_subgraph(error)
                             .await
                             .context("Failed to set subgraph status to `failed`")?;
+                        if let Some(parent_ptr) = parent_ptr {
+                            self.inputs
+                                .store
+                                .set_last_healthy_block(parent_ptr)
+                                .await
+                                .context("Failed to set the last healthy block")?;
+                        }
 
                         return Err(err);
                     }
@@ -974,6 +1354,46 @@ where
         }
         Ok(is_caught_up)
     }
+
+    /// If `GRAPH_STORE_DEFER_INDEXES_DISTANCE` is set, drop the indexes
+    /// that indexing does not need when the deployment is far behind the
+    /// chain head as it starts, and recreate them once it gets close to
+    /// the chain head. Indexes are only ever dropped for the first block
+    /// that we process so that a deployment that briefly falls behind
+    /// does not lose its indexes
+    async fn maintain_deferred_indexes(&mut self, block_ptr: &BlockPtr) -> Result<(), Error> {
+        let Some(distance) = ENV_VARS.store.defer_indexes_distance else {
+            return Ok(());
+        };
+        if self.state.indexes_deferred == Some(false) || self.state.cached_head_ptr.is_none() {
+            return Ok(());
+        }
+
+        // `cached_head_ptr` is only refreshed when the deployment gets
+        // close to it, make sure we really are close before recreating
+        // indexes
+        let mut close = close_to_chain_head(block_ptr, &self.state.cached_head_ptr, distance);
+        if close {
+            let head_ptr = self.inputs.chain.chain_store().chain_head_ptr().await?;
+            close = close_to_chain_head(block_ptr, &head_ptr, distance);
+        }
+
+        match (self.state.indexes_deferred, close) {
+            (None, false) => {
+                self.inputs.store.defer_indexes().await?;
+                self.state.indexes_deferred = Some(true);
+            }
+            // For `None`, recreate any indexes that were dropped before
+            // the deployment was restarted. Keep checking until all of
+            // them have been recreated
+            (None, true) | (Some(true), true) => {
+                let restored = self.inputs.store.restore_deferred_indexes().await?;
+                self.state.indexes_deferred = Some(!restored);
+            }
+            (Some(true), false) | (Some(false), _) => {}
+        }
+        Ok(())
+    }
 }
 
 impl<C, T> SubgraphRunner<C, T>
@@ -1037,6 +1457,154 @@ where
         Ok(action)
     }
 
+    /// Load the entities that the sources of subgraph data sources wrote
+    /// since the last block this subgraph processed, up to and including
+    /// `block`. Source changes from blocks that the block stream skipped
+    /// are processed with the next block the subgraph processes. To keep
+    /// the subgraph aligned with its sources, this waits until all sources
+    /// have processed `block`
+    async fn subgraph_triggers(
+        &self,
+        logger: &Logger,
+        block: BlockNumber,
+        cancel_handle: &CancelHandle,
+    ) -> Result<Vec<subgraph::TriggerData>, BlockProcessingError> {
+        let mut sources: BTreeMap<DeploymentHash, BlockNumber> = BTreeMap::new();
+        for ds in self
+            .ctx
+            .static_data_sources()
+            .iter()
+            .filter_map(|ds| ds.as_subgraph())
+            .filter(|ds| ds.start_block() <= block)
+        {
+            let start = sources.entry(ds.source.address.clone()).or_insert(block);
+            *start = (*start).min(ds.start_block());
+        }
+        if sources.is_empty() {
+            return Ok(vec![]);
+        }
+
+        for source in sources.keys() {
+            self.wait_for_source(logger, source, block, cancel_handle)
+                .await?;
+        }
+
+        let first = self
+            .inputs
+            .store
+            .block_ptr()
+            .map(|ptr| ptr.number + 1)
+            .unwrap_or(0);
+        let store = &self.inputs.subgraph_store;
+        let mut triggers = Vec::new();
+        for number in first..=block {
+            for (source, start) in &sources {
+                if number < *start {
+                    continue;
+                }
+                // Only entities that were created or updated cause
+                // triggers; removals have no entity to pass to handlers
+                let mut changes: Vec<_> = store
+                    .entity_changes_in_block(source, number)?
+                    .into_iter()
+                    .filter_map(|change| match change {
+                        EntityOperation::Set { key, data } => Some((key, data)),
+                        EntityOperation::Remove { .. } => None,
+                    })
+                    .collect();
+                changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+                triggers.extend(
+                    changes
+                        .into_iter()
+                        .map(|(key, entity)| subgraph::TriggerData {
+                            source: source.clone(),
+                            entity_type: key.entity_type.as_str().to_string(),
+                            entity,
+                        }),
+                );
+            }
+        }
+        Ok(triggers)
+    }
+
+    /// Wait until the deployment `source`, either the source of a subgraph
+    /// data source or a deployment that mappings read with
+    /// `store.loadFrom`, has processed `block`
+    async fn wait_for_source(
+        &self,
+        logger: &Logger,
+        source: &DeploymentHash,
+        block: BlockNumber,
+        cancel_handle: &CancelHandle,
+    ) -> Result<(), BlockProcessingError> {
+        let mut logged = false;
+        loop {
+            let source_ptr = self.inputs.subgraph_store.least_block_ptr(source).await?;
+            if source_ptr.as_ref().map_or(false, |ptr| ptr.number >= block) {
+                return Ok(());
+            }
+            if !self.inputs.subgraph_store.is_deployed(source)? {
+                return Err(BlockProcessingError::Unknown(anyhow!(
+                    "the source deployment {} does not exist",
+                    source
+                )));
+            }
+            if !logged {
+                info!(logger, "Waiting for source subgraph to process block";
+                    "source" => source,
+                    "source_block" => source_ptr.map(|ptr| ptr.number));
+                logged = true;
+            }
+            if cancel_handle.is_canceled() {
+                return Err(BlockProcessingError::Canceled);
+            }
+            graph::tokio::time::sleep(SOURCE_SUBGRAPH_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Check that the deployment `source`, which has processed `block`,
+    /// processed the same block as this subgraph and not a block from a
+    /// different fork, and remember that for `store.loadFrom`. If the
+    /// source processed a different block, one of the two deployments
+    /// will be reverted and processing the block can be retried afterwards
+    async fn check_source_block(
+        &self,
+        source: &DeploymentHash,
+        block: &BlockPtr,
+    ) -> Result<(), BlockProcessingError> {
+        let head = self
+            .inputs
+            .subgraph_store
+            .least_block_ptr(source)
+            .await?
+            .ok_or_else(|| anyhow!("the source deployment {} has no blocks", source))?;
+        let source_block = if head.number == block.number {
+            Some(head.clone())
+        } else {
+            self.inputs
+                .triggers_adapter
+                .ancestor_block(head.clone(), head.number - block.number, None)
+                .await?
+                .map(|ancestor| ancestor.ptr())
+        };
+        match source_block {
+            Some(source_block) if &source_block == block => {}
+            // Without the blocks in between in the block cache, we can
+            // only rely on the block being final
+            None if head.number - block.number > ENV_VARS.reorg_threshold => {}
+            _ => {
+                return Err(BlockProcessingError::Unknown(anyhow!(
+                    "the source deployment {} did not process block {}; its latest block is {}",
+                    source,
+                    block,
+                    head
+                )))
+            }
+        }
+        self.inputs.dependencies.set_checked(source, block.clone());
+        Ok(())
+    }
+
     async fn handle_offchain_triggers(
         &mut self,
         triggers: Vec<offchain::TriggerData>,
@@ -1053,89 +1621,111 @@ where
         let mut processed_data_sources = vec![];
         let mut persisted_data_sources = vec![];
 
-        for trigger in triggers {
-            // Using an `EmptyStore` and clearing the cache for each trigger is a makeshift way to
-            // get causality region isolation.
-            let schema = ReadStore::input_schema(&self.inputs.store);
-            let mut block_state = BlockState::new(EmptyStore::new(schema), LfuCache::new());
-
-            // PoI ignores offchain events.
-            // See also: poi-ignores-offchain
-            let proof_of_indexing = None;
-            let causality_region = "";
-
-            let trigger = TriggerData::Offchain(trigger);
-            let process_res = {
-                let hosts = self.ctx.instance.hosts_for_trigger(&trigger);
-                let triggers_res = self.ctx.decoder.match_and_decode(
-                    &self.logger,
-                    block,
-                    trigger,
-                    hosts,
-                    &self.metrics.subgraph,
-                );
-                match triggers_res {
-                    Ok(runnable) => {
-                        self.ctx
-                            .trigger_processor
-                            .process_trigger(
-                                &self.logger,
-                                runnable.hosted_triggers,
-                                block,
-                                block_state,
-                                &proof_of_indexing,
-                                causality_region,
-                                &self.inputs.debug_fork,
-                                &self.metrics.subgraph,
-                                self.inputs.instrument,
-                            )
-                            .await
+        let concurrency = self
+            .inputs
+            .priority
+            .file_data_source_concurrency(ENV_VARS.mappings.file_data_source_concurrency);
+        let queue_depth = self.metrics.subgraph.offchain_trigger_queue_depth.clone();
+        queue_depth.set(triggers.len() as f64);
+
+        let mut pending = VecDeque::from(triggers);
+        while !pending.is_empty() {
+            // Run the handlers for a window of triggers concurrently. Each
+            // trigger has its own block state, so their results can be
+            // applied in trigger order afterwards. Data sources created by
+            // a trigger only see triggers from the next window on.
+            let results = run_window(&mut pending, concurrency, |trigger| {
+                self.process_offchain_trigger(trigger, block)
+            })
+            .await;
+
+            for process_res in results {
+                queue_depth.dec();
+                let mut block_state = match process_res {
+                    Ok(state) => state,
+                    Err(err) => {
+                        let err = match err {
+                            // Ignoring `PossibleReorg` isn't so bad since the subgraph will retry
+                            // non-deterministic errors.
+                            MappingError::PossibleReorg(e) | MappingError::Unknown(e) => e,
+                        };
+                        return Err(err.context("failed to process trigger".to_string()));
                     }
-                    Err(e) => Err(e),
-                }
-            };
-            match process_res {
-                Ok(state) => block_state = state,
-                Err(err) => {
-                    let err = match err {
-                        // Ignoring `PossibleReorg` isn't so bad since the subgraph will retry
-                        // non-deterministic errors.
-                        MappingError::PossibleReorg(e) | MappingError::Unknown(e) => e,
-                    };
-                    return Err(err.context("failed to process trigger".to_string()));
-                }
-            }
+                };
 
-            anyhow::ensure!(
-                !block_state.has_created_on_chain_data_sources(),
-                "Attempted to create on-chain data source in offchain data source handler. This is not yet supported.",
-            );
+                anyhow::ensure!(
+                    !block_state.has_created_on_chain_data_sources(),
+                    "Attempted to create on-chain data source in offchain data source handler. This is not yet supported.",
+                );
 
-            let (data_sources, _) =
-                self.create_dynamic_data_sources(block_state.drain_created_data_sources())?;
+                let (data_sources, _) =
+                    self.create_dynamic_data_sources(block_state.drain_created_data_sources())?;
+
+                // Add entity operations for the new data sources to the block state
+                // and add runtimes for the data sources to the subgraph instance.
+                self.persist_dynamic_data_sources(&mut block_state, data_sources);
 
-            // Add entity operations for the new data sources to the block state
-            // and add runtimes for the data sources to the subgraph instance.
-            self.persist_dynamic_data_sources(&mut block_state, data_sources);
+                // This propagates any deterministic error as a non-deterministic one. Which might make
+                // sense considering offchain data sources are non-deterministic.
+                if let Some(err) = block_state.deterministic_errors.into_iter().next() {
+                    return Err(anyhow!("{}", err.to_string()));
+                }
 
-            // This propagates any deterministic error as a non-deterministic one. Which might make
-            // sense considering offchain data sources are non-deterministic.
-            if let Some(err) = block_state.deterministic_errors.into_iter().next() {
-                return Err(anyhow!("{}", err.to_string()));
+                mods.extend(
+                    block_state
+                        .entity_cache
+                        .as_modifications(block.number())?
+                        .modifications,
+                );
+                processed_data_sources.extend(block_state.processed_data_sources);
+                persisted_data_sources.extend(block_state.persisted_data_sources)
             }
-
-            mods.extend(
-                block_state
-                    .entity_cache
-                    .as_modifications(block.number())?
-                    .modifications,
-            );
-            processed_data_sources.extend(block_state.processed_data_sources);
-            persisted_data_sources.extend(block_state.persisted_data_sources)
         }
 
         Ok((mods, processed_data_sources, persisted_data_sources))
     }
+
+    /// Run the handlers for an offchain trigger against an empty block
+    /// state and return that state
+    async fn process_offchain_trigger(
+        &self,
+        trigger: offchain::TriggerData,
+        block: &Arc<C::Block>,
+    ) -> Result<BlockState, MappingError> {
+        // Using an `EmptyStore` and clearing the cache for each trigger is a makeshift way to
+        // get causality region isolation.
+        let schema = ReadStore::input_schema(&self.inputs.store);
+        let block_state = BlockState::new(EmptyStore::new(schema), LfuCache::new());
+
+        // PoI ignores offchain events.
+        // See also: poi-ignores-offchain
+        let proof_of_indexing = None;
+        let causality_region = "";
+
+        let trigger = TriggerData::Offchain(trigger);
+        let hosts = self.ctx.instance.hosts_for_trigger(&trigger);
+        let runnable = self.ctx.decoder.match_and_decode(
+            &self.logger,
+            block,
+            trigger,
+            hosts,
+            &self.metrics.subgraph,
+        )?;
+        self.ctx
+            .trigger_processor
+            .process_trigger(
+                &self.logger,
+                runnable.hosted_triggers,
+                block,
+                block_state,
+                &proof_of_indexing,
+                causality_region,
+                &self.inputs.debug_fork,
+                &self.metrics.subgraph,
+                self.inputs.instrument,
+            )
+            .await
+    }
 }
 
 #[derive(Debug)]
@@ -1207,6 +1797,8 @@ where
 
         debug!(logger, "Start processing wasm block";);
 
+        self.quota.throttle().await;
+
         let proof_of_indexing = if self.inputs.store.supports_proof_of_indexing().await? {
             Some(Arc::new(AtomicRefCell::new(ProofOfIndexing::new(
                 block_ptr.number,
@@ -1219,6 +1811,7 @@ where
         // Causality region for onchain triggers.
         let causality_region = PoICausalityRegion::from_network(&self.inputs.network);
 
+        let mapping_start = Instant::now();
         let mut block_state = {
             match self
                 .process_wasm_block(
@@ -1255,6 +1848,7 @@ where
                 }
             }
         };
+        let mapping_time = mapping_start.elapsed();
 
         let has_errors = block_state.has_errors();
         let is_non_fatal_errors_active = self
@@ -1348,7 +1942,9 @@ where
 
         // We consider a subgraph caught up when it's at most 1 blocks behind the chain head.
         let is_caught_up = self.is_caught_up(&block_ptr).await?;
+        self.maintain_deferred_indexes(&block_ptr).await?;
 
+        let writes = mods.len();
         self.inputs
             .store
             .transact_block_operations(
@@ -1365,6 +1961,7 @@ where
             )
             .await
             .context("Failed to transact block operations")?;
+        self.quota.record(mapping_time, writes);
 
         // For subgraphs with `nonFatalErrors` feature disabled, we consider
         // any error as fatal.
@@ -1435,11 +2032,16 @@ where
             self.state.skip_ptr_updates_timer = Instant::now();
         }
 
+        // Give other deployments on this node a turn if this one has used
+        // more than its quota
+        self.quota.throttle().await;
+
         let start = Instant::now();
+        let parent_ptr = block.block.parent_ptr();
 
         let res = self.process_block(cancel_handle, block, cursor).await;
 
-        self.handle_action(start, block_ptr, res).await
+        self.handle_action(start, block_ptr, parent_ptr, res).await
     }
 
     async fn handle_revert(
@@ -1536,7 +2138,15 @@ where
             "error" => format!("{}", err),
         );
 
-        Ok(Action::Continue)
+        let err = match err {
+            CancelableError::Error(err) => err,
+            CancelableError::Cancel => return Ok(Action::Continue),
+        };
+        match self.stream_supervisor.on_error(&err) {
+            StreamErrorAction::Continue => Ok(Action::Continue),
+            StreamErrorAction::Restart => Ok(Action::Restart),
+            StreamErrorAction::Fail => Ok(Action::Stop),
+        }
     }
 
     /// Determines if the subgraph needs to be restarted.
@@ -1551,6 +2161,52 @@ where
     }
 }
 
+/// Whether the result of running a trigger in a fork can be merged after
+/// the triggers that changed the entities in `changed` were merged
+fn can_merge(fork: &BlockState, changed: &HashSet<EntityKey>) -> bool {
+    !fork.has_errors()
+        && !fork.has_created_data_sources()
+        && fork.persisted_data_sources.is_empty()
+        && fork.processed_data_sources.is_empty()
+        && !fork.entity_cache.footprint().conflicts_with(changed)
+}
+
+/// Decode `trigger` again for `hosts`, which all matched it before; the
+/// triggers that `process_trigger` consumed can not be run a second time
+fn redecode<'a, C: Blockchain>(
+    logger: &Logger,
+    block: &Arc<C::Block>,
+    trigger: TriggerData<C>,
+    hosts: Vec<&'a dyn RuntimeHost<C>>,
+) -> Result<RunnableTriggers<'a, C>, MappingError> {
+    let mut hosted_triggers = Vec::with_capacity(hosts.len());
+    for host in hosts {
+        let mapping_trigger = host
+            .match_and_decode(&trigger, block, logger)
+            .map_err(|e| MappingError::from(e).add_trigger_context(&trigger))?;
+        if let Some(mapping_trigger) = mapping_trigger {
+            hosted_triggers.push(HostedTrigger {
+                host,
+                mapping_trigger,
+            });
+        }
+    }
+    Ok(RunnableTriggers {
+        trigger,
+        hosted_triggers,
+    })
+}
+
+/// Run `run` concurrently for up to `concurrency` of the items at the
+/// front of `pending` and return the results in the order of the items
+async fn run_window<T, R, F, Fut>(pending: &mut VecDeque<T>, concurrency: usize, run: F) -> Vec<R>
+where
+    F: FnMut(T) -> Fut,
+    Fut: std::future::Future<Output = R>,
+{
+    join_all(pending.drain(..concurrency.min(pending.len())).map(run)).await
+}
+
 /// Transform the proof of indexing changes into entity updates that will be
 /// inserted when as_modifications is called.
 async fn update_proof_of_indexing(
@@ -1674,3 +2330,42 @@ fn test_close_to_chain_head() {
         offset
     ));
 }
+
+#[tokio::test]
+async fn run_window_keeps_the_order_of_items() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Run all `items` in windows of `concurrency` and return the results
+    /// and the largest number of items that were running at once. Later
+    /// items in a window finish before earlier ones
+    async fn run_all(items: Vec<u64>, concurrency: usize) -> (Vec<u64>, usize) {
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let mut pending = VecDeque::from(items);
+        let mut results = Vec::new();
+        while !pending.is_empty() {
+            let (running, most) = (&running, &most);
+            let window = run_window(&mut pending, concurrency, |item| async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20 - item)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                item * 2
+            })
+            .await;
+            results.extend(window);
+        }
+        (results, most.into_inner())
+    }
+
+    let items: Vec<u64> = (0..10).collect();
+    let expected: Vec<u64> = items.iter().map(|item| item * 2).collect();
+
+    let (sequential, most) = run_all(items.clone(), 1).await;
+    assert_eq!(expected, sequential);
+    assert_eq!(1, most);
+
+    let (concurrent, most) = run_all(items, 4).await;
+    assert_eq!(sequential, concurrent);
+    assert_eq!(4, most);
+}
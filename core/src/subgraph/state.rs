@@ -16,4 +16,9 @@ pub struct IndexingState {
     pub skip_ptr_updates_timer: Instant,
     pub entity_lfu_cache: EntityLfuCache,
     pub cached_head_ptr: Option<BlockPtr>,
+    /// Whether indexes were dropped because the deployment was far behind
+    /// the chain head; `None` until that is decided for the first block
+    pub indexes_deferred: Option<bool>,
+    /// When the keys of the entity cache were last saved
+    pub entity_cache_checkpointed: Instant,
 }
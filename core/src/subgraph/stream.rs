@@ -1,9 +1,234 @@
 use crate::subgraph::inputs::IndexingInputs;
 use anyhow::bail;
-use graph::blockchain::block_stream::{BlockStream, BufferedBlockStream};
+use graph::blockchain::block_stream::{
+    BlockStream, BlockStreamError, BufferSize, BufferedBlockStream,
+};
 use graph::blockchain::Blockchain;
-use graph::prelude::{CheapClone, Error, SubgraphInstanceMetrics};
+use graph::prelude::{
+    info, warn, CancelHandle, CheapClone, Error, Logger, SubgraphInstanceMetrics, ENV_VARS,
+};
+use graph::util::backoff::ExponentialBackoff;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// How many transient errors in a row a block stream may produce before we
+/// give up on it and create a new one
+const TRANSIENT_ERRORS_BEFORE_RESTART: u32 = 10;
+
+/// Fragments of error messages with which providers reject a cursor; they
+/// only count if the message also mentions the cursor
+const INVALID_CURSOR_ERRORS: &[&str] = &[
+    "invalid",
+    "not found",
+    "unknown",
+    "expired",
+    "malformed",
+    "unable to decode",
+    "cannot decode",
+];
+
+/// Fragments of error messages with which providers reject a request
+/// because the filter or the response is too big
+const FILTER_TOO_BIG_ERRORS: &[&str] = &[
+    "query returned more than",
+    "response size exceeded",
+    "response is too big",
+    "message length too large",
+    "too many addresses",
+    "too many topics",
+    "filter too large",
+    "exceeds the limit",
+];
+
+/// The kinds of errors that block streams run into. Each kind is retried
+/// according to its own policy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamErrorKind {
+    /// The provider had a problem that usually goes away on its own, like
+    /// a dropped connection or a timeout
+    Transient,
+    /// The provider does not accept the cursor the stream resumed from
+    InvalidCursor,
+    /// The provider refused the request because the filter or the
+    /// response is too big
+    FilterTooBig,
+    /// Retrying will not make the error go away
+    Fatal,
+}
+
+impl StreamErrorKind {
+    pub fn classify(err: &BlockStreamError) -> Self {
+        match err {
+            BlockStreamError::Fatal(_) => StreamErrorKind::Fatal,
+            BlockStreamError::Unknown(e) => Self::from_message(&format!("{:#}", e)),
+            e => Self::from_message(&e.to_string()),
+        }
+    }
+
+    fn from_message(msg: &str) -> Self {
+        let msg = msg.to_lowercase();
+        if msg.contains("cursor") && INVALID_CURSOR_ERRORS.iter().any(|s| msg.contains(s)) {
+            StreamErrorKind::InvalidCursor
+        } else if FILTER_TOO_BIG_ERRORS.iter().any(|s| msg.contains(s)) {
+            StreamErrorKind::FilterTooBig
+        } else {
+            StreamErrorKind::Transient
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamErrorKind::Transient => "transient",
+            StreamErrorKind::InvalidCursor => "invalid_cursor",
+            StreamErrorKind::FilterTooBig => "filter_too_big",
+            StreamErrorKind::Fatal => "fatal",
+        }
+    }
+}
+
+/// What the runner should do about an error from the block stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamErrorAction {
+    /// Keep reading from the stream; it recovers from the error by itself
+    Continue,
+    /// Drop the stream and create a new one
+    Restart,
+    /// Stop indexing the deployment
+    Fail,
+}
+
+/// Creates the block streams of a deployment and decides what to do about
+/// the errors they produce. Every kind of error has its own backoff so that
+/// a provider that keeps rejecting one request does not slow down the
+/// recovery from unrelated errors. Restarts are counted in the
+/// `deployment_block_stream_restarts` metric
+pub struct BlockStreamSupervisor {
+    logger: Logger,
+    metrics: Arc<SubgraphInstanceMetrics>,
+    transient: ExponentialBackoff,
+    invalid_cursor: ExponentialBackoff,
+    filter_too_big: ExponentialBackoff,
+    /// The number of transient errors since the stream last produced an
+    /// event
+    transient_errors: u32,
+    /// Whether we already started a stream
+    started: bool,
+    /// The error that made us restart the stream, if any
+    restart_reason: Option<StreamErrorKind>,
+}
+
+impl BlockStreamSupervisor {
+    pub fn new(logger: Logger, metrics: Arc<SubgraphInstanceMetrics>) -> Self {
+        Self {
+            logger,
+            metrics,
+            transient: ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+            invalid_cursor: ExponentialBackoff::new(
+                Duration::from_secs(5),
+                Duration::from_secs(300),
+            ),
+            filter_too_big: ExponentialBackoff::new(
+                Duration::from_secs(10),
+                Duration::from_secs(600),
+            ),
+            transient_errors: 0,
+            started: false,
+            restart_reason: None,
+        }
+    }
+
+    fn backoff(&mut self, kind: StreamErrorKind) -> &mut ExponentialBackoff {
+        match kind {
+            StreamErrorKind::Transient | StreamErrorKind::Fatal => &mut self.transient,
+            StreamErrorKind::InvalidCursor => &mut self.invalid_cursor,
+            StreamErrorKind::FilterTooBig => &mut self.filter_too_big,
+        }
+    }
+
+    fn count_restart(&self, reason: &str) {
+        self.metrics
+            .block_stream_restarts
+            .with_label_values(&[reason])
+            .inc();
+    }
+
+    /// Create a new block stream. If the previous stream was dropped
+    /// because of an error, wait according to the backoff for that error
+    /// first. Failures to create the stream are retried until
+    /// `cancel_handle` is canceled, in which case `None` is returned
+    pub async fn start<C: Blockchain>(
+        &mut self,
+        inputs: &IndexingInputs<C>,
+        filter: &C::TriggerFilter,
+        cancel_handle: &CancelHandle,
+    ) -> Result<Option<Box<dyn BlockStream<C>>>, Error> {
+        match self.restart_reason.take() {
+            Some(kind) => {
+                self.count_restart(kind.as_str());
+                self.backoff(kind).sleep_async().await;
+            }
+            None if self.started => self.count_restart("requested"),
+            None => {}
+        }
+        self.started = true;
+
+        loop {
+            if cancel_handle.is_canceled() {
+                return Ok(None);
+            }
+
+            match new_block_stream(inputs, filter, &self.metrics).await {
+                Ok(stream) => {
+                    self.transient_errors = 0;
+                    return Ok(Some(stream));
+                }
+                Err(e) => {
+                    let kind = StreamErrorKind::from_message(&format!("{:#}", e));
+                    warn!(self.logger, "Failed to create block stream, retrying";
+                          "error" => format!("{:#}", e),
+                          "kind" => kind.as_str(),
+                          "attempt" => self.backoff(kind).attempt);
+                    self.count_restart(kind.as_str());
+                    self.backoff(kind).sleep_async().await;
+                }
+            }
+        }
+    }
+
+    /// Decide what to do about an error from the current block stream
+    pub fn on_error(&mut self, err: &BlockStreamError) -> StreamErrorAction {
+        let kind = StreamErrorKind::classify(err);
+        match kind {
+            StreamErrorKind::Fatal => StreamErrorAction::Fail,
+            StreamErrorKind::Transient => {
+                self.transient_errors += 1;
+                if self.transient_errors < TRANSIENT_ERRORS_BEFORE_RESTART {
+                    return StreamErrorAction::Continue;
+                }
+                info!(self.logger, "Restarting block stream after repeated errors";
+                      "errors" => self.transient_errors);
+                self.restart_reason = Some(kind);
+                StreamErrorAction::Restart
+            }
+            StreamErrorKind::InvalidCursor | StreamErrorKind::FilterTooBig => {
+                info!(self.logger, "Restarting block stream";
+                      "error" => err.to_string(),
+                      "kind" => kind.as_str());
+                self.restart_reason = Some(kind);
+                StreamErrorAction::Restart
+            }
+        }
+    }
+
+    /// Record that the current block stream produced an event, which means
+    /// that it has recovered from any earlier errors
+    pub fn on_event(&mut self) {
+        self.transient_errors = 0;
+        self.transient.reset();
+        self.invalid_cursor.reset();
+        self.filter_too_big.reset();
+    }
+}
 
 pub async fn new_block_stream<C: Blockchain>(
     inputs: &IndexingInputs<C>,
@@ -23,10 +248,20 @@ pub async fn new_block_stream<C: Blockchain>(
         )
         .await
     {
-        Ok(block_stream) => Ok(BufferedBlockStream::spawn_from_stream(
-            block_stream.buffer_size_hint(),
-            block_stream,
-        )),
+        Ok(block_stream) => {
+            let size = match ENV_VARS.block_stream_buffer_size(&inputs.deployment.hash) {
+                Some(size) => BufferSize::Fixed(size),
+                None => BufferSize::Adaptive {
+                    max: inputs.priority.buffer_size(block_stream.buffer_size_hint()),
+                    memory_budget: ENV_VARS.block_stream_buffer_memory_budget,
+                },
+            };
+            Ok(BufferedBlockStream::spawn_with_buffer(
+                size,
+                Some(metrics.block_stream_buffer_occupancy.clone()),
+                block_stream,
+            ))
+        }
         Err(e) => {
             if is_firehose {
                 metrics.firehose_connection_errors.inc();
@@ -35,3 +270,34 @@ pub async fn new_block_stream<C: Blockchain>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::prelude::anyhow::anyhow;
+
+    #[test]
+    fn classify_stream_errors() {
+        fn classify(msg: &str) -> StreamErrorKind {
+            StreamErrorKind::classify(&BlockStreamError::Unknown(anyhow!("{}", msg)))
+        }
+
+        assert_eq!(
+            StreamErrorKind::Fatal,
+            StreamErrorKind::classify(&BlockStreamError::Fatal("boom".to_string()))
+        );
+        assert_eq!(
+            StreamErrorKind::InvalidCursor,
+            classify("status: InvalidArgument, message: \"invalid start cursor\"")
+        );
+        assert_eq!(
+            StreamErrorKind::FilterTooBig,
+            classify("query returned more than 10000 results")
+        );
+        assert_eq!(
+            StreamErrorKind::Transient,
+            classify("connection reset by peer")
+        );
+        assert_eq!(StreamErrorKind::Transient, classify("too many requests"));
+    }
+}
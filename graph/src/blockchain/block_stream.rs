@@ -6,13 +6,15 @@ use async_stream::stream;
 use futures03::Stream;
 use prost_types::Any;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use thiserror::Error;
 use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::Notify;
 
 use super::substreams_block_stream::SubstreamsLogData;
-use super::{Block, BlockPtr, BlockTime, Blockchain};
+use super::{Block, BlockPtr, BlockTime, Blockchain, TriggerFilter};
 use crate::anyhow::Result;
 use crate::components::store::{BlockNumber, DeploymentLocator};
 use crate::data::subgraph::UnifiedMappingApiVersion;
@@ -26,23 +28,114 @@ pub const BUFFERED_BLOCK_STREAM_SIZE: usize = 100;
 pub const FIREHOSE_BUFFER_STREAM_SIZE: usize = 1;
 pub const SUBSTREAMS_BUFFER_STREAM_SIZE: usize = 100;
 
+/// How many events a `BufferedBlockStream` holds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferSize {
+    /// Always buffer up to this many events
+    Fixed(usize),
+    /// Buffer up to `max` events, but fewer if the average size of the
+    /// blocks seen so far means that `max` of them would take up more than
+    /// `memory_budget` bytes. At least one event is always buffered
+    Adaptive { max: usize, memory_budget: usize },
+}
+
+impl BufferSize {
+    fn max(&self) -> usize {
+        match self {
+            BufferSize::Fixed(size) => *size,
+            BufferSize::Adaptive { max, .. } => *max,
+        }
+        .max(1)
+    }
+
+    /// The number of events to buffer when blocks are `avg_block_size`
+    /// bytes large on average
+    pub fn limit(&self, avg_block_size: Option<f64>) -> usize {
+        match (self, avg_block_size) {
+            (BufferSize::Adaptive { memory_budget, .. }, Some(avg)) if avg >= 1.0 => {
+                ((*memory_budget as f64 / avg) as usize).clamp(1, self.max())
+            }
+            _ => self.max(),
+        }
+    }
+}
+
+/// Keeps track of how many events are in the buffer of a
+/// `BufferedBlockStream` so that the task filling it can wait for the
+/// consumer when the buffer is full
+struct BufferState {
+    occupancy: AtomicUsize,
+    /// Notified whenever the consumer takes an event out of the buffer
+    taken: Notify,
+    gauge: Option<Gauge>,
+}
+
+impl BufferState {
+    fn added(&self) {
+        let occupancy = self.occupancy.fetch_add(1, Ordering::SeqCst) + 1;
+        self.set_gauge(occupancy);
+    }
+
+    fn taken(&self) {
+        let occupancy = self.occupancy.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.set_gauge(occupancy);
+        self.taken.notify_one();
+    }
+
+    fn set_gauge(&self, occupancy: usize) {
+        if let Some(gauge) = &self.gauge {
+            gauge.set(occupancy as f64);
+        }
+    }
+}
+
 pub struct BufferedBlockStream<C: Blockchain> {
     inner: Pin<Box<dyn Stream<Item = Result<BlockStreamEvent<C>, BlockStreamError>> + Send>>,
+    filter_extender: Option<Arc<dyn FilterExtender<C>>>,
 }
 
 impl<C: Blockchain + 'static> BufferedBlockStream<C> {
     pub fn spawn_from_stream(
         size_hint: usize,
         stream: Box<dyn BlockStream<C>>,
+    ) -> Box<dyn BlockStream<C>> {
+        Self::spawn_with_buffer(BufferSize::Fixed(size_hint), None, stream)
+    }
+
+    /// Buffer the events from `stream` in a buffer of the given `size`.
+    /// If `gauge` is given, it is kept up to date with the number of events
+    /// in the buffer
+    pub fn spawn_with_buffer(
+        size: BufferSize,
+        gauge: Option<Gauge>,
+        stream: Box<dyn BlockStream<C>>,
     ) -> Box<dyn BlockStream<C>> {
         let (sender, receiver) =
-            mpsc::channel::<Result<BlockStreamEvent<C>, BlockStreamError>>(size_hint);
-        crate::spawn(async move { BufferedBlockStream::stream_blocks(stream, sender).await });
+            mpsc::channel::<Result<BlockStreamEvent<C>, BlockStreamError>>(size.max());
+        let state = Arc::new(BufferState {
+            occupancy: AtomicUsize::new(0),
+            taken: Notify::new(),
+            gauge,
+        });
+        let producer_state = state.cheap_clone();
+        let filter_extender = stream.filter_extender();
+        crate::spawn(async move {
+            BufferedBlockStream::fill_buffer(stream, sender, size, producer_state).await
+        });
+
+        let mut buffered = BufferedBlockStream::with_state(receiver, Some(state));
+        buffered.filter_extender = filter_extender;
+        Box::new(buffered)
+    }
 
-        Box::new(BufferedBlockStream::new(receiver))
+    pub fn new(receiver: Receiver<Result<BlockStreamEvent<C>, BlockStreamError>>) -> Self {
+        Self::with_state(receiver, None)
     }
 
-    pub fn new(mut receiver: Receiver<Result<BlockStreamEvent<C>, BlockStreamError>>) -> Self {
+    fn with_state(
+        mut receiver: Receiver<Result<BlockStreamEvent<C>, BlockStreamError>>,
+        state: Option<Arc<BufferState>>,
+    ) -> Self {
         let inner = stream! {
             loop {
                 let event = match receiver.recv().await {
@@ -50,28 +143,49 @@ impl<C: Blockchain + 'static> BufferedBlockStream<C> {
                     None => return,
                 };
 
+                if let Some(state) = &state {
+                    state.taken();
+                }
+
                 yield event
             }
         };
 
         Self {
             inner: Box::pin(inner),
+            filter_extender: None,
         }
     }
 
-    pub async fn stream_blocks(
+    async fn fill_buffer(
         mut stream: Box<dyn BlockStream<C>>,
         sender: Sender<Result<BlockStreamEvent<C>, BlockStreamError>>,
+        size: BufferSize,
+        state: Arc<BufferState>,
     ) -> Result<(), Error> {
+        // An exponential moving average of the size of the blocks we've
+        // seen so far
+        let mut avg_block_size: Option<f64> = None;
+
         while let Some(event) = stream.next().await {
-            match sender.send(event).await {
-                Ok(_) => continue,
-                Err(err) => {
-                    return Err(anyhow!(
-                        "buffered blockstream channel is closed, stopping. Err: {}",
-                        err
-                    ))
-                }
+            if let Some(block_size) = event.as_ref().ok().and_then(BlockStreamEvent::size_hint) {
+                let block_size = block_size as f64;
+                avg_block_size =
+                    Some(avg_block_size.map_or(block_size, |avg| 0.9 * avg + 0.1 * block_size));
+            }
+
+            // Wait for the consumer if the buffer is full
+            let limit = size.limit(avg_block_size);
+            while state.occupancy.load(Ordering::SeqCst) >= limit {
+                state.taken.notified().await;
+            }
+
+            state.added();
+            if let Err(err) = sender.send(event).await {
+                return Err(anyhow!(
+                    "buffered blockstream channel is closed, stopping. Err: {}",
+                    err
+                ));
             }
         }
 
@@ -83,6 +197,10 @@ impl<C: Blockchain> BlockStream<C> for BufferedBlockStream<C> {
     fn buffer_size_hint(&self) -> usize {
         unreachable!()
     }
+
+    fn filter_extender(&self) -> Option<Arc<dyn FilterExtender<C>>> {
+        self.filter_extender.cheap_clone()
+    }
 }
 
 impl<C: Blockchain> Stream for BufferedBlockStream<C> {
@@ -100,6 +218,70 @@ pub trait BlockStream<C: Blockchain>:
     Stream<Item = Result<BlockStreamEvent<C>, BlockStreamError>> + Unpin + Send
 {
     fn buffer_size_hint(&self) -> usize;
+
+    /// A handle to extend the filter of the stream while it runs, or
+    /// `None` if the stream has to be restarted to change its filter
+    fn filter_extender(&self) -> Option<Arc<dyn FilterExtender<C>>> {
+        None
+    }
+}
+
+/// Extends the filter of a running block stream
+pub trait FilterExtender<C: Blockchain>: Send + Sync {
+    /// Add the triggers of `data_sources` to the filter of the stream.
+    /// Returns the first block that the stream will scan with the extended
+    /// filter; blocks before that might already have been scanned with the
+    /// old filter and are missing the triggers for `data_sources`
+    fn extend(&self, data_sources: &[C::DataSource]) -> BlockNumber;
+}
+
+/// A trigger filter that can be extended while the block stream that uses
+/// it runs. The stream gets the filter for each scan from here so that we
+/// know which blocks it scanned before the filter was extended
+pub struct ExtensibleFilter<C: Blockchain> {
+    inner: Mutex<ExtensibleFilterInner<C>>,
+}
+
+struct ExtensibleFilterInner<C: Blockchain> {
+    filter: Arc<C::TriggerFilter>,
+    /// The highest block that a scan used the filter for
+    scanned_to: Option<BlockNumber>,
+    extended: bool,
+}
+
+impl<C: Blockchain> ExtensibleFilter<C> {
+    pub fn new(filter: Arc<C::TriggerFilter>) -> Self {
+        Self {
+            inner: Mutex::new(ExtensibleFilterInner {
+                filter,
+                scanned_to: None,
+                extended: false,
+            }),
+        }
+    }
+
+    /// The filter to use for a scan of blocks up to `to`
+    pub fn for_scan(&self, to: BlockNumber) -> Arc<C::TriggerFilter> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.scanned_to = Some(inner.scanned_to.map_or(to, |scanned_to| scanned_to.max(to)));
+        inner.filter.cheap_clone()
+    }
+
+    /// Whether the filter was extended since it was created
+    pub fn is_extended(&self) -> bool {
+        self.inner.lock().unwrap().extended
+    }
+}
+
+impl<C: Blockchain> FilterExtender<C> for ExtensibleFilter<C> {
+    fn extend(&self, data_sources: &[C::DataSource]) -> BlockNumber {
+        let mut inner = self.inner.lock().unwrap();
+        let mut filter = inner.filter.as_ref().clone();
+        filter.extend(data_sources.iter());
+        inner.filter = Arc::new(filter);
+        inner.extended = true;
+        inner.scanned_to.map_or(0, |scanned_to| scanned_to + 1)
+    }
 }
 
 /// BlockRefetcher abstraction allows a chain to decide if a block must be refetched after a dynamic data source was added
@@ -541,6 +723,18 @@ pub enum BlockStreamEvent<C: Blockchain> {
     ProcessWasmBlock(BlockPtr, BlockTime, Box<[u8]>, String, FirehoseCursor),
 }
 
+impl<C: Blockchain> BlockStreamEvent<C> {
+    /// An estimate of how much memory the block in this event takes up, if
+    /// it is known
+    pub fn size_hint(&self) -> Option<usize> {
+        match self {
+            BlockStreamEvent::Revert(_, _) => None,
+            BlockStreamEvent::ProcessBlock(block, _) => block.block.size_hint(),
+            BlockStreamEvent::ProcessWasmBlock(_, _, data, _, _) => Some(data.len()),
+        }
+    }
+}
+
 impl<C: Blockchain> Clone for BlockStreamEvent<C>
 where
     C::TriggerData: Clone,
@@ -628,14 +822,16 @@ mod test {
 
     use futures03::{Stream, StreamExt, TryStreamExt};
 
+    use std::sync::Arc;
+
     use crate::{
-        blockchain::mock::{MockBlock, MockBlockchain},
+        blockchain::mock::{MockBlock, MockBlockchain, MockTriggerFilter},
         ext::futures::{CancelableError, SharedCancelGuard, StreamExtension},
     };
 
     use super::{
-        BlockStream, BlockStreamError, BlockStreamEvent, BlockWithTriggers, BufferedBlockStream,
-        FirehoseCursor,
+        BlockStream, BlockStreamError, BlockStreamEvent, BlockWithTriggers, BufferSize,
+        BufferedBlockStream, ExtensibleFilter, FilterExtender, FirehoseCursor,
     };
 
     #[derive(Debug)]
@@ -712,4 +908,37 @@ mod test {
         );
         assert_eq!(count, blocks.len(), "should not have duplicated blocks");
     }
+
+    #[test]
+    fn buffer_size_limit() {
+        let fixed = BufferSize::Fixed(100);
+        assert_eq!(100, fixed.limit(None));
+        assert_eq!(100, fixed.limit(Some(1_000_000.0)));
+
+        let adaptive = BufferSize::Adaptive {
+            max: 100,
+            memory_budget: 1_000_000,
+        };
+        // Without knowing how large blocks are, we buffer as many as allowed
+        assert_eq!(100, adaptive.limit(None));
+        assert_eq!(100, adaptive.limit(Some(1_000.0)));
+        assert_eq!(10, adaptive.limit(Some(100_000.0)));
+        // We always buffer at least one block
+        assert_eq!(1, adaptive.limit(Some(10_000_000.0)));
+    }
+
+    #[test]
+    fn extensible_filter() {
+        let filter = ExtensibleFilter::<MockBlockchain>::new(Arc::new(MockTriggerFilter));
+        assert!(!filter.is_extended());
+
+        // Nothing was scanned yet, all blocks will use the new filter
+        assert_eq!(0, filter.extend(&[]));
+        assert!(filter.is_extended());
+
+        // Only blocks after the highest scanned block use the new filter
+        filter.for_scan(10);
+        filter.for_scan(5);
+        assert_eq!(11, filter.extend(&[]));
+    }
 }
@@ -21,6 +21,7 @@ struct FirehoseBlockStreamMetrics {
     connect_duration: GaugeVec,
     time_between_responses: HistogramVec,
     responses: CounterVec,
+    received_bytes: CounterVec,
 }
 
 impl FirehoseBlockStreamMetrics {
@@ -59,6 +60,14 @@ impl FirehoseBlockStreamMetrics {
                     vec!["deployment", "provider", "kind"].as_slice(),
                 )
                 .unwrap(),
+
+            received_bytes: registry
+                .global_counter_vec(
+                    "deployment_firehose_blockstream_received_bytes",
+                    "Counts the number of block bytes received from a Firehose block stream",
+                    vec!["deployment", "provider"].as_slice(),
+                )
+                .unwrap(),
         }
     }
 
@@ -97,6 +106,12 @@ impl FirehoseBlockStreamMetrics {
         // Reset last response timestamp
         *time = Instant::now();
     }
+
+    fn observe_received_bytes(&self, bytes: usize, provider: &str) {
+        self.received_bytes
+            .with_label_values(&[&self.deployment, &provider])
+            .inc_by(bytes as f64);
+    }
 }
 
 pub struct FirehoseBlockStream<C: Blockchain> {
@@ -246,7 +261,7 @@ fn stream_blocks<C: Blockchain, F: FirehoseMapper<C>>(
             let result = tokio::time::timeout(Duration::from_secs(120), req).await.map_err(|x| x.into()).and_then(|x| x);
 
             match result {
-                Ok(stream) => {
+                Ok(mut stream) => {
                     info!(&logger, "Blockstream connected");
 
                     // Track the time it takes to set up the block stream
@@ -255,7 +270,36 @@ fn stream_blocks<C: Blockchain, F: FirehoseMapper<C>>(
                     let mut last_response_time = Instant::now();
                     let mut expected_stream_end = false;
 
-                    for await response in stream {
+                    loop {
+                        // If the provider stops sending responses without closing the stream,
+                        // we give up on it and reconnect, resuming from the latest cursor. The
+                        // stalled provider is reported as failing so that we prefer another one
+                        let response = match tokio::time::timeout(ENV_VARS.firehose_stream_timeout, stream.next()).await {
+                            Ok(Some(response)) => response,
+                            Ok(None) => break,
+                            Err(_) => {
+                                warn!(
+                                    &logger,
+                                    "Blockstream did not receive a response in {}s, reconnecting",
+                                    ENV_VARS.firehose_stream_timeout.as_secs()
+                                );
+
+                                metrics.observe_response("timeout", &mut last_response_time, &endpoint.provider);
+                                endpoint.report_stalled_stream();
+
+                                skip_backoff = true;
+                                expected_stream_end = true;
+                                break;
+                            }
+                        };
+
+                        if let Ok(response) = &response {
+                            metrics.observe_received_bytes(
+                                response.block.as_ref().map_or(0, |block| block.value.len()),
+                                &endpoint.provider,
+                            );
+                        }
+
                         match process_firehose_response(
                             &endpoint,
                             response,
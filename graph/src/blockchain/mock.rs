@@ -280,9 +280,7 @@ impl MappingTriggerTrait for MockMappingTrigger {
 pub struct MockTriggerFilter;
 
 impl<C: Blockchain> TriggerFilter<C> for MockTriggerFilter {
-    fn extend<'a>(&mut self, _data_sources: impl Iterator<Item = &'a C::DataSource> + Clone) {
-        todo!()
-    }
+    fn extend<'a>(&mut self, _data_sources: impl Iterator<Item = &'a C::DataSource> + Clone) {}
 
     fn node_capabilities(&self) -> C::NodeCapabilities {
         todo!()
@@ -98,6 +98,13 @@ pub trait Block: Send + Sync {
     }
 
     fn timestamp(&self) -> BlockTime;
+
+    /// An estimate of how much memory this block takes up, used to decide
+    /// how many blocks block streams buffer. Chains that can not estimate
+    /// the size of their blocks return `None`
+    fn size_hint(&self) -> Option<usize> {
+        None
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -263,6 +270,14 @@ pub trait TriggerFilter<C: Blockchain>: Default + Clone + Send + Sync {
     fn node_capabilities(&self) -> C::NodeCapabilities;
 
     fn to_firehose_filter(self) -> Vec<prost_types::Any>;
+
+    /// A fingerprint of the triggers this filter matches. Polling block
+    /// streams use it to recognize block ranges that they scanned with the
+    /// same filter before a restart. Filters that return `None` always
+    /// rescan
+    fn fingerprint(&self) -> Option<String> {
+        None
+    }
 }
 
 pub trait DataSource<C: Blockchain>: 'static + Sized + Send + Sync + Clone {
@@ -464,6 +479,18 @@ pub enum BlockchainKind {
     Starknet,
 }
 
+impl BlockchainKind {
+    /// All kinds of chains this build supports
+    pub const ALL: [BlockchainKind; 6] = [
+        BlockchainKind::Arweave,
+        BlockchainKind::Ethereum,
+        BlockchainKind::Near,
+        BlockchainKind::Cosmos,
+        BlockchainKind::Substreams,
+        BlockchainKind::Starknet,
+    ];
+}
+
 impl fmt::Display for BlockchainKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let value = match self {
@@ -533,6 +560,13 @@ impl BlockchainMap {
         self.0.insert((C::KIND, network), chain);
     }
 
+    /// Whether a chain of kind `kind` is configured for `network`
+    pub fn has_network(&self, kind: BlockchainKind, network: &str) -> bool {
+        self.0
+            .keys()
+            .any(|(k, chain_id)| k == &kind && chain_id.as_str() == network)
+    }
+
     pub fn get_all_by_kind<C: Blockchain>(
         &self,
         kind: BlockchainKind,
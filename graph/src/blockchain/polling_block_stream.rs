@@ -1,5 +1,5 @@
 use anyhow::Error;
-use futures03::{stream::Stream, Future, FutureExt};
+use futures03::{stream::Stream, Future, FutureExt, StreamExt, TryStreamExt};
 use std::cmp;
 use std::collections::VecDeque;
 use std::pin::Pin;
@@ -9,9 +9,9 @@ use std::time::Duration;
 
 use super::block_stream::{
     BlockStream, BlockStreamError, BlockStreamEvent, BlockWithTriggers, ChainHeadUpdateStream,
-    FirehoseCursor, TriggersAdapter, BUFFERED_BLOCK_STREAM_SIZE,
+    ExtensibleFilter, FilterExtender, FirehoseCursor, TriggersAdapter, BUFFERED_BLOCK_STREAM_SIZE,
 };
-use super::{Block, BlockPtr, Blockchain};
+use super::{Block, BlockPtr, Blockchain, TriggerFilter};
 
 use crate::components::store::BlockNumber;
 use crate::data::subgraph::UnifiedMappingApiVersion;
@@ -20,6 +20,37 @@ use crate::prelude::*;
 // A high number here forces a slow start.
 const STARTING_PREVIOUS_TRIGGERS_PER_BLOCK: f64 = 1_000_000.0;
 
+/// The last range of final blocks that a polling block stream scanned for
+/// triggers. It is persisted so that a restarted stream whose subgraph
+/// pointer is inside the range does not need to scan the blocks without
+/// triggers in the range again
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamCheckpoint {
+    /// The fingerprint of the filter that was used for the scan
+    pub filter: String,
+    pub from: BlockNumber,
+    pub to: BlockNumber,
+    /// The blocks in the range that the scan returned; these are the
+    /// blocks with triggers and `to`
+    pub blocks: Vec<BlockNumber>,
+}
+
+impl StreamCheckpoint {
+    /// The first block at or after `from` that needs to be processed if
+    /// this checkpoint was made with a filter with `fingerprint` and
+    /// covers `from`. Returns `None` if the checkpoint can not be used
+    pub fn next_block(&self, fingerprint: &str, from: BlockNumber) -> Option<BlockNumber> {
+        if self.filter != fingerprint || from < self.from || from > self.to {
+            return None;
+        }
+        self.blocks
+            .iter()
+            .copied()
+            .filter(|number| *number >= from)
+            .min()
+    }
+}
+
 enum BlockStreamState<C>
 where
     C: Blockchain,
@@ -85,7 +116,7 @@ where
     // This is not really a block number, but the (unsigned) difference
     // between two block numbers
     reorg_threshold: BlockNumber,
-    filter: Arc<C::TriggerFilter>,
+    filter: Arc<ExtensibleFilter<C>>,
     start_blocks: Vec<BlockNumber>,
     logger: Logger,
     previous_triggers_per_block: f64,
@@ -94,8 +125,11 @@ where
     // Not a BlockNumber, but the difference between two block numbers
     max_block_range_size: BlockNumber,
     target_triggers_per_block_range: u64,
+    // How many block ranges we scan for triggers at the same time
+    scan_concurrency: usize,
     unified_api_version: UnifiedMappingApiVersion,
     current_block: Option<BlockPtr>,
+    filter_fingerprint: Option<String>,
 }
 
 impl<C: Blockchain> Clone for PollingBlockStreamContext<C> {
@@ -106,15 +140,17 @@ impl<C: Blockchain> Clone for PollingBlockStreamContext<C> {
             node_id: self.node_id.clone(),
             subgraph_id: self.subgraph_id.clone(),
             reorg_threshold: self.reorg_threshold,
-            filter: self.filter.clone(),
+            filter: self.filter.cheap_clone(),
             start_blocks: self.start_blocks.clone(),
             logger: self.logger.clone(),
             previous_triggers_per_block: self.previous_triggers_per_block,
             previous_block_range_size: self.previous_block_range_size,
             max_block_range_size: self.max_block_range_size,
             target_triggers_per_block_range: self.target_triggers_per_block_range,
+            scan_concurrency: self.scan_concurrency,
             unified_api_version: self.unified_api_version.clone(),
             current_block: self.current_block.clone(),
+            filter_fingerprint: self.filter_fingerprint.clone(),
         }
     }
 }
@@ -155,6 +191,7 @@ where
         logger: Logger,
         max_block_range_size: BlockNumber,
         target_triggers_per_block_range: u64,
+        scan_concurrency: usize,
         unified_api_version: UnifiedMappingApiVersion,
         start_block: Option<BlockPtr>,
     ) -> Self {
@@ -170,12 +207,14 @@ where
                 subgraph_id,
                 reorg_threshold,
                 logger,
-                filter,
+                filter_fingerprint: filter.fingerprint(),
+                filter: Arc::new(ExtensibleFilter::new(filter)),
                 start_blocks,
                 previous_triggers_per_block: STARTING_PREVIOUS_TRIGGERS_PER_BLOCK,
                 previous_block_range_size: 1,
                 max_block_range_size,
                 target_triggers_per_block_range,
+                scan_concurrency: scan_concurrency.max(1),
                 unified_api_version,
             },
         }
@@ -242,8 +281,19 @@ where
             "number" => subgraph_ptr.as_ref().map(|block| &block.number),
         );
 
+        // Blocks up to the finalized block can not be reverted anymore. If
+        // the chain tells us which block is finalized, there is no need to
+        // stay further behind the chain head than that.
+        let finalized_ptr = ctx.chain_store.cheap_clone().finalized_block_ptr().await?;
+        let reorg_threshold = match &finalized_ptr {
+            Some(finalized) if finalized.number <= head_ptr.number => {
+                ctx.reorg_threshold.min(head_ptr.number - finalized.number)
+            }
+            _ => ctx.reorg_threshold,
+        };
+
         // Make sure not to include genesis in the reorg threshold.
-        let reorg_threshold = ctx.reorg_threshold.min(head_ptr.number);
+        let reorg_threshold = reorg_threshold.min(head_ptr.number);
 
         // Only continue if the subgraph block ptr is behind the head block ptr.
         // subgraph_ptr > head_ptr shouldn't happen, but if it does, it's safest to just stop.
@@ -330,6 +380,15 @@ where
             // then we start with the genesis block
             let from = subgraph_ptr.map_or(0, |ptr| ptr.number + 1);
 
+            // If a scan before a restart already covered `from`, we know
+            // which blocks have triggers and can skip ahead to the next one
+            if let Some(step) = self
+                .checkpoint_step(from, head_ptr.number - reorg_threshold)
+                .await?
+            {
+                return Ok(step);
+            }
+
             // Get the next subsequent data source start block to ensure the block
             // range is aligned with data source. This is not necessary for
             // correctness, but it avoids an ineffecient situation such as the range
@@ -370,16 +429,22 @@ where
                     .max(1.0)
                     .min(range_size_upper_limit as f64) as BlockNumber
             };
-            let to = cmp::min(from + target_range_size - 1, to_limit);
+            // We scan up to `scan_concurrency` ranges of the target size at
+            // the same time
+            let to = cmp::min(
+                from + target_range_size * ctx.scan_concurrency as BlockNumber - 1,
+                to_limit,
+            );
 
             info!(
                 ctx.logger,
                 "Scanning blocks [{}, {}]", from, to;
-                "target_range_size" => target_range_size
+                "target_range_size" => target_range_size,
+                "scan_concurrency" => ctx.scan_concurrency
             );
 
             // Update with actually scanned range, to account for any skipped null blocks.
-            let (blocks, to) = self.adapter.scan_triggers(from, to, &self.filter).await?;
+            let (blocks, to) = self.scan_ranges(from, to, target_range_size).await?;
             let range_size = to - from + 1;
 
             // If the target block (`to`) is within the reorg threshold, indicating no non-null finalized blocks are
@@ -400,6 +465,31 @@ where
                 "range_size" => range_size
             );
 
+            // A checkpoint for the filter we started with does not say
+            // anything about the triggers of data sources added since then
+            let fingerprint = ctx
+                .filter_fingerprint
+                .as_ref()
+                .filter(|_| !ctx.filter.is_extended());
+            if let Some(fingerprint) = fingerprint {
+                let checkpoint = StreamCheckpoint {
+                    filter: fingerprint.clone(),
+                    from,
+                    to,
+                    blocks: blocks.iter().map(|block| block.block.number()).collect(),
+                };
+                // The checkpoint only saves work after a restart; failing
+                // to write it is not a reason to fail the stream
+                if let Err(e) = ctx
+                    .chain_store
+                    .cheap_clone()
+                    .set_stream_checkpoint(&ctx.subgraph_id, checkpoint)
+                    .await
+                {
+                    warn!(ctx.logger, "Failed to save stream checkpoint"; "error" => e.to_string());
+                }
+            }
+
             Ok(ReconciliationStep::ProcessDescendantBlocks(
                 blocks, range_size,
             ))
@@ -467,9 +557,10 @@ where
                         // due to the race conditions previously mentioned,
                         // so instead we will advance the subgraph ptr by one block.
                         // Note that head_ancestor is a child of subgraph_ptr.
+                        let filter = self.filter.for_scan(head_ancestor.number());
                         let block = self
                             .adapter
-                            .triggers_in_block(&self.logger, head_ancestor, &self.filter)
+                            .triggers_in_block(&self.logger, head_ancestor, &filter)
                             .await?;
                         Ok(ReconciliationStep::ProcessDescendantBlocks(vec![block], 1))
                     } else {
@@ -485,6 +576,73 @@ where
         }
     }
 
+    /// Scan the blocks `[from, to]` for triggers in ranges of at most
+    /// `range_size` blocks, with up to `scan_concurrency` scans running at
+    /// the same time. Returns the blocks from all ranges in order and the
+    /// last block that was scanned
+    async fn scan_ranges(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+        range_size: BlockNumber,
+    ) -> Result<(Vec<BlockWithTriggers<C>>, BlockNumber), Error> {
+        let filter = self.filter.for_scan(to);
+        let adapter = &self.adapter;
+        let filter = &filter;
+        scan_in_ranges(
+            from,
+            to,
+            range_size,
+            self.scan_concurrency,
+            move |start, end| adapter.scan_triggers(start, end, filter),
+        )
+        .await
+    }
+
+    /// Use the checkpoint of an earlier scan to find the next block with
+    /// triggers at or after `from` and get the triggers for just that
+    /// block. Returns `None` if there is no checkpoint for the current
+    /// filter that covers `from`, or if the next block is after `to_limit`
+    async fn checkpoint_step(
+        &self,
+        from: BlockNumber,
+        to_limit: BlockNumber,
+    ) -> Result<Option<ReconciliationStep<C>>, Error> {
+        let fingerprint = match &self.filter_fingerprint {
+            Some(fingerprint) if !self.filter.is_extended() => fingerprint,
+            _ => return Ok(None),
+        };
+        let checkpoint = match self
+            .chain_store
+            .cheap_clone()
+            .stream_checkpoint(&self.subgraph_id)
+            .await?
+        {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(None),
+        };
+        let next = match checkpoint.next_block(fingerprint, from) {
+            Some(next) if next <= to_limit => next,
+            _ => return Ok(None),
+        };
+
+        if next > from {
+            info!(
+                self.logger,
+                "Skipping blocks [{}, {}] without triggers", from, next - 1;
+                "checkpoint_from" => checkpoint.from,
+                "checkpoint_to" => checkpoint.to
+            );
+        }
+
+        let filter = self.filter.for_scan(next);
+        let (blocks, to) = self.adapter.scan_triggers(next, next, &filter).await?;
+        Ok(Some(ReconciliationStep::ProcessDescendantBlocks(
+            blocks,
+            to - from + 1,
+        )))
+    }
+
     async fn parent_ptr(&self, block_ptr: &BlockPtr, reason: &str) -> Result<BlockPtr, Error> {
         let ptr =
             self.adapter.parent_ptr(block_ptr).await?.ok_or_else(|| {
@@ -499,6 +657,10 @@ impl<C: Blockchain> BlockStream<C> for PollingBlockStream<C> {
     fn buffer_size_hint(&self) -> usize {
         BUFFERED_BLOCK_STREAM_SIZE
     }
+
+    fn filter_extender(&self) -> Option<Arc<dyn FilterExtender<C>>> {
+        Some(self.ctx.filter.cheap_clone())
+    }
 }
 
 impl<C: Blockchain> Stream for PollingBlockStream<C> {
@@ -645,3 +807,123 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
         result.map_err(BlockStreamError::from)
     }
 }
+
+/// Split `[from, to]` into ranges of at most `range_size` blocks and call
+/// `scan` for each of them, with up to `concurrency` calls running at the
+/// same time. Returns what `scan` found for all ranges in the order of the
+/// ranges and the last block that the last range actually scanned
+async fn scan_in_ranges<B, F, Fut>(
+    from: BlockNumber,
+    to: BlockNumber,
+    range_size: BlockNumber,
+    concurrency: usize,
+    scan: F,
+) -> Result<(Vec<B>, BlockNumber), Error>
+where
+    F: Fn(BlockNumber, BlockNumber) -> Fut,
+    Fut: Future<Output = Result<(Vec<B>, BlockNumber), Error>>,
+{
+    let scans = (from..=to).step_by(range_size as usize).map(|start| {
+        let end = cmp::min(start + range_size - 1, to);
+        scan(start, end)
+    });
+
+    // `buffered` yields the results in the order of the ranges, no
+    // matter in which order the scans finish
+    let results: Vec<_> = futures03::stream::iter(scans)
+        .buffered(concurrency)
+        .try_collect()
+        .await?;
+
+    let mut blocks = Vec::new();
+    let mut scanned_to = to;
+    for (range_blocks, range_to) in results {
+        blocks.extend(range_blocks);
+        scanned_to = range_to;
+    }
+    Ok((blocks, scanned_to))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::{scan_in_ranges, StreamCheckpoint};
+    use crate::prelude::{anyhow, BlockNumber};
+
+    #[test]
+    fn stream_checkpoint_next_block() {
+        let checkpoint = StreamCheckpoint {
+            filter: "abc".to_string(),
+            from: 100,
+            to: 200,
+            blocks: vec![120, 150, 200],
+        };
+
+        assert_eq!(Some(120), checkpoint.next_block("abc", 100));
+        assert_eq!(Some(120), checkpoint.next_block("abc", 120));
+        assert_eq!(Some(150), checkpoint.next_block("abc", 121));
+        assert_eq!(Some(200), checkpoint.next_block("abc", 200));
+
+        // Blocks outside of the range and other filters can not use it
+        assert_eq!(None, checkpoint.next_block("abc", 99));
+        assert_eq!(None, checkpoint.next_block("abc", 201));
+        assert_eq!(None, checkpoint.next_block("xyz", 150));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn scan_in_ranges_keeps_order() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let scanned = Mutex::new(Vec::new());
+
+        // Earlier ranges take longer so that the scans finish out of order
+        let scan = |start: BlockNumber, end: BlockNumber| {
+            let (running, max_running, scanned) = (&running, &max_running, &scanned);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_secs(100 - start as u64)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                scanned.lock().unwrap().push((start, end));
+                Ok::<_, anyhow::Error>((vec![start, end], end))
+            }
+        };
+
+        let (blocks, to) = scan_in_ranges(10, 34, 10, 2, scan).await.unwrap();
+        assert_eq!(vec![10, 19, 20, 29, 30, 34], blocks);
+        assert_eq!(34, to);
+        assert_eq!(2, max_running.load(Ordering::SeqCst));
+        // The second range finished before the first one
+        assert_eq!(vec![(20, 29), (10, 19), (30, 34)], *scanned.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn scan_in_ranges_ends_where_last_range_ends() {
+        // The last range skipped null blocks at its end
+        let scan = |start: BlockNumber, end: BlockNumber| async move {
+            let to = if end == 25 { 23 } else { end };
+            Ok::<_, anyhow::Error>((vec![start], to))
+        };
+        let (blocks, to) = scan_in_ranges(1, 25, 5, 3, scan).await.unwrap();
+        assert_eq!(vec![1, 6, 11, 16, 21], blocks);
+        assert_eq!(23, to);
+
+        // A range of one block
+        let (blocks, to) = scan_in_ranges(7, 7, 5, 3, scan).await.unwrap();
+        assert_eq!(vec![7], blocks);
+        assert_eq!(7, to);
+
+        // If any range fails, so does the scan
+        let scan = |start: BlockNumber, end: BlockNumber| async move {
+            if start == 6 {
+                Err::<(Vec<BlockNumber>, BlockNumber), _>(anyhow!("provider unavailable"))
+            } else {
+                Ok((vec![start], end))
+            }
+        };
+        assert!(scan_in_ranges(1, 25, 5, 3, scan).await.is_err());
+    }
+}
@@ -117,6 +117,7 @@ where
         cursor: FirehoseCursor,
         mapper: Arc<F>,
         modules: Modules,
+        params_updates: Vec<(BlockNumber, Modules)>,
         module_name: String,
         start_blocks: Vec<BlockNumber>,
         end_blocks: Vec<BlockNumber>,
@@ -132,13 +133,16 @@ where
 
         let metrics = SubstreamsBlockStreamMetrics::new(registry, deployment.clone());
 
+        let mut segments = vec![(manifest_start_block_num, modules)];
+        segments.extend(params_updates);
+
         SubstreamsBlockStream {
             stream: Box::pin(stream_blocks(
                 client,
                 cursor,
                 deployment,
                 mapper,
-                modules,
+                segments,
                 module_name,
                 manifest_start_block_num,
                 manifest_end_block_num,
@@ -155,7 +159,7 @@ fn stream_blocks<C: Blockchain, F: BlockStreamMapper<C>>(
     cursor: FirehoseCursor,
     deployment: DeploymentHash,
     mapper: Arc<F>,
-    modules: Modules,
+    segments: Vec<(BlockNumber, Modules)>,
     module_name: String,
     manifest_start_block_num: BlockNumber,
     manifest_end_block_num: BlockNumber,
@@ -165,7 +169,7 @@ fn stream_blocks<C: Blockchain, F: BlockStreamMapper<C>>(
 ) -> impl Stream<Item = Result<BlockStreamEvent<C>, BlockStreamError>> {
     let mut latest_cursor = cursor.to_string();
 
-    let start_block_num = subgraph_current_block
+    let mut start_block_num = subgraph_current_block
         .as_ref()
         .map(|ptr| {
             // current_block has already been processed, we start at next block
@@ -173,7 +177,18 @@ fn stream_blocks<C: Blockchain, F: BlockStreamMapper<C>>(
         })
         .unwrap_or(manifest_start_block_num as i64);
 
-    let stop_block_num = manifest_end_block_num as u64;
+    // `segments` are the modules to use from each block on when the
+    // manifest updates the module params. Cursors can only be used with
+    // the modules they were produced by, so that we start each segment
+    // without a cursor
+    let mut segment = segments
+        .iter()
+        .rposition(|(start, _)| *start as i64 <= start_block_num)
+        .unwrap_or(0);
+    if segment > 0 && segments[segment].0 as i64 == start_block_num {
+        latest_cursor = String::new();
+    }
+    let mut last_block_num: Option<BlockNumber> = None;
 
     let headers = ConnectionHeaders::new().with_deployment(deployment.clone());
 
@@ -187,7 +202,10 @@ fn stream_blocks<C: Blockchain, F: BlockStreamMapper<C>>(
     let mut log_data = SubstreamsLogData::new();
 
     try_stream! {
-            if !modules.modules.iter().any(|m| module_name.eq(&m.name)) {
+            if !segments
+                .iter()
+                .all(|(_, modules)| modules.modules.iter().any(|m| module_name.eq(&m.name)))
+            {
                 Err(BlockStreamError::Fatal(format!(
                     "module `{}` not found",
                     module_name
@@ -202,11 +220,20 @@ fn stream_blocks<C: Blockchain, F: BlockStreamMapper<C>>(
             skip_backoff = false;
 
             let mut connect_start = Instant::now();
+            // The stream for a segment stops where the next segment starts
+            let stop_block_num = match segments.get(segment + 1) {
+                Some((next_start, _))
+                    if manifest_end_block_num == 0 || *next_start < manifest_end_block_num =>
+                {
+                    *next_start as u64
+                }
+                _ => manifest_end_block_num as u64,
+            };
             let request = Request {
                 start_block_num,
                 start_cursor: latest_cursor.to_string(),
                 stop_block_num,
-                modules: Some(modules.clone()),
+                modules: Some(segments[segment].1.clone()),
                 output_module: module_name.clone(),
                 production_mode: true,
                 ..Default::default()
@@ -241,6 +268,12 @@ fn stream_blocks<C: Blockchain, F: BlockStreamMapper<C>>(
 
                                         metrics.observe_response("proceed", &mut last_response_time, &endpoint.provider);
 
+                                        last_block_num = Some(match &event {
+                                            BlockStreamEvent::Revert(ptr, _) => ptr.number,
+                                            BlockStreamEvent::ProcessBlock(block, _) => block.ptr().number,
+                                            BlockStreamEvent::ProcessWasmBlock(ptr, _, _, _, _) => ptr.number,
+                                        });
+
                                         yield event;
 
                                         latest_cursor = cursor;
@@ -271,7 +304,20 @@ fn stream_blocks<C: Blockchain, F: BlockStreamMapper<C>>(
                         }
                     }
 
-                    if !expected_stream_end {
+                    let segment_done = match (segments.get(segment + 1), last_block_num) {
+                        (Some((next_start, _)), Some(last)) => last + 1 >= *next_start,
+                        _ => false,
+                    };
+
+                    if !expected_stream_end && segment_done {
+                        // Continue with the modules for the next segment
+                        segment += 1;
+                        start_block_num = segments[segment].0 as i64;
+                        latest_cursor = String::new();
+                        info!(&logger, "Switching to updated module params";
+                              "start_block" => start_block_num);
+                        skip_backoff = true;
+                    } else if !expected_stream_end {
                         error!(logger, "Stream blocks complete unexpectedly, expecting stream to always stream blocks");
                     }
                 },
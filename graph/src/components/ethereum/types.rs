@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::{convert::TryFrom, sync::Arc};
 use web3::types::{
-    Action, Address, Block, Bytes, Log, Res, Trace, Transaction, TransactionReceipt, H256, U256,
-    U64,
+    Action, Address, Block, Bytes, CallType, Log, Res, Trace, Transaction, TransactionReceipt,
+    H256, U256, U64,
 };
 
 use crate::{
@@ -122,6 +122,9 @@ pub struct EthereumCall {
     pub block_hash: H256,
     pub transaction_hash: Option<H256>,
     pub transaction_index: u64,
+    /// Whether this is a `CALL` made by a contract, rather than by the
+    /// transaction itself, that transfers ETH
+    pub internal_transfer: bool,
 }
 
 impl EthereumCall {
@@ -133,11 +136,18 @@ impl EthereumCall {
         }
         // We are only interested in traces from CALLs
         let call = match &trace.action {
-            // Contract to contract value transfers compile to the CALL opcode
-            // and have no input. Call handlers are for triggering on explicit method calls right now.
-            Action::Call(call) if call.input.0.len() >= 4 => call,
+            Action::Call(call) => call,
             _ => return None,
         };
+        // Contract to contract value transfers compile to the CALL opcode
+        // and have no input. Call handlers are for triggering on explicit
+        // method calls, and we only keep such calls for transfer handlers
+        let internal_transfer = !trace.trace_address.is_empty()
+            && call.call_type == CallType::Call
+            && !call.value.is_zero();
+        if call.input.0.len() < 4 && !internal_transfer {
+            return None;
+        }
         let (output, gas_used) = match &trace.result {
             Some(Res::Call(result)) => (result.output.clone(), result.gas_used),
             _ => return None,
@@ -158,6 +168,7 @@ impl EthereumCall {
             block_hash: trace.block_hash,
             transaction_hash: trace.transaction_hash,
             transaction_index,
+            internal_transfer,
         })
     }
 }
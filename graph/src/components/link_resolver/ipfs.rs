@@ -1,10 +1,11 @@
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::env::EnvVars;
 use crate::futures01::{stream::poll_fn, try_ready};
 use crate::futures01::{Async, Poll};
-use crate::ipfs_client::IpfsError;
+use crate::ipfs_client::{available_clients, failover_client, CidFile, IpfsError};
 use crate::util::futures::RetryConfigNoTimeout;
 use anyhow::anyhow;
 use async_trait::async_trait;
@@ -62,6 +63,7 @@ async fn select_fastest_client(
     timeout: Duration,
     do_retry: bool,
 ) -> Result<IpfsClient, Error> {
+    let clients = available_clients(&clients);
     if clients.len() == 1 {
         return Ok(clients[0].cheap_clone());
     }
@@ -99,6 +101,29 @@ async fn select_fastest_client(
     }))
 }
 
+/// Check whether any of `clients` has `path`. If none of them has it,
+/// return the error of the last client that failed
+async fn exists_in_any(
+    clients: &[IpfsClient],
+    path: &str,
+    timeout: Duration,
+) -> Result<(), IpfsError> {
+    let mut exists: FuturesUnordered<_> = clients
+        .iter()
+        .map(|c| c.exists(path, Some(timeout)))
+        .collect();
+
+    let mut err = None;
+    while let Some(result) = exists.next().await {
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => err = Some(e),
+        }
+    }
+    // `IpfsResolver` is never constructed without clients
+    Err(err.expect("there is at least one IPFS client"))
+}
+
 #[derive(Clone, CheapClone)]
 pub struct IpfsResolver {
     clients: Arc<Vec<IpfsClient>>,
@@ -120,6 +145,21 @@ impl IpfsResolver {
             env_vars,
         }
     }
+
+    fn cache_insert(&self, logger: &Logger, path: String, data: &[u8]) {
+        // Only cache files if they are not too large
+        if data.len() <= self.env_vars.mappings.max_ipfs_cache_file_size {
+            let mut cache = self.cache.lock().unwrap();
+            if !cache.contains_key(&path) {
+                cache.insert(path, data.to_vec());
+            }
+        } else {
+            debug!(logger, "File too large for cache";
+                        "path" => path,
+                        "size" => data.len()
+            );
+        }
+    }
 }
 
 impl Debug for IpfsResolver {
@@ -166,15 +206,15 @@ impl LinkResolverTrait for IpfsResolver {
         )
         .await?;
 
-        let max_cache_file_size = self.env_vars.mappings.max_ipfs_cache_file_size;
         let max_file_size = self.env_vars.mappings.max_ipfs_file_bytes;
 
         let req_path = path.clone();
         let timeout = self.timeout;
+        let clients = self.clients.cheap_clone();
         let data = retry_policy(self.retry, "ipfs.cat", logger)
             .run(move || {
                 let path = req_path.clone();
-                let client = client.clone();
+                let client = failover_client(&clients, &client);
                 async move {
                     Ok(client
                         .cat_all(&path, Some(timeout), max_file_size)
@@ -184,20 +224,87 @@ impl LinkResolverTrait for IpfsResolver {
             })
             .await?;
 
-        // Only cache files if they are not too large
-        if data.len() <= max_cache_file_size {
-            let mut cache = self.cache.lock().unwrap();
-            if !cache.contains_key(&path) {
-                cache.insert(path.clone(), data.clone());
+        self.cache_insert(logger, path, &data);
+
+        Ok(data)
+    }
+
+    async fn cat_bounded(
+        &self,
+        logger: &Logger,
+        link: &Link,
+        max_bytes: usize,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let path = link.link.trim_start_matches("/ipfs/").to_owned();
+
+        if let Some(data) = self.cache.lock().unwrap().get(&path) {
+            trace!(logger, "IPFS cache hit"; "hash" => &path);
+            return Ok((data.len() <= max_bytes).then(|| data.clone()));
+        }
+
+        let client = select_fastest_client(
+            self.clients.cheap_clone(),
+            logger.cheap_clone(),
+            path.clone(),
+            self.timeout,
+            self.retry,
+        )
+        .await?;
+
+        // A file that is larger than what this node is configured to fetch
+        // is an error rather than `None` since other nodes might be
+        // configured to fetch it
+        let max_file_size = self.env_vars.mappings.max_ipfs_file_bytes;
+        let limit = max_bytes.min(max_file_size);
+
+        let req_path = path.clone();
+        let timeout = self.timeout;
+        let clients = self.clients.cheap_clone();
+        let res = retry_policy(self.retry, "ipfs.catBounded", logger)
+            .run(move || {
+                let path = req_path.clone();
+                let client = failover_client(&clients, &client);
+                async move { Ok(client.cat_all(&path, Some(timeout), limit).await?.to_vec()) }
+            })
+            .await;
+
+        match res {
+            Ok(data) => {
+                self.cache_insert(logger, path, &data);
+                Ok(Some(data))
             }
-        } else {
-            debug!(logger, "File too large for cache";
-                        "path" => path,
-                        "size" => data.len()
-            );
+            Err(IpfsError::FileTooLarge(..)) if max_bytes <= max_file_size => Ok(None),
+            Err(e) => Err(e.into()),
         }
+    }
 
-        Ok(data)
+    async fn exists(&self, logger: &Logger, link: &Link) -> Result<bool, Error> {
+        let path = link.link.trim_start_matches("/ipfs/").to_owned();
+
+        let file = match CidFile::from_str(&path) {
+            Ok(file) => file,
+            Err(_) => return Ok(false),
+        };
+        if self.cache.lock().unwrap().contains_key(&path) {
+            return Ok(true);
+        }
+        trace!(logger, "IPFS exists"; "hash" => &path);
+
+        // If the root of the link is not available, we can't tell whether
+        // the link exists
+        let root = file.cid.to_string();
+        exists_in_any(&self.clients, &root, self.timeout).await?;
+        if file.path.is_none() {
+            return Ok(true);
+        }
+
+        // The root is available, and resolving a path in it fails with an
+        // error status if the path does not exist
+        match exists_in_any(&self.clients, &path, self.timeout).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.is_status() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
     }
 
     async fn get_block(&self, logger: &Logger, link: &Link) -> Result<Vec<u8>, Error> {
@@ -214,10 +321,11 @@ impl LinkResolverTrait for IpfsResolver {
         // Note: The IPFS protocol limits the size of blocks to 1MB, so we don't need to enforce size
         // limits here.
         let link = link.link.clone();
+        let clients = self.clients.cheap_clone();
         let data = retry_policy(self.retry, "ipfs.getBlock", logger)
             .run(move || {
                 let link = link.clone();
-                let client = client.clone();
+                let client = failover_client(&clients, &client);
                 async move {
                     let data = client.get_block(link.clone()).await?.to_vec();
                     Result::<Vec<u8>, _>::Ok(data)
@@ -352,6 +460,68 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn cat_bounded() {
+        let mut env_vars = EnvVars::default();
+        env_vars.mappings.max_ipfs_file_bytes = 200;
+
+        let file: &[u8] = &[1u8; 100];
+        let client = IpfsClient::localhost();
+        let resolver = super::IpfsResolver::new(vec![client.clone()], Arc::new(env_vars));
+
+        let logger = Logger::root(slog::Discard, o!());
+
+        let link = Link {
+            link: client.add(file.into()).await.unwrap().hash,
+        };
+        let data = IpfsResolver::cat_bounded(&resolver, &logger, &link, 100)
+            .await
+            .unwrap();
+        assert_eq!(Some(file.to_vec()), data);
+
+        // The file is now cached, which must not change the outcome
+        let data = IpfsResolver::cat_bounded(&resolver, &logger, &link, 99)
+            .await
+            .unwrap();
+        assert_eq!(None, data);
+
+        // Files over the limit of this node are an error, not `None`
+        let file: &[u8] = &[2u8; 201];
+        let link = Link {
+            link: client.add(file.into()).await.unwrap().hash,
+        };
+        let data = IpfsResolver::cat_bounded(&resolver, &logger, &link, 150)
+            .await
+            .unwrap();
+        assert_eq!(None, data);
+        IpfsResolver::cat_bounded(&resolver, &logger, &link, 300)
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn exists() {
+        let client = IpfsClient::localhost();
+        let resolver = super::IpfsResolver::new(vec![client.clone()], Arc::new(EnvVars::default()));
+
+        let logger = Logger::root(slog::Discard, o!());
+
+        let hash = client.add(b"exists".to_vec()).await.unwrap().hash;
+        let exists = |link: String| {
+            let resolver = resolver.cheap_clone();
+            let logger = logger.cheap_clone();
+            async move {
+                IpfsResolver::exists(&resolver, &logger, &Link { link })
+                    .await
+                    .unwrap()
+            }
+        };
+        assert!(exists(hash.clone()).await);
+        assert!(exists(format!("/ipfs/{}", hash)).await);
+        assert!(!exists("not a cid".to_string()).await);
+        assert!(!exists(format!("{}/missing.json", hash)).await);
+    }
+
     async fn json_round_trip(text: &'static str, env_vars: EnvVars) -> Result<Vec<Value>, Error> {
         let client = IpfsClient::localhost();
         let resolver = super::IpfsResolver::new(vec![client.clone()], Arc::new(env_vars));
@@ -25,6 +25,21 @@ pub trait LinkResolver: Send + Sync + 'static + Debug {
     /// Fetches the link contents as bytes.
     async fn cat(&self, logger: &Logger, link: &Link) -> Result<Vec<u8>, Error>;
 
+    /// Fetches the link contents as bytes if they are at most `max_bytes`
+    /// long. Returns `Ok(None)` if they are longer, and an error if they
+    /// could not be fetched.
+    async fn cat_bounded(
+        &self,
+        logger: &Logger,
+        link: &Link,
+        max_bytes: usize,
+    ) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Checks whether the link can be resolved. Returns `Ok(false)` only if
+    /// the link is known not to exist, and an error if that can not be
+    /// determined, for example, because the content is not available.
+    async fn exists(&self, logger: &Logger, link: &Link) -> Result<bool, Error>;
+
     /// Fetches the IPLD block contents as bytes.
     async fn get_block(&self, logger: &Logger, link: &Link) -> Result<Vec<u8>, Error>;
 
@@ -1,4 +1,4 @@
-use prometheus::Counter;
+use prometheus::{Counter, CounterVec};
 
 use crate::blockchain::block_stream::BlockStreamMetrics;
 use crate::prelude::{Gauge, Histogram, HostMetrics};
@@ -14,6 +14,14 @@ pub struct SubgraphInstanceMetrics {
     pub block_processing_duration: Box<Histogram>,
     pub block_ops_transaction_duration: Box<Histogram>,
     pub firehose_connection_errors: Counter,
+    pub block_stream_buffer_occupancy: Gauge,
+    pub block_stream_restarts: Box<CounterVec>,
+    pub trigger_conflicts: Counter,
+    pub skipped_triggers: Counter,
+    pub offchain_trigger_queue_depth: Gauge,
+    pub quota_cpu_ms: Counter,
+    pub quota_writes: Counter,
+    pub quota_throttled_secs: Counter,
 
     pub stopwatch: StopwatchMetrics,
     trigger_processing_duration: Box<Histogram>,
@@ -68,6 +76,71 @@ impl SubgraphInstanceMetrics {
             )
             .expect("failed to create firehose_connection_errors counter");
 
+        let block_stream_buffer_occupancy = registry
+            .new_deployment_gauge(
+                "deployment_block_stream_buffer_occupancy",
+                "The number of blocks that the block stream of a deployment buffered ahead of processing",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_block_stream_buffer_occupancy` gauge");
+
+        let block_stream_restarts = registry
+            .new_deployment_counter_vec(
+                "deployment_block_stream_restarts",
+                "Counts how often the block stream of a deployment was restarted, by reason",
+                subgraph_hash,
+                vec!["reason".to_string()],
+            )
+            .expect("failed to create `deployment_block_stream_restarts` counter");
+
+        let trigger_conflicts = registry
+            .new_deployment_counter(
+                "deployment_trigger_conflicts",
+                "Counts triggers that were run concurrently and had to be run again because they conflicted with earlier triggers",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_trigger_conflicts` counter");
+
+        let skipped_triggers = registry
+            .new_deployment_counter(
+                "deployment_skipped_triggers",
+                "Counts triggers whose handler failed and that were skipped because of the handler's error policy",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_skipped_triggers` counter");
+
+        let offchain_trigger_queue_depth = registry
+            .new_deployment_gauge(
+                "deployment_offchain_trigger_queue_depth",
+                "The number of ready file data source triggers of a deployment that have not been handled yet",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_offchain_trigger_queue_depth` gauge");
+
+        let quota_cpu_ms = registry
+            .new_deployment_counter(
+                "deployment_quota_cpu_ms",
+                "Counts the mapping time in milliseconds charged against the CPU quota of a deployment",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_quota_cpu_ms` counter");
+
+        let quota_writes = registry
+            .new_deployment_counter(
+                "deployment_quota_writes",
+                "Counts the entity writes charged against the write quota of a deployment",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_quota_writes` counter");
+
+        let quota_throttled_secs = registry
+            .new_deployment_counter(
+                "deployment_quota_throttled_secs",
+                "Counts the seconds a deployment was held back because it exceeded its quota",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_quota_throttled_secs` counter");
+
         let labels = HashMap::from_iter([
             ("deployment".to_string(), subgraph_hash.to_string()),
             ("shard".to_string(), stopwatch.shard().to_string()),
@@ -92,6 +165,14 @@ impl SubgraphInstanceMetrics {
             trigger_processing_duration,
             block_ops_transaction_duration,
             firehose_connection_errors,
+            block_stream_buffer_occupancy,
+            block_stream_restarts,
+            trigger_conflicts,
+            skipped_triggers,
+            offchain_trigger_queue_depth,
+            quota_cpu_ms,
+            quota_writes,
+            quota_throttled_secs,
             stopwatch,
             blocks_processed_secs,
             blocks_processed_count,
@@ -114,6 +195,14 @@ impl SubgraphInstanceMetrics {
         registry.unregister(self.block_trigger_count.clone());
         registry.unregister(self.trigger_processing_duration.clone());
         registry.unregister(self.block_ops_transaction_duration.clone());
+        registry.unregister(Box::new(self.block_stream_buffer_occupancy.clone()));
+        registry.unregister(self.block_stream_restarts.clone());
+        registry.unregister(Box::new(self.trigger_conflicts.clone()));
+        registry.unregister(Box::new(self.skipped_triggers.clone()));
+        registry.unregister(Box::new(self.offchain_trigger_queue_depth.clone()));
+        registry.unregister(Box::new(self.quota_cpu_ms.clone()));
+        registry.unregister(Box::new(self.quota_writes.clone()));
+        registry.unregister(Box::new(self.quota_throttled_secs.clone()));
     }
 }
 
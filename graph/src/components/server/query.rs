@@ -1,8 +1,10 @@
+use http_body_util::combinators::UnsyncBoxBody;
 use http_body_util::Full;
 use hyper::body::Bytes;
 use hyper::Response;
 
-use crate::data::query::QueryError;
+use crate::data::query::{ErrorCode, QueryError};
+use std::convert::Infallible;
 use std::error::Error;
 use std::fmt;
 
@@ -11,6 +13,11 @@ use crate::components::store::StoreError;
 pub type ServerResponse = Response<Full<Bytes>>;
 pub type ServerResult = Result<ServerResponse, ServerError>;
 
+/// The body of a response that is sent to the client in several pieces as
+/// they become available, like the parts of an incremental GraphQL response
+pub type StreamingBody = UnsyncBoxBody<Bytes, Infallible>;
+pub type StreamingResponse = Response<StreamingBody>;
+
 /// Errors that can occur while processing incoming requests.
 #[derive(Debug)]
 pub enum ServerError {
@@ -19,6 +26,16 @@ pub enum ServerError {
     InternalError(String),
 }
 
+impl ServerError {
+    /// The code that classifies this error for clients, if it has one
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ServerError::QueryError(e) => e.code(),
+            ServerError::ClientError(_) | ServerError::InternalError(_) => None,
+        }
+    }
+}
+
 impl From<QueryError> for ServerError {
     fn from(e: QueryError) -> Self {
         ServerError::QueryError(e)
@@ -1,6 +1,6 @@
 use anyhow::anyhow;
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Debug};
 use std::sync::Arc;
 
@@ -85,8 +85,9 @@ pub struct EntityCache {
     /// means that the entity is not present in the store
     current: LfuCache<EntityKey, Option<Arc<Entity>>>,
 
-    /// The accumulated changes to an entity.
-    updates: HashMap<EntityKey, EntityOp>,
+    /// The accumulated changes to an entity. Forks made with `forks` share
+    /// them with this cache while their handlers run
+    updates: Arc<HashMap<EntityKey, EntityOp>>,
 
     // Updates for a currently executing handler.
     handler_updates: HashMap<EntityKey, EntityOp>,
@@ -105,6 +106,43 @@ pub struct EntityCache {
     /// generated IDs, the `EntityCache` needs to be newly instantiated for
     /// each block
     seq: u32,
+
+    /// For a cache made with `forks`, the changes that the parent cache
+    /// had accumulated when the fork was made
+    base: Option<Arc<HashMap<EntityKey, EntityOp>>>,
+
+    /// The keys that were read through a fork
+    reads: HashSet<EntityKey>,
+
+    /// Whether a fork did something whose outcome depends on more than
+    /// the keys in `reads`
+    opaque: bool,
+
+    /// The number of sets and removes that handlers made through this
+    /// cache; only used for handler statistics
+    ops: usize,
+}
+
+/// The entities that a handler running in a fork read and changed. See
+/// `EntityCache::forks`
+#[derive(Debug, Default)]
+pub struct EntityFootprint {
+    pub reads: HashSet<EntityKey>,
+    pub writes: HashSet<EntityKey>,
+    /// The handler generated ids or loaded derived fields; its outcome
+    /// might depend on changes to any entity
+    pub opaque: bool,
+}
+
+impl EntityFootprint {
+    /// Whether the handler could have had a different outcome had it run
+    /// after the entities in `changed` were changed. Writes don't conflict
+    /// with each other since applying the changes of the fork after those
+    /// in `changed` accumulates them the same way as running the handler
+    /// sequentially would have
+    pub fn conflicts_with(&self, changed: &HashSet<EntityKey>) -> bool {
+        self.opaque || self.reads.iter().any(|key| changed.contains(key))
+    }
 }
 
 impl Debug for EntityCache {
@@ -124,15 +162,7 @@ pub struct ModificationsAndCache {
 
 impl EntityCache {
     pub fn new(store: Arc<dyn s::ReadStore>) -> Self {
-        Self {
-            current: LfuCache::new(),
-            updates: HashMap::new(),
-            handler_updates: HashMap::new(),
-            in_handler: false,
-            schema: store.input_schema(),
-            store,
-            seq: 0,
-        }
+        Self::with_current(store, LfuCache::new())
     }
 
     /// Make a new entity. The entity is not part of the cache
@@ -146,12 +176,61 @@ impl EntityCache {
     pub fn with_current(store: Arc<dyn s::ReadStore>, current: EntityLfuCache) -> EntityCache {
         EntityCache {
             current,
-            updates: HashMap::new(),
+            updates: Arc::new(HashMap::new()),
             handler_updates: HashMap::new(),
             in_handler: false,
             schema: store.input_schema(),
             store,
             seq: 0,
+            base: None,
+            reads: HashSet::new(),
+            opaque: false,
+            ops: 0,
+        }
+    }
+
+    /// Make `count` caches in which handlers can run concurrently against
+    /// the state of this cache. Reads in a fork see the changes made in
+    /// this cache so far, but a fork starts out without any changes of its
+    /// own, so that passing it to `extend` only applies what was done in
+    /// the fork. Forks remember what they read; use `footprint` to decide
+    /// whether a fork can be merged after other changes were made.
+    pub fn forks(&self, count: usize) -> Vec<EntityCache> {
+        assert!(!self.in_handler);
+
+        (0..count)
+            .map(|_| EntityCache {
+                current: LfuCache::new(),
+                updates: Arc::new(HashMap::new()),
+                handler_updates: HashMap::new(),
+                in_handler: false,
+                store: self.store.cheap_clone(),
+                schema: self.schema.cheap_clone(),
+                seq: self.seq,
+                base: Some(self.updates.cheap_clone()),
+                reads: HashSet::new(),
+                opaque: false,
+                ops: 0,
+            })
+            .collect()
+    }
+
+    /// Stop sharing the changes of the cache this fork was made from once
+    /// its handlers are done, so that merging forks into that cache does
+    /// not have to copy its changes
+    pub fn detach(&mut self) {
+        assert!(!self.in_handler);
+        self.base = None;
+    }
+
+    /// The entities read and changed in this fork
+    pub fn footprint(&self) -> EntityFootprint {
+        assert!(!self.in_handler);
+
+        EntityFootprint {
+            reads: self.reads.clone(),
+            writes: self.updates.keys().cloned().collect(),
+            opaque: self.opaque,
         }
     }
 
@@ -203,6 +282,15 @@ impl EntityCache {
             GetScope::InBlock => true,
         });
 
+        if let Some(base) = &self.base {
+            self.reads.insert(key.clone());
+            if let Some(op) = base.get(key).cloned() {
+                entity = op
+                    .apply_to(&mut entity)
+                    .map_err(|e| key.unknown_attribute(e))?
+                    .map(Arc::new);
+            }
+        }
         if let Some(op) = self.updates.get(key).cloned() {
             entity = op
                 .apply_to(&mut entity)
@@ -222,6 +310,10 @@ impl EntityCache {
         &mut self,
         eref: &LoadRelatedRequest,
     ) -> Result<Vec<Entity>, anyhow::Error> {
+        // Derived loads in a fork don't see the changes in `base`; the
+        // fork must not be merged
+        self.opaque |= self.base.is_some();
+
         let (entity_type, field) = self.schema.get_field_related(eref)?;
 
         let query = DerivedEntityQuery {
@@ -340,7 +432,13 @@ impl EntityCache {
         Ok(entity_map.into_values().collect())
     }
 
+    /// The number of entity sets and removes done with `set` and `remove`
+    pub fn ops(&self) -> usize {
+        self.ops
+    }
+
     pub fn remove(&mut self, key: EntityKey) {
+        self.ops += 1;
         self.entity_op(key, EntityOp::Remove);
     }
 
@@ -353,6 +451,7 @@ impl EntityCache {
         // check the validate for derived fields
         let is_valid = entity.validate(&key).is_ok();
 
+        self.ops += 1;
         self.entity_op(key.clone(), EntityOp::Update(entity));
 
         // The updates we were given are not valid by themselves; force a
@@ -391,7 +490,7 @@ impl EntityCache {
         use std::collections::hash_map::Entry;
         let updates = match self.in_handler {
             true => &mut self.handler_updates,
-            false => &mut self.updates,
+            false => Arc::make_mut(&mut self.updates),
         };
 
         match updates.entry(key) {
@@ -405,8 +504,11 @@ impl EntityCache {
     pub(crate) fn extend(&mut self, other: EntityCache) {
         assert!(!other.in_handler);
 
+        // We don't know what was read to produce `other`
+        self.opaque |= self.base.is_some();
+
         self.current.extend(other.current);
-        for (key, op) in other.updates {
+        for (key, op) in Arc::unwrap_or_clone(other.updates) {
             self.entity_op(key, op);
         }
     }
@@ -415,6 +517,8 @@ impl EntityCache {
     pub fn generate_id(&mut self, id_type: IdType, block: BlockNumber) -> anyhow::Result<Id> {
         let id = id_type.generate_id(block, self.seq)?;
         self.seq += 1;
+        // Generated ids depend on how many ids were generated before
+        self.opaque |= self.base.is_some();
         Ok(id)
     }
 
@@ -450,7 +554,7 @@ impl EntityCache {
         }
 
         let mut mods = Vec::new();
-        for (key, update) in self.updates {
+        for (key, update) in Arc::unwrap_or_clone(self.updates) {
             use EntityModification::*;
 
             let current = self.current.remove(&key).and_then(|entity| entity);
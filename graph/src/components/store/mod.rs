@@ -1,15 +1,19 @@
 mod entity_cache;
 mod err;
+mod replay;
 mod traits;
 pub mod write;
 
-pub use entity_cache::{EntityCache, EntityLfuCache, GetScope, ModificationsAndCache};
+pub use entity_cache::{
+    EntityCache, EntityFootprint, EntityLfuCache, GetScope, ModificationsAndCache,
+};
 use futures03::future::{FutureExt, TryFutureExt};
 use slog::{trace, Logger};
 
 pub use super::subgraph::Entity;
 pub use err::StoreError;
 use itertools::Itertools;
+pub use replay::{diff_replay, ReplayChange, ReplayDiff};
 use strum_macros::Display;
 pub use traits::*;
 pub use write::Batch;
@@ -23,20 +27,22 @@ use std::fmt;
 use std::fmt::Display;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::blockchain::{Block, BlockHash, BlockPtr};
 use crate::cheap_clone::CheapClone;
 use crate::components::store::write::EntityModification;
 use crate::constraint_violation;
-use crate::data::store::scalar::Bytes;
+use crate::data::store::scalar::{BigDecimal, Bytes};
 use crate::data::store::{Id, IdList, Value};
 use crate::data::value::Word;
 use crate::data_source::CausalityRegion;
 use crate::derive::CheapClone;
 use crate::env::ENV_VARS;
 use crate::prelude::{s, Attribute, DeploymentHash, SubscriptionFilter, ValueType};
-use crate::schema::{ast as sast, EntityKey, EntityType, InputSchema};
+use crate::schema::{
+    ast as sast, EntityKey, EntityType, FulltextAlgorithm, FulltextLanguage, InputSchema,
+};
 use crate::util::stats::MovingStats;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -130,9 +136,72 @@ pub enum EntityFilter {
     EndsWithNoCase(Attribute, Value),
     NotEndsWith(Attribute, Value),
     NotEndsWithNoCase(Attribute, Value),
+    /// Match with a SQL `ilike` pattern
+    MatchesNoCase(Attribute, Value),
+    /// Match with a POSIX regular expression
+    Regex(Attribute, Value),
+    RegexNoCase(Attribute, Value),
     ChangeBlockGte(BlockNumber),
     Child(Child),
-    Fulltext(Attribute, Value),
+    Fulltext(Attribute, Value, FulltextOptions),
+    /// Match entities whose latitude and longitude attributes, in that
+    /// order, lie within the area
+    Spatial(Attribute, Attribute, SpatialArea),
+}
+
+/// Per-query settings for fulltext searches that override the
+/// configuration of the `@fulltext` directive
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FulltextOptions {
+    /// The ranking function to use when ordering by relevance
+    pub rank_by: Option<FulltextAlgorithm>,
+    /// The language used to parse the search terms
+    pub language: Option<FulltextLanguage>,
+    /// Replace the values of the fields included in the search with
+    /// snippets that highlight the matches
+    pub highlight: bool,
+}
+
+/// An area on the earth's surface for spatial filters. All coordinates
+/// are in degrees
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpatialArea {
+    /// The area between two latitudes and two longitudes. If
+    /// `min_longitude` is larger than `max_longitude`, the area crosses
+    /// the antimeridian
+    Box {
+        min_latitude: BigDecimal,
+        min_longitude: BigDecimal,
+        max_latitude: BigDecimal,
+        max_longitude: BigDecimal,
+    },
+    /// The points that are at most `meters` away from the center
+    Radius {
+        latitude: BigDecimal,
+        longitude: BigDecimal,
+        meters: BigDecimal,
+    },
+}
+
+impl fmt::Display for SpatialArea {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpatialArea::Box {
+                min_latitude,
+                min_longitude,
+                max_latitude,
+                max_longitude,
+            } => write!(
+                f,
+                "box(({min_latitude}, {min_longitude}), ({max_latitude}, {max_longitude}))"
+            ),
+            SpatialArea::Radius {
+                latitude,
+                longitude,
+                meters,
+            } => write!(f, "radius(({latitude}, {longitude}), {meters}m)"),
+        }
+    }
 }
 
 // A somewhat concise string representation of a filter
@@ -147,7 +216,7 @@ impl fmt::Display for EntityFilter {
             Or(fs) => {
                 write!(f, "{}", fs.iter().map(|f| f.to_string()).join(" or "))
             }
-            Equal(a, v) | Fulltext(a, v) => write!(f, "{a} = {v}"),
+            Equal(a, v) | Fulltext(a, v, _) => write!(f, "{a} = {v}"),
             Not(a, v) => write!(f, "{a} != {v}"),
             GreaterThan(a, v) => write!(f, "{a} > {v}"),
             LessThan(a, v) => write!(f, "{a} < {v}"),
@@ -171,12 +240,16 @@ impl fmt::Display for EntityFilter {
             EndsWithNoCase(a, v) => write!(f, "{a} ~ *{v}$i"),
             NotEndsWith(a, v) => write!(f, "{a} !~ *{v}$"),
             NotEndsWithNoCase(a, v) => write!(f, "{a} !~ *{v}$i"),
+            MatchesNoCase(a, v) => write!(f, "{a} ilike {v}"),
+            Regex(a, v) => write!(f, "{a} ~ /{v}/"),
+            RegexNoCase(a, v) => write!(f, "{a} ~ /{v}/i"),
             ChangeBlockGte(b) => write!(f, "block >= {b}"),
             Child(child /* a, et, cf, _ */) => write!(
                 f,
                 "join on {} with {}({})",
                 child.attr, child.entity_type, child.filter
             ),
+            Spatial(lat, lon, area) => write!(f, "({lat}, {lon}) within {area}"),
         }
     }
 }
@@ -292,6 +365,44 @@ impl std::default::Default for EntityRange {
     }
 }
 
+/// The position of an entity in the result of a query. Queries that have
+/// a cursor only return entities that come after that position in the
+/// order of the query, which makes it possible to paginate without
+/// skipping over rows
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityCursor {
+    /// The id of the entity at the cursor
+    pub id: Value,
+    /// The value of the attribute the query is ordered by for the entity
+    /// at the cursor, or `None` if the query is ordered by `id`
+    pub value: Option<Value>,
+}
+
+/// An aggregate that is computed over all the entities that an
+/// `EntityQuery` matches. All but `Count` are computed over the values of
+/// a numeric attribute
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntityAggregate {
+    Count,
+    Sum(String),
+    Avg(String),
+    Min(String),
+    Max(String),
+}
+
+impl EntityAggregate {
+    /// The attribute this aggregate is computed over
+    pub fn attribute(&self) -> Option<&str> {
+        match self {
+            EntityAggregate::Count => None,
+            EntityAggregate::Sum(attr)
+            | EntityAggregate::Avg(attr)
+            | EntityAggregate::Min(attr)
+            | EntityAggregate::Max(attr) => Some(attr.as_str()),
+        }
+    }
+}
+
 /// The attribute we want to window by in an `EntityWindow`. We have to
 /// distinguish between scalar and list attributes since we need to use
 /// different queries for them, and the JSONB storage scheme can not
@@ -452,6 +563,10 @@ pub struct EntityQuery {
     /// A range to limit the size of the result.
     pub range: EntityRange,
 
+    /// Only return entities that come after this cursor. For queries with
+    /// windows, the cursor applies to the children of each parent
+    pub after: Option<EntityCursor>,
+
     /// Optional logger for anything related to this query
     pub logger: Option<Logger>,
 
@@ -459,6 +574,10 @@ pub struct EntityQuery {
 
     pub trace: bool,
 
+    /// When the GraphQL query this query is part of times out. The store
+    /// uses it to limit how long Postgres will run the query for
+    pub deadline: Option<Instant>,
+
     _force_use_of_new: (),
 }
 
@@ -475,9 +594,11 @@ impl EntityQuery {
             filter: None,
             order: EntityOrder::Default,
             range: EntityRange::default(),
+            after: None,
             logger: None,
             query_id: None,
             trace: false,
+            deadline: None,
             _force_use_of_new: (),
         }
     }
@@ -497,6 +618,11 @@ impl EntityQuery {
         self
     }
 
+    pub fn after(mut self, cursor: EntityCursor) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+
     pub fn first(mut self, first: u32) -> Self {
         self.range.first = Some(first);
         self
@@ -538,6 +664,71 @@ impl EntityQuery {
     }
 }
 
+/// The formats in which the entities of a table can be exported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma-separated values with a header line that lists the
+    /// attributes of the entity type
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            _ => Err(format!(
+                "unknown export format `{}`, expected `csv` or `ndjson`",
+                s
+            )),
+        }
+    }
+}
+
+/// An index on the table for an entity type
+#[derive(Clone, Debug)]
+pub struct EntityIndex {
+    pub name: String,
+    /// The `create index` statement for the index
+    pub definition: String,
+    /// Whether queries can use the index. Indexes that are still being
+    /// built concurrently, or whose build failed, are not valid
+    pub valid: bool,
+    /// How far building the index has progressed if it is being built
+    pub progress: Option<IndexBuildProgress>,
+}
+
+/// Statistics about the table for an entity type. The number of entities
+/// and versions are the estimates that Postgres keeps for the table
+#[derive(Clone, Debug)]
+pub struct EntityTypeStats {
+    pub entity_type: String,
+    pub table: String,
+    pub entities: i64,
+    pub versions: i64,
+    /// The size of the table in bytes
+    pub table_size: i64,
+    /// The size of all indexes on the table in bytes
+    pub index_size: i64,
+    /// The last block to which the table was pruned
+    pub last_pruned_block: Option<BlockNumber>,
+}
+
+/// The progress of building an index as reported by Postgres'
+/// `pg_stat_progress_create_index` view
+#[derive(Clone, Debug)]
+pub struct IndexBuildProgress {
+    pub phase: String,
+    pub blocks_done: i64,
+    pub blocks_total: i64,
+    pub tuples_done: i64,
+    pub tuples_total: i64,
+}
+
 /// Operation types that lead to entity changes.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -548,6 +739,11 @@ pub enum EntityChangeOperation {
     Removed,
 }
 
+/// The largest number of ids of changed entities of one entity type that
+/// an `EntityChange` lists. If more entities of a type changed, the change
+/// only names the type so that notifications stay small
+pub const ENTITY_CHANGE_MAX_IDS: usize = 100;
+
 /// Entity change events emitted by [Store](trait.Store.html) implementations.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum EntityChange {
@@ -555,6 +751,10 @@ pub enum EntityChange {
         subgraph_id: DeploymentHash,
         /// Entity type name of the changed entity.
         entity_type: String,
+        /// The ids of the changed entities. If this is `None`, any entity
+        /// of the type might have changed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ids: Option<BTreeSet<String>>,
     },
     Assignment {
         deployment: DeploymentLocator,
@@ -564,9 +764,53 @@ pub enum EntityChange {
 
 impl EntityChange {
     pub fn for_data(subgraph_id: DeploymentHash, key: EntityKey) -> Self {
+        Self::for_ids(
+            subgraph_id,
+            key.entity_type.to_string(),
+            std::iter::once(key.entity_id.to_string()),
+        )
+    }
+
+    /// A change to the entities of type `entity_type` with the given `ids`.
+    /// If there are more than `ENTITY_CHANGE_MAX_IDS` of them, the change
+    /// does not list them
+    pub fn for_ids(
+        subgraph_id: DeploymentHash,
+        entity_type: String,
+        ids: impl IntoIterator<Item = String>,
+    ) -> Self {
+        let mut listed = BTreeSet::new();
+        for id in ids {
+            listed.insert(id);
+            if listed.len() > ENTITY_CHANGE_MAX_IDS {
+                return Self::Data {
+                    subgraph_id,
+                    entity_type,
+                    ids: None,
+                };
+            }
+        }
         Self::Data {
             subgraph_id,
-            entity_type: key.entity_type.to_string(),
+            entity_type,
+            ids: Some(listed),
+        }
+    }
+
+    /// Whether this change might have changed the entity of type
+    /// `entity_type` with id `id` in `deployment`
+    pub fn affects(&self, deployment: &DeploymentHash, entity_type: &str, id: &str) -> bool {
+        match self {
+            Self::Data {
+                subgraph_id,
+                entity_type: changed_type,
+                ids,
+            } => {
+                subgraph_id == deployment
+                    && changed_type == entity_type
+                    && ids.as_ref().map(|ids| ids.contains(id)).unwrap_or(true)
+            }
+            Self::Assignment { .. } => false,
         }
     }
 
@@ -626,16 +870,19 @@ impl StoreEvent {
         subgraph_id: &DeploymentHash,
         mods: I,
     ) -> Self {
-        let changes: Vec<_> = mods
+        let mut ids: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for op in mods {
+            use EntityModification::*;
+            match op {
+                Insert { key, .. } | Overwrite { key, .. } | Remove { key, .. } => ids
+                    .entry(key.entity_type.to_string())
+                    .or_default()
+                    .push(key.entity_id.to_string()),
+            }
+        }
+        let changes = ids
             .into_iter()
-            .map(|op| {
-                use EntityModification::*;
-                match op {
-                    Insert { key, .. } | Overwrite { key, .. } | Remove { key, .. } => {
-                        EntityChange::for_data(subgraph_id.clone(), key.clone())
-                    }
-                }
-            })
+            .map(|(entity_type, ids)| EntityChange::for_ids(subgraph_id.clone(), entity_type, ids))
             .collect();
         StoreEvent::new(changes)
     }
@@ -648,6 +895,7 @@ impl StoreEvent {
                     .map(|entity_type| EntityChange::Data {
                         subgraph_id: deployment.clone(),
                         entity_type: entity_type.to_string(),
+                        ids: None,
                     }),
             );
         Self::from_set(changes)
@@ -706,6 +954,10 @@ pub type StoreEventStreamBox =
 
 pub type UnitStream = Box<dyn futures03::Stream<Item = ()> + Unpin + Send + Sync>;
 
+/// A stream that yields whenever something changed that might change the
+/// result of a subscription
+pub type ChangeStream = futures03::stream::BoxStream<'static, ()>;
+
 impl<S> Stream for StoreEventStream<S>
 where
     S: Stream<Item = Arc<StoreEvent>, Error = ()> + Send,
@@ -902,6 +1154,85 @@ impl Display for DeploymentLocator {
     }
 }
 
+/// How much of the resources of an index node a deployment gets compared
+/// to the other deployments on the node. High priority deployments, like
+/// subgraphs that need to stay close to the chain head, prefetch more
+/// blocks, process more triggers concurrently and are exempt from
+/// deployment quotas; low priority deployments, like backfills, prefetch
+/// fewer blocks, process triggers sequentially and are held back first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeploymentPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl DeploymentPriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentPriority::Low => "low",
+            DeploymentPriority::Normal => "normal",
+            DeploymentPriority::High => "high",
+        }
+    }
+
+    /// The number of blocks the block stream should buffer ahead of
+    /// processing when deployments of normal priority buffer `size` blocks
+    pub fn buffer_size(&self, size: usize) -> usize {
+        match self {
+            DeploymentPriority::Low => (size / 4).max(1),
+            DeploymentPriority::Normal => size,
+            DeploymentPriority::High => size.saturating_mul(2),
+        }
+    }
+
+    /// The number of triggers of a block that may be processed
+    /// concurrently when deployments of normal priority process
+    /// `concurrency` triggers concurrently
+    pub fn trigger_concurrency(&self, concurrency: usize) -> usize {
+        match self {
+            DeploymentPriority::Low => 1,
+            DeploymentPriority::Normal => concurrency,
+            DeploymentPriority::High => concurrency.saturating_mul(2),
+        }
+    }
+
+    /// The number of file data source triggers that may be resolved and
+    /// handled concurrently when deployments of normal priority handle
+    /// `concurrency` of them concurrently
+    pub fn file_data_source_concurrency(&self, concurrency: usize) -> usize {
+        match self {
+            DeploymentPriority::Low => (concurrency / 2).max(1),
+            DeploymentPriority::Normal => concurrency,
+            DeploymentPriority::High => concurrency.saturating_mul(2),
+        }
+    }
+}
+
+impl Display for DeploymentPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for DeploymentPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(DeploymentPriority::Low),
+            "normal" => Ok(DeploymentPriority::Normal),
+            "high" => Ok(DeploymentPriority::High),
+            _ => Err(format!(
+                "unknown deployment priority `{}`, expected `low`, `normal` or `high`",
+                s
+            )),
+        }
+    }
+}
+
 // The type that the connection pool uses to track wait times for
 // connection checkouts
 pub type PoolWaitStats = Arc<RwLock<MovingStats>>;
@@ -1111,6 +1442,19 @@ pub trait PruneReporter: Send + 'static {
     fn finish(&mut self) {}
 }
 
+/// Callbacks for `SubgraphStore.rollup_backfill` so that callers can
+/// report progress of filling aggregations from their timeseries to users
+#[allow(unused_variables)]
+pub trait RollupBackfillReporter: Send + 'static {
+    /// Backfilling the aggregation table `table` has started; `buckets` is
+    /// the number of buckets that need to be looked at
+    fn start_table(&mut self, table: &str, buckets: usize) {}
+    /// A batch of buckets has been processed. Of the first `done` buckets,
+    /// `filled` were missing and have been filled in
+    fn backfill_batch(&mut self, table: &str, done: usize, filled: usize) {}
+    fn finish_table(&mut self, table: &str, filled: usize) {}
+}
+
 /// Select how pruning should be done
 #[derive(Clone, Copy, Debug, Display, PartialEq)]
 pub enum PruningStrategy {
@@ -1203,6 +1547,20 @@ impl PruneRequest {
         })
     }
 
+    /// Return a request like this one that only keeps `history_blocks`
+    /// blocks of history. The request still keeps enough history to cover
+    /// the reorg threshold, and never keeps more history than this request
+    pub fn with_history_blocks(&self, history_blocks: BlockNumber) -> Self {
+        let history_blocks = history_blocks
+            .max(self.reorg_threshold + 1)
+            .min(self.history_blocks);
+        Self {
+            history_blocks,
+            earliest_block: self.latest_block - history_blocks,
+            ..*self
+        }
+    }
+
     /// Determine what strategy to use for pruning
     ///
     /// We are pruning `history_pct` of the blocks from a table that has a
@@ -0,0 +1,150 @@
+//! Compare the entity changes of a replay of a block range against the
+//! changes that the original deployment made in the same blocks
+use std::collections::BTreeMap;
+
+use crate::components::store::{BlockNumber, EntityOperation};
+use crate::data::store::Entity;
+use crate::schema::EntityKey;
+
+/// What a deployment did to an entity in a block
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayChange {
+    /// The entity was not changed
+    Unchanged,
+    /// The entity was created or updated to this
+    Set(Entity),
+    /// The entity was removed
+    Removed,
+}
+
+impl ReplayChange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplayChange::Unchanged => "unchanged",
+            ReplayChange::Set(_) => "set",
+            ReplayChange::Removed => "removed",
+        }
+    }
+
+    pub fn entity(&self) -> Option<&Entity> {
+        match self {
+            ReplayChange::Set(entity) => Some(entity),
+            ReplayChange::Unchanged | ReplayChange::Removed => None,
+        }
+    }
+}
+
+/// An entity that the replay changed differently from the original
+/// deployment in `block`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayDiff {
+    pub block: BlockNumber,
+    pub key: EntityKey,
+    pub original: ReplayChange,
+    pub replay: ReplayChange,
+}
+
+fn changes_by_key(ops: Vec<EntityOperation>) -> BTreeMap<EntityKey, ReplayChange> {
+    ops.into_iter()
+        .map(|op| match op {
+            EntityOperation::Set { key, data } => (key, ReplayChange::Set(data)),
+            EntityOperation::Remove { key } => (key, ReplayChange::Removed),
+        })
+        .collect()
+}
+
+/// Compare the changes that the original deployment and the replay made
+/// in `block` and return the entities for which they differ, ordered by
+/// entity key
+pub fn diff_replay(
+    block: BlockNumber,
+    original: Vec<EntityOperation>,
+    replay: Vec<EntityOperation>,
+) -> Vec<ReplayDiff> {
+    let mut original = changes_by_key(original);
+    let replay = changes_by_key(replay);
+
+    let mut diffs = Vec::new();
+    for (key, replay) in replay {
+        let original = original.remove(&key).unwrap_or(ReplayChange::Unchanged);
+        if original != replay {
+            diffs.push(ReplayDiff {
+                block,
+                key,
+                original,
+                replay,
+            });
+        }
+    }
+    diffs.extend(original.into_iter().map(|(key, original)| ReplayDiff {
+        block,
+        key,
+        original,
+        replay: ReplayChange::Unchanged,
+    }));
+    diffs.sort_by(|a, b| a.key.cmp(&b.key));
+    diffs
+}
+
+#[cfg(test)]
+mod test {
+    use lazy_static::lazy_static;
+
+    use crate::components::store::{EntityOperation, EntityType};
+    use crate::entity;
+    use crate::prelude::DeploymentHash;
+    use crate::schema::InputSchema;
+
+    use super::{diff_replay, ReplayChange};
+
+    const GQL: &str = "type Thing @entity { id: ID!, count: Int! }";
+
+    lazy_static! {
+        static ref SCHEMA: InputSchema =
+            InputSchema::parse_latest(GQL, DeploymentHash::new("replayDiff").unwrap()).unwrap();
+        static ref THING_TYPE: EntityType = SCHEMA.entity_type("Thing").unwrap();
+    }
+
+    fn set(id: &str, count: i32) -> EntityOperation {
+        EntityOperation::Set {
+            key: THING_TYPE.parse_key(id).unwrap(),
+            data: entity! { SCHEMA => id: id, count: count },
+        }
+    }
+
+    fn remove(id: &str) -> EntityOperation {
+        EntityOperation::Remove {
+            key: THING_TYPE.parse_key(id).unwrap(),
+        }
+    }
+
+    #[test]
+    fn replay_diff() {
+        let original = vec![set("same", 1), set("changed", 1), remove("removed")];
+        let replay = vec![set("same", 1), set("changed", 2), set("added", 1)];
+
+        let diffs = diff_replay(7, original, replay);
+        let diffs: Vec<_> = diffs
+            .iter()
+            .map(|diff| {
+                assert_eq!(7, diff.block);
+                (
+                    diff.key.entity_id.to_string(),
+                    diff.original.as_str(),
+                    diff.replay.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("added".to_string(), "unchanged", "set"),
+                ("changed".to_string(), "set", "set"),
+                ("removed".to_string(), "removed", "unchanged"),
+            ],
+            diffs
+        );
+
+        assert!(diff_replay(7, vec![set("same", 1)], vec![set("same", 1)]).is_empty());
+        assert_eq!(None, ReplayChange::Removed.entity());
+    }
+}
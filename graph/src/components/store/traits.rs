@@ -6,6 +6,7 @@ use web3::types::{Address, H256};
 
 use super::*;
 use crate::blockchain::block_stream::FirehoseCursor;
+use crate::blockchain::polling_block_stream::StreamCheckpoint;
 use crate::blockchain::{BlockTime, ChainIdentifier};
 use crate::components::metrics::stopwatch::StopwatchMetrics;
 use crate::components::server::index_node::VersionInfo;
@@ -46,6 +47,52 @@ pub trait EnsLookup: Send + Sync + 'static {
     fn is_table_empty(&self) -> Result<bool, StoreError>;
 }
 
+/// Looks up keys in auxiliary datasets like ENS names or token lists that
+/// operators load into the store, for the `aux.lookup` host function
+pub trait AuxLookup: Send + Sync + 'static {
+    /// Find the value for `key` in `dataset`. All lookups of `deployment`
+    /// go to the version of `dataset` that was current when it first
+    /// looked something up in it so that they are deterministic
+    fn lookup(
+        &self,
+        deployment: &DeploymentHash,
+        dataset: &str,
+        key: &str,
+    ) -> Result<Option<String>, StoreError>;
+}
+
+/// Reads the entities of other deployments on behalf of mappings, for the
+/// `store.loadFrom` host function
+pub trait DeploymentReader: Send + Sync + 'static {
+    /// The latest block that `deployment` has written to the store, or
+    /// `None` if it has not processed any blocks yet
+    fn head(&self, deployment: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError>;
+
+    /// The earliest block for which `deployment` still has data; pruning
+    /// removes the data for blocks before it
+    fn earliest_block(&self, deployment: &DeploymentHash) -> Result<BlockNumber, StoreError>;
+
+    fn input_schema(&self, deployment: &DeploymentHash) -> Result<InputSchema, StoreError>;
+
+    /// Get the entity with `key` of `deployment` as it was at `block`
+    fn get(
+        &self,
+        deployment: &DeploymentHash,
+        key: &EntityKey,
+        block: BlockNumber,
+    ) -> Result<Option<Entity>, StoreError>;
+}
+
+/// Durable storage for the query texts of Automatic Persisted Queries. The
+/// `hash` is the hex-encoded sha256 hash of the query text
+pub trait PersistedQueryStore: Send + Sync + 'static {
+    fn find_query(&self, hash: &str) -> Result<Option<String>, StoreError>;
+
+    /// Remember `query` under `hash`. If there already is a query for
+    /// `hash`, leave it alone
+    fn add_query(&self, hash: &str, query: &str) -> Result<(), StoreError>;
+}
+
 /// An entry point for all operations that require access to the node's storage
 /// layer. It provides access to a [`BlockStore`] and a [`SubgraphStore`].
 pub trait Store: Clone + StatusStore + Send + Sync + 'static {
@@ -65,6 +112,12 @@ pub trait Store: Clone + StatusStore + Send + Sync + 'static {
 pub trait SubgraphStore: Send + Sync + 'static {
     fn ens_lookup(&self) -> Arc<dyn EnsLookup>;
 
+    fn aux_lookup(&self) -> Arc<dyn AuxLookup>;
+
+    fn deployment_reader(&self) -> Arc<dyn DeploymentReader>;
+
+    fn persisted_query_store(&self) -> Arc<dyn PersistedQueryStore>;
+
     /// Check if the store is accepting queries for the specified subgraph.
     /// May return true even if the specified subgraph is not currently assigned to an indexing
     /// node, as the store will still accept queries.
@@ -114,6 +167,18 @@ pub trait SubgraphStore: Send + Sync + 'static {
 
     fn resume_subgraph(&self, deployment: &DeploymentLocator) -> Result<(), StoreError>;
 
+    /// Set the priority of the deployment. The new priority takes effect
+    /// when the deployment is started the next time
+    fn set_priority(
+        &self,
+        deployment: &DeploymentLocator,
+        priority: DeploymentPriority,
+    ) -> Result<(), StoreError>;
+
+    /// The priority of the deployment; deployments that are not assigned
+    /// have normal priority
+    fn priority(&self, deployment: &DeploymentLocator) -> Result<DeploymentPriority, StoreError>;
+
     fn assigned_node(&self, deployment: &DeploymentLocator) -> Result<Option<NodeId>, StoreError>;
 
     /// Returns Option<(node_id,is_paused)> where `node_id` is the node that
@@ -144,6 +209,24 @@ pub trait SubgraphStore: Send + Sync + 'static {
         block_number: BlockNumber,
     ) -> Result<Vec<EntityOperation>, StoreError>;
 
+    /// Like `entity_changes_in_block`, but for each block in `[from, to]`
+    /// and for the specific deployment `deployment` instead of the active
+    /// deployment for a hash, so that the changes of copies and replays can
+    /// be looked at, too. The changes are returned in block order
+    async fn deployment_entity_changes(
+        &self,
+        deployment: &DeploymentLocator,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<(BlockNumber, Vec<EntityOperation>)>, StoreError>;
+
+    /// Return the triggers of `deployment` that were skipped because of the
+    /// error policy of their handler, ordered by block number
+    fn skipped_triggers(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Vec<SkippedTrigger>, StoreError>;
+
     /// Return the GraphQL schema supplied by the user
     fn input_schema(&self, subgraph_id: &DeploymentHash) -> Result<InputSchema, StoreError>;
 
@@ -215,6 +298,34 @@ pub trait SubgraphStore: Send + Sync + 'static {
     /// When this flag is set, indexing of the deployment should log
     /// additional diagnostic information
     fn instrument(&self, deployment: &DeploymentLocator) -> Result<bool, StoreError>;
+
+    /// Create an index on the `fields` of `entity` in the deployment `id`
+    /// with `create index concurrently`, using the index `method`, e.g.,
+    /// `btree`. Resolves once the index has been built. If the index is
+    /// invalid after the build, it is dropped and `StoreError::Canceled`
+    /// is returned
+    async fn create_entity_index(
+        &self,
+        id: &DeploymentHash,
+        entity: &str,
+        fields: Vec<String>,
+        method: &str,
+    ) -> Result<(), StoreError>;
+
+    /// Return the indexes on the table for `entity` in the deployment `id`,
+    /// including ones that are still being built
+    async fn entity_indexes(
+        &self,
+        id: &DeploymentHash,
+        entity: &str,
+    ) -> Result<Vec<EntityIndex>, StoreError>;
+
+    /// Return the statistics for the table of each entity type in the
+    /// deployment `id`, sorted by entity type
+    async fn deployment_stats(
+        &self,
+        id: &DeploymentHash,
+    ) -> Result<Vec<EntityTypeStats>, StoreError>;
 }
 
 pub trait ReadStore: Send + Sync + 'static {
@@ -324,6 +435,21 @@ pub trait WritableStore: ReadStore + DeploymentCursorTracker {
     /// Set subgraph status to failed with the given error as the cause.
     async fn fail_subgraph(&self, error: SubgraphError) -> Result<(), StoreError>;
 
+    /// Remember `block_ptr` as the last block that the deployment processed
+    /// without errors before it failed deterministically. That is the block
+    /// at which a deployment that fixes the failure can be grafted onto
+    /// this one
+    async fn set_last_healthy_block(&self, block_ptr: BlockPtr) -> Result<(), StoreError>;
+
+    /// Record triggers that were skipped because of the error policy of
+    /// their handler. The records are written right away, not with the
+    /// next batch, and recording the same trigger again has no effect.
+    /// Reverting a block removes the records for it
+    async fn record_skipped_triggers(
+        &self,
+        triggers: Vec<SkippedTrigger>,
+    ) -> Result<(), StoreError>;
+
     async fn supports_proof_of_indexing(&self) -> Result<bool, StoreError>;
 
     /// Transact the entity changes from a single block atomically into the store, and update the
@@ -358,6 +484,19 @@ pub trait WritableStore: ReadStore + DeploymentCursorTracker {
 
     fn unassign_subgraph(&self) -> Result<(), StoreError>;
 
+    /// Drop the indexes on entity tables that indexing does not need so
+    /// that writes are faster while the deployment is far behind the
+    /// chain head. The store remembers which indexes it dropped so that
+    /// `restore_deferred_indexes` can recreate them, even after a restart
+    async fn defer_indexes(&self) -> Result<(), StoreError>;
+
+    /// Recreate the indexes that `defer_indexes` dropped. The indexes are
+    /// created in the background, and this method returns right away with
+    /// `true` once all of them have been recreated. Callers should call it
+    /// again until it returns `true`; if recreating indexes failed, that
+    /// starts over
+    async fn restore_deferred_indexes(&self) -> Result<bool, StoreError>;
+
     /// Load the dynamic data sources for the given deployment
     async fn load_dynamic_data_sources(
         &self,
@@ -367,6 +506,26 @@ pub trait WritableStore: ReadStore + DeploymentCursorTracker {
     /// The maximum assigned causality region. Any higher number is therefore free to be assigned.
     async fn causality_region_curr_val(&self) -> Result<Option<CausalityRegion>, StoreError>;
 
+    /// Return the keys of the `count` entities of type `entity_type` that
+    /// were written most recently. This is only a hint for warming up the
+    /// entity cache; it does not take writes that are still queued into
+    /// account, and the entities should be loaded with `get_many`
+    async fn recent_entity_keys(
+        &self,
+        entity_type: &EntityType,
+        count: usize,
+    ) -> Result<Vec<EntityKey>, StoreError>;
+
+    /// Save the keys of the entities in the entity cache so that they can
+    /// be loaded into the cache again when the subgraph is restarted. This
+    /// replaces the previous checkpoint
+    async fn checkpoint_entity_cache(&self, keys: Vec<EntityKey>) -> Result<(), StoreError>;
+
+    /// Return the keys saved by the last call to `checkpoint_entity_cache`.
+    /// Like `recent_entity_keys`, this is only a hint and the entities
+    /// should be loaded with `get_many`
+    fn entity_cache_checkpoint(&self) -> Result<Vec<EntityKey>, StoreError>;
+
     /// Report the name of the shard in which the subgraph is stored. This
     /// should only be used for reporting and monitoring
     fn shard(&self) -> &str;
@@ -472,6 +631,31 @@ pub trait ChainStore: Send + Sync + 'static {
         cursor: String,
     ) -> Result<(), Error>;
 
+    /// Get the latest block that the chain considers finalized, if the
+    /// chain has a notion of finality and we know about a finalized block.
+    /// Finalized blocks can never be reverted.
+    async fn finalized_block_ptr(self: Arc<Self>) -> Result<Option<BlockPtr>, Error>;
+
+    /// Remember that `ptr` is finalized and remove all other blocks with
+    /// the same number from the store. Only updates the finalized block if
+    /// `ptr` is later than the current one.
+    async fn set_finalized_block(self: Arc<Self>, ptr: BlockPtr) -> Result<(), Error>;
+
+    /// Get the range of blocks that the polling block stream for
+    /// `deployment` scanned last, if it recorded one
+    async fn stream_checkpoint(
+        self: Arc<Self>,
+        deployment: &DeploymentHash,
+    ) -> Result<Option<StreamCheckpoint>, Error>;
+
+    /// Remember the range of blocks that the polling block stream for
+    /// `deployment` scanned last, replacing any earlier checkpoint
+    async fn set_stream_checkpoint(
+        self: Arc<Self>,
+        deployment: &DeploymentHash,
+        checkpoint: StreamCheckpoint,
+    ) -> Result<(), Error>;
+
     /// Returns the blocks present in the store.
     async fn blocks(
         self: Arc<Self>,
@@ -593,6 +777,17 @@ pub trait QueryStore: Send + Sync {
         query: EntityQuery,
     ) -> Result<(Vec<QueryObject>, Trace), QueryExecutionError>;
 
+    /// Compute `aggregates` over the entities that `query` matches. The
+    /// `order` and `range` of the query are ignored. The values are
+    /// returned in the same order as `aggregates`; `Count` is an `Int8`,
+    /// all other aggregates are a `BigDecimal`, or `Null` if no entities
+    /// matched
+    fn aggregate_query_values(
+        &self,
+        query: EntityQuery,
+        aggregates: &[EntityAggregate],
+    ) -> Result<(Vec<Value>, Trace), QueryExecutionError>;
+
     async fn is_deployment_synced(&self) -> Result<bool, Error>;
 
     async fn block_ptr(&self) -> Result<Option<BlockPtr>, StoreError>;
@@ -13,7 +13,10 @@ use crate::{
     util::cache_weight::CacheWeight,
 };
 
-use super::{BlockNumber, EntityKey, EntityType, StoreError, StoreEvent, StoredDynamicDataSource};
+use super::{
+    BlockNumber, EntityChange, EntityKey, EntityType, StoreError, StoreEvent,
+    StoredDynamicDataSource,
+};
 
 /// A data structure similar to `EntityModification`, but tagged with a
 /// block. We might eventually replace `EntityModification` with this, but
@@ -341,7 +344,7 @@ impl RowGroup {
         self.append_row(emod)
     }
 
-    fn row_count(&self) -> usize {
+    pub fn row_count(&self) -> usize {
         self.rows.len()
     }
 
@@ -781,13 +784,19 @@ impl Batch {
 
     /// Generate a store event for all the changes that this batch makes
     pub fn store_event(&self, deployment: &DeploymentHash) -> StoreEvent {
-        let entity_types = HashSet::from_iter(
-            self.mods
-                .groups
-                .iter()
-                .map(|group| group.entity_type.clone()),
-        );
-        StoreEvent::from_types(deployment, entity_types)
+        let changes = self
+            .mods
+            .groups
+            .iter()
+            .map(|group| {
+                EntityChange::for_ids(
+                    deployment.clone(),
+                    group.entity_type.to_string(),
+                    group.rows.iter().map(|row| row.id().to_string()),
+                )
+            })
+            .collect();
+        StoreEvent::new(changes)
     }
 
     pub fn groups<'a>(&'a self) -> impl Iterator<Item = &'a RowGroup> {
@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::prelude::{BlockPtr, DeploymentHash};
+
+/// The deployments whose entities the mappings of a subgraph read with
+/// `store.loadFrom`. Before it processes a block, the subgraph runner waits
+/// until all of them have processed that block, too, and checks that they
+/// processed the same block and not a block from a different fork
+#[derive(Debug, Default)]
+pub struct DeploymentDependencies {
+    /// Maps each deployment to the last block for which the runner
+    /// checked that the deployment is on the same chain as the subgraph
+    deployments: Mutex<BTreeMap<DeploymentHash, Option<BlockPtr>>>,
+}
+
+impl DeploymentDependencies {
+    pub fn add(&self, deployment: &DeploymentHash) {
+        let mut deployments = self.deployments.lock().unwrap();
+        if !deployments.contains_key(deployment) {
+            deployments.insert(deployment.clone(), None);
+        }
+    }
+
+    pub fn deployments(&self) -> Vec<DeploymentHash> {
+        self.deployments.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Remember that `deployment` processed the same block as the subgraph
+    /// at `block`
+    pub fn set_checked(&self, deployment: &DeploymentHash, block: BlockPtr) {
+        self.deployments
+            .lock()
+            .unwrap()
+            .insert(deployment.clone(), Some(block));
+    }
+
+    /// Whether it is known that `deployment` processed `block` and not a
+    /// different block with the same number
+    pub fn is_checked(&self, deployment: &DeploymentHash, block: &BlockPtr) -> bool {
+        matches!(
+            self.deployments.lock().unwrap().get(deployment),
+            Some(Some(checked)) if checked == block
+        )
+    }
+}
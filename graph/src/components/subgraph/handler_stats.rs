@@ -0,0 +1,252 @@
+//! Statistics about the handlers of the deployments that this node
+//! indexes, for the `handlerStats` of the index node API.
+//!
+//! Runtime hosts record every handler invocation in [`HANDLER_STATS`];
+//! the statistics cover a sliding window of
+//! `GRAPH_HANDLER_STATS_WINDOW` seconds and are only kept in memory, so
+//! that they reflect what the handlers did recently on this node.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+
+use crate::env::ENV_VARS;
+
+/// The number of buckets that the sliding window is divided into. Old
+/// invocations drop out of the window one bucket at a time
+const BUCKETS: u32 = 10;
+
+lazy_static! {
+    /// The handler statistics of all deployments that this node indexes
+    pub static ref HANDLER_STATS: HandlerStatsRegistry = HandlerStatsRegistry::default();
+}
+
+/// The statistics of one handler over the sliding window
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HandlerStats {
+    pub data_source: String,
+    pub handler: String,
+    pub invocations: u64,
+    /// The total time spent in the handler, including host functions
+    pub execution_time: Duration,
+    /// The total number of entity sets and removes that the handler did
+    pub entity_ops: u64,
+    /// The number of invocations that failed
+    pub errors: u64,
+}
+
+impl HandlerStats {
+    pub fn avg_entity_ops(&self) -> f64 {
+        if self.invocations == 0 {
+            0.0
+        } else {
+            self.entity_ops as f64 / self.invocations as f64
+        }
+    }
+
+    fn add(&mut self, other: &HandlerStats) {
+        self.invocations += other.invocations;
+        self.execution_time += other.execution_time;
+        self.entity_ops += other.entity_ops;
+        self.errors += other.errors;
+    }
+}
+
+struct Bucket {
+    start: Instant,
+    // Keyed by data source and handler name
+    stats: HashMap<(String, String), HandlerStats>,
+}
+
+/// The handler statistics of one deployment
+pub struct DeploymentHandlerStats {
+    window: Duration,
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+impl DeploymentHandlerStats {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Remove buckets that are entirely outside of the window
+    fn expire(&self, buckets: &mut VecDeque<Bucket>, now: Instant) {
+        let bucket_len = self.window / BUCKETS;
+        while let Some(bucket) = buckets.front() {
+            if bucket.start + bucket_len + self.window <= now {
+                buckets.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Record an invocation of `handler` in `data_source` that took
+    /// `elapsed` and did `entity_ops` entity operations
+    pub fn record(
+        &self,
+        data_source: &str,
+        handler: &str,
+        elapsed: Duration,
+        entity_ops: usize,
+        failed: bool,
+    ) {
+        self.record_at(
+            Instant::now(),
+            data_source,
+            handler,
+            elapsed,
+            entity_ops,
+            failed,
+        )
+    }
+
+    fn record_at(
+        &self,
+        now: Instant,
+        data_source: &str,
+        handler: &str,
+        elapsed: Duration,
+        entity_ops: usize,
+        failed: bool,
+    ) {
+        let bucket_len = self.window / BUCKETS;
+        let mut buckets = self.buckets.lock();
+        self.expire(&mut buckets, now);
+        let current = buckets
+            .back()
+            .map(|bucket| bucket.start + bucket_len > now)
+            .unwrap_or(false);
+        if !current {
+            buckets.push_back(Bucket {
+                start: now,
+                stats: HashMap::new(),
+            });
+        }
+        let bucket = buckets.back_mut().unwrap();
+        let stats = bucket
+            .stats
+            .entry((data_source.to_string(), handler.to_string()))
+            .or_insert_with(|| HandlerStats {
+                data_source: data_source.to_string(),
+                handler: handler.to_string(),
+                ..Default::default()
+            });
+        stats.invocations += 1;
+        stats.execution_time += elapsed;
+        stats.entity_ops += entity_ops as u64;
+        if failed {
+            stats.errors += 1;
+        }
+    }
+
+    /// The statistics for all handlers that were invoked during the
+    /// window, sorted by data source and handler
+    pub fn snapshot(&self) -> Vec<HandlerStats> {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn snapshot_at(&self, now: Instant) -> Vec<HandlerStats> {
+        let mut buckets = self.buckets.lock();
+        self.expire(&mut buckets, now);
+        let mut totals: BTreeMap<&(String, String), HandlerStats> = BTreeMap::new();
+        for bucket in buckets.iter() {
+            for (key, stats) in &bucket.stats {
+                totals
+                    .entry(key)
+                    .or_insert_with(|| HandlerStats {
+                        data_source: stats.data_source.clone(),
+                        handler: stats.handler.clone(),
+                        ..Default::default()
+                    })
+                    .add(stats);
+            }
+        }
+        totals.into_values().collect()
+    }
+}
+
+/// The handler statistics of all deployments, keyed by deployment hash
+#[derive(Default)]
+pub struct HandlerStatsRegistry {
+    deployments: RwLock<HashMap<String, Arc<DeploymentHandlerStats>>>,
+}
+
+impl HandlerStatsRegistry {
+    /// Return the statistics for `deployment`, creating them if needed
+    pub fn for_deployment(&self, deployment: &str) -> Arc<DeploymentHandlerStats> {
+        if let Some(stats) = self.deployments.read().get(deployment) {
+            return stats.clone();
+        }
+        self.deployments
+            .write()
+            .entry(deployment.to_string())
+            .or_insert_with(|| {
+                Arc::new(DeploymentHandlerStats::new(
+                    ENV_VARS.mappings.handler_stats_window,
+                ))
+            })
+            .clone()
+    }
+
+    /// The statistics for `deployment`; empty if it is not indexed on
+    /// this node
+    pub fn snapshot(&self, deployment: &str) -> Vec<HandlerStats> {
+        self.deployments
+            .read()
+            .get(deployment)
+            .map(|stats| stats.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Forget the statistics for `deployment`, e.g., because it stopped
+    pub fn remove(&self, deployment: &str) {
+        self.deployments.write().remove(deployment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sliding_window() {
+        let stats = DeploymentHandlerStats::new(Duration::from_secs(100));
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+
+        stats.record_at(start, "Token", "handleTransfer", ms(10), 2, false);
+        stats.record_at(start, "Token", "handleTransfer", ms(30), 4, true);
+        stats.record_at(
+            start + ms(50_000),
+            "Token",
+            "handleApproval",
+            ms(5),
+            1,
+            false,
+        );
+
+        let snapshot = stats.snapshot_at(start + ms(60_000));
+        assert_eq!(2, snapshot.len());
+        assert_eq!("handleApproval", snapshot[0].handler);
+        let transfer = &snapshot[1];
+        assert_eq!(2, transfer.invocations);
+        assert_eq!(ms(40), transfer.execution_time);
+        assert_eq!(3.0, transfer.avg_entity_ops());
+        assert_eq!(1, transfer.errors);
+
+        // The first invocations have left the window
+        let snapshot = stats.snapshot_at(start + ms(115_000));
+        assert_eq!(1, snapshot.len());
+        assert_eq!("handleApproval", snapshot[0].handler);
+
+        let snapshot = stats.snapshot_at(start + ms(200_000));
+        assert!(snapshot.is_empty());
+    }
+}
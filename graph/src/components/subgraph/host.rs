@@ -1,6 +1,7 @@
 use std::cmp::PartialEq;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::Error;
 use async_trait::async_trait;
@@ -9,6 +10,8 @@ use futures01::sync::mpsc;
 use crate::blockchain::BlockTime;
 use crate::components::metrics::gas::GasMetrics;
 use crate::components::store::SubgraphFork;
+use crate::components::subgraph::{DeploymentHandlerStats, HANDLER_STATS};
+use crate::data::subgraph::HandlerLimits;
 use crate::data_source::{
     DataSource, DataSourceTemplate, MappingTrigger, TriggerData, TriggerWithHandler,
 };
@@ -116,6 +119,7 @@ pub struct HostMetrics {
     eth_call_execution_time: Box<HistogramVec>,
     pub gas_metrics: GasMetrics,
     pub stopwatch: StopwatchMetrics,
+    handler_stats: Arc<DeploymentHandlerStats>,
 }
 
 impl HostMetrics {
@@ -159,6 +163,7 @@ impl HostMetrics {
             stopwatch,
             gas_metrics,
             eth_call_execution_time,
+            handler_stats: HANDLER_STATS.for_deployment(subgraph),
         }
     }
 
@@ -168,6 +173,20 @@ impl HostMetrics {
             .observe(duration);
     }
 
+    /// Record an invocation of `handler` in the handler statistics of the
+    /// index node API
+    pub fn record_handler_stats(
+        &self,
+        data_source: &str,
+        handler: &str,
+        elapsed: Duration,
+        entity_ops: usize,
+        failed: bool,
+    ) {
+        self.handler_stats
+            .record(data_source, handler, elapsed, entity_ops, failed);
+    }
+
     pub fn observe_host_fn_execution_time(&self, duration: f64, fn_name: &str) {
         self.host_fn_execution_time
             .with_label_values(&[fn_name][..])
@@ -228,11 +247,16 @@ pub trait RuntimeHostBuilder<C: Blockchain>: Clone + Send + Sync + 'static {
     ) -> Result<Self::Host, Error>;
 
     /// Spawn a mapping and return a channel for mapping requests. The sender should be able to be
-    /// cached and shared among mappings that use the same wasm file.
+    /// cached and shared among mappings that use the same wasm file. The `handler_limits` are
+    /// the limits from the manifest, keyed by handler name, and `fuel` is the fuel that each
+    /// invocation of a handler may use, if handlers are metered with fuel. Requests are served by a pool of `workers` threads.
     fn spawn_mapping(
         raw_module: &[u8],
         logger: Logger,
         subgraph_id: DeploymentHash,
         metrics: Arc<HostMetrics>,
+        handler_limits: Arc<BTreeMap<String, HandlerLimits>>,
+        fuel: Option<u64>,
+        workers: usize,
     ) -> Result<mpsc::Sender<Self::Req>, anyhow::Error>;
 }
@@ -1,10 +1,12 @@
+use std::collections::BTreeSet;
+
 use crate::{
     blockchain::{Blockchain, DataSourceTemplate as _},
     components::{
         metrics::block_state::BlockStateMetrics,
         store::{EntityLfuCache, ReadStore, StoredDynamicDataSource},
     },
-    data::subgraph::schema::SubgraphError,
+    data::subgraph::schema::{SkippedTrigger, SubgraphError},
     data_source::{DataSourceTemplate, DataSourceTemplateInfo},
     prelude::*,
 };
@@ -66,6 +68,9 @@ pub struct InstanceDSTemplateInfo {
 pub struct BlockState {
     pub entity_cache: EntityCache,
     pub deterministic_errors: Vec<SubgraphError>,
+    // Triggers whose deterministic errors were skipped because of the
+    // handler's error policy.
+    pub skipped_triggers: Vec<SkippedTrigger>,
     created_data_sources: Vec<InstanceDSTemplateInfo>,
 
     // Data sources to be transacted into the store.
@@ -88,6 +93,7 @@ impl BlockState {
         BlockState {
             entity_cache: EntityCache::with_current(Arc::new(store), lfu_cache),
             deterministic_errors: Vec::new(),
+            skipped_triggers: Vec::new(),
             created_data_sources: Vec::new(),
             persisted_data_sources: Vec::new(),
             handler_created_data_sources: Vec::new(),
@@ -96,6 +102,30 @@ impl BlockState {
             metrics: BlockStateMetrics::new(),
         }
     }
+
+    /// Make `count` states in which triggers can be processed concurrently
+    /// against this state. The entity caches of the new states are forks
+    /// of the entity cache of this state; the result of processing a
+    /// trigger in one of them can be merged back with `extend`
+    pub fn forks(&self, count: usize) -> Vec<BlockState> {
+        assert!(!self.in_handler);
+
+        self.entity_cache
+            .forks(count)
+            .into_iter()
+            .map(|entity_cache| BlockState {
+                entity_cache,
+                deterministic_errors: Vec::new(),
+                skipped_triggers: Vec::new(),
+                created_data_sources: Vec::new(),
+                persisted_data_sources: Vec::new(),
+                handler_created_data_sources: Vec::new(),
+                processed_data_sources: Vec::new(),
+                in_handler: false,
+                metrics: BlockStateMetrics::new(),
+            })
+            .collect()
+    }
 }
 
 impl BlockState {
@@ -105,6 +135,7 @@ impl BlockState {
         let BlockState {
             entity_cache,
             deterministic_errors,
+            skipped_triggers,
             created_data_sources,
             persisted_data_sources,
             handler_created_data_sources,
@@ -118,6 +149,7 @@ impl BlockState {
             false => created_data_sources.extend(other.created_data_sources),
         }
         deterministic_errors.extend(other.deterministic_errors);
+        skipped_triggers.extend(other.skipped_triggers);
         entity_cache.extend(other.entity_cache);
         processed_data_sources.extend(other.processed_data_sources);
         persisted_data_sources.extend(other.persisted_data_sources);
@@ -128,6 +160,31 @@ impl BlockState {
         !self.deterministic_errors.is_empty()
     }
 
+    /// Turn the deterministic errors from the `first` one on that happened
+    /// in one of `handlers` into skipped triggers for `trigger`. The
+    /// changes the handlers made were already discarded when the errors
+    /// happened
+    pub fn skip_errors(&mut self, first: usize, handlers: &BTreeSet<String>, trigger: &str) {
+        if handlers.is_empty() || first >= self.deterministic_errors.len() {
+            return;
+        }
+
+        let errors = self.deterministic_errors.split_off(first);
+        for error in errors {
+            match (&error.handler, &error.block_ptr) {
+                (Some(handler), Some(block_ptr)) if handlers.contains(handler) => {
+                    self.skipped_triggers.push(SkippedTrigger {
+                        block_ptr: block_ptr.clone(),
+                        handler: handler.clone(),
+                        trigger: trigger.to_string(),
+                        message: error.message,
+                    })
+                }
+                _ => self.deterministic_errors.push(error),
+            }
+        }
+    }
+
     pub fn has_created_data_sources(&self) -> bool {
         assert!(!self.in_handler);
         !self.created_data_sources.is_empty()
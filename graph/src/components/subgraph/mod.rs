@@ -1,3 +1,5 @@
+mod dependencies;
+mod handler_stats;
 mod host;
 mod instance;
 mod instance_manager;
@@ -8,6 +10,10 @@ mod settings;
 
 pub use crate::prelude::Entity;
 
+pub use self::dependencies::DeploymentDependencies;
+pub use self::handler_stats::{
+    DeploymentHandlerStats, HandlerStats, HandlerStatsRegistry, HANDLER_STATS,
+};
 pub use self::host::{HostMetrics, MappingError, RuntimeHost, RuntimeHostBuilder};
 pub use self::instance::{BlockState, InstanceDSTemplate, InstanceDSTemplateInfo};
 pub use self::instance_manager::SubgraphInstanceManager;
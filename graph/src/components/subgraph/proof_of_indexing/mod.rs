@@ -82,18 +82,31 @@ mod tests {
 
             for block_i in 0..block_count {
                 let mut stream = ProofOfIndexing::new(block_i.try_into().unwrap(), version);
+                // Replaying recorded events must give the same digest as
+                // writing them directly
+                let mut recorder = ProofOfIndexing::recorder(block_i.try_into().unwrap(), version);
 
                 for (name, region) in case.data.causality_regions.iter() {
                     let block = &region.blocks[block_i];
 
                     for evt in block.events.iter() {
                         stream.write(&logger, name, evt);
+                        recorder.write(&logger, name, evt);
                     }
                 }
 
+                let mut replayed = ProofOfIndexing::new(block_i.try_into().unwrap(), version);
+                replayed.replay(&logger, &mut recorder);
+                let mut replayed = replayed.take();
+
                 for (name, region) in stream.take() {
                     let prev = db.get(&name);
+                    let replayed = replayed
+                        .remove(&name)
+                        .expect("replay has all causality regions")
+                        .pause(prev.map(|v| &v[..]));
                     let update = region.pause(prev.map(|v| &v[..]));
+                    assert_eq!(update, replayed, "case: {}", case.name);
                     db.insert(name, update);
                 }
             }
@@ -5,7 +5,7 @@
 use super::{ProofOfIndexingEvent, ProofOfIndexingVersion};
 use crate::{
     blockchain::BlockPtr,
-    data::store::Id,
+    data::store::{Entity, Id},
     prelude::{debug, BlockNumber, DeploymentHash, Logger, ENV_VARS},
     util::stable_hash_glue::AsBytes,
 };
@@ -168,6 +168,59 @@ pub struct ProofOfIndexing {
     /// state with other data sources. This may also give us some freedom to change
     /// the order of triggers in the future.
     per_causality_region: HashMap<Id, BlockEventStream>,
+    /// For a `ProofOfIndexing` made with `recorder`, the events in the
+    /// order in which they were written
+    recorded: Option<Vec<RecordedEvent>>,
+}
+
+/// An owned copy of the calls made to a recording `ProofOfIndexing`
+enum RecordedEvent {
+    StartHandler(String),
+    DeterministicError(String),
+    RemoveEntity {
+        causality_region: String,
+        entity_type: String,
+        id: String,
+    },
+    SetEntity {
+        causality_region: String,
+        entity_type: String,
+        id: String,
+        data: Entity,
+    },
+    Redacted {
+        causality_region: String,
+        redacted_events: u64,
+    },
+}
+
+impl RecordedEvent {
+    fn new(causality_region: &str, event: &ProofOfIndexingEvent<'_>) -> Self {
+        let causality_region = causality_region.to_owned();
+        match event {
+            ProofOfIndexingEvent::RemoveEntity { entity_type, id } => RecordedEvent::RemoveEntity {
+                causality_region,
+                entity_type: entity_type.to_string(),
+                id: id.to_string(),
+            },
+            ProofOfIndexingEvent::SetEntity {
+                entity_type,
+                id,
+                data,
+            } => RecordedEvent::SetEntity {
+                causality_region,
+                entity_type: entity_type.to_string(),
+                id: id.to_string(),
+                data: (*data).clone(),
+            },
+            ProofOfIndexingEvent::DeterministicError { redacted_events } => {
+                RecordedEvent::Redacted {
+                    causality_region,
+                    redacted_events: *redacted_events,
+                }
+            }
+        }
+    }
 }
 
 impl fmt::Debug for ProofOfIndexing {
@@ -182,12 +235,32 @@ impl ProofOfIndexing {
             version,
             block_number,
             per_causality_region: HashMap::new(),
+            recorded: None,
+        }
+    }
+
+    /// A `ProofOfIndexing` that remembers the events written to it instead
+    /// of hashing them. This makes it possible to run handlers for
+    /// different triggers concurrently and later `replay` their events in
+    /// trigger order so that the digest is the same as if the handlers had
+    /// run one after the other.
+    pub fn recorder(block_number: BlockNumber, version: ProofOfIndexingVersion) -> Self {
+        Self {
+            recorded: Some(Vec::new()),
+            ..Self::new(block_number, version)
         }
     }
 }
 
 impl ProofOfIndexing {
     pub fn write_deterministic_error(&mut self, logger: &Logger, causality_region: &str) {
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(RecordedEvent::DeterministicError(
+                causality_region.to_owned(),
+            ));
+            return;
+        }
+
         let redacted_events = self.with_causality_region(causality_region, |entry| {
             entry.vec_length - entry.handler_start
         });
@@ -206,6 +279,11 @@ impl ProofOfIndexing {
         causality_region: &str,
         event: &ProofOfIndexingEvent<'_>,
     ) {
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(RecordedEvent::new(causality_region, event));
+            return;
+        }
+
         if ENV_VARS.log_poi_events {
             debug!(
                 logger,
@@ -220,6 +298,11 @@ impl ProofOfIndexing {
     }
 
     pub fn start_handler(&mut self, causality_region: &str) {
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(RecordedEvent::StartHandler(causality_region.to_owned()));
+            return;
+        }
+
         self.with_causality_region(causality_region, |entry| entry.start_handler())
     }
 
@@ -239,6 +322,61 @@ impl ProofOfIndexing {
         }
     }
 
+    /// Write the events remembered by a `ProofOfIndexing` made with
+    /// `recorder` into this one, in the order in which they were recorded,
+    /// and clear them from `recorder`
+    pub fn replay(&mut self, logger: &Logger, recorder: &mut ProofOfIndexing) {
+        let events = recorder
+            .recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        for event in events {
+            match event {
+                RecordedEvent::StartHandler(causality_region) => {
+                    self.start_handler(&causality_region)
+                }
+                RecordedEvent::DeterministicError(causality_region) => {
+                    self.write_deterministic_error(logger, &causality_region)
+                }
+                RecordedEvent::RemoveEntity {
+                    causality_region,
+                    entity_type,
+                    id,
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::RemoveEntity {
+                        entity_type: &entity_type,
+                        id: &id,
+                    },
+                ),
+                RecordedEvent::SetEntity {
+                    causality_region,
+                    entity_type,
+                    id,
+                    data,
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::SetEntity {
+                        entity_type: &entity_type,
+                        id: &id,
+                        data: &data,
+                    },
+                ),
+                RecordedEvent::Redacted {
+                    causality_region,
+                    redacted_events,
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::DeterministicError { redacted_events },
+                ),
+            }
+        }
+    }
+
     pub fn take(self) -> HashMap<Id, BlockEventStream> {
         self.per_causality_region
     }
@@ -2,7 +2,10 @@ use std::str::FromStr;
 
 use async_trait::async_trait;
 
-use crate::{components::store::DeploymentLocator, prelude::*};
+use crate::{
+    components::store::{DeploymentLocator, DeploymentPriority},
+    prelude::*,
+};
 
 #[derive(Clone, Copy, Debug)]
 pub enum SubgraphVersionSwitchingMode {
@@ -58,4 +61,10 @@ pub trait SubgraphRegistrar: Send + Sync + 'static {
     async fn pause_subgraph(&self, hash: &DeploymentHash) -> Result<(), SubgraphRegistrarError>;
 
     async fn resume_subgraph(&self, hash: &DeploymentHash) -> Result<(), SubgraphRegistrarError>;
+
+    async fn set_subgraph_priority(
+        &self,
+        hash: &DeploymentHash,
+        priority: DeploymentPriority,
+    ) -> Result<(), SubgraphRegistrarError>;
 }
@@ -54,6 +54,8 @@ pub trait DocumentExt {
 
     fn get_root_subscription_type(&self) -> Option<&ObjectType>;
 
+    fn get_root_mutation_type(&self) -> Option<&ObjectType>;
+
     fn object_or_interface(&self, name: &str) -> Option<ObjectOrInterface<'_>>;
 
     fn get_named_type(&self, name: &str) -> Option<&TypeDefinition>;
@@ -174,6 +176,15 @@ impl DocumentExt for Document {
             .next()
     }
 
+    fn get_root_mutation_type(&self) -> Option<&ObjectType> {
+        self.definitions.iter().find_map(|d| match d {
+            Definition::TypeDefinition(TypeDefinition::Object(t)) if t.name == "Mutation" => {
+                Some(t)
+            }
+            _ => None,
+        })
+    }
+
     fn object_or_interface(&self, name: &str) -> Option<ObjectOrInterface<'_>> {
         match self.get_named_type(name) {
             Some(TypeDefinition::Object(t)) => Some(t.into()),
@@ -0,0 +1,117 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::prelude::{anyhow, BlockHash, BlockNumber, BlockPtr, DeploymentHash};
+
+/// The name of the HTTP header in which we send the consistency token for
+/// a response, and in which clients send it back with later requests
+pub const CONSISTENCY_TOKEN_HEADER: &str = "Graph-Consistency-Token";
+
+/// A token that identifies the block at which a query against a deployment
+/// was answered. When a client sends it back with a later query, that
+/// query is answered at that block or a later one, which gives clients
+/// read-your-writes consistency even when their queries are answered by
+/// different nodes or replicas.
+///
+/// The token is formatted as `<deployment>:<block number>:<block hash>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsistencyToken {
+    pub deployment: DeploymentHash,
+    pub block: BlockPtr,
+}
+
+impl ConsistencyToken {
+    pub fn new(deployment: DeploymentHash, block: BlockPtr) -> Self {
+        ConsistencyToken { deployment, block }
+    }
+
+    /// The block that a query against `deployment` must at least be
+    /// answered at. Tokens for other deployments do not constrain the
+    /// query
+    pub fn min_block(&self, deployment: &DeploymentHash) -> Option<BlockNumber> {
+        if &self.deployment == deployment {
+            Some(self.block.number)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ConsistencyToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.deployment,
+            self.block.number,
+            self.block.hash_hex()
+        )
+    }
+}
+
+impl FromStr for ConsistencyToken {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(':');
+        let (Some(deployment), Some(number), Some(hash), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow!("invalid consistency token `{}`", s));
+        };
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(|_| anyhow!("invalid deployment in consistency token `{}`", s))?;
+        let number = number
+            .parse::<BlockNumber>()
+            .map_err(|_| anyhow!("invalid block number in consistency token `{}`", s))?;
+        if number < 0 {
+            return Err(anyhow!("invalid block number in consistency token `{}`", s));
+        }
+        let hash = BlockHash::from_str(hash)
+            .map_err(|_| anyhow!("invalid block hash in consistency token `{}`", s))?;
+        Ok(ConsistencyToken::new(
+            deployment,
+            BlockPtr::new(hash, number),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let deployment = DeploymentHash::new("QmTestDeployment").unwrap();
+        let hash = BlockHash::from_str(
+            "0x8511fa04b64657581e3f00e14543c1d522d5d7e771b54aa3060b662ade47da13",
+        )
+        .unwrap();
+        let token = ConsistencyToken::new(deployment.clone(), BlockPtr::new(hash, 42));
+        let text = token.to_string();
+        assert_eq!(
+            "QmTestDeployment:42:8511fa04b64657581e3f00e14543c1d522d5d7e771b54aa3060b662ade47da13",
+            text
+        );
+        assert_eq!(token, ConsistencyToken::from_str(&text).unwrap());
+        assert_eq!(Some(42), token.min_block(&deployment));
+
+        let other = DeploymentHash::new("QmOtherDeployment").unwrap();
+        assert_eq!(None, token.min_block(&other));
+    }
+
+    #[test]
+    fn invalid() {
+        for text in [
+            "",
+            "QmTestDeployment",
+            "QmTestDeployment:42",
+            "QmTestDeployment:x:00",
+            "QmTestDeployment:-1:00",
+            "QmTestDeployment:42:xyz",
+            "QmTestDeployment:42:00:00",
+        ] {
+            assert!(ConsistencyToken::from_str(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Configuration for query cost budgeting. The cost of a query is the sum
+//! of the weights of all the fields it selects, where the cost of a list
+//! field with a selection set is multiplied by its `first` argument
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+use serde::Deserialize;
+
+use crate::anyhow::{self, anyhow};
+use crate::prelude::{r, DeploymentHash};
+
+#[derive(Clone, Debug, Deserialize)]
+struct FieldWeight {
+    /// The field in the form `Type.field`
+    field: String,
+    weight: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct DeploymentBudget {
+    hash: String,
+    max_cost: u64,
+}
+
+fn default_object_weight() -> u64 {
+    1
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct RawCostModel {
+    #[serde(default)]
+    max_cost: Option<u64>,
+    #[serde(default = "default_object_weight")]
+    object_weight: u64,
+    #[serde(default)]
+    scalar_weight: u64,
+    #[serde(default, alias = "weight")]
+    weights: Vec<FieldWeight>,
+    #[serde(default, alias = "deployment")]
+    deployments: Vec<DeploymentBudget>,
+}
+
+/// The weights and budgets used to compute the cost of a query and to
+/// decide whether it is too expensive to run. It is read from the file
+/// given with `--query-cost-config`
+#[derive(Clone, Debug)]
+pub struct QueryCostModel {
+    /// The budget for deployments that do not have their own budget
+    max_cost: Option<u64>,
+    /// The weight of fields with a selection set that do not have an
+    /// explicit weight
+    object_weight: u64,
+    /// The weight of scalar fields that do not have an explicit weight
+    scalar_weight: u64,
+    /// Explicit weights, keyed by type name and field name
+    weights: HashMap<(String, String), u64>,
+    deployments: HashMap<DeploymentHash, u64>,
+}
+
+impl Default for QueryCostModel {
+    fn default() -> Self {
+        Self {
+            max_cost: None,
+            object_weight: default_object_weight(),
+            scalar_weight: 0,
+            weights: HashMap::new(),
+            deployments: HashMap::new(),
+        }
+    }
+}
+
+impl QueryCostModel {
+    pub fn from_file(path: &str) -> Result<Self, anyhow::Error> {
+        Self::from_str(&read_to_string(path)?)
+    }
+
+    pub fn from_str(toml: &str) -> Result<Self, anyhow::Error> {
+        let raw = toml::from_str::<RawCostModel>(toml)?;
+
+        let mut weights = HashMap::new();
+        for FieldWeight { field, weight } in raw.weights {
+            let (type_name, field_name) = field
+                .split_once('.')
+                .ok_or_else(|| anyhow!("field `{}` must have the form `Type.field`", field))?;
+            weights.insert((type_name.to_string(), field_name.to_string()), weight);
+        }
+
+        let mut deployments = HashMap::new();
+        for DeploymentBudget { hash, max_cost } in raw.deployments {
+            let hash = DeploymentHash::new(hash)
+                .map_err(|hash| anyhow!("invalid deployment hash `{}`", hash))?;
+            deployments.insert(hash, max_cost);
+        }
+
+        Ok(Self {
+            max_cost: raw.max_cost,
+            object_weight: raw.object_weight,
+            scalar_weight: raw.scalar_weight,
+            weights,
+            deployments,
+        })
+    }
+
+    /// The weight of a single occurrence of `field_name` on `type_name`.
+    /// `leaf` indicates whether the field has no selection set
+    pub fn weight(&self, type_name: &str, field_name: &str, leaf: bool) -> u64 {
+        // Avoid allocating for the common case that there are no explicit
+        // weights
+        if !self.weights.is_empty() {
+            let key = (type_name.to_string(), field_name.to_string());
+            if let Some(weight) = self.weights.get(&key) {
+                return *weight;
+            }
+        }
+        if leaf {
+            self.scalar_weight
+        } else {
+            self.object_weight
+        }
+    }
+
+    /// The budget for queries against `deployment`, if there is one
+    pub fn budget(&self, deployment: &DeploymentHash) -> Option<u64> {
+        self.deployments.get(deployment).copied().or(self.max_cost)
+    }
+}
+
+/// The cost of a query together with the budget it was checked against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueryCost {
+    pub cost: u64,
+    pub budget: Option<u64>,
+}
+
+/// Costs are reported as GraphQL `Int` values; costs that do not fit into
+/// an `Int` are reported as the largest `Int`
+fn int_value(cost: u64) -> r::Value {
+    r::Value::Int(cost.min(i32::MAX as u64) as i64)
+}
+
+impl From<QueryCost> for r::Value {
+    fn from(cost: QueryCost) -> Self {
+        crate::object! {
+            cost: int_value(cost.cost),
+            budget: cost.budget.map(int_value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::{r, DeploymentHash};
+
+    use super::{QueryCost, QueryCostModel};
+
+    #[test]
+    fn parses_correctly() {
+        let content = r#"
+        max_cost = 10000
+        scalar_weight = 1
+
+        [[weight]]
+        field = "Token.holders"
+        weight = 5
+
+        [[deployment]]
+        hash = "QmSmallBudget"
+        max_cost = 500
+        "#;
+
+        let model = QueryCostModel::from_str(content).unwrap();
+        assert_eq!(5, model.weight("Token", "holders", false));
+        assert_eq!(1, model.weight("Token", "owner", false));
+        assert_eq!(1, model.weight("Token", "name", true));
+
+        let small = DeploymentHash::new("QmSmallBudget").unwrap();
+        let other = DeploymentHash::new("QmOther").unwrap();
+        assert_eq!(Some(500), model.budget(&small));
+        assert_eq!(Some(10000), model.budget(&other));
+    }
+
+    #[test]
+    fn cost_is_reported_as_int() {
+        let cost = QueryCost {
+            cost: 220,
+            budget: None,
+        };
+        assert_eq!(
+            r#"{"cost":220,"budget":null}"#,
+            serde_json::to_string(&r::Value::from(cost)).unwrap()
+        );
+
+        let cost = QueryCost {
+            cost: u64::MAX,
+            budget: Some(500),
+        };
+        assert_eq!(
+            r#"{"cost":2147483647,"budget":500}"#,
+            serde_json::to_string(&r::Value::from(cost)).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_field() {
+        let content = r#"
+        [[weight]]
+        field = "holders"
+        weight = 5
+        "#;
+
+        assert!(QueryCostModel::from_str(content).is_err());
+    }
+}
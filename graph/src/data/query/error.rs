@@ -9,7 +9,10 @@ use std::sync::Arc;
 
 use crate::data::subgraph::*;
 use crate::prelude::q;
-use crate::{components::store::StoreError, prelude::CacheWeight};
+use crate::{
+    components::store::{BlockNumber, StoreError},
+    prelude::CacheWeight,
+};
 
 #[derive(Debug, Clone)]
 pub struct CloneableAnyhowError(Arc<anyhow::Error>);
@@ -20,6 +23,48 @@ impl From<anyhow::Error> for CloneableAnyhowError {
     }
 }
 
+/// A machine-readable classification of query errors that is sent to
+/// clients as `extensions.code` so that they can decide whether to retry a
+/// query without having to parse error messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The query took too long; retrying it unchanged will most likely
+    /// fail again
+    Timeout,
+    /// The query exceeds the complexity, depth, cost or result size limits
+    /// and needs to be changed before it can succeed
+    TooComplex,
+    /// The subgraph or deployment the query was sent to does not exist
+    DeploymentNotFound,
+    /// The store is overloaded or unavailable; clients should back off and
+    /// retry later
+    StoreUnavailable,
+    /// Any other error that happened while resolving the query
+    ResolverError,
+    /// The hash of a persisted query is unknown, and the query needs to be
+    /// resent with its full text
+    PersistedQueryNotFound,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Timeout => "TIMEOUT",
+            ErrorCode::TooComplex => "TOO_COMPLEX",
+            ErrorCode::DeploymentNotFound => "DEPLOYMENT_NOT_FOUND",
+            ErrorCode::StoreUnavailable => "STORE_UNAVAILABLE",
+            ErrorCode::ResolverError => "RESOLVER_ERROR",
+            ErrorCode::PersistedQueryNotFound => "PERSISTED_QUERY_NOT_FOUND",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// Error caused while executing a [Query](struct.Query.html).
 #[derive(Debug, Clone)]
 pub enum QueryExecutionError {
@@ -64,6 +109,7 @@ pub enum QueryExecutionError {
     TooDeep(u8),          // max_depth
     CyclicalFragment(String),
     TooExpensive,
+    CostExceeded(u64, u64), // (cost, budget)
     Throttled,
     UndefinedFragment(String),
     Panic(String),
@@ -78,6 +124,11 @@ pub enum QueryExecutionError {
     IdMissing,
     IdNotString,
     ConstraintViolation(String),
+    IntrospectionOnly(String),
+    Unauthorized(String),
+    /// The history of the entity type was pruned and does not go back to
+    /// the block the query asked for: (entity type, block, earliest block)
+    HistoryPruned(String, BlockNumber, BlockNumber),
 }
 
 impl QueryExecutionError {
@@ -129,6 +180,7 @@ impl QueryExecutionError {
             | Panic(_)
             | EventStreamError
             | TooExpensive
+            | CostExceeded(_, _)
             | Throttled
             | DeploymentReverted
             | SubgraphManifestResolveError(_)
@@ -138,7 +190,33 @@ impl QueryExecutionError {
             | DeploymentNotFound(_)
             | IdMissing
             | IdNotString
-            | ConstraintViolation(_) => false,
+            | ConstraintViolation(_)
+            | IntrospectionOnly(_)
+            | Unauthorized(_)
+            | HistoryPruned(_, _, _) => false,
+        }
+    }
+
+    /// The code that classifies this error for clients
+    pub fn code(&self) -> ErrorCode {
+        use self::QueryExecutionError::*;
+        match self {
+            Timeout => ErrorCode::Timeout,
+            TooComplex(_, _)
+            | TooDeep(_)
+            | TooExpensive
+            | CostExceeded(_, _)
+            | ResultTooBig(_, _) => ErrorCode::TooComplex,
+            DeploymentNotFound(_) => ErrorCode::DeploymentNotFound,
+            Throttled => ErrorCode::StoreUnavailable,
+            StoreError(e) => match e.0.downcast_ref::<crate::components::store::StoreError>() {
+                Some(crate::components::store::StoreError::DatabaseUnavailable)
+                | Some(crate::components::store::StoreError::DatabaseDisabled) => {
+                    ErrorCode::StoreUnavailable
+                }
+                _ => ErrorCode::ResolverError,
+            },
+            _ => ErrorCode::ResolverError,
         }
     }
 }
@@ -280,6 +358,11 @@ impl fmt::Display for QueryExecutionError {
             FulltextQueryRequiresFilter => write!(f, "fulltext search queries can only use EntityFilter::Equal"),
             FulltextQueryInvalidSyntax(msg) => write!(f, "Invalid fulltext search query syntax. Error: {}. Hint: Search terms with spaces need to be enclosed in single quotes", msg),
             TooExpensive => write!(f, "query is too expensive"),
+            CostExceeded(cost, budget) => {
+                write!(f, "query has a cost of `{}` and thereby exceeds the budget of `{}`. \
+                           Possible solutions are querying fewer fields or relationships or \
+                           using `first` to return smaller collections", cost, budget)
+            }
             Throttled => write!(f, "service is overloaded and can not run the query right now. Please try again in a few minutes"),
             DeploymentReverted => write!(f, "the chain was reorganized while executing the query"),
             SubgraphManifestResolveError(e) => write!(f, "failed to resolve subgraph manifest: {}", e),
@@ -289,6 +372,13 @@ impl fmt::Display for QueryExecutionError {
             IdMissing => write!(f, "entity is missing an `id` attribute"),
             IdNotString => write!(f, "entity `id` attribute is not a string"),
             ConstraintViolation(msg) => write!(f, "internal constraint violated: {}", msg),
+            IntrospectionOnly(id) => write!(f, "deployment `{}` only serves introspection queries", id),
+            Unauthorized(msg) => write!(f, "unauthorized: {}", msg),
+            HistoryPruned(entity, block, earliest) => write!(
+                f,
+                "the history of `{}` only goes back to block {}, but the query is for block {}",
+                entity, earliest, block
+            ),
         }
     }
 }
@@ -351,6 +441,12 @@ pub enum QueryError {
     ParseError(Arc<anyhow::Error>),
     ExecutionError(QueryExecutionError),
     IndexingError,
+    /// The request only contained the hash of a persisted query, and we
+    /// don't know the query text for that hash
+    PersistedQueryNotFound,
+    /// An error from the part of a federated query whose result is nested
+    /// under the given key
+    Subgraph(String, Box<QueryError>),
 }
 
 impl QueryError {
@@ -358,7 +454,23 @@ impl QueryError {
         match self {
             QueryError::EncodingError(_) | QueryError::ParseError(_) => true,
             QueryError::ExecutionError(err) => err.is_attestable(),
-            QueryError::IndexingError => false,
+            QueryError::IndexingError | QueryError::PersistedQueryNotFound => false,
+            QueryError::Subgraph(_, e) => e.is_attestable(),
+        }
+    }
+
+    /// The code that is sent to clients in `extensions.code`. Attestable
+    /// errors do not have a code since that would change the responses
+    /// that indexers attest to
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            QueryError::EncodingError(_)
+            | QueryError::ParseError(_)
+            | QueryError::IndexingError => None,
+            QueryError::ExecutionError(e) if e.is_attestable() => None,
+            QueryError::ExecutionError(e) => Some(e.code()),
+            QueryError::PersistedQueryNotFound => Some(ErrorCode::PersistedQueryNotFound),
+            QueryError::Subgraph(_, e) => e.code(),
         }
     }
 }
@@ -384,6 +496,7 @@ impl Error for QueryError {
         match *self {
             QueryError::EncodingError(ref e) => Some(e),
             QueryError::ExecutionError(ref e) => Some(e),
+            QueryError::Subgraph(_, ref e) => Some(e.as_ref()),
             _ => None,
         }
     }
@@ -398,6 +511,12 @@ impl fmt::Display for QueryError {
 
             // This error message is part of attestable responses.
             QueryError::IndexingError => write!(f, "indexing_error"),
+
+            // Clients look for this exact message to decide whether to
+            // resend the request with the full query text
+            QueryError::PersistedQueryNotFound => write!(f, "PersistedQueryNotFound"),
+
+            QueryError::Subgraph(ref key, ref e) => write!(f, "{}: {}", key, e),
         }
     }
 }
@@ -409,15 +528,22 @@ impl Serialize for QueryError {
     {
         use self::QueryExecutionError::*;
 
+        // Errors from federated queries are reported with the path of
+        // the subgraph they came from
+        let (key, error) = match self {
+            QueryError::Subgraph(key, error) => (Some(key), error.as_ref()),
+            error => (None, error),
+        };
+
         let mut map = serializer.serialize_map(Some(1))?;
 
-        let msg = match self {
+        let msg = match error {
             // Serialize parse errors with their location (line, column) to make it easier
             // for users to find where the errors are; this is likely to change as the
             // graphql_parser team makes improvements to their error reporting
             QueryError::ParseError(_) => {
                 // Split the inner message into (first line, rest)
-                let msg = format!("{}", self);
+                let msg = format!("{}", error);
                 let inner_msg = msg.replace("query parse error:", "");
                 let inner_msg = inner_msg.trim();
                 let parts: Vec<&str> = inner_msg.splitn(2, '\n').collect();
@@ -463,12 +589,21 @@ impl Serialize for QueryError {
                 location.insert("line", pos.line);
                 location.insert("column", pos.column);
                 map.serialize_entry("locations", &vec![location])?;
-                format!("{}", self)
+                format!("{}", error)
             }
-            _ => format!("{}", self),
+            _ => format!("{}", error),
         };
 
+        if let Some(code) = error.code() {
+            let mut extensions = HashMap::new();
+            extensions.insert("code", code.as_str());
+            map.serialize_entry("extensions", &extensions)?;
+        }
+
         map.serialize_entry("message", msg.as_str())?;
+        if let Some(key) = key {
+            map.serialize_entry("path", &[key])?;
+        }
         map.end()
     }
 }
@@ -479,3 +614,56 @@ impl CacheWeight for QueryError {
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{ErrorCode, QueryError, QueryExecutionError};
+
+    #[test]
+    fn serializes_error_codes() {
+        let err = QueryError::from(QueryExecutionError::Timeout);
+        assert_eq!(Some(ErrorCode::Timeout), err.code());
+        assert_eq!(
+            json!({ "message": "Query timed out", "extensions": { "code": "TIMEOUT" } }),
+            serde_json::to_value(&err).unwrap()
+        );
+
+        let err = QueryError::from(QueryExecutionError::CostExceeded(10, 5));
+        assert_eq!(Some(ErrorCode::TooComplex), err.code());
+
+        let err = QueryError::from(QueryExecutionError::Throttled);
+        assert_eq!(Some(ErrorCode::StoreUnavailable), err.code());
+
+        // Attestable errors must serialize exactly as before
+        let err = QueryError::from(QueryExecutionError::EmptyQuery);
+        assert_eq!(None, err.code());
+        assert_eq!(
+            json!({ "message": "The query is empty" }),
+            serde_json::to_value(&err).unwrap()
+        );
+        assert_eq!(
+            json!({ "message": "indexing_error" }),
+            serde_json::to_value(&QueryError::IndexingError).unwrap()
+        );
+    }
+
+    #[test]
+    fn serializes_subgraph_errors_with_path() {
+        let err = QueryError::Subgraph(
+            "uni".to_string(),
+            Box::new(QueryError::from(QueryExecutionError::Timeout)),
+        );
+        assert_eq!(Some(ErrorCode::Timeout), err.code());
+        assert_eq!("uni: Query timed out", err.to_string());
+        assert_eq!(
+            json!({
+                "message": "Query timed out",
+                "extensions": { "code": "TIMEOUT" },
+                "path": ["uni"]
+            }),
+            serde_json::to_value(&err).unwrap()
+        );
+    }
+}
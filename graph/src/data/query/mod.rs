@@ -1,11 +1,15 @@
 mod cache_status;
+mod consistency;
+mod cost;
 mod error;
 mod query;
 mod result;
 mod trace;
 
 pub use self::cache_status::CacheStatus;
-pub use self::error::{QueryError, QueryExecutionError};
+pub use self::consistency::{ConsistencyToken, CONSISTENCY_TOKEN_HEADER};
+pub use self::cost::{QueryCost, QueryCostModel};
+pub use self::error::{ErrorCode, QueryError, QueryExecutionError};
 pub use self::query::{Query, QueryTarget, QueryVariables};
-pub use self::result::{QueryResult, QueryResults};
-pub use self::trace::Trace;
+pub use self::result::{QueryResult, QueryResults, StreamedField, MULTIPART_CONTENT_TYPE};
+pub use self::trace::{FieldTrace, Trace};
@@ -5,6 +5,7 @@ use std::convert::TryFrom;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 
+use super::ConsistencyToken;
 use crate::{
     data::graphql::shape_hash::shape_hash,
     prelude::{q, r, ApiVersion, DeploymentHash, SubgraphName, ENV_VARS},
@@ -133,6 +134,14 @@ pub struct Query {
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
     pub trace: bool,
+    /// The consistency token the client sent with the query; if it is for
+    /// the deployment being queried, the query must be answered at the
+    /// block in the token or a later one
+    pub consistency_token: Option<ConsistencyToken>,
+    /// Whether the client accepts incremental delivery of `@defer` and
+    /// `@stream` results. If it does not, deferred results are part of the
+    /// one response the client gets
+    pub incremental: bool,
     _force_use_of_new: (),
 }
 
@@ -161,7 +170,19 @@ impl Query {
             query_text: Arc::new(query_text),
             variables_text: Arc::new(variables_text),
             trace,
+            consistency_token: None,
+            incremental: false,
             _force_use_of_new: (),
         }
     }
+
+    pub fn with_consistency_token(mut self, token: Option<ConsistencyToken>) -> Self {
+        self.consistency_token = token;
+        self
+    }
+
+    pub fn with_incremental_delivery(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
 }
@@ -1,23 +1,28 @@
 use super::error::{QueryError, QueryExecutionError};
 use super::trace::{HttpTrace, TRACE_NONE};
 use crate::cheap_clone::CheapClone;
-use crate::components::server::query::ServerResponse;
-use crate::data::value::Object;
+use crate::components::server::query::{ServerResponse, StreamingResponse};
+use crate::data::value::{Object, Word};
 use crate::derive::CacheWeight;
+use crate::ext::futures::CancelGuard;
 use crate::prelude::{r, CacheWeight, DeploymentHash};
-use http_body_util::Full;
+use futures03::future::{self, BoxFuture, FutureExt};
+use futures03::stream::{self, BoxStream, FuturesUnordered, StreamExt};
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Bytes, Frame};
 use hyper::header::{
     ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
-    CONTENT_TYPE,
+    ACCESS_CONTROL_EXPOSE_HEADERS, CONTENT_TYPE,
 };
+use hyper::http::response::Builder;
 use hyper::Response;
 use serde::ser::*;
 use serde::Serialize;
-use std::convert::TryFrom;
+use std::convert::{Infallible, TryFrom};
 use std::sync::Arc;
 use std::time::Instant;
 
-use super::{CacheStatus, Trace};
+use super::{CacheStatus, ConsistencyToken, Trace, CONSISTENCY_TOKEN_HEADER};
 
 fn serialize_data<S>(data: &Option<Data>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -50,10 +55,83 @@ where
 
 pub type Data = Object;
 
+/// Serialize an object as a map
+struct SerObject<'a>(&'a Object);
+
+impl Serialize for SerObject<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_value_map(std::iter::once(self.0), serializer)
+    }
+}
+
+/// The content type announced for responses that use incremental delivery
+/// for `@defer` and `@stream`
+pub const MULTIPART_CONTENT_TYPE: &str = "multipart/mixed; boundary=\"-\"; deferSpec=20220824";
+
+/// A list field with a `@stream` directive. The first `initial_count`
+/// items of the list are sent with the initial payload and the remaining
+/// items in a subsequent payload. The `path` holds the response keys of the
+/// fields from the query root down to the list
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamedField {
+    pub path: Vec<String>,
+    pub initial_count: usize,
+    pub label: Option<String>,
+}
+
+/// A selection set that was deferred with `@defer` and whose result is
+/// still being computed
+pub struct PendingResult {
+    label: Option<String>,
+    result: BoxFuture<'static, (Arc<QueryResult>, CacheStatus)>,
+}
+
+impl std::fmt::Debug for PendingResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingResult")
+            .field("label", &self.label)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The items of a streamed list beyond its `initial_count` ones that are
+/// still being computed
+pub struct PendingStream {
+    stream: StreamedField,
+    result: BoxFuture<'static, (Arc<QueryResult>, CacheStatus)>,
+}
+
+impl std::fmt::Debug for PendingStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingStream")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 /// A collection of query results that is serialized as a single result.
 pub struct QueryResults {
     results: Vec<Arc<QueryResult>>,
+    /// Results for selections that were deferred with `@defer`, together
+    /// with the label of the directive
+    deferred: Vec<(Option<String>, Arc<QueryResult>)>,
+    /// Deferred selections that are still being executed; they are only
+    /// used for clients that accept incremental delivery
+    pending: Vec<PendingResult>,
+    /// List fields whose items are streamed with `@stream`
+    streams: Vec<StreamedField>,
+    /// Streamed lists whose remaining items are still being computed
+    pending_streams: Vec<PendingStream>,
+    /// Cancels the computation of pending results when the response is
+    /// dropped
+    tasks: CancelGuard,
+    /// Additional information about the query that is sent in the
+    /// `extensions` entry of the response
+    extensions: Object,
+    /// The token for the block at which the query was answered that is
+    /// sent to the client in the `Graph-Consistency-Token` header
+    consistency_token: Option<ConsistencyToken>,
     pub trace: Trace,
 }
 
@@ -61,35 +139,104 @@ impl QueryResults {
     pub fn empty(trace: Trace) -> Self {
         QueryResults {
             results: Vec::new(),
+            deferred: Vec::new(),
+            pending: Vec::new(),
+            streams: Vec::new(),
+            pending_streams: Vec::new(),
+            tasks: CancelGuard::new(),
+            extensions: Object::empty(),
+            consistency_token: None,
             trace,
         }
     }
 
+    fn new(results: Vec<Arc<QueryResult>>) -> Self {
+        QueryResults {
+            results,
+            deferred: Vec::new(),
+            pending: Vec::new(),
+            streams: Vec::new(),
+            pending_streams: Vec::new(),
+            tasks: CancelGuard::new(),
+            extensions: Object::empty(),
+            consistency_token: None,
+            trace: Trace::None,
+        }
+    }
+
+    /// All results, including deferred ones
+    fn all(&self) -> impl Iterator<Item = &Arc<QueryResult>> {
+        self.results
+            .iter()
+            .chain(self.deferred.iter().map(|(_, result)| result))
+    }
+
     pub fn first(&self) -> Option<&Arc<QueryResult>> {
         self.results.first()
     }
 
     pub fn has_errors(&self) -> bool {
-        self.results.iter().any(|result| result.has_errors())
+        self.all().any(|result| result.has_errors())
     }
 
     pub fn not_found(&self) -> bool {
-        self.results.iter().any(|result| result.not_found())
+        self.all().any(|result| result.not_found())
     }
 
     pub fn deployment_hash(&self) -> Option<&DeploymentHash> {
-        self.results
-            .iter()
+        self.all()
             .filter_map(|result| result.deployment.as_ref())
             .next()
     }
 
     pub fn errors(&self) -> Vec<QueryError> {
-        self.results.iter().flat_map(|r| r.errors.clone()).collect()
+        self.all().flat_map(|r| r.errors.clone()).collect()
     }
 
     pub fn is_attestable(&self) -> bool {
-        self.results.iter().all(|r| r.is_attestable())
+        self.all().all(|r| r.is_attestable())
+    }
+
+    /// Return `true` if parts of this result should be delivered
+    /// incrementally because the query used `@defer` or `@stream`
+    pub fn is_incremental(&self) -> bool {
+        !self.deferred.is_empty()
+            || !self.pending.is_empty()
+            || !self.streams.is_empty()
+            || !self.pending_streams.is_empty()
+    }
+
+    /// Combine the results of separate queries into one result in which
+    /// the data of each query is nested under its key. Keys for queries
+    /// that did not produce any data are set to `null`, and errors name
+    /// the key of the query that caused them
+    pub fn nested(results: Vec<(String, QueryResults)>) -> QueryResults {
+        let mut data = Object::empty();
+        let mut errors = Vec::new();
+        for (key, results) in results {
+            let mut nested = None;
+            for result in results.all() {
+                if let Some(more) = result.data() {
+                    nested
+                        .get_or_insert_with(Object::empty)
+                        .append(more.clone());
+                }
+                errors.extend(
+                    result
+                        .errors
+                        .iter()
+                        .map(|e| QueryError::Subgraph(key.clone(), Box::new(e.clone()))),
+                );
+            }
+            let value = nested.map(r::Value::Object).unwrap_or(r::Value::Null);
+            data.extend([(Word::from(key), value)]);
+        }
+        QueryResults::from(QueryResult {
+            data: Some(data),
+            errors,
+            deployment: None,
+            trace: TRACE_NONE.cheap_clone(),
+        })
     }
 }
 
@@ -97,11 +244,11 @@ impl Serialize for QueryResults {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         let start = Instant::now();
         let mut len = 0;
-        let has_data = self.results.iter().any(|r| r.has_data());
+        let has_data = self.all().any(|r| r.has_data());
         if has_data {
             len += 1;
         }
-        let has_errors = self.results.iter().any(|r| r.has_errors());
+        let has_errors = self.all().any(|r| r.has_errors());
         if has_errors {
             len += 1;
         }
@@ -114,10 +261,7 @@ impl Serialize for QueryResults {
 
             impl Serialize for SerData<'_> {
                 fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-                    serialize_value_map(
-                        self.0.results.iter().filter_map(|r| r.data.as_ref()),
-                        serializer,
-                    )
+                    serialize_value_map(self.0.all().filter_map(|r| r.data.as_ref()), serializer)
                 }
             }
 
@@ -131,7 +275,7 @@ impl Serialize for QueryResults {
             impl Serialize for SerError<'_> {
                 fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
                     let mut seq = serializer.serialize_seq(None)?;
-                    for err in self.0.results.iter().flat_map(|r| &r.errors) {
+                    for err in self.0.all().flat_map(|r| &r.errors) {
                         seq.serialize_element(err)?;
                     }
                     seq.end()
@@ -141,6 +285,10 @@ impl Serialize for QueryResults {
             state.serialize_field("errors", &SerError(self))?;
         }
 
+        if !self.extensions.is_empty() {
+            state.serialize_field("extensions", &SerObject(&self.extensions))?;
+        }
+
         if !self.trace.is_none() {
             let http = HttpTrace::new(start.elapsed(), self.results.weight());
             state.serialize_field("trace", &self.trace)?;
@@ -152,46 +300,31 @@ impl Serialize for QueryResults {
 
 impl From<Data> for QueryResults {
     fn from(x: Data) -> Self {
-        QueryResults {
-            results: vec![Arc::new(x.into())],
-            trace: Trace::None,
-        }
+        QueryResults::new(vec![Arc::new(x.into())])
     }
 }
 
 impl From<QueryResult> for QueryResults {
     fn from(x: QueryResult) -> Self {
-        QueryResults {
-            results: vec![Arc::new(x)],
-            trace: Trace::None,
-        }
+        QueryResults::new(vec![Arc::new(x)])
     }
 }
 
 impl From<Arc<QueryResult>> for QueryResults {
     fn from(x: Arc<QueryResult>) -> Self {
-        QueryResults {
-            results: vec![x],
-            trace: Trace::None,
-        }
+        QueryResults::new(vec![x])
     }
 }
 
 impl From<QueryExecutionError> for QueryResults {
     fn from(x: QueryExecutionError) -> Self {
-        QueryResults {
-            results: vec![Arc::new(x.into())],
-            trace: Trace::None,
-        }
+        QueryResults::new(vec![Arc::new(x.into())])
     }
 }
 
 impl From<Vec<QueryExecutionError>> for QueryResults {
     fn from(x: Vec<QueryExecutionError>) -> Self {
-        QueryResults {
-            results: vec![Arc::new(x.into())],
-            trace: Trace::None,
-        }
+        QueryResults::new(vec![Arc::new(x.into())])
     }
 }
 
@@ -202,10 +335,95 @@ impl QueryResults {
         self.results.push(other);
     }
 
+    /// Add the result of executing a selection set that was deferred with
+    /// `@defer(label: label)`
+    pub fn append_deferred(
+        &mut self,
+        label: Option<String>,
+        other: Arc<QueryResult>,
+        cache_status: CacheStatus,
+    ) {
+        let trace = other.trace.cheap_clone();
+        self.trace.append(trace, cache_status);
+        self.deferred.push((label, other));
+    }
+
+    /// Add a selection that was deferred with `@defer(label: label)` and
+    /// whose `result` is still being computed
+    pub fn append_pending(
+        &mut self,
+        label: Option<String>,
+        result: BoxFuture<'static, (Arc<QueryResult>, CacheStatus)>,
+    ) {
+        self.pending.push(PendingResult { label, result });
+    }
+
+    /// Wait for all deferred selections that are still being computed
+    pub async fn resolve_pending(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        let resolved = future::join_all(
+            pending
+                .into_iter()
+                .map(|pending| async move { (pending.label, pending.result.await) }),
+        )
+        .await;
+        for (label, (result, cache_status)) in resolved {
+            self.append_deferred(label, result, cache_status);
+        }
+    }
+
+    /// Mark a list field as streamed
+    pub fn add_stream(&mut self, stream: StreamedField) {
+        self.streams.push(stream);
+    }
+
+    /// Add a streamed list whose items beyond the initial ones are still
+    /// being computed by `result`
+    pub fn append_pending_stream(
+        &mut self,
+        stream: StreamedField,
+        result: BoxFuture<'static, (Arc<QueryResult>, CacheStatus)>,
+    ) {
+        self.pending_streams.push(PendingStream { stream, result });
+    }
+
+    /// The guard for the tasks that compute pending results. The tasks are
+    /// canceled when the guard, and with it these results or the response
+    /// body made from them, is dropped
+    pub fn tasks(&self) -> &CancelGuard {
+        &self.tasks
+    }
+
+    /// Add an entry to the `extensions` of the response. If there already
+    /// is an entry for `key`, it is kept
+    pub fn add_extension(&mut self, key: &str, value: r::Value) {
+        self.extensions.extend([(Word::from(key), value)]);
+    }
+
+    pub fn set_consistency_token(&mut self, token: ConsistencyToken) {
+        self.consistency_token = Some(token);
+    }
+
+    pub fn consistency_token(&self) -> Option<&ConsistencyToken> {
+        self.consistency_token.as_ref()
+    }
+
+    /// Start a response, adding the consistency token header if we have
+    /// a token
+    fn response_builder(&self) -> Builder {
+        let builder = Response::builder();
+        match &self.consistency_token {
+            Some(token) => builder
+                .header(CONSISTENCY_TOKEN_HEADER, token.to_string())
+                .header(ACCESS_CONTROL_EXPOSE_HEADERS, CONSISTENCY_TOKEN_HEADER),
+            None => builder,
+        }
+    }
+
     pub fn as_http_response(&self) -> ServerResponse {
         let json = serde_json::to_string(&self).unwrap();
         let attestable = self.results.iter().all(|r| r.is_attestable());
-        Response::builder()
+        self.response_builder()
             .status(200)
             .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
             .header(CONTENT_TYPE, "application/json")
@@ -218,6 +436,289 @@ impl QueryResults {
     }
 }
 
+const PART_HEADER: &str = "\r\n---\r\nContent-Type: application/json; charset=utf-8\r\n\r\n";
+const MULTIPART_END: &[u8] = b"\r\n-----\r\n";
+
+/// Remove the items of the list at `path` in `value` beyond the first
+/// `initial_count` ones and add them to `out`, together with the path of
+/// the first removed item. If one of the fields along `path` is a list, the
+/// streamed list is split for each of its entries
+fn split_stream(
+    value: &mut r::Value,
+    path: &[String],
+    initial_count: usize,
+    prefix: &mut Vec<serde_json::Value>,
+    out: &mut Vec<(Vec<serde_json::Value>, Vec<r::Value>)>,
+) {
+    match (value, path.split_first()) {
+        (r::Value::List(items), None) => {
+            if items.len() > initial_count {
+                let rest = items.split_off(initial_count);
+                let mut path = prefix.clone();
+                path.push(initial_count.into());
+                out.push((path, rest));
+            }
+        }
+        (r::Value::List(items), Some(_)) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                prefix.push(i.into());
+                split_stream(item, path, initial_count, prefix, out);
+                prefix.pop();
+            }
+        }
+        (r::Value::Object(obj), Some((key, rest))) => {
+            if let Some(value) = obj.get_mut(key) {
+                prefix.push(key.as_str().into());
+                split_stream(value, rest, initial_count, prefix, out);
+                prefix.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Split the list for `stream` in `data` after `initial_count` items and
+/// return the path and the removed items for each list that was split
+fn split_streamed_field(
+    data: &mut Data,
+    stream: &StreamedField,
+    initial_count: usize,
+) -> Vec<(Vec<serde_json::Value>, Vec<r::Value>)> {
+    let mut items = Vec::new();
+    let Some((key, rest)) = stream.path.split_first() else {
+        return items;
+    };
+    if let Some(value) = data.get_mut(key) {
+        let mut prefix = vec![key.as_str().into()];
+        split_stream(value, rest, initial_count, &mut prefix, &mut items);
+    }
+    items
+}
+
+/// The initial payload of an incremental response
+struct InitialPayload<'a> {
+    data: Option<&'a Data>,
+    errors: Vec<&'a QueryError>,
+    extensions: &'a Object,
+    has_next: bool,
+}
+
+impl Serialize for InitialPayload<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("InitialPayload", 4)?;
+        if let Some(data) = self.data {
+            state.serialize_field("data", &SerObject(data))?;
+        }
+        if !self.errors.is_empty() {
+            state.serialize_field("errors", &self.errors)?;
+        }
+        if !self.extensions.is_empty() {
+            state.serialize_field("extensions", &SerObject(self.extensions))?;
+        }
+        state.serialize_field("hasNext", &self.has_next)?;
+        state.end()
+    }
+}
+
+/// One entry in the `incremental` list of a subsequent payload
+#[derive(Serialize)]
+struct IncrementalPayload<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<SerObject<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    items: Option<&'a [r::Value]>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    errors: &'a [QueryError],
+    path: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubsequentPayload<'a> {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    incremental: Vec<IncrementalPayload<'a>>,
+    has_next: bool,
+}
+
+impl SubsequentPayload<'_> {
+    fn part(incremental: Vec<IncrementalPayload<'_>>, has_next: bool) -> Bytes {
+        multipart_part(&SubsequentPayload {
+            incremental,
+            has_next,
+        })
+    }
+}
+
+/// A part of the response that is sent after the initial payload once
+/// its result is available
+enum Subsequent {
+    Deferred(Option<String>, Arc<QueryResult>),
+    Streamed(StreamedField, Arc<QueryResult>),
+}
+
+impl Subsequent {
+    fn part(self, has_next: bool) -> Bytes {
+        match self {
+            Subsequent::Deferred(label, result) => {
+                let payload = IncrementalPayload {
+                    data: result.data.as_ref().map(SerObject),
+                    items: None,
+                    errors: &result.errors,
+                    path: vec![],
+                    label: label.as_deref(),
+                };
+                SubsequentPayload::part(vec![payload], has_next)
+            }
+            Subsequent::Streamed(stream, result) => {
+                let label = stream.label.as_deref();
+                if result.has_errors() {
+                    let payload = IncrementalPayload {
+                        data: None,
+                        items: None,
+                        errors: &result.errors,
+                        path: stream.path.iter().map(|key| key.as_str().into()).collect(),
+                        label,
+                    };
+                    return SubsequentPayload::part(vec![payload], has_next);
+                }
+                // The result only contains the items after the initial
+                // ones; their position in the list starts after them
+                let mut data = result.data.clone().unwrap_or_else(Object::empty);
+                let mut items = split_streamed_field(&mut data, &stream, 0);
+                for (path, _) in &mut items {
+                    if let Some(last) = path.last_mut() {
+                        *last = stream.initial_count.into();
+                    }
+                }
+                let payloads = items
+                    .iter()
+                    .map(|(path, items)| IncrementalPayload {
+                        data: None,
+                        items: Some(items.as_slice()),
+                        errors: &[],
+                        path: path.clone(),
+                        label,
+                    })
+                    .collect();
+                SubsequentPayload::part(payloads, has_next)
+            }
+        }
+    }
+}
+
+fn multipart_part(payload: &impl Serialize) -> Bytes {
+    let mut part = String::from(PART_HEADER);
+    part.push_str(&serde_json::to_string(payload).unwrap());
+    Bytes::from(part)
+}
+
+impl QueryResults {
+    /// Turn the results into the parts of a `multipart/mixed` body
+    /// following the incremental delivery protocol: an initial payload with
+    /// everything that was not deferred, one payload for each streamed
+    /// list, and one payload for each deferred selection as soon as its
+    /// result is available. Dropping the body cancels the computation of
+    /// results that are still pending
+    pub fn into_multipart_body(self) -> BoxStream<'static, Bytes> {
+        let QueryResults {
+            results,
+            deferred,
+            pending,
+            streams,
+            pending_streams,
+            tasks,
+            extensions,
+            ..
+        } = self;
+
+        let mut data: Option<Data> = None;
+        for more in results.iter().filter_map(|r| r.data.as_ref()) {
+            data.get_or_insert_with(Object::empty).append(more.clone());
+        }
+        let mut streamed = Vec::new();
+        if let Some(data) = data.as_mut() {
+            for stream in &streams {
+                let items = split_streamed_field(data, stream, stream.initial_count);
+                streamed.extend(
+                    items
+                        .into_iter()
+                        .map(|(path, items)| (path, items, stream.label.as_deref())),
+                );
+            }
+            // At least one item is resolved for lists whose remaining
+            // items are pending, even if `initial_count` is 0
+            for pending in &pending_streams {
+                split_streamed_field(data, &pending.stream, pending.stream.initial_count);
+            }
+        }
+
+        let mut remaining = streamed.len() + deferred.len() + pending.len() + pending_streams.len();
+        let mut parts = vec![multipart_part(&InitialPayload {
+            data: data.as_ref(),
+            errors: results.iter().flat_map(|r| &r.errors).collect(),
+            extensions: &extensions,
+            has_next: remaining > 0,
+        })];
+        for (path, items, label) in streamed {
+            remaining -= 1;
+            let payload = IncrementalPayload {
+                data: None,
+                items: Some(items.as_slice()),
+                errors: &[],
+                path,
+                label,
+            };
+            parts.push(SubsequentPayload::part(vec![payload], remaining > 0));
+        }
+
+        // Deferred selections and the remaining items of streamed lists
+        // are sent in the order in which they finish
+        let subsequent: FuturesUnordered<_> = deferred
+            .into_iter()
+            .map(|(label, result)| future::ready(Subsequent::Deferred(label, result)).boxed())
+            .chain(pending.into_iter().map(|pending| {
+                async move { Subsequent::Deferred(pending.label, pending.result.await.0) }.boxed()
+            }))
+            .chain(pending_streams.into_iter().map(|pending| {
+                async move { Subsequent::Streamed(pending.stream, pending.result.await.0) }.boxed()
+            }))
+            .collect();
+        let subsequent = subsequent.map(move |subsequent| {
+            remaining -= 1;
+            subsequent.part(remaining > 0)
+        });
+
+        stream::iter(parts)
+            .chain(subsequent)
+            .chain(stream::once(async move {
+                // Keep pending results from being canceled until all of
+                // them have been sent
+                drop(tasks);
+                Bytes::from_static(MULTIPART_END)
+            }))
+            .boxed()
+    }
+
+    pub fn into_multipart_http_response(self) -> StreamingResponse {
+        let attestable = self.is_attestable();
+        let builder = self
+            .response_builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, User-Agent")
+            .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS, POST")
+            .header(CONTENT_TYPE, MULTIPART_CONTENT_TYPE)
+            .header("Graph-Attestable", attestable.to_string());
+        let body = StreamBody::new(
+            self.into_multipart_body()
+                .map(|part| Ok::<_, Infallible>(Frame::data(part))),
+        );
+        builder.body(body.boxed_unsync()).unwrap()
+    }
+}
+
 /// The result of running a query, if successful.
 #[derive(Debug, CacheWeight, Default, Serialize)]
 pub struct QueryResult {
@@ -396,3 +897,176 @@ fn multiple_data_items() {
     let actual = serde_json::to_string(&res).unwrap();
     assert_eq!(expected, actual)
 }
+
+// Check that streamed lists are split between the initial and the
+// subsequent payloads of a multipart response, including lists that are
+// nested inside other lists
+#[test]
+fn streamed_list_items() {
+    use crate::data::value::Word;
+
+    fn parts(res: QueryResults) -> Vec<String> {
+        let body = futures03::executor::block_on(res.into_multipart_body().collect::<Vec<_>>());
+        let body = String::from_utf8(body.concat()).unwrap();
+        body.split(PART_HEADER)
+            .skip(1)
+            .map(|part| part.trim_end_matches("\r\n-----\r\n").to_string())
+            .collect()
+    }
+
+    let items = || r::Value::List((0..3).map(r::Value::Int).collect());
+    let obj = Object::from_iter([(Word::from("items"), items())]);
+
+    let mut res = QueryResults::empty(Trace::None);
+    res.append(Arc::new(obj.into()), CacheStatus::default());
+    res.add_stream(StreamedField {
+        path: vec!["items".to_string()],
+        initial_count: 1,
+        label: None,
+    });
+    assert!(res.is_incremental());
+    assert_eq!(
+        parts(res),
+        vec![
+            r#"{"data":{"items":[0]},"hasNext":true}"#,
+            r#"{"incremental":[{"items":[1,2],"path":["items",1]}],"hasNext":false}"#
+        ]
+    );
+
+    let outer = (0..2)
+        .map(|_| r::Value::Object(Object::from_iter([(Word::from("inner"), items())])))
+        .collect();
+    let obj = Object::from_iter([(Word::from("outer"), r::Value::List(outer))]);
+    let mut res = QueryResults::empty(Trace::None);
+    res.append(Arc::new(obj.into()), CacheStatus::default());
+    res.add_stream(StreamedField {
+        path: vec!["outer".to_string(), "inner".to_string()],
+        initial_count: 2,
+        label: Some("inner".to_string()),
+    });
+    assert_eq!(
+        parts(res),
+        vec![
+            r#"{"data":{"outer":[{"inner":[0,1]},{"inner":[0,1]}]},"hasNext":true}"#,
+            r#"{"incremental":[{"items":[2],"path":["outer",0,"inner",2],"label":"inner"}],"hasNext":true}"#,
+            r#"{"incremental":[{"items":[2],"path":["outer",1,"inner",2],"label":"inner"}],"hasNext":false}"#
+        ]
+    );
+}
+
+// Check that deferred results that are still being computed are sent
+// after the initial payload
+#[test]
+fn pending_deferred_results() {
+    use crate::data::value::Word;
+
+    let obj = |key: &str| Object::from_iter([(Word::from(key), r::Value::Int(1))]);
+    let mut res = QueryResults::empty(Trace::None);
+    res.append(Arc::new(obj("a").into()), CacheStatus::default());
+    let deferred: QueryResult = obj("b").into();
+    res.append_pending(
+        Some("b".to_string()),
+        future::ready((Arc::new(deferred), CacheStatus::default())).boxed(),
+    );
+    assert!(res.is_incremental());
+
+    let body = futures03::executor::block_on(res.into_multipart_body().collect::<Vec<_>>());
+    let body = String::from_utf8(body.concat()).unwrap();
+    assert_eq!(
+        body,
+        format!(
+            "{}{}{}{}\r\n-----\r\n",
+            PART_HEADER,
+            r#"{"data":{"a":1},"hasNext":true}"#,
+            PART_HEADER,
+            r#"{"incremental":[{"data":{"b":1},"path":[],"label":"b"}],"hasNext":false}"#
+        )
+    );
+}
+
+// Check that the remaining items of streamed lists that are computed
+// separately are sent after the initial items, at the right position
+#[test]
+fn pending_streamed_items() {
+    use crate::data::value::Word;
+
+    let list = |ids: &[i64]| r::Value::List(ids.iter().copied().map(r::Value::Int).collect());
+    let outer = |lists: Vec<r::Value>| {
+        let outer = lists
+            .into_iter()
+            .map(|inner| r::Value::Object(Object::from_iter([(Word::from("inner"), inner)])))
+            .collect();
+        Object::from_iter([(Word::from("outer"), r::Value::List(outer))])
+    };
+
+    let mut res = QueryResults::empty(Trace::None);
+    res.append(
+        Arc::new(outer(vec![list(&[0, 1]), list(&[0])]).into()),
+        CacheStatus::default(),
+    );
+    let rest: QueryResult = outer(vec![list(&[2, 3]), list(&[])]).into();
+    res.append_pending_stream(
+        StreamedField {
+            path: vec!["outer".to_string(), "inner".to_string()],
+            initial_count: 2,
+            label: Some("inner".to_string()),
+        },
+        future::ready((Arc::new(rest), CacheStatus::default())).boxed(),
+    );
+    assert!(res.is_incremental());
+
+    let body = futures03::executor::block_on(res.into_multipart_body().collect::<Vec<_>>());
+    let body = String::from_utf8(body.concat()).unwrap();
+    assert_eq!(
+        body,
+        format!(
+            "{}{}{}{}\r\n-----\r\n",
+            PART_HEADER,
+            r#"{"data":{"outer":[{"inner":[0,1]},{"inner":[0]}]},"hasNext":true}"#,
+            PART_HEADER,
+            r#"{"incremental":[{"items":[2,3],"path":["outer",0,"inner",2],"label":"inner"}],"hasNext":false}"#
+        )
+    );
+
+    // With an `initialCount` of 0, the one item that had to be resolved
+    // is removed from the initial payload
+    let mut res = QueryResults::empty(Trace::None);
+    let items = Object::from_iter([(Word::from("items"), list(&[0]))]);
+    res.append(Arc::new(items.into()), CacheStatus::default());
+    let rest: QueryResult = Object::from_iter([(Word::from("items"), list(&[0, 1]))]).into();
+    res.append_pending_stream(
+        StreamedField {
+            path: vec!["items".to_string()],
+            initial_count: 0,
+            label: None,
+        },
+        future::ready((Arc::new(rest), CacheStatus::default())).boxed(),
+    );
+    let body = futures03::executor::block_on(res.into_multipart_body().collect::<Vec<_>>());
+    let body = String::from_utf8(body.concat()).unwrap();
+    assert_eq!(
+        body,
+        format!(
+            "{}{}{}{}\r\n-----\r\n",
+            PART_HEADER,
+            r#"{"data":{"items":[]},"hasNext":true}"#,
+            PART_HEADER,
+            r#"{"incremental":[{"items":[0,1],"path":["items",0]}],"hasNext":false}"#
+        )
+    );
+}
+
+// Check that pending results stop being computed when the body of the
+// response is dropped, for example, because the client went away
+#[test]
+fn dropping_the_body_cancels_pending_results() {
+    use crate::ext::futures::FutureExtension;
+
+    let mut res = QueryResults::empty(Trace::None);
+    let task = future::pending::<bool>().cancelable(res.tasks(), || true);
+    res.append_pending(None, future::pending().boxed());
+
+    let body = res.into_multipart_body();
+    drop(body);
+    assert!(futures03::executor::block_on(task));
+}
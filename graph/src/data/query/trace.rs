@@ -1,11 +1,15 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde::{ser::SerializeMap, Serialize};
 
 use crate::{
     components::store::{BlockNumber, QueryPermit},
     derive::CacheWeight,
-    prelude::{lazy_static, CheapClone},
+    prelude::{lazy_static, r, CheapClone},
 };
 
 use super::{CacheStatus, QueryExecutionError};
@@ -35,6 +39,21 @@ impl HttpTrace {
     }
 }
 
+/// The GraphQL field whose values a `Trace::Query` fetched. This is only
+/// used for the Apollo tracing format
+#[derive(Debug, CacheWeight)]
+pub struct FieldTrace {
+    pub parent_type: String,
+    pub field_name: String,
+    pub return_type: String,
+    /// When we started fetching the field, relative to the start of query
+    /// execution
+    pub start_offset: Duration,
+    /// How long fetching the field took, including building the SQL query
+    /// and waiting for a connection
+    pub duration: Duration,
+}
+
 #[derive(Debug, CacheWeight)]
 pub enum Trace {
     None,
@@ -72,6 +91,9 @@ pub enum Trace {
         conn_wait: Duration,
         permit_wait: Duration,
         entity_count: usize,
+        /// The field for which the query was run. This is set when query
+        /// execution has finished with the query
+        field: Option<FieldTrace>,
         /// Pairs of response key and traces. Each trace is either a `Trace::Query` or a `Trace::None`
         children: Vec<(String, Trace)>,
     },
@@ -172,10 +194,27 @@ impl Trace {
             conn_wait: Duration::from_millis(0),
             permit_wait: Duration::from_millis(0),
             entity_count,
+            field: None,
             children: Vec::new(),
         }
     }
 
+    /// Remember which GraphQL field this query fetched. Only traces of
+    /// queries have a field
+    pub fn field(&mut self, trace: FieldTrace) -> Result<(), QueryExecutionError> {
+        match self {
+            Trace::None => { /* nothing to do */ }
+            Trace::Root { .. } | Trace::Block { .. } => {
+                return Err(QueryExecutionError::ConstraintViolation(format!(
+                    "can not set the field `{}.{}` on the trace of a Root or Block",
+                    trace.parent_type, trace.field_name
+                )))
+            }
+            Trace::Query { field, .. } => *field = Some(trace),
+        }
+        Ok(())
+    }
+
     pub fn push(&mut self, name: &str, trace: Trace) {
         match (self, &trace) {
             (Self::Block { children, .. }, Self::Query { .. }) => {
@@ -254,6 +293,118 @@ impl Trace {
     }
 }
 
+/// Statistics for the Apollo tracing format
+#[derive(Default)]
+struct ApolloStats {
+    resolvers: Vec<r::Value>,
+    statements: usize,
+    entity_count: usize,
+    cache_hits: usize,
+    cache_misses: usize,
+}
+
+impl ApolloStats {
+    fn add(&mut self, trace: &Trace, path: &mut Vec<String>, base: Duration) {
+        use Trace::*;
+        match trace {
+            None => { /* nothing to do */ }
+            Root { blocks, .. } => {
+                for twc in blocks {
+                    // Traces of cached results were recorded when the
+                    // result was computed, not for this query
+                    if twc.cache_status.uses_database() {
+                        self.cache_misses += 1;
+                        self.add(&twc.trace, path, base);
+                    } else {
+                        self.cache_hits += 1;
+                    }
+                }
+            }
+            Block { children, .. } => {
+                for (key, trace) in children {
+                    path.push(key.clone());
+                    self.add(trace, path, base);
+                    path.pop();
+                }
+            }
+            Query {
+                entity_count,
+                field,
+                children,
+                ..
+            } => {
+                self.statements += 1;
+                self.entity_count += entity_count;
+                if let Some(field) = field {
+                    let path = path
+                        .iter()
+                        .map(|key| r::Value::String(key.clone()))
+                        .collect();
+                    self.resolvers.push(crate::object! {
+                        path: r::Value::List(path),
+                        parentType: field.parent_type.as_str(),
+                        fieldName: field.field_name.as_str(),
+                        returnType: field.return_type.as_str(),
+                        startOffset: nanos(base + field.start_offset),
+                        duration: nanos(field.duration),
+                    });
+                }
+                for (key, trace) in children {
+                    path.push(key.clone());
+                    self.add(trace, path, base);
+                    path.pop();
+                }
+            }
+        }
+    }
+}
+
+fn nanos(duration: Duration) -> r::Value {
+    r::Value::Int(duration.as_nanos().min(i64::MAX as u128) as i64)
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+impl Trace {
+    /// Convert this trace into the Apollo tracing format. The request
+    /// started at `start`, parsing it took `parsing` and handling it
+    /// overall took `total`. Resolver offsets are measured from the start
+    /// of query execution, which follows parsing. Besides the standard fields, the result
+    /// contains statistics about the SQL queries we ran and how many
+    /// results came from the query cache under `database`
+    pub fn apollo_tracing(
+        &self,
+        start: SystemTime,
+        parsing: Duration,
+        total: Duration,
+    ) -> r::Value {
+        let mut stats = ApolloStats::default();
+        stats.add(self, &mut Vec::new(), parsing);
+
+        crate::object! {
+            version: 1,
+            startTime: rfc3339(start),
+            endTime: rfc3339(start + total),
+            duration: nanos(total),
+            parsing: crate::object! {
+                startOffset: 0,
+                duration: nanos(parsing),
+            },
+            execution: crate::object! {
+                resolvers: r::Value::List(stats.resolvers),
+            },
+            database: crate::object! {
+                statements: stats.statements as i32,
+                entityCount: stats.entity_count as i32,
+                cacheHits: stats.cache_hits as i32,
+                cacheMisses: stats.cache_misses as i32,
+            },
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct QueryTotal {
     pub elapsed: Duration,
@@ -373,6 +524,7 @@ impl Serialize for Trace {
                 conn_wait,
                 permit_wait,
                 entity_count,
+                field: _,
                 children,
             } => {
                 let mut map = ser.serialize_map(Some(children.len() + 3))?;
@@ -48,11 +48,12 @@ pub mod call {
     use super::CheapClone;
 
     /// The return value of an ethereum call. `Null` indicates that we made
-    /// the call but didn't get a value back (including when we get the
-    /// error 'call reverted')
+    /// the call but didn't get a value back, and `Reverted` that the call
+    /// reverted, together with the reason the Ethereum node gave for that
     #[derive(Debug, Clone, PartialEq)]
     pub enum Retval {
         Null,
+        Reverted(String),
         Value(Bytes),
     }
 
@@ -62,6 +63,7 @@ pub mod call {
             match self {
                 Value(val) => val,
                 Null => panic!("called `call::Retval::unwrap()` on a `Null` value"),
+                Reverted(_) => panic!("called `call::Retval::unwrap()` on a `Reverted` value"),
             }
         }
     }
@@ -12,8 +12,10 @@ use std::str::FromStr;
 
 use crate::runtime::gas::{Gas, GasSizeOf};
 
+use anyhow::anyhow;
 use old_bigdecimal::BigDecimal as OldBigDecimal;
 pub use old_bigdecimal::ToPrimitive;
+use old_bigdecimal::Zero;
 
 use super::BigInt;
 
@@ -51,8 +53,6 @@ impl BigDecimal {
     }
 
     pub fn zero() -> BigDecimal {
-        use old_bigdecimal::Zero;
-
         BigDecimal(OldBigDecimal::zero())
     }
 
@@ -84,6 +84,240 @@ impl BigDecimal {
 
         BigDecimal(OldBigDecimal::new(int_val, scale))
     }
+
+    /// `self` raised to the power `exp`. Exponents that are 32 bit
+    /// integers are computed by repeated multiplication and work for any
+    /// `self`; other exponents are computed as `exp(exp * ln(self))` and
+    /// require a positive `self`.
+    ///
+    /// Like `sqrt`, `ln` and `exp`, this computes intermediate results with
+    /// `WORKING_PRECISION` significant digits and rounds the result to
+    /// `MAX_SIGNFICANT_DIGITS` like every other operation. In rare cases,
+    /// that makes the last digit differ from the correctly rounded result.
+    /// The result is an error if its exponent is outside of the range
+    /// that `BigDecimal` supports
+    pub fn pow(&self, exp: &BigDecimal) -> Result<BigDecimal, anyhow::Error> {
+        let zero = OldBigDecimal::zero();
+        let int_exp = match exp.as_bigint_and_exponent().1 <= 0 {
+            true => exp.0.to_i64().and_then(|exp| i32::try_from(exp).ok()),
+            false => None,
+        };
+
+        let result = match int_exp {
+            Some(exp) => {
+                if exp < 0 && self.0 == zero {
+                    return Err(anyhow!("cannot raise zero to the negative power {}", exp));
+                }
+                powi(&self.0, exp)
+            }
+            None if self.0 > zero => {
+                let arg = rounded(exp.0.clone() * ln(&self.0));
+                if arg > OldBigDecimal::from(EXP_LIMIT) {
+                    return Err(anyhow!("{} to the power {} is too large", self, exp));
+                }
+                if arg < OldBigDecimal::from(-EXP_LIMIT) {
+                    return Err(anyhow!("{} to the power {} is too small", self, exp));
+                }
+                self::exp(&arg)
+            }
+            None if self.0 == zero && exp.0 > zero => zero,
+            None => {
+                return Err(anyhow!(
+                    "cannot raise {} to the power {} since the power is not a 32 bit integer",
+                    self,
+                    exp
+                ))
+            }
+        };
+        BigDecimal::from(result).checked_exp()
+    }
+
+    /// The square root of `self`, which must not be negative. See `pow`
+    /// for its precision
+    pub fn sqrt(&self) -> Result<BigDecimal, anyhow::Error> {
+        let zero = OldBigDecimal::zero();
+        if self.0 < zero {
+            return Err(anyhow!("cannot take the square root of {}", self));
+        }
+        if self.0 == zero {
+            return Ok(BigDecimal::zero());
+        }
+        Ok(BigDecimal::from(sqrt(&self.0)))
+    }
+
+    /// The natural logarithm of `self`, which must be positive. See `pow`
+    /// for its precision
+    pub fn ln(&self) -> Result<BigDecimal, anyhow::Error> {
+        if self.0 <= OldBigDecimal::zero() {
+            return Err(anyhow!("cannot take the logarithm of {}", self));
+        }
+        Ok(BigDecimal::from(ln(&self.0)))
+    }
+
+    /// `e` raised to the power `self`. See `pow` for its precision
+    pub fn exp(&self) -> Result<BigDecimal, anyhow::Error> {
+        // The results for arguments beyond the limit can not be
+        // represented; checking them up front avoids computing with huge
+        // numbers
+        if self.0 > OldBigDecimal::from(EXP_LIMIT) || self.0 < OldBigDecimal::from(-EXP_LIMIT) {
+            return Err(anyhow!("exp({}) is out of range", self));
+        }
+        BigDecimal::from(exp(&self.0)).checked_exp()
+    }
+
+    /// Return `self` if its exponent is between `MIN_EXP` and `MAX_EXP`,
+    /// and an error otherwise
+    fn checked_exp(self) -> Result<BigDecimal, anyhow::Error> {
+        let exp = -self.as_bigint_and_exponent().1;
+        let min_exp: i64 = BigDecimal::MIN_EXP.into();
+        let max_exp: i64 = BigDecimal::MAX_EXP.into();
+        if exp < min_exp || max_exp < exp {
+            Err(anyhow!(
+                "big decimal exponent `{}` is outside the `{}` to `{}` range",
+                exp,
+                min_exp,
+                max_exp
+            ))
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// The number of significant digits for the intermediate results of
+/// `BigDecimal::pow`, `sqrt`, `ln` and `exp`. The digits beyond
+/// `MAX_SIGNFICANT_DIGITS` absorb the rounding errors of the intermediate
+/// steps
+const WORKING_PRECISION: u64 = 50;
+
+/// The most iterations that any of the series or Newton's method run.
+/// They all converge long before that; the limit only makes sure that they
+/// stop when the last digit keeps changing because of rounding
+const MAX_ITERATIONS: i64 = 1000;
+
+/// Arguments to `exp` beyond this lead to exponents that `BigDecimal` can
+/// not represent since `ln(10^6144)` is about 14147
+const EXP_LIMIT: i64 = 15_000;
+
+/// `ln(10)` with more digits than `WORKING_PRECISION`
+const LN_10: &str = "2.302585092994045684017991454684364207601101488628772976033327900967573";
+
+fn rounded(x: OldBigDecimal) -> OldBigDecimal {
+    x.with_prec(WORKING_PRECISION)
+}
+
+/// `x` raised to the power `exp` by repeated squaring
+fn powi(x: &OldBigDecimal, exp: i32) -> OldBigDecimal {
+    let one = OldBigDecimal::from(1);
+    let mut result = one.clone();
+    let mut base = x.clone();
+    let mut n = exp.unsigned_abs();
+    while n > 0 {
+        if n & 1 == 1 {
+            result = rounded(result * base.clone());
+        }
+        n >>= 1;
+        if n > 0 {
+            base = rounded(base.clone() * base);
+        }
+    }
+    match exp < 0 {
+        true => rounded(one / result),
+        false => result,
+    }
+}
+
+/// The square root of the positive `x` with Newton's method
+fn sqrt(x: &OldBigDecimal) -> OldBigDecimal {
+    let two = OldBigDecimal::from(2);
+
+    // Start from a power of ten within a factor of ten of the root
+    let magnitude = x.digits() as i64 - x.as_bigint_and_exponent().1;
+    let mut y = OldBigDecimal::new(1.into(), -magnitude.div_euclid(2));
+    for _ in 0..MAX_ITERATIONS {
+        let next = rounded((y.clone() + x.clone() / y.clone()) / two.clone());
+        if next == y {
+            break;
+        }
+        y = next;
+    }
+    y
+}
+
+/// The natural logarithm of the positive `x`
+fn ln(x: &OldBigDecimal) -> OldBigDecimal {
+    let one = OldBigDecimal::from(1);
+
+    // For `x` far from 1, split `x` into `m * 10^e` with `m` between 1 and
+    // 10 so that `ln(x) = ln(m) + e * ln(10)`. Doing that for `x` close to 1
+    // would lose precision when the two terms cancel out
+    let (mut m, e) = if x >= &OldBigDecimal::new(5.into(), 1) && x <= &OldBigDecimal::from(2) {
+        (x.clone(), 0)
+    } else {
+        let (int, scale) = x.as_bigint_and_exponent();
+        let e = x.digits() as i64 - scale - 1;
+        (OldBigDecimal::new(int, scale + e), e)
+    };
+
+    // Since `ln(m) = 2^k * ln(m^(1/2^k))`, take square roots until `m` is
+    // close enough to 1 for the series below to converge quickly
+    let mut k = 0;
+    while m < OldBigDecimal::new(9.into(), 1) || m > OldBigDecimal::new(11.into(), 1) {
+        m = sqrt(&m);
+        k += 1;
+    }
+
+    // ln(m) = 2 * (z + z^3/3 + z^5/5 + ...) with z = (m - 1) / (m + 1)
+    let z = rounded((m.clone() - one.clone()) / (m + one));
+    let z2 = rounded(z.clone() * z.clone());
+    let mut power = z.clone();
+    let mut sum = z;
+    for n in 1..MAX_ITERATIONS {
+        power = rounded(power * z2.clone());
+        let next = rounded(sum.clone() + power.clone() / OldBigDecimal::from(2 * n + 1));
+        if next == sum {
+            break;
+        }
+        sum = next;
+    }
+
+    let ln_m = sum * OldBigDecimal::from(2u64 << k);
+    let ln_10 = OldBigDecimal::from_str(LN_10).unwrap();
+    rounded(ln_m + ln_10 * OldBigDecimal::from(e))
+}
+
+/// `e` raised to the power `x`, where `x` is at most `EXP_LIMIT` in
+/// absolute value
+fn exp(x: &OldBigDecimal) -> OldBigDecimal {
+    let half = OldBigDecimal::new(5.into(), 1);
+    let minus_half = OldBigDecimal::new((-5).into(), 1);
+    let two = OldBigDecimal::from(2);
+
+    // Since `exp(x) = exp(x / 2^k)^(2^k)`, halve `x` until the series
+    // below converges quickly
+    let mut r = x.clone();
+    let mut k = 0;
+    while r > half || r < minus_half {
+        r = rounded(r / two.clone());
+        k += 1;
+    }
+
+    // exp(r) = 1 + r + r^2/2! + r^3/3! + ...
+    let mut term = OldBigDecimal::from(1);
+    let mut sum = term.clone();
+    for n in 1..MAX_ITERATIONS {
+        term = rounded(term * r.clone() / OldBigDecimal::from(n));
+        let next = rounded(sum.clone() + term.clone());
+        if next == sum {
+            break;
+        }
+        sum = next;
+    }
+
+    for _ in 0..k {
+        sum = rounded(sum.clone() * sum);
+    }
+    sum
 }
 
 impl Display for BigDecimal {
@@ -627,6 +861,46 @@ mod test {
         }
     }
 
+    #[test]
+    fn big_decimal_math() {
+        fn dec(s: &str) -> BigDecimal {
+            BigDecimal::from_str(s).unwrap()
+        }
+
+        let sqrt_2 = dec("1.414213562373095048801688724209698");
+        assert_eq!(sqrt_2, dec("2").sqrt().unwrap());
+        assert_eq!(dec("0.02"), dec("0.0004").sqrt().unwrap());
+        assert_eq!(BigDecimal::zero(), dec("0").sqrt().unwrap());
+        assert!(dec("-1").sqrt().is_err());
+
+        assert_eq!(
+            dec("2.302585092994045684017991454684364"),
+            dec("10").ln().unwrap()
+        );
+        assert_eq!(
+            dec("-46.05170185988091368035982909368728"),
+            dec("1e-20").ln().unwrap()
+        );
+        assert_eq!(BigDecimal::zero(), dec("1").ln().unwrap());
+        assert!(dec("0").ln().is_err());
+
+        assert_eq!(
+            dec("2.718281828459045235360287471352662"),
+            dec("1").exp().unwrap()
+        );
+        assert_eq!(dec("1"), dec("0").exp().unwrap());
+        assert!(dec("20000").exp().is_err());
+
+        assert_eq!(dec("3.375"), dec("1.5").pow(&dec("3")).unwrap());
+        assert_eq!(dec("0.125"), dec("2").pow(&dec("-3")).unwrap());
+        assert_eq!(dec("-8"), dec("-2").pow(&dec("3")).unwrap());
+        assert_eq!(dec("1"), dec("-2").pow(&dec("0")).unwrap());
+        assert_eq!(sqrt_2, dec("2").pow(&dec("0.5")).unwrap());
+        assert!(dec("0").pow(&dec("-1")).is_err());
+        assert!(dec("-2").pow(&dec("0.5")).is_err());
+        assert!(dec("10").pow(&dec("7000")).is_err());
+    }
+
     #[test]
     fn test_normalize() {
         let vals = vec![
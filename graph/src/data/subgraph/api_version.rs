@@ -54,8 +54,17 @@ pub const SPEC_VERSION_1_1_0: Version = Version::new(1, 1, 0);
 // Enables eth call declarations and indexed arguments(topics) filtering in manifest
 pub const SPEC_VERSION_1_2_0: Version = Version::new(1, 2, 0);
 
+// Enables subgraph data sources
+pub const SPEC_VERSION_1_3_0: Version = Version::new(1, 3, 0);
+
+// Enables `file/https` data sources
+pub const SPEC_VERSION_1_4_0: Version = Version::new(1, 4, 0);
+
+// Enables metering handlers with fuel
+pub const SPEC_VERSION_1_5_0: Version = Version::new(1, 5, 0);
+
 // The latest spec version available
-pub const LATEST_VERSION: &Version = &SPEC_VERSION_1_2_0;
+pub const LATEST_VERSION: &Version = &SPEC_VERSION_1_5_0;
 
 pub const MIN_SPEC_VERSION: Version = Version::new(0, 0, 2);
 
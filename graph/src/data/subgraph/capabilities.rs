@@ -0,0 +1,356 @@
+//! Check a manifest against the capabilities of this node.
+//!
+//! Before a manifest is resolved, [`check_capabilities`] looks at the raw
+//! manifest and lists everything it asks for that this node can not
+//! provide: a `specVersion` outside of the supported range, unknown or
+//! disabled `features`, data source kinds this build does not know,
+//! data source kinds that need a newer `specVersion`, and networks that
+//! are not configured. [`check_schema_capabilities`] does the same for
+//! the schema. The result is a list of [`UnsupportedFeature`] that is
+//! reported to the deployer as a whole, rather than failing on the first
+//! problem somewhere during resolution or, worse, at runtime.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use itertools::Itertools;
+use semver::Version;
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::blockchain::{BlockchainKind, BlockchainMap};
+use crate::data::graphql::ext::DirectiveFinder as _;
+use crate::data::graphql::DocumentExt as _;
+use crate::data_source::offchain::OFFCHAIN_KINDS;
+use crate::data_source::subgraph::SUBGRAPH_DS_KIND;
+use crate::env::ENV_VARS;
+use crate::schema::kw;
+
+use super::{SubgraphFeature, MIN_SPEC_VERSION, SPEC_VERSION_1_1_0, SPEC_VERSION_1_3_0};
+
+/// Something a manifest asks for that this node does not support
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum UnsupportedFeature {
+    /// The `specVersion` is not in the range this node supports
+    #[serde(rename_all = "camelCase")]
+    SpecVersion {
+        declared: String,
+        min: String,
+        max: String,
+    },
+    /// An entry in `features` is unknown or disabled on this node
+    #[serde(rename_all = "camelCase")]
+    Feature { name: String, reason: String },
+    /// This build does not know the `kind` of a data source or template
+    #[serde(rename_all = "camelCase")]
+    DataSourceKind {
+        data_source: String,
+        kind: String,
+        supported: Vec<String>,
+    },
+    /// The `kind` of a data source or template requires a newer
+    /// `specVersion` than the manifest declares
+    #[serde(rename_all = "camelCase")]
+    DataSourceSpecVersion {
+        data_source: String,
+        kind: String,
+        required: String,
+        declared: String,
+    },
+    /// No chain for the `network` of a data source is configured
+    #[serde(rename_all = "camelCase")]
+    Network {
+        data_source: String,
+        kind: String,
+        network: String,
+    },
+    /// The schema uses aggregations, which require a newer `specVersion`
+    /// than the manifest declares
+    #[serde(rename_all = "camelCase")]
+    Aggregations { required: String, declared: String },
+}
+
+impl fmt::Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use UnsupportedFeature::*;
+
+        match self {
+            SpecVersion { declared, min, max } => write!(
+                f,
+                "specVersion `{declared}` is not supported, this node supports {min} to {max}"
+            ),
+            Feature { name, reason } => write!(f, "feature `{name}` is not supported: {reason}"),
+            DataSourceKind {
+                data_source,
+                kind,
+                supported,
+            } => write!(
+                f,
+                "data source `{data_source}` has kind `{kind}` which this node does not support, supported kinds are {}",
+                supported.join(", ")
+            ),
+            DataSourceSpecVersion {
+                data_source,
+                kind,
+                required,
+                declared,
+            } => write!(
+                f,
+                "data source `{data_source}` of kind `{kind}` requires specVersion {required} or later, but the manifest declares {declared}"
+            ),
+            Network {
+                data_source,
+                kind,
+                network,
+            } => write!(
+                f,
+                "data source `{data_source}` uses network `{network}` for {kind} which is not configured on this node"
+            ),
+            Aggregations { required, declared } => write!(
+                f,
+                "the schema uses aggregations which require specVersion {required} or later, but the manifest declares {declared}"
+            ),
+        }
+    }
+}
+
+fn get<'a>(map: &'a serde_yaml::Mapping, key: &str) -> Option<&'a Value> {
+    map.get(&Value::String(key.to_owned()))
+}
+
+fn get_str<'a>(map: &'a serde_yaml::Mapping, key: &str) -> Option<&'a str> {
+    get(map, key).and_then(Value::as_str)
+}
+
+/// The kinds of data sources this build supports, for error messages
+fn supported_kinds() -> Vec<String> {
+    BlockchainKind::ALL
+        .iter()
+        .map(ToString::to_string)
+        .chain(OFFCHAIN_KINDS.keys().sorted().map(ToString::to_string))
+        .chain(std::iter::once(SUBGRAPH_DS_KIND.to_string()))
+        .collect()
+}
+
+fn check_feature(name: &str) -> Option<UnsupportedFeature> {
+    let reason = match SubgraphFeature::from_str(name) {
+        Ok(SubgraphFeature::IpfsOnEthereumContracts)
+            if !ENV_VARS.mappings.allow_non_deterministic_ipfs =>
+        {
+            "this node does not allow IPFS calls from onchain handlers \
+             (see `GRAPH_ALLOW_NON_DETERMINISTIC_IPFS`)"
+                .to_string()
+        }
+        Ok(_) => return None,
+        Err(_) => format!(
+            "unknown feature, supported features are {}",
+            SubgraphFeature::ALL.iter().join(", ")
+        ),
+    };
+    Some(UnsupportedFeature::Feature {
+        name: name.to_string(),
+        reason,
+    })
+}
+
+/// Check the data source or template `ds` and add anything about it
+/// that this node does not support to `unsupported`
+fn check_data_source(
+    ds: &serde_yaml::Mapping,
+    spec_version: Option<&Version>,
+    chains: &BlockchainMap,
+    unsupported: &mut BTreeSet<UnsupportedFeature>,
+) {
+    let name = get_str(ds, "name").unwrap_or("?").to_string();
+    let kind = get_str(ds, "kind").unwrap_or("?").to_string();
+
+    let required = if let Some(offchain) = OFFCHAIN_KINDS.get(kind.as_str()) {
+        Some(offchain.min_spec_version())
+    } else if kind == SUBGRAPH_DS_KIND {
+        Some(SPEC_VERSION_1_3_0)
+    } else {
+        // Onchain kinds look like `ethereum/contract`
+        let chain = kind.split('/').next().unwrap_or_default();
+        match BlockchainKind::from_str(chain) {
+            Ok(chain_kind) => {
+                if let Some(network) = get_str(ds, "network") {
+                    if !chains.has_network(chain_kind, network) {
+                        unsupported.insert(UnsupportedFeature::Network {
+                            data_source: name.clone(),
+                            kind: chain_kind.to_string(),
+                            network: network.to_string(),
+                        });
+                    }
+                }
+            }
+            Err(_) => {
+                unsupported.insert(UnsupportedFeature::DataSourceKind {
+                    data_source: name.clone(),
+                    kind: kind.clone(),
+                    supported: supported_kinds(),
+                });
+            }
+        }
+        None
+    };
+
+    if let (Some(required), Some(declared)) = (required, spec_version) {
+        if declared < &required {
+            unsupported.insert(UnsupportedFeature::DataSourceSpecVersion {
+                data_source: name,
+                kind,
+                required: required.to_string(),
+                declared: declared.to_string(),
+            });
+        }
+    }
+}
+
+/// Check the raw manifest `raw` against what this node supports and
+/// return everything it does not support. Malformed parts of the manifest
+/// are not reported here; resolving the manifest reports them.
+pub fn check_capabilities(
+    raw: &serde_yaml::Mapping,
+    max_spec_version: &Version,
+    chains: &BlockchainMap,
+) -> Vec<UnsupportedFeature> {
+    let mut unsupported = BTreeSet::new();
+
+    let declared = get_str(raw, "specVersion").unwrap_or_default();
+    let spec_version = Version::parse(declared).ok();
+    match &spec_version {
+        Some(version) if (MIN_SPEC_VERSION..=max_spec_version.clone()).contains(version) => {}
+        _ => {
+            unsupported.insert(UnsupportedFeature::SpecVersion {
+                declared: declared.to_string(),
+                min: MIN_SPEC_VERSION.to_string(),
+                max: max_spec_version.to_string(),
+            });
+        }
+    }
+
+    let features = get(raw, "features")
+        .and_then(Value::as_sequence)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str);
+    unsupported.extend(features.filter_map(check_feature));
+
+    let data_sources = ["dataSources", "templates"]
+        .into_iter()
+        .filter_map(|key| get(raw, key).and_then(Value::as_sequence))
+        .flatten()
+        .filter_map(Value::as_mapping);
+    for ds in data_sources {
+        check_data_source(ds, spec_version.as_ref(), chains, &mut unsupported);
+    }
+
+    unsupported.into_iter().collect()
+}
+
+/// Check the GraphQL schema `schema` of a manifest with spec version
+/// `spec_version` against what this node supports. A schema that can not
+/// be parsed is not reported here; resolving the manifest reports it.
+pub fn check_schema_capabilities(schema: &str, spec_version: &Version) -> Vec<UnsupportedFeature> {
+    let document = match graphql_parser::parse_schema::<String>(schema) {
+        Ok(document) => document.into_static(),
+        Err(_) => return vec![],
+    };
+    let has_aggregations = document
+        .get_object_type_definitions()
+        .into_iter()
+        .any(|obj_type| obj_type.find_directive(kw::AGGREGATION).is_some());
+
+    if has_aggregations && spec_version < &SPEC_VERSION_1_1_0 {
+        vec![UnsupportedFeature::Aggregations {
+            required: SPEC_VERSION_1_1_0.to_string(),
+            declared: spec_version.to_string(),
+        }]
+    } else {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(manifest: &str) -> Vec<UnsupportedFeature> {
+        let raw: serde_yaml::Mapping = serde_yaml::from_str(manifest).unwrap();
+        check_capabilities(&raw, &Version::new(1, 2, 0), &BlockchainMap::new())
+    }
+
+    #[test]
+    fn reports_everything_unsupported() {
+        const MANIFEST: &str = "
+specVersion: 1.2.0
+features:
+  - fullTextSearch
+  - teleportation
+dataSources:
+  - kind: ethereum/contract
+    name: Token
+    network: mainnet
+  - kind: solana
+    name: Sol
+templates:
+  - kind: file/https
+    name: Metadata
+  - kind: file/ipfs
+    name: Image
+";
+        let unsupported = check(MANIFEST);
+        assert_eq!(4, unsupported.len(), "{:?}", unsupported);
+        assert!(unsupported.iter().any(
+            |u| matches!(u, UnsupportedFeature::Feature { name, .. } if name == "teleportation")
+        ));
+        assert!(unsupported.contains(&UnsupportedFeature::Network {
+            data_source: "Token".to_string(),
+            kind: "ethereum".to_string(),
+            network: "mainnet".to_string()
+        }));
+        assert!(unsupported.iter().any(
+            |u| matches!(u, UnsupportedFeature::DataSourceKind { kind, .. } if kind == "solana")
+        ));
+        assert!(
+            unsupported.contains(&UnsupportedFeature::DataSourceSpecVersion {
+                data_source: "Metadata".to_string(),
+                kind: "file/https".to_string(),
+                required: "1.4.0".to_string(),
+                declared: "1.2.0".to_string()
+            })
+        );
+
+        let json = serde_json::to_value(&unsupported[0]).unwrap();
+        assert!(json.get("type").is_some());
+    }
+
+    #[test]
+    fn spec_version_out_of_range() {
+        let unsupported = check("specVersion: 1.3.0\ndataSources: []\n");
+        assert_eq!(
+            vec![UnsupportedFeature::SpecVersion {
+                declared: "1.3.0".to_string(),
+                min: "0.0.2".to_string(),
+                max: "1.2.0".to_string()
+            }],
+            unsupported
+        );
+    }
+
+    #[test]
+    fn aggregations() {
+        const SCHEMA: &str = "
+type Data @entity(timeseries: true) { id: Int8! timestamp: Timestamp! price: BigDecimal! }
+type Stats @aggregation(intervals: [\"hour\"], source: \"Data\") {
+  id: Int8! timestamp: Timestamp! sum: BigDecimal! @aggregate(fn: \"sum\", arg: \"price\")
+}";
+        assert_eq!(
+            1,
+            check_schema_capabilities(SCHEMA, &Version::new(1, 0, 0)).len()
+        );
+        assert!(check_schema_capabilities(SCHEMA, &SPEC_VERSION_1_1_0).is_empty());
+    }
+}
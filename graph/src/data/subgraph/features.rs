@@ -24,8 +24,13 @@ use super::calls_host_fn;
 /// This array must contain all IPFS-related functions that are exported by the host WASM runtime.
 ///
 /// For reference, search this codebase for: ff652476-e6ad-40e4-85b8-e815d6c6e5e2
-const IPFS_ON_ETHEREUM_CONTRACTS_FUNCTION_NAMES: [&str; 3] =
-    ["ipfs.cat", "ipfs.getBlock", "ipfs.map"];
+const IPFS_ON_ETHEREUM_CONTRACTS_FUNCTION_NAMES: [&str; 5] = [
+    "ipfs.cat",
+    "ipfs.catBounded",
+    "ipfs.exists",
+    "ipfs.getBlock",
+    "ipfs.map",
+];
 
 #[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "camelCase")]
@@ -37,6 +42,16 @@ pub enum SubgraphFeature {
     IpfsOnEthereumContracts,
 }
 
+impl SubgraphFeature {
+    /// All features this node knows about
+    pub const ALL: [SubgraphFeature; 4] = [
+        SubgraphFeature::NonFatalErrors,
+        SubgraphFeature::Grafting,
+        SubgraphFeature::FullTextSearch,
+        SubgraphFeature::IpfsOnEthereumContracts,
+    ];
+}
+
 impl fmt::Display for SubgraphFeature {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         serde_plain::to_string(self)
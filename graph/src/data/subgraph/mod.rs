@@ -5,9 +5,11 @@ pub mod schema;
 pub mod api_version;
 pub use api_version::*;
 
+pub mod capabilities;
 pub mod features;
 pub mod status;
 
+pub use capabilities::UnsupportedFeature;
 pub use features::{SubgraphFeature, SubgraphFeatureValidationError};
 
 use crate::{cheap_clone::CheapClone, components::store::BLOCK_NUMBER_MAX, object};
@@ -24,8 +26,9 @@ use slog::Logger;
 use stable_hash::{FieldAddress, StableHash};
 use stable_hash_legacy::SequenceNumber;
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     marker::PhantomData,
+    time::Duration,
 };
 use thiserror::Error;
 use wasmparser;
@@ -49,6 +52,7 @@ use crate::{
     derive::CacheWeight,
     ensure,
     prelude::{r, Value, ENV_VARS},
+    runtime::gas::{default_fuel_per_handler, CONST_MAX_GAS_PER_HANDLER},
     schema::{InputSchema, SchemaValidationError},
 };
 
@@ -294,6 +298,8 @@ pub enum SubgraphRegistrarError {
     StoreError(StoreError),
     #[error("subgraph validation error: {}", display_vector(.0))]
     ManifestValidationError(Vec<SubgraphManifestValidationError>),
+    #[error("subgraph uses features this node does not support: {}", display_vector(.0))]
+    UnsupportedFeatures(Vec<UnsupportedFeature>),
     #[error("subgraph deployment error: {0}")]
     SubgraphDeploymentError(StoreError),
     #[error("subgraph registrar error: {0}")]
@@ -354,6 +360,16 @@ pub enum SubgraphManifestValidationError {
     FeatureValidationError(#[from] SubgraphFeatureValidationError),
     #[error("data source {0} is invalid: {1}")]
     DataSourceValidation(String, Error),
+    #[error("indexerHints.pruneByEntity mentions unknown entity type {0}")]
+    UnknownPruneEntity(String),
+    #[error("indexerHints.storageByEntity mentions unknown entity type {0}")]
+    UnknownStorageEntity(String),
+    #[error("indexerHints.storageByEntity has invalid storage parameters for {0}: {1}")]
+    InvalidStorageParams(String, Error),
+    #[error("indexerHints.handlerLimits has invalid limits for handler {0}: {1}")]
+    InvalidHandlerLimits(String, Error),
+    #[error("indexerHints.fuel must be at least 1")]
+    InvalidFuel,
 }
 
 #[derive(Error, Debug)]
@@ -578,13 +594,119 @@ pub struct BaseSubgraphManifest<C, S, D, T> {
 #[serde(rename_all = "camelCase")]
 pub struct IndexerHints {
     prune: Option<Prune>,
+    /// The entity types whose most recently written entities should be
+    /// loaded into the entity cache when indexing starts
+    warmup: Option<Vec<String>>,
+    /// How much history to keep for individual entity types; entity
+    /// types that are not mentioned keep the history given by `prune`
+    prune_by_entity: Option<BTreeMap<String, Prune>>,
+    /// Postgres storage parameters for the tables of individual entity
+    /// types
+    storage_by_entity: Option<BTreeMap<String, StorageParams>>,
+    /// Limits on the time and gas that individual handlers may use
+    handler_limits: Option<BTreeMap<String, HandlerLimits>>,
+    /// What to do when individual handlers fail with a deterministic
+    /// error
+    error_policy: Option<BTreeMap<String, ErrorPolicy>>,
+    /// The fuel that each invocation of a handler may use; overrides
+    /// the default for the network
+    fuel: Option<u64>,
 }
 
 impl IndexerHints {
+    /// The history the deployment as a whole keeps. That is the longest
+    /// history that any of its entity types keeps
     pub fn history_blocks(&self) -> BlockNumber {
-        match self.prune {
+        let default = match self.prune {
             Some(ref hb) => hb.history_blocks(),
             None => BLOCK_NUMBER_MAX,
+        };
+        self.prune_by_entity
+            .iter()
+            .flat_map(|prune| prune.values())
+            .map(Prune::history_blocks)
+            .fold(default, BlockNumber::max)
+    }
+
+    /// The entity types that keep less history than the deployment as a
+    /// whole, together with how many blocks of history they keep
+    pub fn entity_history_blocks(&self) -> BTreeMap<String, BlockNumber> {
+        let history_blocks = self.history_blocks();
+        self.prune_by_entity
+            .iter()
+            .flat_map(|prune| prune.iter())
+            .map(|(entity, prune)| (entity.clone(), prune.history_blocks()))
+            .filter(|(_, blocks)| *blocks < history_blocks)
+            .collect()
+    }
+
+    pub fn prune_entity_types(&self) -> impl Iterator<Item = &String> {
+        self.prune_by_entity.iter().flat_map(|prune| prune.keys())
+    }
+
+    pub fn warmup(&self) -> Option<&[String]> {
+        self.warmup.as_deref()
+    }
+
+    pub fn storage_by_entity(&self) -> impl Iterator<Item = (&String, &StorageParams)> {
+        self.storage_by_entity
+            .iter()
+            .flat_map(|storage| storage.iter())
+    }
+
+    pub fn handler_limits(&self) -> impl Iterator<Item = (&String, &HandlerLimits)> {
+        self.handler_limits.iter().flat_map(|limits| limits.iter())
+    }
+
+    pub fn error_policy(&self) -> impl Iterator<Item = (&String, &ErrorPolicy)> {
+        self.error_policy.iter().flat_map(|policy| policy.iter())
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+}
+
+/// How a deterministic error in a handler is handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// The error is a subgraph error like any other; without the
+    /// `nonFatalErrors` feature, it fails the deployment
+    #[default]
+    Fail,
+    /// The changes of the handler are discarded and the trigger is
+    /// recorded as skipped; indexing continues as if the handler had not
+    /// run
+    Skip,
+}
+
+/// Limits on what one invocation of a handler may use. Only running out of
+/// gas is a deterministic error for that handler; a timeout is retried
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct HandlerLimits {
+    /// The wall-clock time in seconds
+    pub timeout: Option<u64>,
+    /// The gas the handler may use; it can only be lower than the gas
+    /// limit that applies to all handlers
+    pub gas: Option<u64>,
+}
+
+impl HandlerLimits {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.timeout == Some(0) {
+            return Err(anyhow!("the `timeout` must be at least 1 second"));
+        }
+        match self.gas {
+            Some(gas) if gas == 0 || gas > CONST_MAX_GAS_PER_HANDLER => Err(anyhow!(
+                "the `gas` must be between 1 and {CONST_MAX_GAS_PER_HANDLER}"
+            )),
+            _ => Ok(()),
         }
     }
 }
@@ -656,6 +778,155 @@ impl<'de> de::Deserialize<'de> for Prune {
     }
 }
 
+/// Postgres storage parameters for the table of an entity type. Tables
+/// whose entities are updated frequently benefit from a lower `fillfactor`
+/// and more aggressive autovacuum settings than tables that are only
+/// appended to
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct StorageParams {
+    pub fillfactor: Option<i32>,
+    pub toast_tuple_target: Option<i32>,
+    pub autovacuum_vacuum_threshold: Option<i32>,
+    pub autovacuum_vacuum_scale_factor: Option<f64>,
+    pub autovacuum_analyze_threshold: Option<i32>,
+    pub autovacuum_analyze_scale_factor: Option<f64>,
+}
+
+impl StorageParams {
+    /// The names of the storage parameters as Postgres knows them
+    pub const NAMES: [&'static str; 6] = [
+        "fillfactor",
+        "toast_tuple_target",
+        "autovacuum_vacuum_threshold",
+        "autovacuum_vacuum_scale_factor",
+        "autovacuum_analyze_threshold",
+        "autovacuum_analyze_scale_factor",
+    ];
+
+    /// The parameters that are set as `(name, value)` pairs, using the
+    /// names that Postgres uses
+    pub fn options(&self) -> Vec<(&'static str, String)> {
+        let ints = [
+            ("fillfactor", self.fillfactor),
+            ("toast_tuple_target", self.toast_tuple_target),
+            (
+                "autovacuum_vacuum_threshold",
+                self.autovacuum_vacuum_threshold,
+            ),
+            (
+                "autovacuum_analyze_threshold",
+                self.autovacuum_analyze_threshold,
+            ),
+        ];
+        let floats = [
+            (
+                "autovacuum_vacuum_scale_factor",
+                self.autovacuum_vacuum_scale_factor,
+            ),
+            (
+                "autovacuum_analyze_scale_factor",
+                self.autovacuum_analyze_scale_factor,
+            ),
+        ];
+        ints.into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value.to_string())))
+            .chain(
+                floats
+                    .into_iter()
+                    .filter_map(|(name, value)| value.map(|value| (name, value.to_string()))),
+            )
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.options().is_empty()
+    }
+
+    /// Set the parameter `name`, using the name that Postgres uses, to
+    /// `value`
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        fn int(name: &str, value: &str) -> Result<Option<i32>, Error> {
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| anyhow!("the value `{value}` for `{name}` must be an integer"))
+        }
+
+        fn float(name: &str, value: &str) -> Result<Option<f64>, Error> {
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| anyhow!("the value `{value}` for `{name}` must be a number"))
+        }
+
+        match name {
+            "fillfactor" => self.fillfactor = int(name, value)?,
+            "toast_tuple_target" => self.toast_tuple_target = int(name, value)?,
+            "autovacuum_vacuum_threshold" => self.autovacuum_vacuum_threshold = int(name, value)?,
+            "autovacuum_vacuum_scale_factor" => {
+                self.autovacuum_vacuum_scale_factor = float(name, value)?
+            }
+            "autovacuum_analyze_threshold" => self.autovacuum_analyze_threshold = int(name, value)?,
+            "autovacuum_analyze_scale_factor" => {
+                self.autovacuum_analyze_scale_factor = float(name, value)?
+            }
+            _ => {
+                return Err(anyhow!(
+                    "unknown storage parameter `{name}`, must be one of {}",
+                    Self::NAMES.join(", ")
+                ))
+            }
+        }
+        self.validate()
+    }
+
+    /// Check that all parameters are within the ranges that Postgres
+    /// accepts
+    pub fn validate(&self) -> Result<(), Error> {
+        fn check<T: PartialOrd + fmt::Display>(
+            name: &str,
+            value: Option<T>,
+            min: T,
+            max: T,
+        ) -> Result<(), Error> {
+            match value {
+                Some(value) if value < min || value > max => Err(anyhow!(
+                    "the value {value} for `{name}` must be between {min} and {max}"
+                )),
+                _ => Ok(()),
+            }
+        }
+
+        check("fillfactor", self.fillfactor, 10, 100)?;
+        check("toast_tuple_target", self.toast_tuple_target, 128, 8160)?;
+        check(
+            "autovacuum_vacuum_threshold",
+            self.autovacuum_vacuum_threshold,
+            0,
+            i32::MAX,
+        )?;
+        check(
+            "autovacuum_vacuum_scale_factor",
+            self.autovacuum_vacuum_scale_factor,
+            0.0,
+            100.0,
+        )?;
+        check(
+            "autovacuum_analyze_threshold",
+            self.autovacuum_analyze_threshold,
+            0,
+            i32::MAX,
+        )?;
+        check(
+            "autovacuum_analyze_scale_factor",
+            self.autovacuum_analyze_scale_factor,
+            0.0,
+            100.0,
+        )
+    }
+}
+
 /// SubgraphManifest with IPFS links unresolved
 pub type UnresolvedSubgraphManifest<C> = BaseSubgraphManifest<
     C,
@@ -735,6 +1006,52 @@ impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
             }
         }
 
+        if let Some(hints) = &self.0.indexer_hints {
+            for entity in hints.prune_entity_types() {
+                let known = self
+                    .0
+                    .schema
+                    .entity_type(entity.as_str())
+                    .map(|entity_type| entity_type.is_object_type())
+                    .unwrap_or(false);
+                if !known {
+                    errors.push(SubgraphManifestValidationError::UnknownPruneEntity(
+                        entity.clone(),
+                    ));
+                }
+            }
+            for (entity, params) in hints.storage_by_entity() {
+                let known = self
+                    .0
+                    .schema
+                    .entity_type(entity.as_str())
+                    .map(|entity_type| entity_type.is_object_type())
+                    .unwrap_or(false);
+                if !known {
+                    errors.push(SubgraphManifestValidationError::UnknownStorageEntity(
+                        entity.clone(),
+                    ));
+                }
+                if let Err(e) = params.validate() {
+                    errors.push(SubgraphManifestValidationError::InvalidStorageParams(
+                        entity.clone(),
+                        e,
+                    ));
+                }
+            }
+            for (handler, limits) in hints.handler_limits() {
+                if let Err(e) = limits.validate() {
+                    errors.push(SubgraphManifestValidationError::InvalidHandlerLimits(
+                        handler.clone(),
+                        e,
+                    ));
+                }
+            }
+            if hints.fuel() == Some(0) {
+                errors.push(SubgraphManifestValidationError::InvalidFuel);
+            }
+        }
+
         // Validate subgraph feature usage and declaration.
         if self.0.spec_version >= SPEC_VERSION_0_0_4 {
             if let Err(feature_validation_error) = validate_subgraph_features(&self.0) {
@@ -793,6 +1110,70 @@ impl<C: Blockchain> SubgraphManifest<C> {
         }
     }
 
+    /// The entity types that keep less history than `history_blocks`
+    /// because of `indexerHints.pruneByEntity`
+    pub fn entity_history_blocks(&self) -> BTreeMap<String, BlockNumber> {
+        self.indexer_hints
+            .as_ref()
+            .map(|hints| hints.entity_history_blocks())
+            .unwrap_or_default()
+    }
+
+    /// The storage parameters for entity tables from
+    /// `indexerHints.storageByEntity`
+    pub fn entity_storage_params(&self) -> BTreeMap<String, StorageParams> {
+        self.indexer_hints
+            .iter()
+            .flat_map(|hints| hints.storage_by_entity())
+            .filter(|(_, params)| !params.is_empty())
+            .map(|(entity, params)| (entity.clone(), params.clone()))
+            .collect()
+    }
+
+    /// The limits for individual handlers from
+    /// `indexerHints.handlerLimits`, keyed by handler name
+    pub fn handler_limits(&self) -> BTreeMap<String, HandlerLimits> {
+        self.indexer_hints
+            .iter()
+            .flat_map(|hints| hints.handler_limits())
+            .map(|(handler, limits)| (handler.clone(), limits.clone()))
+            .collect()
+    }
+
+    /// The fuel that each invocation of a handler may use. That is
+    /// `indexerHints.fuel` if the manifest sets it, and the default for
+    /// the network otherwise. Handlers of subgraphs with a spec version
+    /// before 1.5.0 are not metered with fuel, since that would make
+    /// handlers that used to succeed fail
+    pub fn fuel(&self) -> Option<u64> {
+        if self.spec_version < SPEC_VERSION_1_5_0 {
+            return None;
+        }
+        let fuel = self
+            .indexer_hints
+            .as_ref()
+            .and_then(|hints| hints.fuel())
+            .unwrap_or_else(|| default_fuel_per_handler(&self.network_name()));
+        Some(fuel)
+    }
+
+    /// The handlers whose deterministic errors should be skipped according
+    /// to `indexerHints.errorPolicy`
+    pub fn skip_error_handlers(&self) -> BTreeSet<String> {
+        self.indexer_hints
+            .iter()
+            .flat_map(|hints| hints.error_policy())
+            .filter(|(_, policy)| **policy == ErrorPolicy::Skip)
+            .map(|(handler, _)| handler.clone())
+            .collect()
+    }
+
+    /// The names of the entity types the subgraph author asked to warm up
+    /// the entity cache with, or `None` if they did not specify any
+    pub fn warmup_entity_types(&self) -> Option<&[String]> {
+        self.indexer_hints.as_ref().and_then(|hints| hints.warmup())
+    }
+
     pub fn api_versions(&self) -> impl Iterator<Item = semver::Version> + '_ {
         self.templates
             .iter()
@@ -999,6 +1380,18 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
             );
         }
 
+        if spec_version < SPEC_VERSION_1_5_0
+            && indexer_hints
+                .as_ref()
+                .and_then(|hints| hints.fuel())
+                .is_some()
+        {
+            bail!(
+                "`indexerHints.fuel` is not supported prior to {}",
+                SPEC_VERSION_1_5_0
+            );
+        }
+
         // Check the min_spec_version of each data source against the spec version of the subgraph
         let min_spec_version_mismatch = data_sources
             .iter()
@@ -4,7 +4,7 @@ use anyhow::{anyhow, bail, Error};
 use hex;
 use rand::rngs::OsRng;
 use rand::Rng;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 use std::{fmt, fmt::Display};
 
@@ -12,7 +12,7 @@ use super::DeploymentHash;
 use crate::blockchain::Blockchain;
 use crate::data::graphql::TryFromValue;
 use crate::data::store::Value;
-use crate::data::subgraph::SubgraphManifest;
+use crate::data::subgraph::{StorageParams, SubgraphManifest};
 use crate::prelude::*;
 use crate::schema::EntityType;
 use crate::util::stable_hash_glue::impl_stable_hash;
@@ -106,6 +106,12 @@ pub struct DeploymentCreate {
     pub graft_block: Option<BlockPtr>,
     pub debug_fork: Option<DeploymentHash>,
     pub history_blocks_override: Option<i32>,
+    /// The entity types that keep less history than the deployment, see
+    /// `SubgraphManifest::entity_history_blocks`
+    pub entity_history_blocks: BTreeMap<String, BlockNumber>,
+    /// The storage parameters for entity tables, see
+    /// `SubgraphManifest::entity_storage_params`
+    pub entity_storage_params: BTreeMap<String, StorageParams>,
 }
 
 impl DeploymentCreate {
@@ -121,11 +127,16 @@ impl DeploymentCreate {
             graft_block: None,
             debug_fork: None,
             history_blocks_override: None,
+            entity_history_blocks: source_manifest.entity_history_blocks(),
+            entity_storage_params: source_manifest.entity_storage_params(),
         }
     }
 
+    /// Keep `blocks` of history for the deployment; the override applies
+    /// to all entity types
     pub fn with_history_blocks_override(mut self, blocks: i32) -> Self {
         self.history_blocks_override = Some(blocks);
+        self.entity_history_blocks.clear();
         self
     }
 
@@ -270,6 +281,19 @@ impl_stable_hash!(SubgraphError {
     deterministic
 });
 
+/// A trigger whose handler failed with a deterministic error that was not
+/// turned into a `SubgraphError` because the manifest sets the handler's
+/// `errorPolicy` to `skip`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedTrigger {
+    pub block_ptr: BlockPtr,
+    pub handler: String,
+    /// A description of the trigger, usually the transaction that caused
+    /// it; empty if there is none
+    pub trigger: String,
+    pub message: String,
+}
+
 pub fn generate_entity_id() -> String {
     // Fast crypto RNG from operating system
     let mut rng = OsRng::default();
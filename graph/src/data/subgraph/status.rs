@@ -2,7 +2,8 @@
 
 use super::schema::{SubgraphError, SubgraphHealth};
 use crate::blockchain::BlockHash;
-use crate::components::store::{BlockNumber, DeploymentId};
+use crate::components::store::{BlockNumber, DeploymentId, DeploymentPriority};
+use crate::components::subgraph::HandlerStats;
 use crate::data::graphql::{object, IntoValue};
 use crate::prelude::{r, BlockPtr, Value};
 
@@ -65,6 +66,9 @@ pub struct ChainInfo {
     pub earliest_block_number: BlockNumber,
     /// The latest block that the subgraph has synced to.
     pub latest_block: Option<EthereumBlock>,
+    /// The block before the block at which the subgraph failed with a
+    /// deterministic error, if it failed that way.
+    pub last_healthy_block: Option<EthereumBlock>,
 }
 
 impl IntoValue for ChainInfo {
@@ -74,6 +78,7 @@ impl IntoValue for ChainInfo {
             chain_head_block,
             earliest_block_number,
             latest_block,
+            last_healthy_block,
         } = self;
         object! {
             // `__typename` is needed for the `ChainIndexingStatus` interface
@@ -87,6 +92,7 @@ impl IntoValue for ChainInfo {
                 hash: "0x0"
             },
             latestBlock: latest_block,
+            lastHealthyBlock: last_healthy_block,
         }
     }
 }
@@ -104,6 +110,7 @@ pub struct Info {
     pub fatal_error: Option<SubgraphError>,
     pub non_fatal_errors: Vec<SubgraphError>,
     pub paused: Option<bool>,
+    pub priority: Option<DeploymentPriority>,
 
     /// Indexing status on different chains involved in the subgraph's data sources.
     pub chains: Vec<ChainInfo>,
@@ -114,6 +121,34 @@ pub struct Info {
     pub node: Option<String>,
 
     pub history_blocks: i32,
+
+    /// Statistics about the handlers that ran recently on this node. Only
+    /// filled in by the index node
+    pub handler_stats: Vec<HandlerStats>,
+}
+
+impl IntoValue for HandlerStats {
+    fn into_value(self) -> r::Value {
+        let avg_entity_ops = self.avg_entity_ops();
+        let HandlerStats {
+            data_source,
+            handler,
+            invocations,
+            execution_time,
+            entity_ops: _,
+            errors,
+        } = self;
+
+        object! {
+            __typename: "HandlerStats",
+            dataSource: data_source,
+            handler: handler,
+            invocations: invocations,
+            executionTimeMs: execution_time.as_millis() as u64,
+            avgEntityOps: avg_entity_ops,
+            errors: errors,
+        }
+    }
 }
 
 impl IntoValue for Info {
@@ -126,10 +161,12 @@ impl IntoValue for Info {
             fatal_error,
             health,
             paused,
+            priority,
             node,
             non_fatal_errors,
             synced,
             history_blocks,
+            handler_stats,
         } = self;
 
         fn subgraph_error_to_value(subgraph_error: SubgraphError) -> r::Value {
@@ -167,12 +204,14 @@ impl IntoValue for Info {
             synced: synced,
             health: r::Value::from(health),
             paused: paused,
+            priority: priority.map(|priority| priority.to_string()),
             fatalError: fatal_error_val,
             nonFatalErrors: non_fatal_errors,
             chains: chains.into_iter().map(|chain| chain.into_value()).collect::<Vec<_>>(),
             entityCount: format!("{}", entity_count),
             node: node,
             historyBlocks: history_blocks,
+            handlerStats: handler_stats,
         }
     }
 }
@@ -158,6 +158,13 @@ impl Object {
             .map(|entry| &entry.value)
     }
 
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.0
+            .iter_mut()
+            .find(|entry| entry.has_key(key))
+            .map(|entry| &mut entry.value)
+    }
+
     pub fn remove(&mut self, key: &str) -> Option<Value> {
         self.0
             .iter_mut()
@@ -1,5 +1,6 @@
 pub mod causality_region;
 pub mod offchain;
+pub mod subgraph;
 
 pub use causality_region::CausalityRegion;
 
@@ -16,7 +17,7 @@ use crate::{
         link_resolver::LinkResolver,
         store::{BlockNumber, StoredDynamicDataSource},
     },
-    data_source::offchain::OFFCHAIN_KINDS,
+    data_source::{offchain::OFFCHAIN_KINDS, subgraph::SUBGRAPH_DS_KIND},
     prelude::{CheapClone as _, DataSourceContext},
     schema::{EntityType, InputSchema},
 };
@@ -35,6 +36,7 @@ use thiserror::Error;
 pub enum DataSource<C: Blockchain> {
     Onchain(C::DataSource),
     Offchain(offchain::DataSource),
+    Subgraph(subgraph::DataSource),
 }
 
 #[derive(Error, Debug)]
@@ -88,14 +90,21 @@ impl<C: Blockchain> DataSource<C> {
     pub fn as_onchain(&self) -> Option<&C::DataSource> {
         match self {
             Self::Onchain(ds) => Some(ds),
-            Self::Offchain(_) => None,
+            Self::Offchain(_) | Self::Subgraph(_) => None,
         }
     }
 
     pub fn as_offchain(&self) -> Option<&offchain::DataSource> {
         match self {
-            Self::Onchain(_) => None,
             Self::Offchain(ds) => Some(ds),
+            Self::Onchain(_) | Self::Subgraph(_) => None,
+        }
+    }
+
+    pub fn as_subgraph(&self) -> Option<&subgraph::DataSource> {
+        match self {
+            Self::Subgraph(ds) => Some(ds),
+            Self::Onchain(_) | Self::Offchain(_) => None,
         }
     }
 
@@ -111,6 +120,7 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.address().map(ToOwned::to_owned),
             Self::Offchain(ds) => ds.address(),
+            Self::Subgraph(ds) => ds.address(),
         }
     }
 
@@ -118,6 +128,7 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.name(),
             Self::Offchain(ds) => &ds.name,
+            Self::Subgraph(ds) => &ds.name,
         }
     }
 
@@ -125,6 +136,7 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.kind().to_owned(),
             Self::Offchain(ds) => ds.kind.to_string(),
+            Self::Subgraph(ds) => ds.kind.clone(),
         }
     }
 
@@ -132,13 +144,14 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.min_spec_version(),
             Self::Offchain(ds) => ds.min_spec_version(),
+            Self::Subgraph(ds) => ds.min_spec_version(),
         }
     }
 
     pub fn end_block(&self) -> Option<BlockNumber> {
         match self {
             Self::Onchain(ds) => ds.end_block(),
-            Self::Offchain(_) => None,
+            Self::Offchain(_) | Self::Subgraph(_) => None,
         }
     }
 
@@ -146,6 +159,7 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.creation_block(),
             Self::Offchain(ds) => ds.creation_block,
+            Self::Subgraph(ds) => ds.creation_block,
         }
     }
 
@@ -153,6 +167,7 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.context(),
             Self::Offchain(ds) => ds.context.clone(),
+            Self::Subgraph(ds) => ds.context.clone(),
         }
     }
 
@@ -160,6 +175,7 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.api_version(),
             Self::Offchain(ds) => ds.mapping.api_version.clone(),
+            Self::Subgraph(ds) => ds.mapping.api_version.clone(),
         }
     }
 
@@ -167,14 +183,16 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.runtime(),
             Self::Offchain(ds) => Some(ds.mapping.runtime.cheap_clone()),
+            Self::Subgraph(ds) => Some(ds.mapping.runtime.cheap_clone()),
         }
     }
 
     pub fn entities(&self) -> EntityTypeAccess {
         match self {
             // Note: Onchain data sources have an `entities` field in the manifest, but it has never
-            // been enforced.
-            Self::Onchain(_) => EntityTypeAccess::Any,
+            // been enforced. Subgraph data sources run in the onchain causality region and are
+            // treated the same.
+            Self::Onchain(_) | Self::Subgraph(_) => EntityTypeAccess::Any,
             Self::Offchain(ds) => EntityTypeAccess::Restriced(ds.mapping.entities.clone()),
         }
     }
@@ -183,13 +201,14 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.handler_kinds(),
             Self::Offchain(ds) => vec![ds.handler_kind()].into_iter().collect(),
+            Self::Subgraph(ds) => vec![ds.handler_kind()].into_iter().collect(),
         }
     }
 
     pub fn has_declared_calls(&self) -> bool {
         match self {
             Self::Onchain(ds) => ds.has_declared_calls(),
-            Self::Offchain(_) => false,
+            Self::Offchain(_) | Self::Subgraph(_) => false,
         }
     }
 
@@ -207,8 +226,10 @@ impl<C: Blockchain> DataSource<C> {
             (Self::Offchain(ds), TriggerData::Offchain(trigger)) => {
                 Ok(ds.match_and_decode(trigger))
             }
-            (Self::Onchain(_), TriggerData::Offchain(_))
-            | (Self::Offchain(_), TriggerData::Onchain(_)) => Ok(None),
+            (Self::Subgraph(ds), TriggerData::Subgraph(trigger)) => {
+                Ok(ds.match_and_decode(trigger, block))
+            }
+            (Self::Onchain(_), _) | (Self::Offchain(_), _) | (Self::Subgraph(_), _) => Ok(None),
         }
     }
 
@@ -216,6 +237,7 @@ impl<C: Blockchain> DataSource<C> {
         match (self, other) {
             (Self::Onchain(a), Self::Onchain(b)) => a.is_duplicate_of(b),
             (Self::Offchain(a), Self::Offchain(b)) => a.is_duplicate_of(b),
+            (Self::Subgraph(a), Self::Subgraph(b)) => a.is_duplicate_of(b),
             _ => false,
         }
     }
@@ -224,6 +246,7 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.as_stored_dynamic_data_source(),
             Self::Offchain(ds) => ds.as_stored_dynamic_data_source(),
+            Self::Subgraph(ds) => ds.as_stored_dynamic_data_source(),
         }
     }
 
@@ -247,12 +270,13 @@ impl<C: Blockchain> DataSource<C> {
         match self {
             Self::Onchain(ds) => ds.validate(spec_version),
             Self::Offchain(_) => vec![],
+            Self::Subgraph(ds) => ds.validate(),
         }
     }
 
     pub fn causality_region(&self) -> CausalityRegion {
         match self {
-            Self::Onchain(_) => CausalityRegion::ONCHAIN,
+            Self::Onchain(_) | Self::Subgraph(_) => CausalityRegion::ONCHAIN,
             Self::Offchain(ds) => ds.causality_region,
         }
     }
@@ -262,6 +286,7 @@ impl<C: Blockchain> DataSource<C> {
 pub enum UnresolvedDataSource<C: Blockchain> {
     Onchain(C::UnresolvedDataSource),
     Offchain(offchain::UnresolvedDataSource),
+    Subgraph(subgraph::UnresolvedDataSource),
 }
 
 impl<C: Blockchain> UnresolvedDataSource<C> {
@@ -282,8 +307,18 @@ impl<C: Blockchain> UnresolvedDataSource<C> {
                      for details see https://github.com/graphprotocol/graph-node/issues/3864"
                 );
             }
+            Self::Subgraph(unresolved) => unresolved
+                .resolve(resolver, logger, manifest_idx)
+                .await
+                .map(DataSource::Subgraph),
         }
     }
+
+    fn deserialize_subgraph(
+        map: BTreeMap<String, serde_json::Value>,
+    ) -> Result<Self, serde_json::Error> {
+        subgraph::UnresolvedDataSource::deserialize(map.into_deserializer()).map(Self::Subgraph)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -379,6 +414,14 @@ impl<C: Blockchain> Default for UnresolvedDataSourceTemplate<C> {
 }
 
 impl<C: Blockchain> UnresolvedDataSourceTemplate<C> {
+    fn deserialize_subgraph(
+        _map: BTreeMap<String, serde_json::Value>,
+    ) -> Result<Self, serde_json::Error> {
+        Err(serde::de::Error::custom(
+            "subgraph data sources can not be used as templates",
+        ))
+    }
+
     pub async fn resolve(
         self,
         resolver: &Arc<dyn LinkResolver>,
@@ -475,6 +518,7 @@ impl<T> TriggerWithHandler<T> {
 pub enum TriggerData<C: Blockchain> {
     Onchain(C::TriggerData),
     Offchain(offchain::TriggerData),
+    Subgraph(subgraph::TriggerData),
 }
 
 impl<C: Blockchain> TriggerData<C> {
@@ -482,6 +526,10 @@ impl<C: Blockchain> TriggerData<C> {
         match self {
             Self::Onchain(trigger) => trigger.error_context(),
             Self::Offchain(trigger) => format!("{:?}", trigger.source),
+            Self::Subgraph(trigger) => format!(
+                "{} entity from deployment {}",
+                trigger.entity_type, trigger.source
+            ),
         }
     }
 }
@@ -490,6 +538,7 @@ impl<C: Blockchain> TriggerData<C> {
 pub enum MappingTrigger<C: Blockchain> {
     Onchain(C::MappingTrigger),
     Offchain(offchain::TriggerData),
+    Subgraph(subgraph::TriggerData),
 }
 
 impl<C: Blockchain> MappingTrigger<C> {
@@ -497,32 +546,35 @@ impl<C: Blockchain> MappingTrigger<C> {
         match self {
             Self::Onchain(trigger) => Some(trigger.error_context()),
             Self::Offchain(_) => None, // TODO: Add error context for offchain triggers
+            Self::Subgraph(trigger) => Some(format!(
+                "{} entity from deployment {}",
+                trigger.entity_type, trigger.source
+            )),
         }
     }
 
     pub fn as_onchain(&self) -> Option<&C::MappingTrigger> {
         match self {
             Self::Onchain(trigger) => Some(trigger),
-            Self::Offchain(_) => None,
+            Self::Offchain(_) | Self::Subgraph(_) => None,
         }
     }
 }
 
 macro_rules! clone_data_source {
-    ($t:ident) => {
+    ($t:ident, $($variant:ident),+) => {
         impl<C: Blockchain> Clone for $t<C> {
             fn clone(&self) -> Self {
                 match self {
-                    Self::Onchain(ds) => Self::Onchain(ds.clone()),
-                    Self::Offchain(ds) => Self::Offchain(ds.clone()),
+                    $(Self::$variant(ds) => Self::$variant(ds.clone()),)+
                 }
             }
         }
     };
 }
 
-clone_data_source!(DataSource);
-clone_data_source!(DataSourceTemplate);
+clone_data_source!(DataSource, Onchain, Offchain, Subgraph);
+clone_data_source!(DataSourceTemplate, Onchain, Offchain);
 
 macro_rules! deserialize_data_source {
     ($t:ident) => {
@@ -541,14 +593,17 @@ macro_rules! deserialize_data_source {
                     offchain::$t::deserialize(map.into_deserializer())
                         .map_err(serde::de::Error::custom)
                         .map($t::Offchain)
+                } else if kind == SUBGRAPH_DS_KIND {
+                    $t::deserialize_subgraph(map).map_err(serde::de::Error::custom)
                 } else if (&C::KIND.to_string() == kind) || C::ALIASES.contains(&kind) {
                     C::$t::deserialize(map.into_deserializer())
                         .map_err(serde::de::Error::custom)
                         .map($t::Onchain)
                 } else {
                     Err(serde::de::Error::custom(format!(
-                        "data source has invalid `kind`; expected {}, file/ipfs",
+                        "data source has invalid `kind`; expected {}, file/ipfs or {}",
                         C::KIND,
+                        SUBGRAPH_DS_KIND,
                     )))
                 }
             }
@@ -6,7 +6,11 @@ use crate::{
         store::{BlockNumber, StoredDynamicDataSource},
         subgraph::{InstanceDSTemplate, InstanceDSTemplateInfo},
     },
-    data::{store::scalar::Bytes, subgraph::SPEC_VERSION_0_0_7, value::Word},
+    data::{
+        store::scalar::Bytes,
+        subgraph::{SPEC_VERSION_0_0_7, SPEC_VERSION_1_4_0},
+        value::Word,
+    },
     data_source,
     ipfs_client::CidFile,
     prelude::{DataSourceContext, Link},
@@ -30,6 +34,7 @@ lazy_static! {
     pub static ref OFFCHAIN_KINDS: HashMap<&'static str, OffchainDataSourceKind> = [
         ("file/ipfs", OffchainDataSourceKind::Ipfs),
         ("file/arweave", OffchainDataSourceKind::Arweave),
+        ("file/https", OffchainDataSourceKind::Https),
     ]
     .into_iter()
     .collect();
@@ -42,8 +47,18 @@ const NOT_DONE_VALUE: i32 = -1;
 pub enum OffchainDataSourceKind {
     Ipfs,
     Arweave,
+    Https,
 }
 impl OffchainDataSourceKind {
+    /// The minimum spec version a manifest needs to use this kind of
+    /// data source
+    pub fn min_spec_version(&self) -> semver::Version {
+        match self {
+            OffchainDataSourceKind::Ipfs | OffchainDataSourceKind::Arweave => SPEC_VERSION_0_0_7,
+            OffchainDataSourceKind::Https => SPEC_VERSION_1_4_0,
+        }
+    }
+
     pub fn try_parse_source(&self, bs: Bytes) -> Result<Source, anyhow::Error> {
         let source = match self {
             OffchainDataSourceKind::Ipfs => {
@@ -54,6 +69,10 @@ impl OffchainDataSourceKind {
                 let base64 = Word::from(String::from_utf8(bs.to_vec())?);
                 Source::Arweave(base64)
             }
+            OffchainDataSourceKind::Https => {
+                let url = PinnedUrl::from_str(&String::from_utf8(bs.to_vec())?)?;
+                Source::Https(url)
+            }
         };
         Ok(source)
     }
@@ -155,11 +174,7 @@ impl DataSource {
     }
 
     pub fn min_spec_version(&self) -> semver::Version {
-        // off-chain data sources are only supported in spec version 0.0.7 and up
-        // As more and more kinds of off-chain data sources are added, this
-        // function should be updated to return the minimum spec version
-        // required for each kind
-        SPEC_VERSION_0_0_7
+        self.kind.min_spec_version()
     }
 
     pub fn handler_kind(&self) -> &str {
@@ -190,6 +205,11 @@ impl DataSource {
                 Err(e) => return Err(DataSourceCreationError::Ignore(source, e)),
             },
             OffchainDataSourceKind::Arweave => Source::Arweave(Word::from(source)),
+            OffchainDataSourceKind::Https => match source.parse() {
+                Ok(source) => Source::Https(source),
+                // Ignore data sources created with a URL that is not pinned.
+                Err(e) => return Err(DataSourceCreationError::Ignore(source, e)),
+            },
         };
 
         Ok(Self {
@@ -311,10 +331,58 @@ impl DataSource {
 
 pub type Base64 = Word;
 
+/// An HTTPS URL together with the sha256 hash of its content, written as
+/// `https://example.com/file.json#sha256=<hex>`. Content that does not have
+/// that hash is not accepted, which makes the URL content-addressed like an
+/// IPFS or Arweave file.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct PinnedUrl {
+    /// The URL without the fragment with the hash
+    pub url: String,
+    pub sha256: [u8; 32],
+}
+
+impl PinnedUrl {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_string().as_bytes().to_vec()
+    }
+}
+
+impl fmt::Display for PinnedUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#sha256={}", self.url, hex::encode(self.sha256))
+    }
+}
+
+impl FromStr for PinnedUrl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (url, hash) = s
+            .split_once("#sha256=")
+            .ok_or_else(|| anyhow!("the URL `{}` must end with `#sha256=<hash>`", s))?;
+
+        let parsed = url::Url::parse(url)?;
+        if parsed.scheme() != "https" {
+            bail!("only https URLs are supported, not `{}`", url);
+        }
+
+        let sha256 = hex::decode(hash.trim_start_matches("0x"))?
+            .try_into()
+            .map_err(|_| anyhow!("the sha256 hash `{}` of `{}` is not 32 bytes", hash, url))?;
+
+        Ok(PinnedUrl {
+            url: url.to_string(),
+            sha256,
+        })
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Source {
     Ipfs(CidFile),
     Arweave(Base64),
+    Https(PinnedUrl),
 }
 
 impl Source {
@@ -328,6 +396,7 @@ impl Source {
         match self {
             Source::Ipfs(ref cid) => Some(cid.to_bytes()),
             Source::Arweave(ref base64) => Some(base64.as_bytes().to_vec()),
+            Source::Https(ref url) => Some(url.to_bytes()),
         }
     }
 }
@@ -337,6 +406,7 @@ impl Into<Bytes> for Source {
         match self {
             Source::Ipfs(ref link) => Bytes::from(link.to_bytes()),
             Source::Arweave(ref base64) => Bytes::from(base64.as_bytes()),
+            Source::Https(ref url) => Bytes::from(url.to_bytes()),
         }
     }
 }
@@ -533,7 +603,7 @@ mod test {
         ipfs_client::CidFile,
     };
 
-    use super::{OffchainDataSourceKind, Source};
+    use super::{OffchainDataSourceKind, PinnedUrl, Source};
 
     #[test]
     fn test_source_bytes_round_trip() {
@@ -551,5 +621,39 @@ mod test {
             .try_parse_source(arweave_source.into())
             .unwrap();
         assert! { matches!(s, Source::Arweave(b64) if b64.eq(&base64))};
+
+        let url = PinnedUrl::from_str(
+            "https://example.com/a.json#sha256=\
+             2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        )
+        .unwrap();
+        let https_source: Bytes = Source::Https(url.clone()).into();
+        let s = OffchainDataSourceKind::Https
+            .try_parse_source(https_source)
+            .unwrap();
+        assert! { matches!(s, Source::Https(u) if u.eq(&url))};
+    }
+
+    #[test]
+    fn pinned_url_parsing() {
+        const HASH: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+        let url =
+            PinnedUrl::from_str(&format!("https://example.com/a.json#sha256={HASH}")).unwrap();
+        assert_eq!("https://example.com/a.json", url.url);
+        assert_eq!(HASH, hex::encode(url.sha256));
+        assert_eq!(
+            format!("https://example.com/a.json#sha256={HASH}"),
+            url.to_string()
+        );
+
+        let url =
+            PinnedUrl::from_str(&format!("https://example.com/a.json#sha256=0x{HASH}")).unwrap();
+        assert_eq!(HASH, hex::encode(url.sha256));
+
+        // No hash, not https, or a hash of the wrong length
+        assert!(PinnedUrl::from_str("https://example.com/a.json").is_err());
+        assert!(PinnedUrl::from_str(&format!("http://example.com/a.json#sha256={HASH}")).is_err());
+        assert!(PinnedUrl::from_str("https://example.com/a.json#sha256=2cf24dba").is_err());
     }
 }
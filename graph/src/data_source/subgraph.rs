@@ -0,0 +1,216 @@
+use crate::{
+    blockchain::{Block, Blockchain},
+    components::{
+        link_resolver::LinkResolver,
+        store::{BlockNumber, StoredDynamicDataSource},
+    },
+    data::subgraph::SPEC_VERSION_1_3_0,
+    data_source,
+    prelude::{DataSourceContext, DeploymentHash, Entity, Link},
+};
+use anyhow::{anyhow, Error};
+use serde::Deserialize;
+use slog::{info, Logger};
+use std::{collections::HashSet, sync::Arc};
+
+use super::{CausalityRegion, TriggerWithHandler};
+
+/// The `kind` of data sources that take their triggers from the entity
+/// changes of another deployment
+pub const SUBGRAPH_DS_KIND: &str = "subgraph";
+
+const ENTITY_HANDLER_KIND: &str = "entity";
+
+/// A data source that runs its handlers for the entities that another
+/// deployment, the source, writes. The handlers for a block are only run
+/// once the source deployment has processed that block, so that both
+/// deployments always agree on the block the entities belong to
+#[derive(Debug, Clone)]
+pub struct DataSource {
+    pub kind: String,
+    pub name: String,
+    pub manifest_idx: u32,
+    pub source: Source,
+    pub mapping: Mapping,
+    pub context: Arc<Option<DataSourceContext>>,
+    pub creation_block: Option<BlockNumber>,
+}
+
+impl DataSource {
+    pub fn min_spec_version(&self) -> semver::Version {
+        SPEC_VERSION_1_3_0
+    }
+
+    pub fn handler_kind(&self) -> &str {
+        ENTITY_HANDLER_KIND
+    }
+
+    /// The deployment hash of the source, used to match triggers with
+    /// hosts in the same way as the address of onchain data sources
+    pub fn address(&self) -> Option<Vec<u8>> {
+        Some(self.source.address.as_bytes().to_vec())
+    }
+
+    pub fn start_block(&self) -> BlockNumber {
+        self.source.start_block
+    }
+
+    pub fn match_and_decode<C: Blockchain>(
+        &self,
+        trigger: &TriggerData,
+        block: &Arc<C::Block>,
+    ) -> Option<TriggerWithHandler<super::MappingTrigger<C>>> {
+        if self.source.address != trigger.source || block.number() < self.source.start_block {
+            return None;
+        }
+
+        let handler = self
+            .mapping
+            .handlers
+            .iter()
+            .find(|handler| handler.entity == trigger.entity_type)?;
+
+        Some(TriggerWithHandler::new(
+            data_source::MappingTrigger::Subgraph(trigger.clone()),
+            handler.handler.clone(),
+            block.ptr(),
+            block.timestamp(),
+        ))
+    }
+
+    pub fn as_stored_dynamic_data_source(&self) -> StoredDynamicDataSource {
+        // Subgraph data sources can only be declared in the manifest and
+        // are therefore never stored as dynamic data sources
+        StoredDynamicDataSource {
+            manifest_idx: self.manifest_idx,
+            param: None,
+            context: None,
+            creation_block: self.creation_block,
+            done_at: None,
+            causality_region: CausalityRegion::ONCHAIN,
+        }
+    }
+
+    pub fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+
+        if self.mapping.handlers.is_empty() {
+            errors.push(anyhow!(
+                "subgraph data sources must have at least one handler"
+            ));
+        }
+
+        let mut entities = HashSet::new();
+        for handler in &self.mapping.handlers {
+            if !entities.insert(handler.entity.as_str()) {
+                errors.push(anyhow!(
+                    "entity `{}` has more than one handler",
+                    handler.entity
+                ));
+            }
+        }
+
+        errors
+    }
+
+    pub(super) fn is_duplicate_of(&self, b: &DataSource) -> bool {
+        self.manifest_idx == b.manifest_idx && self.source == b.source && self.context == b.context
+    }
+}
+
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Source {
+    /// The deployment hash of the source deployment
+    pub address: DeploymentHash,
+    #[serde(default)]
+    pub start_block: BlockNumber,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    pub language: String,
+    pub api_version: semver::Version,
+    pub entities: Vec<String>,
+    pub handlers: Vec<EntityHandler>,
+    pub runtime: Arc<Vec<u8>>,
+    pub link: Link,
+}
+
+/// Runs `handler` for every entity of type `entity` that the source
+/// deployment creates or updates
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize)]
+pub struct EntityHandler {
+    pub handler: String,
+    pub entity: String,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+pub struct UnresolvedDataSource {
+    pub kind: String,
+    pub name: String,
+    pub source: Source,
+    pub mapping: UnresolvedMapping,
+}
+
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnresolvedMapping {
+    pub api_version: String,
+    pub language: String,
+    pub file: Link,
+    pub handlers: Vec<EntityHandler>,
+    #[serde(default)]
+    pub entities: Vec<String>,
+}
+
+impl UnresolvedDataSource {
+    pub(super) async fn resolve(
+        self,
+        resolver: &Arc<dyn LinkResolver>,
+        logger: &Logger,
+        manifest_idx: u32,
+    ) -> Result<DataSource, Error> {
+        info!(logger, "Resolve subgraph data source";
+            "name" => &self.name,
+            "source" => &self.source.address,
+        );
+
+        Ok(DataSource {
+            kind: self.kind,
+            name: self.name,
+            manifest_idx,
+            source: self.source,
+            mapping: self.mapping.resolve(resolver, logger).await?,
+            context: Arc::new(None),
+            creation_block: None,
+        })
+    }
+}
+
+impl UnresolvedMapping {
+    pub async fn resolve(
+        self,
+        resolver: &Arc<dyn LinkResolver>,
+        logger: &Logger,
+    ) -> Result<Mapping, Error> {
+        info!(logger, "Resolve subgraph mapping"; "link" => &self.file.link);
+
+        Ok(Mapping {
+            language: self.language,
+            api_version: semver::Version::parse(&self.api_version)?,
+            entities: self.entities,
+            handlers: self.handlers,
+            runtime: Arc::new(resolver.cat(logger, &self.file).await?),
+            link: self.file,
+        })
+    }
+}
+
+/// An entity that the source deployment created or updated
+#[derive(Clone, Debug)]
+pub struct TriggerData {
+    pub source: DeploymentHash,
+    pub entity_type: String,
+    pub entity: Entity,
+}
@@ -3,7 +3,7 @@ use cid::Cid;
 use crate::{
     blockchain::mock::{MockBlockchain, MockDataSource},
     ipfs_client::CidFile,
-    prelude::Link,
+    prelude::{DeploymentHash, Link},
 };
 
 use super::{
@@ -68,6 +68,84 @@ fn data_source_helpers() {
     assert!(onchain.as_offchain().is_none());
 }
 
+#[test]
+fn subgraph_data_source_deserialize() {
+    const DS: &str = "
+kind: subgraph
+name: Gravatars
+source:
+  address: QmSourceDeployment
+  startBlock: 100
+mapping:
+  apiVersion: 0.0.7
+  language: wasm/assemblyscript
+  file:
+    /: /ipfs/QmMapping
+  handlers:
+    - handler: handleGravatar
+      entity: Gravatar
+";
+
+    let ds: UnresolvedDataSource<MockBlockchain> = serde_yaml::from_str(DS).unwrap();
+    let UnresolvedDataSource::Subgraph(ds) = ds else {
+        panic!("expected a subgraph data source");
+    };
+    assert_eq!("QmSourceDeployment", ds.source.address.as_str());
+    assert_eq!(100, ds.source.start_block);
+    assert_eq!(
+        vec![subgraph::EntityHandler {
+            handler: "handleGravatar".to_string(),
+            entity: "Gravatar".to_string(),
+        }],
+        ds.mapping.handlers
+    );
+
+    let template: Result<UnresolvedDataSourceTemplate<MockBlockchain>, _> =
+        serde_yaml::from_str(DS);
+    assert!(template.is_err());
+}
+
+#[test]
+fn subgraph_data_source_validate() {
+    let handler = |handler: &str, entity: &str| subgraph::EntityHandler {
+        handler: handler.to_string(),
+        entity: entity.to_string(),
+    };
+    let mut ds = subgraph::DataSource {
+        kind: subgraph::SUBGRAPH_DS_KIND.to_string(),
+        name: "theName".into(),
+        manifest_idx: 0,
+        source: subgraph::Source {
+            address: DeploymentHash::new("QmSourceDeployment").unwrap(),
+            start_block: 0,
+        },
+        mapping: subgraph::Mapping {
+            language: String::new(),
+            api_version: Version::new(0, 0, 0),
+            entities: vec![],
+            handlers: vec![handler("handleGravatar", "Gravatar")],
+            runtime: Arc::new(vec![]),
+            link: Link {
+                link: String::new(),
+            },
+        },
+        context: Arc::new(None),
+        creation_block: None,
+    };
+    assert!(ds.validate().is_empty());
+
+    ds.mapping.handlers.push(handler("handleOther", "Gravatar"));
+    assert_eq!(1, ds.validate().len());
+
+    ds.mapping.handlers.clear();
+    assert_eq!(1, ds.validate().len());
+
+    let ds = DataSource::<MockBlockchain>::Subgraph(ds);
+    assert!(ds.causality_region() == CausalityRegion::ONCHAIN);
+    assert!(ds.as_subgraph().is_some());
+    assert!(ds.as_onchain().is_none());
+}
+
 fn new_datasource() -> offchain::DataSource {
     offchain::DataSource::new(
         offchain::OffchainDataSourceKind::Ipfs,
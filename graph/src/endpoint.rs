@@ -1,12 +1,13 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc,
+        Arc, Mutex,
     },
+    time::Duration,
 };
 
-use prometheus::IntCounterVec;
+use prometheus::{GaugeVec, IntCounterVec};
 use slog::{warn, Logger};
 
 use crate::{
@@ -19,6 +20,117 @@ use crate::{
 /// avoid locking since we don't need to modify the entire struture.
 type ProviderCount = Arc<HashMap<ProviderName, AtomicU64>>;
 
+/// The number of recent request latencies we keep for each provider and
+/// request class to estimate latency percentiles
+const LATENCY_SAMPLES: usize = 100;
+/// How much the outcome of the most recent request counts when updating
+/// the error and rate-limit rates of a provider
+const RATE_DECAY: f64 = 0.1;
+/// How many seconds of latency an error rate of 1 is worth when scoring a
+/// provider
+const ERROR_PENALTY_SECS: f64 = 10.0;
+/// How many seconds of latency a rate-limit rate of 1 is worth when scoring
+/// a provider
+const RATE_LIMIT_PENALTY_SECS: f64 = 30.0;
+
+/// The classes of requests that we score providers for separately since
+/// providers can be good at some kinds of requests and bad at others
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestClass {
+    Logs,
+    Calls,
+    Blocks,
+    Traces,
+    Other,
+}
+
+impl RequestClass {
+    pub const ALL: [RequestClass; 5] = [
+        RequestClass::Logs,
+        RequestClass::Calls,
+        RequestClass::Blocks,
+        RequestClass::Traces,
+        RequestClass::Other,
+    ];
+
+    /// The class of requests for the JSON-RPC method `method`
+    pub fn from_method(method: &str) -> Self {
+        match method {
+            "eth_getLogs" => RequestClass::Logs,
+            "eth_call" | "eth_getBalance" | "eth_getCode" => RequestClass::Calls,
+            "eth_getBlockByHash"
+            | "eth_getBlockByNumber"
+            | "eth_getBlockReceipts"
+            | "eth_getTransactionReceipt"
+            | "eth_blockNumber" => RequestClass::Blocks,
+            method if method.starts_with("trace_") => RequestClass::Traces,
+            _ => RequestClass::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestClass::Logs => "logs",
+            RequestClass::Calls => "calls",
+            RequestClass::Blocks => "blocks",
+            RequestClass::Traces => "traces",
+            RequestClass::Other => "other",
+        }
+    }
+}
+
+/// What we know about how well a provider handles one class of requests
+#[derive(Debug, Default)]
+struct Score {
+    latencies: VecDeque<f64>,
+    error_rate: f64,
+    rate_limit_rate: f64,
+}
+
+impl Score {
+    fn latency(&mut self, secs: f64) {
+        if self.latencies.len() == LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(secs);
+    }
+
+    /// The latency percentile `p` (between 0 and 1) of recent requests, or
+    /// 0 if there haven't been any
+    fn percentile(&self, p: f64) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        let mut latencies: Vec<_> = self.latencies.iter().copied().collect();
+        latencies.sort_by(|a, b| a.total_cmp(b));
+        let idx = ((latencies.len() - 1) as f64 * p).round() as usize;
+        latencies[idx]
+    }
+
+    fn outcome(&mut self, success: bool) {
+        let error = if success { 0.0 } else { 1.0 };
+        self.error_rate += RATE_DECAY * (error - self.error_rate);
+        if success {
+            self.rate_limit_rate -= RATE_DECAY * self.rate_limit_rate;
+        }
+    }
+
+    fn rate_limited(&mut self) {
+        self.rate_limit_rate += RATE_DECAY * (1.0 - self.rate_limit_rate);
+    }
+
+    /// The score of the provider; lower is better. It is the 90th latency
+    /// percentile in seconds plus penalties for errors and rate limiting
+    fn score(&self) -> f64 {
+        self.percentile(0.9)
+            + ERROR_PENALTY_SECS * self.error_rate
+            + RATE_LIMIT_PENALTY_SECS * self.rate_limit_rate
+    }
+}
+
+/// The scores of a provider, indexed by `RequestClass`
+type ProviderScores = Arc<HashMap<ProviderName, Vec<Mutex<Score>>>>;
+
 /// This struct represents all the current labels except for the result
 /// which is added separately. If any new labels are necessary they should
 /// remain in the same order as added in [`EndpointMetrics::new`]
@@ -63,10 +175,17 @@ impl RequestLabels {
 
 /// EndpointMetrics keeps track of calls success rate for specific calls,
 /// a success call to a host will clear the error count.
+///
+/// It also scores each provider for each `RequestClass` based on the
+/// latency percentiles, error rate and rate-limit responses of recent
+/// requests so that requests can be routed to the best provider
 pub struct EndpointMetrics {
     logger: Logger,
     providers: ProviderCount,
+    scores: ProviderScores,
     counter: Box<IntCounterVec>,
+    rate_limited: Box<IntCounterVec>,
+    score_gauge: Box<GaugeVec>,
 }
 
 impl std::fmt::Debug for EndpointMetrics {
@@ -86,6 +205,13 @@ impl EndpointMetrics {
                 .iter()
                 .map(|h| (ProviderName::from(h.as_ref()), AtomicU64::new(0))),
         ));
+        let scores = Arc::new(HashMap::from_iter(providers.keys().map(|provider| {
+            let scores = RequestClass::ALL
+                .iter()
+                .map(|_| Mutex::new(Score::default()))
+                .collect();
+            (provider.clone(), scores)
+        })));
 
         let counter = registry
             .new_int_counter_vec(
@@ -95,10 +221,29 @@ impl EndpointMetrics {
             )
             .expect("unable to create endpoint_request counter_vec");
 
+        let rate_limited = registry
+            .new_int_counter_vec(
+                "endpoint_rate_limited",
+                "requests that the provider rejected because of rate limiting",
+                &["req_class", "provider"],
+            )
+            .expect("unable to create endpoint_rate_limited counter_vec");
+
+        let score_gauge = registry
+            .new_gauge_vec(
+                "endpoint_score",
+                "score of the provider for a class of requests; lower is better",
+                vec!["req_class".to_string(), "provider".to_string()],
+            )
+            .expect("unable to create endpoint_score gauge_vec");
+
         Self {
             logger,
             providers,
+            scores,
             counter,
+            rate_limited,
+            score_gauge,
         }
     }
 
@@ -141,6 +286,7 @@ impl EndpointMetrics {
         };
 
         self.counter.with_label_values(&labels.to_slice(true)).inc();
+        self.update_score(labels, |score| score.outcome(true));
     }
 
     pub fn failure(&self, labels: &RequestLabels) {
@@ -157,6 +303,43 @@ impl EndpointMetrics {
         self.counter
             .with_label_values(&labels.to_slice(false))
             .inc();
+        self.update_score(labels, |score| score.outcome(false));
+    }
+
+    /// Record that a request took `elapsed` to complete, whether it
+    /// succeeded or not
+    pub fn latency(&self, labels: &RequestLabels, elapsed: Duration) {
+        self.update_score(labels, |score| score.latency(elapsed.as_secs_f64()));
+    }
+
+    /// Record that the provider rejected a request because of rate
+    /// limiting. The request should also be reported as a `failure`
+    pub fn rate_limited(&self, labels: &RequestLabels) {
+        let class = RequestClass::from_method(labels.req_type.as_str());
+        self.rate_limited
+            .with_label_values(&[class.as_str(), labels.provider.as_str()])
+            .inc();
+        self.update_score(labels, |score| score.rate_limited());
+    }
+
+    fn update_score(&self, labels: &RequestLabels, f: impl FnOnce(&mut Score)) {
+        let class = RequestClass::from_method(labels.req_type.as_str());
+        if let Some(scores) = self.scores.get(&labels.provider) {
+            let mut score = scores[class as usize].lock().unwrap();
+            f(&mut score);
+            self.score_gauge
+                .with_label_values(&[class.as_str(), labels.provider.as_str()])
+                .set(score.score());
+        }
+    }
+
+    /// The current score of `provider` for requests of class `class`;
+    /// lower is better. Providers we know nothing about have a score of 0
+    pub fn score(&self, provider: &ProviderName, class: RequestClass) -> f64 {
+        self.scores
+            .get(provider)
+            .map(|scores| scores[class as usize].lock().unwrap().score())
+            .unwrap_or(0.0)
     }
 
     /// Returns the current error count of a host or 0 if the host
@@ -171,13 +354,13 @@ impl EndpointMetrics {
 
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
     use slog::{o, Discard, Logger};
 
     use crate::{
         components::metrics::MetricsRegistry,
-        endpoint::{EndpointMetrics, ProviderName},
+        endpoint::{ConnectionType, EndpointMetrics, ProviderName, RequestClass, RequestLabels},
     };
 
     #[tokio::test]
@@ -199,4 +382,51 @@ mod test {
         assert_eq!(metrics.get_count(&b), 2);
         assert_eq!(metrics.get_count(&c), 0);
     }
+
+    #[test]
+    fn scores_providers_per_request_class() {
+        let (fast, slow, failing, limited): (
+            ProviderName,
+            ProviderName,
+            ProviderName,
+            ProviderName,
+        ) = (
+            "fast".into(),
+            "slow".into(),
+            "failing".into(),
+            "limited".into(),
+        );
+        let hosts: &[&str] = &[&fast, &slow, &failing, &limited];
+        let logger = Logger::root(Discard, o!());
+        let metrics = EndpointMetrics::new(logger, hosts, Arc::new(MetricsRegistry::mock()));
+
+        let labels = |provider: &ProviderName, method: &str| RequestLabels {
+            provider: provider.clone(),
+            req_type: method.into(),
+            conn_type: ConnectionType::Rpc,
+        };
+        let logs = |provider| labels(provider, "eth_getLogs");
+
+        for _ in 0..10 {
+            metrics.latency(&logs(&fast), Duration::from_millis(100));
+            metrics.success(&logs(&fast));
+            metrics.latency(&logs(&slow), Duration::from_secs(2));
+            metrics.success(&logs(&slow));
+            metrics.latency(&logs(&failing), Duration::from_millis(10));
+            metrics.failure(&logs(&failing));
+            metrics.latency(&logs(&limited), Duration::from_millis(10));
+            metrics.failure(&logs(&limited));
+            metrics.rate_limited(&logs(&limited));
+        }
+        // A slow provider for logs can still be the best one for calls
+        metrics.latency(&labels(&slow, "eth_call"), Duration::from_millis(50));
+        metrics.latency(&labels(&fast, "eth_call"), Duration::from_millis(500));
+
+        let score = |provider, class| metrics.score(provider, class);
+        assert!(score(&fast, RequestClass::Logs) < score(&slow, RequestClass::Logs));
+        assert!(score(&slow, RequestClass::Logs) < score(&failing, RequestClass::Logs));
+        assert!(score(&failing, RequestClass::Logs) < score(&limited, RequestClass::Logs));
+        assert!(score(&slow, RequestClass::Calls) < score(&fast, RequestClass::Calls));
+        assert_eq!(0.0, score(&fast, RequestClass::Traces));
+    }
 }
@@ -23,6 +23,13 @@ pub struct EnvVarsGraphQl {
     /// for all subgraphs, which is the default
     /// behavior.
     pub cached_subgraph_ids: CachedSubgraphIds,
+    /// Deployments for which only introspection queries are allowed. Data
+    /// queries and subscriptions against them fail
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_INTROSPECTION_ONLY_SUBGRAPH_IDS` (comma separated). The
+    /// default is empty.
+    pub introspection_only_subgraph_ids: Vec<String>,
     /// In how many shards (mutexes) the query block cache is split.
     /// Ideally this should divide 256 so that the distribution of queries to
     /// shards is even.
@@ -103,6 +110,78 @@ pub struct EnvVarsGraphQl {
     /// Set by the env var `GRAPH_PARALLEL_BLOCK_CONSTRAINTS`
     /// Whether to run top-level queries with different block constraints in parallel
     pub parallel_block_constraints: bool,
+    /// How many query texts for Automatic Persisted Queries to keep in
+    /// memory. Set to 0 to disable persisted queries.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_PERSISTED_QUERY_CACHE_SIZE`.
+    /// The default value is 1000.
+    pub persisted_query_cache_size: usize,
+    /// The maximum length in bytes of query texts that are remembered for
+    /// Automatic Persisted Queries. Longer queries are still run, but
+    /// clients always have to send their text.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_PERSISTED_QUERY_MAX_SIZE`. The default value is 100000.
+    pub persisted_query_max_size: usize,
+    /// Whether to also store query texts for persisted queries in the
+    /// primary database so that they survive restarts and are shared
+    /// between query nodes.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_PERSISTED_QUERIES_IN_DB`. Off by
+    /// default.
+    pub persisted_queries_in_db: bool,
+    /// How many query texts for persisted queries to keep in the primary
+    /// database. When there are more, the ones that were stored first are
+    /// deleted.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_PERSISTED_QUERIES_IN_DB_MAX`. The default value is
+    /// 10000.
+    pub persisted_queries_in_db_max: usize,
+    /// Whether to include an execution trace in the Apollo tracing format
+    /// in the `extensions` of every response. Clients can also request
+    /// such a trace for individual queries with the header
+    /// `X-GraphQL-Trace: true`.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_APOLLO_TRACING`. Off by default.
+    pub apollo_tracing: bool,
+    /// Whether to add Relay-style connection fields like
+    /// `usersConnection` to the API schema. These fields page through
+    /// collections with opaque cursors instead of `skip`.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_CONNECTIONS`. Off by default.
+    pub connections: bool,
+    /// Whether to add fields like `usersAggregate` to the API schema that
+    /// compute `count`, `sum`, `avg`, `min` and `max` over a collection.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_COLLECTION_AGGREGATES`. Off by
+    /// default.
+    pub collection_aggregates: bool,
+    /// The maximum length of the patterns for `_regex` and `_iregex`
+    /// filters. Since the work to match a regular expression grows with
+    /// its size, this keeps clients from sending patterns that are
+    /// expensive for the database. A value of 0 disables regular
+    /// expression filters.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_MAX_REGEX_LENGTH`.
+    /// Defaults to 256.
+    pub max_regex_length: usize,
+    /// The maximum size in bytes that a `_regex` or `_iregex` pattern may
+    /// take up once compiled. A short pattern with nested repetitions can
+    /// still be very expensive to match, and the compiled size bounds
+    /// that independently of the length of the pattern.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_MAX_REGEX_SIZE`.
+    /// Defaults to 65536.
+    pub max_regex_size: usize,
+    /// The maximum number of subgraphs that one query to the
+    /// `/subgraphs/federated` endpoint may select. Each of them is queried
+    /// in parallel, and this bounds how much work a single request can
+    /// start.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_MAX_FEDERATED_SUBGRAPHS`. Defaults to 10.
+    pub max_federated_subgraphs: usize,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -128,6 +207,13 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
                         .collect(),
                 )
             },
+            introspection_only_subgraph_ids: x
+                .introspection_only_subgraph_ids
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .collect(),
             query_block_cache_shards: x.query_block_cache_shards,
             query_lfu_cache_shards: x
                 .query_lfu_cache_shards
@@ -149,6 +235,16 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             disable_child_sorting: x.disable_child_sorting.0,
             query_trace_token: x.query_trace_token,
             parallel_block_constraints: x.parallel_block_constraints.0,
+            persisted_query_cache_size: x.persisted_query_cache_size,
+            persisted_query_max_size: x.persisted_query_max_size,
+            persisted_queries_in_db: x.persisted_queries_in_db.0,
+            persisted_queries_in_db_max: x.persisted_queries_in_db_max,
+            apollo_tracing: x.apollo_tracing.0,
+            connections: x.connections.0,
+            collection_aggregates: x.collection_aggregates.0,
+            max_regex_length: x.max_regex_length,
+            max_regex_size: x.max_regex_size,
+            max_federated_subgraphs: x.max_federated_subgraphs,
         }
     }
 }
@@ -164,6 +260,8 @@ pub struct InnerGraphQl {
 
     #[envconfig(from = "GRAPH_CACHED_SUBGRAPH_IDS", default = "*")]
     cached_subgraph_ids: String,
+    #[envconfig(from = "GRAPH_INTROSPECTION_ONLY_SUBGRAPH_IDS", default = "")]
+    introspection_only_subgraph_ids: String,
     #[envconfig(from = "GRAPH_QUERY_BLOCK_CACHE_SHARDS", default = "128")]
     query_block_cache_shards: u8,
     #[envconfig(from = "GRAPH_QUERY_LFU_CACHE_SHARDS")]
@@ -202,4 +300,24 @@ pub struct InnerGraphQl {
     query_trace_token: String,
     #[envconfig(from = "GRAPH_PARALLEL_BLOCK_CONSTRAINTS", default = "false")]
     pub parallel_block_constraints: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERY_CACHE_SIZE", default = "1000")]
+    persisted_query_cache_size: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERY_MAX_SIZE", default = "100000")]
+    persisted_query_max_size: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERIES_IN_DB", default = "false")]
+    persisted_queries_in_db: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERIES_IN_DB_MAX", default = "10000")]
+    persisted_queries_in_db_max: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_APOLLO_TRACING", default = "false")]
+    apollo_tracing: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_CONNECTIONS", default = "false")]
+    connections: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_COLLECTION_AGGREGATES", default = "false")]
+    collection_aggregates: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_MAX_REGEX_LENGTH", default = "256")]
+    max_regex_length: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_MAX_REGEX_SIZE", default = "65536")]
+    max_regex_size: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_MAX_FEDERATED_SUBGRAPHS", default = "10")]
+    max_federated_subgraphs: usize,
 }
@@ -1,4 +1,5 @@
 use std::fmt;
+use std::path::PathBuf;
 
 use super::*;
 
@@ -15,6 +16,22 @@ pub struct EnvVarsMapping {
     /// Set by the environment variable `GRAPH_ENTITY_CACHE_SIZE` (expressed in
     /// kilobytes). The default value is 10 megabytes.
     pub entity_cache_size: usize,
+    /// How many of the most recently written entities of each entity type
+    /// to load into the entity cache before a subgraph processes its first
+    /// block after starting. A value of 0 disables the warm-up.
+    ///
+    /// Set by the environment variable `GRAPH_ENTITY_CACHE_WARMUP`. The
+    /// default value is 0.
+    pub entity_cache_warmup: usize,
+    /// How often to save the keys of the entities in the entity cache of a
+    /// subgraph so that a restarted subgraph can load them into its cache
+    /// before processing its first block. The keys are also saved when a
+    /// subgraph stops. `None` disables the periodic checkpoints.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_ENTITY_CACHE_CHECKPOINT_INTERVAL` (expressed in seconds). The
+    /// default value is 600s; a value of 0 disables periodic checkpoints.
+    pub entity_cache_checkpoint_interval: Option<Duration>,
     /// Set by the environment variable `GRAPH_MAX_API_VERSION`. The default
     /// value is `0.0.8`.
     pub max_api_version: Version,
@@ -26,6 +43,12 @@ pub struct EnvVarsMapping {
     /// Set by the environment variable `GRAPH_RUNTIME_MAX_STACK_SIZE`
     /// (expressed in bytes). The default value is 512KiB.
     pub max_stack_size: usize,
+    /// The length of the sliding window over which handler statistics are
+    /// collected for the index node API.
+    ///
+    /// Set by the environment variable `GRAPH_HANDLER_STATS_WINDOW`
+    /// (expressed in seconds). The default value is 600s.
+    pub handler_stats_window: Duration,
 
     /// Set by the environment variable `GRAPH_MAX_IPFS_CACHE_FILE_SIZE`
     /// (expressed in bytes). The default value is 1MiB.
@@ -54,6 +77,38 @@ pub struct EnvVarsMapping {
     /// Set by the environment variable `GRAPH_IPFS_REQUEST_LIMIT`. Defaults to 100.
     pub ipfs_request_limit: u16,
 
+    /// Limits per second requests to each IPFS endpoint. A value of 0
+    /// means no limit.
+    ///
+    /// Set by the environment variable `GRAPH_IPFS_ENDPOINT_RATE_LIMIT`.
+    /// Defaults to 0.
+    pub ipfs_endpoint_rate_limit: u16,
+    /// Limits per second requests to each IPFS endpoint that are retries
+    /// of requests that failed on another endpoint.
+    ///
+    /// Set by the environment variable `GRAPH_IPFS_RETRY_BUDGET`. Defaults
+    /// to 10.
+    pub ipfs_retry_budget: u16,
+    /// The number of consecutive failed requests after which an IPFS
+    /// endpoint is considered down and requests fail over to other
+    /// endpoints.
+    ///
+    /// Set by the environment variable `GRAPH_IPFS_FAILOVER_THRESHOLD`.
+    /// Defaults to 3.
+    pub ipfs_failover_threshold: u32,
+    /// How often IPFS endpoints are checked, and how long an endpoint that
+    /// is down is skipped before it is tried again.
+    ///
+    /// Set by the environment variable `GRAPH_IPFS_HEALTH_CHECK_INTERVAL`
+    /// (expressed in seconds). Defaults to 30s.
+    pub ipfs_health_check_interval: Duration,
+    /// A directory in which files that file data sources fetched from IPFS
+    /// are cached, shared by all deployments.
+    ///
+    /// Set by the environment variable `GRAPH_IPFS_CACHE_LOCATION`. By
+    /// default, files are not cached on disk.
+    pub ipfs_cache_location: Option<PathBuf>,
+
     /// Set by the flag `GRAPH_ALLOW_NON_DETERMINISTIC_IPFS`. Off by
     /// default.
     pub allow_non_deterministic_ipfs: bool,
@@ -62,6 +117,25 @@ pub struct EnvVarsMapping {
     /// eth calls before running triggers; instead eth calls happen when
     /// mappings call `ethereum.call`. Off by default.
     pub disable_declared_calls: bool,
+
+    /// How many triggers of a block to run concurrently. Triggers are run
+    /// against the state of the block before any of them, and the result of
+    /// a trigger is discarded and the trigger is run again if it read an
+    /// entity that an earlier trigger changed. A value of 1 runs all
+    /// triggers sequentially.
+    ///
+    /// Set by the environment variable `GRAPH_MAPPING_TRIGGER_CONCURRENCY`.
+    /// The default value is 1.
+    pub trigger_concurrency: usize,
+
+    /// How many triggers for file data sources of a deployment to resolve
+    /// and handle concurrently. The mappings of file data sources are run
+    /// in a pool of that many threads. A value of 1 handles them
+    /// sequentially.
+    ///
+    /// Set by the environment variable `GRAPH_FILE_DATA_SOURCE_CONCURRENCY`.
+    /// The default value is 1.
+    pub file_data_source_concurrency: usize,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -76,10 +150,16 @@ impl From<InnerMappingHandlers> for EnvVarsMapping {
         Self {
             entity_cache_dead_weight: x.entity_cache_dead_weight.0,
             entity_cache_size: x.entity_cache_size_in_kb * 1000,
+            entity_cache_warmup: x.entity_cache_warmup,
+            entity_cache_checkpoint_interval: match x.entity_cache_checkpoint_interval_in_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
 
             max_api_version: x.max_api_version,
             timeout: x.mapping_handler_timeout_in_secs.map(Duration::from_secs),
             max_stack_size: x.runtime_max_stack_size.0 .0,
+            handler_stats_window: Duration::from_secs(x.handler_stats_window_in_secs.max(1)),
 
             max_ipfs_cache_file_size: x.max_ipfs_cache_file_size.0,
             max_ipfs_cache_size: x.max_ipfs_cache_size,
@@ -87,8 +167,15 @@ impl From<InnerMappingHandlers> for EnvVarsMapping {
             max_ipfs_map_file_size: x.max_ipfs_map_file_size.0,
             max_ipfs_file_bytes: x.max_ipfs_file_bytes.0,
             ipfs_request_limit: x.ipfs_request_limit,
+            ipfs_endpoint_rate_limit: x.ipfs_endpoint_rate_limit,
+            ipfs_retry_budget: x.ipfs_retry_budget,
+            ipfs_failover_threshold: x.ipfs_failover_threshold.max(1),
+            ipfs_health_check_interval: Duration::from_secs(x.ipfs_health_check_interval_in_secs),
+            ipfs_cache_location: x.ipfs_cache_location.map(PathBuf::from),
             allow_non_deterministic_ipfs: x.allow_non_deterministic_ipfs.0,
             disable_declared_calls: x.disable_declared_calls.0,
+            trigger_concurrency: x.trigger_concurrency.max(1),
+            file_data_source_concurrency: x.file_data_source_concurrency.max(1),
         }
     }
 }
@@ -99,12 +186,18 @@ pub struct InnerMappingHandlers {
     entity_cache_dead_weight: EnvVarBoolean,
     #[envconfig(from = "GRAPH_ENTITY_CACHE_SIZE", default = "10000")]
     entity_cache_size_in_kb: usize,
+    #[envconfig(from = "GRAPH_ENTITY_CACHE_WARMUP", default = "0")]
+    entity_cache_warmup: usize,
+    #[envconfig(from = "GRAPH_ENTITY_CACHE_CHECKPOINT_INTERVAL", default = "600")]
+    entity_cache_checkpoint_interval_in_secs: u64,
     #[envconfig(from = "GRAPH_MAX_API_VERSION", default = "0.0.9")]
     max_api_version: Version,
     #[envconfig(from = "GRAPH_MAPPING_HANDLER_TIMEOUT")]
     mapping_handler_timeout_in_secs: Option<u64>,
     #[envconfig(from = "GRAPH_RUNTIME_MAX_STACK_SIZE", default = "")]
     runtime_max_stack_size: WithDefaultUsize<NoUnderscores<usize>, { 512 * 1024 }>,
+    #[envconfig(from = "GRAPH_HANDLER_STATS_WINDOW", default = "600")]
+    handler_stats_window_in_secs: u64,
 
     // IPFS.
     #[envconfig(from = "GRAPH_MAX_IPFS_CACHE_FILE_SIZE", default = "")]
@@ -119,8 +212,22 @@ pub struct InnerMappingHandlers {
     max_ipfs_file_bytes: WithDefaultUsize<usize, { 25 * 1024 * 1024 }>,
     #[envconfig(from = "GRAPH_IPFS_REQUEST_LIMIT", default = "100")]
     ipfs_request_limit: u16,
+    #[envconfig(from = "GRAPH_IPFS_ENDPOINT_RATE_LIMIT", default = "0")]
+    ipfs_endpoint_rate_limit: u16,
+    #[envconfig(from = "GRAPH_IPFS_RETRY_BUDGET", default = "10")]
+    ipfs_retry_budget: u16,
+    #[envconfig(from = "GRAPH_IPFS_FAILOVER_THRESHOLD", default = "3")]
+    ipfs_failover_threshold: u32,
+    #[envconfig(from = "GRAPH_IPFS_HEALTH_CHECK_INTERVAL", default = "30")]
+    ipfs_health_check_interval_in_secs: u64,
+    #[envconfig(from = "GRAPH_IPFS_CACHE_LOCATION")]
+    ipfs_cache_location: Option<String>,
     #[envconfig(from = "GRAPH_ALLOW_NON_DETERMINISTIC_IPFS", default = "false")]
     allow_non_deterministic_ipfs: EnvVarBoolean,
     #[envconfig(from = "GRAPH_DISABLE_DECLARED_CALLS", default = "false")]
     disable_declared_calls: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_MAPPING_TRIGGER_CONCURRENCY", default = "1")]
+    trigger_concurrency: usize,
+    #[envconfig(from = "GRAPH_FILE_DATA_SOURCE_CONCURRENCY", default = "1")]
+    file_data_source_concurrency: usize,
 }
@@ -130,6 +130,12 @@ pub struct EnvVars {
     /// Set by the environment variable `GRAPH_POI_ACCESS_TOKEN`. No default
     /// value is provided.
     pub poi_access_token: Option<String>,
+    /// Guards the mutations of the `index-node` API. Mutations are only
+    /// allowed when this is set and requests carry it as a bearer token.
+    ///
+    /// Set by the environment variable `GRAPH_INDEX_NODE_ADMIN_TOKEN`. No
+    /// default value is provided.
+    pub index_node_admin_token: Option<String>,
     /// Set by the environment variable `GRAPH_SUBGRAPH_MAX_DATA_SOURCES`. Defaults to 1 billion.
     pub subgraph_max_data_sources: usize,
     /// Keep deterministic errors non-fatal even if the subgraph is pending.
@@ -147,6 +153,19 @@ pub struct EnvVars {
     /// Set by the environment variable `GRAPH_SUBGRAPH_ERROR_RETRY_JITTER`
     /// (clamped between 0.0 and 1.0). The default value is 0.2.
     pub subgraph_error_retry_jitter: f64,
+    /// Mapping CPU time, in milliseconds per second, that a deployment may
+    /// use before it is deprioritized in favor of other deployments on the
+    /// same node.
+    ///
+    /// Set by the environment variable `GRAPH_DEPLOYMENT_CPU_QUOTA`. The
+    /// default value is 0, which means no quota.
+    pub deployment_cpu_quota: f64,
+    /// Entity writes per second that a deployment may perform before it is
+    /// deprioritized in favor of other deployments on the same node.
+    ///
+    /// Set by the environment variable `GRAPH_DEPLOYMENT_WRITE_QUOTA`. The
+    /// default value is 0, which means no quota.
+    pub deployment_write_quota: f64,
     /// Experimental feature.
     ///
     /// Set by the flag `GRAPH_ENABLE_SELECT_BY_SPECIFIC_ATTRIBUTES`. On by
@@ -208,6 +227,20 @@ pub struct EnvVars {
     /// Set the maximum grpc decode size(in MB) for firehose BlockIngestor connections.
     /// Defaults to 25MB
     pub firehose_grpc_max_decode_size_mb: usize,
+    /// How long a Firehose block stream may go without a response before
+    /// we reconnect, possibly to a different provider, and resume from the
+    /// latest cursor. Set by `GRAPH_NODE_FIREHOSE_STREAM_TIMEOUT` in
+    /// seconds. Defaults to 120
+    pub firehose_stream_timeout: Duration,
+    /// How much memory, in bytes, the blocks buffered by the block stream
+    /// of a deployment may take up. The buffer holds fewer blocks when
+    /// blocks are large. Set by `GRAPH_BLOCK_STREAM_BUFFER_MEMORY_MB`.
+    /// Defaults to 256MB
+    pub block_stream_buffer_memory_budget: usize,
+    /// Fixed block stream buffer sizes for individual deployments. Set by
+    /// `GRAPH_BLOCK_STREAM_BUFFER_SIZE` as a comma separated list of
+    /// `deployment=size` entries. Defaults to no deployments
+    pub block_stream_buffer_sizes: Vec<(String, usize)>,
 }
 
 impl EnvVars {
@@ -266,10 +299,13 @@ impl EnvVars {
                 inner.kill_if_unresponsive_timeout_secs,
             ),
             poi_access_token: inner.poi_access_token,
+            index_node_admin_token: inner.index_node_admin_token,
             subgraph_max_data_sources: inner.subgraph_max_data_sources.0,
             disable_fail_fast: inner.disable_fail_fast.0,
             subgraph_error_retry_ceil: Duration::from_secs(inner.subgraph_error_retry_ceil_in_secs),
             subgraph_error_retry_jitter: inner.subgraph_error_retry_jitter,
+            deployment_cpu_quota: inner.deployment_cpu_quota,
+            deployment_write_quota: inner.deployment_write_quota,
             enable_select_by_specific_attributes: inner.enable_select_by_specific_attributes.0,
             log_trigger_data: inner.log_trigger_data.0,
             explorer_ttl: Duration::from_secs(inner.explorer_ttl_in_secs),
@@ -288,9 +324,36 @@ impl EnvVars {
             dips_metrics_object_store_url: inner.dips_metrics_object_store_url,
             section_map: inner.section_map,
             firehose_grpc_max_decode_size_mb: inner.firehose_grpc_max_decode_size_mb,
+            firehose_stream_timeout: Duration::from_secs(inner.firehose_stream_timeout_in_secs),
+            block_stream_buffer_memory_budget: inner.block_stream_buffer_memory_mb * 1024 * 1024,
+            block_stream_buffer_sizes: inner
+                .block_stream_buffer_size
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|entry| {
+                    let size = entry.split_once('=').and_then(|(deployment, size)| {
+                        Some((deployment.trim().to_string(), size.trim().parse().ok()?))
+                    });
+                    size.unwrap_or_else(|| {
+                        panic!(
+                            "GRAPH_BLOCK_STREAM_BUFFER_SIZE: `{}` must have the form `deployment=size`",
+                            entry
+                        )
+                    })
+                })
+                .collect(),
         })
     }
 
+    /// The fixed size of the block stream buffer for `deployment`, if one
+    /// was set with `GRAPH_BLOCK_STREAM_BUFFER_SIZE`
+    pub fn block_stream_buffer_size(&self, deployment: &str) -> Option<usize> {
+        self.block_stream_buffer_sizes
+            .iter()
+            .find(|(name, _)| name == deployment)
+            .map(|(_, size)| *size)
+    }
+
     /// Equivalent to checking if [`EnvVar::load_threshold`] is set to
     /// [`Duration::ZERO`].
     pub fn load_management_is_disabled(&self) -> bool {
@@ -340,7 +403,7 @@ struct Inner {
         default = "false"
     )]
     allow_non_deterministic_fulltext_search: EnvVarBoolean,
-    #[envconfig(from = "GRAPH_MAX_SPEC_VERSION", default = "1.2.0")]
+    #[envconfig(from = "GRAPH_MAX_SPEC_VERSION", default = "1.5.0")]
     max_spec_version: Version,
     #[envconfig(from = "GRAPH_LOAD_WINDOW_SIZE", default = "300")]
     load_window_size_in_secs: u64,
@@ -383,6 +446,8 @@ struct Inner {
     kill_if_unresponsive_timeout_secs: u64,
     #[envconfig(from = "GRAPH_POI_ACCESS_TOKEN")]
     poi_access_token: Option<String>,
+    #[envconfig(from = "GRAPH_INDEX_NODE_ADMIN_TOKEN")]
+    index_node_admin_token: Option<String>,
     #[envconfig(from = "GRAPH_SUBGRAPH_MAX_DATA_SOURCES", default = "1_000_000_000")]
     subgraph_max_data_sources: NoUnderscores<usize>,
     #[envconfig(from = "GRAPH_DISABLE_FAIL_FAST", default = "false")]
@@ -391,6 +456,10 @@ struct Inner {
     subgraph_error_retry_ceil_in_secs: u64,
     #[envconfig(from = "GRAPH_SUBGRAPH_ERROR_RETRY_JITTER", default = "0.2")]
     subgraph_error_retry_jitter: f64,
+    #[envconfig(from = "GRAPH_DEPLOYMENT_CPU_QUOTA", default = "0")]
+    deployment_cpu_quota: f64,
+    #[envconfig(from = "GRAPH_DEPLOYMENT_WRITE_QUOTA", default = "0")]
+    deployment_write_quota: f64,
     #[envconfig(from = "GRAPH_ENABLE_SELECT_BY_SPECIFIC_ATTRIBUTES", default = "true")]
     enable_select_by_specific_attributes: EnvVarBoolean,
     #[envconfig(from = "GRAPH_LOG_TRIGGER_DATA", default = "false")]
@@ -431,6 +500,12 @@ struct Inner {
     section_map: Option<String>,
     #[envconfig(from = "GRAPH_NODE_FIREHOSE_MAX_DECODE_SIZE", default = "25")]
     firehose_grpc_max_decode_size_mb: usize,
+    #[envconfig(from = "GRAPH_NODE_FIREHOSE_STREAM_TIMEOUT", default = "120")]
+    firehose_stream_timeout_in_secs: u64,
+    #[envconfig(from = "GRAPH_BLOCK_STREAM_BUFFER_MEMORY_MB", default = "256")]
+    block_stream_buffer_memory_mb: usize,
+    #[envconfig(from = "GRAPH_BLOCK_STREAM_BUFFER_SIZE", default = "")]
+    block_stream_buffer_size: String,
 }
 
 #[derive(Clone, Debug)]
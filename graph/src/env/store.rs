@@ -88,6 +88,19 @@ pub struct EnvVarsStore {
     /// The default is 180s.
     pub batch_target_duration: Duration,
 
+    /// How many tables to copy at the same time when copying or grafting
+    /// a deployment. Each worker beyond the first uses an additional
+    /// connection from the fdw pool. Set by `GRAPH_STORE_COPY_WORKERS`. The
+    /// default is 1
+    pub copy_workers: usize,
+
+    /// When set, deployments that are more than this many blocks behind
+    /// the chain head when they start indexing drop their attribute
+    /// indexes, and recreate them once they are within this many blocks
+    /// of the chain head. Set by `GRAPH_STORE_DEFER_INDEXES_DISTANCE`. The
+    /// default is to never drop indexes
+    pub defer_indexes_distance: Option<i32>,
+
     /// Prune tables where we will remove at least this fraction of entity
     /// versions by rebuilding the table. Set by
     /// `GRAPH_STORE_HISTORY_REBUILD_THRESHOLD`. The default is 0.5
@@ -113,13 +126,67 @@ pub struct EnvVarsStore {
     /// is 10_000 which corresponds to 10MB. Setting this to 0 disables
     /// write batching.
     pub write_batch_size: usize,
-    /// Whether to create GIN indexes for array attributes. Set by
-    /// `GRAPH_STORE_CREATE_GIN_INDEXES`. The default is `false`
+    /// Whether to create GIN indexes for array attributes of mutable
+    /// entity types; they are always created for immutable entity types.
+    /// Set by `GRAPH_STORE_CREATE_GIN_INDEXES`. The default is `false`
     pub create_gin_indexes: bool,
     /// Temporary env var in case we need to quickly rollback PR #5010
     pub use_brin_for_all_query_types: bool,
     /// Temporary env var to disable certain lookups in the chain store
     pub disable_block_cache_for_lookup: bool,
+    /// Whether lookups of single entities by id may use prepared
+    /// statements that are cached on the database connection. Set by
+    /// `GRAPH_STORE_CACHE_PREPARED_LOOKUPS`. The default is `false` since
+    /// cached prepared statements do not work with connection poolers
+    /// like pgbouncer in transaction mode
+    pub cache_prepared_lookups: bool,
+    /// If this is larger than 0, the tables for mutable entities of new
+    /// deployments are partitioned by the lower bound of their block
+    /// range, with each partition covering this many blocks. Set by
+    /// `GRAPH_STORE_PARTITION_BLOCKS`. The default is 0, i.e., tables are
+    /// not partitioned
+    pub partition_blocks: i32,
+    /// Whether to record which attributes queries filter and sort by so
+    /// that `graphman index suggest` can propose indexes. Set by
+    /// `GRAPH_STORE_RECORD_QUERY_PATTERNS`. Off by default
+    pub record_query_patterns: bool,
+    /// Analyze an entity table once indexing has written this fraction of
+    /// its estimated number of rows since the table was last analyzed. Set
+    /// by `GRAPH_STORE_ANALYZE_CHURN_RATIO`, e.g., to 0.1. The default is 0,
+    /// which turns tracking writes off
+    pub analyze_churn_ratio: f64,
+    /// Whether tables that are analyzed because of churn should also be
+    /// vacuumed. Set by `GRAPH_STORE_VACUUM_CHURNED_TABLES`. Off by default
+    pub vacuum_churned_tables: bool,
+    /// Queries for GraphQL requests that take longer than this are run a
+    /// second time under `EXPLAIN ANALYZE` in the background and their
+    /// plan is logged. Each distinct query is only explained once per
+    /// process, and only one query is explained at a time. Set by
+    /// `GRAPH_STORE_EXPLAIN_SLOW_QUERIES_MS`. Off by default
+    pub explain_slow_queries: Option<Duration>,
+    /// The URL of an object store like `s3://bucket/path` or
+    /// `gs://bucket/path` into which blocks that are more than
+    /// `block_archive_depth` blocks behind the chain head are moved. Set
+    /// by `GRAPH_STORE_BLOCK_ARCHIVE_URL`. Off by default
+    pub block_archive_url: Option<String>,
+    /// How many blocks behind the chain head blocks need to be before
+    /// they are moved into the block archive. Set by
+    /// `GRAPH_STORE_BLOCK_ARCHIVE_DEPTH`. The default is 100,000 blocks
+    pub block_archive_depth: i32,
+    /// The maximum size in bytes of the data in the call cache of each
+    /// chain. When set, a background job evicts the calls that were
+    /// accessed least recently from call caches that are bigger. Set by
+    /// `GRAPH_STORE_CALL_CACHE_MAX_BYTES`. Off by default
+    pub call_cache_max_bytes: Option<u64>,
+    /// The fraction of reads from the call cache that update the access
+    /// time of the call. Set by `GRAPH_STORE_CALL_CACHE_ACCESS_SAMPLE`.
+    /// The default is 0.01
+    pub call_cache_access_sample: f64,
+    /// Whether to also cache eth_calls that reverted, together with the
+    /// revert reason, so that handlers that are run again for the same
+    /// block do not repeat calls that are known to revert. Set by
+    /// `GRAPH_STORE_CACHE_REVERTED_CALLS`. Off by default
+    pub cache_reverted_calls: bool,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -160,6 +227,8 @@ impl From<InnerStore> for EnvVarsStore {
             connection_idle_timeout: Duration::from_secs(x.connection_idle_timeout_in_secs),
             write_queue_size: x.write_queue_size,
             batch_target_duration: Duration::from_secs(x.batch_target_duration_in_secs),
+            copy_workers: x.copy_workers,
+            defer_indexes_distance: x.defer_indexes_distance,
             rebuild_threshold: x.rebuild_threshold.0,
             delete_threshold: x.delete_threshold.0,
             history_slack_factor: x.history_slack_factor.0,
@@ -168,6 +237,17 @@ impl From<InnerStore> for EnvVarsStore {
             create_gin_indexes: x.create_gin_indexes,
             use_brin_for_all_query_types: x.use_brin_for_all_query_types,
             disable_block_cache_for_lookup: x.disable_block_cache_for_lookup,
+            cache_prepared_lookups: x.cache_prepared_lookups,
+            partition_blocks: x.partition_blocks,
+            record_query_patterns: x.record_query_patterns,
+            analyze_churn_ratio: x.analyze_churn_ratio,
+            vacuum_churned_tables: x.vacuum_churned_tables,
+            explain_slow_queries: x.explain_slow_queries_ms.map(Duration::from_millis),
+            block_archive_url: x.block_archive_url,
+            block_archive_depth: x.block_archive_depth,
+            call_cache_max_bytes: x.call_cache_max_bytes,
+            call_cache_access_sample: x.call_cache_access_sample.0,
+            cache_reverted_calls: x.cache_reverted_calls,
         }
     }
 }
@@ -213,6 +293,10 @@ pub struct InnerStore {
     write_queue_size: usize,
     #[envconfig(from = "GRAPH_STORE_BATCH_TARGET_DURATION", default = "180")]
     batch_target_duration_in_secs: u64,
+    #[envconfig(from = "GRAPH_STORE_COPY_WORKERS", default = "1")]
+    copy_workers: usize,
+    #[envconfig(from = "GRAPH_STORE_DEFER_INDEXES_DISTANCE")]
+    defer_indexes_distance: Option<i32>,
     #[envconfig(from = "GRAPH_STORE_HISTORY_REBUILD_THRESHOLD", default = "0.5")]
     rebuild_threshold: ZeroToOneF64,
     #[envconfig(from = "GRAPH_STORE_HISTORY_DELETE_THRESHOLD", default = "0.05")]
@@ -229,6 +313,28 @@ pub struct InnerStore {
     use_brin_for_all_query_types: bool,
     #[envconfig(from = "GRAPH_STORE_DISABLE_BLOCK_CACHE_FOR_LOOKUP", default = "false")]
     disable_block_cache_for_lookup: bool,
+    #[envconfig(from = "GRAPH_STORE_CACHE_PREPARED_LOOKUPS", default = "false")]
+    cache_prepared_lookups: bool,
+    #[envconfig(from = "GRAPH_STORE_PARTITION_BLOCKS", default = "0")]
+    partition_blocks: i32,
+    #[envconfig(from = "GRAPH_STORE_RECORD_QUERY_PATTERNS", default = "false")]
+    record_query_patterns: bool,
+    #[envconfig(from = "GRAPH_STORE_ANALYZE_CHURN_RATIO", default = "0")]
+    analyze_churn_ratio: f64,
+    #[envconfig(from = "GRAPH_STORE_VACUUM_CHURNED_TABLES", default = "false")]
+    vacuum_churned_tables: bool,
+    #[envconfig(from = "GRAPH_STORE_EXPLAIN_SLOW_QUERIES_MS")]
+    explain_slow_queries_ms: Option<u64>,
+    #[envconfig(from = "GRAPH_STORE_BLOCK_ARCHIVE_URL")]
+    block_archive_url: Option<String>,
+    #[envconfig(from = "GRAPH_STORE_BLOCK_ARCHIVE_DEPTH", default = "100000")]
+    block_archive_depth: i32,
+    #[envconfig(from = "GRAPH_STORE_CALL_CACHE_MAX_BYTES")]
+    call_cache_max_bytes: Option<u64>,
+    #[envconfig(from = "GRAPH_STORE_CALL_CACHE_ACCESS_SAMPLE", default = "0.01")]
+    call_cache_access_sample: ZeroToOneF64,
+    #[envconfig(from = "GRAPH_STORE_CACHE_REVERTED_CALLS", default = "false")]
+    cache_reverted_calls: bool,
 }
 
 #[derive(Clone, Copy, Debug)]
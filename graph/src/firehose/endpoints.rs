@@ -384,6 +384,16 @@ impl FirehoseEndpoint {
         self.endpoint_metrics.get_count(&self.provider)
     }
 
+    /// Record a failure of a block stream that was connected but stopped
+    /// sending blocks, so that the next connection prefers another provider
+    pub fn report_stalled_stream(&self) {
+        self.endpoint_metrics.failure(&RequestLabels {
+            provider: self.provider.clone().into(),
+            req_type: "stream_blocks".into(),
+            conn_type: ConnectionType::Firehose,
+        });
+    }
+
     // we need to -1 because there will always be a reference
     // inside FirehoseEndpoints that is not used (is always cloned).
     pub fn get_capacity(self: &Arc<Self>) -> AvailableCapacity {
@@ -659,7 +669,9 @@ impl FirehoseEndpoints {
     }
 
     /// This function will attempt to grab an endpoint based on the Lowest error count
-    //  with high capacity available. If an adapter cannot be found `endpoint` will
+    //  with high capacity available. Among endpoints with the same error count, the
+    //  channel with the fewest streams on it is preferred so that the load is spread
+    //  over the whole pool. If an adapter cannot be found `endpoint` will
     // return an error.
     pub async fn endpoint(&self) -> anyhow::Result<Arc<FirehoseEndpoint>> {
         let endpoint = self
@@ -667,7 +679,7 @@ impl FirehoseEndpoints {
             .get_all(&self.0)
             .await?
             .into_iter()
-            .sorted_by_key(|x| x.current_error_count())
+            .sorted_by_key(|x| (x.current_error_count(), Arc::strong_count(x)))
             .try_fold(None, |acc, adapter| {
                 match adapter.get_capacity() {
                     AvailableCapacity::Unavailable => ControlFlow::Continue(acc),
@@ -785,6 +797,34 @@ mod test {
         assert!(err.to_string().contains("conn_pool_size"));
     }
 
+    #[tokio::test]
+    async fn firehose_endpoint_spreads_load_over_pool() {
+        let endpoint_metrics = Arc::new(EndpointMetrics::mock());
+        let pool: Vec<_> = (0..2)
+            .map(|_| {
+                Arc::new(FirehoseEndpoint::new(
+                    "pooled".to_string(),
+                    "http://127.0.0.1".to_string(),
+                    None,
+                    None,
+                    false,
+                    false,
+                    SubgraphLimit::Unlimited,
+                    endpoint_metrics.clone(),
+                    NoopGenesisDecoder::boxed(),
+                ))
+            })
+            .collect();
+
+        let endpoints = FirehoseEndpoints::for_testing(pool.clone());
+
+        // The first channel is in use, so the second one should be picked
+        let in_use = endpoints.endpoint().await.unwrap();
+        assert!(Arc::ptr_eq(&in_use, &pool[0]));
+        let res = endpoints.endpoint().await.unwrap();
+        assert!(Arc::ptr_eq(&res, &pool[1]));
+    }
+
     #[tokio::test]
     async fn firehose_endpoint_selection() {
         let logger = Logger::root(Discard, o!());
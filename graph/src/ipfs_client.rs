@@ -5,15 +5,19 @@ use bytes::BytesMut;
 use cid::Cid;
 use futures03::stream::TryStreamExt as _;
 use futures03::Stream;
-use http::header::CONTENT_LENGTH;
+use http::header::{ACCEPT, CONTENT_LENGTH, RANGE};
 use http::Uri;
+use parking_lot::Mutex;
 use reqwest::multipart;
 use serde::Deserialize;
 use std::fmt::Display;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 use std::{str::FromStr, sync::Arc};
 
+use crate::cheap_clone::CheapClone as _;
 use crate::derive::CheapClone;
+use crate::env::ENV_VARS;
 
 #[derive(Debug, thiserror::Error)]
 pub enum IpfsError {
@@ -21,6 +25,8 @@ pub enum IpfsError {
     Request(#[from] reqwest::Error),
     #[error("IPFS file {0} is too large. It can be at most {1} bytes")]
     FileTooLarge(String, usize),
+    #[error("IPFS gateway {1} does not support {0}")]
+    Unsupported(&'static str, String),
 }
 
 impl IpfsError {
@@ -114,20 +120,146 @@ pub struct AddResponse {
     pub size: String,
 }
 
+/// The prefix of `--ipfs` addresses for endpoints that are HTTP gateways
+/// rather than the IPFS RPC API
+pub const GATEWAY_PREFIX: &str = "gateway+";
+
+/// The style of HTTP interface an IPFS endpoint offers
+#[derive(Clone, Copy, CheapClone, Debug, PartialEq, Eq)]
+pub enum IpfsEndpointKind {
+    /// The RPC API of an IPFS node, i.e., `/api/v0/cat` etc.
+    Api,
+    /// A path gateway that serves files at `/ipfs/<cid>`
+    Gateway,
+}
+
+/// Requests in a window of one second, used to limit the rate of requests
+/// to an endpoint
+struct RateWindow {
+    limit: u32,
+    start: Instant,
+    count: u32,
+}
+
+impl RateWindow {
+    fn new(limit: u16) -> Self {
+        RateWindow {
+            limit: limit as u32,
+            start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    /// Take a slot in the current window. If there is none left, return
+    /// how long it is until the next window starts. A limit of 0 means
+    /// there are always slots left.
+    fn take(&mut self) -> Result<(), Duration> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+        let elapsed = self.start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.start = Instant::now();
+            self.count = 0;
+        } else if self.count >= self.limit {
+            return Err(Duration::from_secs(1) - elapsed);
+        }
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// The health of an endpoint, shared by all clones of an `IpfsClient`. An
+/// endpoint is down after `GRAPH_IPFS_FAILOVER_THRESHOLD` consecutive
+/// failed requests and is skipped in favor of other endpoints until a
+/// health check succeeds or `GRAPH_IPFS_HEALTH_CHECK_INTERVAL` has passed.
+struct EndpointHealth {
+    failures: AtomicU32,
+    down_until: Mutex<Option<Instant>>,
+    requests: Mutex<RateWindow>,
+    retries: Mutex<RateWindow>,
+}
+
+impl EndpointHealth {
+    fn new() -> Self {
+        EndpointHealth {
+            failures: AtomicU32::new(0),
+            down_until: Mutex::new(None),
+            requests: Mutex::new(RateWindow::new(ENV_VARS.mappings.ipfs_endpoint_rate_limit)),
+            retries: Mutex::new(RateWindow::new(ENV_VARS.mappings.ipfs_retry_budget)),
+        }
+    }
+
+    fn is_up(&self) -> bool {
+        match *self.down_until.lock() {
+            Some(until) => until <= Instant::now(),
+            None => true,
+        }
+    }
+
+    fn success(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        *self.down_until.lock() = None;
+    }
+
+    fn failure(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= ENV_VARS.mappings.ipfs_failover_threshold {
+            *self.down_until.lock() =
+                Some(Instant::now() + ENV_VARS.mappings.ipfs_health_check_interval);
+        }
+    }
+
+    /// Wait until the rate limit allows another request
+    async fn acquire(&self) {
+        loop {
+            let wait = self.requests.lock().take();
+            match wait {
+                Ok(()) => return,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+}
+
+/// Whether `e` indicates a problem with the endpoint rather than with the
+/// file that was requested. Timeouts are not failures since IPFS times out
+/// when a file is not available.
+fn is_endpoint_failure(e: &reqwest::Error) -> bool {
+    if e.is_connect() {
+        return true;
+    }
+    match e.status().map(|status| status.as_u16()) {
+        Some(504) | Some(524) => false,
+        Some(status) => status == 429 || status >= 500,
+        None => false,
+    }
+}
+
 /// Reference type, clones will share the connection pool.
 #[derive(Clone, CheapClone)]
 pub struct IpfsClient {
     base: Arc<Uri>,
+    kind: IpfsEndpointKind,
     // reqwest::Client doesn't need to be `Arc` because it has one internally
     // already.
     client: reqwest::Client,
+    health: Arc<EndpointHealth>,
 }
 
 impl IpfsClient {
+    /// Create a client for `base`. Addresses that start with
+    /// `GATEWAY_PREFIX` are HTTP gateways, all others IPFS RPC APIs.
     pub fn new(base: &str) -> Result<Self, Error> {
+        let (kind, base) = match base.strip_prefix(GATEWAY_PREFIX) {
+            Some(base) => (IpfsEndpointKind::Gateway, base),
+            None => (IpfsEndpointKind::Api, base),
+        };
         Ok(IpfsClient {
             client: reqwest::Client::new(),
             base: Arc::new(Uri::from_str(base)?),
+            kind,
+            health: Arc::new(EndpointHealth::new()),
         })
     }
 
@@ -135,13 +267,41 @@ impl IpfsClient {
         IpfsClient {
             client: reqwest::Client::new(),
             base: Arc::new(Uri::from_str("http://localhost:5001").unwrap()),
+            kind: IpfsEndpointKind::Api,
+            health: Arc::new(EndpointHealth::new()),
         }
     }
 
+    pub fn kind(&self) -> IpfsEndpointKind {
+        self.kind
+    }
+
+    /// The address of the endpoint, without the `GATEWAY_PREFIX`
+    pub fn address(&self) -> String {
+        self.base.to_string()
+    }
+
+    /// Whether the endpoint is currently considered up. Requests should
+    /// prefer endpoints that are up.
+    pub fn is_up(&self) -> bool {
+        self.health.is_up()
+    }
+
+    /// Take a slot in the retry budget of this endpoint. Returns `false`
+    /// if the budget for the current second is used up, in which case
+    /// a failed request should not be retried on this endpoint.
+    pub fn try_retry(&self) -> bool {
+        self.health.retries.lock().take().is_ok()
+    }
+
+    /// Check the endpoint, which marks it as up or down accordingly
+    pub async fn check_health(&self) -> bool {
+        self.test().await.is_ok()
+    }
+
     /// To check the existence of a cid, we do a cat of a single byte.
     pub async fn exists(&self, cid: &str, timeout: Option<Duration>) -> Result<(), IpfsError> {
-        self.call(self.cat_url("cat", cid, Some(1)), None, timeout)
-            .await?;
+        self.call(self.cat_request(cid, Some(1)), timeout).await?;
         Ok(())
     }
 
@@ -171,71 +331,147 @@ impl IpfsClient {
         &self,
         cid: &str,
         timeout: Option<Duration>,
-    ) -> Result<impl Stream<Item = Result<Bytes, reqwest::Error>> + 'static, reqwest::Error> {
+    ) -> Result<impl Stream<Item = Result<Bytes, reqwest::Error>> + 'static, IpfsError> {
         Ok(self
-            .call(self.cat_url("cat", cid, None), None, timeout)
+            .call(self.cat_request(cid, None), timeout)
             .await?
             .bytes_stream())
     }
 
-    pub async fn get_block(&self, cid: String) -> Result<Bytes, reqwest::Error> {
-        let form = multipart::Form::new().part("arg", multipart::Part::text(cid));
-        self.call(format!("{}api/v0/block/get", self.base), Some(form), None)
-            .await?
-            .bytes()
-            .await
+    pub async fn get_block(&self, cid: String) -> Result<Bytes, IpfsError> {
+        let req = match self.kind {
+            IpfsEndpointKind::Api => {
+                let form = multipart::Form::new().part("arg", multipart::Part::text(cid));
+                self.client
+                    .post(format!("{}api/v0/block/get", self.base))
+                    .multipart(form)
+            }
+            // URL security: user-supplied input only goes into the path
+            // below `/ipfs/`
+            IpfsEndpointKind::Gateway => self
+                .client
+                .get(format!("{}ipfs/{}?format=raw", self.base, cid))
+                .header(ACCEPT, "application/vnd.ipld.raw"),
+        };
+        Ok(self.call(req, None).await?.bytes().await?)
     }
 
-    pub async fn test(&self) -> Result<(), reqwest::Error> {
-        self.call(format!("{}api/v0/version", self.base), None, None)
-            .await
-            .map(|_| ())
+    pub async fn test(&self) -> Result<(), IpfsError> {
+        let req = match self.kind {
+            IpfsEndpointKind::Api => self
+                .client
+                .post(format!("{}api/v0/version", self.base))
+                .header(CONTENT_LENGTH, 0),
+            // The empty file, inlined in its CID, which every gateway can
+            // serve without fetching anything
+            IpfsEndpointKind::Gateway => self
+                .client
+                .get(format!("{}ipfs/{}", self.base, EMPTY_FILE_CID)),
+        };
+        self.call(req, None).await?;
+        Ok(())
     }
 
-    pub async fn add(&self, data: Vec<u8>) -> Result<AddResponse, reqwest::Error> {
+    pub async fn add(&self, data: Vec<u8>) -> Result<AddResponse, IpfsError> {
+        if self.kind == IpfsEndpointKind::Gateway {
+            return Err(IpfsError::Unsupported(
+                "adding files",
+                self.base.to_string(),
+            ));
+        }
+
         let form = multipart::Form::new().part("path", multipart::Part::bytes(data));
+        let req = self
+            .client
+            .post(format!("{}api/v0/add", self.base))
+            .multipart(form);
 
-        self.call(format!("{}api/v0/add", self.base), Some(form), None)
-            .await?
-            .json()
-            .await
+        Ok(self.call(req, None).await?.json().await?)
     }
 
-    fn cat_url(&self, route: &str, arg: &str, length: Option<u64>) -> String {
-        // URL security: We control the base and the route, user-supplied input goes only into the
-        // query parameters.
-        let mut url = format!("{}api/v0/{}?arg={}", self.base, route, arg);
-        if let Some(length) = length {
-            url.push_str(&format!("&length={}", length));
+    fn cat_request(&self, arg: &str, length: Option<u64>) -> reqwest::RequestBuilder {
+        match self.kind {
+            IpfsEndpointKind::Api => {
+                // URL security: We control the base and the route, user-supplied input goes only into the
+                // query parameters.
+                let mut url = format!("{}api/v0/cat?arg={}", self.base, arg);
+                if let Some(length) = length {
+                    url.push_str(&format!("&length={}", length));
+                }
+                // Some servers require `content-length` even for an empty body.
+                self.client.post(&url).header(CONTENT_LENGTH, 0)
+            }
+            IpfsEndpointKind::Gateway => {
+                // URL security: user-supplied input only goes into the path
+                // below `/ipfs/`
+                let req = self.client.get(format!("{}ipfs/{}", self.base, arg));
+                match length {
+                    Some(length) => req.header(RANGE, format!("bytes=0-{}", length - 1)),
+                    None => req,
+                }
+            }
         }
-        url
     }
 
     async fn call(
         &self,
-        url: String,
-        form: Option<multipart::Form>,
+        mut req: reqwest::RequestBuilder,
         timeout: Option<Duration>,
     ) -> Result<reqwest::Response, reqwest::Error> {
-        let mut req = self.client.post(&url);
-        if let Some(form) = form {
-            req = req.multipart(form);
-        } else {
-            // Some servers require `content-length` even for an empty body.
-            req = req.header(CONTENT_LENGTH, 0);
-        }
-
         if let Some(timeout) = timeout {
             req = req.timeout(timeout)
         }
 
-        req.send()
+        self.health.acquire().await;
+        let res = req
+            .send()
             .await
             .map(|res| res.error_for_status())
-            .and_then(|x| x)
+            .and_then(|x| x);
+
+        match &res {
+            Ok(_) => self.health.success(),
+            Err(e) if is_endpoint_failure(e) => self.health.failure(),
+            Err(_) => {}
+        }
+        res
     }
 }
 
+/// The CID of the empty file, with the content inlined in the CID
+const EMPTY_FILE_CID: &str = "bafkqaaa";
+
+/// The clients whose endpoints are up, in the order in which they were
+/// configured. If all of them are down, return all of them so that
+/// requests are still attempted.
+pub fn available_clients(clients: &[IpfsClient]) -> Vec<IpfsClient> {
+    let up: Vec<_> = clients
+        .iter()
+        .filter(|client| client.is_up())
+        .map(|client| client.cheap_clone())
+        .collect();
+    if up.is_empty() {
+        clients.to_vec()
+    } else {
+        up
+    }
+}
+
+/// The client to use for retrying a request that was last sent to
+/// `current`: `current` if its endpoint is still up, otherwise the first
+/// other client whose endpoint is up and that has retry budget left
+pub fn failover_client(clients: &[IpfsClient], current: &IpfsClient) -> IpfsClient {
+    if current.is_up() {
+        return current.cheap_clone();
+    }
+    clients
+        .iter()
+        .filter(|client| !Arc::ptr_eq(&client.health, &current.health))
+        .find(|client| client.is_up() && client.try_retry())
+        .unwrap_or(current)
+        .cheap_clone()
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
@@ -243,7 +479,7 @@ mod test {
     use anyhow::anyhow;
     use cid::Cid;
 
-    use crate::ipfs_client::CidFile;
+    use crate::ipfs_client::{CidFile, RateWindow};
 
     #[test]
     fn test_cid_parsing() {
@@ -327,4 +563,19 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn rate_window() {
+        let mut window = RateWindow::new(2);
+        assert!(window.take().is_ok());
+        assert!(window.take().is_ok());
+        let wait = window.take().unwrap_err();
+        assert!(wait <= std::time::Duration::from_secs(1));
+
+        // A limit of 0 means no limit
+        let mut window = RateWindow::new(0);
+        for _ in 0..100 {
+            assert!(window.take().is_ok());
+        }
+    }
 }
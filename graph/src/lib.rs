@@ -45,6 +45,7 @@ pub use task_spawn::{
 
 pub use anyhow;
 pub use bytes;
+pub use csv;
 pub use futures01;
 pub use futures03;
 pub use graph_derive as derive;
@@ -53,6 +54,7 @@ pub use http_body_util;
 pub use hyper;
 pub use hyper_util;
 pub use itertools;
+pub use object_store;
 pub use parking_lot;
 pub use petgraph;
 pub use prometheus;
@@ -128,12 +130,13 @@ pub mod prelude {
     pub use crate::components::server::subscription::SubscriptionServer;
     pub use crate::components::store::{
         write::EntityModification, AttributeNames, BlockNumber, CachedEthereumCall, ChainStore,
-        Child, ChildMultiplicity, EntityCache, EntityChange, EntityChangeOperation,
-        EntityCollection, EntityFilter, EntityLink, EntityOperation, EntityOrder,
-        EntityOrderByChild, EntityOrderByChildInfo, EntityQuery, EntityRange, EntityWindow,
-        EthereumCallCache, ParentLink, PartialBlockPtr, PoolWaitStats, QueryStore,
-        QueryStoreManager, StoreError, StoreEvent, StoreEventStream, StoreEventStreamBox,
-        SubgraphStore, UnfailOutcome, WindowAttribute, BLOCK_NUMBER_MAX,
+        Child, ChildMultiplicity, EntityAggregate, EntityCache, EntityChange,
+        EntityChangeOperation, EntityCollection, EntityCursor, EntityFilter, EntityLink,
+        EntityOperation, EntityOrder, EntityOrderByChild, EntityOrderByChildInfo, EntityQuery,
+        EntityRange, EntityWindow, EthereumCallCache, FulltextOptions, ParentLink, PartialBlockPtr,
+        PoolWaitStats, QueryStore, QueryStoreManager, SpatialArea, StoreError, StoreEvent,
+        StoreEventStream, StoreEventStreamBox, SubgraphStore, UnfailOutcome, WindowAttribute,
+        BLOCK_NUMBER_MAX,
     };
     pub use crate::components::subgraph::{
         BlockState, HostMetrics, InstanceDSTemplateInfo, RuntimeHost, RuntimeHostBuilder,
@@ -13,6 +13,29 @@ const GAS_PER_SECOND: u64 = 10_000_000_000;
 /// still charge very high numbers for other things.
 pub const CONST_MAX_GAS_PER_HANDLER: u64 = 1000 * GAS_PER_SECOND;
 
+/// The fuel that one invocation of a handler may use unless the manifest sets a different budget
+/// with `indexerHints.fuel`. Wasmtime charges one unit of fuel for most wasm instructions, so
+/// running out of fuel happens at the same point on every indexer, no matter how fast it is. At
+/// roughly one instruction per nanosecond, this is about 100 seconds of execution.
+pub const DEFAULT_FUEL_PER_HANDLER: u64 = 100_000_000_000;
+
+/// Networks whose handlers get a larger default fuel budget because they regularly process much
+/// larger inputs, like whole blocks with all their transactions or receipts.
+const FUEL_PER_HANDLER_BY_NETWORK: &[(&str, u64)] = &[
+    ("arweave-mainnet", 4 * DEFAULT_FUEL_PER_HANDLER),
+    ("near-mainnet", 4 * DEFAULT_FUEL_PER_HANDLER),
+    ("near-testnet", 4 * DEFAULT_FUEL_PER_HANDLER),
+];
+
+/// The default fuel budget for each invocation of a handler of a subgraph on `network`
+pub fn default_fuel_per_handler(network: &str) -> u64 {
+    FUEL_PER_HANDLER_BY_NETWORK
+        .iter()
+        .find(|(name, _)| *name == network)
+        .map(|(_, fuel)| *fuel)
+        .unwrap_or(DEFAULT_FUEL_PER_HANDLER)
+}
+
 /// Gas for instructions are aggregated into blocks, so hopefully gas calls each have relatively
 /// large gas. But in the case they don't, we don't want the overhead of calling out into a host
 /// export to be the dominant cost that causes unexpectedly high execution times.
@@ -55,6 +78,19 @@ pub const CREATE_DATA_SOURCE: Gas = Gas(CONST_MAX_GAS_PER_HANDLER / 100_000);
 
 pub const ENS_NAME_BY_HASH: Gas = Gas(DEFAULT_BASE_COST);
 
+pub const AUX_LOOKUP: Gas = Gas(DEFAULT_BASE_COST);
+
+// Requesting a file from IPFS; the bytes that are returned are charged
+// separately
+pub const IPFS_REQUEST: Gas = Gas(DEFAULT_BASE_COST);
+
+// Recovering a public key takes about 50µs
+pub const CRYPTO_ECRECOVER: Gas = Gas(50_000 * GAS_PER_SECOND / 1_000_000_000);
+
+// `BigDecimal` roots, logarithms and powers run series or Newton's method with a fixed
+// precision; they take at most about 1ms
+pub const BIG_DECIMAL_FUNCTION: Gas = Gas(GAS_PER_SECOND / 1_000);
+
 pub const LOG_OP: GasOp = GasOp {
     // Allow up to 100,000 logs
     base_cost: CONST_MAX_GAS_PER_HANDLER / 100_000,
@@ -80,6 +80,7 @@ impl Display for Gas {
 #[derive(Clone, CheapClone)]
 pub struct GasCounter {
     counter: Arc<AtomicU64>,
+    limit: Arc<AtomicU64>,
     metrics: GasMetrics,
 }
 
@@ -87,10 +88,18 @@ impl GasCounter {
     pub fn new(metrics: GasMetrics) -> Self {
         Self {
             counter: Arc::new(AtomicU64::new(0)),
+            limit: Arc::new(AtomicU64::new(ENV_VARS.max_gas_per_handler)),
             metrics,
         }
     }
 
+    /// Limit the gas to `limit` for the handler that is about to run. The
+    /// limit can't be raised above `max_gas_per_handler`
+    pub fn set_limit(&self, limit: Gas) {
+        self.limit
+            .store(limit.0.min(ENV_VARS.max_gas_per_handler), SeqCst);
+    }
+
     /// This should be called once per host export
     pub fn consume_host_fn_inner(
         &self,
@@ -112,7 +121,7 @@ impl GasCounter {
             .fetch_update(SeqCst, SeqCst, |v| Some(v.saturating_add(amount.0)))
             .unwrap();
         let new = old.saturating_add(amount.0);
-        if new >= ENV_VARS.max_gas_per_handler {
+        if new >= self.limit.load(SeqCst) {
             Err(DeterministicHostError::gas(anyhow::anyhow!(
                 "Gas limit exceeded. Used: {}",
                 new
@@ -261,6 +261,7 @@ pub enum IndexForAscTypeId {
     ArrayH256 = 1002,
     ArrayLog = 1003,
     ArrayTypedMapStringStoreValue = 1004,
+    EthereumTransfer = 1005,
     // Continue to add more Ethereum type IDs here.
     // e.g.:
     // NextEthereumType = 1004,
@@ -9,17 +9,15 @@ use thiserror::Error;
 
 use crate::cheap_clone::CheapClone;
 use crate::data::graphql::{ObjectOrInterface, ObjectTypeExt, TypeExt};
-use crate::data::store::IdType;
+use crate::data::store::ValueType;
 use crate::env::ENV_VARS;
-use crate::schema::{ast, META_FIELD_NAME, META_FIELD_TYPE, SCHEMA_TYPE_NAME};
+use crate::schema::{ast, META_FIELD_NAME, META_FIELD_TYPE, PAGE_INFO_TYPE, SCHEMA_TYPE_NAME};
 
-use crate::data::graphql::ext::{
-    camel_cased_names, DefinitionExt, DirectiveExt, DocumentExt, ValueExt,
-};
+use crate::data::graphql::ext::{camel_cased_names, DefinitionExt, DocumentExt};
 use crate::derive::CheapClone;
 use crate::prelude::{q, r, s, DeploymentHash};
 
-use super::{Aggregation, Field, InputSchema, Schema, TypeKind};
+use super::{kw, Aggregation, Field, FulltextDefinition, InputSchema, Schema, TypeKind};
 
 #[derive(Error, Debug)]
 pub enum APISchemaError {
@@ -40,6 +38,69 @@ const BLOCK_HEIGHT: &str = "Block_height";
 const CHANGE_BLOCK_FILTER_NAME: &str = "BlockChangedFilter";
 const ERROR_POLICY_TYPE: &str = "_SubgraphErrorPolicy_";
 
+// The followoing types are defined in fulltext.graphql
+const FULLTEXT_RANK_BY_TYPE: &str = "_FulltextRankBy_";
+const FULLTEXT_LANGUAGE_TYPE: &str = "_FulltextLanguage_";
+
+// The followoing types are defined in spatial.graphql
+const SPATIAL_BOX_TYPE: &str = "Spatial_box";
+const SPATIAL_RADIUS_TYPE: &str = "Spatial_radius";
+
+const CONNECTION_TYPE_SUFFIX: &str = "_connection";
+const EDGE_TYPE_SUFFIX: &str = "_edge";
+
+/// The name of the type of the edges in the connection for `type_name`
+pub fn edge_type_name(type_name: &str) -> String {
+    format!("{}{}", type_name, EDGE_TYPE_SUFFIX)
+}
+
+fn connection_type_name(type_name: &str) -> String {
+    format!("{}{}", type_name, CONNECTION_TYPE_SUFFIX)
+}
+
+/// If `type_name` is the name of a connection type, return the name of the
+/// type whose entities the connection pages through
+pub fn connected_type(type_name: &str) -> Option<&str> {
+    type_name.strip_suffix(CONNECTION_TYPE_SUFFIX)
+}
+
+const AGGREGATE_TYPE_SUFFIX: &str = "_aggregate";
+const AGGREGATE_FIELDS_TYPE_SUFFIX: &str = "_aggregate_fields";
+
+fn aggregate_type_name(type_name: &str) -> String {
+    format!("{}{}", type_name, AGGREGATE_TYPE_SUFFIX)
+}
+
+/// The name of the type that holds the `sum`, `avg`, `min` or `max` of the
+/// numeric fields of `type_name`
+pub fn aggregate_fields_type_name(type_name: &str) -> String {
+    format!("{}{}", type_name, AGGREGATE_FIELDS_TYPE_SUFFIX)
+}
+
+/// If `type_name` is the name of an aggregate type, return the name of the
+/// type whose entities are aggregated
+pub fn aggregated_type(type_name: &str) -> Option<&str> {
+    type_name.strip_suffix(AGGREGATE_TYPE_SUFFIX)
+}
+
+/// Parts of the API schema that are only generated when they are turned on
+#[derive(Clone, Copy, Debug, Default)]
+struct ApiFeatures {
+    /// Add `<type>_connection` types and `<plural>Connection` fields
+    connections: bool,
+    /// Add `<type>_aggregate` types and `<plural>Aggregate` fields
+    aggregates: bool,
+}
+
+impl ApiFeatures {
+    fn from_env() -> Self {
+        ApiFeatures {
+            connections: ENV_VARS.graphql.connections,
+            aggregates: ENV_VARS.graphql.collection_aggregates,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone, CheapClone)]
 pub enum ErrorPolicy {
     Allow,
@@ -103,6 +164,7 @@ pub struct ApiSchema {
     // Root types for the api schema.
     pub query_type: Arc<s::ObjectType>,
     pub subscription_type: Option<Arc<s::ObjectType>>,
+    pub mutation_type: Option<Arc<s::ObjectType>>,
     object_types: HashMap<String, Arc<s::ObjectType>>,
 }
 
@@ -126,6 +188,11 @@ impl ApiSchema {
             .get_root_subscription_type()
             .cloned()
             .map(Arc::new);
+        let mutation_type = schema
+            .document
+            .get_root_mutation_type()
+            .cloned()
+            .map(Arc::new);
 
         let object_types = HashMap::from_iter(
             schema
@@ -139,6 +206,7 @@ impl ApiSchema {
             schema,
             query_type: Arc::new(query_type),
             subscription_type,
+            mutation_type,
             object_types,
         })
     }
@@ -352,6 +420,15 @@ fn add_introspection_schema(schema: &mut s::Document) {
 /// all its fields and their input arguments, based on the existing types.
 pub(in crate::schema) fn api_schema(
     input_schema: &InputSchema,
+) -> Result<s::Document, APISchemaError> {
+    api_schema_with_features(input_schema, ApiFeatures::from_env())
+}
+
+/// Like `api_schema`, but `features` determines which optional types and
+/// fields are added to the schema
+fn api_schema_with_features(
+    input_schema: &InputSchema,
+    features: ApiFeatures,
 ) -> Result<s::Document, APISchemaError> {
     // Refactor: Don't clone the schema.
     let mut api = init_api_schema(input_schema)?;
@@ -359,7 +436,13 @@ pub(in crate::schema) fn api_schema(
     add_types_for_object_types(&mut api, input_schema)?;
     add_types_for_interface_types(&mut api, input_schema)?;
     add_types_for_aggregation_types(&mut api, input_schema)?;
-    add_query_type(&mut api.document, input_schema)?;
+    if features.connections {
+        add_connection_types(&mut api.document, input_schema)?;
+    }
+    if features.aggregates {
+        add_aggregate_types(&mut api.document, input_schema)?;
+    }
+    add_query_type(&mut api.document, input_schema, features)?;
     add_subscription_type(&mut api.document, input_schema)?;
     Ok(api.document)
 }
@@ -465,13 +548,165 @@ fn add_meta_field_type(api: &mut s::Document) {
         .extend(META_FIELD_SCHEMA.definitions.iter().cloned());
 }
 
+/// Adds `<type_name>_connection` and `<type_name>_edge` types for all object
+/// and interface types, and the `_PageInfo_` type they share
+fn add_connection_types(
+    api: &mut s::Document,
+    input_schema: &InputSchema,
+) -> Result<(), APISchemaError> {
+    lazy_static! {
+        static ref CONNECTION_SCHEMA: s::Document = {
+            let schema = include_str!("connection.graphql");
+            s::parse_schema(schema).expect("the schema `connection.graphql` is invalid")
+        };
+    }
+
+    fn field(name: &str, type_name: &str) -> s::Field {
+        s::Field {
+            position: Pos::default(),
+            description: None,
+            name: name.to_owned(),
+            arguments: vec![],
+            field_type: s::Type::NonNullType(Box::new(s::Type::NamedType(type_name.to_owned()))),
+            directives: vec![],
+        }
+    }
+
+    fn object_type(name: String, fields: Vec<s::Field>) -> s::Definition {
+        s::Definition::TypeDefinition(s::TypeDefinition::Object(s::ObjectType {
+            position: Pos::default(),
+            description: None,
+            name,
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields,
+        }))
+    }
+
+    api.definitions
+        .extend(CONNECTION_SCHEMA.definitions.iter().cloned());
+
+    let names = input_schema
+        .object_types()
+        .map(|(name, _)| name)
+        .chain(input_schema.interface_types().map(|(name, _)| name));
+    for name in names {
+        let edge_name = edge_type_name(name);
+        let connection_name = connection_type_name(name);
+        for type_name in [&edge_name, &connection_name] {
+            if api.get_named_type(type_name).is_some() {
+                return Err(APISchemaError::TypeExists(type_name.clone()));
+            }
+        }
+
+        let mut edges = field("edges", &edge_name);
+        edges.field_type =
+            s::Type::NonNullType(Box::new(s::Type::ListType(Box::new(edges.field_type))));
+        let connection = object_type(
+            connection_name,
+            vec![edges, field("pageInfo", PAGE_INFO_TYPE)],
+        );
+        let edge = object_type(
+            edge_name,
+            vec![field("node", name), field("cursor", "String")],
+        );
+        api.definitions.push(edge);
+        api.definitions.push(connection);
+    }
+    Ok(())
+}
+
+/// Adds `<type_name>_aggregate` and `<type_name>_aggregate_fields` types
+/// for all object types. The fields type has a `BigDecimal` field for each
+/// numeric scalar field of the object type, and is only added if there is
+/// at least one such field
+fn add_aggregate_types(
+    api: &mut s::Document,
+    input_schema: &InputSchema,
+) -> Result<(), APISchemaError> {
+    fn field(name: &str, type_name: &str, non_null: bool) -> s::Field {
+        let field_type = s::Type::NamedType(type_name.to_owned());
+        s::Field {
+            position: Pos::default(),
+            description: None,
+            name: name.to_owned(),
+            arguments: vec![],
+            field_type: if non_null {
+                s::Type::NonNullType(Box::new(field_type))
+            } else {
+                field_type
+            },
+            directives: vec![],
+        }
+    }
+
+    fn object_type(name: String, fields: Vec<s::Field>) -> s::Definition {
+        s::Definition::TypeDefinition(s::TypeDefinition::Object(s::ObjectType {
+            position: Pos::default(),
+            description: None,
+            name,
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields,
+        }))
+    }
+
+    for (name, object_type) in input_schema.object_types() {
+        let aggregate_name = aggregate_type_name(name);
+        let fields_name = aggregate_fields_type_name(name);
+        for type_name in [&aggregate_name, &fields_name] {
+            if api.get_named_type(type_name).is_some() {
+                return Err(APISchemaError::TypeExists(type_name.clone()));
+            }
+        }
+
+        let numeric_fields: Vec<_> = object_type
+            .fields
+            .iter()
+            .filter(|f| !f.is_list() && !f.is_derived() && f.value_type.is_numeric())
+            .map(|f| field(&f.name, "BigDecimal", false))
+            .collect();
+
+        let mut fields = vec![field("count", "Int", true)];
+        if !numeric_fields.is_empty() {
+            for func in ["sum", "avg", "min", "max"] {
+                fields.push(field(func, &fields_name, true));
+            }
+            api.definitions
+                .push(object_type(fields_name, numeric_fields));
+        }
+        api.definitions.push(object_type(aggregate_name, fields));
+    }
+    Ok(())
+}
+
 fn add_types_for_object_types(
     api: &mut Schema,
     schema: &InputSchema,
 ) -> Result<(), APISchemaError> {
+    lazy_static! {
+        static ref SPATIAL_SCHEMA: s::Document = {
+            let schema = include_str!("spatial.graphql");
+            s::parse_schema(schema).expect("the schema `spatial.graphql` is invalid")
+        };
+    }
+
+    let mut spatial = false;
     for (name, object_type) in schema.object_types() {
-        add_order_by_type(&mut api.document, name, &object_type.fields)?;
-        add_filter_type(api, name, &object_type.fields)?;
+        add_order_by_type(&mut api.document, schema, name, &object_type.fields)?;
+        add_filter_type(
+            api,
+            schema,
+            name,
+            &object_type.fields,
+            object_type.spatial.is_some(),
+        )?;
+        spatial |= object_type.spatial.is_some();
+    }
+    if spatial {
+        api.document
+            .definitions
+            .extend(SPATIAL_SCHEMA.definitions.iter().cloned());
     }
     Ok(())
 }
@@ -482,8 +717,13 @@ fn add_types_for_interface_types(
     input_schema: &InputSchema,
 ) -> Result<(), APISchemaError> {
     for (name, interface_type) in input_schema.interface_types() {
-        add_order_by_type(&mut api.document, name, &interface_type.fields)?;
-        add_filter_type(api, name, &interface_type.fields)?;
+        add_order_by_type(
+            &mut api.document,
+            input_schema,
+            name,
+            &interface_type.fields,
+        )?;
+        add_filter_type(api, input_schema, name, &interface_type.fields, false)?;
     }
     Ok(())
 }
@@ -493,7 +733,7 @@ fn add_types_for_aggregation_types(
     input_schema: &InputSchema,
 ) -> Result<(), APISchemaError> {
     for (name, agg_type) in input_schema.aggregation_types() {
-        add_aggregation_filter_type(api, name, agg_type)?;
+        add_aggregation_filter_type(api, input_schema, name, agg_type)?;
     }
     Ok(())
 }
@@ -501,6 +741,7 @@ fn add_types_for_aggregation_types(
 /// Adds a `<type_name>_orderBy` enum type for the given fields to the schema.
 fn add_order_by_type(
     api: &mut s::Document,
+    input_schema: &InputSchema,
     type_name: &str,
     fields: &[Field],
 ) -> Result<(), APISchemaError> {
@@ -513,7 +754,7 @@ fn add_order_by_type(
                 description: None,
                 name: type_name,
                 directives: vec![],
-                values: field_enum_values(api, fields)?,
+                values: field_enum_values(input_schema, fields)?,
             });
             let def = s::Definition::TypeDefinition(typedef);
             api.definitions.push(def);
@@ -525,7 +766,7 @@ fn add_order_by_type(
 
 /// Generates enum values for the given set of fields.
 fn field_enum_values(
-    schema: &s::Document,
+    input_schema: &InputSchema,
     fields: &[Field],
 ) -> Result<Vec<s::EnumValue>, APISchemaError> {
     let mut enum_values = vec![];
@@ -536,18 +777,21 @@ fn field_enum_values(
             name: field.name.to_string(),
             directives: vec![],
         });
-        enum_values.extend(field_enum_values_from_child_entity(schema, field)?);
+        enum_values.extend(field_enum_values_from_child_entity(input_schema, field)?);
     }
     Ok(enum_values)
 }
 
 fn enum_value_from_child_entity_field(
-    schema: &s::Document,
+    input_schema: &InputSchema,
     parent_field_name: &str,
-    field: &s::Field,
+    field: &Field,
 ) -> Option<s::EnumValue> {
-    if ast::is_list_or_non_null_list_field(field) || ast::is_entity_type(schema, &field.field_type)
-    {
+    let is_entity = matches!(
+        input_schema.kind_of_declared_type(field.field_type.get_base_type()),
+        Some(TypeKind::Object | TypeKind::Interface)
+    );
+    if field.is_list() || is_entity {
         // Sorting on lists or entities is not supported.
         None
     } else {
@@ -561,7 +805,7 @@ fn enum_value_from_child_entity_field(
 }
 
 fn field_enum_values_from_child_entity(
-    schema: &s::Document,
+    input_schema: &InputSchema,
     field: &Field,
 ) -> Result<Vec<s::EnumValue>, APISchemaError> {
     fn resolve_supported_type_name(field_type: &s::Type) -> Option<&String> {
@@ -577,24 +821,22 @@ fn field_enum_values_from_child_entity(
         false => resolve_supported_type_name(&field.field_type),
     };
 
-    Ok(match type_name {
-        Some(name) => {
-            let named_type = schema
-                .get_named_type(name)
-                .ok_or_else(|| APISchemaError::TypeNotFound(name.clone()))?;
-            match named_type {
-                s::TypeDefinition::Object(s::ObjectType { fields, .. })
-                | s::TypeDefinition::Interface(s::InterfaceType { fields, .. }) => fields
-                    .iter()
-                    .filter_map(|f| {
-                        enum_value_from_child_entity_field(schema, field.name.as_str(), f)
-                    })
-                    .collect(),
-                _ => vec![],
-            }
-        }
-        None => vec![],
-    })
+    let type_name = match type_name {
+        Some(name) => name,
+        None => return Ok(vec![]),
+    };
+
+    let fields = match FieldTypeKind::of_type(input_schema, type_name)? {
+        FieldTypeKind::Entity(_) => match input_schema.object_or_interface(type_name, None) {
+            Some(child) => child.fields(),
+            None => return Ok(vec![]),
+        },
+        FieldTypeKind::Scalar(_) | FieldTypeKind::Enum(_) => return Ok(vec![]),
+    };
+    Ok(fields
+        .iter()
+        .filter_map(|f| enum_value_from_child_entity_field(input_schema, field.name.as_str(), f))
+        .collect())
 }
 
 /// Create an input object type definition for the `where` argument of a
@@ -645,10 +887,10 @@ enum FilterOps {
 }
 
 impl FilterOps {
-    fn for_type<'a>(&self, scalar_type: &'a s::ScalarType) -> FilterOpsSet<'a> {
+    fn for_type<'a>(&self, scalar_type: &'a str) -> FilterOpsSet<'a> {
         match self {
-            Self::Object => FilterOpsSet::Object(&scalar_type.name),
-            Self::Aggregation => FilterOpsSet::Aggregation(&scalar_type.name),
+            Self::Object => FilterOpsSet::Object(scalar_type),
+            Self::Aggregation => FilterOpsSet::Aggregation(scalar_type),
         }
     }
 
@@ -718,17 +960,32 @@ impl<'a> FilterOpsSet<'a> {
 }
 
 /// Adds a `<type_name>_filter` enum type for the given fields to the
-/// schema. Used for object and interface types
+/// schema. Used for object and interface types. If `spatial` is `true`,
+/// the filter also gets `withinBox` and `withinRadius` fields
 fn add_filter_type(
     api: &mut Schema,
+    input_schema: &InputSchema,
     type_name: &str,
     fields: &[Field],
+    spatial: bool,
 ) -> Result<(), APISchemaError> {
     let filter_type_name = format!("{}_filter", type_name);
     if api.document.get_named_type(&filter_type_name).is_some() {
         return Err(APISchemaError::TypeExists(filter_type_name));
     }
-    let filter_fields = field_input_values(api, fields, FilterOps::Object)?;
+    let mut filter_fields = field_input_values(input_schema, fields, FilterOps::Object)?;
+    if spatial {
+        filter_fields.push(input_value(
+            kw::WITHIN_BOX,
+            "",
+            s::Type::NamedType(SPATIAL_BOX_TYPE.to_string()),
+        ));
+        filter_fields.push(input_value(
+            kw::WITHIN_RADIUS,
+            "",
+            s::Type::NamedType(SPATIAL_RADIUS_TYPE.to_string()),
+        ));
+    }
 
     let defn = filter_type_defn(filter_type_name, filter_fields);
     api.document.definitions.push(defn);
@@ -738,6 +995,7 @@ fn add_filter_type(
 
 fn add_aggregation_filter_type(
     api: &mut Schema,
+    input_schema: &InputSchema,
     type_name: &str,
     agg: &Aggregation,
 ) -> Result<(), APISchemaError> {
@@ -745,7 +1003,7 @@ fn add_aggregation_filter_type(
     if api.document.get_named_type(&filter_type_name).is_some() {
         return Err(APISchemaError::TypeExists(filter_type_name));
     }
-    let filter_fields = field_input_values(api, &agg.fields, FilterOps::Aggregation)?;
+    let filter_fields = field_input_values(input_schema, &agg.fields, FilterOps::Aggregation)?;
 
     let defn = filter_type_defn(filter_type_name, filter_fields);
     api.document.definitions.push(defn);
@@ -755,96 +1013,92 @@ fn add_aggregation_filter_type(
 
 /// Generates `*_filter` input values for the given set of fields.
 fn field_input_values(
-    schema: &Schema,
+    input_schema: &InputSchema,
     fields: &[Field],
     ops: FilterOps,
 ) -> Result<Vec<s::InputValue>, APISchemaError> {
     let mut input_values = vec![];
     for field in fields {
-        input_values.extend(field_filter_input_values(schema, field, ops)?);
+        input_values.extend(field_filter_input_values(input_schema, field, ops)?);
     }
     Ok(input_values)
 }
 
+/// What kind of type the base type of a field is, as far as generating
+/// filters for it is concerned
+enum FieldTypeKind<'a> {
+    /// The field references an entity, i.e., an object, interface or
+    /// aggregation type. The string is the name of that type
+    Entity(&'a str),
+    Scalar(&'a str),
+    Enum(&'a str),
+}
+
+impl<'a> FieldTypeKind<'a> {
+    fn new(input_schema: &InputSchema, field: &'a Field) -> Result<Self, APISchemaError> {
+        Self::of_type(input_schema, field.field_type.get_base_type())
+    }
+
+    fn of_type(input_schema: &InputSchema, type_name: &'a str) -> Result<Self, APISchemaError> {
+        if ValueType::is_scalar(type_name) {
+            Ok(FieldTypeKind::Scalar(type_name))
+        } else if input_schema.is_enum_type(type_name) {
+            Ok(FieldTypeKind::Enum(type_name))
+        } else if input_schema.kind_of_declared_type(type_name).is_some() {
+            Ok(FieldTypeKind::Entity(type_name))
+        } else {
+            Err(APISchemaError::TypeNotFound(type_name.to_string()))
+        }
+    }
+}
+
 /// Generates `*_filter` input values for the given field.
 fn field_filter_input_values(
-    schema: &Schema,
+    input_schema: &InputSchema,
     field: &Field,
     ops: FilterOps,
 ) -> Result<Vec<s::InputValue>, APISchemaError> {
-    let type_name = field.field_type.get_base_type();
     if field.is_list() {
-        Ok(field_list_filter_input_values(schema, field)?.unwrap_or_default())
-    } else {
-        let named_type = schema
-            .document
-            .get_named_type(type_name)
-            .ok_or_else(|| APISchemaError::TypeNotFound(type_name.to_string()))?;
-        Ok(match named_type {
-            s::TypeDefinition::Object(_) | s::TypeDefinition::Interface(_) => {
-                let scalar_type = id_type_as_scalar(schema, named_type)?.unwrap();
-                let mut input_values = if field.is_derived() {
-                    // Only add `where` filter fields for object and interface fields
-                    // if they are not @derivedFrom
-                    vec![]
-                } else {
-                    // We allow filtering with `where: { other: "some-id" }` and
-                    // `where: { others: ["some-id", "other-id"] }`. In both cases,
-                    // we allow ID strings as the values to be passed to these
-                    // filters.
-                    field_scalar_filter_input_values(
-                        &schema.document,
-                        field,
-                        ops.for_type(&scalar_type),
-                    )
-                };
-                extend_with_child_filter_input_value(field, type_name, &mut input_values);
-                input_values
-            }
-            s::TypeDefinition::Scalar(ref t) => {
-                field_scalar_filter_input_values(&schema.document, field, ops.for_type(t))
-            }
-            s::TypeDefinition::Enum(ref t) => {
-                field_enum_filter_input_values(&schema.document, field, t)
-            }
-            _ => vec![],
-        })
+        return field_list_filter_input_values(input_schema, field);
     }
-}
 
-fn id_type_as_scalar(
-    schema: &Schema,
-    typedef: &s::TypeDefinition,
-) -> Result<Option<s::ScalarType>, APISchemaError> {
-    let id_type = match typedef {
-        s::TypeDefinition::Object(obj_type) => IdType::try_from(obj_type)
-            .map(Option::Some)
-            .map_err(|_| APISchemaError::IllegalIdType(obj_type.name.to_owned())),
-        s::TypeDefinition::Interface(intf_type) => {
-            match schema
-                .types_for_interface
-                .get(&intf_type.name)
-                .and_then(|obj_types| obj_types.first())
-            {
-                None => Ok(Some(IdType::String)),
-                Some(obj_type) => IdType::try_from(obj_type)
-                    .map(Option::Some)
-                    .map_err(|_| APISchemaError::IllegalIdType(obj_type.name.to_owned())),
-            }
+    Ok(match FieldTypeKind::new(input_schema, field)? {
+        FieldTypeKind::Entity(type_name) => {
+            let mut input_values = if field.is_derived() {
+                // Only add `where` filter fields for object and interface fields
+                // if they are not @derivedFrom
+                vec![]
+            } else {
+                // We allow filtering with `where: { other: "some-id" }` and
+                // `where: { others: ["some-id", "other-id"] }`. In both cases,
+                // we allow ID strings as the values to be passed to these
+                // filters.
+                field_scalar_filter_input_values(field, ops.for_type(id_type_as_scalar(field)))
+            };
+            extend_with_child_filter_input_value(field, type_name, &mut input_values);
+            input_values
+        }
+        FieldTypeKind::Scalar(type_name) => {
+            field_scalar_filter_input_values(field, ops.for_type(type_name))
         }
-        _ => Ok(None),
-    }?;
-    let scalar_type = id_type.map(|id_type| match id_type {
-        IdType::String | IdType::Bytes => s::ScalarType::new(String::from("String")),
+        FieldTypeKind::Enum(type_name) => field_enum_filter_input_values(field, type_name),
+    })
+}
+
+/// Return the name of the scalar type that should be used when filtering
+/// `field`, which must reference an entity type, by id. The `value_type`
+/// of such a field is the type of the `id` of the referenced entity type
+fn id_type_as_scalar(field: &Field) -> &'static str {
+    match field.value_type {
         // It would be more logical to use "Int8" here, but currently, that
         // leads to values being turned into strings, not i64 which causes
         // database queries to fail in various places. Once this is fixed
         // (check e.g., `Value::coerce_scalar` in `graph/src/data/value.rs`)
         // we can turn that into "Int8". For now, queries can only query
         // Int8 id values up to i32::MAX.
-        IdType::Int8 => s::ScalarType::new(String::from("Int")),
-    });
-    Ok(scalar_type)
+        ValueType::Int8 => "Int",
+        _ => "String",
+    }
 }
 
 fn field_filter_ops(set: FilterOpsSet<'_>) -> &'static [&'static str] {
@@ -888,6 +1142,9 @@ fn field_filter_ops(set: FilterOpsSet<'_>) -> &'static [&'static str] {
             "ends_with_nocase",
             "not_ends_with",
             "not_ends_with_nocase",
+            "imatches",
+            "regex",
+            "iregex",
         ],
         Aggregation("BigInt")
         | Aggregation("BigDecimal")
@@ -900,11 +1157,7 @@ fn field_filter_ops(set: FilterOpsSet<'_>) -> &'static [&'static str] {
 }
 
 /// Generates `*_filter` input values for the given scalar field.
-fn field_scalar_filter_input_values(
-    _schema: &s::Document,
-    field: &Field,
-    set: FilterOpsSet<'_>,
-) -> Vec<s::InputValue> {
+fn field_scalar_filter_input_values(field: &Field, set: FilterOpsSet<'_>) -> Vec<s::InputValue> {
     field_filter_ops(set)
         .into_iter()
         .map(|filter_type| {
@@ -934,15 +1187,11 @@ fn extend_with_child_filter_input_value(
 }
 
 /// Generates `*_filter` input values for the given enum field.
-fn field_enum_filter_input_values(
-    _schema: &s::Document,
-    field: &Field,
-    field_type: &s::EnumType,
-) -> Vec<s::InputValue> {
+fn field_enum_filter_input_values(field: &Field, type_name: &str) -> Vec<s::InputValue> {
     vec!["", "not", "in", "not_in"]
         .into_iter()
         .map(|filter_type| {
-            let field_type = s::Type::NamedType(field_type.name.clone());
+            let field_type = s::Type::NamedType(type_name.to_string());
             let value_type = match filter_type {
                 "in" | "not_in" => {
                     s::Type::ListType(Box::new(s::Type::NonNullType(Box::new(field_type))))
@@ -956,33 +1205,27 @@ fn field_enum_filter_input_values(
 
 /// Generates `*_filter` input values for the given list field.
 fn field_list_filter_input_values(
-    schema: &Schema,
+    input_schema: &InputSchema,
     field: &Field,
-) -> Result<Option<Vec<s::InputValue>>, APISchemaError> {
-    // Only add a filter field if the type of the field exists in the schema
-    let typedef = match ast::get_type_definition_from_type(&schema.document, &field.field_type) {
-        Some(typedef) => typedef,
-        None => return Ok(None),
-    };
+) -> Result<Vec<s::InputValue>, APISchemaError> {
+    let kind = FieldTypeKind::new(input_schema, field)?;
 
     // Decide what type of values can be passed to the filter. In the case
     // one-to-many or many-to-many object or interface fields that are not
     // derived, we allow ID strings to be passed on.
     // Adds child filter only to object types.
-    let (input_field_type, parent_type_name) = match typedef {
-        s::TypeDefinition::Object(s::ObjectType { name, .. })
-        | s::TypeDefinition::Interface(s::InterfaceType { name, .. }) => {
+    let (input_field_type, parent_type_name) = match kind {
+        FieldTypeKind::Entity(name) => {
             if field.is_derived() {
-                (None, Some(name.clone()))
+                (None, Some(name))
             } else {
-                let scalar_type = id_type_as_scalar(schema, typedef)?.unwrap();
-                let named_type = s::Type::NamedType(scalar_type.name);
-                (Some(named_type), Some(name.clone()))
+                let named_type = s::Type::NamedType(id_type_as_scalar(field).to_string());
+                (Some(named_type), Some(name))
             }
         }
-        s::TypeDefinition::Scalar(ref t) => (Some(s::Type::NamedType(t.name.clone())), None),
-        s::TypeDefinition::Enum(ref t) => (Some(s::Type::NamedType(t.name.clone())), None),
-        s::TypeDefinition::InputObject(_) | s::TypeDefinition::Union(_) => (None, None),
+        FieldTypeKind::Scalar(name) | FieldTypeKind::Enum(name) => {
+            (Some(s::Type::NamedType(name.to_string())), None)
+        }
     };
 
     let mut input_values: Vec<s::InputValue> = match input_field_type {
@@ -1011,10 +1254,10 @@ fn field_list_filter_input_values(
     };
 
     if let Some(parent) = parent_type_name {
-        extend_with_child_filter_input_value(field, &parent, &mut input_values);
+        extend_with_child_filter_input_value(field, parent, &mut input_values);
     }
 
-    Ok(Some(input_values))
+    Ok(input_values)
 }
 
 /// Generates a `*_filter` input value for the given field name, suffix and value type.
@@ -1034,7 +1277,18 @@ fn input_value(name: &str, suffix: &'static str, value_type: s::Type) -> s::Inpu
 }
 
 /// Adds a root `Query` object type to the schema.
-fn add_query_type(api: &mut s::Document, input_schema: &InputSchema) -> Result<(), APISchemaError> {
+fn add_query_type(
+    api: &mut s::Document,
+    input_schema: &InputSchema,
+    features: ApiFeatures,
+) -> Result<(), APISchemaError> {
+    lazy_static! {
+        static ref FULLTEXT_SCHEMA: s::Document = {
+            let schema = include_str!("fulltext.graphql");
+            s::parse_schema(schema).expect("the schema `fulltext.graphql` is invalid")
+        };
+    }
+
     let type_name = String::from("Query");
 
     if api.get_named_type(&type_name).is_some() {
@@ -1045,19 +1299,36 @@ fn add_query_type(api: &mut s::Document, input_schema: &InputSchema) -> Result<(
         .object_types()
         .map(|(name, _)| name)
         .chain(input_schema.interface_types().map(|(name, _)| name))
-        .flat_map(|name| query_fields_for_type(name, FilterOps::Object))
+        .flat_map(|name| {
+            let mut fields = query_fields_for_type(name, FilterOps::Object);
+            if features.connections {
+                fields.push(query_field_for_connection(name));
+            }
+            fields
+        })
         .collect::<Vec<s::Field>>();
+    if features.aggregates {
+        fields.extend(
+            input_schema
+                .object_types()
+                .map(|(name, _)| query_field_for_aggregate(name)),
+        );
+    }
     let mut agg_fields = input_schema
         .aggregation_types()
         .map(|(name, _)| name)
         .flat_map(query_fields_for_agg_type)
         .collect::<Vec<s::Field>>();
     let mut fulltext_fields = input_schema
-        .get_fulltext_directives()
+        .all_fulltext_definitions()
         .map_err(|_| APISchemaError::FulltextSearchNonDeterministic)?
         .iter()
-        .filter_map(|fulltext| query_field_for_fulltext(fulltext))
-        .collect();
+        .map(query_field_for_fulltext)
+        .collect::<Vec<s::Field>>();
+    if !fulltext_fields.is_empty() {
+        api.definitions
+            .extend(FULLTEXT_SCHEMA.definitions.iter().cloned());
+    }
     fields.append(&mut agg_fields);
     fields.append(&mut fulltext_fields);
     fields.push(meta_field());
@@ -1075,14 +1346,8 @@ fn add_query_type(api: &mut s::Document, input_schema: &InputSchema) -> Result<(
     Ok(())
 }
 
-fn query_field_for_fulltext(fulltext: &s::Directive) -> Option<s::Field> {
-    let name = fulltext.argument("name").unwrap().as_str().unwrap().into();
-
-    let includes = fulltext.argument("include").unwrap().as_list().unwrap();
-    // Only one include is allowed per fulltext directive
-    let include = includes.iter().next().unwrap();
-    let included_entity = include.as_object().unwrap();
-    let entity_name = included_entity.get("entity").unwrap().as_str().unwrap();
+fn query_field_for_fulltext(fulltext: &FulltextDefinition) -> s::Field {
+    let entity_name = fulltext.entity.as_str();
 
     let mut arguments = vec![
         // text: String
@@ -1119,20 +1384,41 @@ fn query_field_for_fulltext(fulltext: &s::Directive) -> Option<s::Field> {
             "",
             s::Type::NamedType(format!("{}_filter", entity_name)),
         ),
+        // rankBy: _FulltextRankBy_
+        input_value(
+            "rankBy",
+            "",
+            s::Type::NamedType(FULLTEXT_RANK_BY_TYPE.to_string()),
+        ),
+        // language: _FulltextLanguage_
+        input_value(
+            "language",
+            "",
+            s::Type::NamedType(FULLTEXT_LANGUAGE_TYPE.to_string()),
+        ),
+        // highlight: Boolean
+        s::InputValue {
+            position: Pos::default(),
+            description: None,
+            name: String::from("highlight"),
+            value_type: s::Type::NamedType(String::from("Boolean")),
+            default_value: Some(s::Value::Boolean(false)),
+            directives: vec![],
+        },
     ];
 
     arguments.push(subgraph_error_argument());
 
-    Some(s::Field {
+    s::Field {
         position: Pos::default(),
         description: None,
-        name,
+        name: fulltext.name.clone(),
         arguments,
         field_type: s::Type::NonNullType(Box::new(s::Type::ListType(Box::new(
             s::Type::NonNullType(Box::new(s::Type::NamedType(entity_name.into()))),
         )))), // included entity type name
-        directives: vec![fulltext.clone()],
-    })
+        directives: vec![],
+    }
 }
 
 /// Adds a root `Subscription` object type to the schema.
@@ -1262,6 +1548,66 @@ fn query_fields_for_type(type_name: &str, ops: FilterOps) -> Vec<s::Field> {
     ]
 }
 
+/// Generates the `Query` field that pages through the entities of the
+/// given type with cursors (e.g. `usersConnection`)
+fn query_field_for_connection(type_name: &str) -> s::Field {
+    let mut arguments: Vec<_> = FilterOps::Object
+        .collection_arguments(type_name)
+        .into_iter()
+        .filter(|arg| arg.name != "skip")
+        .collect();
+    let mut after = input_value("after", "", s::Type::NamedType("String".to_string()));
+    after.description = Some(
+        "Only return entities after the one with this cursor. \
+         Use the `endCursor` of the previous page to get the next page."
+            .to_owned(),
+    );
+    arguments.push(after);
+    arguments.push(block_argument());
+    arguments.push(subgraph_error_argument());
+
+    let (_, plural) = camel_cased_names(type_name);
+    s::Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}Connection", plural),
+        arguments,
+        field_type: s::Type::NonNullType(Box::new(s::Type::NamedType(connection_type_name(
+            type_name,
+        )))),
+        directives: vec![],
+    }
+}
+
+/// Generates the `Query` field that aggregates over the entities of the
+/// given type (e.g. `usersAggregate`)
+fn query_field_for_aggregate(type_name: &str) -> s::Field {
+    let arguments = vec![
+        input_value(
+            "where",
+            "",
+            s::Type::NamedType(format!("{}_filter", type_name)),
+        ),
+        block_argument(),
+        subgraph_error_argument(),
+    ];
+
+    let (_, plural) = camel_cased_names(type_name);
+    s::Field {
+        position: Pos::default(),
+        description: Some(format!(
+            "Count `{}` entities and aggregate their numeric fields",
+            type_name
+        )),
+        name: format!("{}Aggregate", plural),
+        arguments,
+        field_type: s::Type::NonNullType(Box::new(s::Type::NamedType(aggregate_type_name(
+            type_name,
+        )))),
+        directives: vec![],
+    }
+}
+
 fn query_fields_for_agg_type(type_name: &str) -> Vec<s::Field> {
     let mut collection_arguments = FilterOps::Aggregation.collection_arguments(type_name);
     collection_arguments.push(block_argument());
@@ -1312,7 +1658,7 @@ mod tests {
             subgraph::LATEST_VERSION,
         },
         prelude::{s, DeploymentHash},
-        schema::{InputSchema, SCHEMA_TYPE_NAME},
+        schema::{InputSchema, Schema, SCHEMA_TYPE_NAME},
     };
     use graphql_parser::schema::*;
     use lazy_static::lazy_static;
@@ -1673,6 +2019,9 @@ mod tests {
                 "name_ends_with_nocase",
                 "name_not_ends_with",
                 "name_not_ends_with_nocase",
+                "name_imatches",
+                "name_regex",
+                "name_iregex",
                 "favoritePetNames",
                 "favoritePetNames_not",
                 "favoritePetNames_contains",
@@ -1710,6 +2059,9 @@ mod tests {
                 "favoritePet_ends_with_nocase",
                 "favoritePet_not_ends_with",
                 "favoritePet_not_ends_with_nocase",
+                "favoritePet_imatches",
+                "favoritePet_regex",
+                "favoritePet_iregex",
                 "favoritePet_",
                 "leastFavoritePet_",
                 "mostFavoritePets_",
@@ -1778,6 +2130,9 @@ mod tests {
                 "name_ends_with_nocase",
                 "name_not_ends_with",
                 "name_not_ends_with_nocase",
+                "name_imatches",
+                "name_regex",
+                "name_iregex",
                 "mostHatedBy",
                 "mostHatedBy_not",
                 "mostHatedBy_contains",
@@ -1896,6 +2251,9 @@ mod tests {
                 "name_ends_with_nocase",
                 "name_not_ends_with",
                 "name_not_ends_with_nocase",
+                "name_imatches",
+                "name_regex",
+                "name_iregex",
                 "pets_",
                 "favoritePet",
                 "favoritePet_not",
@@ -1917,6 +2275,9 @@ mod tests {
                 "favoritePet_ends_with_nocase",
                 "favoritePet_not_ends_with",
                 "favoritePet_not_ends_with_nocase",
+                "favoritePet_imatches",
+                "favoritePet_regex",
+                "favoritePet_iregex",
                 "favoritePet_",
                 "_change_block",
                 "and",
@@ -2142,11 +2503,202 @@ type Gravatar @entity {
             .get_named_type("Query")
             .expect("Query type is missing in derived API schema");
 
-        let _metadata_field = match query_type {
+        let metadata_field = match query_type {
             TypeDefinition::Object(t) => ast::get_field(t, &String::from("metadata")),
             _ => None,
         }
         .expect("\"metadata\" field is missing on Query type");
+
+        let argument_type = |name: &str| -> Option<String> {
+            metadata_field
+                .arguments
+                .iter()
+                .find(|argument| argument.name == name)
+                .map(|argument| argument.value_type.to_string())
+        };
+        assert_eq!(
+            Some("_FulltextRankBy_".to_string()),
+            argument_type("rankBy")
+        );
+        assert_eq!(
+            Some("_FulltextLanguage_".to_string()),
+            argument_type("language")
+        );
+        assert_eq!(Some("Boolean".to_string()), argument_type("highlight"));
+        assert!(schema.get_named_type("_FulltextRankBy_").is_some());
+        assert!(schema.get_named_type("_FulltextLanguage_").is_some());
+    }
+
+    #[track_caller]
+    fn field_type(schema: &ApiSchema, type_name: &str, field_name: &str) -> String {
+        match schema.get_named_type(type_name) {
+            Some(TypeDefinition::Object(t)) => ast::get_field(t, field_name)
+                .expect(&format!("{} should have a field {}", type_name, field_name))
+                .field_type
+                .to_string(),
+            _ => panic!("Schema should contain an object type {}", type_name),
+        }
+    }
+
+    #[test]
+    fn api_schema_contains_connections() {
+        const SCHEMA: &str = r#"
+type User @entity { id: ID!, name: String! }
+interface Pet { id: ID!, name: String! }
+type Dog implements Pet @entity { id: ID!, name: String! }
+"#;
+        let input_schema = InputSchema::parse(LATEST_VERSION, SCHEMA, ID.clone())
+            .expect("Failed to parse input schema");
+        let features = super::ApiFeatures {
+            connections: true,
+            ..Default::default()
+        };
+        let document = super::api_schema_with_features(&input_schema, features)
+            .expect("Failed to derive API schema");
+        let schema = Schema::new(ID.clone(), document).unwrap();
+        let schema = ApiSchema::from_api_schema(schema).unwrap();
+
+        for (plural, type_name) in [("usersConnection", "User"), ("petsConnection", "Pet")] {
+            let field = query_field(&schema, plural);
+            let args: Vec<_> = field
+                .arguments
+                .iter()
+                .map(|arg| arg.name.as_str())
+                .collect();
+            assert_eq!(
+                vec![
+                    "first",
+                    "orderBy",
+                    "orderDirection",
+                    "where",
+                    "after",
+                    "block",
+                    "subgraphError"
+                ],
+                args
+            );
+            assert_eq!(
+                format!("{}_connection!", type_name),
+                field.field_type.to_string()
+            );
+
+            let connection = format!("{}_connection", type_name);
+            let edge = format!("{}_edge", type_name);
+            assert_eq!(
+                format!("[{}!]!", edge),
+                field_type(&schema, &connection, "edges")
+            );
+            assert_eq!("_PageInfo_!", field_type(&schema, &connection, "pageInfo"));
+            assert_eq!(
+                format!("{}!", type_name),
+                field_type(&schema, &edge, "node")
+            );
+            assert_eq!("String!", field_type(&schema, &edge, "cursor"));
+        }
+        assert_eq!("Boolean!", field_type(&schema, "_PageInfo_", "hasNextPage"));
+    }
+
+    #[test]
+    fn api_schema_contains_aggregates() {
+        const SCHEMA: &str = r#"
+type Token @entity {
+  id: ID!
+  name: String!
+  volume: BigDecimal!
+  holders: Int
+  balances: [BigInt!]!
+}
+type Tag @entity { id: ID!, name: String! }
+"#;
+        let input_schema = InputSchema::parse(LATEST_VERSION, SCHEMA, ID.clone())
+            .expect("Failed to parse input schema");
+        let features = super::ApiFeatures {
+            aggregates: true,
+            ..Default::default()
+        };
+        let document = super::api_schema_with_features(&input_schema, features)
+            .expect("Failed to derive API schema");
+        let schema = Schema::new(ID.clone(), document).unwrap();
+        let schema = ApiSchema::from_api_schema(schema).unwrap();
+
+        let field = query_field(&schema, "tokensAggregate");
+        let args: Vec<_> = field
+            .arguments
+            .iter()
+            .map(|arg| arg.name.as_str())
+            .collect();
+        assert_eq!(vec!["where", "block", "subgraphError"], args);
+        assert_eq!("Token_aggregate!", field.field_type.to_string());
+
+        assert_eq!("Int!", field_type(&schema, "Token_aggregate", "count"));
+        for func in ["sum", "avg", "min", "max"] {
+            assert_eq!(
+                "Token_aggregate_fields!",
+                field_type(&schema, "Token_aggregate", func)
+            );
+        }
+        let fields = match schema.get_named_type("Token_aggregate_fields") {
+            Some(TypeDefinition::Object(t)) => t
+                .fields
+                .iter()
+                .map(|field| (field.name.as_str(), field.field_type.to_string()))
+                .collect::<Vec<_>>(),
+            _ => panic!("Schema should contain Token_aggregate_fields"),
+        };
+        assert_eq!(
+            vec![
+                ("volume", "BigDecimal".to_string()),
+                ("holders", "BigDecimal".to_string())
+            ],
+            fields
+        );
+
+        // `Tag` has no numeric fields, and can only be counted
+        assert_eq!("Int!", field_type(&schema, "Tag_aggregate", "count"));
+        assert!(schema.get_named_type("Tag_aggregate_fields").is_none());
+        match schema.get_named_type("Tag_aggregate") {
+            Some(TypeDefinition::Object(t)) => assert_eq!(1, t.fields.len()),
+            _ => panic!("Schema should contain Tag_aggregate"),
+        }
+    }
+
+    #[test]
+    fn api_schema_contains_spatial_filters() {
+        const SCHEMA: &str = r#"
+type Shop @entity @spatial(latitude: "lat", longitude: "lon") {
+  id: ID!
+  lat: BigDecimal!
+  lon: BigDecimal!
+}
+type Tag @entity { id: ID!, name: String! }
+"#;
+        let schema = parse(SCHEMA);
+
+        let filter_field_type = |type_name: &str, field_name: &str| -> Option<String> {
+            match schema.get_named_type(type_name) {
+                Some(TypeDefinition::InputObject(t)) => t
+                    .fields
+                    .iter()
+                    .find(|field| field.name == field_name)
+                    .map(|field| field.value_type.to_string()),
+                _ => panic!("Schema should contain an input type {}", type_name),
+            }
+        };
+
+        assert_eq!(
+            Some("Spatial_box".to_string()),
+            filter_field_type("Shop_filter", "withinBox")
+        );
+        assert_eq!(
+            Some("Spatial_radius".to_string()),
+            filter_field_type("Shop_filter", "withinRadius")
+        );
+        assert_eq!(None, filter_field_type("Tag_filter", "withinBox"));
+        assert_eq!(
+            Some("BigDecimal!".to_string()),
+            filter_field_type("Spatial_radius", "meters")
+        );
+        assert!(schema.get_named_type("Spatial_box").is_some());
     }
 
     #[test]
@@ -32,6 +32,9 @@ pub enum FilterOp {
     EndsWithNoCase,
     NotEndsWith,
     NotEndsWithNoCase,
+    MatchesNoCase,
+    Regex,
+    RegexNoCase,
     Equal,
     Child,
     And,
@@ -68,6 +71,9 @@ pub fn parse_field_as_filter(key: &str) -> (String, FilterOp) {
         }
         k if k.ends_with("_ends_with") => ("_ends_with", FilterOp::EndsWith),
         k if k.ends_with("_ends_with_nocase") => ("_ends_with_nocase", FilterOp::EndsWithNoCase),
+        k if k.ends_with("_imatches") => ("_imatches", FilterOp::MatchesNoCase),
+        k if k.ends_with("_regex") => ("_regex", FilterOp::Regex),
+        k if k.ends_with("_iregex") => ("_iregex", FilterOp::RegexNoCase),
         k if k.ends_with('_') => ("_", FilterOp::Child),
         k if k.eq("and") => ("and", FilterOp::And),
         k if k.eq("or") => ("or", FilterOp::Or),
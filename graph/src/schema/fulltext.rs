@@ -106,6 +106,8 @@ pub struct FulltextConfig {
 
 pub struct FulltextDefinition {
     pub config: FulltextConfig,
+    /// The name of the entity type whose fields are searched
+    pub entity: String,
     pub included_fields: HashSet<String>,
     pub name: String,
 }
@@ -128,6 +130,7 @@ impl From<&s::Directive> for FulltextDefinition {
         let included_entity_list = directive.argument("include").unwrap().as_list().unwrap();
         // Currently fulltext query fields are limited to 1 entity, so we just take the first (and only) included Entity
         let included_entity = included_entity_list.first().unwrap().as_object().unwrap();
+        let entity = included_entity.get("entity").unwrap().as_str().unwrap();
         let included_field_values = included_entity.get("fields").unwrap().as_list().unwrap();
         let included_fields: HashSet<String> = included_field_values
             .iter()
@@ -148,6 +151,7 @@ impl From<&s::Directive> for FulltextDefinition {
                 language,
                 algorithm,
             },
+            entity: entity.into(),
             included_fields,
             name: name.into(),
         }
@@ -20,7 +20,7 @@ use crate::data::store::{
 use crate::data::value::Word;
 use crate::derive::CheapClone;
 use crate::prelude::q::Value;
-use crate::prelude::{s, DeploymentHash};
+use crate::prelude::{s, BlockNumber, DeploymentHash};
 use crate::schema::api::api_schema;
 use crate::util::intern::{Atom, AtomPool};
 
@@ -49,6 +49,14 @@ pub mod kw {
     pub const INTERVALS: &str = "intervals";
     pub const INTERVAL: &str = "interval";
     pub const CUMULATIVE: &str = "cumulative";
+    pub const WEIGHT: &str = "weight";
+    pub const SPATIAL: &str = "spatial";
+    pub const LATITUDE: &str = "latitude";
+    pub const LONGITUDE: &str = "longitude";
+    pub const WITHIN_BOX: &str = "withinBox";
+    pub const WITHIN_RADIUS: &str = "withinRadius";
+    pub const TTL: &str = "ttl";
+    pub const BLOCKS: &str = "blocks";
 }
 
 /// The internal representation of a subgraph schema, i.e., the
@@ -346,6 +354,15 @@ impl<'a> ObjectOrInterface<'a> {
         }
     }
 
+    /// Return all fields of the type. For interfaces, these are the fields
+    /// declared in the interface
+    pub fn fields(self) -> &'a [Field] {
+        match self {
+            ObjectOrInterface::Object(_, object) => &object.fields,
+            ObjectOrInterface::Interface(_, interface) => &interface.fields,
+        }
+    }
+
     /// Return the field with the given name. For object types, that's the
     /// field with that name. For interfaces, it's the field with that name
     /// in the first object type that implements the interface; to be
@@ -368,6 +385,15 @@ impl<'a> ObjectOrInterface<'a> {
         }
     }
 
+    /// The coordinates of the type if it is an object type that is marked
+    /// with `@spatial`
+    pub fn spatial(&self) -> Option<&Spatial> {
+        match self {
+            ObjectOrInterface::Object(_, object) => object.spatial.as_ref(),
+            ObjectOrInterface::Interface(_, _) => None,
+        }
+    }
+
     pub fn derived_from(&self, field_name: &str) -> Option<&str> {
         let field = self.field(field_name)?;
         field.derived_from.as_ref().map(|name| name.as_str())
@@ -413,10 +439,26 @@ pub struct ObjectType {
     /// is part of an aggregation
     aggregation: Option<Atom>,
     pub timeseries: bool,
+    /// The coordinates of entities of this type if the type is marked
+    /// with `@spatial`
+    pub spatial: Option<Spatial>,
+    /// For how many blocks after it was written a version of an entity of
+    /// this type is visible to queries if the type is marked with
+    /// `@ttl(blocks: ..)`
+    pub ttl: Option<BlockNumber>,
     interfaces: Box<[Word]>,
     shared_interfaces: Box<[Atom]>,
 }
 
+/// The fields that hold the latitude and longitude of an entity, both in
+/// degrees. Entity types marked with `@spatial(latitude: .., longitude:
+/// ..)` can be filtered with `withinBox` and `withinRadius`
+#[derive(PartialEq, Debug)]
+pub struct Spatial {
+    pub latitude: Word,
+    pub longitude: Word,
+}
+
 impl ObjectType {
     fn new(
         schema: &Schema,
@@ -452,6 +494,25 @@ impl ObjectType {
             None => timeseries,
             _ => unreachable!("validations ensure we don't get here"),
         };
+        let spatial = object_type.find_directive(kw::SPATIAL).map(|dir| {
+            let field = |name| match dir.argument(name) {
+                Some(Value::String(field)) => Word::from(field.as_str()),
+                _ => unreachable!("validations ensure we don't get here"),
+            };
+            Spatial {
+                latitude: field(kw::LATITUDE),
+                longitude: field(kw::LONGITUDE),
+            }
+        });
+        let ttl = object_type
+            .find_directive(kw::TTL)
+            .map(|dir| match dir.argument(kw::BLOCKS) {
+                Some(Value::Int(blocks)) => blocks
+                    .as_i64()
+                    .and_then(|blocks| BlockNumber::try_from(blocks).ok())
+                    .expect("validations ensure we don't get here"),
+                _ => unreachable!("validations ensure we don't get here"),
+            });
         Self {
             name,
             fields,
@@ -459,6 +520,8 @@ impl ObjectType {
             immutable,
             aggregation: None,
             timeseries,
+            spatial,
+            ttl,
             interfaces,
             shared_interfaces,
         }
@@ -490,6 +553,8 @@ impl ObjectType {
             immutable: false,
             aggregation: None,
             timeseries: false,
+            spatial: None,
+            ttl: None,
             fields,
             shared_interfaces: Box::new([]),
         }
@@ -610,6 +675,8 @@ pub enum AggregateFn {
     Count,
     First,
     Last,
+    CountDistinct,
+    WeightedAvg,
 }
 
 impl FromStr for AggregateFn {
@@ -623,6 +690,8 @@ impl FromStr for AggregateFn {
             "count" => Ok(AggregateFn::Count),
             "first" => Ok(AggregateFn::First),
             "last" => Ok(AggregateFn::Last),
+            "count_distinct" => Ok(AggregateFn::CountDistinct),
+            "weighted_avg" => Ok(AggregateFn::WeightedAvg),
             _ => Err(anyhow!("invalid aggregate function `{}`", s)),
         }
     }
@@ -632,11 +701,38 @@ impl AggregateFn {
     pub fn has_arg(&self) -> bool {
         use AggregateFn::*;
         match self {
-            Sum | Max | Min | First | Last => true,
+            Sum | Max | Min | First | Last | CountDistinct | WeightedAvg => true,
             Count => false,
         }
     }
 
+    /// Whether the argument of the function must be numeric. Only
+    /// `count_distinct` can count values of any type
+    pub fn has_numeric_arg(&self) -> bool {
+        use AggregateFn::*;
+        match self {
+            Sum | Max | Min | First | Last | Count | WeightedAvg => true,
+            CountDistinct => false,
+        }
+    }
+
+    /// Whether the function takes a `weight` argument
+    pub fn has_weight(&self) -> bool {
+        matches!(self, AggregateFn::WeightedAvg)
+    }
+
+    /// Whether the aggregates of two intervals can be combined into the
+    /// aggregate of both intervals, which is needed for cumulative
+    /// aggregates. Distinct counts and weighted averages would need more
+    /// than the aggregated value for that
+    pub fn can_be_cumulative(&self) -> bool {
+        use AggregateFn::*;
+        match self {
+            Sum | Max | Min | First | Last | Count => true,
+            CountDistinct | WeightedAvg => false,
+        }
+    }
+
     fn as_str(&self) -> &'static str {
         use AggregateFn::*;
         match self {
@@ -646,6 +742,8 @@ impl AggregateFn {
             Count => "count",
             First => "first",
             Last => "last",
+            CountDistinct => "count_distinct",
+            WeightedAvg => "weighted_avg",
         }
     }
 }
@@ -777,6 +875,9 @@ pub struct Aggregate {
     pub func: AggregateFn,
     /// The field to aggregate in the source table
     pub arg: Word,
+    /// The weight of each value for `weighted_avg`, an expression over
+    /// fields in the source table
+    pub weight: Option<Word>,
     /// The type of the field `name` in the aggregation
     pub field_type: s::Type,
     /// The `ValueType` corresponding to `field_type`
@@ -786,8 +887,17 @@ pub struct Aggregate {
 }
 
 impl Aggregate {
-    fn new(_schema: &Schema, name: &str, field_type: &s::Type, dir: &s::Directive) -> Self {
-        let func = dir
+    /// Create the aggregate for the `@aggregate` directive `dir`. Unless
+    /// `dir` says otherwise, the aggregate is cumulative if
+    /// `cumulative_default` is set and its function can be cumulative
+    fn new(
+        _schema: &Schema,
+        name: &str,
+        field_type: &s::Type,
+        dir: &s::Directive,
+        cumulative_default: bool,
+    ) -> Self {
+        let func: AggregateFn = dir
             .argument("fn")
             .unwrap()
             .as_str()
@@ -802,18 +912,22 @@ impl Aggregate {
             .argument("arg")
             .map(|arg| Word::from(arg.as_str().unwrap()))
             .unwrap_or_else(|| ID.clone());
+        let weight = dir
+            .argument(kw::WEIGHT)
+            .map(|weight| Word::from(weight.as_str().unwrap()));
         let cumulative = dir
             .argument(kw::CUMULATIVE)
             .map(|arg| match arg {
                 Value::Boolean(b) => *b,
                 _ => unreachable!("validation ensures this is a boolean"),
             })
-            .unwrap_or(false);
+            .unwrap_or(cumulative_default && func.can_be_cumulative());
 
         Aggregate {
             name: Word::from(name),
             func,
             arg,
+            weight,
             cumulative,
             field_type: field_type.clone(),
             value_type: field_type.get_base_type().parse().unwrap(),
@@ -859,6 +973,15 @@ impl Aggregation {
             .as_str()
             .unwrap();
         let source = pool.lookup(source).unwrap();
+        let cumulative = agg_type
+            .find_directive(kw::AGGREGATION)
+            .unwrap()
+            .argument(kw::CUMULATIVE)
+            .map(|arg| match arg {
+                Value::Boolean(b) => *b,
+                _ => unreachable!("validation ensures this is a boolean"),
+            })
+            .unwrap_or(false);
         let fields: Box<[_]> = agg_type
             .fields
             .iter()
@@ -869,7 +992,9 @@ impl Aggregation {
             .fields
             .iter()
             .filter_map(|field| field.find_directive(kw::AGGREGATE).map(|dir| (field, dir)))
-            .map(|(field, dir)| Aggregate::new(schema, &field.name, &field.field_type, dir))
+            .map(|(field, dir)| {
+                Aggregate::new(schema, &field.name, &field.field_type, dir, cumulative)
+            })
             .collect();
 
         let obj_types = intervals
@@ -888,6 +1013,8 @@ impl Aggregation {
                     immutable: true,
                     aggregation: Some(name),
                     timeseries: false,
+                    spatial: None,
+                    ttl: None,
                     interfaces: Box::new([]),
                     shared_interfaces: Box::new([]),
                 }
@@ -1423,6 +1550,15 @@ impl InputSchema {
         self.inner.schema.document.get_fulltext_directives()
     }
 
+    /// Return the definitions of all `@fulltext` directives in the schema
+    pub fn all_fulltext_definitions(&self) -> Result<Vec<FulltextDefinition>, Error> {
+        Ok(self
+            .get_fulltext_directives()?
+            .into_iter()
+            .map(FulltextDefinition::from)
+            .collect())
+    }
+
     pub fn make_entity<I: IntoEntityIterator>(
         &self,
         iter: I,
@@ -1678,7 +1814,7 @@ mod validations {
             store::{IdType, ValueType, ID},
             subgraph::SPEC_VERSION_1_1_0,
         },
-        prelude::s,
+        prelude::{s, BLOCK_NUMBER_MAX},
         schema::{
             input::{kw, sqlexpr, AggregateFn, AggregationInterval},
             FulltextAlgorithm, FulltextLanguage, Schema as BaseSchema, SchemaValidationError,
@@ -1718,6 +1854,8 @@ mod validations {
         .collect();
 
         errors.append(&mut schema.validate_entity_directives());
+        errors.append(&mut schema.validate_spatial_directives());
+        errors.append(&mut schema.validate_ttl_directives());
         errors.append(&mut schema.validate_entity_type_ids());
         errors.append(&mut schema.validate_fields());
         errors.append(&mut schema.validate_fulltext_directives());
@@ -2039,6 +2177,75 @@ mod validations {
                 .collect()
         }
 
+        /// The `latitude` and `longitude` arguments of the `@spatial`
+        /// directive must name non-derived scalar fields of type
+        /// `BigDecimal`. Since `@spatial` adds the `withinBox` and
+        /// `withinRadius` filters, the type can not have fields with those
+        /// names
+        fn validate_spatial_directives(&self) -> Vec<SchemaValidationError> {
+            let mut errors = vec![];
+            for object_type in &self.entity_types {
+                let dir = match object_type.find_directive(kw::SPATIAL) {
+                    Some(dir) => dir,
+                    None => continue,
+                };
+                for arg in [kw::LATITUDE, kw::LONGITUDE] {
+                    let name = match dir.argument(arg) {
+                        Some(s::Value::String(name)) => name,
+                        _ => {
+                            errors.push(Err::SpatialArgMissing(
+                                object_type.name.clone(),
+                                arg.to_string(),
+                            ));
+                            continue;
+                        }
+                    };
+                    let valid = object_type.field(name).map_or(false, |field| {
+                        field.derived_from().is_none()
+                            && !field.field_type.is_list()
+                            && field.field_type.get_base_type() == "BigDecimal"
+                    });
+                    if !valid {
+                        errors.push(Err::SpatialInvalidField(
+                            object_type.name.clone(),
+                            name.clone(),
+                        ));
+                    }
+                }
+                for filter in [kw::WITHIN_BOX, kw::WITHIN_RADIUS] {
+                    if object_type.field(filter).is_some() {
+                        errors.push(Err::SpatialFieldCollision(
+                            object_type.name.clone(),
+                            filter.to_string(),
+                        ));
+                    }
+                }
+            }
+            errors
+        }
+
+        /// The `blocks` argument of the `@ttl` directive must be a positive
+        /// integer
+        fn validate_ttl_directives(&self) -> Vec<SchemaValidationError> {
+            let mut errors = vec![];
+            for object_type in &self.entity_types {
+                let dir = match object_type.find_directive(kw::TTL) {
+                    Some(dir) => dir,
+                    None => continue,
+                };
+                let valid = match dir.argument(kw::BLOCKS) {
+                    Some(s::Value::Int(blocks)) => blocks.as_i64().map_or(false, |blocks| {
+                        blocks > 0 && blocks <= BLOCK_NUMBER_MAX as i64
+                    }),
+                    _ => false,
+                };
+                if !valid {
+                    errors.push(Err::TtlInvalidBlocks(object_type.name.clone()));
+                }
+            }
+            errors
+        }
+
         /// 1. All object types besides `_Schema_` must have an id field
         /// 2. The id field must be recognized by IdType
         fn validate_entity_type_ids(&self) -> Vec<SchemaValidationError> {
@@ -2419,6 +2626,17 @@ mod validations {
                         return;
                     }
                 }
+                match agg_type
+                    .find_directive(kw::AGGREGATION)
+                    .and_then(|dir| dir.argument(kw::CUMULATIVE))
+                {
+                    Some(s::Value::Boolean(_)) | None => { /* ok */ }
+                    Some(_) => {
+                        errors.push(Err::AggregationInvalidCumulativeDefault(
+                            agg_type.name.to_owned(),
+                        ));
+                    }
+                }
                 match IdType::try_from(source) {
                     Ok(id_type) => valid_id_field(agg_type, id_type, errors),
                     Err(e) => errors.push(Err::IllegalIdType(e.to_string())),
@@ -2489,6 +2707,14 @@ mod validations {
                                 }
                             };
                             match agg.argument(kw::CUMULATIVE) {
+                                Some(s::Value::Boolean(true)) if !func.can_be_cumulative() => {
+                                    errors.push(Err::AggregationNonCumulativeFn(
+                                        agg_type.name.to_owned(),
+                                        field.name.to_owned(),
+                                        func.as_str().to_owned(),
+                                    ));
+                                    continue;
+                                }
                                 Some(s::Value::Boolean(_)) | None => { /* ok */ }
                                 Some(_) => {
                                     errors.push(Err::AggregationInvalidCumulative(
@@ -2498,6 +2724,26 @@ mod validations {
                                     continue;
                                 }
                             };
+                            let weight = match (agg.argument(kw::WEIGHT), func.has_weight()) {
+                                (Some(s::Value::String(weight)), true) => Some(weight),
+                                (None, false) => None,
+                                (Some(_), true) | (None, true) => {
+                                    errors.push(Err::AggregationInvalidWeight(
+                                        agg_type.name.to_owned(),
+                                        field.name.to_owned(),
+                                        func.as_str().to_owned(),
+                                    ));
+                                    continue;
+                                }
+                                (Some(_), false) => {
+                                    errors.push(Err::AggregationUnexpectedWeight(
+                                        agg_type.name.to_owned(),
+                                        field.name.to_owned(),
+                                        func.as_str().to_owned(),
+                                    ));
+                                    continue;
+                                }
+                            };
                             let field_type = match field.field_type.value_type() {
                                 Ok(field_type) => field_type,
                                 Err(_) => {
@@ -2517,6 +2763,7 @@ mod validations {
                             let check_ident = |ident: &str| -> Result<(), SchemaValidationError> {
                                 let arg_type = match source.field(ident) {
                                     Some(arg_field) => match arg_field.field_type.value_type() {
+                                        _ if !func.has_numeric_arg() => return Ok(()),
                                         Ok(arg_type) if arg_type.is_numeric() => arg_type,
                                         Ok(_) | Err(_) => {
                                             return Err(Err::AggregationNonNumericArg(
@@ -2549,6 +2796,33 @@ mod validations {
                             if let Err(mut errs) = sqlexpr::parse(arg, check_ident) {
                                 errors.append(&mut errs);
                             }
+                            if let Some(weight) = weight {
+                                // Weights only need to be numeric; they do
+                                // not end up in the aggregate field
+                                let check_weight =
+                                    |ident: &str| -> Result<(), SchemaValidationError> {
+                                        match source.field(ident).map(|f| f.field_type.value_type())
+                                        {
+                                            Some(Ok(weight_type)) if weight_type.is_numeric() => {
+                                                Ok(())
+                                            }
+                                            Some(_) => Err(Err::AggregationNonNumericArg(
+                                                agg_type.name.to_owned(),
+                                                field.name.to_owned(),
+                                                source.name.to_owned(),
+                                                weight.to_owned(),
+                                            )),
+                                            None => Err(Err::AggregationUnknownArg(
+                                                agg_type.name.to_owned(),
+                                                field.name.to_owned(),
+                                                weight.to_owned(),
+                                            )),
+                                        }
+                                    };
+                                if let Err(mut errs) = sqlexpr::parse(weight, check_weight) {
+                                    errors.append(&mut errs);
+                                }
+                            }
                         }
                         None => {
                             // Non-aggregate fields must have the
@@ -2980,6 +3254,63 @@ type Gravatar @entity {
             assert_eq!(schema.validate_fulltext_directives(), vec![]);
         }
 
+        #[test]
+        fn test_spatial_directive_validation() {
+            const SCHEMA: &str = r#"
+type Shop @entity @spatial(latitude: "lat", longitude: "lon") {
+  id: ID!
+  lat: BigDecimal!
+  lon: BigDecimal
+}
+type Venue @entity @spatial(latitude: "lat", longitude: "position") {
+  id: ID!
+  lat: BigDecimal!
+  position: [BigDecimal!]!
+  withinBox: String
+}
+type Stall @entity @spatial(latitude: "lat") {
+  id: ID!
+  lat: BigDecimal!
+}"#;
+
+            let document = graphql_parser::parse_schema(SCHEMA).expect("Failed to parse schema");
+            let schema = BaseSchema::new(DeploymentHash::new("id1").unwrap(), document).unwrap();
+            let schema = Schema::new(LATEST_VERSION, &schema);
+            assert_eq!(
+                schema.validate_spatial_directives(),
+                vec![
+                    Err::SpatialInvalidField("Venue".to_string(), "position".to_string()),
+                    Err::SpatialFieldCollision("Venue".to_string(), "withinBox".to_string()),
+                    Err::SpatialArgMissing("Stall".to_string(), "longitude".to_string()),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_ttl_directive_validation() {
+            const SCHEMA: &str = r#"
+type Pending @entity @ttl(blocks: 100) {
+  id: ID!
+}
+type Negative @entity @ttl(blocks: -1) {
+  id: ID!
+}
+type Missing @entity @ttl {
+  id: ID!
+}"#;
+
+            let document = graphql_parser::parse_schema(SCHEMA).expect("Failed to parse schema");
+            let schema = BaseSchema::new(DeploymentHash::new("id1").unwrap(), document).unwrap();
+            let schema = Schema::new(LATEST_VERSION, &schema);
+            assert_eq!(
+                schema.validate_ttl_directives(),
+                vec![
+                    Err::TtlInvalidBlocks("Negative".to_string()),
+                    Err::TtlInvalidBlocks("Missing".to_string()),
+                ]
+            );
+        }
+
         #[test]
         fn agg() {
             fn parse_annotation(file_name: &str, line: &str) -> (bool, Version, String) {
@@ -23,7 +23,10 @@ mod entity_type;
 mod fulltext;
 mod input;
 
-pub use api::{is_introspection_field, APISchemaError, INTROSPECTION_QUERY_TYPE};
+pub use api::{
+    aggregate_fields_type_name, aggregated_type, connected_type, edge_type_name,
+    is_introspection_field, APISchemaError, INTROSPECTION_QUERY_TYPE,
+};
 
 pub use api::{ApiSchema, ErrorPolicy};
 pub use entity_key::EntityKey;
@@ -33,7 +36,7 @@ pub use input::sqlexpr::{ExprVisitor, VisitExpr};
 pub(crate) use input::POI_OBJECT;
 pub use input::{
     kw, Aggregate, AggregateFn, Aggregation, AggregationInterval, AggregationMapping, Field,
-    InputSchema, InterfaceType, ObjectOrInterface, ObjectType, TypeKind,
+    InputSchema, InterfaceType, ObjectOrInterface, ObjectType, Spatial, TypeKind,
 };
 
 pub const SCHEMA_TYPE_NAME: &str = "_Schema_";
@@ -46,6 +49,8 @@ pub const INTROSPECTION_TYPE_FIELD_NAME: &str = "__type";
 
 pub const BLOCK_FIELD_TYPE: &str = "_Block_";
 
+pub const PAGE_INFO_TYPE: &str = "_PageInfo_";
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Strings(Vec<String>);
 
@@ -115,6 +120,14 @@ pub enum SchemaValidationError {
     FulltextIncludedFieldMissingRequiredProperty,
     #[error("Fulltext entity field, {0}, not found or not a string")]
     FulltextIncludedFieldInvalid(String),
+    #[error("Type {0}: the @spatial directive needs a string argument `{1}`")]
+    SpatialArgMissing(String, String),
+    #[error("Type {0}: the @spatial field `{1}` must be a non-derived field of type BigDecimal")]
+    SpatialInvalidField(String, String),
+    #[error("Type {0} is marked @spatial and can therefore not have a field `{1}`")]
+    SpatialFieldCollision(String, String),
+    #[error("Type {0}: the @ttl directive needs a positive integer argument `blocks`")]
+    TtlInvalidBlocks(String),
     #[error("Type {0} is missing an `id` field")]
     IdFieldMissing(String),
     #[error("{0}")]
@@ -177,6 +190,14 @@ pub enum SchemaValidationError {
     AggregationNonNumericArg(String, String, String, String),
     #[error("Field {1} in aggregation {0} has an invalid value for `cumulative`. It needs to be a boolean")]
     AggregationInvalidCumulative(String, String),
+    #[error("Field {1} in aggregation {0} can not be cumulative since the function {2} can not combine aggregates across intervals")]
+    AggregationNonCumulativeFn(String, String, String),
+    #[error("Aggregation {0} has an invalid value for `cumulative`. It needs to be a boolean")]
+    AggregationInvalidCumulativeDefault(String),
+    #[error("Field {1} in aggregation {0} uses the function {2} and needs a `weight` argument that is a string")]
+    AggregationInvalidWeight(String, String, String),
+    #[error("Field {1} in aggregation {0} has a `weight` argument but the function {2} does not take one")]
+    AggregationUnexpectedWeight(String, String, String),
     #[error("Aggregations are not supported with spec version {0}; please migrate the subgraph to the latest version")]
     AggregationsNotSupported(Version),
     #[error("Using Int8 as the type for the `id` field is not supported with spec version {0}; please migrate the subgraph to the latest version")]
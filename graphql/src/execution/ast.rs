@@ -191,6 +191,79 @@ impl SelectionSet {
         Ok(())
     }
 
+    /// Remove all fields that carry an active `@defer` directive from this
+    /// selection set and return them grouped by the label of the directive,
+    /// in the order in which the groups first appear in the query. Only
+    /// the fields directly in this selection set are considered; deferred
+    /// fields further down in the tree are resolved as part of their parent
+    /// which the incremental delivery spec explicitly allows
+    pub fn split_deferred(
+        &mut self,
+    ) -> Result<Vec<(Option<String>, SelectionSet)>, QueryExecutionError> {
+        let empty = SelectionSet::empty_from(self);
+        let mut groups: Vec<(Option<String>, SelectionSet)> = Vec::new();
+        for (obj_type, fields) in &mut self.items {
+            let (deferred, kept): (Vec<_>, Vec<_>) = std::mem::take(fields)
+                .into_iter()
+                .partition(|field| field.deferred().is_some());
+            *fields = kept;
+            for field in deferred {
+                let label = field.defer_label().map(str::to_string);
+                let pos = match groups.iter().position(|(l, _)| l == &label) {
+                    Some(pos) => pos,
+                    None => {
+                        groups.push((label, empty.clone()));
+                        groups.len() - 1
+                    }
+                };
+                let (_, group_fields) = groups[pos]
+                    .1
+                    .items
+                    .iter_mut()
+                    .find(|(ty, _)| *ty == *obj_type)
+                    .expect("groups are created with the same types as `self`");
+                Self::merge_field(group_fields, field)?;
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Limit the list of entities at `path`, which consists of response
+    /// keys, to the `initial_count` items that are sent with the initial
+    /// payload of a `@stream`, and return a selection set that only
+    /// selects the path down to the list and the remaining items in it.
+    /// Return `None` if there is no such list or if it does not have more
+    /// than `initial_count` items; lists of scalars are never limited
+    pub fn split_stream(&mut self, path: &[String], initial_count: usize) -> Option<SelectionSet> {
+        let (key, rest) = path.split_first()?;
+        let mut remaining = SelectionSet::empty_from(self);
+        for ((_, fields), (_, remaining_fields)) in
+            self.items.iter_mut().zip(remaining.items.iter_mut())
+        {
+            let Some(field) = fields.iter_mut().find(|field| field.response_key() == key) else {
+                continue;
+            };
+            let remaining_field = if rest.is_empty() {
+                field.split_items(initial_count)
+            } else {
+                field
+                    .selection_set
+                    .split_stream(rest, initial_count)
+                    .map(|selection_set| Field {
+                        position: field.position,
+                        alias: field.alias.clone(),
+                        name: field.name.clone(),
+                        arguments: field.arguments.clone(),
+                        directives: field.directives.clone(),
+                        selection_set,
+                        multiplicity: field.multiplicity,
+                    })
+            };
+            remaining_fields.extend(remaining_field);
+        }
+        (!remaining.is_empty()).then_some(remaining)
+    }
+
     /// Dump the selection set as a string for debugging
     #[cfg(debug_assertions)]
     pub fn dump(&self) -> String {
@@ -216,6 +289,11 @@ impl SelectionSet {
     }
 }
 
+/// Name of the directive that marks fragments for deferred delivery
+pub const DEFER_DIRECTIVE: &str = "defer";
+/// Name of the directive that marks list fields for streamed delivery
+pub const STREAM_DIRECTIVE: &str = "stream";
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Directive {
     pub position: q::Pos,
@@ -251,6 +329,20 @@ impl Directive {
             _ => false,
         }
     }
+
+    /// Return `true` if this is a `@defer` or `@stream` directive whose
+    /// `if` condition is not `false`
+    fn is_incremental(&self, name: &str) -> bool {
+        self.name == name && self.eval_if()
+    }
+
+    /// The `label` argument of an incremental delivery directive
+    fn label(&self) -> Option<&str> {
+        match self.argument_value("label") {
+            Some(r::Value::String(label)) => Some(label.as_str()),
+            _ => None,
+        }
+    }
 }
 
 /// A field to execute as part of a query. When the field is constructed by
@@ -293,6 +385,72 @@ impl Field {
         self.selection_set.is_empty()
     }
 
+    fn set_argument(&mut self, name: &str, value: r::Value) {
+        match self.arguments.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.arguments.push((name.to_string(), value)),
+        }
+    }
+
+    /// If this is a list of entities with more than `initial_count` items,
+    /// limit it to the first `initial_count` items and return a copy of the
+    /// field that selects the remaining items. Since `first` must be
+    /// positive, a list with an `initial_count` of 0 is limited to one item
+    /// which must be removed from the response
+    fn split_items(&mut self, initial_count: usize) -> Option<Field> {
+        if self.is_leaf() || self.multiplicity != ChildMultiplicity::Many {
+            return None;
+        }
+        let first = match self.argument_value("first") {
+            Some(r::Value::Int(n)) => *n,
+            _ => 100,
+        };
+        let skip = match self.argument_value("skip") {
+            Some(r::Value::Int(n)) => *n,
+            _ => 0,
+        };
+        let initial_count = initial_count as i64;
+        if first <= initial_count {
+            return None;
+        }
+
+        let mut remaining = self.clone();
+        remaining.set_argument("first", r::Value::Int(first - initial_count));
+        remaining.set_argument("skip", r::Value::Int(skip + initial_count));
+        self.set_argument("first", r::Value::Int(initial_count.max(1)));
+        Some(remaining)
+    }
+
+    /// Return the active `@defer` directive for this field, if any. Fields
+    /// inherit the directives of the fragment through which they were
+    /// selected, so a field is deferred if the fragment it came from was
+    pub fn deferred(&self) -> Option<&Directive> {
+        self.directives
+            .iter()
+            .find(|dir| dir.is_incremental(DEFER_DIRECTIVE))
+    }
+
+    /// The label of the `@defer` directive attached to this field
+    pub fn defer_label(&self) -> Option<&str> {
+        self.deferred().and_then(|dir| dir.label())
+    }
+
+    /// If this field has an active `@stream` directive, return the number
+    /// of items that should be sent in the initial response and the label
+    /// of the directive
+    pub fn stream(&self) -> Option<(usize, Option<&str>)> {
+        self.directives
+            .iter()
+            .find(|dir| dir.is_incremental(STREAM_DIRECTIVE))
+            .map(|dir| {
+                let initial_count = match dir.argument_value("initialCount") {
+                    Some(r::Value::Int(n)) if *n > 0 => *n as usize,
+                    _ => 0,
+                };
+                (initial_count, dir.label())
+            })
+    }
+
     /// Return the set of attributes that should be selected for this field.
     /// If `ENV_VARS.enable_select_by_specific_attributes` is `false`,
     /// return `AttributeNames::All
@@ -14,13 +14,13 @@ use std::{collections::hash_map::DefaultHasher, convert::TryFrom};
 
 use graph::data::graphql::{ext::TypeExt, ObjectOrInterface};
 use graph::data::query::{Query as GraphDataQuery, QueryVariables};
-use graph::data::query::{QueryExecutionError, Trace};
+use graph::data::query::{QueryCost, QueryCostModel, QueryExecutionError, StreamedField, Trace};
 use graph::prelude::{
     info, o, q, r, s, warn, BlockNumber, CheapClone, DeploymentHash, EntityRange, GraphQLMetrics,
     Logger, TryFromValue, ENV_VARS,
 };
 use graph::schema::ast::{self as sast};
-use graph::schema::ErrorPolicy;
+use graph::schema::{is_introspection_field, ErrorPolicy};
 
 use crate::execution::ast as a;
 use crate::execution::get_field;
@@ -29,36 +29,42 @@ use crate::values::coercion;
 
 lazy_static! {
     static ref GRAPHQL_VALIDATION_PLAN: ValidationPlan =
-        ValidationPlan::from(if !ENV_VARS.graphql.enable_validations {
-            vec![]
-        } else {
-            vec![
-                Box::new(UniqueOperationNames::new()),
-                Box::new(LoneAnonymousOperation::new()),
-                Box::new(SingleFieldSubscriptions::new()),
-                Box::new(KnownTypeNames::new()),
-                Box::new(FragmentsOnCompositeTypes::new()),
-                Box::new(VariablesAreInputTypes::new()),
-                Box::new(LeafFieldSelections::new()),
-                Box::new(FieldsOnCorrectType::new()),
-                Box::new(UniqueFragmentNames::new()),
-                Box::new(KnownFragmentNames::new()),
-                Box::new(NoUnusedFragments::new()),
-                Box::new(OverlappingFieldsCanBeMerged::new()),
-                Box::new(NoFragmentsCycle::new()),
-                Box::new(PossibleFragmentSpreads::new()),
-                Box::new(NoUnusedVariables::new()),
-                Box::new(NoUndefinedVariables::new()),
-                Box::new(KnownArgumentNames::new()),
-                Box::new(UniqueArgumentNames::new()),
-                Box::new(UniqueVariableNames::new()),
-                Box::new(ProvidedRequiredArguments::new()),
-                Box::new(KnownDirectives::new()),
-                Box::new(VariablesInAllowedPosition::new()),
-                Box::new(ValuesOfCorrectType::new()),
-                Box::new(UniqueDirectivesPerLocation::new()),
-            ]
-        });
+        validation_plan(ENV_VARS.graphql.enable_validations);
+}
+
+/// The rules that queries are checked against; if validations are not
+/// enabled, queries are not checked at all
+fn validation_plan(enable_validations: bool) -> ValidationPlan {
+    ValidationPlan::from(if !enable_validations {
+        vec![]
+    } else {
+        vec![
+            Box::new(UniqueOperationNames::new()),
+            Box::new(LoneAnonymousOperation::new()),
+            Box::new(SingleFieldSubscriptions::new()),
+            Box::new(KnownTypeNames::new()),
+            Box::new(FragmentsOnCompositeTypes::new()),
+            Box::new(VariablesAreInputTypes::new()),
+            Box::new(LeafFieldSelections::new()),
+            Box::new(FieldsOnCorrectType::new()),
+            Box::new(UniqueFragmentNames::new()),
+            Box::new(KnownFragmentNames::new()),
+            Box::new(NoUnusedFragments::new()),
+            Box::new(OverlappingFieldsCanBeMerged::new()),
+            Box::new(NoFragmentsCycle::new()),
+            Box::new(PossibleFragmentSpreads::new()),
+            Box::new(NoUnusedVariables::new()),
+            Box::new(NoUndefinedVariables::new()),
+            Box::new(KnownArgumentNames::new()),
+            Box::new(UniqueArgumentNames::new()),
+            Box::new(UniqueVariableNames::new()),
+            Box::new(ProvidedRequiredArguments::new()),
+            Box::new(KnownDirectives::new()),
+            Box::new(VariablesInAllowedPosition::new()),
+            Box::new(ValuesOfCorrectType::new()),
+            Box::new(UniqueDirectivesPerLocation::new()),
+        ]
+    })
 }
 
 #[derive(Clone, Debug)]
@@ -73,6 +79,7 @@ pub enum ComplexityError {
 enum Kind {
     Query,
     Subscription,
+    Mutation,
 }
 
 /// Helper to log the fields in a `SelectionSet` without cloning. Writes
@@ -235,6 +242,13 @@ impl Query {
             q::OperationDefinition::Subscription(q::Subscription { selection_set, .. }) => {
                 (Kind::Subscription, selection_set)
             }
+            // Only schemas with a `Mutation` type, like the one for the
+            // index node server, support mutations
+            q::OperationDefinition::Mutation(q::Mutation { selection_set, .. })
+                if schema.mutation_type.is_some() =>
+            {
+                (Kind::Mutation, selection_set)
+            }
             q::OperationDefinition::Mutation(_) => {
                 return Err(vec![QueryExecutionError::NotSupported(
                     "Mutations are not supported".to_owned(),
@@ -246,6 +260,7 @@ impl Query {
         let root_type = match kind {
             Kind::Query => schema.query_type.as_ref(),
             Kind::Subscription => schema.subscription_type.as_ref().unwrap(),
+            Kind::Mutation => schema.mutation_type.as_ref().unwrap(),
         };
         // Use an intermediate struct so we can modify the query before
         // enclosing it in an Arc
@@ -281,6 +296,11 @@ impl Query {
         Ok(Arc::new(query))
     }
 
+    /// The time at which execution of the query started
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
     pub fn root_trace(&self, do_trace: bool) -> Trace {
         Trace::root(
             &self.query_text,
@@ -345,12 +365,93 @@ impl Query {
         Ok(bcs)
     }
 
+    /// Return all list fields that carry a `@stream` directive, including
+    /// ones that are nested inside other fields
+    pub fn streamed_fields(&self) -> Vec<StreamedField> {
+        fn collect<'a>(
+            fields: impl Iterator<Item = &'a a::Field>,
+            path: &mut Vec<String>,
+            streams: &mut Vec<StreamedField>,
+        ) {
+            for field in fields {
+                path.push(field.response_key().to_string());
+                match field.stream() {
+                    Some((initial_count, label))
+                        if field.multiplicity == ChildMultiplicity::Many =>
+                    {
+                        let stream = StreamedField {
+                            path: path.clone(),
+                            initial_count,
+                            label: label.map(str::to_string),
+                        };
+                        if !streams.contains(&stream) {
+                            streams.push(stream);
+                        }
+                    }
+                    _ => {
+                        for (_, fields) in field.selection_set.fields() {
+                            collect(fields, path, streams);
+                        }
+                    }
+                }
+                path.pop();
+            }
+        }
+
+        let root_type = sast::ObjectType::from(self.schema.query_type.cheap_clone());
+        let mut streams = Vec::new();
+        if let Ok(fields) = self.selection_set.fields_for(&root_type) {
+            collect(fields, &mut Vec::new(), &mut streams);
+        }
+        streams
+    }
+
+    /// Compute the cost of the query according to `model` and check it
+    /// against the budget for the deployment the query is for. Queries
+    /// that exceed the budget fail with `CostExceeded`
+    pub fn check_cost(&self, model: &QueryCostModel) -> Result<QueryCost, QueryExecutionError> {
+        let cost = selection_set_cost(model, &self.selection_set);
+        let budget = model.budget(self.schema.id());
+        if let Some(budget) = budget {
+            if cost > budget {
+                return Err(QueryExecutionError::CostExceeded(cost, budget));
+            }
+        }
+        Ok(QueryCost { cost, budget })
+    }
+
+    /// Check that the query can be run against its deployment. Deployments
+    /// listed in `introspection_only`, which normally comes from
+    /// `GRAPH_INTROSPECTION_ONLY_SUBGRAPH_IDS`, only accept queries that
+    /// select nothing but introspection fields
+    pub fn check_introspection_only(
+        &self,
+        introspection_only: &[String],
+    ) -> Result<(), QueryExecutionError> {
+        let id = self.schema.id();
+        if !introspection_only.iter().any(|only| only == id.as_str()) {
+            return Ok(());
+        }
+
+        let introspection_only = self.is_query()
+            && self
+                .selection_set
+                .fields()
+                .flat_map(|(_, fields)| fields)
+                .all(|field| is_introspection_field(&field.name) || field.name == "__typename");
+        if introspection_only {
+            Ok(())
+        } else {
+            Err(QueryExecutionError::IntrospectionOnly(id.to_string()))
+        }
+    }
+
     /// Return `true` if this is a query, and not a subscription or
     /// mutation
     pub fn is_query(&self) -> bool {
         match self.kind {
             Kind::Query => true,
-            Kind::Subscription => false,
+            Kind::Subscription | Kind::Mutation => false,
         }
     }
 
@@ -358,7 +459,24 @@ impl Query {
     pub fn is_subscription(&self) -> bool {
         match self.kind {
             Kind::Subscription => true,
-            Kind::Query => false,
+            Kind::Query | Kind::Mutation => false,
+        }
+    }
+
+    /// Return `true` if this is a mutation, not a query or a subscription
+    pub fn is_mutation(&self) -> bool {
+        match self.kind {
+            Kind::Mutation => true,
+            Kind::Query | Kind::Subscription => false,
+        }
+    }
+
+    /// The type of the root of the operation, e.g., `Query` for queries
+    pub fn root_type(&self) -> sast::ObjectType {
+        match self.kind {
+            Kind::Query => self.schema.query_type.cheap_clone().into(),
+            Kind::Subscription => self.schema.subscription_type.clone().unwrap().into(),
+            Kind::Mutation => self.schema.mutation_type.clone().unwrap().into(),
         }
     }
 
@@ -398,6 +516,38 @@ impl Query {
     }
 }
 
+/// The cost of `selection_set` according to `model`. Each field costs its
+/// weight plus the cost of its selection set, and that is multiplied by
+/// `first` for list fields. For interfaces and unions, the cost is that of
+/// the most expensive object type. Introspection fields are free. The
+/// arithmetic saturates so that absurd queries simply exceed any budget
+fn selection_set_cost(model: &QueryCostModel, selection_set: &a::SelectionSet) -> u64 {
+    selection_set
+        .fields()
+        .map(|(obj_type, fields)| {
+            fields
+                .filter(|field| !field.name.starts_with("__"))
+                .fold(0u64, |total, field| {
+                    let leaf = field.selection_set.is_empty();
+                    let cost = model
+                        .weight(&obj_type.name, &field.name, leaf)
+                        .saturating_add(selection_set_cost(model, &field.selection_set));
+                    let cost = if !leaf && field.multiplicity == ChildMultiplicity::Many {
+                        let first = match field.argument_value("first") {
+                            Some(r::Value::Int(first)) => (*first).max(0) as u64,
+                            _ => EntityRange::FIRST as u64,
+                        };
+                        cost.saturating_mul(first)
+                    } else {
+                        cost
+                    };
+                    total.saturating_add(cost)
+                })
+        })
+        .max()
+        .unwrap_or(0)
+}
+
 /// Coerces variable values for an operation.
 pub fn coerce_variables(
     schema: &ApiSchema,
@@ -619,7 +769,7 @@ impl<'s> RawQuery<'s> {
     /// If the query is invalid, returns `Ok(0)` so that execution proceeds and
     /// gives a proper error.
     fn complexity(&self, max_depth: u8) -> Result<u64, QueryExecutionError> {
-        let root_type = self.schema.get_root_query_type_def().unwrap();
+        let root_type = self.schema.get_named_type(&self.root_type.name).unwrap();
 
         match self.complexity_inner(
             root_type,
@@ -641,9 +791,10 @@ impl<'s> RawQuery<'s> {
     }
 
     fn validate_fields(&self) -> Result<(), Vec<QueryExecutionError>> {
-        let root_type = self.schema.query_type.as_ref();
+        let root_type = self.root_type;
 
-        let errors = self.validate_fields_inner("Query", root_type.into(), &self.selection_set);
+        let errors =
+            self.validate_fields_inner(&root_type.name, root_type.into(), &self.selection_set);
         if errors.is_empty() {
             Ok(())
         } else {
@@ -994,13 +1145,18 @@ impl Transform {
                     }
                 }
                 q::Selection::FragmentSpread(spread) => {
-                    // TODO: we ignore the directives here (and so did the
-                    // old implementation), but that seems wrong
                     let q::FragmentSpread {
                         position: _,
                         fragment_name,
-                        directives: _,
+                        directives: spread_directives,
                     } = spread;
+                    // A spread that is skipped does not count as a visit
+                    // so that another spread of the same fragment that is
+                    // not skipped still gets expanded
+                    let (_, skip) = self.interpolate_directives(spread_directives.clone())?;
+                    if skip {
+                        continue;
+                    }
                     let frag = self.fragments.get(&fragment_name).unwrap();
                     if visited_fragments.insert(fragment_name) {
                         let q::FragmentDefinition {
@@ -1010,8 +1166,14 @@ impl Transform {
                             directives,
                             selection_set,
                         } = frag;
+                        // Directives on the spread apply to the fields of
+                        // the fragment just like the ones on its definition
+                        let directives = spread_directives
+                            .into_iter()
+                            .chain(directives.iter().cloned())
+                            .collect();
                         self.expand_fragment(
-                            directives.clone(),
+                            directives,
                             Some(type_condition),
                             type_set,
                             selection_set.clone(),
@@ -1068,3 +1230,42 @@ impl Transform {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::{q, DeploymentHash};
+    use graph::schema::InputSchema;
+    use graphql_tools::validation::validate::validate;
+
+    use super::validation_plan;
+
+    // Check that queries using `@defer` and `@stream` pass validation when
+    // `ENABLE_GRAPHQL_VALIDATIONS` is on
+    #[test]
+    fn incremental_delivery_directives_are_valid() {
+        const SCHEMA: &str = "type Musician @entity { id: ID!, name: String! }";
+        const QUERY: &str = "
+        query {
+            musicians(first: 10) @stream(initialCount: 1, label: \"musicians\") { id }
+            ... @defer(label: \"names\") {
+                more: musicians { name }
+            }
+        }";
+
+        let id = DeploymentHash::new("validations").unwrap();
+        let schema = InputSchema::parse_latest(SCHEMA, id)
+            .unwrap()
+            .api_schema()
+            .unwrap();
+        let query = q::parse_query(QUERY).unwrap().into_static();
+        let errors = validate(schema.document(), &query, &validation_plan(true));
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // Directives that are not declared are still rejected
+        let query = q::parse_query("query { musicians @unknown { id } }")
+            .unwrap()
+            .into_static();
+        let errors = validate(schema.document(), &query, &validation_plan(true));
+        assert!(!errors.is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use graph::components::store::{QueryPermit, UnitStream};
+use graph::components::store::{ChangeStream, QueryPermit};
 use graph::data::query::{CacheStatus, Trace};
 use graph::prelude::{async_trait, s, Error, QueryExecutionError};
 use graph::schema::ApiSchema;
@@ -117,7 +117,7 @@ pub trait Resolver: Sized + Send + Sync + 'static {
         _schema: &ApiSchema,
         _object_type: &s::ObjectType,
         _field: &a::Field,
-    ) -> Result<UnitStream, QueryExecutionError> {
+    ) -> Result<ChangeStream, QueryExecutionError> {
         Err(QueryExecutionError::NotSupported(String::from(
             "Resolving field streams is not supported by this resolver",
         )))
@@ -336,7 +336,10 @@ impl IntrospectionResolver {
                 self.type_objects
                     .get(&String::from("Subscription"))
                     .cloned(),
-            mutationType: r::Value::Null,
+            mutationType:
+                self.type_objects
+                    .get(&String::from("Mutation"))
+                    .cloned(),
             types: self.type_objects.values().cloned().collect::<Vec<_>>(),
             directives: self.directives.clone(),
         }
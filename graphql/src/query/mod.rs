@@ -54,10 +54,13 @@ where
         trace: options.trace,
     });
 
-    if !query.is_query() {
+    if query.is_subscription() {
         return (
             Arc::new(
-                QueryExecutionError::NotSupported("Only queries are supported".to_string()).into(),
+                QueryExecutionError::NotSupported(
+                    "Only queries and mutations are supported".to_string(),
+                )
+                .into(),
             ),
             CacheStatus::default(),
         );
@@ -66,13 +69,14 @@ where
         .map(Arc::new)
         .unwrap_or_else(|| query.selection_set.cheap_clone());
 
-    // Execute top-level `query { ... }` and `{ ... }` expressions.
-    let query_type = ctx.query.schema.query_type.cheap_clone().into();
+    // Execute top-level `query { ... }`, `mutation { ... }` and `{ ... }`
+    // expressions.
+    let root_type = ctx.query.root_type();
     let start = Instant::now();
     let result = execute_root_selection_set(
         ctx.cheap_clone(),
         selection_set.cheap_clone(),
-        query_type,
+        root_type,
         block_ptr.clone(),
     )
     .await;
@@ -5,20 +5,21 @@ use crate::metrics::GraphQLMetrics;
 use crate::prelude::{QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions};
 use crate::query::execute_query;
 use crate::subscription::execute_prepared_subscription;
-use graph::futures03::future;
+use graph::futures03::future::{self, BoxFuture, FutureExt};
 use graph::prelude::MetricsRegistry;
 use graph::{
     components::store::SubscriptionManager,
     prelude::{
-        async_trait, o, CheapClone, DeploymentState, GraphQLMetrics as GraphQLMetricsTrait,
-        GraphQlRunner as GraphQlRunnerTrait, Logger, Query, QueryExecutionError, Subscription,
-        SubscriptionError, SubscriptionResult, ENV_VARS,
+        async_trait, o, CheapClone, DeploymentState, FutureExtension,
+        GraphQLMetrics as GraphQLMetricsTrait, GraphQlRunner as GraphQlRunnerTrait, Logger, Query,
+        QueryExecutionError, QueryResult, Subscription, SubscriptionError, SubscriptionResult,
+        ENV_VARS,
     },
 };
 use graph::{data::graphql::load_manager::LoadManager, prelude::QueryStoreManager};
 use graph::{
-    data::query::{QueryResults, QueryTarget},
-    prelude::QueryStore,
+    data::query::{CacheStatus, ConsistencyToken, QueryCostModel, QueryResults, QueryTarget},
+    prelude::{BlockPtr, QueryStore},
 };
 
 /// GraphQL runner implementation for The Graph.
@@ -28,6 +29,9 @@ pub struct GraphQlRunner<S, SM> {
     subscription_manager: Arc<SM>,
     load_manager: Arc<LoadManager>,
     graphql_metrics: Arc<GraphQLMetrics>,
+    /// If set, queries are checked against the budgets of this model before
+    /// they are executed
+    cost_model: Option<Arc<QueryCostModel>>,
 }
 
 #[cfg(debug_assertions)]
@@ -48,6 +52,7 @@ where
         subscription_manager: Arc<SM>,
         load_manager: Arc<LoadManager>,
         registry: Arc<MetricsRegistry>,
+        cost_model: Option<Arc<QueryCostModel>>,
     ) -> Self {
         let logger = logger.new(o!("component" => "GraphQlRunner"));
         let graphql_metrics = Arc::new(GraphQLMetrics::new(registry));
@@ -57,6 +62,7 @@ where
             subscription_manager,
             load_manager,
             graphql_metrics,
+            cost_model,
         }
     }
 
@@ -112,8 +118,20 @@ where
         // point, and everything needs to go through the `store` we are
         // setting up here
 
-        let store = self.store.query_store(target.clone(), false).await?;
-        let state = store.deployment_state().await?;
+        let mut store = self.store.query_store(target.clone(), false).await?;
+        let mut state = store.deployment_state().await?;
+
+        // If the client has seen a later block for this deployment than
+        // the replica we picked has, use the main database which is never
+        // behind any replica
+        let consistency_token = query.consistency_token.clone();
+        let min_block = consistency_token
+            .as_ref()
+            .and_then(|token| token.min_block(&state.id));
+        if min_block.map_or(false, |min| state.latest_block.number < min) {
+            store = self.store.query_store(target.clone(), true).await?;
+            state = store.deployment_state().await?;
+        }
         let network = Some(store.network_name().to_string());
         let schema = store.api_schema()?;
 
@@ -127,6 +145,7 @@ where
 
         let max_depth = max_depth.unwrap_or(ENV_VARS.graphql.max_depth);
         let do_trace = query.trace;
+        let incremental = query.incremental;
         let query = crate::execution::Query::new(
             &self.logger,
             schema,
@@ -136,6 +155,11 @@ where
             max_depth,
             metrics.cheap_clone(),
         )?;
+        query.check_introspection_only(&ENV_VARS.graphql.introspection_only_subgraph_ids)?;
+        let cost = match &self.cost_model {
+            Some(model) => Some(query.check_cost(model)?),
+            None => None,
+        };
         self.load_manager
             .decide(
                 &store.wait_stats().map_err(QueryExecutionError::from)?,
@@ -146,14 +170,36 @@ where
             )
             .to_result()?;
         let by_block_constraint =
-            StoreResolver::locate_blocks(store.as_ref(), &state, &query).await?;
+            StoreResolver::locate_blocks(store.as_ref(), &state, &query, min_block).await?;
         let mut max_block = 0;
+        let mut max_ptr: Option<BlockPtr> = None;
         let mut result: QueryResults = QueryResults::empty(query.root_trace(do_trace));
         let mut query_res_futures: Vec<_> = vec![];
+        let mut deferred_futures: Vec<_> = vec![];
+        let mut stream_futures: Vec<_> = vec![];
+        // Streamed lists that are not split into separate queries for the
+        // initial and the remaining items are cut off after the result has
+        // been computed
+        let mut streams = query.streamed_fields();
         let setup_elapsed = execute_start.elapsed();
 
         // Note: This will always iterate at least once.
-        for (ptr, (selection_set, error_policy)) in by_block_constraint {
+        for (ptr, (mut selection_set, error_policy)) in by_block_constraint {
+            let deferred = selection_set.split_deferred().map_err(QueryResults::from)?;
+            // Only resolve the initial items of streamed lists now, and
+            // the remaining items separately
+            let mut split_streams = vec![];
+            if incremental {
+                streams.retain(|stream| {
+                    match selection_set.split_stream(&stream.path, stream.initial_count) {
+                        Some(remaining) => {
+                            split_streams.push((stream.clone(), remaining));
+                            false
+                        }
+                        None => true,
+                    }
+                });
+            }
             let resolver = StoreResolver::at_block(
                 &self.logger,
                 store.cheap_clone(),
@@ -167,20 +213,72 @@ where
             )
             .await?;
             max_block = max_block.max(resolver.block_number());
+            if let Some(ptr) = &resolver.block_ptr {
+                if max_ptr.as_ref().map_or(true, |max| max.number < ptr.number) {
+                    max_ptr = Some(ptr.clone());
+                }
+            }
+            let options = |resolver| QueryExecutionOptions {
+                resolver,
+                deadline: ENV_VARS.graphql.query_timeout.map(|t| Instant::now() + t),
+                max_first: max_first.unwrap_or(ENV_VARS.graphql.max_first),
+                max_skip: max_skip.unwrap_or(ENV_VARS.graphql.max_skip),
+                trace: do_trace,
+            };
+            for (label, selection_set) in deferred {
+                deferred_futures.push((
+                    label,
+                    execute_query(
+                        query.clone(),
+                        Some(selection_set),
+                        resolver.block_ptr.clone(),
+                        options(resolver.cheap_clone()),
+                    ),
+                ));
+            }
+            for (stream, selection_set) in split_streams {
+                stream_futures.push((
+                    stream,
+                    execute_query(
+                        query.clone(),
+                        Some(selection_set),
+                        resolver.block_ptr.clone(),
+                        options(resolver.cheap_clone()),
+                    ),
+                ));
+            }
             query_res_futures.push(execute_query(
                 query.clone(),
                 Some(selection_set),
                 resolver.block_ptr.clone(),
-                QueryExecutionOptions {
-                    resolver,
-                    deadline: ENV_VARS.graphql.query_timeout.map(|t| Instant::now() + t),
-                    max_first: max_first.unwrap_or(ENV_VARS.graphql.max_first),
-                    max_skip: max_skip.unwrap_or(ENV_VARS.graphql.max_skip),
-                    trace: do_trace,
-                },
+                options(resolver),
             ));
         }
 
+        // Deferred selections and the remaining items of streamed lists
+        // run concurrently with the rest of the query. The tasks are
+        // canceled when the result is dropped, for example, because the
+        // client went away before all of the response was sent
+        let spawn = |future: BoxFuture<'static, (Arc<QueryResult>, CacheStatus)>| {
+            let canceled = || (Arc::new(QueryResult::default()), CacheStatus::default());
+            graph::spawn(future.cancelable(result.tasks(), canceled))
+                .map(|res| {
+                    res.unwrap_or_else(|e| {
+                        let e = QueryExecutionError::Panic(e.to_string());
+                        (Arc::new(e.into()), CacheStatus::default())
+                    })
+                })
+                .boxed()
+        };
+        let deferred: Vec<_> = deferred_futures
+            .into_iter()
+            .map(|(label, deferred_future)| (label, spawn(deferred_future.boxed())))
+            .collect();
+        let streamed: Vec<_> = stream_futures
+            .into_iter()
+            .map(|(stream, stream_future)| (stream, spawn(stream_future.boxed())))
+            .collect();
+
         let results: Vec<_> = if ENV_VARS.graphql.parallel_block_constraints {
             future::join_all(query_res_futures).await
         } else {
@@ -195,6 +293,30 @@ where
             result.append(query_res, cache_status);
         }
 
+        // Clients that accept incremental delivery get deferred results
+        // as soon as each of them is ready; everybody else gets them as
+        // part of the one response
+        for (label, deferred_result) in deferred {
+            result.append_pending(label, deferred_result);
+        }
+        if !incremental {
+            result.resolve_pending().await;
+        }
+        for (stream, stream_result) in streamed {
+            result.append_pending_stream(stream, stream_result);
+        }
+        for stream in streams {
+            result.add_stream(stream);
+        }
+
+        if let Some(cost) = cost {
+            result.add_extension("cost", cost.into());
+        }
+
+        if let Some(ptr) = max_ptr {
+            result.set_consistency_token(ConsistencyToken::new(state.id.clone(), ptr));
+        }
+
         query.log_execution(max_block);
         result.trace.finish(setup_elapsed, execute_start.elapsed());
         self.deployment_changed(store.as_ref(), state, max_block as u64)
@@ -262,6 +384,10 @@ where
             ENV_VARS.graphql.max_depth,
             self.graphql_metrics.cheap_clone(),
         )?;
+        query.check_introspection_only(&ENV_VARS.graphql.introspection_only_subgraph_ids)?;
+        if let Some(model) = &self.cost_model {
+            query.check_cost(model)?;
+        }
 
         if let Err(err) = self
             .load_manager
@@ -2,7 +2,7 @@
 //! final result
 
 use graph::data::graphql::ObjectTypeExt;
-use graph::data::query::Trace;
+use graph::data::query::{FieldTrace, Trace};
 use graph::data::store::Id;
 use graph::data::store::IdList;
 use graph::data::store::IdType;
@@ -20,14 +20,18 @@ use std::time::Instant;
 
 use graph::data::graphql::TypeExt;
 use graph::prelude::{
-    AttributeNames, ChildMultiplicity, EntityCollection, EntityFilter, EntityLink, EntityOrder,
-    EntityWindow, ParentLink, QueryExecutionError, Value as StoreValue, WindowAttribute, ENV_VARS,
+    AttributeNames, ChildMultiplicity, EntityAggregate, EntityCollection, EntityFilter, EntityLink,
+    EntityOrder, EntityRange, EntityWindow, ParentLink, QueryExecutionError, Value as StoreValue,
+    WindowAttribute, ENV_VARS,
+};
+use graph::schema::{
+    aggregate_fields_type_name, aggregated_type, connected_type, edge_type_name, EntityType,
+    InputSchema, ObjectOrInterface, PAGE_INFO_TYPE,
 };
-use graph::schema::{EntityType, InputSchema, ObjectOrInterface};
 
 use crate::execution::ast as a;
 use crate::metrics::GraphQLMetrics;
-use crate::store::query::build_query;
+use crate::store::query::{build_query, encode_cursor};
 use crate::store::StoreResolver;
 
 pub const ARG_ID: &str = "id";
@@ -103,6 +107,17 @@ impl From<QueryObject> for Node {
     }
 }
 
+impl From<Object> for Node {
+    fn from(entity: Object) -> Self {
+        Node {
+            children_weight: entity.weight(),
+            parent: None,
+            entity,
+            children: BTreeMap::default(),
+        }
+    }
+}
+
 impl CacheWeight for Node {
     fn indirect_weight(&self) -> usize {
         self.children_weight + cache_weight::btree::node_size(&self.children)
@@ -622,9 +637,45 @@ impl<'a> Loader<'a> {
                 let field_type = object_type
                     .field(&field.name)
                     .expect("field names are valid");
-                let child_type = input_schema
-                    .object_or_interface(field_type.field_type.get_base_type(), child_interval)
-                    .expect("we only collect fields that are objects or interfaces");
+                let base_type = field_type.field_type.get_base_type();
+                let child_type = match input_schema.object_or_interface(base_type, child_interval) {
+                    Some(child_type) => child_type,
+                    None => {
+                        // Connections like `usersConnection` and aggregates
+                        // like `usersAggregate` only exist on the root
+                        // `Query` type
+                        let res = if let Some(name) = aggregated_type(base_type) {
+                            let entity = input_schema
+                                .object_or_interface(name, None)
+                                .filter(|_| at_root)
+                                .expect("aggregates are only generated for object types");
+                            self.execute_aggregate(entity, base_type, field)
+                        } else {
+                            let entity = connected_type(base_type)
+                                .filter(|_| at_root)
+                                .and_then(|name| input_schema.object_or_interface(name, None))
+                                .expect(
+                                    "we only collect fields that are entities, connections \
+                                     or aggregates",
+                                );
+                            self.execute_connection(entity, base_type, field)
+                        };
+                        match res {
+                            Ok((node, trace)) => {
+                                add_children(
+                                    &input_schema,
+                                    &mut parents,
+                                    vec![node],
+                                    field.response_key(),
+                                )?;
+                                self.check_result_size(&parents)?;
+                                parent_trace.push(field.response_key(), trace);
+                            }
+                            Err(mut e) => errors.append(&mut e),
+                        }
+                        continue;
+                    }
+                };
 
                 let join = if at_root {
                     MaybeJoin::Root { child_type }
@@ -651,8 +702,18 @@ impl<'a> Loader<'a> {
                     ))
                 };
 
+                let fetch_start = Instant::now();
                 match self.fetch(&parents, &join, field) {
-                    Ok((children, trace)) => {
+                    Ok((children, mut trace)) => {
+                        if self.ctx.trace {
+                            trace.field(FieldTrace {
+                                parent_type: object_type.name.clone(),
+                                field_name: field.name.clone(),
+                                return_type: field_type.field_type.to_string(),
+                                start_offset: fetch_start.duration_since(self.ctx.query.start()),
+                                duration: fetch_start.elapsed(),
+                            })?;
+                        }
                         match self.execute_selection_set(
                             children,
                             trace,
@@ -686,6 +747,271 @@ impl<'a> Loader<'a> {
         }
     }
 
+    /// Execute a connection field like `usersConnection` whose edges are
+    /// entities of type `entity`. The result is a node for the connection
+    /// with children for its `edges` and `pageInfo` fields. The edges have
+    /// the entity as a child under the response key of each `node` field
+    fn execute_connection(
+        &self,
+        entity: ObjectOrInterface<'_>,
+        connection_type: &str,
+        field: &a::Field,
+    ) -> Result<(Node, Trace), Vec<QueryExecutionError>> {
+        fn typename(name: &str) -> (Word, r::Value) {
+            (Word::from("__typename"), r::Value::String(name.to_string()))
+        }
+
+        fn cursor_value(cursor: Option<&String>) -> r::Value {
+            cursor
+                .map(|cursor| r::Value::String(cursor.clone()))
+                .unwrap_or(r::Value::Null)
+        }
+
+        let input_schema = self.resolver.store.input_schema()?;
+
+        // Collect the response keys of the `edges` and `pageInfo` fields,
+        // and merge the selection sets of all `node` fields so that we can
+        // fetch the entities with one query
+        let object_types = a::resolve_object_types(&self.ctx.query.schema, entity.typename())?;
+        let mut node_set = a::SelectionSet::new(object_types.into_iter().collect());
+        let mut edges_keys = Vec::new();
+        let mut page_info_keys = Vec::new();
+        for (_, fields) in field.selection_set.fields() {
+            for conn_field in fields {
+                match conn_field.name.as_str() {
+                    "edges" => {
+                        let mut node_keys = Vec::new();
+                        for (_, fields) in conn_field.selection_set.fields() {
+                            for edge_field in fields.filter(|field| field.name == "node") {
+                                node_set.merge(edge_field.selection_set.clone(), vec![])?;
+                                node_keys.push(edge_field.response_key());
+                            }
+                        }
+                        edges_keys.push((conn_field.response_key(), node_keys));
+                    }
+                    "pageInfo" => page_info_keys.push(conn_field.response_key()),
+                    _ => { /* `__typename` */ }
+                }
+            }
+        }
+
+        let nodes_field = a::Field {
+            position: field.position,
+            alias: None,
+            name: field.name.clone(),
+            arguments: field.arguments.clone(),
+            directives: vec![],
+            selection_set: node_set,
+            multiplicity: ChildMultiplicity::Many,
+        };
+        let mut query = build_query(
+            &entity,
+            self.resolver.block_number(),
+            &nodes_field,
+            self.ctx.max_first,
+            self.ctx.max_skip,
+            &input_schema,
+        )?;
+        if let EntityOrder::ChildAscending(_) | EntityOrder::ChildDescending(_) = query.order {
+            return Err(vec![QueryExecutionError::NotSupported(
+                "connections can not be ordered by attributes of child entities".to_string(),
+            )]);
+        }
+        // Fetch one more entity than asked for to find out whether there
+        // is a next page
+        let first = query.range.first.unwrap_or(EntityRange::FIRST);
+        query.range.first = Some(first + 1);
+        query.trace = self.ctx.trace;
+        query.query_id = Some(self.ctx.query.query_id.clone());
+        query.deadline = self.ctx.deadline;
+        query.logger = Some(self.ctx.logger.cheap_clone());
+        let order = query.order.clone();
+        let has_previous_page = query.after.is_some();
+
+        let fetch_start = Instant::now();
+        let (values, mut trace) = self.resolver.store.find_query_values(query)?;
+        if self.ctx.trace {
+            trace.field(FieldTrace {
+                parent_type: "Query".to_string(),
+                field_name: field.name.clone(),
+                return_type: connection_type.to_string(),
+                start_offset: fetch_start.duration_since(self.ctx.query.start()),
+                duration: fetch_start.elapsed(),
+            })?;
+        }
+        let has_next_page = values.len() > first as usize;
+        let children: Vec<Node> = values
+            .into_iter()
+            .take(first as usize)
+            .map(Node::from)
+            .collect();
+        let cursors = children
+            .iter()
+            .map(|child| encode_cursor(&child.entity, &order))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (children, trace) =
+            self.execute_selection_set(children, trace, &nodes_field.selection_set, None)?;
+        let children: Vec<_> = children.into_iter().map(Rc::new).collect();
+
+        let mut connection = Node::from(Object::from_iter(vec![typename(connection_type)]));
+        let edge_type = edge_type_name(entity.typename());
+        for (edges_key, node_keys) in edges_keys {
+            let edges = children
+                .iter()
+                .zip(cursors.iter())
+                .map(|(child, cursor)| {
+                    let mut edge = Node::from(Object::from_iter(vec![
+                        typename(&edge_type),
+                        (Word::from("cursor"), r::Value::String(cursor.clone())),
+                    ]));
+                    for node_key in &node_keys {
+                        edge.set_children(node_key.to_string(), vec![child.clone()]);
+                    }
+                    Rc::new(edge)
+                })
+                .collect();
+            connection.set_children(edges_key.to_string(), edges);
+        }
+        for page_info_key in page_info_keys {
+            let page_info = Node::from(Object::from_iter(vec![
+                typename(PAGE_INFO_TYPE),
+                (Word::from("hasNextPage"), r::Value::Boolean(has_next_page)),
+                (
+                    Word::from("hasPreviousPage"),
+                    r::Value::Boolean(has_previous_page),
+                ),
+                (Word::from("startCursor"), cursor_value(cursors.first())),
+                (Word::from("endCursor"), cursor_value(cursors.last())),
+            ]));
+            connection.set_children(page_info_key.to_string(), vec![Rc::new(page_info)]);
+        }
+
+        Ok((connection, trace))
+    }
+
+    /// Execute an aggregate field like `usersAggregate` over the entities
+    /// of type `entity`. The result is a node with the `count` and a child
+    /// for each of the `sum`, `avg`, `min` and `max` fields that holds the
+    /// aggregated values of the selected attributes
+    fn execute_aggregate(
+        &self,
+        entity: ObjectOrInterface<'_>,
+        aggregate_type: &str,
+        field: &a::Field,
+    ) -> Result<(Node, Trace), Vec<QueryExecutionError>> {
+        fn typename(name: &str) -> (Word, r::Value) {
+            (Word::from("__typename"), r::Value::String(name.to_string()))
+        }
+
+        /// The position of `aggregate` in `aggregates`, adding it if it
+        /// is not there yet
+        fn position(aggregates: &mut Vec<EntityAggregate>, aggregate: EntityAggregate) -> usize {
+            match aggregates.iter().position(|agg| agg == &aggregate) {
+                Some(pos) => pos,
+                None => {
+                    aggregates.push(aggregate);
+                    aggregates.len() - 1
+                }
+            }
+        }
+
+        let input_schema = self.resolver.store.input_schema()?;
+
+        // Collect the aggregates we need to compute. Each `sum` etc. field
+        // is remembered with the positions of its attributes in
+        // `aggregates` so we can distribute the results afterwards
+        let mut aggregates = Vec::new();
+        let mut count_pos = None;
+        let mut func_fields = Vec::new();
+        for (_, fields) in field.selection_set.fields() {
+            for agg_field in fields {
+                let make: fn(String) -> EntityAggregate = match agg_field.name.as_str() {
+                    "count" => {
+                        count_pos = Some(position(&mut aggregates, EntityAggregate::Count));
+                        continue;
+                    }
+                    "sum" => EntityAggregate::Sum,
+                    "avg" => EntityAggregate::Avg,
+                    "min" => EntityAggregate::Min,
+                    "max" => EntityAggregate::Max,
+                    // `__typename`
+                    _ => continue,
+                };
+                let mut attrs = Vec::new();
+                for (_, attr_fields) in agg_field.selection_set.fields() {
+                    for attr_field in attr_fields.filter(|field| field.name != "__typename") {
+                        let pos = position(&mut aggregates, make(attr_field.name.clone()));
+                        attrs.push((attr_field.name.clone(), pos));
+                    }
+                }
+                func_fields.push((agg_field.response_key(), attrs));
+            }
+        }
+
+        let object_types = a::resolve_object_types(&self.ctx.query.schema, entity.typename())?;
+        let entity_field = a::Field {
+            position: field.position,
+            alias: None,
+            name: field.name.clone(),
+            arguments: field.arguments.clone(),
+            directives: vec![],
+            selection_set: a::SelectionSet::new(object_types.into_iter().collect()),
+            multiplicity: ChildMultiplicity::Many,
+        };
+        let mut query = build_query(
+            &entity,
+            self.resolver.block_number(),
+            &entity_field,
+            self.ctx.max_first,
+            self.ctx.max_skip,
+            &input_schema,
+        )?;
+        query.order = EntityOrder::Unordered;
+        query.trace = self.ctx.trace;
+        query.query_id = Some(self.ctx.query.query_id.clone());
+        query.deadline = self.ctx.deadline;
+        query.logger = Some(self.ctx.logger.cheap_clone());
+
+        let fetch_start = Instant::now();
+        let (values, mut trace) = self
+            .resolver
+            .store
+            .aggregate_query_values(query, &aggregates)?;
+        if self.ctx.trace {
+            trace.field(FieldTrace {
+                parent_type: "Query".to_string(),
+                field_name: field.name.clone(),
+                return_type: aggregate_type.to_string(),
+                start_offset: fetch_start.duration_since(self.ctx.query.start()),
+                duration: fetch_start.elapsed(),
+            })?;
+        }
+
+        let mut entries = vec![typename(aggregate_type)];
+        if let Some(pos) = count_pos {
+            let count = match &values[pos] {
+                StoreValue::Int8(count) => r::Value::Int(*count),
+                value => r::Value::from(value.clone()),
+            };
+            entries.push((Word::from("count"), count));
+        }
+        let mut aggregate = Node::from(Object::from_iter(entries));
+
+        let fields_type = aggregate_fields_type_name(entity.typename());
+        for (response_key, attrs) in func_fields {
+            let mut entries = vec![typename(&fields_type)];
+            entries.extend(
+                attrs
+                    .into_iter()
+                    .map(|(attr, pos)| (Word::from(attr), r::Value::from(values[pos].clone()))),
+            );
+            let values = Node::from(Object::from_iter(entries));
+            aggregate.set_children(response_key.to_string(), vec![Rc::new(values)]);
+        }
+
+        Ok((aggregate, trace))
+    }
+
     /// Query child entities for `parents` from the store. The `join` indicates
     /// in which child field to look for the parent's id/join field. When
     /// `is_single` is `true`, there is at most one child per parent.
@@ -707,6 +1033,7 @@ impl<'a> Loader<'a> {
         )?;
         query.trace = self.ctx.trace;
         query.query_id = Some(self.ctx.query.query_id.clone());
+        query.deadline = self.ctx.deadline;
 
         if field.multiplicity == ChildMultiplicity::Single {
             // Suppress 'order by' in lookups of scalar values since
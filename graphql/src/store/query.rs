@@ -3,17 +3,23 @@ use std::mem::discriminant;
 
 use graph::cheap_clone::CheapClone;
 use graph::components::store::{
-    BlockNumber, Child, EntityCollection, EntityFilter, EntityOrder, EntityOrderByChild,
-    EntityOrderByChildInfo, EntityQuery, EntityRange,
+    BlockNumber, Child, EntityChange, EntityCollection, EntityCursor, EntityFilter, EntityOrder,
+    EntityOrderByChild, EntityOrderByChildInfo, EntityQuery, EntityRange, FulltextOptions,
+    SpatialArea, StoreEvent,
 };
 use graph::data::graphql::TypeExt as _;
 use graph::data::query::QueryExecutionError;
-use graph::data::store::{Attribute, SubscriptionFilter, Value, ValueType};
+use graph::data::store::scalar::BigDecimal;
+use graph::data::store::{Attribute, SubscriptionFilter, Value, ValueType, ID};
 use graph::data::value::Object;
 use graph::data::value::Value as DataValue;
-use graph::prelude::{r, s, TryFromValue, ENV_VARS};
+use graph::prelude::regex::{Error as RegexError, RegexBuilder};
+use graph::prelude::{hex, r, s, serde_json, DeploymentHash, TryFromValue, ENV_VARS};
 use graph::schema::ast::{self as sast, FilterOp};
-use graph::schema::{ApiSchema, EntityType, InputSchema, ObjectOrInterface};
+use graph::schema::{
+    kw, ApiSchema, EntityType, FulltextAlgorithm, FulltextLanguage, InputSchema, ObjectOrInterface,
+    Spatial,
+};
 
 use crate::execution::ast as a;
 
@@ -49,10 +55,108 @@ pub(crate) fn build_query<'a>(
     if let Some(filter) = build_filter(entity, field, schema)? {
         query = query.filter(filter);
     }
+    if let Some(r::Value::String(cursor)) = field.argument_value("after") {
+        query = query.after(decode_cursor(cursor, entity, &order)?);
+    }
     query = query.order(order);
     Ok(query)
 }
 
+/// The attribute other than `id` that `order` sorts by, if any. Cursors
+/// for such an order need to contain the entity's value for it
+fn cursor_attribute(order: &EntityOrder) -> Option<&str> {
+    match order {
+        EntityOrder::Ascending(attr, _) | EntityOrder::Descending(attr, _)
+            if attr != ID.as_str() =>
+        {
+            Some(attr.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Create the cursor for `entity` in a connection that is ordered by
+/// `order`. Cursors are opaque to clients; they are the hex encoding of a
+/// JSON array `[id]` or, if the connection is ordered by an attribute,
+/// `[id, attribute, value]`
+pub(crate) fn encode_cursor(
+    entity: &Object,
+    order: &EntityOrder,
+) -> Result<String, QueryExecutionError> {
+    let id = match entity.get(ID.as_str()) {
+        Some(r::Value::String(id)) => id.as_str(),
+        Some(_) => return Err(QueryExecutionError::IdNotString),
+        None => return Err(QueryExecutionError::IdMissing),
+    };
+    let cursor = match cursor_attribute(order) {
+        Some(attr) => {
+            let value = entity.get(attr).unwrap_or(&r::Value::Null);
+            serde_json::json!([id, attr, value])
+        }
+        None => serde_json::json!([id]),
+    };
+    Ok(hex::encode(cursor.to_string()))
+}
+
+/// Decode a cursor created by `encode_cursor`. Fails if the cursor is
+/// malformed or if it was created for a connection with a different order
+fn decode_cursor(
+    cursor: &str,
+    entity: &ObjectOrInterface<'_>,
+    order: &EntityOrder,
+) -> Result<EntityCursor, QueryExecutionError> {
+    use serde_json::Value as JsonValue;
+
+    let invalid = |reason: &str| {
+        QueryExecutionError::ValueParseError(
+            "after".to_string(),
+            format!("invalid cursor `{}`: {}", cursor, reason),
+        )
+    };
+
+    let raw: JsonValue = hex::decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(|| invalid("not a cursor"))?;
+    let (id, key) = match raw.as_array().map(Vec::as_slice) {
+        Some([JsonValue::String(id)]) => (id, None),
+        Some([JsonValue::String(id), JsonValue::String(attr), value]) => {
+            (id, Some((attr.as_str(), value)))
+        }
+        _ => return Err(invalid("not a cursor")),
+    };
+
+    let entity_type = entity
+        .object_types()
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid("the type has no entities"))?;
+    let id = entity_type
+        .parse_id(id.as_str())
+        .map_err(|e| invalid(&e.to_string()))?;
+
+    let value = match (cursor_attribute(order), key) {
+        (None, None) => None,
+        (Some(attr), Some((key, value))) if attr == key => {
+            let field = entity
+                .field(attr)
+                .ok_or_else(|| invalid("unknown attribute"))?;
+            let value = r::Value::from(value.clone());
+            Some(Value::from_query_value(&value, &field.field_type)?)
+        }
+        _ => {
+            return Err(invalid(
+                "the cursor was created for a different `orderBy` argument",
+            ))
+        }
+    };
+
+    Ok(EntityCursor {
+        id: Value::from(id),
+        value,
+    })
+}
+
 /// Parses GraphQL arguments into a EntityRange, if present.
 fn build_range(
     field: &a::Field,
@@ -111,7 +215,9 @@ fn build_filter(
     }?;
 
     let text_filter = match field.argument_value("text") {
-        Some(r::Value::Object(filter)) => build_fulltext_filter_from_object(filter),
+        Some(r::Value::Object(filter)) => {
+            build_fulltext_filter_from_object(filter, build_fulltext_options(field)?)
+        }
         None => Ok(None),
         _ => Err(QueryExecutionError::InvalidFilterError),
     }?;
@@ -125,6 +231,7 @@ fn build_filter(
 
 fn build_fulltext_filter_from_object(
     object: &Object,
+    options: FulltextOptions,
 ) -> Result<Option<EntityFilter>, QueryExecutionError> {
     object.iter().next().map_or(
         Err(QueryExecutionError::FulltextQueryRequiresFilter),
@@ -133,6 +240,7 @@ fn build_fulltext_filter_from_object(
                 Ok(Some(EntityFilter::Fulltext(
                     key.to_string(),
                     Value::String(s.clone()),
+                    options,
                 )))
             } else {
                 Err(QueryExecutionError::FulltextQueryRequiresFilter)
@@ -141,6 +249,37 @@ fn build_fulltext_filter_from_object(
     )
 }
 
+/// Parses the `rankBy`, `language` and `highlight` arguments of a fulltext
+/// query field
+fn build_fulltext_options(field: &a::Field) -> Result<FulltextOptions, QueryExecutionError> {
+    let rank_by = match field.argument_value("rankBy") {
+        Some(r::Value::Enum(rank_by)) if rank_by == "RANK" => Some(FulltextAlgorithm::Rank),
+        Some(r::Value::Enum(rank_by)) if rank_by == "PROXIMITY" => {
+            Some(FulltextAlgorithm::ProximityRank)
+        }
+        Some(r::Value::Null) | None => None,
+        _ => return Err(QueryExecutionError::InvalidFilterError),
+    };
+    let language = match field.argument_value("language") {
+        Some(r::Value::Enum(language)) => Some(
+            FulltextLanguage::try_from(language.as_str())
+                .map_err(|_| QueryExecutionError::InvalidFilterError)?,
+        ),
+        Some(r::Value::Null) | None => None,
+        _ => return Err(QueryExecutionError::InvalidFilterError),
+    };
+    let highlight = match field.argument_value("highlight") {
+        Some(r::Value::Boolean(highlight)) => *highlight,
+        Some(r::Value::Null) | None => false,
+        _ => return Err(QueryExecutionError::InvalidFilterError),
+    };
+    Ok(FulltextOptions {
+        rank_by,
+        language,
+        highlight,
+    })
+}
+
 fn parse_change_block_filter(value: &r::Value) -> Result<BlockNumber, QueryExecutionError> {
     match value {
         r::Value::Object(object) => i32::try_from_value(
@@ -153,6 +292,49 @@ fn parse_change_block_filter(value: &r::Value) -> Result<BlockNumber, QueryExecu
     }
 }
 
+/// Parses the `withinBox` and `withinRadius` filters for types that are
+/// marked with `@spatial`
+fn build_spatial_filter(
+    spatial: &Spatial,
+    key: &str,
+    value: &r::Value,
+) -> Result<EntityFilter, QueryExecutionError> {
+    let object = match value {
+        r::Value::Object(object) => object,
+        _ => return Err(QueryExecutionError::InvalidFilterError),
+    };
+    let decimal_type = s::Type::NamedType("BigDecimal".to_string());
+    let coord = |name: &str| -> Result<BigDecimal, QueryExecutionError> {
+        let value = object
+            .get(name)
+            .ok_or(QueryExecutionError::InvalidFilterError)?;
+        match Value::from_query_value(value, &decimal_type)? {
+            Value::BigDecimal(d) => Ok(d),
+            _ => Err(QueryExecutionError::InvalidFilterError),
+        }
+    };
+
+    let area = if key == kw::WITHIN_BOX {
+        SpatialArea::Box {
+            min_latitude: coord("minLatitude")?,
+            min_longitude: coord("minLongitude")?,
+            max_latitude: coord("maxLatitude")?,
+            max_longitude: coord("maxLongitude")?,
+        }
+    } else {
+        SpatialArea::Radius {
+            latitude: coord("latitude")?,
+            longitude: coord("longitude")?,
+            meters: coord("meters")?,
+        }
+    };
+    Ok(EntityFilter::Spatial(
+        spatial.latitude.to_string(),
+        spatial.longitude.to_string(),
+        area,
+    ))
+}
+
 /// Parses a GraphQL Filter Value into an EntityFilter.
 fn build_entity_filter(
     field_name: String,
@@ -187,11 +369,60 @@ fn build_entity_filter(
         FilterOp::EndsWithNoCase => Ok(EntityFilter::EndsWithNoCase(field_name, store_value)),
         FilterOp::NotEndsWith => Ok(EntityFilter::NotEndsWith(field_name, store_value)),
         FilterOp::NotEndsWithNoCase => Ok(EntityFilter::NotEndsWithNoCase(field_name, store_value)),
+        FilterOp::MatchesNoCase => Ok(EntityFilter::MatchesNoCase(field_name, store_value)),
+        FilterOp::Regex => {
+            check_regex(&field_name, "_regex", &store_value)?;
+            Ok(EntityFilter::Regex(field_name, store_value))
+        }
+        FilterOp::RegexNoCase => {
+            check_regex(&field_name, "_iregex", &store_value)?;
+            Ok(EntityFilter::RegexNoCase(field_name, store_value))
+        }
         FilterOp::Equal => Ok(EntityFilter::Equal(field_name, store_value)),
         _ => unreachable!(),
     }
 }
 
+/// Reject regular expressions that are longer than what we allow for
+/// regular expression filters, or whose compiled form would be too big.
+/// Patterns that can not be compiled, for example because they use
+/// backreferences, are rejected, too, since we can not bound their cost
+fn check_regex(field_name: &str, suffix: &str, value: &Value) -> Result<(), QueryExecutionError> {
+    let max_len = ENV_VARS.graphql.max_regex_length;
+    if max_len == 0 {
+        return Err(QueryExecutionError::NotSupported(
+            "regular expression filters are disabled".to_string(),
+        ));
+    }
+    let pattern = match value {
+        Value::String(pattern) => pattern,
+        _ => return Ok(()),
+    };
+    if pattern.len() > max_len {
+        return Err(QueryExecutionError::NotSupported(format!(
+            "the pattern for `{}{}` is {} characters long, but at most {} are allowed",
+            field_name,
+            suffix,
+            pattern.len(),
+            max_len
+        )));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(ENV_VARS.graphql.max_regex_size)
+        .build()
+        .map(|_| ())
+        .map_err(|e| match e {
+            RegexError::CompiledTooBig(_) => QueryExecutionError::NotSupported(format!(
+                "the pattern for `{}{}` is too complex",
+                field_name, suffix
+            )),
+            e => QueryExecutionError::NotSupported(format!(
+                "the pattern for `{}{}` is not supported: {}",
+                field_name, suffix, e
+            )),
+        })
+}
+
 /// Iterate over the list and generate an EntityFilter from it
 fn build_list_filter_from_value(
     entity: &ObjectOrInterface,
@@ -252,6 +483,11 @@ fn build_filter_from_object<'a>(
                     Err(e) => Err(e),
                 };
             }
+            if key == kw::WITHIN_BOX || key == kw::WITHIN_RADIUS {
+                if let Some(spatial) = entity.spatial() {
+                    return build_spatial_filter(spatial, key, value);
+                }
+            }
             use self::sast::FilterOp::*;
             let (field_name, op) = sast::parse_field_as_filter(key);
 
@@ -672,8 +908,20 @@ pub(crate) fn collect_entities_from_query_field(
             // Check if the field type corresponds to a type definition (in a valid schema,
             // this should always be the case)
             if let Some(type_definition) = schema.get_type_definition_from_field(field_type) {
-                // If the field's type definition is an object type, extract that type
-                if let s::TypeDefinition::Object(object_type) = type_definition {
+                // If the field's type definition is an object type, extract
+                // that type; for interfaces, extract all the object types
+                // that implement it since a change to any of them can
+                // change the result
+                let object_types: Vec<&s::ObjectType> = match type_definition {
+                    s::TypeDefinition::Object(object_type) => vec![object_type],
+                    s::TypeDefinition::Interface(interface) => schema
+                        .types_for_interface()
+                        .get(&interface.name)
+                        .map(|types| types.iter().collect())
+                        .unwrap_or_default(),
+                    _ => vec![],
+                };
+                for object_type in object_types {
                     // Only collect whether the field's type has an @entity directive
                     if sast::get_object_type_directive(object_type, String::from("entity"))
                         .is_some()
@@ -704,9 +952,132 @@ pub(crate) fn collect_entities_from_query_field(
         .map_err(Into::into)
 }
 
+/// Decides which store events can change the result of a subscription.
+/// Besides the entity types the subscription uses, it remembers the ids
+/// that the root field of the subscription asks for with an `id` argument
+/// or an `id` or `id_in` filter. Changes to other entities of that type can
+/// not change the result, unless the type also appears further down in the
+/// query
+#[derive(Debug)]
+pub(crate) struct ChangeFilter {
+    deployment: DeploymentHash,
+    entities: BTreeSet<SubscriptionFilter>,
+    root: Option<(EntityType, BTreeSet<String>)>,
+}
+
+impl ChangeFilter {
+    pub(crate) fn new(
+        input_schema: &InputSchema,
+        schema: &ApiSchema,
+        object_type: sast::ObjectType,
+        field: &a::Field,
+    ) -> Result<Self, QueryExecutionError> {
+        let entities = collect_entities_from_query_field(
+            input_schema,
+            schema,
+            object_type.cheap_clone(),
+            field,
+        )?;
+        let root = Self::root_ids(input_schema, schema, &object_type, field)?;
+        Ok(ChangeFilter {
+            deployment: input_schema.id().clone(),
+            entities,
+            root,
+        })
+    }
+
+    /// The entity type of the root `field` and the ids it is restricted
+    /// to, or `None` if the field might return any entity of its type
+    fn root_ids(
+        input_schema: &InputSchema,
+        schema: &ApiSchema,
+        object_type: &sast::ObjectType,
+        field: &a::Field,
+    ) -> Result<Option<(EntityType, BTreeSet<String>)>, QueryExecutionError> {
+        let root_type = match sast::get_field(object_type, &field.name)
+            .and_then(|field_type| schema.get_type_definition_from_field(field_type))
+        {
+            Some(s::TypeDefinition::Object(root_type)) => root_type,
+            _ => return Ok(None),
+        };
+        let entity_type = match input_schema.entity_type(&root_type.name) {
+            Ok(entity_type) => entity_type,
+            Err(_) => return Ok(None),
+        };
+
+        let values: Vec<&r::Value> = match field.argument_value("id") {
+            Some(id) => vec![id],
+            None => match field.argument_value("where") {
+                Some(r::Value::Object(filter)) => match (filter.get("id"), filter.get("id_in")) {
+                    (Some(id), None) => vec![id],
+                    (None, Some(r::Value::List(ids))) => ids.iter().collect(),
+                    _ => return Ok(None),
+                },
+                _ => return Ok(None),
+            },
+        };
+
+        // Use the same representation for ids as entity changes
+        let mut ids = BTreeSet::new();
+        for value in values {
+            let id = match value {
+                r::Value::String(id) => id.clone(),
+                r::Value::Int(id) => id.to_string(),
+                _ => return Ok(None),
+            };
+            match entity_type.parse_key(id) {
+                Ok(key) => ids.insert(key.entity_id.to_string()),
+                Err(_) => return Ok(None),
+            };
+        }
+
+        // If the query reaches entities of the root type through other
+        // entities, any change to an entity of that type matters
+        let root_filter =
+            SubscriptionFilter::Entities(input_schema.id().cheap_clone(), entity_type.clone());
+        let root_object: sast::ObjectType = schema.object_type(root_type).into();
+        for sub_field in field.selection_set.fields_for(&root_object)? {
+            let nested = collect_entities_from_query_field(
+                input_schema,
+                schema,
+                root_object.cheap_clone(),
+                sub_field,
+            )?;
+            if nested.contains(&root_filter) {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some((entity_type, ids)))
+    }
+
+    /// The entity types whose changes might change the result
+    pub(crate) fn entities(&self) -> BTreeSet<SubscriptionFilter> {
+        self.entities.clone()
+    }
+
+    /// Whether `event` might change the result of the subscription
+    pub(crate) fn matches(&self, event: &StoreEvent) -> bool {
+        event.changes.iter().any(|change| {
+            if !self.entities.iter().any(|filter| filter.matches(change)) {
+                return false;
+            }
+            match (&self.root, change) {
+                (Some((root_type, ids)), EntityChange::Data { entity_type, .. })
+                    if entity_type == root_type.typename() =>
+                {
+                    ids.iter()
+                        .any(|id| change.affects(&self.deployment, root_type.typename(), id))
+                }
+                _ => true,
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use graph::components::store::EntityQuery;
+    use graph::components::store::{EntityCursor, EntityQuery};
     use graph::data::store::ID;
     use graph::env::ENV_VARS;
     use graph::{
@@ -717,14 +1088,14 @@ mod tests {
             r,
             s::{self, Directive, Field, InputValue, ObjectType, Type, Value as SchemaValue},
             AttributeNames, DeploymentHash, EntityCollection, EntityFilter, EntityOrder,
-            EntityRange, Value, ValueType, BLOCK_NUMBER_MAX,
+            EntityRange, QueryExecutionError, Value, ValueType, BLOCK_NUMBER_MAX,
         },
         schema::{EntityType, InputSchema},
     };
     use std::collections::BTreeSet;
     use std::{iter::FromIterator, sync::Arc};
 
-    use super::{a, build_query};
+    use super::{a, build_query, encode_cursor, ChangeFilter};
 
     const DEFAULT_OBJECT: &str = "DefaultObject";
     const ENTITY1: &str = "Entity1";
@@ -999,6 +1370,76 @@ mod tests {
         )
     }
 
+    #[test]
+    fn build_query_yields_regex_filters() {
+        let query_field = default_field_with(
+            "where",
+            r::Value::Object(Object::from_iter(vec![
+                (
+                    "name_imatches".into(),
+                    r::Value::String("%ell%".to_string()),
+                ),
+                ("name_iregex".into(), r::Value::String("^h.*o$".to_string())),
+            ])),
+        );
+        assert_eq!(
+            query(&query_field).filter,
+            Some(EntityFilter::And(vec![
+                EntityFilter::MatchesNoCase("name".to_string(), Value::String("%ell%".to_string())),
+                EntityFilter::RegexNoCase("name".to_string(), Value::String("^h.*o$".to_string())),
+            ]))
+        )
+    }
+
+    #[test]
+    fn build_query_rejects_long_regex() {
+        let pattern = "a".repeat(ENV_VARS.graphql.max_regex_length + 1);
+        let query_field = default_field_with(
+            "where",
+            r::Value::Object(Object::from_iter(vec![(
+                "name_regex".into(),
+                r::Value::String(pattern),
+            )])),
+        );
+        let object = INPUT_SCHEMA
+            .object_or_interface(DEFAULT_OBJECT, None)
+            .unwrap();
+        let res = build_query(
+            &object,
+            BLOCK_NUMBER_MAX,
+            &query_field,
+            std::u32::MAX,
+            std::u32::MAX,
+            &*INPUT_SCHEMA,
+        );
+        assert!(matches!(res, Err(QueryExecutionError::NotSupported(_))));
+    }
+
+    #[test]
+    fn build_query_rejects_complex_regex() {
+        let pattern = "((a{100}){100}){100}".to_string();
+        assert!(pattern.len() <= ENV_VARS.graphql.max_regex_length);
+        let query_field = default_field_with(
+            "where",
+            r::Value::Object(Object::from_iter(vec![(
+                "name_regex".into(),
+                r::Value::String(pattern),
+            )])),
+        );
+        let object = INPUT_SCHEMA
+            .object_or_interface(DEFAULT_OBJECT, None)
+            .unwrap();
+        let res = build_query(
+            &object,
+            BLOCK_NUMBER_MAX,
+            &query_field,
+            std::u32::MAX,
+            std::u32::MAX,
+            &*INPUT_SCHEMA,
+        );
+        assert!(matches!(res, Err(QueryExecutionError::NotSupported(_))));
+    }
+
     #[test]
     fn build_query_yields_block_change_gte_filter() {
         let query_field = default_field_with(
@@ -1016,4 +1457,100 @@ mod tests {
             Some(EntityFilter::And(vec![EntityFilter::ChangeBlockGte(10)]))
         )
     }
+
+    #[test]
+    fn build_query_decodes_cursors() {
+        let entity = Object::from_iter(vec![
+            ("id".into(), r::Value::String("1".to_string())),
+            ("name".into(), r::Value::String("Bob".to_string())),
+        ]);
+        let by_id = encode_cursor(&entity, &EntityOrder::Default).unwrap();
+        let by_name = encode_cursor(
+            &entity,
+            &EntityOrder::Ascending("name".to_string(), ValueType::String),
+        )
+        .unwrap();
+
+        let query_field = default_field_with("after", r::Value::String(by_id));
+        assert_eq!(
+            query(&query_field).after,
+            Some(EntityCursor {
+                id: Value::String("1".to_string()),
+                value: None
+            })
+        );
+
+        let query_field = default_field_with_vec(vec![
+            ("orderBy", r::Value::Enum("name".to_string())),
+            ("after", r::Value::String(by_name.clone())),
+        ]);
+        assert_eq!(
+            query(&query_field).after,
+            Some(EntityCursor {
+                id: Value::String("1".to_string()),
+                value: Some(Value::String("Bob".to_string()))
+            })
+        );
+
+        // Cursors can only be used with the order they were created for,
+        // and must be well-formed
+        let object = INPUT_SCHEMA
+            .object_or_interface(DEFAULT_OBJECT, None)
+            .unwrap();
+        for cursor in [by_name, "not a cursor".to_string()] {
+            let query_field = default_field_with("after", r::Value::String(cursor));
+            let res = build_query(
+                &object,
+                BLOCK_NUMBER_MAX,
+                &query_field,
+                std::u32::MAX,
+                std::u32::MAX,
+                &INPUT_SCHEMA,
+            );
+            assert!(res.is_err());
+        }
+    }
+
+    #[test]
+    fn change_filter_matches_changes_to_subscribed_ids() {
+        use graph::components::store::{EntityChange, StoreEvent};
+        use graph::prelude::SubscriptionFilter;
+
+        let id = INPUT_SCHEMA.id().clone();
+        let change = |entity: &str, ids: Option<Vec<&str>>| {
+            let ids = ids.map(|ids| ids.into_iter().map(|id| id.to_string()).collect());
+            StoreEvent::new(vec![EntityChange::Data {
+                subgraph_id: id.clone(),
+                entity_type: entity.to_string(),
+                ids,
+            }])
+        };
+        let entities: BTreeSet<_> = [ENTITY1, ENTITY2]
+            .into_iter()
+            .map(|name| SubscriptionFilter::Entities(id.clone(), entity_type(name)))
+            .collect();
+
+        // A subscription like `entity1(id: "1") { id entity2 { id } }`
+        let filter = ChangeFilter {
+            deployment: id.clone(),
+            entities: entities.clone(),
+            root: Some((entity_type(ENTITY1), BTreeSet::from(["1".to_string()]))),
+        };
+        assert!(filter.matches(&change(ENTITY1, Some(vec!["1", "2"]))));
+        assert!(!filter.matches(&change(ENTITY1, Some(vec!["2"]))));
+        // Changes that do not list ids might affect any entity
+        assert!(filter.matches(&change(ENTITY1, None)));
+        // Any change to a nested entity type matters
+        assert!(filter.matches(&change(ENTITY2, Some(vec!["2"]))));
+        // Changes to types the subscription does not use never matter
+        assert!(!filter.matches(&change(DEFAULT_OBJECT, None)));
+
+        // A subscription for a collection without an id filter
+        let filter = ChangeFilter {
+            deployment: id.clone(),
+            entities,
+            root: None,
+        };
+        assert!(filter.matches(&change(ENTITY1, Some(vec!["2"]))));
+    }
 }
@@ -3,7 +3,7 @@ use std::result;
 use std::sync::Arc;
 
 use graph::components::graphql::GraphQLMetrics as _;
-use graph::components::store::{QueryPermit, SubscriptionManager, UnitStream};
+use graph::components::store::{ChangeStream, QueryPermit, SubscriptionManager};
 use graph::data::graphql::load_manager::LoadManager;
 use graph::data::graphql::{object, ObjectOrInterface};
 use graph::data::query::{CacheStatus, QueryResults, Trace};
@@ -21,7 +21,7 @@ use crate::execution::{ast as a, Query};
 use crate::metrics::GraphQLMetrics;
 use crate::prelude::{ExecutionContext, Resolver};
 use crate::query::ext::BlockConstraint;
-use crate::store::query::collect_entities_from_query_field;
+use crate::store::query::ChangeFilter;
 
 /// A resolver that fetches entities from a `Store`.
 #[derive(Clone, CheapClone)]
@@ -33,6 +33,10 @@ pub struct StoreResolver {
     pub(crate) block_ptr: Option<BlockPtr>,
     deployment: DeploymentHash,
     has_non_fatal_errors: bool,
+    /// The state of the deployment when the resolver was created. Only
+    /// used to resolve `_meta { indexingStatus }`, and not available for
+    /// subscriptions
+    state: Option<DeploymentState>,
     error_policy: ErrorPolicy,
     graphql_metrics: Arc<GraphQLMetrics>,
     load_manager: Arc<LoadManager>,
@@ -60,6 +64,7 @@ impl StoreResolver {
 
             // Checking for non-fatal errors does not work with subscriptions.
             has_non_fatal_errors: false,
+            state: None,
             error_policy: ErrorPolicy::Deny,
             graphql_metrics,
             load_manager,
@@ -94,6 +99,7 @@ impl StoreResolver {
             block_ptr: Some(block_ptr),
             deployment,
             has_non_fatal_errors,
+            state: Some(state.clone()),
             error_policy,
             graphql_metrics,
             load_manager,
@@ -110,11 +116,14 @@ impl StoreResolver {
 
     /// Locate all the blocks needed for the query by resolving block
     /// constraints and return the selection sets with the blocks at which
-    /// they should be executed
+    /// they should be executed. If `min_block` is given, selections
+    /// without an explicit block constraint are treated as if they had
+    /// asked for `block: { number_gte: min_block }`
     pub async fn locate_blocks(
         store: &dyn QueryStore,
         state: &DeploymentState,
         query: &Query,
+        min_block: Option<BlockNumber>,
     ) -> Result<Vec<(BlockPtr, (a::SelectionSet, ErrorPolicy))>, QueryResults> {
         fn block_queryable(
             state: &DeploymentState,
@@ -137,6 +146,10 @@ impl StoreResolver {
             .map_err(QueryExecutionError::from)?;
         let mut ptrs_and_sels = Vec::new();
         for (bc, sel) in by_block_constraint {
+            let bc = match (bc, min_block) {
+                (BlockConstraint::Latest, Some(min)) => BlockConstraint::Min(min),
+                (bc, _) => bc,
+            };
             let ptr = match bc {
                 BlockConstraint::Hash(hash) => {
                     let Some(number) = hashes.get(&hash) else {
@@ -188,6 +201,7 @@ impl StoreResolver {
         const BLOCK: &str = "block";
         const TIMESTAMP: &str = "timestamp";
         const PARENT_HASH: &str = "parentHash";
+        const INDEXING_STATUS: &str = "indexingStatus";
 
         /// Check if field is of the form `_ { block { X }}` where X is
         /// either `timestamp` or `parentHash`. In that case, we need to
@@ -268,6 +282,11 @@ impl StoreResolver {
         };
         let block_key = Word::from(format!("prefetch:{BLOCK}"));
         map.insert(block_key, r::Value::List(vec![block]));
+
+        // Without the deployment state, `indexingStatus` is `null`
+        let indexing_status = indexing_status(self.state.as_ref()).into_iter().collect();
+        let indexing_status_key = Word::from(format!("prefetch:{INDEXING_STATUS}"));
+        map.insert(indexing_status_key, r::Value::List(indexing_status));
         map.insert(
             "deployment".into(),
             r::Value::String(self.deployment.to_string()),
@@ -385,15 +404,37 @@ impl Resolver for StoreResolver {
         schema: &ApiSchema,
         object_type: &s::ObjectType,
         field: &a::Field,
-    ) -> result::Result<UnitStream, QueryExecutionError> {
+    ) -> result::Result<ChangeStream, QueryExecutionError> {
+        use graph::futures03::compat::Stream01CompatExt as _;
+        use graph::futures03::StreamExt as _;
+        use graph::tokio::sync::watch;
+        use graph::tokio_stream::wrappers::WatchStream;
+
         // Collect all entities involved in the query field
         let object_type = schema.object_type(object_type).into();
         let input_schema = self.store.input_schema()?;
-        let entities =
-            collect_entities_from_query_field(&input_schema, schema, object_type, field)?;
-
-        // Subscribe to the store and return the entity change stream
-        Ok(self.subscription_manager.subscribe_no_payload(entities))
+        let filter = ChangeFilter::new(&input_schema, schema, object_type, field)?;
+
+        // Subscribe to the store and only pass on events for changes that
+        // can change the result of the subscription. Events are consumed
+        // as they arrive so that a slow subscription does not hold up
+        // delivery to others; the watch channel coalesces them
+        let mut events = self
+            .subscription_manager
+            .subscribe(filter.entities())
+            .compat();
+        let (sender, receiver) = watch::channel(());
+        graph::spawn(async move {
+            while let Some(Ok(event)) = events.next().await {
+                if sender.is_closed() {
+                    break;
+                }
+                if filter.matches(&event) && sender.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Box::pin(WatchStream::from_changes(receiver)))
     }
 
     fn post_process(&self, result: &mut QueryResult) -> Result<(), anyhow::Error> {
@@ -458,3 +499,83 @@ impl Resolver for StoreResolver {
         );
     }
 }
+
+/// The `indexingStatus` in `_meta` for a deployment in `state`, or `None`
+/// if the state is not known
+fn indexing_status(state: Option<&DeploymentState>) -> Option<r::Value> {
+    // This constant is closely related to the `_IndexingStatus_` type in
+    // `graph/src/schema/meta.graphql`
+    const INDEXING_STATUS_TYPE: &str = "_IndexingStatus_";
+
+    let state = state?;
+    // Blocks that are less than `reorg_threshold` blocks behind the head
+    // of the deployment might still be reverted
+    let first_non_final_block =
+        (state.latest_block.number - ENV_VARS.reorg_threshold + 1).max(state.earliest_block_number);
+    Some(object! {
+        reorgCount: i32::try_from(state.reorg_count).unwrap_or(i32::MAX),
+        maxReorgDepth: i32::try_from(state.max_reorg_depth).unwrap_or(i32::MAX),
+        firstNonFinalBlock: first_non_final_block,
+        earliestBlock: state.earliest_block_number,
+        __typename: INDEXING_STATUS_TYPE
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexing_status_of_deployment() {
+        let threshold = ENV_VARS.reorg_threshold;
+        let state = |latest: BlockNumber, earliest: BlockNumber| DeploymentState {
+            id: DeploymentHash::new("indexingStatus").unwrap(),
+            reorg_count: 3,
+            max_reorg_depth: 2,
+            latest_block: BlockPtr::new(BlockHash::zero(), latest),
+            earliest_block_number: earliest,
+            first_error_block: None,
+        };
+        let status =
+            |reorgs: i32, depth: i32, first_non_final: BlockNumber, earliest: BlockNumber| {
+                Some(object! {
+                    reorgCount: reorgs,
+                    maxReorgDepth: depth,
+                    firstNonFinalBlock: first_non_final,
+                    earliestBlock: earliest,
+                    __typename: "_IndexingStatus_"
+                })
+            };
+
+        // Without the deployment state, the status is `null`
+        assert_eq!(None, indexing_status(None));
+
+        // All blocks are within the reorg threshold
+        assert_eq!(
+            status(3, 2, 0, 0),
+            indexing_status(Some(&state(threshold - 1, 0)))
+        );
+
+        // The head is past the reorg threshold
+        let latest = threshold + 10;
+        assert_eq!(
+            status(3, 2, 11, 0),
+            indexing_status(Some(&state(latest, 0)))
+        );
+
+        // Pruned blocks are never non-final
+        assert_eq!(
+            status(3, 2, 11, 5),
+            indexing_status(Some(&state(latest, 5)))
+        );
+        assert_eq!(
+            status(3, 2, 20, 20),
+            indexing_status(Some(&state(latest, 20)))
+        );
+
+        // Counts that do not fit into an `Int` are capped
+        let mut large = state(latest, 0);
+        large.reorg_count = u32::MAX;
+        assert_eq!(status(i32::MAX, 2, 11, 0), indexing_status(Some(&large)));
+    }
+}
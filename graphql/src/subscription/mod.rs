@@ -1,7 +1,7 @@
 use std::result::Result;
 use std::time::{Duration, Instant};
 
-use graph::components::store::UnitStream;
+use graph::components::store::ChangeStream;
 use graph::data::graphql::load_manager::LoadManager;
 use graph::futures03::future::FutureExt;
 use graph::futures03::stream::StreamExt;
@@ -82,7 +82,7 @@ pub(crate) fn execute_prepared_subscription(
 fn create_source_event_stream(
     query: Arc<crate::execution::Query>,
     options: &SubscriptionExecutionOptions,
-) -> Result<UnitStream, SubscriptionError> {
+) -> Result<ChangeStream, SubscriptionError> {
     let resolver = StoreResolver::for_subscription(
         &options.logger,
         query.schema.id().clone(),
@@ -129,7 +129,7 @@ fn resolve_field_stream(
     ctx: &ExecutionContext<impl Resolver>,
     object_type: &s::ObjectType,
     field: &a::Field,
-) -> Result<UnitStream, SubscriptionError> {
+) -> Result<ChangeStream, SubscriptionError> {
     ctx.resolver
         .resolve_field_stream(&ctx.query.schema, object_type, field)
         .map_err(SubscriptionError::from)
@@ -138,7 +138,7 @@ fn resolve_field_stream(
 fn map_source_to_response_stream(
     query: Arc<crate::execution::Query>,
     options: SubscriptionExecutionOptions,
-    source_stream: UnitStream,
+    source_stream: ChangeStream,
 ) -> QueryResultStream {
     // Create a stream with a single empty event. By chaining this in front
     // of the real events, we trick the subscription into executing its query
@@ -3,6 +3,7 @@ use config::PoolSize;
 use git_testament::{git_testament, render_testament};
 use graph::bail;
 use graph::cheap_clone::CheapClone;
+use graph::components::store::{DeploymentPriority, ExportFormat};
 use graph::endpoint::EndpointMetrics;
 use graph::env::ENV_VARS;
 use graph::log::logger_with_levels;
@@ -33,7 +34,7 @@ use graph_store_postgres::{
     SubscriptionManager, PRIMARY_SHARD,
 };
 use lazy_static::lazy_static;
-use std::{collections::HashMap, num::ParseIntError, sync::Arc, time::Duration};
+use std::{collections::HashMap, num::ParseIntError, path::PathBuf, sync::Arc, time::Duration};
 const VERSION_LABEL_KEY: &str = "version";
 
 git_testament!(TESTAMENT);
@@ -191,6 +192,31 @@ pub enum Command {
         )]
         sleep: Duration,
     },
+    /// Show or change the priority of a deployment
+    ///
+    /// Deployments with high priority prefetch more blocks, process more
+    /// triggers concurrently and are exempt from deployment quotas;
+    /// deployments with low priority prefetch fewer blocks, process
+    /// triggers sequentially and are held back first when they exceed
+    /// their quota. Changing the priority restarts the deployment
+    Priority {
+        /// Do not restart the deployment; the new priority takes effect the
+        /// next time the deployment is started
+        #[clap(long)]
+        no_restart: bool,
+        /// Sleep for this many seconds after pausing the deployment
+        #[clap(
+            long,
+            short,
+            default_value = "20",
+            value_parser = parse_duration_in_secs
+        )]
+        sleep: Duration,
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// The new priority: `low`, `normal` or `high`
+        priority: Option<DeploymentPriority>,
+    },
     /// Rewind a subgraph to a specific block
     Rewind {
         /// Force rewinding even if the block hash is not found in the local
@@ -249,6 +275,32 @@ pub enum Command {
         /// Prometheus push gateway endpoint.
         prometheus_host: Option<String>,
     },
+    /// Replay a block range of a deployment and compare the results
+    ///
+    /// Copies the deployment as of the block before `from` into a scratch
+    /// deployment in `shard`, indexes the blocks `from` to `to` with it in
+    /// this process and prints every entity that the replay changed
+    /// differently from the deployment. The scratch deployment is never
+    /// activated, so queries are not affected. It is removed once the
+    /// differences are printed unless `--keep` is given.
+    ///
+    /// The block before `from` must be in the block cache, and `shard` can
+    /// not already contain a copy of the deployment.
+    Replay {
+        /// The first block to replay
+        #[clap(long, short)]
+        from: i32,
+        /// The last block to replay
+        #[clap(long, short)]
+        to: i32,
+        /// Keep the scratch deployment after printing the differences
+        #[clap(long, short)]
+        keep: bool,
+        /// The deployment to replay (see `help info`)
+        deployment: DeploymentSearch,
+        /// The shard to put the scratch deployment into
+        shard: String,
+    },
     /// Check and interrogate the configuration
     ///
     /// Print information about a configuration file without
@@ -261,6 +313,9 @@ pub enum Command {
     /// Manage deployment copies and grafts
     #[clap(subcommand)]
     Copy(CopyCommand),
+    /// Manage where deployments are stored
+    #[clap(subcommand)]
+    Deployment(DeploymentCommand),
     /// Run a GraphQL query
     Query {
         /// Save the JSON query result in this file
@@ -279,6 +334,30 @@ pub enum Command {
         /// The variables in the form `key=value`
         vars: Vec<String>,
     },
+    /// Export the entities of one type in a deployment as CSV or NDJSON
+    ///
+    /// This reads the entity table directly and is much faster than
+    /// paginating through the entities with GraphQL queries. Progress
+    /// messages are printed to stderr so that the output can be piped.
+    Export {
+        /// The format of the export, either `csv` or `ndjson`
+        #[clap(long, short, default_value = "csv")]
+        format: ExportFormat,
+        /// Export the entities as they were at this block. Defaults to the
+        /// latest state of the entities
+        #[clap(long, short)]
+        block: Option<i32>,
+        /// Write the export to this file instead of stdout
+        #[clap(long, short)]
+        output: Option<String>,
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// The name of the entity type to export
+        entity: String,
+    },
+    /// Benchmark the query path against a live shard
+    #[clap(subcommand)]
+    Bench(BenchCommand),
     /// Get information about chains and manipulate them
     #[clap(subcommand)]
     Chain(ChainCommand),
@@ -290,6 +369,15 @@ pub enum Command {
     #[clap(subcommand)]
     Index(IndexCommand),
 
+    /// Inspect and reprocess triggers that were skipped because of the
+    /// error policy of their handler
+    #[clap(subcommand)]
+    Skipped(SkippedCommand),
+
+    /// Manage the auxiliary datasets that mappings read with `aux.lookup`
+    #[clap(subcommand)]
+    Aux(AuxCommand),
+
     /// Prune a deployment
     ///
     /// Keep only entity versions that are needed to respond to queries at
@@ -320,6 +408,25 @@ pub enum Command {
         once: bool,
     },
 
+    /// Archive old entity versions of a deployment
+    ///
+    /// Move entity versions whose block range ended more than `history`
+    /// blocks before the subgraph head into archive tables. Archived
+    /// versions can still be queried, but take up less space and are not
+    /// covered by the indexes of the deployment's tables.
+    Archive {
+        /// The deployment to archive (see `help info`)
+        deployment: DeploymentSearch,
+        /// Archive versions that were closed more than this many blocks
+        /// before the subgraph head. Defaults to GRAPH_MIN_HISTORY_BLOCKS
+        #[clap(long, short = 'y')]
+        history: Option<usize>,
+        /// Compress large columns of the archive tables with lz4. Requires
+        /// Postgres 14 or later with lz4 support
+        #[clap(long)]
+        lz4: bool,
+    },
+
     /// General database management
     #[clap(subcommand)]
     Database(DatabaseCommand),
@@ -446,6 +553,41 @@ pub enum ConfigCommand {
     },
 }
 
+#[derive(Clone, Debug, Subcommand)]
+pub enum BenchCommand {
+    /// Create a deployment for the benchmark dataset and load the data
+    ///
+    /// The deployment is placed according to the deployment rules for
+    /// `name` and `network`, like any other deployment. Use `graphman
+    /// config place` to check which shard that will be. The deployment is
+    /// paused right after it is created so that no index node tries to
+    /// index it.
+    Load {
+        /// The size of the dataset; the dataset contains `15 * size` entities
+        #[clap(long, short, default_value = "1000")]
+        size: usize,
+        /// The network to use for placing the deployment
+        #[clap(long, short, default_value = "mainnet")]
+        network: String,
+        /// The subgraph name to use for the deployment
+        name: String,
+    },
+    /// Run the benchmark queries against a deployment created by `load`
+    ///
+    /// Prints the minimum, median, 90th percentile and maximum time it took
+    /// to run each query. For meaningful numbers, the query cache should be
+    /// turned off by setting `GRAPH_QUERY_CACHE_BLOCKS=0` and
+    /// `GRAPH_QUERY_LFU_CACHE_SHARDS=0`
+    Run {
+        /// The size of the dataset that was loaded
+        #[clap(long, short, default_value = "1000")]
+        size: usize,
+        /// How many times to run each query
+        #[clap(long, short, default_value = "20")]
+        iterations: usize,
+    },
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum ListenCommand {
     /// Listen only to assignment events
@@ -496,6 +638,37 @@ pub enum CopyCommand {
         /// The name of the database shard that holds the copy
         shard: String,
     },
+    /// Promote a warm standby copy of a deployment
+    ///
+    /// The standby is a copy of the deployment (see `copy create`) that is
+    /// assigned to its own node and keeps indexing next to the active copy.
+    /// Promoting it routes queries to the standby and pauses the previously
+    /// active copy. Standbys that trail the active copy by more than
+    /// `max-lag` blocks are only promoted with `--force`
+    Promote {
+        /// The maximum number of blocks the standby may be behind
+        #[clap(long, default_value = "10")]
+        max_lag: i32,
+        /// Promote the standby even if it is too far behind
+        #[clap(long)]
+        force: bool,
+        /// The IPFS hash of the deployment
+        deployment: String,
+        /// The name of the database shard that holds the standby
+        shard: String,
+    },
+    /// Let a copy or graft make mutable entity types immutable
+    ///
+    /// Copying fails when an entity type that is mutable in the source is
+    /// immutable in the destination and some of its entities were updated
+    /// or deleted. After running this command, such entity types are
+    /// converted by only copying the version of each entity that is
+    /// current at the block that is being copied. The copy continues once
+    /// the destination deployment is restarted
+    ConvertImmutable {
+        /// The destination deployment of the copy operation (see `help info`)
+        dst: DeploymentSearch,
+    },
     /// List all currently running copy and graft operations
     List,
     /// Print the progress of a copy operation
@@ -505,6 +678,76 @@ pub enum CopyCommand {
     },
 }
 
+#[derive(Clone, Debug, Subcommand)]
+pub enum DeploymentCommand {
+    /// Move a deployment to another shard without downtime
+    ///
+    /// The deployment is copied into `to-shard` as of the block that is
+    /// `offset` behind its subgraph head; the copy is indexed by the same
+    /// node while the deployment keeps indexing. Once the copy is within
+    /// `max-lag` blocks of the deployment, the deployment is paused until
+    /// the copy has caught up completely, the copy is activated, and the
+    /// deployment is removed. The command waits until all of that is done
+    Move {
+        /// How far behind the subgraph head to copy
+        #[clap(long, short, default_value = "200")]
+        offset: u32,
+        /// Pause the deployment once the copy is at most this many blocks
+        /// behind it
+        #[clap(long, default_value = "10")]
+        max_lag: i32,
+        /// How many seconds to wait for the copy to catch up once the
+        /// deployment is paused before giving up and resuming it
+        #[clap(long, default_value = "600")]
+        timeout: u64,
+        /// The name of the database shard to move the deployment to
+        #[clap(long)]
+        to_shard: String,
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
+    /// Graft a new deployment onto a deployment that failed
+    ///
+    /// When a deployment fails with a deterministic error, graph-node
+    /// remembers the block before the failing block as its last healthy
+    /// block. This command takes the manifest with the fix, sets its
+    /// `graft` to the failed deployment at that block, adds the grafting
+    /// feature, uploads it to IPFS, and deploys it to `name`. The data of
+    /// the failed deployment is copied once a node starts indexing the new
+    /// deployment.
+    GraftFromFailure {
+        /// The IPFS hash of the manifest with the fix. Defaults to the
+        /// manifest of the failed deployment
+        #[clap(long, short)]
+        manifest: Option<String>,
+        /// The url of the graph-node
+        #[clap(long, short, default_value = "http://localhost:8020")]
+        url: String,
+        /// The failed deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// The name of the subgraph to deploy the new deployment to
+        name: String,
+    },
+    /// Fill missing aggregation buckets from the timeseries data
+    ///
+    /// When a deployment adds an aggregation and is grafted onto a
+    /// deployment that already has the data for its timeseries, the
+    /// aggregation has no values for the time before the graft. This
+    /// command computes all buckets of the deployment's aggregations that
+    /// are missing and that end before the time of the subgraph head from
+    /// the timeseries, in batches that each run in their own transaction.
+    /// Buckets that already have values are skipped, so that the command
+    /// can be rerun if it is interrupted. Cumulative aggregates in buckets
+    /// that already had values are not updated.
+    RollupBackfill {
+        /// How many buckets to fill in one transaction
+        #[clap(long, default_value = "100")]
+        batch_size: usize,
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum ChainCommand {
     /// List all chains that are in the database
@@ -557,6 +800,31 @@ pub enum ChainCommand {
         shard: String,
     },
 
+    /// Remove cached blocks and call cache entries no deployment needs
+    ///
+    /// Determine the oldest block that any deployment on the chain still
+    /// needs, and remove all blocks and call cache entries that are more
+    /// than `keep` blocks older than that. Deployments with block handlers
+    /// that run on every block (unfiltered, polling, or call filters) need
+    /// every block back to their earliest block so that they can be rewound
+    /// without fetching these blocks again; all other deployments only need
+    /// blocks from their current head on. Deployments whose handlers are
+    /// unknown are assumed to need every block. Keeping `keep` blocks before
+    /// that makes sure that walks back through ancestors, e.g., when handling
+    /// reorgs, still find the blocks they need.
+    Prune {
+        /// Chain name (must be an existing chain, see 'chain list')
+        #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new())]
+        chain_name: String,
+        /// How many blocks before the oldest needed block to keep. Must be
+        /// at least the reorg threshold
+        #[clap(long)]
+        keep: i32,
+        /// Only report what would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+
     /// Execute operations on call cache.
     CallCache {
         #[clap(subcommand)]
@@ -588,6 +856,64 @@ pub enum CallCacheCommand {
     },
 }
 
+#[derive(Clone, Debug, Subcommand)]
+pub enum AuxCommand {
+    /// List all versions of all auxiliary datasets
+    List,
+    /// Load a CSV file as a new version of an auxiliary dataset
+    ///
+    /// Each line of the file consists of a key and a value; there is no
+    /// header line. Deployments keep reading from the version of the
+    /// dataset that was current when they first looked something up in
+    /// it; only deployments that start using the dataset later see the
+    /// new version.
+    Load {
+        /// The name of the dataset
+        dataset: String,
+        /// The CSV file with the entries
+        file: PathBuf,
+    },
+    /// Remove a version of an auxiliary dataset that no deployment uses
+    Remove {
+        /// The name of the dataset
+        dataset: String,
+        /// The version to remove
+        version: i32,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum SkippedCommand {
+    /// List the skipped triggers of a deployment
+    List {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
+    /// Process the skipped triggers of a deployment again
+    ///
+    /// Rewinds the deployment to the block before its earliest skipped
+    /// trigger, for example after upgrading to a version of graph-node
+    /// that fixes what made the handlers fail. Rewinding removes the
+    /// records of all skipped triggers from that block on; triggers whose
+    /// handler fails again are recorded again.
+    Reprocess {
+        /// Force rewinding even if the block hash is not found in the local
+        /// database
+        #[clap(long, short)]
+        force: bool,
+        /// Sleep for this many seconds after pausing the deployment
+        #[clap(
+            long,
+            short,
+            default_value = "20",
+            value_parser = parse_duration_in_secs
+        )]
+        sleep: Duration,
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum StatsCommand {
     /// Toggle whether a table is account-like
@@ -659,6 +985,28 @@ pub enum StatsCommand {
         /// The columns to which to apply the target. Defaults to `id, block_range`
         columns: Vec<String>,
     },
+    /// Show or set the storage parameters of tables
+    ///
+    /// Without an entity, show the storage parameters that are set for the
+    /// tables of a deployment. With an entity, set the parameters given as
+    /// `name=value`, e.g., `fillfactor=80`, on its table. The supported
+    /// parameters are `fillfactor`, `toast_tuple_target`,
+    /// `autovacuum_vacuum_threshold`, `autovacuum_vacuum_scale_factor`,
+    /// `autovacuum_analyze_threshold`, and `autovacuum_analyze_scale_factor`.
+    /// A lower `fillfactor` only affects pages that are written after the
+    /// change
+    Storage {
+        /// Reset the named parameters, or all of them if none are named,
+        /// to their defaults
+        #[clap(long)]
+        reset: bool,
+        /// The deployment (see `help info`).
+        deployment: DeploymentSearch,
+        /// The entity whose table to change
+        entity: Option<String>,
+        /// The parameters to set as `name=value`, or to reset with `--reset`
+        params: Vec<String>,
+    },
 }
 
 #[derive(Clone, Debug, Subcommand)]
@@ -735,6 +1083,22 @@ pub enum IndexCommand {
         #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new())]
         index_name: String,
     },
+
+    /// Suggests indexes based on the queries run against a deployment
+    ///
+    /// Suggestions are based on the attributes that queries filter and
+    /// sort by. They are only available if query nodes run with
+    /// `GRAPH_STORE_RECORD_QUERY_PATTERNS=true`.
+    Suggest {
+        /// The deployment (see `help info`).
+        deployment: DeploymentSearch,
+        /// Only consider query patterns that were seen at least this often
+        #[clap(long, default_value = "100")]
+        min_count: i64,
+        /// Create the suggested indexes concurrently
+        #[clap(long)]
+        create: bool,
+    },
 }
 
 #[derive(Clone, Debug, Subcommand)]
@@ -984,6 +1348,7 @@ impl Context {
             subscription_manager,
             load_manager,
             registry,
+            None,
         ))
     }
 
@@ -1194,6 +1559,18 @@ async fn main() -> anyhow::Result<()> {
 
             commands::assign::restart(pool, &sender, locator, sleep)
         }
+        Priority {
+            no_restart,
+            sleep,
+            deployment,
+            priority,
+        } => {
+            let sender = ctx.notification_sender();
+            let pool = ctx.primary_pool();
+            let locator = &deployment.locate_unique(&pool)?;
+
+            commands::assign::priority(pool, &sender, locator, priority, !no_restart, sleep)
+        }
         Rewind {
             force,
             sleep,
@@ -1253,6 +1630,43 @@ async fn main() -> anyhow::Result<()> {
             )
             .await
         }
+        Replay {
+            from,
+            to,
+            keep,
+            deployment,
+            shard,
+        } => {
+            let logger = ctx.logger.clone();
+            let config = ctx.config();
+            let registry = ctx.metrics_registry().clone();
+            let node_id = ctx.node_id().clone();
+            let store_builder = ctx.store_builder().await;
+            let ipfs_url = ctx.ipfs_url.clone();
+            let arweave_url = ctx.arweave_url.clone();
+            let metrics_ctx = MetricsContext {
+                prometheus: ctx.prometheus_registry.clone(),
+                registry,
+                prometheus_host: None,
+                job_name: None,
+            };
+
+            commands::replay::run(
+                logger,
+                store_builder,
+                ipfs_url,
+                arweave_url,
+                config,
+                metrics_ctx,
+                node_id,
+                deployment,
+                shard,
+                from,
+                to,
+                keep,
+            )
+            .await
+        }
         Listen(cmd) => {
             use ListenCommand::*;
             match cmd {
@@ -1287,10 +1701,75 @@ async fn main() -> anyhow::Result<()> {
                 Activate { deployment, shard } => {
                     commands::copy::activate(ctx.subgraph_store(), deployment, shard)
                 }
+                Promote {
+                    deployment,
+                    shard,
+                    max_lag,
+                    force,
+                } => {
+                    commands::copy::promote(ctx.subgraph_store(), deployment, shard, max_lag, force)
+                }
+                ConvertImmutable { dst } => {
+                    let (store, primary) = ctx.store_and_primary();
+                    commands::copy::convert_immutable(store.subgraph_store(), primary, &dst)
+                }
                 List => commands::copy::list(ctx.pools()),
                 Status { dst } => commands::copy::status(ctx.pools(), &dst),
             }
         }
+        Deployment(cmd) => {
+            use DeploymentCommand::*;
+            match cmd {
+                Move {
+                    deployment,
+                    to_shard,
+                    offset,
+                    max_lag,
+                    timeout,
+                } => {
+                    let shards: Vec<_> = ctx.config.stores.keys().cloned().collect();
+                    let (store, primary) = ctx.store_and_primary();
+                    commands::copy::move_deployment(
+                        store,
+                        primary,
+                        deployment,
+                        to_shard,
+                        shards,
+                        offset,
+                        max_lag,
+                        Duration::from_secs(timeout),
+                    )
+                    .await
+                }
+                GraftFromFailure {
+                    manifest,
+                    url,
+                    deployment,
+                    name,
+                } => {
+                    let logger = ctx.logger.clone();
+                    let ipfs_url = ctx.ipfs_url.clone();
+                    let (store, primary) = ctx.store_and_primary();
+                    commands::graft::graft_from_failure(
+                        &logger, store, primary, ipfs_url, deployment, manifest, name, url,
+                    )
+                    .await
+                }
+                RollupBackfill {
+                    batch_size,
+                    deployment,
+                } => {
+                    let (store, primary) = ctx.store_and_primary();
+                    commands::rollup::backfill(
+                        store.subgraph_store(),
+                        primary,
+                        deployment,
+                        batch_size,
+                    )
+                    .await
+                }
+            }
+        }
         Query {
             output,
             trace,
@@ -1298,6 +1777,51 @@ async fn main() -> anyhow::Result<()> {
             query,
             vars,
         } => commands::query::run(ctx.graphql_runner(), target, query, vars, output, trace).await,
+        Export {
+            format,
+            block,
+            output,
+            deployment,
+            entity,
+        } => {
+            let (store, primary_pool) = ctx.store_and_primary();
+            commands::export::run(
+                store.subgraph_store(),
+                primary_pool,
+                deployment,
+                &entity,
+                block,
+                format,
+                output,
+            )
+        }
+        Bench(cmd) => {
+            use BenchCommand::*;
+            match cmd {
+                Load {
+                    size,
+                    network,
+                    name,
+                } => {
+                    let logger = ctx.logger.clone();
+                    let registry = ctx.metrics_registry();
+                    let node = ctx.node_id();
+                    commands::bench::load(
+                        ctx.subgraph_store(),
+                        registry,
+                        logger,
+                        node,
+                        name,
+                        network,
+                        size,
+                    )
+                    .await
+                }
+                Run { size, iterations } => {
+                    commands::bench::run(ctx.graphql_runner(), size, iterations).await
+                }
+            }
+        }
         Chain(cmd) => {
             use ChainCommand::*;
             match cmd {
@@ -1366,6 +1890,14 @@ async fn main() -> anyhow::Result<()> {
                         }
                     }
                 }
+                Prune {
+                    chain_name,
+                    keep,
+                    dry_run,
+                } => {
+                    let (store, primary) = ctx.store_and_primary();
+                    commands::chain::prune(store, primary, chain_name, keep, dry_run).await
+                }
                 Truncate { chain_name, force } => {
                     use commands::check_blocks::truncate;
                     let chain_store = ctx.chain_store(&chain_name)?;
@@ -1394,6 +1926,40 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
         }
+        Aux(cmd) => {
+            use AuxCommand::*;
+            let primary = ctx.primary_pool();
+            match cmd {
+                List => commands::aux_dataset::list(primary),
+                Load { dataset, file } => commands::aux_dataset::load(primary, dataset, file),
+                Remove { dataset, version } => {
+                    commands::aux_dataset::remove(primary, dataset, version)
+                }
+            }
+        }
+        Skipped(cmd) => {
+            use SkippedCommand::*;
+            let notification_sender = ctx.notification_sender();
+            let (store, primary) = ctx.store_and_primary();
+            match cmd {
+                List { deployment } => commands::skipped::list(primary, store, deployment),
+                Reprocess {
+                    force,
+                    sleep,
+                    deployment,
+                } => {
+                    commands::skipped::reprocess(
+                        primary,
+                        store,
+                        deployment,
+                        &notification_sender,
+                        force,
+                        sleep,
+                    )
+                    .await
+                }
+            }
+        }
         Stats(cmd) => {
             use StatsCommand::*;
             match cmd {
@@ -1450,6 +2016,23 @@ async fn main() -> anyhow::Result<()> {
                         no_analyze,
                     )
                 }
+                Storage {
+                    reset,
+                    deployment,
+                    entity,
+                    params,
+                } => {
+                    let (store, primary) = ctx.store_and_primary();
+                    let store = store.subgraph_store();
+                    commands::stats::storage(
+                        store,
+                        primary,
+                        &deployment,
+                        entity.as_deref(),
+                        params,
+                        reset,
+                    )
+                }
             }
         }
         Index(cmd) => {
@@ -1504,6 +2087,20 @@ async fn main() -> anyhow::Result<()> {
                     commands::index::drop(subgraph_store, primary_pool, deployment, &index_name)
                         .await
                 }
+                Suggest {
+                    deployment,
+                    min_count,
+                    create,
+                } => {
+                    commands::index::suggest(
+                        subgraph_store,
+                        primary_pool,
+                        deployment,
+                        min_count,
+                        create,
+                    )
+                    .await
+                }
             }
         }
         Database(cmd) => {
@@ -1544,6 +2141,15 @@ async fn main() -> anyhow::Result<()> {
             )
             .await
         }
+        Archive {
+            deployment,
+            history,
+            lz4,
+        } => {
+            let (store, primary_pool) = ctx.store_and_primary();
+            let history = history.unwrap_or(ENV_VARS.min_history_blocks.try_into()?);
+            commands::archive::run(store, primary_pool, deployment, history, lz4).await
+        }
         Drop {
             deployment,
             current,
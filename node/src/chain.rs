@@ -26,12 +26,13 @@ use graph::firehose::{
 };
 use graph::futures03::future::try_join_all;
 use graph::futures03::TryFutureExt;
-use graph::ipfs_client::IpfsClient;
+use graph::ipfs_client::{IpfsClient, GATEWAY_PREFIX};
 use graph::itertools::Itertools;
 use graph::log::factory::LoggerFactory;
 use graph::prelude::anyhow;
 use graph::prelude::MetricsRegistry;
-use graph::slog::{debug, error, info, o, Logger};
+use graph::slog::{debug, error, info, o, warn, Logger};
+use graph::tokio;
 use graph::url::Url;
 use graph::util::security::SafeDisplay;
 use graph_chain_ethereum::{self as ethereum, Transport};
@@ -54,19 +55,28 @@ pub enum ProviderNetworkStatus {
 }
 
 pub fn create_ipfs_clients(logger: &Logger, ipfs_addresses: &Vec<String>) -> Vec<IpfsClient> {
-    // Parse the IPFS URL from the `--ipfs` command line argument
+    // Parse the IPFS URL from the `--ipfs` command line argument. Gateways
+    // are marked with a prefix that has to stay in front of the scheme
     let ipfs_addresses: Vec<_> = ipfs_addresses
         .iter()
         .map(|uri| {
+            let (prefix, uri) = match uri.strip_prefix(GATEWAY_PREFIX) {
+                Some(uri) => (GATEWAY_PREFIX, uri),
+                None => ("", uri.as_str()),
+            };
             if uri.starts_with("http://") || uri.starts_with("https://") {
-                String::from(uri)
+                format!("{}{}", prefix, uri)
             } else {
-                format!("http://{}", uri)
+                format!("{}http://{}", prefix, uri)
             }
         })
         .collect();
 
-    ipfs_addresses
+    // With several endpoints, an endpoint that can't be reached is only
+    // marked as down and requests fail over to the others
+    let failover = ipfs_addresses.len() > 1;
+
+    let clients: Vec<_> = ipfs_addresses
         .into_iter()
         .map(|ipfs_address| {
             info!(
@@ -103,7 +113,9 @@ pub fn create_ipfs_clients(logger: &Logger, ipfs_addresses: &Vec<String>) -> Vec
                             "Is there an IPFS node running at \"{}\"?",
                             SafeDisplay(ipfs_address_for_err),
                         );
-                        panic!("Failed to connect to IPFS: {}", e);
+                        if !failover {
+                            panic!("Failed to connect to IPFS: {}", e);
+                        }
                     })
                     .map_ok(move |_| {
                         info!(
@@ -117,7 +129,33 @@ pub fn create_ipfs_clients(logger: &Logger, ipfs_addresses: &Vec<String>) -> Vec
 
             ipfs_client
         })
-        .collect()
+        .collect();
+
+    if failover {
+        spawn_ipfs_health_checks(logger.clone(), clients.clone());
+    }
+
+    clients
+}
+
+/// Periodically check all IPFS endpoints so that endpoints that are down
+/// are used again as soon as they recover
+fn spawn_ipfs_health_checks(logger: Logger, clients: Vec<IpfsClient>) {
+    graph::spawn(async move {
+        loop {
+            tokio::time::sleep(ENV_VARS.mappings.ipfs_health_check_interval).await;
+            for client in &clients {
+                let was_up = client.is_up();
+                let is_up = client.check_health().await;
+                let address = client.address();
+                if was_up && !client.is_up() {
+                    warn!(logger, "IPFS endpoint is down"; "address" => SafeDisplay(&address));
+                } else if !was_up && is_up {
+                    info!(logger, "IPFS endpoint is up again"; "address" => SafeDisplay(&address));
+                }
+            }
+        }
+    });
 }
 
 pub fn create_substreams_networks(
@@ -344,11 +382,20 @@ pub async fn create_ethereum_networks_for_chain(
                 web3.headers.clone(),
                 endpoint_metrics.cheap_clone(),
                 &provider.label,
-            ),
+            )
+            .with_batching(&logger, web3.batch.size, web3.batch.concurrency),
             Ipc => Transport::new_ipc(&web3.url).await,
             Ws => Transport::new_ws(&web3.url).await,
         };
 
+        let subscription_url = web3
+            .subscription_url
+            .clone()
+            .or_else(|| match web3.transport {
+                Ws => Some(web3.url.clone()),
+                Rpc | Ipc => None,
+            });
+
         let supports_eip_1898 = !web3.features.contains("no_eip1898");
         let adapter = EthereumNetworkAdapter::new(
             endpoint_metrics.cheap_clone(),
@@ -362,7 +409,10 @@ pub async fn create_ethereum_networks_for_chain(
                     supports_eip_1898,
                     call_only,
                 )
-                .await,
+                .await
+                .with_subscription_url(subscription_url)
+                .with_trace_method(web3.trace_method())
+                .with_beacon_url(web3.beacon_url.clone()),
             ),
             web3.limit_for(&config.node),
         );
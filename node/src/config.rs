@@ -17,7 +17,7 @@ use graph::{
     },
 };
 use graph_chain_ethereum as ethereum;
-use graph_chain_ethereum::NodeCapabilities;
+use graph_chain_ethereum::{NodeCapabilities, TraceMethod};
 use graph_store_postgres::{DeploymentPlacer, Shard as ShardName, PRIMARY_SHARD};
 
 use graph::http::{HeaderMap, Uri};
@@ -501,6 +501,9 @@ impl ChainSection {
                         url: url.to_string(),
                         features,
                         headers: Default::default(),
+                        batch: Default::default(),
+                        subscription_url: None,
+                        beacon_url: None,
                         rules: vec![],
                     }),
                 };
@@ -703,15 +706,70 @@ pub struct Web3Provider {
     )]
     pub headers: HeaderMap,
 
+    /// How requests are combined into JSON-RPC batch requests
+    #[serde(default)]
+    pub batch: Web3Batching,
+
+    /// A WebSocket URL for the same node that is used to subscribe to new
+    /// block headers with `eth_subscribe` instead of polling for them
+    #[serde(default)]
+    pub subscription_url: Option<String>,
+
+    /// The URL of the beacon API of the consensus client paired with the
+    /// node, which is used to get the blobs of blob transactions
+    #[serde(default)]
+    pub beacon_url: Option<String>,
+
     #[serde(default, rename = "match")]
     rules: Vec<Web3Rule>,
 }
 
+/// Settings for combining requests to a Web3 provider into JSON-RPC batch
+/// requests
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Web3Batching {
+    /// The maximum number of requests in one batch request. Batching is
+    /// turned off if this is 0 or 1
+    #[serde(default = "default_batch_size")]
+    pub size: usize,
+    /// The maximum number of batch requests in flight at the same time
+    #[serde(default = "default_batch_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for Web3Batching {
+    fn default() -> Self {
+        Self {
+            size: default_batch_size(),
+            concurrency: default_batch_concurrency(),
+        }
+    }
+}
+
+fn default_batch_size() -> usize {
+    ethereum::ENV_VARS.json_rpc_batch_size
+}
+
+fn default_batch_concurrency() -> usize {
+    ethereum::ENV_VARS.json_rpc_max_batches_in_flight
+}
+
 impl Web3Provider {
     pub fn node_capabilities(&self) -> NodeCapabilities {
         NodeCapabilities {
             archive: self.features.contains("archive"),
-            traces: self.features.contains("traces"),
+            traces: self.features.contains("traces") || self.features.contains("debug_traces"),
+        }
+    }
+
+    /// How to get call traces from this provider. Providers that support
+    /// `trace_filter` use that; providers that only have the `debug_traces`
+    /// feature are traced with `debug_traceBlockByNumber`
+    pub fn trace_method(&self) -> TraceMethod {
+        if !self.features.contains("traces") && self.features.contains("debug_traces") {
+            TraceMethod::DebugTraceBlock
+        } else {
+            TraceMethod::TraceFilter
         }
     }
 
@@ -720,7 +778,7 @@ impl Web3Provider {
     }
 }
 
-const PROVIDER_FEATURES: [&str; 3] = ["traces", "archive", "no_eip1898"];
+const PROVIDER_FEATURES: [&str; 4] = ["traces", "debug_traces", "archive", "no_eip1898"];
 const DEFAULT_PROVIDER_FEATURES: [&str; 2] = ["traces", "archive"];
 
 impl Provider {
@@ -793,6 +851,44 @@ impl Provider {
                         e
                     )
                 })?;
+
+                if let Some(subscription_url) = web3.subscription_url.as_mut() {
+                    *subscription_url = shellexpand::env(subscription_url)?.into_owned();
+                    let url = Url::parse(subscription_url).map_err(|e| {
+                        anyhow!(
+                            "the subscription_url `{}` for provider {} is not a legal URL: {}",
+                            subscription_url,
+                            label,
+                            e
+                        )
+                    })?;
+                    if url.scheme() != "ws" && url.scheme() != "wss" {
+                        return Err(anyhow!(
+                            "the subscription_url `{}` for provider {} must be a ws:// or wss:// URL",
+                            subscription_url,
+                            label
+                        ));
+                    }
+                }
+
+                if let Some(beacon_url) = web3.beacon_url.as_mut() {
+                    *beacon_url = shellexpand::env(beacon_url)?.into_owned();
+                    let url = Url::parse(beacon_url).map_err(|e| {
+                        anyhow!(
+                            "the beacon_url `{}` for provider {} is not a legal URL: {}",
+                            beacon_url,
+                            label,
+                            e
+                        )
+                    })?;
+                    if url.scheme() != "http" && url.scheme() != "https" {
+                        return Err(anyhow!(
+                            "the beacon_url `{}` for provider {} must be a http:// or https:// URL",
+                            beacon_url,
+                            label
+                        ));
+                    }
+                }
             }
         }
 
@@ -900,6 +996,9 @@ impl<'de> Deserialize<'de> for Provider {
                         features: features
                             .ok_or_else(|| serde::de::Error::missing_field("features"))?,
                         headers: headers.unwrap_or_else(HeaderMap::new),
+                        batch: Default::default(),
+                        subscription_url: None,
+                        beacon_url: None,
                         rules: nodes,
                     }),
                 };
@@ -1216,7 +1315,8 @@ mod tests {
     use crate::config::{default_polling_interval, ChainSection, Web3Rule};
 
     use super::{
-        Chain, Config, FirehoseProvider, Provider, ProviderDetails, Transport, Web3Provider,
+        Chain, Config, FirehoseProvider, Provider, ProviderDetails, Transport, Web3Batching,
+        Web3Provider,
     };
     use graph::blockchain::BlockchainKind;
     use graph::firehose::SubgraphLimit;
@@ -1306,6 +1406,9 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
+                    batch: Default::default(),
+                    subscription_url: None,
+                    beacon_url: None,
                     rules: Vec::new(),
                 }),
             },
@@ -1332,6 +1435,9 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
+                    batch: Default::default(),
+                    subscription_url: None,
+                    beacon_url: None,
                     rules: Vec::new(),
                 }),
             },
@@ -1439,6 +1545,9 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features,
                     headers,
+                    batch: Default::default(),
+                    subscription_url: None,
+                    beacon_url: None,
                     rules: Vec::new(),
                 }),
             },
@@ -1464,6 +1573,40 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
+                    batch: Default::default(),
+                    subscription_url: None,
+                    beacon_url: None,
+                    rules: Vec::new(),
+                }),
+            },
+            actual
+        );
+    }
+
+    #[test]
+    fn it_works_on_new_web3_provider_with_batching_from_toml() {
+        let actual = toml::from_str(
+            r#"
+            label = "peering"
+            details = { type = "web3", url = "http://localhost:8545", features = [], batch = { size = 50 } }
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Provider {
+                label: "peering".to_owned(),
+                details: ProviderDetails::Web3(Web3Provider {
+                    transport: Transport::Rpc,
+                    url: "http://localhost:8545".to_owned(),
+                    features: BTreeSet::new(),
+                    headers: HeaderMap::new(),
+                    batch: Web3Batching {
+                        size: 50,
+                        concurrency: 4,
+                    },
+                    subscription_url: None,
+                    beacon_url: None,
                     rules: Vec::new(),
                 }),
             },
@@ -1833,6 +1976,9 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
+                    batch: Default::default(),
+                    subscription_url: None,
+                    beacon_url: None,
                     rules: Vec::new(),
                 }),
             },
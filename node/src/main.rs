@@ -9,13 +9,14 @@ use graph::blockchain::{Blockchain, BlockchainKind};
 use graph::components::link_resolver::{ArweaveClient, FileSizeLimit};
 use graph::components::subgraph::Settings;
 use graph::data::graphql::load_manager::LoadManager;
+use graph::data::query::QueryCostModel;
 use graph::endpoint::EndpointMetrics;
 use graph::env::EnvVars;
 use graph::log::logger;
 use graph::prelude::*;
 use graph::prometheus::Registry;
 use graph::url::Url;
-use graph_core::polling_monitor::{arweave_service, ipfs_service};
+use graph_core::polling_monitor::{arweave_service, https_service, ipfs_service, IpfsDiskCache};
 use graph_core::{
     SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider, SubgraphInstanceManager,
     SubgraphRegistrar as IpfsSubgraphRegistrar,
@@ -123,6 +124,20 @@ async fn main() {
         None => Settings::default(),
     };
 
+    let cost_model = match opt.query_cost_config {
+        Some(ref path) => {
+            info!(logger, "Reading query cost configuration file `{}`", path);
+            match QueryCostModel::from_file(path) {
+                Ok(model) => Some(Arc::new(model)),
+                Err(e) => {
+                    eprintln!("configuration error in query cost config {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
     if opt.check_config {
         match config.to_json() {
             Ok(txt) => println!("{}", txt),
@@ -200,12 +215,16 @@ async fn main() {
 
     // Try to create IPFS clients for each URL specified in `--ipfs`
     let ipfs_clients: Vec<_> = create_ipfs_clients(&logger, &opt.ipfs);
-    let ipfs_client = ipfs_clients.first().cloned().expect("Missing IPFS client");
+    let ipfs_cache = ENV_VARS.mappings.ipfs_cache_location.as_ref().map(|dir| {
+        info!(logger, "Caching IPFS files on disk"; "location" => dir.display().to_string());
+        IpfsDiskCache::new(dir).expect("failed to create the IPFS cache directory")
+    });
     let ipfs_service = ipfs_service(
-        ipfs_client,
+        ipfs_clients.clone(),
         ENV_VARS.mappings.max_ipfs_file_bytes,
         ENV_VARS.mappings.ipfs_timeout,
         ENV_VARS.mappings.ipfs_request_limit,
+        ipfs_cache,
     );
     let arweave_resolver = Arc::new(ArweaveClient::new(
         logger.cheap_clone(),
@@ -222,6 +241,11 @@ async fn main() {
             n => FileSizeLimit::MaxBytes(n as u64),
         },
     );
+    let https_service = https_service(
+        env_vars.mappings.max_ipfs_file_bytes,
+        env_vars.mappings.ipfs_timeout,
+        env_vars.mappings.ipfs_request_limit,
+    );
 
     // Convert the clients into a link resolver. Since we want to get past
     // possible temporary DNS failures, make the resolver retry
@@ -328,8 +352,17 @@ async fn main() {
             subscription_manager.clone(),
             load_manager,
             graphql_metrics_registry,
+            cost_model,
         ));
-        let graphql_server = GraphQLQueryServer::new(&logger_factory, graphql_runner.clone());
+        let persisted_query_store = ENV_VARS
+            .graphql
+            .persisted_queries_in_db
+            .then(|| network_store.subgraph_store().persisted_query_store());
+        let graphql_server = GraphQLQueryServer::new(
+            &logger_factory,
+            graphql_runner.clone(),
+            persisted_query_store,
+        );
         let subscription_server =
             GraphQLSubscriptionServer::new(&logger, graphql_runner.clone(), network_store.clone());
 
@@ -377,6 +410,7 @@ async fn main() {
             link_resolver.clone(),
             ipfs_service,
             arweave_service,
+            https_service,
             static_filters,
         );
 
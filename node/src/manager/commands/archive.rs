@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use graph::{
+    components::store::StatusStore,
+    data::subgraph::status,
+    env::ENV_VARS,
+    prelude::{anyhow, BlockNumber},
+};
+use graph_store_postgres::{connection_pool::ConnectionPool, Store};
+
+use crate::manager::{commands::stats::abbreviate_table_name, deployment::DeploymentSearch};
+
+pub async fn run(
+    store: Arc<Store>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    history: usize,
+    lz4: bool,
+) -> Result<(), anyhow::Error> {
+    let history = history as BlockNumber;
+    if history <= ENV_VARS.reorg_threshold {
+        return Err(anyhow!(
+            "history must be more than the reorg threshold {}",
+            ENV_VARS.reorg_threshold
+        ));
+    }
+    let deployment = search.locate_unique(&primary_pool)?;
+    let mut info = store
+        .status(status::Filter::DeploymentIds(vec![deployment.id]))?
+        .pop()
+        .ok_or_else(|| anyhow!("deployment {deployment} not found"))?;
+    let status = info
+        .chains
+        .pop()
+        .ok_or_else(|| anyhow!("deployment {} does not index any chain", deployment))?;
+    let latest = status.latest_block.map(|ptr| ptr.number()).unwrap_or(0);
+    if latest <= history {
+        return Err(anyhow!("deployment {deployment} has only indexed up to block {latest} and there is nothing older than {history} blocks to archive"));
+    }
+    let block = latest - history;
+
+    println!("archive {deployment}");
+    println!("    latest: {latest}");
+    println!("   archive: versions closed at or before block {block}\n");
+
+    let counts = store
+        .subgraph_store()
+        .archive(&deployment, block, lz4)
+        .await?;
+
+    for (table, count) in counts {
+        println!(
+            "{:<30} | {:>10} versions",
+            abbreviate_table_name(&table, 30),
+            count
+        );
+    }
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use graph::components::store::DeploymentLocator;
+use graph::components::store::{DeploymentLocator, DeploymentPriority};
 use graph::prelude::{anyhow::anyhow, Error, NodeId, StoreEvent};
 use graph_store_postgres::{
     command_support::catalog, connection_pool::ConnectionPool, NotificationSender,
@@ -126,3 +126,50 @@ pub fn restart(
     pause_or_resume(primary, sender, locator, false)?;
     Ok(())
 }
+
+/// Show the priority of a deployment, or set it to `priority`. Since the
+/// priority only takes effect when a deployment is started, the deployment
+/// is restarted after changing its priority unless `restart` is `false`
+pub fn priority(
+    primary: ConnectionPool,
+    sender: &NotificationSender,
+    locator: &DeploymentLocator,
+    priority: Option<DeploymentPriority>,
+    restart: bool,
+    sleep: Duration,
+) -> Result<(), Error> {
+    let pconn = primary.get()?;
+    let mut conn = catalog::Connection::new(pconn);
+
+    let site = conn
+        .locate_site(locator.clone())?
+        .ok_or_else(|| anyhow!("failed to locate site for {locator}"))?;
+
+    let current = match conn.priority(&site)? {
+        Some(current) => current,
+        None => {
+            println!("deployment {locator} is not assigned");
+            return Ok(());
+        }
+    };
+
+    let priority = match priority {
+        Some(priority) if priority != current => priority,
+        _ => {
+            println!("deployment {locator} has priority {current}");
+            return Ok(());
+        }
+    };
+
+    println!("changing priority of {locator} from {current} to {priority}");
+    conn.set_priority(&site, priority)?;
+
+    let is_paused = conn
+        .assignment_status(&site)?
+        .map(|(_, is_paused)| is_paused)
+        .unwrap_or(true);
+    if restart && !is_paused {
+        self::restart(primary, sender, locator, sleep)?;
+    }
+    Ok(())
+}
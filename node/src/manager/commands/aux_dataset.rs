@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::PathBuf;
+
+use graph::csv;
+use graph::prelude::{anyhow::bail, Error};
+use graph_store_postgres::{
+    command_support::catalog::Connection, connection_pool::ConnectionPool, AUX_DATASET_ENS,
+};
+
+pub fn list(primary: ConnectionPool) -> Result<(), Error> {
+    let mut conn = Connection::new(primary.get()?);
+    let datasets = conn.aux_datasets()?;
+
+    if datasets.is_empty() {
+        println!("No auxiliary datasets");
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} | {:>7} | {:>10} | {:>11} | {}",
+        "dataset", "version", "entries", "deployments", "loaded at"
+    );
+    println!("{:-^80}", "");
+    for ds in datasets {
+        println!(
+            "{:<20} | {:>7} | {:>10} | {:>11} | {}",
+            ds.name,
+            ds.version,
+            ds.entries,
+            ds.deployments,
+            ds.loaded_at.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    Ok(())
+}
+
+/// Load the `key,value` rows in the CSV file `path` as a new version of
+/// `dataset`
+pub fn load(primary: ConnectionPool, dataset: String, path: PathBuf) -> Result<(), Error> {
+    if dataset == AUX_DATASET_ENS {
+        bail!(
+            "the dataset `{}` is the ENS rainbow table and can not be loaded with this command",
+            AUX_DATASET_ENS
+        );
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(File::open(&path)?);
+    let mut keys = HashSet::new();
+    let mut entries = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        if record.len() != 2 {
+            bail!(
+                "line {} of {} has {} columns but must have exactly two, a key and a value",
+                line + 1,
+                path.display(),
+                record.len()
+            );
+        }
+        let (key, value) = (record[0].to_string(), record[1].to_string());
+        if !keys.insert(key.clone()) {
+            bail!(
+                "line {} of {} repeats the key `{}`",
+                line + 1,
+                path.display(),
+                key
+            );
+        }
+        entries.push((key, value));
+    }
+
+    let mut conn = Connection::new(primary.get()?);
+    let version = conn.load_aux_dataset(&dataset, &entries)?;
+    println!(
+        "Loaded {} entries as version {} of dataset {}",
+        entries.len(),
+        version,
+        dataset
+    );
+    Ok(())
+}
+
+pub fn remove(primary: ConnectionPool, dataset: String, version: i32) -> Result<(), Error> {
+    let mut conn = Connection::new(primary.get()?);
+    conn.remove_aux_dataset(&dataset, version)?;
+    println!("Removed version {} of dataset {}", version, dataset);
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Fixtures for benchmarking the query path. The same schema, dataset and
+//! queries are used by `graphman bench`, which runs them against a live
+//! shard, and by the criterion benchmarks in `test-store`.
+
+use graph::data::store::scalar::BigInt;
+use graph::entity;
+use graph::prelude::Entity;
+use graph::schema::{EntityType, InputSchema};
+
+/// The schema of the benchmark subgraph
+pub const SCHEMA: &str = include_str!("schema.graphql");
+
+/// A query that is benchmarked
+pub struct BenchQuery {
+    pub name: &'static str,
+    pub text: &'static str,
+}
+
+/// The queries that are run by the benchmarks. They only reference
+/// entities that exist in datasets of any size
+pub const QUERIES: [BenchQuery; 4] = [
+    BenchQuery {
+        name: "find_by_id",
+        text: r#"{
+            musician(id: "m0") { id name instrument band { id name } }
+        }"#,
+    },
+    BenchQuery {
+        name: "filtered_collection",
+        text: r#"{
+            songs(first: 100, where: { plays_gt: "50000", released_gte: 1990 },
+                  orderBy: plays, orderDirection: desc) {
+                id title plays released
+            }
+        }"#,
+    },
+    BenchQuery {
+        name: "interface",
+        text: r#"{
+            performers(first: 100, orderBy: fans, orderDirection: desc) { id name fans }
+        }"#,
+    },
+    BenchQuery {
+        name: "nested_children",
+        text: r#"{
+            bands(first: 50, orderBy: founded) {
+                id name
+                members(first: 10, orderBy: name) { id name instrument }
+                songs(first: 20, orderBy: plays, orderDirection: desc) { id title plays }
+            }
+        }"#,
+    },
+];
+
+const INSTRUMENTS: [&str; 5] = ["guitar", "bass", "drums", "keyboard", "vocals"];
+
+/// Generate the benchmark dataset. For a given `size`, the dataset has
+/// `size` bands, `4 * size` musicians, and `10 * size` songs. The data is
+/// generated deterministically so that benchmark runs are comparable
+pub fn dataset(schema: &InputSchema, size: usize) -> Vec<(EntityType, Entity)> {
+    let band_type = schema.entity_type("Band").unwrap();
+    let musician_type = schema.entity_type("Musician").unwrap();
+    let song_type = schema.entity_type("Song").unwrap();
+
+    let size = size.max(1) as i32;
+    let mut entities = Vec::with_capacity(15 * size as usize);
+
+    for i in 0..size {
+        let band = entity! { schema =>
+            id: format!("b{}", i),
+            name: format!("Band {}", i),
+            fans: ((i as i64 * 7919) % 100_000) as i32,
+            founded: 1950 + i % 70,
+        };
+        entities.push((band_type.clone(), band));
+    }
+
+    for i in 0..4 * size {
+        let id = format!("m{}", i);
+        let name = format!("Musician {}", i);
+        let fans = ((i as i64 * 104_729) % 100_000) as i32;
+        let instrument = INSTRUMENTS[i as usize % INSTRUMENTS.len()];
+        // Every tenth musician is not in a band
+        let musician = if i % 10 == 9 {
+            entity! { schema => id: id, name: name, fans: fans, instrument: instrument }
+        } else {
+            entity! { schema =>
+                id: id,
+                name: name,
+                fans: fans,
+                instrument: instrument,
+                band: format!("b{}", i / 4),
+            }
+        };
+        entities.push((musician_type.clone(), musician));
+    }
+
+    for i in 0..10 * size {
+        let song = entity! { schema =>
+            id: format!("s{}", i),
+            title: format!("Song {}", i),
+            band: format!("b{}", i % size),
+            plays: BigInt::from((i as i64 * 15_485_863) % 1_000_000),
+            released: 1960 + i % 64,
+        };
+        entities.push((song_type.clone(), song));
+    }
+
+    entities
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use graph::blockchain::block_stream::FirehoseCursor;
+use graph::blockchain::mock::MockBlockchain;
+use graph::blockchain::BlockTime;
+use graph::components::store::EntityModification;
+use graph::data::query::QueryTarget;
+use graph::data::subgraph::schema::DeploymentCreate;
+use graph::env::ENV_VARS;
+use graph::prelude::{
+    anyhow::{anyhow, Error},
+    q,
+    web3::types::H256,
+    BlockNumber, BlockPtr, CheapClone, DeploymentHash, GraphQlRunner as _, Logger, MetricsRegistry,
+    NodeId, Query, StopwatchMetrics, SubgraphManifest, SubgraphName, SubgraphStore as _,
+    SubgraphVersionSwitchingMode,
+};
+use graph::schema::InputSchema;
+use graph::semver::Version;
+use graph_graphql::prelude::GraphQlRunner;
+use graph_store_postgres::{Store, SubgraphStore};
+
+use crate::manager::PanicSubscriptionManager;
+
+pub mod fixtures;
+
+use fixtures::QUERIES;
+
+/// The raw manifest we store for the benchmark deployment. The deployment
+/// is never indexed, and therefore does not need any data sources
+const RAW_MANIFEST: &str = "dataSources: []\n";
+
+/// How many entities to write per block when loading the dataset
+const ENTITIES_PER_BLOCK: usize = 10_000;
+
+fn deployment_hash(size: usize) -> DeploymentHash {
+    DeploymentHash::new(format!("graphmanBench{}", size)).unwrap()
+}
+
+/// Create a deployment with the benchmark schema and load the benchmark
+/// dataset of the given `size` into it. The deployment is placed like any
+/// other deployment, i.e., the deployment rules for `name` and `network`
+/// determine which shard it goes into
+pub async fn load(
+    store: Arc<SubgraphStore>,
+    registry: Arc<MetricsRegistry>,
+    logger: Logger,
+    node: NodeId,
+    name: String,
+    network: String,
+    size: usize,
+) -> Result<(), Error> {
+    let id = deployment_hash(size);
+    let name = SubgraphName::new(name.clone())
+        .map_err(|()| anyhow!("illegal subgraph name `{}`", name))?;
+    let schema = InputSchema::parse_latest(fixtures::SCHEMA, id.clone())?;
+
+    let manifest = SubgraphManifest::<MockBlockchain> {
+        id: id.clone(),
+        spec_version: Version::new(1, 0, 0),
+        features: BTreeSet::new(),
+        description: Some("graphman benchmark".to_string()),
+        repository: None,
+        schema: schema.clone(),
+        data_sources: vec![],
+        graft: None,
+        templates: vec![],
+        chain: PhantomData,
+        indexer_hints: None,
+    };
+    let deployment = DeploymentCreate::new(RAW_MANIFEST.to_string(), &manifest, None);
+    let locator = store.create_subgraph_deployment(
+        name,
+        &schema,
+        deployment,
+        node,
+        network,
+        SubgraphVersionSwitchingMode::Instant,
+    )?;
+    // There is nothing to index, make sure no index node tries
+    store.pause_subgraph(&locator)?;
+
+    let writable = store
+        .cheap_clone()
+        .writable(logger.clone(), locator.id, Arc::new(Vec::new()))
+        .await?;
+    writable.start_subgraph_deployment(&logger).await?;
+    let stopwatch = StopwatchMetrics::new(
+        logger,
+        locator.hash.clone(),
+        "bench",
+        registry,
+        writable.shard().to_string(),
+    );
+
+    let entities = fixtures::dataset(&schema, size);
+    let count = entities.len();
+    let start = Instant::now();
+    for (block, chunk) in entities.chunks(ENTITIES_PER_BLOCK).enumerate() {
+        let block = block as BlockNumber + 1;
+        let mods = chunk
+            .iter()
+            .map(|(entity_type, data)| {
+                EntityModification::insert(entity_type.key(data.id()), data.clone(), block)
+            })
+            .collect();
+        let ptr = BlockPtr::from((H256::from_low_u64_be(block as u64), block));
+        writable
+            .transact_block_operations(
+                ptr,
+                BlockTime::since_epoch(block as i64, 0),
+                FirehoseCursor::None,
+                mods,
+                &stopwatch,
+                vec![],
+                vec![],
+                vec![],
+                false,
+                false,
+            )
+            .await?;
+    }
+    writable.flush().await?;
+
+    println!(
+        "loaded {} entities into {} in shard {} in {}s",
+        count,
+        locator,
+        writable.shard(),
+        start.elapsed().as_secs()
+    );
+    Ok(())
+}
+
+/// Run the benchmark queries `iterations` times against the benchmark
+/// deployment for the dataset of the given `size` and print timings
+pub async fn run(
+    runner: Arc<GraphQlRunner<Store, PanicSubscriptionManager>>,
+    size: usize,
+    iterations: usize,
+) -> Result<(), Error> {
+    if ENV_VARS.graphql.query_cache_blocks > 0 || ENV_VARS.graphql.query_lfu_cache_shards > 0 {
+        println!(
+            "warning: the query cache is enabled; set GRAPH_QUERY_CACHE_BLOCKS=0 and \
+             GRAPH_QUERY_LFU_CACHE_SHARDS=0 to measure actual query performance"
+        );
+    }
+
+    let target = QueryTarget::Deployment(deployment_hash(size), Default::default());
+    let iterations = iterations.max(1);
+
+    println!(
+        "{:<24} {:>10} {:>10} {:>10} {:>10}",
+        "query", "min", "median", "p90", "max"
+    );
+    for query in &QUERIES {
+        let document = q::parse_query(query.text)?.into_static();
+        let mut times = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let request = Query::new(document.clone(), None, false);
+            let start = Instant::now();
+            let res = runner
+                .cheap_clone()
+                .run_query(request, target.clone())
+                .await;
+            times.push(start.elapsed());
+            if let Some(err) = res.errors().first().cloned() {
+                return Err(anyhow!("query `{}` failed: {}", query.name, err));
+            }
+        }
+        times.sort();
+
+        let at = |pct: usize| -> f64 {
+            let time: Duration = times[(times.len() - 1) * pct / 100];
+            time.as_secs_f64() * 1000.0
+        };
+        println!(
+            "{:<24} {:>8.2}ms {:>8.2}ms {:>8.2}ms {:>8.2}ms",
+            query.name,
+            at(0),
+            at(50),
+            at(90),
+            at(100)
+        );
+    }
+    Ok(())
+}
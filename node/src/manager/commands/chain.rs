@@ -6,6 +6,8 @@ use diesel::RunQueryDsl;
 use graph::blockchain::BlockPtr;
 use graph::cheap_clone::CheapClone;
 use graph::components::store::StoreError;
+use graph::components::store::SubgraphStore as _;
+use graph::env::ENV_VARS;
 use graph::prelude::BlockNumber;
 use graph::prelude::ChainStore as _;
 use graph::prelude::EthereumBlock;
@@ -21,8 +23,11 @@ use graph_store_postgres::BlockStore;
 use graph_store_postgres::ChainStatus;
 use graph_store_postgres::ChainStore;
 use graph_store_postgres::Shard;
+use graph_store_postgres::Store;
+use graph_store_postgres::SubgraphStore;
 use graph_store_postgres::{
-    command_support::catalog::block_store, connection_pool::ConnectionPool,
+    command_support::catalog::{block_store, Site},
+    connection_pool::ConnectionPool,
 };
 
 pub async fn list(primary: ConnectionPool, store: Arc<BlockStore>) -> Result<(), Error> {
@@ -154,6 +159,127 @@ pub fn remove(primary: ConnectionPool, store: Arc<BlockStore>, name: String) ->
     Ok(())
 }
 
+/// Handler kinds for which a deployment needs the data of every block
+const EVERY_BLOCK_HANDLERS: [&str; 3] = ["block", "block_filter_polling", "block_filter_call"];
+
+/// Return whether the deployment for `site` needs the data of every block
+/// and the oldest block that it still needs
+pub async fn needed_blocks(
+    store: &SubgraphStore,
+    site: Arc<Site>,
+) -> Result<(bool, BlockNumber), Error> {
+    let deployment = store.load_deployment(site.cheap_clone())?;
+    // If we don't know which handlers a deployment has, e.g., because
+    // it was deployed before we recorded features, we have to assume
+    // that it needs every block
+    let every_block = match store.subgraph_features(&site.deployment).await? {
+        Some(features) => features
+            .handler_kinds
+            .iter()
+            .any(|kind| EVERY_BLOCK_HANDLERS.contains(&kind.as_str())),
+        None => true,
+    };
+    // A deployment that has not started yet needs everything from its
+    // start block on; if we don't know that, we can't remove anything
+    let needed = if every_block {
+        deployment.earliest_block_number
+    } else {
+        deployment
+            .latest_block
+            .or(deployment.start_block)
+            .map(|ptr| ptr.number)
+            .unwrap_or(0)
+    };
+    Ok((every_block, needed))
+}
+
+/// Return the block before which blocks can be removed when `keep`
+/// blocks before the oldest block that is `needed` must be kept, or
+/// `None` if there are no such blocks. Blocks after the chain `head` are
+/// never needed
+pub fn prune_before(
+    head: BlockNumber,
+    needed: impl IntoIterator<Item = BlockNumber>,
+    keep: BlockNumber,
+) -> Option<BlockNumber> {
+    let oldest = needed.into_iter().fold(head, BlockNumber::min);
+    let before = oldest - keep;
+    (before > 1).then_some(before)
+}
+
+pub async fn prune(
+    store: Arc<Store>,
+    primary: ConnectionPool,
+    chain_name: String,
+    keep: BlockNumber,
+    dry_run: bool,
+) -> Result<(), Error> {
+    // Reorgs can reach back up to the reorg threshold, and ancestor walks
+    // need all the blocks in that range
+    if keep < ENV_VARS.reorg_threshold {
+        bail!(
+            "the number of blocks to keep must be at least the reorg threshold {}",
+            ENV_VARS.reorg_threshold
+        );
+    }
+
+    let chain_store = store
+        .block_store()
+        .chain_store(&chain_name)
+        .ok_or_else(|| anyhow!("unknown chain: {}", chain_name))?;
+    let head = chain_store
+        .chain_head_block(&chain_name)?
+        .ok_or_else(|| anyhow!("chain {} does not have a head block yet", chain_name))?;
+
+    let sites = {
+        let mut conn =
+            graph_store_postgres::command_support::catalog::Connection::new(primary.get()?);
+        conn.find_sites_for_network(&chain_name)?
+    };
+
+    let subgraph_store = store.subgraph_store();
+    let mut needed = Vec::with_capacity(sites.len());
+    if !sites.is_empty() {
+        println!(
+            "{:<8} | {:<46} | {:^14} | {:>10}",
+            "schema", "deployment", "block handlers", "needs from"
+        );
+        println!("{:-^8}-+-{:-^46}-+-{:-^14}-+-{:-^10}", "", "", "", "");
+    }
+    for site in sites {
+        let site = Arc::new(site);
+        let (every_block, needs) = needed_blocks(&subgraph_store, site.cheap_clone()).await?;
+        println!(
+            "{:<8} | {:<46} | {:^14} | {:>10}",
+            site.namespace,
+            site.deployment,
+            if every_block { "yes" } else { "no" },
+            needs
+        );
+        needed.push(needs);
+    }
+
+    println!();
+    println!("chain head:          {}", head);
+    println!(
+        "oldest needed block: {}",
+        needed.iter().copied().fold(head, BlockNumber::min)
+    );
+    let Some(before) = prune_before(head, needed, keep) else {
+        println!("there are no blocks that can be removed");
+        return Ok(());
+    };
+
+    let (blocks, calls) = chain_store.prune(before, dry_run)?;
+    let verb = if dry_run { "would remove" } else { "removed" };
+    println!(
+        "{} {} blocks and {} call cache entries before block {}",
+        verb, blocks, calls, before
+    );
+
+    Ok(())
+}
+
 pub fn change_block_cache_shard(
     primary_store: ConnectionPool,
     store: Arc<BlockStore>,
@@ -2,12 +2,13 @@ use diesel::{ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, RunQuery
 use std::{collections::HashMap, sync::Arc, time::SystemTime};
 
 use graph::{
-    components::store::{BlockStore as _, DeploymentId},
+    components::store::{BlockStore as _, DeploymentId, DeploymentLocator},
     data::query::QueryTarget,
     prelude::{
         anyhow::{anyhow, bail, Error},
         chrono::{DateTime, Duration, SecondsFormat, Utc},
-        BlockPtr, ChainStore, DeploymentHash, NodeId, QueryStoreManager,
+        BlockNumber, BlockPtr, ChainStore, DeploymentHash, NodeId, QueryStoreManager,
+        SubgraphStore as _,
     },
 };
 use graph_store_postgres::{
@@ -95,7 +96,6 @@ pub async fn create(
     activate: bool,
     replace: bool,
 ) -> Result<(), Error> {
-    let block_offset = block_offset as i32;
     let on_sync = match (activate, replace) {
         (true, true) => bail!("--activate and --replace can't both be specified"),
         (true, false) => OnSync::Activate,
@@ -105,6 +105,24 @@ pub async fn create(
 
     let subgraph_store = store.subgraph_store();
     let src = src.locate_unique(&primary)?;
+    let base_ptr = base_ptr(&store, &src, block_offset).await?;
+    let shard = check_shard(shard, shards)?;
+    let node = NodeId::new(node.clone()).map_err(|()| anyhow!("invalid node id `{}`", node))?;
+
+    let dst = subgraph_store.copy_deployment(&src, shard, node, base_ptr, on_sync)?;
+
+    println!("created deployment {} as copy of {}", dst, src);
+    Ok(())
+}
+
+/// Find the block that is `block_offset` blocks behind the subgraph head
+/// of `src` so that we can copy `src` as of that block
+async fn base_ptr(
+    store: &Store,
+    src: &DeploymentLocator,
+    block_offset: u32,
+) -> Result<BlockPtr, Error> {
+    let block_offset = block_offset as i32;
     let query_store = store
         .query_store(
             QueryTarget::Deployment(src.hash.clone(), Default::default()),
@@ -137,20 +155,85 @@ pub async fn create(
             src_number
         ),
     };
-    let base_ptr = BlockPtr::new(hash, src_number);
+    Ok(BlockPtr::new(hash, src_number))
+}
 
+pub(crate) fn check_shard(shard: String, shards: Vec<String>) -> Result<Shard, Error> {
     if !shards.contains(&shard) {
         bail!(
             "unknown shard {shard}, only shards {} are configured",
             shards.join(", ")
         )
     }
-    let shard = Shard::new(shard)?;
-    let node = NodeId::new(node.clone()).map_err(|()| anyhow!("invalid node id `{}`", node))?;
+    Ok(Shard::new(shard)?)
+}
 
-    let dst = subgraph_store.copy_deployment(&src, shard, node, base_ptr, on_sync)?;
+struct MoveProgress;
 
-    println!("created deployment {} as copy of {}", dst, src);
+impl graph_store_postgres::MoveReporter for MoveProgress {
+    fn copy_created(&mut self, src: &DeploymentLocator, dst: &DeploymentLocator) {
+        println!("created deployment {} as copy of {}", dst, src);
+    }
+
+    fn copy_progress(&mut self, copied: i64, total: i64) {
+        let pct = if total > 0 {
+            copied as f64 * 100.0 / total as f64
+        } else {
+            100.0
+        };
+        println!("  copied {}/{} rows ({:.2}%)", copied, total, pct);
+    }
+
+    fn copy_finished(&mut self) {
+        println!("finished copying data");
+    }
+
+    fn catching_up(&mut self, src_head: BlockNumber, dst_head: Option<BlockNumber>) {
+        match dst_head {
+            Some(dst_head) => println!(
+                "  copy is at block {}, {} blocks behind block {}",
+                dst_head,
+                (src_head - dst_head).max(0),
+                src_head
+            ),
+            None => println!("  copy has not indexed any blocks yet"),
+        }
+    }
+
+    fn source_paused(&mut self, src: &DeploymentLocator) {
+        println!("paused {} so that the copy can catch up", src);
+    }
+
+    fn activated(&mut self, dst: &DeploymentLocator) {
+        println!("activated {}", dst);
+    }
+
+    fn source_removed(&mut self, src: &DeploymentLocator) {
+        println!("removed {}", src);
+    }
+}
+
+/// Move `deployment` to shard `to_shard` by copying it there, switching
+/// queries and indexing over to the copy, and removing the original
+pub async fn move_deployment(
+    store: Arc<Store>,
+    primary: ConnectionPool,
+    deployment: DeploymentSearch,
+    to_shard: String,
+    shards: Vec<String>,
+    block_offset: u32,
+    max_lag: BlockNumber,
+    timeout: std::time::Duration,
+) -> Result<(), Error> {
+    let src = deployment.locate_unique(&primary)?;
+    let base_ptr = base_ptr(&store, &src, block_offset).await?;
+    let shard = check_shard(to_shard, shards)?;
+
+    let dst = store
+        .subgraph_store()
+        .move_deployment(&src, shard, base_ptr, max_lag, timeout, &mut MoveProgress)
+        .await?;
+    println!("moved {} to {}", src, dst);
     Ok(())
 }
 
@@ -172,6 +255,83 @@ pub fn activate(store: Arc<SubgraphStore>, deployment: String, shard: String) ->
     Ok(())
 }
 
+/// The number of blocks a standby whose latest block is `standby_head` is
+/// behind the active copy whose latest block is `active_head`. A standby
+/// that has not indexed any blocks yet is behind by all blocks the active
+/// copy has indexed, and a standby that is ahead of the active copy does
+/// not lag at all
+fn standby_lag(active_head: Option<BlockNumber>, standby_head: Option<BlockNumber>) -> BlockNumber {
+    match (active_head, standby_head) {
+        (Some(active_head), Some(standby_head)) => (active_head - standby_head).max(0),
+        (Some(active_head), None) => active_head + 1,
+        (None, _) => 0,
+    }
+}
+
+/// Promote a warm standby copy of a deployment. The standby is a copy
+/// that is assigned to its own node and keeps indexing next to the active
+/// copy. Promoting it routes queries to the standby and pauses the
+/// previously active copy so that only the standby keeps indexing
+pub fn promote(
+    store: Arc<SubgraphStore>,
+    deployment: String,
+    shard: String,
+    max_lag: BlockNumber,
+    force: bool,
+) -> Result<(), Error> {
+    let shard = Shard::new(shard)?;
+    let hash =
+        DeploymentHash::new(deployment).map_err(|s| anyhow!("illegal deployment hash `{}`", s))?;
+    let standby = store
+        .locate_in_shard(&hash, shard.clone())?
+        .ok_or_else(|| anyhow!("could not find a copy for {} in shard {}", hash, shard))?;
+    let active = store
+        .active_locator(&hash)?
+        .ok_or_else(|| anyhow!("deployment {} does not have an active copy", hash))?;
+    if active.id == standby.id {
+        bail!("the copy of {} in shard {} is already active", hash, shard);
+    }
+
+    let standby_paused = match store.assignment_status(&standby)? {
+        Some((_, paused)) => paused,
+        None => bail!(
+            "the standby {} is not assigned to any node and is not indexing; \
+             assign it with `graphman reassign` first",
+            standby
+        ),
+    };
+
+    let head = |loc: &DeploymentLocator| {
+        store
+            .status_for_id(loc.id)
+            .chains
+            .first()
+            .and_then(|chain| chain.latest_block.as_ref())
+            .map(|block| block.number())
+    };
+    let lag = standby_lag(head(&active), head(&standby));
+    if lag > max_lag && !force {
+        bail!(
+            "the standby {} is {} blocks behind the active copy {}; wait for it to catch up or use --force",
+            standby,
+            lag,
+            active
+        );
+    }
+
+    store.activate(&standby)?;
+    println!("activated standby {} ({} blocks behind)", standby, lag);
+    if standby_paused {
+        store.resume_subgraph(&standby)?;
+        println!("resumed standby {}", standby);
+    }
+    if let Some((node, false)) = store.assignment_status(&active)? {
+        store.pause_subgraph(&active)?;
+        println!("paused previously active copy {} on {}", active, node);
+    }
+    Ok(())
+}
+
 pub fn list(pools: HashMap<Shard, ConnectionPool>) -> Result<(), Error> {
     use catalog::active_copies as ac;
     use catalog::deployment_schemas as ds;
@@ -230,6 +390,21 @@ pub fn list(pools: HashMap<Shard, ConnectionPool>) -> Result<(), Error> {
     Ok(())
 }
 
+pub fn convert_immutable(
+    store: Arc<SubgraphStore>,
+    primary: ConnectionPool,
+    dst: &DeploymentSearch,
+) -> Result<(), Error> {
+    let dst = dst.locate_unique(&primary)?;
+    store.convert_immutable(&dst)?;
+    println!(
+        "copying into {} will only copy the current versions of entities that become immutable",
+        dst
+    );
+    println!("restart the deployment to continue copying");
+    Ok(())
+}
+
 pub fn status(pools: HashMap<Shard, ConnectionPool>, dst: &DeploymentSearch) -> Result<(), Error> {
     use catalog::active_copies as ac;
     use catalog::deployment_schemas as ds;
@@ -360,3 +535,19 @@ pub fn status(pools: HashMap<Shard, ConnectionPool>, dst: &DeploymentSearch) ->
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::standby_lag;
+
+    #[test]
+    fn lag_of_standby() {
+        assert_eq!(0, standby_lag(Some(100), Some(100)));
+        assert_eq!(10, standby_lag(Some(100), Some(90)));
+        // A standby that is ahead of the active copy does not lag
+        assert_eq!(0, standby_lag(Some(90), Some(100)));
+        assert_eq!(101, standby_lag(Some(100), None));
+        assert_eq!(0, standby_lag(None, Some(5)));
+        assert_eq!(0, standby_lag(None, None));
+    }
+}
@@ -43,7 +43,7 @@ async fn send_create_request(name: &str, url: &str) -> Result<()> {
 }
 
 // Function to send subgraph_deploy request
-async fn send_deploy_request(name: &str, deployment: &str, url: &str) -> Result<()> {
+pub(crate) async fn send_deploy_request(name: &str, deployment: &str, url: &str) -> Result<()> {
     // Construct the JSON payload for subgraph_deploy
     let deploy_payload = json!({
         "jsonrpc": "2.0",
@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::sync::Arc;
+use std::time::Instant;
+
+use graph::components::store::ExportFormat;
+use graph::prelude::{anyhow, BlockNumber, BLOCK_NUMBER_MAX};
+use graph_store_postgres::connection_pool::ConnectionPool;
+use graph_store_postgres::SubgraphStore;
+
+use crate::manager::deployment::DeploymentSearch;
+
+pub fn run(
+    store: Arc<SubgraphStore>,
+    primary: ConnectionPool,
+    search: DeploymentSearch,
+    entity: &str,
+    block: Option<BlockNumber>,
+    format: ExportFormat,
+    output: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary)?;
+    let block = block.unwrap_or(BLOCK_NUMBER_MAX);
+
+    let start = Instant::now();
+    let count = match output {
+        Some(path) => {
+            let mut file = BufWriter::new(File::create(path)?);
+            store.export_entities(&locator, entity, block, format, &mut file)?
+        }
+        None => {
+            let mut stdout = BufWriter::new(io::stdout().lock());
+            store.export_entities(&locator, entity, block, format, &mut stdout)?
+        }
+    };
+    eprintln!(
+        "exported {} {} entities from {} in {}s",
+        count,
+        entity,
+        locator,
+        start.elapsed().as_secs()
+    );
+    Ok(())
+}
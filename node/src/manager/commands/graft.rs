@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use graph::components::store::StatusStore;
+use graph::data::subgraph::schema::SubgraphHealth;
+use graph::data::subgraph::status;
+use graph::env::ENV_VARS;
+use graph::prelude::{
+    anyhow::{anyhow, bail, Error},
+    serde_yaml::{self, Mapping, Value},
+    BlockNumber, DeploymentHash,
+};
+use graph::slog::Logger;
+use graph_store_postgres::{connection_pool::ConnectionPool, Store};
+
+use crate::chain::create_ipfs_clients;
+use crate::manager::commands::deploy;
+use crate::manager::deployment::DeploymentSearch;
+
+/// Rewrite the YAML `manifest` so that it grafts onto `base` at block
+/// `block`, and make sure that it declares the `grafting` feature
+fn graft_manifest(
+    manifest: &[u8],
+    base: &DeploymentHash,
+    block: BlockNumber,
+) -> Result<String, Error> {
+    let mut manifest: Mapping = serde_yaml::from_slice(manifest)
+        .map_err(|e| anyhow!("the manifest is not a valid YAML mapping: {}", e))?;
+
+    let mut graft = Mapping::new();
+    graft.insert("base".into(), base.to_string().into());
+    graft.insert("block".into(), Value::from(block));
+    manifest.insert("graft".into(), Value::Mapping(graft));
+
+    let features = manifest
+        .entry("features".into())
+        .or_insert_with(|| Value::Sequence(vec![]));
+    match features {
+        Value::Sequence(features) => {
+            if !features
+                .iter()
+                .any(|feature| feature.as_str() == Some("grafting"))
+            {
+                features.push("grafting".into());
+            }
+        }
+        _ => bail!("the `features` of the manifest are not a list"),
+    }
+
+    Ok(serde_yaml::to_string(&manifest)?)
+}
+
+/// Deploy a new deployment to `name` that is grafted onto the failed
+/// deployment `search` at the last block before the block at which it
+/// failed. The new deployment uses the manifest `fixed` with the graft
+/// added, or the manifest of the failed deployment if `fixed` is not
+/// given. Copying the data of the failed deployment starts once a node
+/// picks up the new deployment
+pub async fn graft_from_failure(
+    logger: &Logger,
+    store: Arc<Store>,
+    primary: ConnectionPool,
+    ipfs_url: Vec<String>,
+    search: DeploymentSearch,
+    fixed: Option<String>,
+    name: String,
+    url: String,
+) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    let info = store
+        .status(status::Filter::DeploymentIds(vec![locator.id]))?
+        .pop()
+        .ok_or_else(|| anyhow!("deployment {} not found", locator))?;
+    match &info.fatal_error {
+        Some(error) if info.health == SubgraphHealth::Failed && error.deterministic => {}
+        _ => bail!(
+            "deployment {} has not failed with a deterministic error; \
+             only such deployments can be grafted from their failure",
+            locator
+        ),
+    }
+    let last_healthy = info
+        .chains
+        .first()
+        .and_then(|chain| chain.last_healthy_block.as_ref())
+        .map(|block| block.number())
+        .ok_or_else(|| {
+            anyhow!(
+                "deployment {} does not know its last healthy block, probably because it \
+                 failed before graph-node started recording it; graft onto it manually",
+                locator
+            )
+        })?;
+
+    let ipfs = create_ipfs_clients(logger, &ipfs_url)
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no IPFS node configured"))?;
+
+    let source = fixed.unwrap_or_else(|| locator.hash.to_string());
+    let manifest = ipfs
+        .cat_all(
+            &source,
+            Some(ENV_VARS.mappings.ipfs_timeout),
+            ENV_VARS.mappings.max_ipfs_file_bytes,
+        )
+        .await
+        .map_err(|e| anyhow!("failed to get manifest {} from IPFS: {}", source, e))?;
+    let manifest = graft_manifest(&manifest, &locator.hash, last_healthy)?;
+    let grafted = ipfs
+        .add(manifest.into_bytes())
+        .await
+        .map_err(|e| anyhow!("failed to add the grafted manifest to IPFS: {}", e))?
+        .hash;
+
+    println!(
+        "Deploying {} to `{}`, grafted onto {} at block {}",
+        grafted, name, locator, last_healthy
+    );
+    deploy::send_deploy_request(&name, &grafted, &url).await?;
+    println!(
+        "Deployed {}; it copies the data of {} once a node starts indexing it",
+        grafted, locator
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::{anyhow::Error, serde_yaml, DeploymentHash};
+
+    use super::graft_manifest;
+
+    #[test]
+    fn grafted_manifest() {
+        let base = DeploymentHash::new("QmBase").unwrap();
+        let graft = |manifest: &str| {
+            let manifest = graft_manifest(manifest.as_bytes(), &base, 41)?;
+            Ok::<_, Error>(serde_yaml::from_str::<serde_yaml::Value>(&manifest)?)
+        };
+
+        let manifest = graft("specVersion: 0.0.4\nschema:\n  file: ./schema.graphql\n").unwrap();
+        assert_eq!("0.0.4", manifest["specVersion"].as_str().unwrap());
+        assert_eq!("QmBase", manifest["graft"]["base"].as_str().unwrap());
+        assert_eq!(41, manifest["graft"]["block"].as_i64().unwrap());
+        assert_eq!(
+            vec!["grafting"],
+            serde_yaml::from_value::<Vec<String>>(manifest["features"].clone()).unwrap()
+        );
+
+        // An existing graft is replaced, and `grafting` is only declared once
+        let manifest = graft(
+            "features:\n  - fullTextSearch\n  - grafting\n\
+             graft:\n  base: QmOther\n  block: 7\n",
+        )
+        .unwrap();
+        assert_eq!("QmBase", manifest["graft"]["base"].as_str().unwrap());
+        assert_eq!(41, manifest["graft"]["block"].as_i64().unwrap());
+        assert_eq!(
+            vec!["fullTextSearch", "grafting"],
+            serde_yaml::from_value::<Vec<String>>(manifest["features"].clone()).unwrap()
+        );
+
+        assert!(graft("features: grafting\n").is_err());
+        assert!(graft("- not a mapping\n").is_err());
+    }
+}
@@ -215,3 +215,45 @@ pub async fn drop(
     println!("Dropped index {index_name}");
     Ok(())
 }
+
+/// Print the indexes that the query patterns recorded for the deployment
+/// suggest, and create them if `create` is set
+pub async fn suggest(
+    store: Arc<SubgraphStore>,
+    pool: ConnectionPool,
+    search: DeploymentSearch,
+    min_count: i64,
+    create: bool,
+) -> Result<(), anyhow::Error> {
+    let deployment_locator = search.locate_unique(&pool)?;
+    let suggestions = store
+        .suggest_indexes(&deployment_locator, min_count)
+        .await?;
+    if suggestions.is_empty() {
+        println!("No indexes to suggest for {deployment_locator}");
+        return Ok(());
+    }
+
+    println!("{:>10}  index", "queries");
+    for suggestion in &suggestions {
+        println!("{:>10}  {}", suggestion.count, suggestion);
+    }
+    if !create {
+        return Ok(());
+    }
+
+    for suggestion in suggestions {
+        println!("Creating index on {suggestion}. Please wait.");
+        store
+            .create_manual_index(
+                &deployment_locator,
+                &suggestion.entity,
+                suggestion.fields,
+                suggestion.method,
+                None,
+            )
+            .await?;
+    }
+    println!("Index creation completed.");
+    Ok(())
+}
@@ -1,4 +1,7 @@
+pub mod archive;
 pub mod assign;
+pub mod aux_dataset;
+pub mod bench;
 pub mod chain;
 pub mod check_blocks;
 pub mod config;
@@ -7,14 +10,19 @@ pub mod create;
 pub mod database;
 pub mod deploy;
 pub mod drop;
+pub mod export;
+pub mod graft;
 pub mod index;
 pub mod info;
 pub mod listen;
 pub mod prune;
 pub mod query;
 pub mod remove;
+pub mod replay;
 pub mod rewind;
+pub mod rollup;
 pub mod run;
+pub mod skipped;
 pub mod stats;
 pub mod txn_speed;
 pub mod unused_deployments;
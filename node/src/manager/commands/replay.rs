@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use graph::components::store::{diff_replay, BlockStore as _, DeploymentLocator, ReplayChange};
+use graph::data::query::QueryTarget;
+use graph::data::subgraph::schema::SubgraphHealth;
+use graph::prelude::{
+    anyhow::{anyhow, bail, Error},
+    tokio, BlockNumber, BlockPtr, ChainStore as _, NodeId, QueryStoreManager,
+    SubgraphAssignmentProvider as _, SubgraphStore as _,
+};
+use graph::slog::Logger;
+
+use crate::config::Config;
+use crate::manager::commands::copy::check_shard;
+use crate::manager::commands::run::Indexer;
+use crate::manager::deployment::DeploymentSearch;
+use crate::store_builder::StoreBuilder;
+use crate::MetricsContext;
+use graph_store_postgres::command_support::OnSync;
+use graph_store_postgres::Store;
+
+/// Find the block pointer for block `number` of the chain that `src`
+/// indexes
+async fn block_ptr(
+    store: &Store,
+    src: &DeploymentLocator,
+    number: BlockNumber,
+) -> Result<BlockPtr, Error> {
+    let query_store = store
+        .query_store(
+            QueryTarget::Deployment(src.hash.clone(), Default::default()),
+            true,
+        )
+        .await?;
+    let network = query_store.network_name();
+
+    let src_ptr = query_store
+        .block_ptr()
+        .await?
+        .ok_or_else(|| anyhow!("subgraph {} has not indexed any blocks yet", src))?;
+    if src_ptr.number < number {
+        bail!(
+            "subgraph {} has only indexed up to block {}, but the replay needs block {}",
+            src,
+            src_ptr.number,
+            number
+        );
+    }
+
+    let chain_store = store
+        .block_store()
+        .chain_store(network)
+        .ok_or_else(|| anyhow!("could not find chain store for network {}", network))?;
+    let mut hashes = chain_store.block_hashes_by_block_number(number)?;
+    match hashes.len() {
+        0 => bail!(
+            "could not find a block with number {} in our cache; the replay has to start \
+             at a block whose parent is in the block cache",
+            number
+        ),
+        1 => Ok(BlockPtr::new(hashes.pop().unwrap(), number)),
+        n => bail!(
+            "the cache contains {} hashes for block number {}",
+            n,
+            number
+        ),
+    }
+}
+
+fn print_change(label: &str, change: &ReplayChange) {
+    match change {
+        ReplayChange::Unchanged => println!("    {label:<9} unchanged"),
+        ReplayChange::Removed => println!("    {label:<9} removed"),
+        ReplayChange::Set(entity) => println!("    {label:<9} {:?}", entity),
+    }
+}
+
+/// Replay the blocks `[from, to]` of the deployment `search` into a scratch
+/// copy of it in `shard` and print how the entity changes of the replay
+/// differ from those that the deployment made in these blocks. The scratch
+/// copy starts as a copy of the deployment as of block `from - 1` and is
+/// never activated, so queries keep going to the deployment. Unless `keep`
+/// is set, the scratch copy is removed again once the diff is printed
+pub async fn run(
+    logger: Logger,
+    store_builder: StoreBuilder,
+    ipfs_url: Vec<String>,
+    arweave_url: String,
+    config: Config,
+    metrics_ctx: MetricsContext,
+    node_id: NodeId,
+    search: DeploymentSearch,
+    shard: String,
+    from: BlockNumber,
+    to: BlockNumber,
+    keep: bool,
+) -> Result<(), Error> {
+    if from < 1 || to < from {
+        bail!("invalid block range [{}, {}]", from, to);
+    }
+    let shards: Vec<_> = config.stores.keys().cloned().collect();
+    let shard = check_shard(shard, shards)?;
+
+    let primary = store_builder.primary_pool();
+    let src = search.locate_unique(&primary)?;
+
+    let indexer = Indexer::new(
+        &logger,
+        store_builder,
+        &ipfs_url,
+        arweave_url,
+        &config,
+        &metrics_ctx,
+        &node_id,
+    )
+    .await?;
+    let subgraph_store = indexer.subgraph_store.clone();
+
+    let in_shard = subgraph_store.locate_in_shard(&src.hash, shard.clone())?;
+    if in_shard.is_some() {
+        bail!(
+            "shard {} already contains a copy of {}; the replay must go into a shard \
+             that does not have one",
+            shard,
+            src
+        );
+    }
+
+    let base_ptr = block_ptr(&indexer.store, &src, from - 1).await?;
+    let replay =
+        subgraph_store.copy_deployment(&src, shard, node_id.clone(), base_ptr, OnSync::None)?;
+    println!(
+        "replaying blocks [{}, {}] of {} in scratch deployment {}",
+        from, to, src, replay
+    );
+
+    indexer
+        .provider
+        .start(replay.clone(), Some(to))
+        .await
+        .map_err(|e| anyhow!("failed to start replay: {}", e))?;
+
+    // Wait for the replay to copy the data of `src` and to process the
+    // blocks up to `to`
+    let result = loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let status = subgraph_store.status_for_id(replay.id);
+        if status.health == SubgraphHealth::Failed {
+            let msg = status
+                .fatal_error
+                .map(|e| e.message)
+                .unwrap_or_else(|| "unknown error".to_string());
+            break Err(anyhow!("the replay failed: {}", msg));
+        }
+        let number = status
+            .chains
+            .into_iter()
+            .next()
+            .and_then(|chain| chain.latest_block)
+            .map(|block| block.to_ptr().number);
+        if number.map_or(false, |number| number >= to) {
+            break Ok(());
+        }
+    };
+    indexer.provider.stop(replay.clone()).await?;
+
+    if result.is_ok() {
+        let mut count = 0;
+        let original = subgraph_store
+            .deployment_entity_changes(&src, from, to)
+            .await?;
+        let replayed = subgraph_store
+            .deployment_entity_changes(&replay, from, to)
+            .await?;
+        for ((block, original), (_, replayed)) in original.into_iter().zip(replayed) {
+            for diff in diff_replay(block, original, replayed) {
+                count += 1;
+                println!(
+                    "block {} {}[{}]",
+                    diff.block, diff.key.entity_type, diff.key.entity_id
+                );
+                print_change("original:", &diff.original);
+                print_change("replay:", &diff.replay);
+            }
+        }
+        println!(
+            "found {} differences between {} and the replay in blocks [{}, {}]",
+            count, src, from, to
+        );
+    }
+
+    subgraph_store.unassign_subgraph(&replay)?;
+    if keep {
+        println!("kept scratch deployment {}", replay);
+    } else {
+        subgraph_store.remove_deployment(replay.id.into())?;
+        println!("removed scratch deployment {}", replay);
+    }
+
+    result
+}
@@ -0,0 +1,84 @@
+use std::io::{self, Write as _};
+use std::sync::Arc;
+use std::time::Instant;
+
+use graph::components::store::RollupBackfillReporter;
+use graph::prelude::anyhow;
+use graph_store_postgres::connection_pool::ConnectionPool;
+use graph_store_postgres::SubgraphStore;
+
+use crate::manager::commands::stats::abbreviate_table_name;
+use crate::manager::deployment::DeploymentSearch;
+
+struct Progress {
+    table_start: Instant,
+    buckets: usize,
+}
+
+impl Progress {
+    fn new() -> Self {
+        Self {
+            table_start: Instant::now(),
+            buckets: 0,
+        }
+    }
+}
+
+impl RollupBackfillReporter for Progress {
+    fn start_table(&mut self, table: &str, buckets: usize) {
+        self.table_start = Instant::now();
+        self.buckets = buckets;
+        println!(
+            "{:<30} | {:>8} buckets to check",
+            abbreviate_table_name(table, 30),
+            buckets
+        );
+    }
+
+    fn backfill_batch(&mut self, table: &str, done: usize, filled: usize) {
+        print!(
+            "\r{:<30} | {:>8} / {:>8} checked, {:>8} filled",
+            abbreviate_table_name(table, 30),
+            done,
+            self.buckets,
+            filled
+        );
+        io::stdout().flush().ok();
+    }
+
+    fn finish_table(&mut self, table: &str, filled: usize) {
+        if self.buckets > 0 {
+            println!();
+        }
+        println!(
+            "{:<30} | {:>8} buckets filled in {}s",
+            abbreviate_table_name(table, 30),
+            filled,
+            self.table_start.elapsed().as_secs()
+        );
+    }
+}
+
+/// Fill the missing buckets of all aggregations of a deployment from the
+/// data in their timeseries
+pub async fn backfill(
+    store: Arc<SubgraphStore>,
+    primary: ConnectionPool,
+    search: DeploymentSearch,
+    batch_size: usize,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    println!("backfill aggregations for {locator}");
+    let start = Instant::now();
+    let filled = store
+        .rollup_backfill(&locator, batch_size, Box::new(Progress::new()))
+        .await?;
+    println!(
+        "\nfilled {} buckets for {} in {}s",
+        filled,
+        locator,
+        start.elapsed().as_secs()
+    );
+    Ok(())
+}
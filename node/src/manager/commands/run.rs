@@ -9,6 +9,7 @@ use crate::network_setup::Networks;
 use crate::store_builder::StoreBuilder;
 use crate::MetricsContext;
 use graph::anyhow::bail;
+use graph::blockchain::BlockchainMap;
 use graph::cheap_clone::CheapClone;
 use graph::components::adapter::IdentValidator;
 use graph::components::link_resolver::{ArweaveClient, FileSizeLimit};
@@ -22,11 +23,12 @@ use graph::prelude::{
     SubgraphStore, SubgraphVersionSwitchingMode, ENV_VARS,
 };
 use graph::slog::{debug, info, Logger};
-use graph_core::polling_monitor::{arweave_service, ipfs_service};
+use graph_core::polling_monitor::{arweave_service, https_service, ipfs_service, IpfsDiskCache};
 use graph_core::{
     SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider, SubgraphInstanceManager,
     SubgraphRegistrar as IpfsSubgraphRegistrar,
 };
+use graph_store_postgres::{Store, SubgraphStore as SubgraphStorePg};
 
 fn locate(store: &dyn SubgraphStore, hash: &str) -> Result<DeploymentLocator, anyhow::Error> {
     let mut locators = store.locators(hash)?;
@@ -37,6 +39,140 @@ fn locate(store: &dyn SubgraphStore, hash: &str) -> Result<DeploymentLocator, an
     }
 }
 
+/// Everything needed to index subgraphs inside of `graphman`
+pub(crate) struct Indexer {
+    pub store: Arc<Store>,
+    pub subgraph_store: Arc<SubgraphStorePg>,
+    pub blockchain_map: Arc<BlockchainMap>,
+    pub link_resolver: Arc<IpfsResolver>,
+    pub logger_factory: LoggerFactory,
+    pub provider: Arc<IpfsSubgraphAssignmentProvider<SubgraphInstanceManager<SubgraphStorePg>>>,
+}
+
+impl Indexer {
+    pub async fn new(
+        logger: &Logger,
+        store_builder: StoreBuilder,
+        ipfs_url: &[String],
+        arweave_url: String,
+        config: &Config,
+        metrics_ctx: &MetricsContext,
+        node_id: &NodeId,
+    ) -> Result<Self, anyhow::Error> {
+        let env_vars = Arc::new(EnvVars::from_env().unwrap());
+        let metrics_registry = metrics_ctx.registry.clone();
+        let logger_factory = LoggerFactory::new(logger.clone(), None, metrics_ctx.registry.clone());
+
+        // FIXME: Hard-coded IPFS config, take it from config file instead?
+        let ipfs_clients: Vec<_> = create_ipfs_clients(logger, ipfs_url);
+        let ipfs_cache = env_vars
+            .mappings
+            .ipfs_cache_location
+            .as_ref()
+            .map(IpfsDiskCache::new)
+            .transpose()?;
+        let ipfs_service = ipfs_service(
+            ipfs_clients.clone(),
+            env_vars.mappings.max_ipfs_file_bytes,
+            env_vars.mappings.ipfs_timeout,
+            env_vars.mappings.ipfs_request_limit,
+            ipfs_cache,
+        );
+        let arweave_resolver = Arc::new(ArweaveClient::new(
+            logger.cheap_clone(),
+            arweave_url.parse().expect("invalid arweave url"),
+        ));
+        let arweave_service = arweave_service(
+            arweave_resolver.cheap_clone(),
+            env_vars.mappings.ipfs_request_limit,
+            match env_vars.mappings.max_ipfs_file_bytes {
+                0 => FileSizeLimit::Unlimited,
+                n => FileSizeLimit::MaxBytes(n as u64),
+            },
+        );
+        let https_service = https_service(
+            env_vars.mappings.max_ipfs_file_bytes,
+            env_vars.mappings.ipfs_timeout,
+            env_vars.mappings.ipfs_request_limit,
+        );
+
+        let endpoint_metrics = Arc::new(EndpointMetrics::new(
+            logger.clone(),
+            &config.chains.providers(),
+            metrics_registry.cheap_clone(),
+        ));
+
+        // Convert the clients into a link resolver. Since we want to get past
+        // possible temporary DNS failures, make the resolver retry
+        let link_resolver = Arc::new(IpfsResolver::new(ipfs_clients, env_vars.cheap_clone()));
+
+        let chain_head_update_listener = store_builder.chain_head_update_listener();
+        let network_store = store_builder.network_store(config.chain_ids());
+        let block_store = network_store.block_store();
+        let ident_validator: Arc<dyn IdentValidator> = network_store.block_store();
+        let networks = Networks::from_config(
+            logger.cheap_clone(),
+            config,
+            metrics_registry.cheap_clone(),
+            endpoint_metrics,
+            ident_validator,
+        )
+        .await
+        .expect("unable to parse network configuration");
+
+        let subgraph_store = network_store.subgraph_store();
+
+        let blockchain_map = Arc::new(
+            networks
+                .blockchain_map(
+                    &env_vars,
+                    node_id,
+                    logger,
+                    block_store,
+                    &logger_factory,
+                    metrics_registry.cheap_clone(),
+                    chain_head_update_listener,
+                )
+                .await,
+        );
+
+        let static_filters = ENV_VARS.experimental_static_filters;
+
+        let sg_metrics = Arc::new(SubgraphCountMetric::new(metrics_registry.clone()));
+
+        let subgraph_instance_manager = SubgraphInstanceManager::new(
+            &logger_factory,
+            env_vars.cheap_clone(),
+            subgraph_store.clone(),
+            blockchain_map.clone(),
+            sg_metrics.cheap_clone(),
+            metrics_registry.clone(),
+            link_resolver.cheap_clone(),
+            ipfs_service,
+            arweave_service,
+            https_service,
+            static_filters,
+        );
+
+        // Create IPFS-based subgraph provider
+        let provider = Arc::new(IpfsSubgraphAssignmentProvider::new(
+            &logger_factory,
+            link_resolver.cheap_clone(),
+            subgraph_instance_manager,
+            sg_metrics,
+        ));
+
+        Ok(Self {
+            store: network_store,
+            subgraph_store,
+            blockchain_map,
+            link_resolver,
+            logger_factory,
+            provider,
+        })
+    }
+}
+
 pub async fn run(
     logger: Logger,
     store_builder: StoreBuilder,
@@ -54,96 +190,23 @@ pub async fn run(
         subgraph, stop_block
     );
 
-    let env_vars = Arc::new(EnvVars::from_env().unwrap());
-    let metrics_registry = metrics_ctx.registry.clone();
-    let logger_factory = LoggerFactory::new(logger.clone(), None, metrics_ctx.registry.clone());
-
-    // FIXME: Hard-coded IPFS config, take it from config file instead?
-    let ipfs_clients: Vec<_> = create_ipfs_clients(&logger, &ipfs_url);
-    let ipfs_client = ipfs_clients.first().cloned().expect("Missing IPFS client");
-    let ipfs_service = ipfs_service(
-        ipfs_client,
-        env_vars.mappings.max_ipfs_file_bytes,
-        env_vars.mappings.ipfs_timeout,
-        env_vars.mappings.ipfs_request_limit,
-    );
-    let arweave_resolver = Arc::new(ArweaveClient::new(
-        logger.cheap_clone(),
-        arweave_url.parse().expect("invalid arweave url"),
-    ));
-    let arweave_service = arweave_service(
-        arweave_resolver.cheap_clone(),
-        env_vars.mappings.ipfs_request_limit,
-        match env_vars.mappings.max_ipfs_file_bytes {
-            0 => FileSizeLimit::Unlimited,
-            n => FileSizeLimit::MaxBytes(n as u64),
-        },
-    );
-
-    let endpoint_metrics = Arc::new(EndpointMetrics::new(
-        logger.clone(),
-        &config.chains.providers(),
-        metrics_registry.cheap_clone(),
-    ));
-
-    // Convert the clients into a link resolver. Since we want to get past
-    // possible temporary DNS failures, make the resolver retry
-    let link_resolver = Arc::new(IpfsResolver::new(ipfs_clients, env_vars.cheap_clone()));
-
-    let chain_head_update_listener = store_builder.chain_head_update_listener();
-    let network_store = store_builder.network_store(config.chain_ids());
-    let block_store = network_store.block_store();
-    let ident_validator: Arc<dyn IdentValidator> = network_store.block_store();
-    let networks = Networks::from_config(
-        logger.cheap_clone(),
+    let Indexer {
+        store: _,
+        subgraph_store,
+        blockchain_map,
+        link_resolver,
+        logger_factory,
+        provider: subgraph_provider,
+    } = Indexer::new(
+        &logger,
+        store_builder,
+        &ipfs_url,
+        arweave_url,
         &config,
-        metrics_registry.cheap_clone(),
-        endpoint_metrics,
-        ident_validator,
+        &metrics_ctx,
+        &node_id,
     )
-    .await
-    .expect("unable to parse network configuration");
-
-    let subgraph_store = network_store.subgraph_store();
-
-    let blockchain_map = Arc::new(
-        networks
-            .blockchain_map(
-                &env_vars,
-                &node_id,
-                &logger,
-                block_store,
-                &logger_factory,
-                metrics_registry.cheap_clone(),
-                chain_head_update_listener,
-            )
-            .await,
-    );
-
-    let static_filters = ENV_VARS.experimental_static_filters;
-
-    let sg_metrics = Arc::new(SubgraphCountMetric::new(metrics_registry.clone()));
-
-    let subgraph_instance_manager = SubgraphInstanceManager::new(
-        &logger_factory,
-        env_vars.cheap_clone(),
-        subgraph_store.clone(),
-        blockchain_map.clone(),
-        sg_metrics.cheap_clone(),
-        metrics_registry.clone(),
-        link_resolver.cheap_clone(),
-        ipfs_service,
-        arweave_service,
-        static_filters,
-    );
-
-    // Create IPFS-based subgraph provider
-    let subgraph_provider = Arc::new(IpfsSubgraphAssignmentProvider::new(
-        &logger_factory,
-        link_resolver.cheap_clone(),
-        subgraph_instance_manager,
-        sg_metrics,
-    ));
+    .await?;
 
     let panicking_subscription_manager = Arc::new(PanicSubscriptionManager {});
 
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use graph::anyhow::bail;
+use graph::components::store::{BlockStore as _, ChainStore as _, SubgraphStore as _};
+use graph::prelude::{anyhow, Error};
+use graph_store_postgres::{connection_pool::ConnectionPool, NotificationSender, Store};
+
+use crate::manager::commands::rewind;
+use crate::manager::deployment::DeploymentSearch;
+
+pub fn list(
+    primary: ConnectionPool,
+    store: Arc<Store>,
+    search: DeploymentSearch,
+) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+    let skipped = store.subgraph_store().skipped_triggers(&locator)?;
+
+    if skipped.is_empty() {
+        println!("No skipped triggers for {}", locator);
+        return Ok(());
+    }
+
+    for trigger in skipped {
+        println!("{:-^74}", "");
+        println!("{:<10} | {}", "block", trigger.block_ptr);
+        println!("{:<10} | {}", "handler", trigger.handler);
+        if !trigger.trigger.is_empty() {
+            println!("{:<10} | {}", "trigger", trigger.trigger);
+        }
+        println!("{:<10} | {}", "error", trigger.message);
+    }
+    Ok(())
+}
+
+/// Rewind the deployment to the block before its earliest skipped
+/// trigger so that all skipped triggers are processed again
+pub async fn reprocess(
+    primary: ConnectionPool,
+    store: Arc<Store>,
+    search: DeploymentSearch,
+    sender: &NotificationSender,
+    force: bool,
+    sleep: Duration,
+) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+    let skipped = store.subgraph_store().skipped_triggers(&locator)?;
+
+    let Some(first) = skipped.first() else {
+        println!("No skipped triggers for {}", locator);
+        return Ok(());
+    };
+
+    let chain = search
+        .lookup(&primary)?
+        .into_iter()
+        .find(|deployment| deployment.id == locator.id.0)
+        .map(|deployment| deployment.chain)
+        .ok_or_else(|| anyhow!("can not find the chain of {}", locator))?;
+    let chain_store = store
+        .block_store()
+        .chain_store(&chain)
+        .ok_or_else(|| anyhow!("can not find chain store for {}", chain))?;
+
+    let parent_hash = match chain_store.block_number(&first.block_ptr.hash).await? {
+        Some((_, _, _, Some(parent_hash))) => parent_hash,
+        _ => bail!(
+            "the block cache for {} does not have block {} with the earliest skipped trigger; \
+             use `graphman rewind` to rewind to the block before it",
+            chain,
+            first.block_ptr
+        ),
+    };
+
+    println!(
+        "Reprocessing {} skipped triggers of {} starting at block {}",
+        skipped.len(),
+        locator,
+        first.block_ptr.number
+    );
+    rewind::run(
+        primary,
+        store,
+        vec![search],
+        Some(parent_hash.hash_hex()),
+        Some(first.block_ptr.number - 1),
+        sender,
+        force,
+        sleep,
+        false,
+    )
+    .await
+}
@@ -8,7 +8,9 @@ use diesel::r2d2::PooledConnection;
 use diesel::PgConnection;
 use graph::components::store::DeploymentLocator;
 use graph::components::store::VersionStats;
+use graph::data::subgraph::StorageParams;
 use graph::prelude::anyhow;
+use graph::prelude::anyhow::bail;
 use graph_store_postgres::command_support::catalog as store_catalog;
 use graph_store_postgres::command_support::catalog::Site;
 use graph_store_postgres::connection_pool::ConnectionPool;
@@ -199,3 +201,60 @@ pub fn set_target(
     }
     Ok(())
 }
+
+pub fn storage(
+    store: Arc<SubgraphStore>,
+    primary: ConnectionPool,
+    search: &DeploymentSearch,
+    entity: Option<&str>,
+    params: Vec<String>,
+    reset: bool,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    let entity = match entity {
+        Some(entity) => entity,
+        None => {
+            let options = store.storage_params(&locator)?;
+            if options.is_empty() {
+                println!("no storage parameters set for sgd{}", locator.id);
+            } else {
+                println!("{:^30} | {:^40}", "table", "storage parameters");
+                println!("{:-^30}-+-{:-^40}", "", "");
+                for (table, options) in options {
+                    println!("{:<30} | {}", table, options.join(", "));
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    let mut storage = StorageParams::default();
+    let mut names = Vec::new();
+    for param in &params {
+        if reset {
+            if !StorageParams::NAMES.contains(&param.as_str()) {
+                bail!(
+                    "unknown storage parameter `{param}`, must be one of {}",
+                    StorageParams::NAMES.join(", ")
+                );
+            }
+            names.push(param.as_str());
+        } else {
+            let (name, value) = param
+                .split_once('=')
+                .ok_or_else(|| anyhow!("storage parameters must be given as `name=value`"))?;
+            storage.set(name.trim(), value.trim())?;
+        }
+    }
+    if reset && names.is_empty() {
+        names = StorageParams::NAMES.to_vec();
+    }
+    if storage.is_empty() && names.is_empty() {
+        bail!("no storage parameters given");
+    }
+
+    store.set_storage_params(&locator, entity, &storage, &names)?;
+    println!("updated storage parameters for sgd{}.{entity}", locator.id);
+    Ok(())
+}
@@ -106,7 +106,7 @@ pub struct Opt {
         long,
         value_name = "HOST:PORT",
         env = "IPFS",
-        help = "HTTP addresses of IPFS nodes"
+        help = "HTTP addresses of IPFS nodes, or of gateways when prefixed with `gateway+`"
     )]
     pub ipfs: Vec<String>,
     #[clap(
@@ -170,6 +170,13 @@ pub struct Opt {
         help = "a file with a list of expensive queries, one query per line. Attempts to run these queries will return a QueryExecutionError::TooExpensive to clients"
     )]
     pub expensive_queries_filename: String,
+    #[clap(
+        long,
+        value_name = "FILE",
+        env = "GRAPH_NODE_QUERY_COST_CONFIG",
+        help = "a TOML file with field weights and budgets for query cost budgeting. Queries whose cost exceeds the budget for their deployment are rejected, and the cost of each query is reported in the response extensions"
+    )]
+    pub query_cost_config: Option<String>,
     #[clap(long, help = "Enable debug logging")]
     pub debug: bool,
 
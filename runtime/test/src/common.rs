@@ -47,6 +47,7 @@ fn mock_host_exports(
                 abis: vec![],
                 event_handlers: vec![],
                 call_handlers: vec![],
+                transfer_handlers: vec![],
                 block_handlers: vec![],
                 link: Link {
                     link: "link".to_owned(),
@@ -58,6 +59,8 @@ fn mock_host_exports(
 
     let network = data_source.network.clone().unwrap();
     let ens_lookup = store.ens_lookup();
+    let aux_lookup = store.aux_lookup();
+    let deployment_reader = store.deployment_reader();
 
     let ds_details = DataSourceDetails::from_data_source(
         &graph::data_source::DataSource::Onchain::<Chain>(data_source),
@@ -73,6 +76,9 @@ fn mock_host_exports(
             Arc::new(EnvVars::default()),
         )),
         ens_lookup,
+        aux_lookup,
+        deployment_reader,
+        Arc::new(Default::default()),
     )
 }
 
@@ -152,6 +158,7 @@ pub fn mock_data_source(path: &str, api_version: Version) -> DataSource {
             abis: vec![],
             event_handlers: vec![],
             call_handlers: vec![],
+            transfer_handlers: vec![],
             block_handlers: vec![],
             link: Link {
                 link: "link".to_owned(),
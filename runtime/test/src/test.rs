@@ -49,14 +49,15 @@ async fn test_valid_module_and_store(
     data_source: DataSource,
     api_version: Version,
 ) -> (WasmInstance, Arc<impl SubgraphStore>, DeploymentLocator) {
-    test_valid_module_and_store_with_timeout(subgraph_id, data_source, api_version, None).await
+    test_valid_module_and_store_with_limits(subgraph_id, data_source, api_version, None, None).await
 }
 
-async fn test_valid_module_and_store_with_timeout(
+async fn test_valid_module_and_store_with_limits(
     subgraph_id: &str,
     data_source: DataSource,
     api_version: Version,
     timeout: Option<Duration>,
+    fuel: Option<u64>,
 ) -> (WasmInstance, Arc<impl SubgraphStore>, DeploymentLocator) {
     let logger = Logger::root(slog::Discard, o!());
     let subgraph_id_with_api_version =
@@ -102,7 +103,16 @@ async fn test_valid_module_and_store_with_timeout(
     };
 
     let module = WasmInstance::from_valid_module_with_ctx(
-        Arc::new(ValidModule::new(&logger, data_source.mapping.runtime.as_ref(), timeout).unwrap()),
+        Arc::new(
+            ValidModule::new(
+                &logger,
+                data_source.mapping.runtime.as_ref(),
+                timeout,
+                Arc::default(),
+                fuel,
+            )
+            .unwrap(),
+        ),
         mock_context(
             deployment.clone(),
             data_source,
@@ -1376,6 +1386,23 @@ impl Host {
             &self.gas,
         )
     }
+
+    fn store_load_from(
+        &mut self,
+        deployment: &str,
+        entity_type: &str,
+        id: &str,
+        block: &BlockPtr,
+    ) -> Result<Option<Entity>, HostExportError> {
+        self.host_exports.store_load_from(
+            &mut self.ctx.state,
+            deployment.to_string(),
+            entity_type.to_string(),
+            id.to_string(),
+            block,
+            &self.gas,
+        )
+    }
 }
 
 #[track_caller]
@@ -1719,3 +1746,113 @@ async fn test_store_ts() {
         "Cannot get entity of type `Stats`. The type must be an @entity type",
     );
 }
+
+/// Test that `store.loadFrom` only reads from deployments that processed
+/// the same block as the subgraph and still have data for that block
+#[tokio::test]
+async fn test_store_load_from() {
+    const SOURCE: &str = "hostLoadFromSource";
+    const USER: &str = "User";
+
+    struct Progress;
+    impl PruneReporter for Progress {}
+
+    let schema = "type User @entity {
+        id: ID!,
+        name: String,
+    }";
+
+    let mut host = Host::new(schema, "hostLoadFrom", "boolean.wasm", None).await;
+
+    let subgraph_store = STORE.subgraph_store();
+    let source = DeploymentHash::new(SOURCE).unwrap();
+    let source = test_store::create_test_subgraph(&source, schema).await;
+    let source_schema = subgraph_store.input_schema(&source.hash).unwrap();
+    let user_type = source_schema.entity_type(USER).unwrap();
+    let set_user = |name: &str| EntityOperation::Set {
+        key: user_type.parse_key("u1").unwrap(),
+        data: entity! { source_schema => id: "u1", name: name },
+    };
+    let blocks = &*test_store::BLOCKS;
+
+    test_store::transact_and_wait(&subgraph_store, &source, blocks[0].clone(), vec![])
+        .await
+        .unwrap();
+    test_store::transact_and_wait(
+        &subgraph_store,
+        &source,
+        blocks[1].clone(),
+        vec![set_user("one")],
+    )
+    .await
+    .unwrap();
+
+    // The source is at the same block
+    let user = host
+        .store_load_from(SOURCE, USER, "u1", &blocks[1])
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(&Value::from("one")), user.get("name"));
+
+    // The source has not processed the block yet
+    let err = host
+        .store_load_from(SOURCE, USER, "u1", &blocks[2])
+        .unwrap_err();
+    assert!(matches!(err, HostExportError::Unknown(_)));
+    err_says(err, "has not processed block 2 yet");
+
+    // The source processed a different block with the same number
+    let sibling = BlockPtr::new(blocks[2].hash.clone(), 1);
+    let err = host
+        .store_load_from(SOURCE, USER, "u1", &sibling)
+        .unwrap_err();
+    assert!(matches!(err, HostExportError::Unknown(_)));
+    err_says(err, "not known to have processed");
+
+    for (block, name) in [(2, "two"), (3, "three")] {
+        test_store::transact_and_wait(
+            &subgraph_store,
+            &source,
+            blocks[block].clone(),
+            vec![set_user(name)],
+        )
+        .await
+        .unwrap();
+    }
+
+    // The source is past the block, and it is only known to have processed
+    // the block once the subgraph runner checked that
+    let err = host
+        .store_load_from(SOURCE, USER, "u1", &blocks[2])
+        .unwrap_err();
+    err_says(err, "not known to have processed");
+    host.host_exports
+        .dependencies()
+        .set_checked(&source.hash, blocks[2].clone());
+    let user = host
+        .store_load_from(SOURCE, USER, "u1", &blocks[2])
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(&Value::from("two")), user.get("name"));
+    assert_eq!(
+        vec![source.hash.clone()],
+        host.host_exports.dependencies().deployments()
+    );
+
+    // Once the source is pruned, blocks before its earliest block can not
+    // be read anymore
+    subgraph_store.set_history_blocks(&source, 2, 1).unwrap();
+    let req = PruneRequest::new(&source, 2, 1, 0, 3).unwrap();
+    subgraph_store
+        .prune(Box::new(Progress), &source, req)
+        .await
+        .unwrap();
+    host.host_exports
+        .dependencies()
+        .set_checked(&source.hash, blocks[0].clone());
+    let err = host
+        .store_load_from(SOURCE, USER, "u1", &blocks[0])
+        .unwrap_err();
+    assert!(matches!(err, HostExportError::Unknown(_)));
+    err_says(err, "only has data starting at block 1");
+}
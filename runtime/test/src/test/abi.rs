@@ -7,7 +7,7 @@ use super::*;
 
 async fn test_unbounded_loop(api_version: Version) {
     // Set handler timeout to 3 seconds.
-    let mut instance = test_valid_module_and_store_with_timeout(
+    let mut instance = test_valid_module_and_store_with_limits(
         "unboundedLoop",
         mock_data_source(
             &wasm_file_path("non_terminating.wasm", api_version.clone()),
@@ -15,6 +15,7 @@ async fn test_unbounded_loop(api_version: Version) {
         ),
         api_version,
         Some(Duration::from_secs(3)),
+        None,
     )
     .await
     .0;
@@ -41,6 +42,44 @@ async fn unbounded_loop_v0_0_5() {
     test_unbounded_loop(API_VERSION_0_0_5).await;
 }
 
+async fn test_unbounded_loop_out_of_fuel(api_version: Version) {
+    // No timeout, so only the fuel stops the loop.
+    let mut instance = test_valid_module_and_store_with_limits(
+        "unboundedLoopOutOfFuel",
+        mock_data_source(
+            &wasm_file_path("non_terminating.wasm", api_version.clone()),
+            api_version.clone(),
+        ),
+        api_version,
+        None,
+        Some(1_000_000),
+    )
+    .await
+    .0;
+    let res: Result<(), _> = instance
+        .get_func("loop")
+        .typed(&mut instance.store.as_context_mut())
+        .unwrap()
+        .call(&mut instance.store.as_context_mut(), ());
+    let err = res.unwrap_err();
+    assert!(
+        err.chain()
+            .any(|e| e.downcast_ref::<wasmtime::Trap>() == Some(&wasmtime::Trap::OutOfFuel)),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unbounded_loop_out_of_fuel_v0_0_4() {
+    test_unbounded_loop_out_of_fuel(API_VERSION_0_0_4).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unbounded_loop_out_of_fuel_v0_0_5() {
+    test_unbounded_loop_out_of_fuel(API_VERSION_0_0_5).await;
+}
+
 async fn test_unbounded_recursion(api_version: Version) {
     let mut instance = test_module(
         "unboundedRecursion",
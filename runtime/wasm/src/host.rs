@@ -1,13 +1,15 @@
 use std::cmp::PartialEq;
-use std::time::Instant;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use graph::futures01::sync::mpsc::Sender;
 use graph::futures03::channel::oneshot::channel;
 
 use graph::blockchain::{BlockTime, Blockchain, HostFn, RuntimeAdapter};
-use graph::components::store::{EnsLookup, SubgraphFork};
-use graph::components::subgraph::{MappingError, SharedProofOfIndexing};
+use graph::components::store::{AuxLookup, DeploymentReader, EnsLookup, SubgraphFork};
+use graph::components::subgraph::{DeploymentDependencies, MappingError, SharedProofOfIndexing};
+use graph::data::subgraph::HandlerLimits;
 use graph::data_source::{
     DataSource, DataSourceTemplate, MappingTrigger, TriggerData, TriggerWithHandler,
 };
@@ -28,6 +30,9 @@ pub struct RuntimeHostBuilder<C: Blockchain> {
     runtime_adapter: Arc<dyn RuntimeAdapter<C>>,
     link_resolver: Arc<dyn LinkResolver>,
     ens_lookup: Arc<dyn EnsLookup>,
+    aux_lookup: Arc<dyn AuxLookup>,
+    deployment_reader: Arc<dyn DeploymentReader>,
+    dependencies: Arc<DeploymentDependencies>,
 }
 
 impl<C: Blockchain> Clone for RuntimeHostBuilder<C> {
@@ -36,6 +41,9 @@ impl<C: Blockchain> Clone for RuntimeHostBuilder<C> {
             runtime_adapter: self.runtime_adapter.cheap_clone(),
             link_resolver: self.link_resolver.cheap_clone(),
             ens_lookup: self.ens_lookup.cheap_clone(),
+            aux_lookup: self.aux_lookup.cheap_clone(),
+            deployment_reader: self.deployment_reader.cheap_clone(),
+            dependencies: self.dependencies.cheap_clone(),
         }
     }
 }
@@ -45,11 +53,17 @@ impl<C: Blockchain> RuntimeHostBuilder<C> {
         runtime_adapter: Arc<dyn RuntimeAdapter<C>>,
         link_resolver: Arc<dyn LinkResolver>,
         ens_lookup: Arc<dyn EnsLookup>,
+        aux_lookup: Arc<dyn AuxLookup>,
+        deployment_reader: Arc<dyn DeploymentReader>,
+        dependencies: Arc<DeploymentDependencies>,
     ) -> Self {
         RuntimeHostBuilder {
             runtime_adapter,
             link_resolver,
             ens_lookup,
+            aux_lookup,
+            deployment_reader,
+            dependencies,
         }
     }
 }
@@ -66,6 +80,9 @@ where
         logger: Logger,
         subgraph_id: DeploymentHash,
         metrics: Arc<HostMetrics>,
+        handler_limits: Arc<BTreeMap<String, HandlerLimits>>,
+        fuel: Option<u64>,
+        workers: usize,
     ) -> Result<Sender<Self::Req>, Error> {
         let experimental_features = ExperimentalFeatures {
             allow_non_deterministic_ipfs: ENV_VARS.mappings.allow_non_deterministic_ipfs,
@@ -77,7 +94,10 @@ where
             metrics,
             tokio::runtime::Handle::current(),
             ENV_VARS.mappings.timeout,
+            handler_limits,
+            fuel,
             experimental_features,
+            workers,
         )
     }
 
@@ -100,6 +120,9 @@ where
             mapping_request_sender,
             metrics,
             self.ens_lookup.cheap_clone(),
+            self.aux_lookup.cheap_clone(),
+            self.deployment_reader.cheap_clone(),
+            self.dependencies.cheap_clone(),
         )
     }
 }
@@ -126,6 +149,9 @@ where
         mapping_request_sender: Sender<WasmRequest<C>>,
         metrics: Arc<HostMetrics>,
         ens_lookup: Arc<dyn EnsLookup>,
+        aux_lookup: Arc<dyn AuxLookup>,
+        deployment_reader: Arc<dyn DeploymentReader>,
+        dependencies: Arc<DeploymentDependencies>,
     ) -> Result<Self, Error> {
         let ds_details = DataSourceDetails::from_data_source(
             &data_source,
@@ -140,6 +166,9 @@ where
             ds_details,
             link_resolver,
             ens_lookup,
+            aux_lookup,
+            deployment_reader,
+            dependencies,
         ));
 
         let host_fns = data_source
@@ -157,6 +186,33 @@ where
         })
     }
 
+    /// Record a handler invocation in the handler statistics. `entity_ops`
+    /// and `errors` are the number of entity operations and deterministic
+    /// errors in the block state before the handler ran
+    fn record_handler_stats(
+        &self,
+        handler: &str,
+        elapsed: Duration,
+        entity_ops: usize,
+        errors: usize,
+        result: &Result<(BlockState, Gas), MappingError>,
+    ) {
+        let (entity_ops, failed) = match result {
+            Ok((state, _)) => (
+                state.entity_cache.ops().saturating_sub(entity_ops),
+                state.deterministic_errors.len() > errors,
+            ),
+            Err(_) => (0, true),
+        };
+        self.metrics.record_handler_stats(
+            self.data_source.name(),
+            handler,
+            elapsed,
+            entity_ops,
+            failed,
+        );
+    }
+
     /// Sends a MappingRequest to the thread which owns the host,
     /// and awaits the result.
     async fn send_mapping_request(
@@ -181,6 +237,7 @@ where
         let (result_sender, result_receiver) = channel();
         let start_time = Instant::now();
         let metrics = self.metrics.clone();
+        let (entity_ops, errors) = (state.entity_cache.ops(), state.deterministic_errors.len());
 
         self.mapping_request_sender
             .clone()
@@ -210,6 +267,7 @@ where
 
         let elapsed = start_time.elapsed();
         metrics.observe_handler_execution_time(elapsed.as_secs_f64(), &handler);
+        self.record_handler_stats(&handler, elapsed, entity_ops, errors, &result);
 
         // If there is an error, "gas_used" is incorrectly reported as 0.
         let gas_used = result.as_ref().map(|(_, gas)| gas).unwrap_or(&Gas::ZERO);
@@ -248,6 +306,7 @@ where
         let (result_sender, result_receiver) = channel();
         let start_time = Instant::now();
         let metrics = self.metrics.clone();
+        let (entity_ops, errors) = (state.entity_cache.ops(), state.deterministic_errors.len());
 
         self.mapping_request_sender
             .clone()
@@ -278,6 +337,7 @@ where
 
         let elapsed = start_time.elapsed();
         metrics.observe_handler_execution_time(elapsed.as_secs_f64(), &handler);
+        self.record_handler_stats(&handler, elapsed, entity_ops, errors, &result);
 
         // If there is an error, "gas_used" is incorrectly reported as 0.
         let gas_used = result.as_ref().map(|(_, gas)| gas).unwrap_or(&Gas::ZERO);
@@ -364,14 +424,14 @@ impl<C: Blockchain> RuntimeHostTrait<C> for RuntimeHost<C> {
     /// trigger has been processed.
     fn done_at(&self) -> Option<BlockNumber> {
         match self.data_source() {
-            DataSource::Onchain(_) => None,
+            DataSource::Onchain(_) | DataSource::Subgraph(_) => None,
             DataSource::Offchain(ds) => ds.done_at(),
         }
     }
 
     fn set_done_at(&self, block: Option<BlockNumber>) {
         match self.data_source() {
-            DataSource::Onchain(_) => {}
+            DataSource::Onchain(_) | DataSource::Subgraph(_) => {}
             DataSource::Offchain(ds) => ds.set_done_at(block),
         }
     }
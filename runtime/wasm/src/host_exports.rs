@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
@@ -6,25 +6,36 @@ use std::time::{Duration, Instant};
 use graph::data::subgraph::API_VERSION_0_0_8;
 use graph::data::value::Word;
 
+use ciborium::value::Value as CborValue;
 use graph::futures03::stream::StreamExt;
 use graph::schema::EntityType;
 use never::Never;
+use ripemd::Ripemd160;
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, Secp256k1, VerifyOnly,
+};
 use semver::Version;
+use sha2::{Digest, Sha256};
 use web3::types::H160;
 
 use graph::blockchain::BlockTime;
 use graph::blockchain::Blockchain;
-use graph::components::store::{EnsLookup, GetScope, LoadRelatedRequest};
+use graph::components::store::{
+    AuxLookup, DeploymentReader, EnsLookup, GetScope, LoadRelatedRequest,
+};
 use graph::components::subgraph::{
-    InstanceDSTemplate, PoICausalityRegion, ProofOfIndexingEvent, SharedProofOfIndexing,
+    DeploymentDependencies, InstanceDSTemplate, PoICausalityRegion, ProofOfIndexingEvent,
+    SharedProofOfIndexing,
 };
 use graph::data::store::{self};
 use graph::data_source::{CausalityRegion, DataSource, EntityTypeAccess};
 use graph::ensure;
+use graph::ipfs_client::CidFile;
 use graph::prelude::ethabi::param_type::Reader;
-use graph::prelude::ethabi::{decode, encode, Token};
+use graph::prelude::ethabi::{decode, encode, ParamType, Token};
 use graph::prelude::serde_json;
-use graph::prelude::{slog::b, slog::record_static, *};
+use graph::prelude::{r, slog::b, slog::record_static, *};
 use graph::runtime::gas::{self, complexity, Gas, GasCounter};
 pub use graph::runtime::{DeterministicHostError, HostExportError};
 
@@ -67,6 +78,9 @@ pub struct HostExports {
     poi_causality_region: String,
     pub(crate) link_resolver: Arc<dyn LinkResolver>,
     ens_lookup: Arc<dyn EnsLookup>,
+    aux_lookup: Arc<dyn AuxLookup>,
+    deployment_reader: Arc<dyn DeploymentReader>,
+    dependencies: Arc<DeploymentDependencies>,
 }
 
 pub struct DataSourceDetails {
@@ -103,6 +117,9 @@ impl HostExports {
         data_source_details: DataSourceDetails,
         link_resolver: Arc<dyn LinkResolver>,
         ens_lookup: Arc<dyn EnsLookup>,
+        aux_lookup: Arc<dyn AuxLookup>,
+        deployment_reader: Arc<dyn DeploymentReader>,
+        dependencies: Arc<DeploymentDependencies>,
     ) -> Self {
         Self {
             subgraph_id,
@@ -111,6 +128,9 @@ impl HostExports {
             subgraph_network,
             link_resolver,
             ens_lookup,
+            aux_lookup,
+            deployment_reader,
+            dependencies,
         }
     }
 
@@ -456,6 +476,108 @@ impl HostExports {
         Ok(result)
     }
 
+    /// Read the entity of another deployment on this node as it was at
+    /// `block`. If the deployment has not processed `block` yet, or if it
+    /// processed a different block with the same number, this fails with a
+    /// non-deterministic error; since the deployment is recorded as a
+    /// dependency of this subgraph, the subgraph waits for it to catch up
+    /// and checks that it is on the same chain before it retries the
+    /// block. Reading a block that the deployment has pruned also fails
+    /// with a non-deterministic error since whether data was pruned depends
+    /// on how the node is configured
+    pub(crate) fn store_load_from(
+        &self,
+        state: &mut BlockState,
+        deployment: String,
+        entity_type: String,
+        entity_id: String,
+        block: &BlockPtr,
+        gas: &GasCounter,
+    ) -> Result<Option<Entity>, HostExportError> {
+        let deployment = DeploymentHash::new(deployment).map_err(|deployment| {
+            HostExportError::Deterministic(anyhow!("invalid deployment `{}`", deployment))
+        })?;
+        if deployment == self.subgraph_id {
+            return Err(HostExportError::Deterministic(anyhow!(
+                "store.loadFrom can not read from the subgraph itself, use store.get instead"
+            )));
+        }
+        self.dependencies.add(&deployment);
+
+        let head = self
+            .deployment_reader
+            .head(&deployment)
+            .map_err(|e| HostExportError::Unknown(e.into()))?;
+        let head = match head {
+            Some(head) if head.number >= block.number => head,
+            _ => {
+                return Err(HostExportError::Unknown(anyhow!(
+                    "deployment {} has not processed block {} yet, its latest block is {:?}",
+                    deployment,
+                    block.number,
+                    head.map(|head| head.number)
+                )))
+            }
+        };
+
+        let earliest_block = self
+            .deployment_reader
+            .earliest_block(&deployment)
+            .map_err(|e| HostExportError::Unknown(e.into()))?;
+        if block.number < earliest_block {
+            return Err(HostExportError::Unknown(anyhow!(
+                "deployment {} only has data starting at block {} and can not be read at block {}",
+                deployment,
+                earliest_block,
+                block.number
+            )));
+        }
+
+        // The subgraph runner checks that the deployment processed the
+        // same block as this subgraph before it processes a block; when
+        // the deployment is at the same block, we can check that here
+        let same_block = if head.number == block.number {
+            &head == block
+        } else {
+            self.dependencies.is_checked(&deployment, block)
+        };
+        if !same_block {
+            return Err(HostExportError::Unknown(anyhow!(
+                "can not read from deployment {} since it is not known to have processed \
+                 block {}; its latest block is {}",
+                deployment,
+                block,
+                head
+            )));
+        }
+
+        let schema = self
+            .deployment_reader
+            .input_schema(&deployment)
+            .map_err(|e| HostExportError::Unknown(e.into()))?;
+        let entity_type = schema
+            .entity_type(&entity_type)
+            .map_err(HostExportError::Deterministic)?;
+        Self::expect_object_type(&entity_type, "loadFrom")?;
+        let key = entity_type
+            .parse_key(entity_id)
+            .map_err(HostExportError::Deterministic)?;
+
+        let result = self
+            .deployment_reader
+            .get(&deployment, &key, block.number)
+            .map_err(|e| HostExportError::Unknown(e.into()))?;
+
+        Self::track_gas_and_ops(
+            gas,
+            state,
+            gas::STORE_GET.with_args(complexity::Linear, (&key, result.as_ref())),
+            "store_load_from",
+        )?;
+
+        Ok(result)
+    }
+
     /// Prints the module of `n` in hex.
     /// Integers are encoded using the least amount of digits (no leading zero digits).
     /// Their encoding may be of uneven length. The number zero encodes as "0x0".
@@ -492,6 +614,68 @@ impl HostExports {
         graph::block_on(self.link_resolver.cat(logger, &Link { link }))
     }
 
+    /// Fetch the IPFS file `link` if it is at most `max_bytes` long. The
+    /// result only depends on the content of the file: an invalid link or a
+    /// file that is too long results in `None`. If the file can not be
+    /// fetched, the error is non-deterministic so that the handler is
+    /// retried rather than recording a result that depends on the
+    /// availability of the file
+    pub(crate) fn ipfs_cat_bounded(
+        &self,
+        logger: &Logger,
+        link: String,
+        max_bytes: usize,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<Option<Vec<u8>>, HostExportError> {
+        Self::track_gas_and_ops(gas, state, gas::IPFS_REQUEST, "ipfs_cat_bounded")?;
+        if CidFile::from_str(link.trim_start_matches("/ipfs/")).is_err() {
+            return Ok(None);
+        }
+
+        let data = graph::block_on(self.link_resolver.cat_bounded(
+            logger,
+            &Link { link: link.clone() },
+            max_bytes,
+        ))
+        .map_err(|e| {
+            HostExportError::Unknown(anyhow!("failed to fetch IPFS file {}: {}", link, e))
+        })?;
+
+        if let Some(data) = &data {
+            Self::track_gas_and_ops(
+                gas,
+                state,
+                gas::DEFAULT_GAS_OP.with_args(complexity::Size, &data[..]),
+                "ipfs_cat_bounded",
+            )?;
+        }
+        Ok(data)
+    }
+
+    /// Check whether the IPFS file `link` exists. Like for
+    /// `ipfs_cat_bounded`, failing to find out is a non-deterministic error
+    pub(crate) fn ipfs_exists(
+        &self,
+        logger: &Logger,
+        link: String,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<bool, HostExportError> {
+        Self::track_gas_and_ops(gas, state, gas::IPFS_REQUEST, "ipfs_exists")?;
+        graph::block_on(
+            self.link_resolver
+                .exists(logger, &Link { link: link.clone() }),
+        )
+        .map_err(|e| {
+            HostExportError::Unknown(anyhow!(
+                "failed to check whether IPFS file {} exists: {}",
+                link,
+                e
+            ))
+        })
+    }
+
     pub(crate) fn ipfs_get_block(
         &self,
         logger: &Logger,
@@ -664,6 +848,67 @@ impl HostExports {
         Ok(tiny_keccak::keccak256(data))
     }
 
+    pub(crate) fn crypto_sha256(
+        &self,
+        input: Vec<u8>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<[u8; 32], DeterministicHostError> {
+        let data = &input[..];
+        Self::track_gas_and_ops(
+            gas,
+            state,
+            gas::DEFAULT_GAS_OP.with_args(complexity::Size, data),
+            "crypto_sha256",
+        )?;
+        Ok(Sha256::digest(data).into())
+    }
+
+    pub(crate) fn crypto_ripemd160(
+        &self,
+        input: Vec<u8>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<[u8; 20], DeterministicHostError> {
+        let data = &input[..];
+        Self::track_gas_and_ops(
+            gas,
+            state,
+            gas::DEFAULT_GAS_OP.with_args(complexity::Size, data),
+            "crypto_ripemd160",
+        )?;
+        Ok(Ripemd160::digest(data).into())
+    }
+
+    pub(crate) fn crypto_blake3(
+        &self,
+        input: Vec<u8>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<[u8; 32], DeterministicHostError> {
+        let data = &input[..];
+        Self::track_gas_and_ops(
+            gas,
+            state,
+            gas::DEFAULT_GAS_OP.with_args(complexity::Size, data),
+            "crypto_blake3",
+        )?;
+        Ok(*blake3::hash(data).as_bytes())
+    }
+
+    /// The address that signed `hash` with `signature`, or `None` if no
+    /// address can be recovered from the signature
+    pub(crate) fn crypto_ecrecover(
+        &self,
+        hash: Vec<u8>,
+        signature: Vec<u8>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<Option<[u8; 20]>, DeterministicHostError> {
+        Self::track_gas_and_ops(gas, state, gas::CRYPTO_ECRECOVER, "crypto_ecrecover")?;
+        ecrecover(&hash, &signature).map_err(DeterministicHostError::from)
+    }
+
     pub(crate) fn big_int_plus(
         &self,
         x: BigInt,
@@ -958,6 +1203,47 @@ impl HostExports {
         Ok(x == y)
     }
 
+    pub(crate) fn big_decimal_pow(
+        &self,
+        x: BigDecimal,
+        y: BigDecimal,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        Self::track_gas_and_ops(gas, state, gas::BIG_DECIMAL_FUNCTION, "big_decimal_pow")?;
+        x.pow(&y).map_err(DeterministicHostError::from)
+    }
+
+    pub(crate) fn big_decimal_sqrt(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        Self::track_gas_and_ops(gas, state, gas::BIG_DECIMAL_FUNCTION, "big_decimal_sqrt")?;
+        x.sqrt().map_err(DeterministicHostError::from)
+    }
+
+    pub(crate) fn big_decimal_ln(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        Self::track_gas_and_ops(gas, state, gas::BIG_DECIMAL_FUNCTION, "big_decimal_ln")?;
+        x.ln().map_err(DeterministicHostError::from)
+    }
+
+    pub(crate) fn big_decimal_exp(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        Self::track_gas_and_ops(gas, state, gas::BIG_DECIMAL_FUNCTION, "big_decimal_exp")?;
+        x.exp().map_err(DeterministicHostError::from)
+    }
+
     pub(crate) fn big_decimal_to_string(
         &self,
         x: BigDecimal,
@@ -1057,6 +1343,17 @@ impl HostExports {
         Ok(self.ens_lookup.is_table_empty()?)
     }
 
+    pub(crate) fn aux_lookup(
+        &self,
+        dataset: &str,
+        key: &str,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<Option<String>, anyhow::Error> {
+        Self::track_gas_and_ops(gas, state, gas::AUX_LOOKUP, "aux_lookup")?;
+        Ok(self.aux_lookup.lookup(&self.subgraph_id, dataset, key)?)
+    }
+
     pub(crate) fn log_log(
         &self,
         logger: &Logger,
@@ -1156,6 +1453,82 @@ impl HostExports {
             .map_err(|e| DeterministicHostError::from(Error::from(e)))
     }
 
+    /// Encode the attributes of an entity as a JSON object, with the
+    /// values represented the same way as in GraphQL responses
+    pub(crate) fn json_from_entity(
+        &self,
+        data: HashMap<Word, Value>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<String, DeterministicHostError> {
+        // Sort the attributes to make the output deterministic
+        let object: BTreeMap<String, r::Value> = data
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), r::Value::from(value)))
+            .collect();
+        let json = serde_json::to_string(&object)
+            .map_err(|e| DeterministicHostError::from(Error::from(e)))?;
+        Self::track_encoded(gas, state, json.as_bytes(), "json_from_entity")?;
+        Ok(json)
+    }
+
+    pub(crate) fn json_stringify(
+        &self,
+        value: serde_json::Value,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<String, DeterministicHostError> {
+        let json = serde_json::to_string(&value)
+            .map_err(|e| DeterministicHostError::from(Error::from(e)))?;
+        Self::track_encoded(gas, state, json.as_bytes(), "json_stringify")?;
+        Ok(json)
+    }
+
+    pub(crate) fn cbor_encode(
+        &self,
+        value: serde_json::Value,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<Vec<u8>, DeterministicHostError> {
+        let bytes = cbor_encode(&value)?;
+        Self::track_encoded(gas, state, &bytes, "cbor_encode")?;
+        Ok(bytes)
+    }
+
+    pub(crate) fn cbor_decode(
+        &self,
+        bytes: Vec<u8>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<serde_json::Value, DeterministicHostError> {
+        Self::track_encoded(gas, state, &bytes, "cbor_decode")?;
+        Ok(cbor_decode(&bytes)?)
+    }
+
+    /// Charge gas for `encoded` and check that it is not larger than
+    /// `MAX_ENCODED_SIZE`
+    fn track_encoded(
+        gas: &GasCounter,
+        state: &mut BlockState,
+        encoded: &[u8],
+        method: &str,
+    ) -> Result<(), DeterministicHostError> {
+        Self::track_gas_and_ops(
+            gas,
+            state,
+            gas::JSON_FROM_BYTES.with_args(complexity::Size, encoded),
+            method,
+        )?;
+        if encoded.len() > MAX_ENCODED_SIZE {
+            return Err(DeterministicHostError::from(anyhow!(
+                "{} exceeds the maximum size of {} bytes",
+                method,
+                MAX_ENCODED_SIZE
+            )));
+        }
+        Ok(())
+    }
+
     pub(crate) fn string_to_h160(
         &self,
         string: &str,
@@ -1230,6 +1603,203 @@ impl HostExports {
             .map(|mut tokens| tokens.pop().unwrap())
             .context("Failed to decode")
     }
+
+    pub(crate) fn ethereum_abi_encode(
+        &self,
+        signature: String,
+        values: Vec<Token>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let encoded = abi_encode(&signature, &values)?;
+
+        Self::track_gas_and_ops(
+            gas,
+            state,
+            gas::DEFAULT_GAS_OP.with_args(complexity::Size, &encoded),
+            "ethereum_abi_encode",
+        )?;
+
+        Ok(encoded)
+    }
+
+    pub(crate) fn ethereum_abi_decode(
+        &self,
+        signature: String,
+        data: Vec<u8>,
+        gas: &GasCounter,
+        state: &mut BlockState,
+    ) -> Result<Vec<Token>, anyhow::Error> {
+        Self::track_gas_and_ops(
+            gas,
+            state,
+            gas::DEFAULT_GAS_OP.with_args(complexity::Size, &data),
+            "ethereum_abi_decode",
+        )?;
+
+        abi_decode(&signature, &data)
+    }
+}
+
+/// Read the types of the parameters in `signature`, which is either a
+/// single type like `uint256` or a parenthesized list of types like
+/// `(uint256,(address,bytes)[])`
+fn abi_param_types(signature: &str) -> Result<Vec<ParamType>, anyhow::Error> {
+    let param_type = Reader::read(signature)
+        .map_err(|e| anyhow::anyhow!("Failed to read signature `{}`: {}", signature, e))?;
+    match param_type {
+        ParamType::Tuple(types) => Ok(types),
+        param_type => Ok(vec![param_type]),
+    }
+}
+
+/// Encode `values` as the parameters in `signature`, the same way that
+/// Solidity's `abi.encode` does. Unlike encoding a tuple with
+/// `ethereum.encode`, this does not add an offset for dynamic parameter
+/// lists, and the values must match the types in the signature exactly
+fn abi_encode(signature: &str, values: &[Token]) -> Result<Vec<u8>, anyhow::Error> {
+    let types = abi_param_types(signature)?;
+    if !Token::types_check(values, &types) {
+        return Err(anyhow::anyhow!(
+            "values do not match signature `{}`",
+            signature
+        ));
+    }
+    Ok(encode(values))
+}
+
+/// Decode `data` as the parameters in `signature`, the same way that
+/// Solidity's `abi.decode` does
+fn abi_decode(signature: &str, data: &[u8]) -> Result<Vec<Token>, anyhow::Error> {
+    let types = abi_param_types(signature)?;
+    decode(&types, data).context("Failed to decode")
+}
+
+/// The largest output of `json.fromEntity`, `json.stringify` and
+/// `cbor.encode`, and the largest input of `cbor.decode`
+const MAX_ENCODED_SIZE: usize = 10_000_000;
+
+/// Encode `value` as CBOR. Numbers that do not fit into a 64 bit integer
+/// are encoded as floats
+fn cbor_encode(value: &serde_json::Value) -> Result<Vec<u8>, anyhow::Error> {
+    fn to_cbor(value: &serde_json::Value) -> Result<CborValue, anyhow::Error> {
+        use serde_json::Value as J;
+
+        Ok(match value {
+            J::Null => CborValue::Null,
+            J::Bool(b) => CborValue::Bool(*b),
+            J::Number(n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+                (Some(i), _, _) => CborValue::Integer(i.into()),
+                (None, Some(u), _) => CborValue::Integer(u.into()),
+                (None, None, Some(f)) => CborValue::Float(f),
+                (None, None, None) => return Err(anyhow!("can not encode the number {}", n)),
+            },
+            J::String(s) => CborValue::Text(s.clone()),
+            J::Array(values) => {
+                CborValue::Array(values.iter().map(to_cbor).collect::<Result<_, _>>()?)
+            }
+            J::Object(object) => CborValue::Map(
+                object
+                    .iter()
+                    .map(|(key, value)| Ok((CborValue::Text(key.clone()), to_cbor(value)?)))
+                    .collect::<Result<_, anyhow::Error>>()?,
+            ),
+        })
+    }
+
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(&to_cbor(value)?, &mut bytes)
+        .map_err(|e| anyhow!("failed to encode CBOR: {}", e))?;
+    Ok(bytes)
+}
+
+/// Decode the CBOR in `bytes` into JSON. Only CBOR values that JSON can
+/// represent are accepted; tags are ignored
+fn cbor_decode(bytes: &[u8]) -> Result<serde_json::Value, anyhow::Error> {
+    fn to_json(value: CborValue) -> Result<serde_json::Value, anyhow::Error> {
+        use serde_json::Value as J;
+
+        Ok(match value {
+            CborValue::Null => J::Null,
+            CborValue::Bool(b) => J::Bool(b),
+            CborValue::Integer(i) => {
+                let i = i128::from(i);
+                match (i64::try_from(i), u64::try_from(i)) {
+                    (Ok(i), _) => J::from(i),
+                    (Err(_), Ok(u)) => J::from(u),
+                    _ => return Err(anyhow!("the CBOR integer {} is too large", i)),
+                }
+            }
+            CborValue::Float(f) => serde_json::Number::from_f64(f)
+                .map(J::Number)
+                .ok_or_else(|| anyhow!("the CBOR float {} is not a JSON number", f))?,
+            CborValue::Text(s) => J::String(s),
+            CborValue::Array(values) => {
+                J::Array(values.into_iter().map(to_json).collect::<Result<_, _>>()?)
+            }
+            CborValue::Map(entries) => J::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| match key {
+                        CborValue::Text(key) => Ok((key, to_json(value)?)),
+                        _ => Err(anyhow!("CBOR map keys must be strings")),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            CborValue::Tag(_, value) => to_json(*value)?,
+            CborValue::Bytes(_) => return Err(anyhow!("CBOR byte strings are not supported")),
+            _ => return Err(anyhow!("unsupported CBOR value")),
+        })
+    }
+
+    let value: CborValue =
+        ciborium::de::from_reader(bytes).map_err(|e| anyhow!("failed to decode CBOR: {}", e))?;
+    to_json(value)
+}
+
+lazy_static! {
+    static ref SECP256K1: Secp256k1<VerifyOnly> = Secp256k1::verification_only();
+}
+
+/// Recover the address that signed the 32 byte `hash` from the 65 byte
+/// `signature`, which consists of `r`, `s` and the recovery id `v`, like
+/// Ethereum's `ecrecover` does. Both `0`/`1` and `27`/`28` are accepted for
+/// `v`. Returns `None` if the signature is invalid
+fn ecrecover(hash: &[u8], signature: &[u8]) -> Result<Option<[u8; 20]>, anyhow::Error> {
+    if hash.len() != 32 {
+        return Err(anyhow!(
+            "the hash must be 32 bytes long, not {}",
+            hash.len()
+        ));
+    }
+    if signature.len() != 65 {
+        return Err(anyhow!(
+            "the signature must be 65 bytes long, not {}",
+            signature.len()
+        ));
+    }
+
+    let v = match signature[64] {
+        v @ 27..=28 => v - 27,
+        v => v,
+    };
+    let recovered = RecoveryId::from_i32(v as i32)
+        .and_then(|id| RecoverableSignature::from_compact(&signature[..64], id))
+        .and_then(|signature| {
+            let message = Message::from_slice(hash)?;
+            SECP256K1.recover_ecdsa(&message, &signature)
+        });
+    let public_key = match recovered {
+        Ok(public_key) => public_key,
+        Err(_) => return Ok(None),
+    };
+
+    // The address is the last 20 bytes of the hash of the public key
+    // without its `0x04` prefix
+    let hash = tiny_keccak::keccak256(&public_key.serialize_uncompressed()[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    Ok(Some(address))
 }
 
 fn string_to_h160(string: &str) -> Result<H160, DeterministicHostError> {
@@ -1268,10 +1838,10 @@ pub mod test_support {
         blockchain::BlockTime,
         components::{
             store::{BlockNumber, GetScope},
-            subgraph::SharedProofOfIndexing,
+            subgraph::{DeploymentDependencies, SharedProofOfIndexing},
         },
         data::value::Word,
-        prelude::{BlockState, Entity, StopwatchMetrics, Value},
+        prelude::{BlockPtr, BlockState, Entity, StopwatchMetrics, Value},
         runtime::{gas::GasCounter, HostExportError},
         slog::Logger,
     };
@@ -1327,6 +1897,23 @@ pub mod test_support {
             self.host_exports
                 .store_get(state, entity_type, entity_id, gas, GetScope::Store)
         }
+
+        pub fn store_load_from(
+            &self,
+            state: &mut BlockState,
+            deployment: String,
+            entity_type: String,
+            entity_id: String,
+            block: &BlockPtr,
+            gas: &GasCounter,
+        ) -> Result<Option<Entity>, HostExportError> {
+            self.host_exports
+                .store_load_from(state, deployment, entity_type, entity_id, block, gas)
+        }
+
+        pub fn dependencies(&self) -> &DeploymentDependencies {
+            &self.host_exports.dependencies
+        }
     }
 }
 #[test]
@@ -1358,3 +1945,98 @@ fn bytes_to_string_is_lossy() {
         )
     )
 }
+
+#[test]
+fn abi_encode_decode_round_trip() {
+    use web3::types::U256;
+
+    let signature = "(uint256,(address,bytes)[],string)";
+    let values = vec![
+        Token::Uint(U256::from(7)),
+        Token::Array(vec![
+            Token::Tuple(vec![
+                Token::Address(H160::from_low_u64_be(1)),
+                Token::Bytes(vec![1, 2, 3]),
+            ]),
+            Token::Tuple(vec![
+                Token::Address(H160::from_low_u64_be(2)),
+                Token::Bytes(vec![]),
+            ]),
+        ]),
+        Token::String("graph".to_string()),
+    ];
+
+    let encoded = abi_encode(signature, &values).unwrap();
+    // Parameters are encoded like `abi.encode` does, without the offset
+    // that encoding the dynamic tuple as a single value would add
+    assert_eq!(encode(&values), encoded);
+    assert_eq!(U256::from(7), U256::from_big_endian(&encoded[..32]));
+    assert_eq!(values, abi_decode(signature, &encoded).unwrap());
+
+    // A single type does not need parentheses
+    let encoded = abi_encode("uint256", &values[..1]).unwrap();
+    assert_eq!(values[..1], abi_decode("uint256", &encoded).unwrap()[..]);
+}
+
+#[test]
+fn ecrecover_recovers_signer() {
+    use secp256k1::{PublicKey, SecretKey};
+
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(&[7u8; 32]).unwrap();
+    let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+    let signer = tiny_keccak::keccak256(&public_key.serialize_uncompressed()[1..]);
+
+    let hash = tiny_keccak::keccak256(b"graph");
+    let message = Message::from_slice(&hash).unwrap();
+    let (id, compact) = secp
+        .sign_ecdsa_recoverable(&message, &secret_key)
+        .serialize_compact();
+    let mut signature = compact.to_vec();
+    signature.push(id.to_i32() as u8 + 27);
+
+    let address = ecrecover(&hash, &signature).unwrap().unwrap();
+    assert_eq!(&signer[12..], &address[..]);
+
+    // `v` can also be given as 0 or 1
+    signature[64] -= 27;
+    assert_eq!(Some(address), ecrecover(&hash, &signature).unwrap());
+
+    // An invalid recovery id does not recover anything
+    signature[64] = 5;
+    assert_eq!(None, ecrecover(&hash, &signature).unwrap());
+
+    // Inputs with the wrong length are errors
+    assert!(ecrecover(&hash[..31], &signature).is_err());
+    assert!(ecrecover(&hash, &signature[..64]).is_err());
+}
+
+#[test]
+fn abi_encode_checks_types() {
+    let values = vec![Token::Bool(true)];
+    assert!(abi_encode("(uint256)", &values).is_err());
+    assert!(abi_encode("(bool,bool)", &values).is_err());
+    assert!(abi_encode("(bool", &values).is_err());
+    assert!(abi_encode("(bool)", &values).is_ok());
+}
+
+#[test]
+fn cbor_round_trip() {
+    let value = serde_json::json!({
+        "name": "graph",
+        "count": 7,
+        "negative": -1,
+        "ratio": 0.5,
+        "flags": [true, false, null],
+        "nested": { "big": 18446744073709551615u64 }
+    });
+    let bytes = cbor_encode(&value).unwrap();
+    assert_eq!(value, cbor_decode(&bytes).unwrap());
+
+    // Byte strings have no JSON equivalent
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(&CborValue::Bytes(vec![1, 2]), &mut bytes).unwrap();
+    assert!(cbor_decode(&bytes).is_err());
+
+    assert!(cbor_decode(&[0xff, 0x00]).is_err());
+}
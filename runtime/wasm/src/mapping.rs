@@ -3,6 +3,7 @@ use crate::module::{ExperimentalFeatures, ToAscPtr, WasmInstance};
 use graph::blockchain::{BlockTime, Blockchain, HostFn};
 use graph::components::store::SubgraphFork;
 use graph::components::subgraph::{MappingError, SharedProofOfIndexing};
+use graph::data::subgraph::HandlerLimits;
 use graph::data_source::{MappingTrigger, TriggerWithHandler};
 use graph::futures01::sync::mpsc;
 use graph::futures01::{Future as _, Stream as _};
@@ -12,10 +13,15 @@ use graph::runtime::gas::Gas;
 use parity_wasm::elements::ExportEntry;
 use std::collections::BTreeMap;
 use std::panic::AssertUnwindSafe;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::{panic, thread};
 
-/// Spawn a wasm module in its own thread.
+/// The length of an epoch when handlers have their own timeouts
+const HANDLER_TIMEOUT_EPOCH: Duration = Duration::from_secs(1);
+
+/// Spawn a wasm module in a pool of `workers` threads. The threads take
+/// requests from a shared channel, so that a request is picked up by
+/// whichever thread becomes idle first.
 pub fn spawn_module<C: Blockchain>(
     raw_module: &[u8],
     logger: Logger,
@@ -23,89 +29,160 @@ pub fn spawn_module<C: Blockchain>(
     host_metrics: Arc<HostMetrics>,
     runtime: tokio::runtime::Handle,
     timeout: Option<Duration>,
+    handler_limits: Arc<BTreeMap<String, HandlerLimits>>,
+    fuel: Option<u64>,
     experimental_features: ExperimentalFeatures,
+    workers: usize,
 ) -> Result<mpsc::Sender<WasmRequest<C>>, anyhow::Error>
 where
     <C as Blockchain>::MappingTrigger: ToAscPtr,
 {
-    let valid_module = Arc::new(ValidModule::new(&logger, raw_module, timeout)?);
+    let valid_module = Arc::new(ValidModule::new(
+        &logger,
+        raw_module,
+        timeout,
+        handler_limits,
+        fuel,
+    )?);
 
     // Create channel for event handling requests
     let (mapping_request_sender, mapping_request_receiver) = mpsc::channel(100);
 
     // wasmtime instances are not `Send` therefore they cannot be scheduled by
-    // the regular tokio executor, so we create a dedicated thread.
+    // the regular tokio executor, so we create dedicated threads.
     //
-    // In case of failure, this thread may panic or simply terminate,
-    // dropping the `mapping_request_receiver` which ultimately causes the
-    // subgraph to fail the next time it tries to handle an event.
-    let conf =
-        thread::Builder::new().name(format!("mapping-{}-{}", &subgraph_id, uuid::Uuid::new_v4()));
-    conf.spawn(move || {
-        let _runtime_guard = runtime.enter();
-
-        // Pass incoming triggers to the WASM module and return entity changes;
-        // Stop when canceled because all RuntimeHosts and their senders were dropped.
-        match mapping_request_receiver
-            .map_err(|()| unreachable!())
-            .for_each(move |request| {
-                let WasmRequest {
-                    ctx,
-                    inner,
-                    result_sender,
-                } = request;
-                let logger = ctx.logger.clone();
-
-                let result = panic::catch_unwind(AssertUnwindSafe(|| {
-                    instantiate_module::<C>(
-                        valid_module.cheap_clone(),
-                        ctx,
-                        host_metrics.cheap_clone(),
-                        experimental_features,
-                    )
-                    .map_err(Into::into)
-                    .and_then(|module| match inner {
-                        WasmRequestInner::TriggerRequest(trigger) => {
-                            handle_trigger(&logger, module, trigger, host_metrics.cheap_clone())
+    // In case of failure, these threads may panic or simply terminate;
+    // once all of them are gone, the `mapping_request_receiver` is dropped
+    // which ultimately causes the subgraph to fail the next time it tries
+    // to handle an event.
+    spawn_workers(
+        &logger,
+        &format!("mapping-{}", &subgraph_id),
+        workers,
+        runtime,
+        mapping_request_receiver,
+        move |request| {
+            handle_request::<C>(
+                request,
+                valid_module.cheap_clone(),
+                host_metrics.cheap_clone(),
+                experimental_features,
+            )
+        },
+    )?;
+
+    Ok(mapping_request_sender)
+}
+
+/// Spawn `workers` threads that take requests from `receiver` and pass
+/// them to `handle`. A thread stops when all senders for `receiver` have
+/// been dropped, or when `handle` fails.
+fn spawn_workers<R, F>(
+    logger: &Logger,
+    name: &str,
+    workers: usize,
+    runtime: tokio::runtime::Handle,
+    receiver: mpsc::Receiver<R>,
+    handle: F,
+) -> Result<Vec<thread::JoinHandle<()>>, anyhow::Error>
+where
+    R: Send + 'static,
+    F: Fn(R) -> Result<(), anyhow::Error> + Clone + Send + 'static,
+{
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    (0..workers.max(1))
+        .map(|_| {
+            let logger = logger.clone();
+            let runtime = runtime.clone();
+            let receiver = receiver.cheap_clone();
+            let handle = handle.clone();
+
+            let conf = thread::Builder::new().name(format!("{}-{}", name, uuid::Uuid::new_v4()));
+            conf.spawn(move || {
+                let _runtime_guard = runtime.enter();
+
+                // Pass incoming requests to `handle`; stop when canceled
+                // because all RuntimeHosts and their senders were dropped.
+                loop {
+                    // The lock is only held while waiting for the next request
+                    let request = {
+                        let mut receiver = receiver.lock().unwrap();
+                        match receiver.by_ref().into_future().wait() {
+                            Ok((Some(request), _)) => request,
+                            Ok((None, _)) => break,
+                            Err(_) => unreachable!(),
                         }
-                        WasmRequestInner::BlockRequest(BlockRequest {
-                            block_data,
-                            handler,
-                        }) => module.handle_block(&logger, &handler, block_data),
-                    })
-                }));
-
-                let result = match result {
-                    Ok(result) => result,
-                    Err(panic_info) => {
-                        let err_msg = if let Some(payload) = panic_info
-                            .downcast_ref::<String>()
-                            .map(String::as_str)
-                            .or(panic_info.downcast_ref::<&str>().copied())
-                        {
-                            anyhow!("Subgraph panicked with message: {}", payload)
-                        } else {
-                            anyhow!("Subgraph panicked with an unknown payload.")
-                        };
-                        Err(MappingError::Unknown(err_msg))
+                    };
+                    if let Err(e) = handle(request) {
+                        debug!(logger, "WASM runtime thread terminated abnormally";
+                                       "error" => e.to_string());
+                        return;
                     }
-                };
-
-                result_sender
-                    .send(result)
-                    .map_err(|_| anyhow::anyhow!("WASM module result receiver dropped."))
+                }
+                debug!(logger, "Subgraph stopped, WASM runtime thread terminated");
             })
-            .wait()
-        {
-            Ok(()) => debug!(logger, "Subgraph stopped, WASM runtime thread terminated"),
-            Err(e) => debug!(logger, "WASM runtime thread terminated abnormally";
-                                    "error" => e.to_string()),
+            .context("Spawning WASM runtime thread failed")
+        })
+        .collect()
+}
+
+/// Run a single request against a fresh instance of `valid_module` and
+/// send the result back to the requester
+fn handle_request<C: Blockchain>(
+    request: WasmRequest<C>,
+    valid_module: Arc<ValidModule>,
+    host_metrics: Arc<HostMetrics>,
+    experimental_features: ExperimentalFeatures,
+) -> Result<(), anyhow::Error>
+where
+    <C as Blockchain>::MappingTrigger: ToAscPtr,
+{
+    let WasmRequest {
+        ctx,
+        inner,
+        result_sender,
+    } = request;
+    let logger = ctx.logger.clone();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        instantiate_module::<C>(
+            valid_module,
+            ctx,
+            host_metrics.cheap_clone(),
+            experimental_features,
+        )
+        .map_err(Into::into)
+        .and_then(|module| match inner {
+            WasmRequestInner::TriggerRequest(trigger) => {
+                handle_trigger(&logger, module, trigger, host_metrics.cheap_clone())
+            }
+            WasmRequestInner::BlockRequest(BlockRequest {
+                block_data,
+                handler,
+            }) => module.handle_block(&logger, &handler, block_data),
+        })
+    }));
+
+    let result = match result {
+        Ok(result) => result,
+        Err(panic_info) => {
+            let err_msg = if let Some(payload) = panic_info
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or(panic_info.downcast_ref::<&str>().copied())
+            {
+                anyhow!("Subgraph panicked with message: {}", payload)
+            } else {
+                anyhow!("Subgraph panicked with an unknown payload.")
+            };
+            Err(MappingError::Unknown(err_msg))
         }
-    })
-    .map(|_| ())
-    .context("Spawning WASM runtime thread failed")?;
+    };
 
-    Ok(mapping_request_sender)
+    result_sender
+        .send(result)
+        .map_err(|_| anyhow::anyhow!("WASM module result receiver dropped."))
 }
 
 fn instantiate_module<C: Blockchain>(
@@ -250,6 +327,17 @@ pub struct ValidModule {
     // The timeout for the module.
     pub timeout: Option<Duration>,
 
+    // The limits for individual handlers, keyed by handler name.
+    handler_limits: Arc<BTreeMap<String, HandlerLimits>>,
+
+    // The fuel that each invocation of a handler may use; `None` if handlers are not metered
+    // with fuel.
+    // See also: runtime-timeouts
+    pub fuel: Option<u64>,
+
+    // How often the epoch of the engine is incremented; `None` if it never is.
+    epoch: Option<Duration>,
+
     // Used as a guard to terminate this task dependency.
     epoch_counter_abort_handle: Option<tokio::task::AbortHandle>,
 }
@@ -260,6 +348,8 @@ impl ValidModule {
         logger: &Logger,
         raw_module: &[u8],
         timeout: Option<Duration>,
+        handler_limits: Arc<BTreeMap<String, HandlerLimits>>,
+        fuel: Option<u64>,
     ) -> Result<Self, anyhow::Error> {
         // Add the gas calls here. Module name "gas" must match. See also
         // e3f03e62-40e4-4f8c-b4a1-d0375cca0b76. We do this by round-tripping the module through
@@ -306,6 +396,11 @@ impl ValidModule {
         let mut config = wasmtime::Config::new();
         config.strategy(wasmtime::Strategy::Cranelift);
         config.epoch_interruption(true);
+        // Fuel is what makes handlers that run too long fail deterministically; the timeouts
+        // only guard against handlers that hang. Subgraphs with older spec versions are not
+        // metered since that would make handlers that used to succeed fail.
+        // See also: runtime-timeouts
+        config.consume_fuel(fuel.is_some());
         config.cranelift_nan_canonicalization(true); // For NaN determinism.
         config.cranelift_opt_level(wasmtime::OptLevel::None);
         config.max_wasm_stack(ENV_VARS.mappings.max_stack_size);
@@ -326,18 +421,27 @@ impl ValidModule {
                 .push(module.to_string());
         }
 
+        // Handler timeouts are whole seconds; when there are any, the epoch needs to be short
+        // enough to enforce them.
+        let epoch = match handler_limits
+            .values()
+            .any(|limits| limits.timeout.is_some())
+        {
+            true => Some(timeout.map_or(HANDLER_TIMEOUT_EPOCH, |t| t.min(HANDLER_TIMEOUT_EPOCH))),
+            false => timeout,
+        };
+
         let mut epoch_counter_abort_handle = None;
-        if let Some(timeout) = timeout {
-            let timeout = timeout.clone();
+        if let Some(epoch) = epoch {
             let engine = engine.clone();
 
-            // The epoch counter task will perpetually increment the epoch every `timeout` seconds.
+            // The epoch counter task will perpetually increment the epoch every `epoch` seconds.
             // Timeouts on instantiated modules will trigger on epoch deltas.
             // Note: The epoch is an u64 so it will never overflow.
             // See also: runtime-timeouts
             let epoch_counter = async move {
                 loop {
-                    tokio::time::sleep(timeout).await;
+                    tokio::time::sleep(epoch).await;
                     engine.increment_epoch();
                 }
             };
@@ -349,9 +453,39 @@ impl ValidModule {
             import_name_to_modules,
             start_function,
             timeout,
+            handler_limits,
+            fuel,
+            epoch,
             epoch_counter_abort_handle,
         })
     }
+
+    /// The limits for `handler` from the manifest
+    pub fn handler_limits(&self, handler: &str) -> Option<&HandlerLimits> {
+        self.handler_limits.get(handler)
+    }
+
+    /// The epoch deadline that interrupts `handler` once it runs out of time. Without a
+    /// `handler`, e.g., for the start function, only the module timeout applies.
+    ///
+    /// A deadline of `n` epochs triggers after between `n - 1` and `n` epochs, so we add one
+    /// epoch to the timeout. With only a module timeout, this is a deadline of 2, which means
+    /// that the interrupt triggers between `timeout` and `timeout * 2`.
+    ///
+    /// See also: runtime-timeouts
+    pub fn epoch_deadline(&self, handler: Option<&str>) -> u64 {
+        let timeout = handler
+            .and_then(|handler| self.handler_limits(handler))
+            .and_then(|limits| limits.timeout())
+            .or(self.timeout);
+        match (timeout, self.epoch) {
+            (Some(timeout), Some(epoch)) => {
+                let epochs = timeout.as_nanos().div_ceil(epoch.as_nanos().max(1));
+                u64::try_from(epochs).unwrap_or(u64::MAX - 1) + 1
+            }
+            _ => u64::MAX,
+        }
+    }
 }
 
 impl Drop for ValidModule {
@@ -361,3 +495,58 @@ impl Drop for ValidModule {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::futures01::Sink as _;
+    use std::collections::HashSet;
+
+    #[test]
+    fn workers_stop_when_senders_are_dropped() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let logger = graph::log::discard();
+        let (sender, receiver) = mpsc::channel(10);
+        let (results, handled) = std::sync::mpsc::channel();
+
+        let workers = spawn_workers(
+            &logger,
+            "mapping-test",
+            3,
+            runtime.handle().clone(),
+            receiver,
+            move |request: usize| {
+                results
+                    .send((request, thread::current().id()))
+                    .map_err(|_| anyhow!("result receiver dropped"))
+            },
+        )
+        .unwrap();
+        assert_eq!(3, workers.len());
+
+        // Every request is handled by exactly one of the workers
+        let sender = (0..20).fold(sender, |sender, i| sender.send(i).wait().unwrap());
+        let mut requests: Vec<_> = handled.iter().take(20).collect();
+        requests.sort_by_key(|(i, _)| *i);
+        assert_eq!(
+            (0..20).collect::<Vec<_>>(),
+            requests.iter().map(|(i, _)| *i).collect::<Vec<_>>()
+        );
+        let threads: HashSet<_> = requests.iter().map(|(_, id)| *id).collect();
+        let worker_ids: HashSet<_> = workers.iter().map(|w| w.thread().id()).collect();
+        assert!(threads.is_subset(&worker_ids));
+
+        // The workers keep running as long as there is a sender
+        let other = sender.clone();
+        drop(sender);
+        thread::sleep(Duration::from_millis(50));
+        assert!(workers.iter().all(|worker| !worker.is_finished()));
+
+        // Once all senders are gone, all workers stop
+        drop(other);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert!(handled.try_recv().is_err());
+    }
+}
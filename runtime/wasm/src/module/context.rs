@@ -63,7 +63,8 @@ impl WasmInstanceContext<'_> {
 
     pub fn start_timeout(&mut self) {
         // See also: runtime-timeouts
-        self.inner.set_epoch_deadline(2);
+        let deadline = self.as_ref().epoch_deadline;
+        self.inner.set_epoch_deadline(deadline);
     }
 }
 
@@ -92,6 +93,10 @@ pub struct WasmInstanceData {
     // A host export trap ocurred for a deterministic reason.
     pub deterministic_host_trap: bool,
 
+    // The epoch deadline for the code that is currently running.
+    // See also: runtime-timeouts
+    pub epoch_deadline: u64,
+
     pub(crate) experimental_features: ExperimentalFeatures,
 
     // This option is needed to break the cyclic dependency between, instance, store, and context.
@@ -106,6 +111,7 @@ impl WasmInstanceData {
         host_metrics: Arc<HostMetrics>,
         experimental_features: ExperimentalFeatures,
     ) -> Self {
+        let epoch_deadline = valid_module.epoch_deadline(None);
         WasmInstanceData {
             asc_heap: None,
             ctx,
@@ -113,6 +119,7 @@ impl WasmInstanceData {
             host_metrics,
             possible_reorg: false,
             deterministic_host_trap: false,
+            epoch_deadline,
             experimental_features,
         }
     }
@@ -363,6 +370,34 @@ impl WasmInstanceContext<'_> {
         Ok(ret)
     }
 
+    /// function store.loadFrom(deployment: string, entity: string, id: string): Entity | null
+    pub fn store_load_from(
+        &mut self,
+        gas: &GasCounter,
+        deployment_ptr: AscPtr<AscString>,
+        entity_ptr: AscPtr<AscString>,
+        id_ptr: AscPtr<AscString>,
+    ) -> Result<AscPtr<AscEntity>, HostExportError> {
+        let deployment: String = asc_get(self, deployment_ptr, gas)?;
+        let entity_type: String = asc_get(self, entity_ptr, gas)?;
+        let id: String = asc_get(self, id_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let block = self.as_ref().ctx.block_ptr.clone();
+        let entity = host_exports.store_load_from(
+            &mut self.as_mut().ctx.state,
+            deployment,
+            entity_type,
+            id,
+            &block,
+            gas,
+        )?;
+
+        match entity {
+            Some(entity) => asc_new(self, &entity.sorted_ref(), gas),
+            None => Ok(AscPtr::null()),
+        }
+    }
+
     /// function typeConversion.bytesToString(bytes: Bytes): string
     pub fn bytes_to_string(
         &mut self,
@@ -505,6 +540,58 @@ impl WasmInstanceContext<'_> {
         asc_new(self, &result, gas)
     }
 
+    /// function json.fromEntity(entity: Entity): string
+    pub fn json_from_entity(
+        &mut self,
+        gas: &GasCounter,
+        entity_ptr: AscPtr<AscEntity>,
+    ) -> Result<AscPtr<AscString>, HostExportError> {
+        let data = asc_get(self, entity_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let json = host_exports.json_from_entity(data, gas, &mut ctx.state)?;
+        asc_new(self, json.as_str(), gas)
+    }
+
+    /// function json.stringify(value: JSONValue): string
+    pub fn json_stringify(
+        &mut self,
+        gas: &GasCounter,
+        value_ptr: AscPtr<AscEnum<JsonValueKind>>,
+    ) -> Result<AscPtr<AscString>, HostExportError> {
+        let value = asc_get(self, value_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let json = host_exports.json_stringify(value, gas, &mut ctx.state)?;
+        asc_new(self, json.as_str(), gas)
+    }
+
+    /// function cbor.encode(value: JSONValue): Bytes
+    pub fn cbor_encode(
+        &mut self,
+        gas: &GasCounter,
+        value_ptr: AscPtr<AscEnum<JsonValueKind>>,
+    ) -> Result<AscPtr<Uint8Array>, HostExportError> {
+        let value = asc_get(self, value_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let bytes = host_exports.cbor_encode(value, gas, &mut ctx.state)?;
+        asc_new(self, bytes.as_slice(), gas)
+    }
+
+    /// function cbor.decode(bytes: Bytes): JSONValue
+    pub fn cbor_decode(
+        &mut self,
+        gas: &GasCounter,
+        bytes_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<AscEnum<JsonValueKind>>, HostExportError> {
+        let bytes: Vec<u8> = asc_get(self, bytes_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let value = host_exports.cbor_decode(bytes, gas, &mut ctx.state)?;
+        asc_new(self, &value, gas)
+    }
+
     /// function ipfs.cat(link: String): Bytes
     pub fn ipfs_cat(
         &mut self,
@@ -542,6 +629,42 @@ impl WasmInstanceContext<'_> {
         }
     }
 
+    /// function ipfs.catBounded(link: String, maxBytes: u32): Bytes | null
+    pub fn ipfs_cat_bounded(
+        &mut self,
+        gas: &GasCounter,
+        link_ptr: AscPtr<AscString>,
+        max_bytes: u32,
+    ) -> Result<AscPtr<Uint8Array>, HostExportError> {
+        let link = asc_get(self, link_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let logger = self.as_ref().ctx.logger.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let data = host_exports.ipfs_cat_bounded(
+            &logger,
+            link,
+            max_bytes as usize,
+            gas,
+            &mut ctx.state,
+        )?;
+
+        data.map(|data| asc_new(self, &*data, gas).map_err(Into::into))
+            .unwrap_or(Ok(AscPtr::null()))
+    }
+
+    /// function ipfs.exists(link: String): bool
+    pub fn ipfs_exists(
+        &mut self,
+        gas: &GasCounter,
+        link_ptr: AscPtr<AscString>,
+    ) -> Result<bool, HostExportError> {
+        let link = asc_get(self, link_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let logger = self.as_ref().ctx.logger.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        host_exports.ipfs_exists(&logger, link, gas, &mut ctx.state)
+    }
+
     /// function ipfs.getBlock(link: String): Bytes
     pub fn ipfs_get_block(
         &mut self,
@@ -704,6 +827,70 @@ impl WasmInstanceContext<'_> {
         asc_new(self, input.as_ref(), gas)
     }
 
+    /// function crypto.sha256(input: Bytes): Bytes
+    pub fn crypto_sha256(
+        &mut self,
+
+        gas: &GasCounter,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, HostExportError> {
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let input = asc_get(self, input_ptr, gas)?;
+        let ctx = &mut self.as_mut().ctx;
+
+        let input = host_exports.crypto_sha256(input, gas, &mut ctx.state)?;
+        asc_new(self, input.as_ref(), gas)
+    }
+
+    /// function crypto.ripemd160(input: Bytes): Bytes
+    pub fn crypto_ripemd160(
+        &mut self,
+
+        gas: &GasCounter,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, HostExportError> {
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let input = asc_get(self, input_ptr, gas)?;
+        let ctx = &mut self.as_mut().ctx;
+
+        let input = host_exports.crypto_ripemd160(input, gas, &mut ctx.state)?;
+        asc_new(self, input.as_ref(), gas)
+    }
+
+    /// function crypto.blake3(input: Bytes): Bytes
+    pub fn crypto_blake3(
+        &mut self,
+
+        gas: &GasCounter,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, HostExportError> {
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let input = asc_get(self, input_ptr, gas)?;
+        let ctx = &mut self.as_mut().ctx;
+
+        let input = host_exports.crypto_blake3(input, gas, &mut ctx.state)?;
+        asc_new(self, input.as_ref(), gas)
+    }
+
+    /// function crypto.ecrecover(hash: Bytes, signature: Bytes): Address | null
+    pub fn crypto_ecrecover(
+        &mut self,
+
+        gas: &GasCounter,
+        hash_ptr: AscPtr<Uint8Array>,
+        signature_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, HostExportError> {
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let hash = asc_get(self, hash_ptr, gas)?;
+        let signature = asc_get(self, signature_ptr, gas)?;
+        let ctx = &mut self.as_mut().ctx;
+
+        match host_exports.crypto_ecrecover(hash, signature, gas, &mut ctx.state)? {
+            Some(address) => asc_new(self, address.as_ref(), gas),
+            None => Ok(AscPtr::null()),
+        }
+    }
+
     /// function bigInt.plus(x: BigInt, y: BigInt): BigInt
     pub fn big_int_plus(
         &mut self,
@@ -1012,6 +1199,64 @@ impl WasmInstanceContext<'_> {
         host_exports.big_decimal_equals(x, y, gas, &mut ctx.state)
     }
 
+    /// function bigDecimal.pow(x: BigDecimal, y: BigDecimal): BigDecimal
+    pub fn big_decimal_pow(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+        y_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, HostExportError> {
+        let x = asc_get(self, x_ptr, gas)?;
+        let y = asc_get(self, y_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+
+        let result = host_exports.big_decimal_pow(x, y, gas, &mut ctx.state)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.sqrt(x: BigDecimal): BigDecimal
+    pub fn big_decimal_sqrt(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, HostExportError> {
+        let x = asc_get(self, x_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+
+        let result = host_exports.big_decimal_sqrt(x, gas, &mut ctx.state)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.ln(x: BigDecimal): BigDecimal
+    pub fn big_decimal_ln(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, HostExportError> {
+        let x = asc_get(self, x_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+
+        let result = host_exports.big_decimal_ln(x, gas, &mut ctx.state)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.exp(x: BigDecimal): BigDecimal
+    pub fn big_decimal_exp(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, HostExportError> {
+        let x = asc_get(self, x_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+
+        let result = host_exports.big_decimal_exp(x, gas, &mut ctx.state)?;
+        asc_new(self, &result, gas)
+    }
+
     /// function dataSource.create(name: string, params: Array<string>): void
     pub fn data_source_create(
         &mut self,
@@ -1120,6 +1365,24 @@ impl WasmInstanceContext<'_> {
             .unwrap_or(Ok(AscPtr::null()))
     }
 
+    /// function aux.lookup(dataset: string, key: string): string | null
+    pub fn aux_lookup(
+        &mut self,
+        gas: &GasCounter,
+        dataset_ptr: AscPtr<AscString>,
+        key_ptr: AscPtr<AscString>,
+    ) -> Result<AscPtr<AscString>, HostExportError> {
+        let dataset: String = asc_get(self, dataset_ptr, gas)?;
+        let key: String = asc_get(self, key_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let value = host_exports.aux_lookup(&dataset, &key, gas, &mut ctx.state)?;
+
+        value
+            .map(|value| asc_new(self, &*value, gas).map_err(Into::into))
+            .unwrap_or(Ok(AscPtr::null()))
+    }
+
     pub fn log_log(
         &mut self,
         gas: &GasCounter,
@@ -1167,6 +1430,42 @@ impl WasmInstanceContext<'_> {
             .unwrap_or(Ok(AscPtr::null()))
     }
 
+    /// function abiEncode(signature: string, values: Array<ethereum.Value>): Bytes | null
+    pub fn ethereum_abi_encode(
+        &mut self,
+        gas: &GasCounter,
+        signature_ptr: AscPtr<AscString>,
+        values_ptr: AscEnumArray<EthereumValueKind>,
+    ) -> Result<AscPtr<Uint8Array>, HostExportError> {
+        let signature = asc_get(self, signature_ptr, gas)?;
+        let values = asc_get(self, values_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let data = host_exports.ethereum_abi_encode(signature, values, gas, &mut ctx.state);
+        // return `null` if it fails
+        data.map(|bytes| asc_new(self, &*bytes, gas))
+            .unwrap_or(Ok(AscPtr::null()))
+    }
+
+    /// function abiDecode(signature: string, data: Bytes): Array<ethereum.Value> | null
+    pub fn ethereum_abi_decode(
+        &mut self,
+        gas: &GasCounter,
+        signature_ptr: AscPtr<AscString>,
+        data_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscEnumArray<EthereumValueKind>, HostExportError> {
+        let signature = asc_get(self, signature_ptr, gas)?;
+        let data = asc_get(self, data_ptr, gas)?;
+        let host_exports = self.as_ref().ctx.host_exports.cheap_clone();
+        let ctx = &mut self.as_mut().ctx;
+        let result = host_exports.ethereum_abi_decode(signature, data, gas, &mut ctx.state);
+
+        // return `null` if it fails
+        result
+            .map(|values| asc_new(self, values.as_slice(), gas))
+            .unwrap_or(Ok(AscPtr::null()))
+    }
+
     /// function arweave.transactionData(txId: string): Bytes | null
     pub fn arweave_transaction_data(
         &self,
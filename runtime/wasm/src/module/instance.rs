@@ -173,6 +173,26 @@ impl WasmInstance {
             .typed(self.store.as_context_mut())
             .context("wasm function has incorrect signature")?;
 
+        // Apply the limits for this handler from the manifest.
+        // See also: runtime-timeouts
+        let valid_module = self.instance_ctx().as_ref().valid_module.cheap_clone();
+        let timeout = valid_module
+            .handler_limits(handler)
+            .and_then(|limits| limits.timeout())
+            .or(valid_module.timeout);
+        if let Some(gas) = valid_module
+            .handler_limits(handler)
+            .and_then(|limits| limits.gas)
+        {
+            self.gas.set_limit(Gas::new(gas));
+        }
+        let deadline = valid_module.epoch_deadline(Some(handler));
+        self.instance_ctx().as_mut().epoch_deadline = deadline;
+        self.store.set_epoch_deadline(deadline);
+        if let Some(fuel) = valid_module.fuel {
+            self.store.set_fuel(fuel)?;
+        }
+
         // Caution: Make sure all exit paths from this function call `exit_handler`.
         self.instance_ctx().as_mut().ctx.state.enter_handler();
 
@@ -189,8 +209,25 @@ impl WasmInstance {
                     return Err(MappingError::PossibleReorg(trap.into()));
                 }
 
+                // Running out of fuel happens after the same instructions on every indexer, which
+                // makes it the deterministic counterpart of a timeout.
+                // See also: runtime-timeouts
+                Err(trap)
+                    if trap
+                        .chain()
+                        .any(|e| e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel)) =>
+                {
+                    Some(trap.context(format!(
+                        "Gas exhausted: handler '{}' used up its fuel of {}",
+                        handler,
+                        valid_module.fuel.unwrap_or_default()
+                    )))
+                }
+
                 // Treat timeouts anywhere in the error chain as a special case to have a better error
-                // message. Any `TrapCode::Interrupt` is assumed to be a timeout.
+                // message. Any `TrapCode::Interrupt` is assumed to be a timeout. How long a handler
+                // takes depends on the indexer, so this is never deterministic, not even for a
+                // timeout that the manifest set for the handler.
                 // See also: runtime-timeouts
                 Err(trap)
                     if trap
@@ -201,7 +238,7 @@ impl WasmInstance {
                     return Err(MappingError::Unknown(Error::from(trap).context(format!(
                         "Handler '{}' hit the timeout of '{}' seconds",
                         handler,
-                        self.instance_ctx().as_ref().valid_module.timeout.unwrap().as_secs()
+                        timeout.unwrap().as_secs()
                     ))));
                 }
                 Err(trap) => {
@@ -280,14 +317,15 @@ impl WasmInstance {
         let mut store = Store::new(engine, wasm_ctx);
 
         // The epoch on the engine will only ever be incremeted if increment_epoch() is explicitly
-        // called, we only do so if a timeout has been set, it will run forever. When a timeout is
-        // set, the timeout duration is used as the duration of one epoch.
-        //
-        // Therefore, the setting of 2 here means that if a `timeout` is provided, then this
-        // interrupt will be triggered between a duration of `timeout` and `timeout * 2`.
+        // called, we only do so if a timeout has been set, it will run forever. The deadline is
+        // computed by `ValidModule::epoch_deadline`; `invoke_handler` replaces it with the
+        // deadline for the handler.
         //
         // See also: runtime-timeouts
-        store.set_epoch_deadline(2);
+        store.set_epoch_deadline(store.data().epoch_deadline);
+        if let Some(fuel) = valid_module.fuel {
+            store.set_fuel(fuel)?;
+        }
 
         // Because `gas` and `deterministic_host_trap` need to be accessed from the gas
         // host fn, they need to be separate from the rest of the context.
@@ -399,6 +437,18 @@ impl WasmInstance {
 
         link!("ethereum.encode", ethereum_encode, params_ptr);
         link!("ethereum.decode", ethereum_decode, params_ptr, data_ptr);
+        link!(
+            "ethereum.abiEncode",
+            ethereum_abi_encode,
+            signature_ptr,
+            values_ptr
+        );
+        link!(
+            "ethereum.abiDecode",
+            ethereum_abi_decode,
+            signature_ptr,
+            data_ptr
+        );
 
         link!("abort", abort, message_ptr, file_name_ptr, line, column);
 
@@ -411,6 +461,14 @@ impl WasmInstance {
             id,
             field
         );
+        link!(
+            "store.loadFrom",
+            store_load_from,
+            "host_export_store_load_from",
+            deployment,
+            entity,
+            id
+        );
         link!(
             "store.get_in_block",
             store_get_in_block,
@@ -442,6 +500,19 @@ impl WasmInstance {
             user_data,
             flags
         );
+        link!(
+            "ipfs.catBounded",
+            ipfs_cat_bounded,
+            "host_export_ipfs_cat",
+            link_ptr,
+            max_bytes
+        );
+        link!(
+            "ipfs.exists",
+            ipfs_exists,
+            "host_export_ipfs_exists",
+            link_ptr
+        );
         // The previous ipfs-related functions are unconditionally linked for backward compatibility
         if experimental_features.allow_non_deterministic_ipfs {
             link!(
@@ -467,8 +538,17 @@ impl WasmInstance {
         link!("json.toU64", json_to_u64, ptr);
         link!("json.toF64", json_to_f64, ptr);
         link!("json.toBigInt", json_to_big_int, ptr);
+        link!("json.fromEntity", json_from_entity, ptr);
+        link!("json.stringify", json_stringify, ptr);
+
+        link!("cbor.encode", cbor_encode, ptr);
+        link!("cbor.decode", cbor_decode, ptr);
 
         link!("crypto.keccak256", crypto_keccak_256, ptr);
+        link!("crypto.sha256", crypto_sha256, ptr);
+        link!("crypto.ripemd160", crypto_ripemd160, ptr);
+        link!("crypto.blake3", crypto_blake3, ptr);
+        link!("crypto.ecrecover", crypto_ecrecover, hash, signature);
 
         link!("bigInt.plus", big_int_plus, x_ptr, y_ptr);
         link!("bigInt.minus", big_int_minus, x_ptr, y_ptr);
@@ -490,6 +570,10 @@ impl WasmInstance {
         link!("bigDecimal.times", big_decimal_times, x_ptr, y_ptr);
         link!("bigDecimal.dividedBy", big_decimal_divided_by, x, y);
         link!("bigDecimal.equals", big_decimal_equals, x_ptr, y_ptr);
+        link!("bigDecimal.pow", big_decimal_pow, x_ptr, y_ptr);
+        link!("bigDecimal.sqrt", big_decimal_sqrt, x_ptr);
+        link!("bigDecimal.ln", big_decimal_ln, x_ptr);
+        link!("bigDecimal.exp", big_decimal_exp, x_ptr);
 
         link!("dataSource.create", data_source_create, name, params);
         link!(
@@ -504,6 +588,7 @@ impl WasmInstance {
         link!("dataSource.context", data_source_context,);
 
         link!("ens.nameByHash", ens_name_by_hash, ptr);
+        link!("aux.lookup", aux_lookup, dataset_ptr, key_ptr);
 
         link!("log.log", log_log, level, msg_ptr);
 
@@ -10,7 +10,7 @@ use wasmtime::AsContext;
 use wasmtime::AsContextMut;
 use wasmtime::Memory;
 
-use graph::data_source::{offchain, MappingTrigger, TriggerWithHandler};
+use graph::data_source::{offchain, subgraph, MappingTrigger, TriggerWithHandler};
 use graph::prelude::*;
 use graph::runtime::AscPtr;
 use graph::runtime::{
@@ -21,6 +21,7 @@ use graph::runtime::{
 };
 pub use into_wasm_ret::IntoWasmRet;
 
+use crate::asc_abi::class::AscEntity;
 use crate::error::DeterminismLevel;
 use crate::gas_rules::{GAS_COST_LOAD, GAS_COST_STORE};
 pub use crate::host_exports;
@@ -69,6 +70,17 @@ impl ToAscPtr for offchain::TriggerData {
     }
 }
 
+impl ToAscPtr for subgraph::TriggerData {
+    fn to_asc_ptr<H: AscHeap>(
+        self,
+        heap: &mut H,
+        gas: &GasCounter,
+    ) -> Result<AscPtr<()>, HostExportError> {
+        let entity: AscPtr<AscEntity> = asc_new(heap, &self.entity.sorted_ref(), gas)?;
+        Ok(entity.erase())
+    }
+}
+
 impl<C: Blockchain> ToAscPtr for MappingTrigger<C>
 where
     C::MappingTrigger: ToAscPtr,
@@ -81,6 +93,7 @@ where
         match self {
             MappingTrigger::Onchain(trigger) => trigger.to_asc_ptr(heap, gas),
             MappingTrigger::Offchain(trigger) => trigger.to_asc_ptr(heap, gas),
+            MappingTrigger::Subgraph(trigger) => trigger.to_asc_ptr(heap, gas),
         }
     }
 }
@@ -113,7 +126,8 @@ fn is_trap_deterministic(trap: &Error) -> bool {
         | IntegerOverflow
         | IntegerDivisionByZero
         | BadConversionToInteger
-        | UnreachableCodeReached => true,
+        | UnreachableCodeReached
+        | OutOfFuel => true,
 
         // `Interrupt`: Can be a timeout, at least as wasmtime currently implements it.
         // `StackOverflow`: We may want to have a configurable stack size.
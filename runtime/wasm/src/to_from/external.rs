@@ -1,4 +1,5 @@
 use ethabi;
+use std::collections::HashMap;
 
 use graph::data::store::scalar::Timestamp;
 use graph::data::value::Word;
@@ -409,6 +410,49 @@ impl ToAscObj<AscEnum<JsonValueKind>> for serde_json::Value {
     }
 }
 
+impl FromAscObj<AscEnum<JsonValueKind>> for serde_json::Value {
+    fn from_asc_obj<H: AscHeap + ?Sized>(
+        asc_enum: AscEnum<JsonValueKind>,
+        heap: &H,
+        gas: &GasCounter,
+        depth: usize,
+    ) -> Result<Self, DeterministicHostError> {
+        use serde_json::Value;
+
+        let payload = asc_enum.payload;
+        Ok(match asc_enum.kind {
+            JsonValueKind::Null => Value::Null,
+            JsonValueKind::Bool => Value::Bool(bool::from(payload)),
+            JsonValueKind::Number => {
+                let ptr: AscPtr<AscString> = AscPtr::from(payload);
+                let number: String = asc_get(heap, ptr, gas, depth)?;
+                let number = serde_json::from_str::<serde_json::Number>(&number).map_err(|_| {
+                    DeterministicHostError::from(anyhow::anyhow!(
+                        "`{}` is not a JSON number",
+                        number
+                    ))
+                })?;
+                Value::Number(number)
+            }
+            JsonValueKind::String => {
+                let ptr: AscPtr<AscString> = AscPtr::from(payload);
+                Value::String(asc_get(heap, ptr, gas, depth)?)
+            }
+            JsonValueKind::Array => {
+                let ptr: AscEnumArray<JsonValueKind> = AscPtr::from(payload);
+                Value::Array(asc_get(heap, ptr, gas, depth)?)
+            }
+            JsonValueKind::Object => {
+                // The keys of `serde_json::Map` are sorted, which keeps
+                // anything produced from the object deterministic
+                let ptr: AscPtr<AscJson> = AscPtr::from(payload);
+                let object: HashMap<String, Value> = asc_get(heap, ptr, gas, depth)?;
+                Value::Object(object.into_iter().collect())
+            }
+        })
+    }
+}
+
 impl From<u32> for LogLevel {
     fn from(i: u32) -> Self {
         match i {
@@ -0,0 +1,314 @@
+//! Queries that span several subgraphs. Every top-level field of a
+//! federated query has the form `key: subgraph(name: "...") { ... }`, and
+//! its selection set is run as a separate query against the subgraph with
+//! that name. The data of each of these queries is returned under `key`
+use std::collections::{HashMap, HashSet};
+
+use graph::components::server::query::ServerError;
+use graph::data::query::QueryVariables;
+use graph::prelude::{q, Query, SubgraphName};
+
+/// The name of the top-level fields that select a subgraph
+const SUBGRAPH_FIELD: &str = "subgraph";
+
+/// The part of a federated query that is run against one subgraph
+#[derive(Debug)]
+pub struct SubQuery {
+    /// The key under which the result is nested in the response
+    pub key: String,
+    pub name: SubgraphName,
+    pub query: Query,
+}
+
+fn client_error(msg: impl Into<String>) -> ServerError {
+    ServerError::ClientError(msg.into())
+}
+
+/// Add the names of all variables used in `value` to `variables`
+fn add_variables<'a>(value: &'a q::Value, variables: &mut HashSet<&'a str>) {
+    match value {
+        q::Value::Variable(name) => {
+            variables.insert(name.as_str());
+        }
+        q::Value::List(values) => values
+            .iter()
+            .for_each(|value| add_variables(value, variables)),
+        q::Value::Object(values) => values
+            .values()
+            .for_each(|value| add_variables(value, variables)),
+        _ => {}
+    }
+}
+
+fn add_directive_variables<'a>(directives: &'a [q::Directive], variables: &mut HashSet<&'a str>) {
+    for directive in directives {
+        for (_, value) in &directive.arguments {
+            add_variables(value, variables);
+        }
+    }
+}
+
+/// Split a federated `query` into one query per subgraph. Each query only
+/// contains the fragments and variables that its selection set uses since
+/// query validation rejects unused ones. Queries that select more than
+/// `max_subgraphs` subgraphs are rejected
+pub fn split(query: &Query, max_subgraphs: usize) -> Result<Vec<SubQuery>, ServerError> {
+    let mut operations = query
+        .document
+        .definitions
+        .iter()
+        .filter_map(|def| match def {
+            q::Definition::Operation(operation) => Some(operation),
+            q::Definition::Fragment(_) => None,
+        });
+    let (operation_name, variable_definitions, selection_set) =
+        match (operations.next(), operations.next()) {
+            (Some(q::OperationDefinition::SelectionSet(set)), None) => (None, &[][..], set),
+            (Some(q::OperationDefinition::Query(query)), None) => (
+                query.name.clone(),
+                &query.variable_definitions[..],
+                &query.selection_set,
+            ),
+            (Some(_), None) => {
+                return Err(client_error(
+                    "Federated queries can only contain a query operation",
+                ))
+            }
+            _ => {
+                return Err(client_error(
+                    "Federated queries must contain exactly one operation",
+                ))
+            }
+        };
+    if selection_set.items.is_empty() {
+        return Err(client_error("Federated queries must select a subgraph"));
+    }
+    if selection_set.items.len() > max_subgraphs {
+        return Err(client_error(format!(
+            "Federated queries can select at most {} subgraphs, but this query selects {}",
+            max_subgraphs,
+            selection_set.items.len()
+        )));
+    }
+
+    let fragments: HashMap<&str, &q::FragmentDefinition> = query
+        .document
+        .definitions
+        .iter()
+        .filter_map(|def| match def {
+            q::Definition::Fragment(fragment) => Some((fragment.name.as_str(), fragment)),
+            q::Definition::Operation(_) => None,
+        })
+        .collect();
+
+    let mut keys = HashSet::new();
+    selection_set
+        .items
+        .iter()
+        .map(|selection| {
+            let field = match selection {
+                q::Selection::Field(field) if field.name == SUBGRAPH_FIELD => field,
+                _ => {
+                    return Err(client_error(format!(
+                        "The top-level selections of federated queries must be `{}` fields",
+                        SUBGRAPH_FIELD
+                    )))
+                }
+            };
+            let name = match field.arguments.iter().find(|(arg, _)| arg == "name") {
+                Some((_, q::Value::String(name))) => SubgraphName::new(name.as_str())
+                    .map_err(|()| client_error(format!("Invalid subgraph name {:?}", name)))?,
+                _ => {
+                    return Err(client_error(format!(
+                        "The `{}` field requires a string argument `name`",
+                        SUBGRAPH_FIELD
+                    )))
+                }
+            };
+            let key = field.alias.clone().unwrap_or_else(|| field.name.clone());
+            if !keys.insert(key.clone()) {
+                return Err(client_error(format!(
+                    "The key `{}` is used for more than one subgraph",
+                    key
+                )));
+            }
+
+            // Find the fragments and variables that the selection set uses
+            let mut used_fragments = HashSet::new();
+            let mut used_variables = HashSet::new();
+            let mut pending = vec![&field.selection_set];
+            while let Some(set) = pending.pop() {
+                for selection in &set.items {
+                    match selection {
+                        q::Selection::Field(field) => {
+                            for (_, value) in &field.arguments {
+                                add_variables(value, &mut used_variables);
+                            }
+                            add_directive_variables(&field.directives, &mut used_variables);
+                            pending.push(&field.selection_set);
+                        }
+                        q::Selection::FragmentSpread(spread) => {
+                            add_directive_variables(&spread.directives, &mut used_variables);
+                            let name = spread.fragment_name.as_str();
+                            // Unknown fragments are reported by validation
+                            if let Some(fragment) = fragments.get(name) {
+                                if used_fragments.insert(name) {
+                                    add_directive_variables(
+                                        &fragment.directives,
+                                        &mut used_variables,
+                                    );
+                                    pending.push(&fragment.selection_set);
+                                }
+                            }
+                        }
+                        q::Selection::InlineFragment(fragment) => {
+                            add_directive_variables(&fragment.directives, &mut used_variables);
+                            pending.push(&fragment.selection_set);
+                        }
+                    }
+                }
+            }
+
+            let operation = q::Query {
+                position: field.position,
+                name: operation_name.clone(),
+                variable_definitions: variable_definitions
+                    .iter()
+                    .filter(|def| used_variables.contains(def.name.as_str()))
+                    .cloned()
+                    .collect(),
+                directives: vec![],
+                selection_set: field.selection_set.clone(),
+            };
+            let mut definitions = vec![q::Definition::Operation(q::OperationDefinition::Query(
+                operation,
+            ))];
+            definitions.extend(
+                query
+                    .document
+                    .definitions
+                    .iter()
+                    .filter(|def| match def {
+                        q::Definition::Fragment(fragment) => {
+                            used_fragments.contains(fragment.name.as_str())
+                        }
+                        q::Definition::Operation(_) => false,
+                    })
+                    .cloned(),
+            );
+            let variables = query.variables.as_ref().map(|variables| {
+                QueryVariables::new(
+                    variables
+                        .iter()
+                        .filter(|(name, _)| used_variables.contains(name.as_str()))
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect(),
+                )
+            });
+
+            Ok(SubQuery {
+                key,
+                name,
+                query: Query::new(q::Document { definitions }, variables, query.trace)
+                    .with_consistency_token(query.consistency_token.clone()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use graph::components::server::query::ServerError;
+    use graph::data::query::QueryVariables;
+    use graph::prelude::{q, r, Query};
+
+    use super::split;
+
+    fn query(text: &str, variables: Vec<(&str, r::Value)>) -> Query {
+        let document = q::parse_query(text).unwrap().into_static();
+        let variables = QueryVariables::new(HashMap::from_iter(
+            variables
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value)),
+        ));
+        Query::new(document, Some(variables), false)
+    }
+
+    #[track_caller]
+    fn assert_query(expected: &str, query: &Query) {
+        let expected = q::parse_query(expected).unwrap().into_static();
+        assert_eq!(expected.to_string(), query.document.to_string());
+    }
+
+    #[test]
+    fn splits_by_subgraph() {
+        let federated = query(
+            r#"
+            query tokens($first: Int, $skip: Int) {
+              uni: subgraph(name: "uniswap/v2") { pairs(first: $first) { ...pair } }
+              sushi: subgraph(name: "sushi/exchange") { tokens(skip: $skip) { id } }
+            }
+            fragment pair on Pair { id token0 { id } }
+            "#,
+            vec![("first", r::Value::Int(5)), ("skip", r::Value::Int(10))],
+        );
+
+        let queries = split(&federated, 10).unwrap();
+        assert_eq!(2, queries.len());
+
+        assert_eq!("uni", queries[0].key);
+        assert_eq!("uniswap/v2", queries[0].name.as_str());
+        assert_query(
+            "query tokens($first: Int) { pairs(first: $first) { ...pair } }
+             fragment pair on Pair { id token0 { id } }",
+            &queries[0].query,
+        );
+        let variables = queries[0].query.variables.as_ref().unwrap();
+        assert_eq!(vec!["first"], variables.keys().collect::<Vec<_>>());
+
+        assert_eq!("sushi", queries[1].key);
+        assert_eq!("sushi/exchange", queries[1].name.as_str());
+        assert_query(
+            "query tokens($skip: Int) { tokens(skip: $skip) { id } }",
+            &queries[1].query,
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        let invalid = [
+            r#"{ pairs { id } }"#,
+            r#"{ subgraph(id: "QmHash") { pairs { id } } }"#,
+            r#"{ a: subgraph(name: "a") { pairs { id } } a: subgraph(name: "b") { pairs { id } } }"#,
+            r#"mutation { subgraph(name: "a") { pairs { id } } }"#,
+            r#"query a { a: subgraph(name: "a") { id } } query b { b: subgraph(name: "b") { id } }"#,
+        ];
+        for text in invalid {
+            let res = split(&query(text, vec![]), 10);
+            assert!(
+                matches!(res, Err(ServerError::ClientError(_))),
+                "query `{}` is rejected",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn limits_number_of_subgraphs() {
+        let federated = query(
+            r#"{
+              a: subgraph(name: "a") { pairs { id } }
+              b: subgraph(name: "b") { pairs { id } }
+              c: subgraph(name: "c") { pairs { id } }
+            }"#,
+            vec![],
+        );
+        assert_eq!(3, split(&federated, 3).unwrap().len());
+        assert!(matches!(
+            split(&federated, 2),
+            Err(ServerError::ClientError(_))
+        ));
+    }
+}
@@ -2,6 +2,8 @@ extern crate graph;
 extern crate graph_graphql;
 extern crate serde;
 
+mod federation;
+mod persisted_query;
 mod request;
 mod server;
 mod service;
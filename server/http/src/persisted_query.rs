@@ -0,0 +1,255 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use graph::components::server::query::ServerError;
+use graph::components::store::PersistedQueryStore;
+use graph::env::ENV_VARS;
+use graph::prelude::{hex, CheapClone, Logger};
+use graph::slog::warn;
+use lru_time_cache::LruCache;
+use sha2::{Digest, Sha256};
+
+/// How long we remember that a hash is not in the store before asking the
+/// store again. Query nodes that share the store might add the query in
+/// the meantime
+const UNKNOWN_TTL: Duration = Duration::from_secs(60);
+
+/// Query texts for Automatic Persisted Queries (APQ). Clients send the
+/// sha256 hash of a query instead of the query text, and only send the
+/// query text together with its hash when we respond with
+/// `PersistedQueryNotFound`. Query texts are kept in an LRU cache, and, if
+/// a `store` is given, also persisted there. Hashes that the `store` does
+/// not know are remembered for a while so that clients that keep sending
+/// unknown hashes do not cause a lookup in the store for each request
+pub struct PersistedQueries {
+    logger: Logger,
+    cache: Option<Mutex<LruCache<String, Arc<String>>>>,
+    unknown: Mutex<LruCache<String, ()>>,
+    store: Option<Arc<dyn PersistedQueryStore>>,
+}
+
+/// A query text that a client sent together with its hash and that we do
+/// not know yet. It is only remembered with `PersistedQueries::insert`
+/// once the query has been validated
+pub struct NewQuery {
+    hash: String,
+    query: String,
+}
+
+impl PersistedQueries {
+    pub fn new(logger: Logger, store: Option<Arc<dyn PersistedQueryStore>>) -> Self {
+        let cache_size = ENV_VARS.graphql.persisted_query_cache_size;
+        let cache = (cache_size > 0).then(|| Mutex::new(LruCache::with_capacity(cache_size)));
+        let unknown = Mutex::new(LruCache::with_expiry_duration_and_capacity(
+            UNKNOWN_TTL,
+            cache_size.max(1),
+        ));
+        PersistedQueries {
+            logger,
+            cache,
+            unknown,
+            store,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.cache.is_some()
+    }
+
+    /// Return the query text for `hash` if we know it
+    pub async fn get(&self, hash: &str) -> Option<Arc<String>> {
+        let cache = self.cache.as_ref()?;
+        let hash = hash.to_lowercase();
+        if let Some(query) = cache.lock().unwrap().get(&hash) {
+            return Some(query.cheap_clone());
+        }
+
+        let store = self.store.as_ref()?.cheap_clone();
+        if self.unknown.lock().unwrap().get(&hash).is_some() {
+            return None;
+        }
+        let key = hash.clone();
+        let res = graph::spawn_blocking_allow_panic(move || store.find_query(&key))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|res| res.map_err(|e| e.to_string()));
+        let query = match res {
+            Ok(Some(query)) => query,
+            Ok(None) => {
+                self.unknown.lock().unwrap().insert(hash, ());
+                return None;
+            }
+            Err(e) => {
+                warn!(self.logger, "Failed to look up persisted query";
+                      "hash" => &hash, "error" => e);
+                return None;
+            }
+        };
+        let query = Arc::new(query);
+        cache.lock().unwrap().insert(hash, query.cheap_clone());
+        Some(query)
+    }
+
+    /// Check that `hash` is the sha256 hash of `query` and fail with a
+    /// client error if it is not. Return the query if it should be
+    /// remembered once it has been validated; queries that we already know
+    /// and queries that are longer than
+    /// `GRAPH_GRAPHQL_PERSISTED_QUERY_MAX_SIZE` are not remembered
+    pub fn check(&self, hash: &str, query: &str) -> Result<Option<NewQuery>, ServerError> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return Ok(None),
+        };
+        let hash = hash.to_lowercase();
+        if hex::encode(Sha256::digest(query.as_bytes())) != hash {
+            return Err(ServerError::ClientError(format!(
+                "provided sha256 hash `{}` does not match query",
+                hash
+            )));
+        }
+
+        if query.len() > ENV_VARS.graphql.persisted_query_max_size
+            || cache.lock().unwrap().get(&hash).is_some()
+        {
+            return Ok(None);
+        }
+        Ok(Some(NewQuery {
+            hash,
+            query: query.to_string(),
+        }))
+    }
+
+    /// Remember `query`. This must only be called for queries that are
+    /// valid
+    pub async fn insert(&self, query: NewQuery) {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return,
+        };
+        let NewQuery { hash, query } = query;
+
+        self.unknown.lock().unwrap().remove(&hash);
+        let known = cache
+            .lock()
+            .unwrap()
+            .insert(hash.clone(), Arc::new(query.clone()))
+            .is_some();
+        if known {
+            return;
+        }
+
+        if let Some(store) = self.store.as_ref() {
+            let store = store.cheap_clone();
+            let key = hash.clone();
+            let res = graph::spawn_blocking_allow_panic(move || store.add_query(&key, &query))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|res| res.map_err(|e| e.to_string()));
+            if let Err(e) = res {
+                warn!(self.logger, "Failed to store persisted query";
+                      "hash" => &hash, "error" => e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use graph::components::store::PersistedQueryStore;
+    use graph::prelude::*;
+    use sha2::{Digest, Sha256};
+
+    use super::PersistedQueries;
+
+    const QUERY: &str = "{ name }";
+    const WRONG_HASH: &str = "6e8f3c8e2d2d55b5f8fd8c4d48ba0e5f34f9ebf0d0d72a1a6b6f4e4bb8e6ae2d";
+
+    fn hash(query: &str) -> String {
+        hex::encode(Sha256::digest(query.as_bytes()))
+    }
+
+    /// A store that does not know any queries and counts lookups
+    #[derive(Default)]
+    struct EmptyStore {
+        lookups: AtomicUsize,
+        added: AtomicUsize,
+    }
+
+    impl PersistedQueryStore for EmptyStore {
+        fn find_query(&self, _hash: &str) -> Result<Option<String>, StoreError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(None)
+        }
+
+        fn add_query(&self, _hash: &str, _query: &str) -> Result<(), StoreError> {
+            self.added.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn remembers_queries() {
+        let logger = Logger::root(slog::Discard, o!());
+        let queries = PersistedQueries::new(logger, None);
+        let hash = hash(QUERY);
+
+        assert!(queries.get(&hash).await.is_none());
+        let new_query = queries.check(&hash, QUERY).unwrap().unwrap();
+        // Until the query is inserted, we do not know it
+        assert!(queries.get(&hash).await.is_none());
+        queries.insert(new_query).await;
+        assert_eq!(QUERY, queries.get(&hash).await.unwrap().as_str());
+        assert_eq!(
+            QUERY,
+            queries.get(&hash.to_uppercase()).await.unwrap().as_str()
+        );
+        // A query we already know does not need to be inserted again
+        assert!(queries.check(&hash, QUERY).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_hash() {
+        let logger = Logger::root(slog::Discard, o!());
+        let queries = PersistedQueries::new(logger, None);
+
+        assert_ne!(WRONG_HASH, hash(QUERY));
+        assert!(
+            queries.check(WRONG_HASH, QUERY).is_err(),
+            "hash does not match query"
+        );
+        assert!(queries.get(WRONG_HASH).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn does_not_remember_large_queries() {
+        let logger = Logger::root(slog::Discard, o!());
+        let queries = PersistedQueries::new(logger, None);
+        let query = format!(
+            "{{ {} }}",
+            "name ".repeat(ENV_VARS.graphql.persisted_query_max_size / 5 + 1)
+        );
+
+        assert!(queries.check(&hash(&query), &query).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn remembers_unknown_hashes() {
+        let logger = Logger::root(slog::Discard, o!());
+        let store = Arc::new(EmptyStore::default());
+        let queries = PersistedQueries::new(logger, Some(store.clone()));
+        let hash = hash(QUERY);
+
+        assert!(queries.get(&hash).await.is_none());
+        assert!(queries.get(&hash).await.is_none());
+        assert_eq!(1, store.lookups.load(Ordering::SeqCst));
+
+        // Inserting the query makes it known right away
+        let new_query = queries.check(&hash, QUERY).unwrap().unwrap();
+        queries.insert(new_query).await;
+        assert_eq!(1, store.added.load(Ordering::SeqCst));
+        assert_eq!(QUERY, queries.get(&hash).await.unwrap().as_str());
+        assert_eq!(1, store.lookups.load(Ordering::SeqCst));
+    }
+}
@@ -4,45 +4,111 @@ use graph::components::server::query::ServerError;
 use graph::hyper::body::Bytes;
 use graph::prelude::*;
 
+/// The JSON object sent as the body of a GraphQL request
+pub struct GraphQLRequest {
+    obj: serde_json::Map<String, serde_json::Value>,
+}
+
+impl GraphQLRequest {
+    pub fn parse(body: &Bytes) -> Result<Self, ServerError> {
+        // Parse request body as JSON
+        let json: serde_json::Value =
+            serde_json::from_slice(body).map_err(|e| ServerError::ClientError(format!("{}", e)))?;
+
+        // Ensure the JSON data is an object
+        match json {
+            serde_json::Value::Object(obj) => Ok(GraphQLRequest { obj }),
+            _ => Err(ServerError::ClientError(String::from(
+                "Request data is not an object",
+            ))),
+        }
+    }
+
+    /// The text of the query, if the request has one. Requests for
+    /// persisted queries might only send the hash of the query
+    pub fn query(&self) -> Result<Option<&str>, ServerError> {
+        match self.obj.get("query") {
+            None => Ok(None),
+            // Ensure the "query" field is a string
+            Some(query) => query.as_str().map(Some).ok_or_else(|| {
+                ServerError::ClientError(String::from("The \"query\" field is not a string"))
+            }),
+        }
+    }
+
+    /// Like `query`, but fail if the request does not have a query
+    pub fn required_query(&self) -> Result<&str, ServerError> {
+        // Ensure the JSON data has a "query" field
+        self.query()?.ok_or_else(|| {
+            ServerError::ClientError(String::from(
+                "The \"query\" field is missing in request data",
+            ))
+        })
+    }
+
+    /// The sha256 hash of the query from the `persistedQuery` extension
+    /// used for Automatic Persisted Queries, if the request has one
+    pub fn persisted_query_hash(&self) -> Result<Option<&str>, ServerError> {
+        let persisted = match self
+            .obj
+            .get("extensions")
+            .and_then(|extensions| extensions.get("persistedQuery"))
+        {
+            None | Some(serde_json::Value::Null) => return Ok(None),
+            Some(persisted) => persisted,
+        };
+
+        match persisted
+            .get("version")
+            .and_then(|version| version.as_i64())
+        {
+            Some(1) => {}
+            _ => {
+                return Err(ServerError::ClientError(String::from(
+                    "Unsupported persisted query version",
+                )))
+            }
+        }
+
+        persisted
+            .get("sha256Hash")
+            .and_then(|hash| hash.as_str())
+            .map(Some)
+            .ok_or_else(|| {
+                ServerError::ClientError(String::from(
+                    "The \"sha256Hash\" of the persisted query is missing or not a string",
+                ))
+            })
+    }
+
+    /// Turn the request into a `Query` with the given `query_string`,
+    /// which is usually the `query` of the request
+    pub fn to_query(&self, query_string: &str, trace: bool) -> Result<Query, ServerError> {
+        // Parse the "query" field of the JSON body
+        let document = q::parse_query(query_string)
+            .map_err(|e| ServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
+            .into_static();
+
+        // Parse the "variables" field of the JSON body, if present
+        let variables = match self.obj.get("variables") {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(variables @ serde_json::Value::Object(_)) => {
+                serde_json::from_value(variables.clone())
+                    .map_err(|e| ServerError::ClientError(e.to_string()))
+                    .map(Some)
+            }
+            _ => Err(ServerError::ClientError(
+                "Invalid query variables provided".to_string(),
+            )),
+        }?;
+
+        Ok(Query::new(document, variables, trace))
+    }
+}
+
 pub fn parse_graphql_request(body: &Bytes, trace: bool) -> Result<Query, ServerError> {
-    // Parse request body as JSON
-    let json: serde_json::Value =
-        serde_json::from_slice(body).map_err(|e| ServerError::ClientError(format!("{}", e)))?;
-
-    // Ensure the JSON data is an object
-    let obj = json
-        .as_object()
-        .ok_or_else(|| ServerError::ClientError(String::from("Request data is not an object")))?;
-
-    // Ensure the JSON data has a "query" field
-    let query_value = obj.get("query").ok_or_else(|| {
-        ServerError::ClientError(String::from(
-            "The \"query\" field is missing in request data",
-        ))
-    })?;
-
-    // Ensure the "query" field is a string
-    let query_string = query_value.as_str().ok_or_else(|| {
-        ServerError::ClientError(String::from("The \"query\" field is not a string"))
-    })?;
-
-    // Parse the "query" field of the JSON body
-    let document = q::parse_query(query_string)
-        .map_err(|e| ServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
-        .into_static();
-
-    // Parse the "variables" field of the JSON body, if present
-    let variables = match obj.get("variables") {
-        None | Some(serde_json::Value::Null) => Ok(None),
-        Some(variables @ serde_json::Value::Object(_)) => serde_json::from_value(variables.clone())
-            .map_err(|e| ServerError::ClientError(e.to_string()))
-            .map(Some),
-        _ => Err(ServerError::ClientError(
-            "Invalid query variables provided".to_string(),
-        )),
-    }?;
-
-    Ok(Query::new(document, variables, trace))
+    let request = GraphQLRequest::parse(body)?;
+    request.to_query(request.required_query()?, trace)
 }
 
 #[cfg(test)]
@@ -59,7 +125,7 @@ mod tests {
         prelude::*,
     };
 
-    use super::parse_graphql_request;
+    use super::{parse_graphql_request, GraphQLRequest};
 
     lazy_static! {
         static ref TARGET: QueryTarget = QueryTarget::Name(
@@ -171,4 +237,27 @@ mod tests {
         assert_eq!(query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    #[test]
+    fn parses_persisted_query_hash() {
+        let body = Bytes::from(
+            "{\"extensions\": {\"persistedQuery\": \
+             {\"version\": 1, \"sha256Hash\": \"abc\"}}}",
+        );
+        let request = GraphQLRequest::parse(&body).unwrap();
+        assert_eq!(Some("abc"), request.persisted_query_hash().unwrap());
+        assert_eq!(None, request.query().unwrap());
+        request
+            .required_query()
+            .expect_err("Should reject requests without query text");
+
+        let body = Bytes::from(
+            "{\"extensions\": {\"persistedQuery\": \
+             {\"version\": 2, \"sha256Hash\": \"abc\"}}}",
+        );
+        let request = GraphQLRequest::parse(&body).unwrap();
+        request
+            .persisted_query_hash()
+            .expect_err("Should reject unknown persisted query versions");
+    }
 }
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use graph::anyhow;
 use graph::cheap_clone::CheapClone;
 use graph::components::server::server::{start, ServerHandle};
+use graph::components::store::PersistedQueryStore;
 use graph::log::factory::{ComponentLoggerConfig, ElasticComponentLoggerConfig};
 use graph::slog::info;
 
@@ -13,11 +14,17 @@ use graph::prelude::{GraphQlRunner, Logger, LoggerFactory};
 pub struct GraphQLServer<Q> {
     logger: Logger,
     graphql_runner: Arc<Q>,
+    persisted_query_store: Option<Arc<dyn PersistedQueryStore>>,
 }
 
 impl<Q: GraphQlRunner> GraphQLServer<Q> {
-    /// Creates a new GraphQL server.
-    pub fn new(logger_factory: &LoggerFactory, graphql_runner: Arc<Q>) -> Self {
+    /// Creates a new GraphQL server. The texts of persisted queries are
+    /// also stored in the `persisted_query_store` if one is given
+    pub fn new(
+        logger_factory: &LoggerFactory,
+        graphql_runner: Arc<Q>,
+        persisted_query_store: Option<Arc<dyn PersistedQueryStore>>,
+    ) -> Self {
         let logger = logger_factory.component_logger(
             "GraphQLServer",
             Some(ComponentLoggerConfig {
@@ -29,6 +36,7 @@ impl<Q: GraphQlRunner> GraphQLServer<Q> {
         GraphQLServer {
             logger,
             graphql_runner,
+            persisted_query_store,
         }
     }
 
@@ -42,7 +50,12 @@ impl<Q: GraphQlRunner> GraphQLServer<Q> {
 
         let graphql_runner = self.graphql_runner.clone();
 
-        let service = Arc::new(GraphQLService::new(logger.clone(), graphql_runner, ws_port));
+        let service = Arc::new(GraphQLService::new(
+            logger.clone(),
+            graphql_runner,
+            ws_port,
+            self.persisted_query_store.cheap_clone(),
+        ));
 
         start(logger, port, move |req| {
             let service = service.cheap_clone();
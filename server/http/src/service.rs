@@ -1,33 +1,50 @@
 use std::convert::TryFrom;
 use std::env;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 
 use graph::cheap_clone::CheapClone;
 use graph::components::graphql::GraphQlRunner;
 use graph::components::server::query::ServerResponse;
 use graph::components::server::query::ServerResult;
+use graph::components::server::query::StreamingResponse;
+use graph::components::store::PersistedQueryStore;
 use graph::components::versions::ApiVersion;
-use graph::data::query::QueryResult;
+use graph::data::query::{
+    ConsistencyToken, QueryError, QueryResult, QueryResults, Trace, CONSISTENCY_TOKEN_HEADER,
+};
 use graph::data::subgraph::DeploymentHash;
 use graph::data::subgraph::SubgraphName;
 use graph::env::ENV_VARS;
+use graph::futures03::future::join_all;
 use graph::http_body_util::{BodyExt, Full};
 use graph::hyper::header::{
-    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
-    CONTENT_LENGTH, CONTENT_TYPE, LOCATION,
+    ACCEPT, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_LENGTH, CONTENT_TYPE, LOCATION,
 };
-use graph::hyper::{body::Body, header::HeaderValue};
+use graph::hyper::{body::Body, body::Bytes, header::HeaderValue};
 use graph::hyper::{Method, Request, Response, StatusCode};
-use graph::prelude::serde_json;
 use graph::prelude::serde_json::json;
+use graph::prelude::{serde_json, Query, QueryExecutionError};
 use graph::semver::VersionReq;
 use graph::slog::error;
 use graph::slog::Logger;
 use graph::url::form_urlencoded;
 use graph::{components::server::query::ServerError, data::query::QueryTarget};
 
-use crate::request::parse_graphql_request;
+use crate::federation::{self, SubQuery};
+use crate::persisted_query::{NewQuery, PersistedQueries};
+use crate::request::GraphQLRequest;
+
+/// Whether `error` says that the query does not validate against the
+/// schema of the subgraph it is run against
+fn is_validation_error(error: &QueryError) -> bool {
+    match error {
+        QueryError::ExecutionError(QueryExecutionError::ValidationError(_, _)) => true,
+        QueryError::Subgraph(_, error) => is_validation_error(error),
+        _ => false,
+    }
+}
 
 fn client_error(msg: impl Into<String>) -> ServerResponse {
     let response_obj = json!({
@@ -43,24 +60,47 @@ fn client_error(msg: impl Into<String>) -> ServerResponse {
         .unwrap()
 }
 
+type StreamingResult = Result<StreamingResponse, ServerError>;
+
+/// Turn a response whose body is sent in one piece into one that can be
+/// returned where the body might be sent incrementally
+fn streaming(response: ServerResponse) -> StreamingResponse {
+    response.map(|body| body.boxed_unsync())
+}
+
+/// What a query is run against
+enum Target {
+    Subgraph(QueryTarget),
+    /// Split the query into queries against several subgraphs by name
+    Federated(ApiVersion),
+}
+
 /// A Hyper Service that serves GraphQL over a POST / endpoint.
-#[derive(Debug)]
 pub struct GraphQLService<Q> {
     logger: Logger,
     graphql_runner: Arc<Q>,
     ws_port: u16,
+    persisted_queries: PersistedQueries,
 }
 
 impl<Q> GraphQLService<Q>
 where
     Q: GraphQlRunner,
 {
-    /// Creates a new GraphQL service.
-    pub fn new(logger: Logger, graphql_runner: Arc<Q>, ws_port: u16) -> Self {
+    /// Creates a new GraphQL service. If a `persisted_query_store` is
+    /// given, the texts of persisted queries are also stored there
+    pub fn new(
+        logger: Logger,
+        graphql_runner: Arc<Q>,
+        ws_port: u16,
+        persisted_query_store: Option<Arc<dyn PersistedQueryStore>>,
+    ) -> Self {
+        let persisted_queries = PersistedQueries::new(logger.clone(), persisted_query_store);
         GraphQLService {
             logger,
             graphql_runner,
             ws_port,
+            persisted_queries,
         }
     }
 
@@ -72,7 +112,8 @@ where
     async fn index(&self) -> ServerResult {
         let response_obj = json!({
             "message": "Access deployed subgraphs by deployment ID at \
-                        /subgraphs/id/<ID> or by name at /subgraphs/name/<NAME>"
+                        /subgraphs/id/<ID> or by name at /subgraphs/name/<NAME>, \
+                        and several subgraphs at once at /subgraphs/federated"
         });
         let response_str = serde_json::to_string(&response_obj).unwrap();
 
@@ -127,35 +168,91 @@ where
         &self,
         subgraph_name: String,
         request: Request<T>,
-    ) -> ServerResult {
+    ) -> StreamingResult {
         let version = self.resolve_api_version(&request)?;
         let subgraph_name = SubgraphName::new(subgraph_name.as_str()).map_err(|()| {
             ServerError::ClientError(format!("Invalid subgraph name {:?}", subgraph_name))
         })?;
 
-        self.handle_graphql_query(QueryTarget::Name(subgraph_name, version), request)
-            .await
+        let target = Target::Subgraph(QueryTarget::Name(subgraph_name, version));
+        self.handle_graphql_query(target, request).await
     }
 
     async fn handle_graphql_query_by_id<T: Body>(
         &self,
         id: String,
         request: Request<T>,
-    ) -> ServerResult {
+    ) -> StreamingResult {
         let id = DeploymentHash::new(id)
             .map_err(|id| ServerError::ClientError(format!("Invalid subgraph id `{}`", id)))?;
         let version = self.resolve_api_version(&request)?;
 
-        self.handle_graphql_query(QueryTarget::Deployment(id, version), request)
+        let target = Target::Subgraph(QueryTarget::Deployment(id, version));
+        self.handle_graphql_query(target, request).await
+    }
+
+    async fn handle_federated_query<T: Body>(&self, request: Request<T>) -> StreamingResult {
+        let version = self.resolve_api_version(&request)?;
+        self.handle_graphql_query(Target::Federated(version), request)
             .await
     }
 
+    /// Run the queries against the individual subgraphs that make up the
+    /// federated `query` in parallel and combine their results
+    async fn run_federated_query(
+        &self,
+        query: Query,
+        version: ApiVersion,
+    ) -> Result<QueryResults, ServerError> {
+        let queries = federation::split(&query, ENV_VARS.graphql.max_federated_subgraphs)?;
+        let results = join_all(queries.into_iter().map(|SubQuery { key, name, query }| {
+            let runner = self.graphql_runner.cheap_clone();
+            let target = QueryTarget::Name(name, version.clone());
+            async move { (key, runner.run_query(query, target).await) }
+        }))
+        .await;
+        Ok(QueryResults::nested(results))
+    }
+
+    /// Turn the request `body` into a query. If the request uses a
+    /// persisted query, look up its text. If the request contains the text
+    /// of a persisted query we do not know yet, also return that so it can
+    /// be remembered once the query has been validated
+    async fn parse_request(
+        &self,
+        body: &Bytes,
+        trace: bool,
+    ) -> Result<(Query, Option<NewQuery>), ServerError> {
+        let request = GraphQLRequest::parse(body)?;
+        let hash = match request.persisted_query_hash()? {
+            Some(hash) if self.persisted_queries.is_enabled() => hash,
+            Some(_) | None => {
+                return request
+                    .to_query(request.required_query()?, trace)
+                    .map(|query| (query, None))
+            }
+        };
+
+        match request.query()? {
+            Some(text) => {
+                let new_query = self.persisted_queries.check(hash, text)?;
+                let query = request.to_query(text, trace)?;
+                Ok((query, new_query))
+            }
+            None => match self.persisted_queries.get(hash).await {
+                Some(text) => request.to_query(&text, trace).map(|query| (query, None)),
+                None => Err(ServerError::QueryError(QueryError::PersistedQueryNotFound)),
+            },
+        }
+    }
+
     async fn handle_graphql_query<T: Body>(
         &self,
-        target: QueryTarget,
+        target: Target,
         request: Request<T>,
-    ) -> ServerResult {
+    ) -> StreamingResult {
         let start = Instant::now();
+        let start_time = SystemTime::now();
         let trace = {
             !ENV_VARS.graphql.query_trace_token.is_empty()
                 && request
@@ -168,25 +265,71 @@ where
                     })
                     .unwrap_or(false)
         };
+        // Clients can only ask for tracing per request if they also have
+        // the trace token since tracing makes queries more expensive
+        let apollo_tracing = ENV_VARS.graphql.apollo_tracing
+            || (trace
+                && request
+                    .headers()
+                    .get("X-GraphQL-Trace")
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.eq_ignore_ascii_case("true"))
+                    .unwrap_or(false));
+        let accepts_multipart = request
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .any(|v| v.contains("multipart/mixed"));
+        let consistency_token = request
+            .headers()
+            .get(CONSISTENCY_TOKEN_HEADER)
+            .map(|v| {
+                v.to_str()
+                    .map_err(|e| e.to_string())
+                    .and_then(|v| v.parse::<ConsistencyToken>().map_err(|e| e.to_string()))
+                    .map_err(ServerError::ClientError)
+            })
+            .transpose()?;
         let body = request
             .collect()
             .await
             .map_err(|_| ServerError::InternalError("Failed to read request body".into()))?
             .to_bytes();
-        let query = parse_graphql_request(&body, trace);
+        let (query, new_query) = match self.parse_request(&body, trace || apollo_tracing).await {
+            Ok((query, new_query)) => (
+                Ok(query
+                    .with_consistency_token(consistency_token)
+                    .with_incremental_delivery(accepts_multipart)),
+                new_query,
+            ),
+            Err(e) => (Err(e), None),
+        };
         let query_parsing_time = start.elapsed();
 
-        let mut result = match query {
-            Ok(query) => {
+        let mut result = match (query, target) {
+            (Ok(query), Target::Subgraph(target)) => {
                 self.graphql_runner
                     .cheap_clone()
                     .run_query(query, target)
                     .await
             }
-            Err(ServerError::QueryError(e)) => QueryResult::from(e).into(),
-            Err(e) => return Err(e),
+            (Ok(query), Target::Federated(version)) => {
+                self.run_federated_query(query, version).await?
+            }
+            (Err(ServerError::QueryError(e)), _) => QueryResult::from(e).into(),
+            (Err(e), _) => return Err(e),
         };
 
+        // Only remember the text of a persisted query once we know that
+        // it is valid so that clients can not fill the cache and the store
+        // with garbage
+        if let Some(new_query) = new_query {
+            if !result.errors().iter().any(is_validation_error) {
+                self.persisted_queries.insert(new_query).await;
+            }
+        }
+
         result.trace.query_parsing(query_parsing_time);
         self.graphql_runner
             .metrics()
@@ -195,7 +338,24 @@ where
             .metrics()
             .observe_query_execution(start.elapsed(), &result);
 
-        Ok(result.as_http_response())
+        if apollo_tracing {
+            let elapsed = start.elapsed();
+            let tracing = result
+                .trace
+                .apollo_tracing(start_time, query_parsing_time, elapsed);
+            result.add_extension("tracing", tracing);
+            // The detailed trace contains the SQL text of queries and must
+            // only be sent to clients that have the trace token
+            if !trace {
+                result.trace = Trace::None;
+            }
+        }
+
+        if accepts_multipart && result.is_incremental() {
+            Ok(result.into_multipart_http_response())
+        } else {
+            Ok(streaming(result.as_http_response()))
+        }
     }
 
     // Handles OPTIONS requests
@@ -203,7 +363,10 @@ where
         Ok(Response::builder()
             .status(200)
             .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-            .header(ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, User-Agent")
+            .header(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                format!("Content-Type, User-Agent, {}", CONSISTENCY_TOKEN_HEADER),
+            )
             .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS, POST")
             .header(CONTENT_TYPE, "text/html; charset=utf-8")
             .body(Full::from(""))
@@ -263,7 +426,7 @@ where
         false
     }
 
-    async fn handle_call<T: Body>(&self, req: Request<T>) -> ServerResult {
+    async fn handle_call<T: Body>(&self, req: Request<T>) -> StreamingResult {
         let method = req.method().clone();
 
         let path = req.uri().path().to_owned();
@@ -283,11 +446,11 @@ where
 
         if !less_strict_graphql_compliance {
             if method == Method::POST && (content_type.is_none()) {
-                return self.handle_requests_without_content_type();
+                return self.handle_requests_without_content_type().map(streaming);
             }
 
             if method == Method::POST && !self.has_request_body(&req) {
-                return self.handle_requests_without_body();
+                return self.handle_requests_without_body().map(streaming);
             }
         }
 
@@ -314,46 +477,55 @@ where
             .to_lowercase()
             .starts_with("mutation");
         match (method, path_segments.as_slice()) {
-            (Method::GET, [""]) => self.index().await,
+            (Method::GET, [""]) => self.index().await.map(streaming),
             (Method::GET, &["subgraphs", "id", _, "graphql"])
             | (Method::GET, &["subgraphs", "name", .., "graphql"])
             | (Method::GET, &["subgraphs", "network", _, _, "graphql"])
-            | (Method::GET, &["subgraphs", "graphql"]) => self.handle_graphiql(),
+            | (Method::GET, &["subgraphs", "graphql"]) => self.handle_graphiql().map(streaming),
 
             (Method::GET, _path @ ["subgraphs", "name", ..]) if is_mutation => {
-                self.handle_mutations()
+                self.handle_mutations().map(streaming)
             }
             (Method::GET, path @ ["subgraphs", "id", _])
             | (Method::GET, path @ ["subgraphs", "name", ..])
             | (Method::GET, path @ ["subgraphs", "network", _, _]) => {
                 let filtered_path = filter_and_join_segments(path);
                 let dest = format!("/{}/graphql", filtered_path);
-                self.handle_temp_redirect(dest)
+                self.handle_temp_redirect(dest).map(streaming)
             }
 
             (Method::POST, &["subgraphs", "id", subgraph_id]) => {
                 self.handle_graphql_query_by_id(subgraph_id.to_owned(), req)
                     .await
             }
-            (Method::OPTIONS, ["subgraphs", "id", _]) => self.handle_graphql_options(req),
+            (Method::OPTIONS, ["subgraphs", "id", _]) => {
+                self.handle_graphql_options(req).map(streaming)
+            }
             (Method::POST, path @ ["subgraphs", "name", ..]) => {
                 let subgraph_name = filter_and_join_segments(&path[2..]);
                 self.handle_graphql_query_by_name(subgraph_name, req).await
             }
 
-            (Method::OPTIONS, ["subgraphs", "name", ..]) => self.handle_graphql_options(req),
+            (Method::OPTIONS, ["subgraphs", "name", ..]) => {
+                self.handle_graphql_options(req).map(streaming)
+            }
+
+            (Method::POST, &["subgraphs", "federated"]) => self.handle_federated_query(req).await,
+            (Method::OPTIONS, ["subgraphs", "federated"]) => {
+                self.handle_graphql_options(req).map(streaming)
+            }
 
-            _ => self.handle_not_found(),
+            _ => self.handle_not_found().map(streaming),
         }
     }
 
-    pub async fn call<T: Body + std::fmt::Debug>(&self, req: Request<T>) -> ServerResponse {
+    pub async fn call<T: Body + std::fmt::Debug>(&self, req: Request<T>) -> StreamingResponse {
         // Returning Err here will prevent the client from receiving any response.
         // Instead, we generate a Response with an error code and return Ok
         let result = self.handle_call(req).await;
 
-        match result {
-            Ok(response) => response,
+        let response = match result {
+            Ok(response) => return response,
             Err(err @ ServerError::ClientError(_)) => {
                 let response_obj = json!({
                     "error": err.to_string()
@@ -370,9 +542,12 @@ where
             Err(err @ ServerError::QueryError(_)) => {
                 error!(self.logger, "GraphQLService call failed: {}", err);
 
-                let response_obj = json!({
+                let mut response_obj = json!({
                     "QueryError": err.to_string()
                 });
+                if let Some(code) = err.code() {
+                    response_obj["extensions"] = json!({ "code": code.as_str() });
+                }
                 let response_str = serde_json::to_string(&response_obj).unwrap();
 
                 Response::builder()
@@ -392,7 +567,8 @@ where
                     .body(Full::from(format!("Internal server error: {}", err)))
                     .unwrap()
             }
-        }
+        };
+        streaming(response)
     }
 }
 
@@ -404,7 +580,9 @@ mod tests {
     use graph::hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
     use graph::hyper::{Method, Request, StatusCode};
     use graph::prelude::serde_json::json;
+    use sha2::{Digest, Sha256};
 
+    use graph::components::server::query::StreamingResponse;
     use graph::data::query::{QueryResults, QueryTarget};
     use graph::prelude::*;
 
@@ -443,7 +621,13 @@ mod tests {
             unimplemented!();
         }
 
-        async fn run_query(self: Arc<Self>, _query: Query, _target: QueryTarget) -> QueryResults {
+        async fn run_query(self: Arc<Self>, query: Query, _target: QueryTarget) -> QueryResults {
+            if query.document.to_string().contains("invalid") {
+                return QueryResults::from(QueryExecutionError::ValidationError(
+                    None,
+                    "Type `Query` has no field `invalid`".to_string(),
+                ));
+            }
             QueryResults::from(Object::from_iter(
                 vec![(Word::from("name"), r::Value::String(String::from("Jordi")))].into_iter(),
             ))
@@ -467,7 +651,7 @@ mod tests {
         let logger = Logger::root(slog::Discard, o!());
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
-        let service = GraphQLService::new(logger, graphql_runner, 8001);
+        let service = GraphQLService::new(logger, graphql_runner, 8001, None);
 
         let request: Request<Full<Bytes>> = Request::builder()
             .method(Method::GET)
@@ -484,7 +668,7 @@ mod tests {
         let content_type_header = response.headers().get(CONTENT_TYPE).unwrap();
         assert_eq!(content_type_header, "application/json");
 
-        let body_bytes = response.body().clone().collect().await.unwrap().to_bytes();
+        let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
         let json: serde_json::Result<serde_json::Value> =
             serde_json::from_str(String::from_utf8(body_bytes.to_vec()).unwrap().as_str());
 
@@ -499,7 +683,7 @@ mod tests {
         let subgraph_id = USERS.clone();
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
-        let service = GraphQLService::new(logger, graphql_runner, 8001);
+        let service = GraphQLService::new(logger, graphql_runner, 8001, None);
 
         let request: Request<Full<Bytes>> = Request::builder()
             .method(Method::POST)
@@ -531,7 +715,7 @@ mod tests {
         let subgraph_id = USERS.clone();
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
-        let service = GraphQLService::new(logger, graphql_runner, 8001);
+        let service = GraphQLService::new(logger, graphql_runner, 8001, None);
 
         let request: Request<Full<Bytes>> = Request::builder()
             .method(Method::POST)
@@ -557,4 +741,114 @@ mod tests {
             .expect("Query result field \"name\" is not a string");
         assert_eq!(name, "Jordi".to_string());
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn posting_federated_queries_nests_results() {
+        let logger = Logger::root(slog::Discard, o!());
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let service = GraphQLService::new(logger, graphql_runner, 8001, None);
+
+        let query = json!({
+            "query": "{ users: subgraph(name: \"test/users\") { name } \
+                        admins: subgraph(name: \"test/admins\") { name } }"
+        })
+        .to_string();
+        let request: Request<Full<Bytes>> = Request::builder()
+            .method(Method::POST)
+            .header(CONTENT_TYPE, "application/json")
+            .header(CONTENT_LENGTH, query.len())
+            .uri("http://localhost:8000/subgraphs/federated")
+            .body(Full::from(query))
+            .unwrap();
+
+        let response = service.call(request).await;
+        let data = test_utils::assert_successful_response(response).await;
+
+        for key in ["users", "admins"] {
+            assert_eq!(
+                Some(&json!({ "name": "Jordi" })),
+                data.get(key),
+                "result for `{}`",
+                key
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tracing_header_requires_trace_token() {
+        let logger = Logger::root(slog::Discard, o!());
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let service = GraphQLService::new(logger, graphql_runner, 8001, None);
+
+        let request: Request<Full<Bytes>> = Request::builder()
+            .method(Method::POST)
+            .header(CONTENT_TYPE, "application/json")
+            .header("X-GraphQL-Trace", "true")
+            .uri(format!(
+                "http://localhost:8000/subgraphs/id/{}",
+                USERS.as_str()
+            ))
+            .body(Full::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+
+        let response = service.call(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json, json!({ "data": { "name": "Jordi" } }));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn persisted_queries_are_only_remembered_when_valid() {
+        let logger = Logger::root(slog::Discard, o!());
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let service = GraphQLService::new(logger, graphql_runner, 8001, None);
+
+        let post = |query: Option<&str>, hash: String| {
+            let mut body = json!({
+                "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } }
+            });
+            if let Some(query) = query {
+                body["query"] = json!(query);
+            }
+            let body = body.to_string();
+            let request: Request<Full<Bytes>> = Request::builder()
+                .method(Method::POST)
+                .header(CONTENT_TYPE, "application/json")
+                .header(CONTENT_LENGTH, body.len())
+                .uri(format!(
+                    "http://localhost:8000/subgraphs/id/{}",
+                    USERS.as_str()
+                ))
+                .body(Full::from(body))
+                .unwrap();
+            service.call(request)
+        };
+        let response_json = |response: StreamingResponse| async move {
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+        let not_found = |json: &serde_json::Value| {
+            json["errors"][0]["message"] == json!("PersistedQueryNotFound")
+        };
+
+        for (query, remembered) in [("{ invalid }", false), ("{ name }", true)] {
+            let hash = hex::encode(Sha256::digest(query.as_bytes()));
+
+            let json = response_json(post(None, hash.clone()).await).await;
+            assert!(not_found(&json), "`{}` is unknown at first", query);
+
+            post(Some(query), hash.clone()).await;
+
+            let json = response_json(post(None, hash).await).await;
+            if remembered {
+                assert_eq!(json!({ "data": { "name": "Jordi" } }), json);
+            } else {
+                assert!(not_found(&json), "`{}` is not remembered", query);
+            }
+        }
+    }
 }
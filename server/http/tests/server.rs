@@ -171,7 +171,7 @@ mod test {
         let logger_factory = LoggerFactory::new(logger, None, Arc::new(MetricsRegistry::mock()));
         let id = USERS.clone();
         let query_runner = Arc::new(TestGraphQlRunner);
-        let server = HyperGraphQLServer::new(&logger_factory, query_runner);
+        let server = HyperGraphQLServer::new(&logger_factory, query_runner, None);
         let server_handle = server
             .start(8007, 8008)
             .await
@@ -203,7 +203,7 @@ mod test {
         let logger_factory = LoggerFactory::new(logger, None, Arc::new(MetricsRegistry::mock()));
         let id = USERS.clone();
         let query_runner = Arc::new(TestGraphQlRunner);
-        let server = HyperGraphQLServer::new(&logger_factory, query_runner);
+        let server = HyperGraphQLServer::new(&logger_factory, query_runner, None);
         let server_handle = server
             .start(8002, 8003)
             .await
@@ -275,7 +275,7 @@ mod test {
         let logger_factory = LoggerFactory::new(logger, None, Arc::new(MetricsRegistry::mock()));
         let id = USERS.clone();
         let query_runner = Arc::new(TestGraphQlRunner);
-        let server = HyperGraphQLServer::new(&logger_factory, query_runner);
+        let server = HyperGraphQLServer::new(&logger_factory, query_runner, None);
         let server_handle = server
             .start(8003, 8004)
             .await
@@ -312,7 +312,7 @@ mod test {
         let logger_factory = LoggerFactory::new(logger, None, Arc::new(MetricsRegistry::mock()));
         let id = USERS.clone();
         let query_runner = Arc::new(TestGraphQlRunner);
-        let server = HyperGraphQLServer::new(&logger_factory, query_runner);
+        let server = HyperGraphQLServer::new(&logger_factory, query_runner, None);
         let server_handle = server
             .start(8005, 8006)
             .await
@@ -26,20 +26,7 @@ impl PoiProtection {
             (None, _) => true,
             // Protection is active, but no access token was provided.
             (Some(_), None) => false,
-            (Some(a), Some(b)) => {
-                // When comparing secrets to untrusted user data, we have to be
-                // careful about timing attacks. Constant-time comparison is the
-                // standard choice in these situations, but it can be quite
-                // convoluted. Instead, we'll compare the BLAKE3 hashes of the
-                // two values: this way we don't have to worry about timing
-                // attacks nor vetting a constant-time comparison crate.
-                //
-                // We get 128 bits of security out of the box (256/2), which
-                // is plenty.
-                let hash_a = blake3::hash(a.as_bytes());
-                let hash_b = blake3::hash(b.as_bytes());
-                hash_a == hash_b
-            }
+            (Some(a), Some(b)) => tokens_match(a, b),
         }
     }
 
@@ -49,7 +36,83 @@ impl PoiProtection {
     }
 }
 
+/// Validation logic for the access token required to run mutations.
+pub struct MutationProtection {
+    reqd_access_token: Option<String>,
+}
+
+impl MutationProtection {
+    /// Creates a new [`MutationProtection`] instance configured in
+    /// accordance with the `GRAPH_INDEX_NODE_ADMIN_TOKEN` environment
+    /// variable.
+    pub fn from_env(env: &EnvVars) -> Self {
+        Self {
+            reqd_access_token: env.index_node_admin_token.clone(),
+        }
+    }
+
+    /// Returns `true` iff the given access token allows running mutations.
+    /// Unlike for POI results, access is denied when no token is configured.
+    pub fn validate_access_token(&self, access_token: Option<&str>) -> bool {
+        match (self.reqd_access_token.as_ref(), access_token) {
+            (Some(a), Some(b)) => tokens_match(a, b),
+            _ => false,
+        }
+    }
+}
+
+fn tokens_match(a: &str, b: &str) -> bool {
+    // When comparing secrets to untrusted user data, we have to be
+    // careful about timing attacks. Constant-time comparison is the
+    // standard choice in these situations, but it can be quite
+    // convoluted. Instead, we'll compare the BLAKE3 hashes of the
+    // two values: this way we don't have to worry about timing
+    // attacks nor vetting a constant-time comparison crate.
+    //
+    // We get 128 bits of security out of the box (256/2), which
+    // is plenty.
+    let hash_a = blake3::hash(a.as_bytes());
+    let hash_b = blake3::hash(b.as_bytes());
+    hash_a == hash_b
+}
+
 pub fn bearer_token(headers: &HeaderMap) -> Option<&[u8]> {
     let header = headers.get(AUTHORIZATION)?.as_bytes();
     header.strip_prefix(b"Bearer ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{MutationProtection, PoiProtection};
+
+    #[test]
+    fn mutations_require_configured_token() {
+        let protection = MutationProtection {
+            reqd_access_token: None,
+        };
+        assert!(!protection.validate_access_token(None));
+        assert!(!protection.validate_access_token(Some("secret")));
+
+        let protection = MutationProtection {
+            reqd_access_token: Some("secret".to_string()),
+        };
+        assert!(!protection.validate_access_token(None));
+        assert!(!protection.validate_access_token(Some("")));
+        assert!(!protection.validate_access_token(Some("not-the-secret")));
+        assert!(protection.validate_access_token(Some("secret")));
+    }
+
+    #[test]
+    fn poi_is_public_without_token() {
+        let protection = PoiProtection {
+            reqd_access_token: None,
+        };
+        assert!(protection.validate_access_token(None));
+
+        let protection = PoiProtection {
+            reqd_access_token: Some("secret".to_string()),
+        };
+        assert!(!protection.validate_access_token(None));
+        assert!(protection.validate_access_token(Some("secret")));
+    }
+}
@@ -1,5 +1,6 @@
 mod auth;
 mod explorer;
+mod poi;
 mod resolver;
 mod schema;
 mod server;
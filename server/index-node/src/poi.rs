@@ -0,0 +1,215 @@
+//! Compare the proofs of indexing of a deployment with those of another
+//! indexer to find the first block at which they diverge
+
+use std::time::Duration;
+
+use graph::prelude::{
+    anyhow, async_trait, reqwest, serde_json, BlockNumber, DeploymentHash, Error,
+};
+
+/// Timeout for requests to the index node of another indexer
+const REMOTE_POI_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something that can produce the public proof of indexing of a
+/// deployment. Proofs of indexing are hex strings with a `0x` prefix
+#[async_trait]
+pub(crate) trait PoiSource: Send + Sync {
+    async fn public_poi(
+        &self,
+        deployment: &DeploymentHash,
+        block: BlockNumber,
+    ) -> Result<Option<String>, Error>;
+}
+
+/// The index node API of another indexer
+pub(crate) struct RemotePoiSource {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl RemotePoiSource {
+    /// `url` is the URL of the GraphQL endpoint of the index node,
+    /// e.g. `http://indexer:8030/graphql`
+    pub fn new(url: String) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .timeout(REMOTE_POI_TIMEOUT)
+            .build()?;
+        Ok(Self { client, url })
+    }
+}
+
+#[async_trait]
+impl PoiSource for RemotePoiSource {
+    async fn public_poi(
+        &self,
+        deployment: &DeploymentHash,
+        block: BlockNumber,
+    ) -> Result<Option<String>, Error> {
+        let query = "query publicPoi($requests: [PublicProofOfIndexingRequest!]!) {
+            publicProofsOfIndexing(requests: $requests) { proofOfIndexing }
+        }";
+        let body = serde_json::json!({
+            "query": query,
+            "variables": {
+                "requests": [{
+                    "deployment": deployment.as_str(),
+                    "blockNumber": block.to_string(),
+                }]
+            }
+        });
+
+        let response: serde_json::Value = self
+            .client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(errors) = response.get("errors") {
+            return Err(anyhow!(
+                "the index node at {} returned errors: {}",
+                self.url,
+                errors
+            ));
+        }
+        Ok(
+            response["data"]["publicProofsOfIndexing"][0]["proofOfIndexing"]
+                .as_str()
+                .map(|poi| poi.to_lowercase()),
+        )
+    }
+}
+
+/// The first block at which the proofs of indexing of two sources differ
+#[derive(Debug, PartialEq)]
+pub(crate) struct Divergence {
+    pub block: BlockNumber,
+    /// The last block at which the proofs of indexing were the same, if
+    /// they were the same for any of the compared blocks
+    pub last_agreeing_block: Option<BlockNumber>,
+    pub local_poi: Option<String>,
+    pub remote_poi: Option<String>,
+    /// How many blocks were compared to find the divergence
+    pub comparisons: u32,
+}
+
+/// Find the first block in `from..=to` at which the proofs of indexing of
+/// `deployment` from `local` and `remote` differ. Return `None` if they are
+/// the same at `to`.
+///
+/// Since the proof of indexing for a block covers all blocks before it,
+/// the proofs of indexing stay different once they diverge, which makes it
+/// possible to find the divergence with a binary search
+pub(crate) async fn bisect(
+    deployment: &DeploymentHash,
+    local: &dyn PoiSource,
+    remote: &dyn PoiSource,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<Option<Divergence>, Error> {
+    let mut comparisons = 0;
+    let mut compare = |block: BlockNumber| {
+        comparisons += 1;
+        async move {
+            let local_poi = local.public_poi(deployment, block).await?;
+            let remote_poi = remote.public_poi(deployment, block).await?;
+            Ok::<_, Error>((local_poi == remote_poi, local_poi, remote_poi))
+        }
+    };
+
+    let (agree, mut local_poi, mut remote_poi) = compare(to).await?;
+    if agree {
+        return Ok(None);
+    }
+
+    let (mut lo, mut hi) = (from, to);
+    let last_agreeing_block = if from < to {
+        let (agree, from_local_poi, from_remote_poi) = compare(from).await?;
+        if agree {
+            // The proofs of indexing are the same at `lo` and differ at `hi`
+            while hi - lo > 1 {
+                let mid = lo + (hi - lo) / 2;
+                let (agree, mid_local_poi, mid_remote_poi) = compare(mid).await?;
+                if agree {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                    local_poi = mid_local_poi;
+                    remote_poi = mid_remote_poi;
+                }
+            }
+            Some(lo)
+        } else {
+            hi = from;
+            local_poi = from_local_poi;
+            remote_poi = from_remote_poi;
+            None
+        }
+    } else {
+        None
+    };
+
+    Ok(Some(Divergence {
+        block: hi,
+        last_agreeing_block,
+        local_poi,
+        remote_poi,
+        comparisons,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::futures03::executor::block_on;
+
+    use super::*;
+
+    /// A source whose proofs of indexing differ from `0x00` starting at
+    /// block `diverges_at`
+    struct Source {
+        diverges_at: Option<BlockNumber>,
+    }
+
+    #[async_trait]
+    impl PoiSource for Source {
+        async fn public_poi(
+            &self,
+            _deployment: &DeploymentHash,
+            block: BlockNumber,
+        ) -> Result<Option<String>, Error> {
+            match self.diverges_at {
+                Some(diverges_at) if block >= diverges_at => Ok(Some("0x01".to_string())),
+                _ => Ok(Some("0x00".to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn bisect_finds_first_divergent_block() {
+        let deployment = DeploymentHash::new("QmTest").unwrap();
+        let local = Source { diverges_at: None };
+
+        let remote = Source {
+            diverges_at: Some(37),
+        };
+        let divergence = block_on(bisect(&deployment, &local, &remote, 0, 100))
+            .unwrap()
+            .unwrap();
+        assert_eq!(37, divergence.block);
+        assert_eq!(Some(36), divergence.last_agreeing_block);
+        assert_eq!(Some("0x00".to_string()), divergence.local_poi);
+        assert_eq!(Some("0x01".to_string()), divergence.remote_poi);
+
+        let divergence = block_on(bisect(&deployment, &local, &remote, 50, 100))
+            .unwrap()
+            .unwrap();
+        assert_eq!(50, divergence.block);
+        assert_eq!(None, divergence.last_agreeing_block);
+
+        let remote = Source { diverges_at: None };
+        let divergence = block_on(bisect(&deployment, &local, &remote, 0, 100)).unwrap();
+        assert_eq!(None, divergence);
+    }
+}
@@ -1,5 +1,6 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::convert::TryInto;
+use std::sync::Mutex;
 
 use graph::data::query::Trace;
 use graph::data::store::Id;
@@ -8,7 +9,11 @@ use web3::types::Address;
 
 use git_testament::{git_testament, CommitKind};
 use graph::blockchain::{Blockchain, BlockchainKind, BlockchainMap};
-use graph::components::store::{BlockPtrForNumber, BlockStore, QueryPermit, Store};
+use graph::components::store::{
+    diff_replay, BlockPtrForNumber, BlockStore, EntityIndex, EntityTypeStats, IndexBuildProgress,
+    QueryPermit, ReplayChange, Store,
+};
+use graph::components::subgraph::HANDLER_STATS;
 use graph::components::versions::VERSIONS;
 use graph::data::graphql::{object, IntoValue, ObjectOrInterface, ValueMap};
 use graph::data::subgraph::{status, DeploymentFeatures};
@@ -17,11 +22,23 @@ use graph::futures03::TryFutureExt;
 use graph::prelude::*;
 use graph_graphql::prelude::{a, ExecutionContext, Resolver};
 
-use crate::auth::PoiProtection;
+use crate::auth::{MutationProtection, PoiProtection};
+use crate::poi::{self, PoiSource, RemotePoiSource};
+
+/// How many index builds `entityIndexBuilds` remembers. Builds that are
+/// still running are always kept
+const MAX_INDEX_BUILDS: usize = 100;
 
 /// Timeout for calls to fetch the block from JSON-RPC or Firehose.
 const BLOCK_HASH_FROM_NUMBER_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// The most blocks that `replayDiff` compares in one query
+const MAX_REPLAY_DIFF_BLOCKS: BlockNumber = 100;
+
+/// The most blocks that `publicProofsOfIndexingRange` returns proofs of
+/// indexing for in one query
+const MAX_POI_RANGE_BLOCKS: BlockNumber = 100;
+
 git_testament!(TESTAMENT);
 
 lazy_static! {
@@ -33,6 +50,8 @@ lazy_static! {
             _ => "unknown".to_string(),
         }
     };
+    /// The index builds started with `createEntityIndex`
+    static ref INDEX_BUILDS: Mutex<IndexBuilds> = Mutex::new(IndexBuilds::default());
 }
 
 #[derive(Clone, Debug)]
@@ -92,6 +111,132 @@ impl IntoValue for PublicProofOfIndexingResult {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+enum IndexBuildStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Clone, Debug)]
+struct EntityIndexBuild {
+    deployment: DeploymentHash,
+    entity: String,
+    fields: Vec<String>,
+    method: String,
+    status: IndexBuildStatus,
+    error: Option<String>,
+}
+
+impl IntoValue for EntityIndexBuild {
+    fn into_value(self) -> r::Value {
+        let status = match self.status {
+            IndexBuildStatus::Running => "running",
+            IndexBuildStatus::Succeeded => "succeeded",
+            IndexBuildStatus::Failed => "failed",
+        };
+        object! {
+            __typename: "EntityIndexBuild",
+            deployment: self.deployment.to_string(),
+            entity: self.entity,
+            fields: self.fields,
+            method: r::Value::Enum(self.method),
+            status: r::Value::Enum(status.to_string()),
+            error: self.error,
+        }
+    }
+}
+
+/// The index builds started with `createEntityIndex`, oldest first. Once
+/// there are more than `MAX_INDEX_BUILDS` of them, the oldest ones that
+/// have finished are forgotten
+#[derive(Default)]
+struct IndexBuilds {
+    next_id: u64,
+    builds: VecDeque<(u64, EntityIndexBuild)>,
+}
+
+impl IndexBuilds {
+    /// Remember `build` and return the id with which it can be updated
+    fn add(&mut self, build: EntityIndexBuild) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.builds.push_back((id, build));
+        while self.builds.len() > MAX_INDEX_BUILDS {
+            let finished = self
+                .builds
+                .iter()
+                .position(|(_, build)| !matches!(build.status, IndexBuildStatus::Running));
+            match finished {
+                Some(pos) => {
+                    self.builds.remove(pos);
+                }
+                None => break,
+            }
+        }
+        id
+    }
+
+    /// Record the outcome of the build with the given `id`
+    fn finish(&mut self, id: u64, res: Result<(), String>) {
+        if let Some((_, build)) = self.builds.iter_mut().find(|(other, _)| *other == id) {
+            match res {
+                Ok(()) => build.status = IndexBuildStatus::Succeeded,
+                Err(e) => {
+                    build.status = IndexBuildStatus::Failed;
+                    build.error = Some(e);
+                }
+            }
+        }
+    }
+
+    fn all(&self) -> Vec<EntityIndexBuild> {
+        self.builds.iter().map(|(_, build)| build.clone()).collect()
+    }
+}
+
+fn entity_index_value(index: EntityIndex) -> r::Value {
+    object! {
+        __typename: "EntityIndex",
+        name: index.name,
+        definition: index.definition,
+        valid: index.valid,
+        progress: index.progress.map(|progress: IndexBuildProgress| object! {
+            __typename: "IndexBuildProgress",
+            phase: progress.phase,
+            blocksDone: progress.blocks_done.to_string(),
+            blocksTotal: progress.blocks_total.to_string(),
+            tuplesDone: progress.tuples_done.to_string(),
+            tuplesTotal: progress.tuples_total.to_string(),
+        }),
+    }
+}
+
+fn entity_type_stats_value(stats: EntityTypeStats) -> r::Value {
+    object! {
+        __typename: "EntityTypeStats",
+        entityType: stats.entity_type,
+        table: stats.table,
+        entities: stats.entities.to_string(),
+        versions: stats.versions.to_string(),
+        tableSize: stats.table_size.to_string(),
+        indexSize: stats.index_size.to_string(),
+        lastPrunedBlock: stats.last_pruned_block,
+    }
+}
+
+/// Fill in the statistics for the handlers that this node ran for each
+/// deployment in `infos`
+fn with_handler_stats(infos: Vec<status::Info>) -> Vec<status::Info> {
+    infos
+        .into_iter()
+        .map(|mut info| {
+            info.handler_stats = HANDLER_STATS.snapshot(&info.subgraph);
+            info
+        })
+        .collect()
+}
+
 /// Resolver for the index node GraphQL API.
 #[derive(Clone)]
 pub struct IndexNodeResolver<S: Store> {
@@ -140,7 +285,7 @@ impl<S: Store> IndexNodeResolver<S> {
         let infos = self
             .store
             .status(status::Filter::Deployments(deployments))?;
-        Ok(infos.into_value())
+        Ok(with_handler_stats(infos).into_value())
     }
 
     fn resolve_indexing_statuses_for_subgraph_name(
@@ -164,7 +309,7 @@ impl<S: Store> IndexNodeResolver<S> {
             .store
             .status(status::Filter::SubgraphName(subgraph_name))?;
 
-        Ok(infos.into_value())
+        Ok(with_handler_stats(infos).into_value())
     }
 
     fn resolve_entity_changes_in_block(
@@ -187,6 +332,110 @@ impl<S: Store> IndexNodeResolver<S> {
         Ok(entity_changes_to_graphql(entity_changes))
     }
 
+    async fn resolve_replay_diff(&self, field: &a::Field) -> Result<r::Value, QueryExecutionError> {
+        // We can safely unwrap because the arguments are non-nullable and
+        // have been validated.
+        let subgraph_id = field.get_required::<DeploymentHash>("subgraphId").unwrap();
+        let replay_id = field.get_required::<i32>("replayId").unwrap();
+        let from = field.get_required::<BlockNumber>("fromBlock").unwrap();
+        let to = field.get_required::<BlockNumber>("toBlock").unwrap();
+
+        // Every block needs two queries
+        if to < from || to - from >= MAX_REPLAY_DIFF_BLOCKS {
+            return Err(QueryExecutionError::TooExpensive);
+        }
+
+        let subgraph_store = self.store.subgraph_store();
+        let original = subgraph_store
+            .active_locator(subgraph_id.as_str())?
+            .ok_or_else(|| QueryExecutionError::DeploymentNotFound(subgraph_id.to_string()))?;
+        let replay = subgraph_store
+            .locators(subgraph_id.as_str())?
+            .into_iter()
+            .find(|loc| loc.id.0 == replay_id && loc.id != original.id)
+            .ok_or_else(|| {
+                QueryExecutionError::DeploymentNotFound(format!("{}[{}]", subgraph_id, replay_id))
+            })?;
+
+        let original_changes = subgraph_store
+            .deployment_entity_changes(&original, from, to)
+            .await?;
+        let replay_changes = subgraph_store
+            .deployment_entity_changes(&replay, from, to)
+            .await?;
+        let diffs: Vec<_> = original_changes
+            .into_iter()
+            .zip(replay_changes)
+            .flat_map(|((block, original), (_, replay))| diff_replay(block, original, replay))
+            .collect();
+
+        fn change_kind(change: &ReplayChange) -> r::Value {
+            r::Value::Enum(change.as_str().to_uppercase())
+        }
+
+        fn entity_value(change: &ReplayChange) -> r::Value {
+            match change.entity() {
+                Some(entity) => r::Value::object(
+                    entity
+                        .clone()
+                        .sorted()
+                        .into_iter()
+                        .map(|(name, value)| (name.into(), value.into()))
+                        .collect(),
+                ),
+                None => r::Value::Null,
+            }
+        }
+
+        Ok(r::Value::List(
+            diffs
+                .into_iter()
+                .map(|diff| {
+                    object! {
+                        blockNumber: diff.block,
+                        type: diff.key.entity_type.to_string(),
+                        id: diff.key.entity_id.to_string(),
+                        originalChange: change_kind(&diff.original),
+                        original: entity_value(&diff.original),
+                        replayChange: change_kind(&diff.replay),
+                        replay: entity_value(&diff.replay),
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    fn resolve_skipped_triggers(&self, field: &a::Field) -> Result<r::Value, QueryExecutionError> {
+        let subgraph_id = field
+            .get_required::<DeploymentHash>("subgraphId")
+            .expect("Valid subgraphId required");
+
+        let subgraph_store = self.store.subgraph_store();
+        let locator = subgraph_store
+            .active_locator(subgraph_id.as_str())?
+            .ok_or_else(|| QueryExecutionError::DeploymentNotFound(subgraph_id.to_string()))?;
+
+        Ok(r::Value::List(
+            subgraph_store
+                .skipped_triggers(&locator)?
+                .into_iter()
+                .map(|skipped| {
+                    object! {
+                        __typename: "SkippedTrigger",
+                        handler: skipped.handler,
+                        block: object! {
+                            __typename: "Block",
+                            hash: skipped.block_ptr.hash_hex(),
+                            number: format!("{}", skipped.block_ptr.number),
+                        },
+                        trigger: skipped.trigger,
+                        message: skipped.message,
+                    }
+                })
+                .collect(),
+        ))
+    }
+
     async fn resolve_block_data(&self, field: &a::Field) -> Result<r::Value, QueryExecutionError> {
         let network = field
             .get_required::<String>("network")
@@ -405,41 +654,135 @@ impl<S: Store> IndexNodeResolver<S> {
 
         let mut public_poi_results = vec![];
         for request in requests {
-            let (poi_result, request) = match self
-                .store
-                .get_public_proof_of_indexing(&request.deployment, request.block_number, self)
-                .await
-            {
-                Ok(Some(poi)) => (Some(poi), request),
-                Ok(None) => (None, request),
-                Err(e) => {
-                    error!(
-                        self.logger,
-                        "Failed to query public proof of indexing";
-                        "subgraph" => &request.deployment,
-                        "block" => format!("{}", request.block_number),
-                        "error" => format!("{:?}", e)
-                    );
-                    (None, request)
-                }
-            };
+            public_poi_results.push(
+                self.public_proof_of_indexing(request.deployment, request.block_number)
+                    .await
+                    .into_value(),
+            )
+        }
+
+        Ok(r::Value::List(public_poi_results))
+    }
+
+    async fn resolve_public_proofs_of_indexing_range(
+        &self,
+        field: &a::Field,
+    ) -> Result<r::Value, QueryExecutionError> {
+        // We can safely unwrap because the arguments are non-nullable and
+        // have been validated.
+        let deployment = field.get_required::<String>("deployment").unwrap();
+        let from = field.get_required::<BlockNumber>("fromBlock").unwrap();
+        let to = field.get_required::<BlockNumber>("toBlock").unwrap();
+
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(QueryExecutionError::SubgraphDeploymentIdError)?;
+        if from < 0 || to < from {
+            return Err(QueryExecutionError::ValueParseError(
+                "toBlock".to_string(),
+                format!("the block range from {from} to {to} is invalid"),
+            ));
+        }
+        if to - from >= MAX_POI_RANGE_BLOCKS {
+            return Err(QueryExecutionError::TooExpensive);
+        }
 
+        let mut public_poi_results = vec![];
+        for block_number in from..=to {
             public_poi_results.push(
-                PublicProofOfIndexingResult {
-                    deployment: request.deployment,
-                    block: match poi_result {
-                        Some((ref block, _)) => block.clone(),
-                        None => PartialBlockPtr::from(request.block_number),
-                    },
-                    proof_of_indexing: poi_result.map(|(_, poi)| poi),
-                }
-                .into_value(),
+                self.public_proof_of_indexing(deployment.clone(), block_number)
+                    .await
+                    .into_value(),
             )
         }
 
         Ok(r::Value::List(public_poi_results))
     }
 
+    async fn public_proof_of_indexing(
+        &self,
+        deployment: DeploymentHash,
+        block_number: BlockNumber,
+    ) -> PublicProofOfIndexingResult {
+        let poi_result = match self
+            .store
+            .get_public_proof_of_indexing(&deployment, block_number, self)
+            .await
+        {
+            Ok(poi) => poi,
+            Err(e) => {
+                error!(
+                    self.logger,
+                    "Failed to query public proof of indexing";
+                    "subgraph" => &deployment,
+                    "block" => format!("{}", block_number),
+                    "error" => format!("{:?}", e)
+                );
+                None
+            }
+        };
+
+        PublicProofOfIndexingResult {
+            deployment,
+            block: match poi_result {
+                Some((ref block, _)) => block.clone(),
+                None => PartialBlockPtr::from(block_number),
+            },
+            proof_of_indexing: poi_result.map(|(_, poi)| poi),
+        }
+    }
+
+    async fn resolve_bisect_divergence(
+        &self,
+        field: &a::Field,
+    ) -> Result<r::Value, QueryExecutionError> {
+        // The remote index node is queried from this node; only allow that
+        // for operators
+        let protection = MutationProtection::from_env(&ENV_VARS);
+        if !protection.validate_access_token(self.bearer_token.as_deref()) {
+            return Err(QueryExecutionError::Unauthorized(
+                "bisecting requires a valid access token".to_string(),
+            ));
+        }
+
+        // We can safely unwrap because the arguments are non-nullable and
+        // have been validated.
+        let deployment = field.get_required::<String>("deployment").unwrap();
+        let poi_source = field.get_required::<String>("poiSource").unwrap();
+        let from = field.get_required::<BlockNumber>("fromBlock").unwrap();
+        let to = field.get_required::<BlockNumber>("toBlock").unwrap();
+
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(QueryExecutionError::SubgraphDeploymentIdError)?;
+        if from < 0 || to < from {
+            return Err(QueryExecutionError::ValueParseError(
+                "toBlock".to_string(),
+                format!("the block range from {from} to {to} is invalid"),
+            ));
+        }
+
+        debug!(
+            self.logger,
+            "Bisect proof of indexing divergence";
+            "subgraph" => &deployment,
+            "poi_source" => &poi_source,
+            "from" => from,
+            "to" => to,
+        );
+
+        let remote = RemotePoiSource::new(poi_source)?;
+        let divergence = poi::bisect(&deployment, self, &remote, from, to).await?;
+
+        Ok(divergence.map(|divergence| object! {
+            __typename: "PoiDivergence",
+            deployment: deployment.to_string(),
+            block: divergence.block.to_string(),
+            lastAgreeingBlock: divergence.last_agreeing_block.map(|block| block.to_string()),
+            localProofOfIndexing: divergence.local_poi,
+            remoteProofOfIndexing: divergence.remote_poi,
+            comparisons: divergence.comparisons as i32,
+        }).unwrap_or(r::Value::Null))
+    }
+
     fn resolve_indexing_status_for_version(
         &self,
         field: &a::Field,
@@ -462,7 +805,7 @@ impl<S: Store> IndexNodeResolver<S> {
             current_version,
         ))?;
 
-        Ok(infos
+        Ok(with_handler_stats(infos)
             .into_iter()
             .next()
             .map(|info| info.into_value())
@@ -634,6 +977,114 @@ impl<S: Store> IndexNodeResolver<S> {
         Ok(features.into_value())
     }
 
+    async fn resolve_entity_indexes(
+        &self,
+        field: &a::Field,
+    ) -> Result<r::Value, QueryExecutionError> {
+        // We can safely unwrap because the arguments are non-nullable and
+        // have been validated.
+        let deployment = field.get_required::<String>("deployment").unwrap();
+        let entity = field.get_required::<String>("entity").unwrap();
+
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(QueryExecutionError::SubgraphDeploymentIdError)?;
+        let indexes = self
+            .store
+            .subgraph_store()
+            .entity_indexes(&deployment, &entity)
+            .await?;
+
+        Ok(r::Value::List(
+            indexes.into_iter().map(entity_index_value).collect(),
+        ))
+    }
+
+    async fn resolve_deployment_stats(
+        &self,
+        field: &a::Field,
+    ) -> Result<r::Value, QueryExecutionError> {
+        // We can safely unwrap because the argument is non-nullable and has
+        // been validated.
+        let deployment = field.get_required::<String>("deployment").unwrap();
+
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(QueryExecutionError::SubgraphDeploymentIdError)?;
+        let stats = self
+            .store
+            .subgraph_store()
+            .deployment_stats(&deployment)
+            .await?;
+
+        Ok(r::Value::List(
+            stats.into_iter().map(entity_type_stats_value).collect(),
+        ))
+    }
+
+    fn resolve_entity_index_builds(&self) -> Result<r::Value, QueryExecutionError> {
+        let builds = INDEX_BUILDS.lock().unwrap().all();
+        Ok(builds.into_value())
+    }
+
+    async fn create_entity_index(&self, field: &a::Field) -> Result<r::Value, QueryExecutionError> {
+        let protection = MutationProtection::from_env(&ENV_VARS);
+        if !protection.validate_access_token(self.bearer_token.as_deref()) {
+            return Err(QueryExecutionError::Unauthorized(
+                "creating indexes requires a valid access token".to_string(),
+            ));
+        }
+
+        // We can safely unwrap because the arguments have been validated.
+        let deployment = field.get_required::<String>("deployment").unwrap();
+        let entity = field.get_required::<String>("entity").unwrap();
+        let fields = field.get_required::<Vec<String>>("fields").unwrap();
+        let method = field
+            .get_optional::<String>("method")
+            .unwrap()
+            .unwrap_or_else(|| "btree".to_string());
+
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(QueryExecutionError::SubgraphDeploymentIdError)?;
+        let subgraph_store = self.store.subgraph_store();
+        // Fail right away if the deployment or entity type do not exist
+        subgraph_store.entity_indexes(&deployment, &entity).await?;
+
+        let build = EntityIndexBuild {
+            deployment: deployment.clone(),
+            entity: entity.clone(),
+            fields: fields.clone(),
+            method: method.clone(),
+            status: IndexBuildStatus::Running,
+            error: None,
+        };
+        let id = INDEX_BUILDS.lock().unwrap().add(build.clone());
+
+        // Building the index can take a long time; keep going in the
+        // background and record the outcome in `INDEX_BUILDS`
+        let logger = self.logger.new(o!(
+            "deployment" => deployment.to_string(),
+            "entity" => entity.clone(),
+        ));
+        graph::spawn(async move {
+            info!(logger, "Creating index"; "fields" => fields.join(", "), "method" => &method);
+            let res = subgraph_store
+                .create_entity_index(&deployment, &entity, fields, &method)
+                .await;
+            let res = match res {
+                Ok(()) => {
+                    info!(logger, "Created index");
+                    Ok(())
+                }
+                Err(e) => {
+                    error!(logger, "Failed to create index"; "error" => e.to_string());
+                    Err(e.to_string())
+                }
+            };
+            INDEX_BUILDS.lock().unwrap().finish(id, res);
+        });
+
+        Ok(build.into_value())
+    }
+
     fn resolve_api_versions(&self, _field: &a::Field) -> Result<r::Value, QueryExecutionError> {
         Ok(r::Value::List(
             VERSIONS
@@ -721,6 +1172,21 @@ impl<S: Store> IndexNodeResolver<S> {
     }
 }
 
+#[async_trait]
+impl<S: Store> PoiSource for IndexNodeResolver<S> {
+    async fn public_poi(
+        &self,
+        deployment: &DeploymentHash,
+        block: BlockNumber,
+    ) -> Result<Option<String>, Error> {
+        let poi = self
+            .store
+            .get_public_proof_of_indexing(deployment, block, self)
+            .await?;
+        Ok(poi.map(|(_, poi)| format!("0x{}", hex::encode(poi))))
+    }
+}
+
 #[async_trait]
 impl<S: Store> BlockPtrForNumber for IndexNodeResolver<S> {
     async fn block_ptr_for_number(
@@ -860,6 +1326,16 @@ impl<S: Store> Resolver for IndexNodeResolver<S> {
             (None, "PublicProofOfIndexingResult", "publicProofsOfIndexing") => {
                 self.resolve_public_proofs_of_indexing(field).await
             }
+            (None, "PublicProofOfIndexingResult", "publicProofsOfIndexingRange") => {
+                self.resolve_public_proofs_of_indexing_range(field).await
+            }
+            (None, "EntityIndex", "entityIndexes") => self.resolve_entity_indexes(field).await,
+            (None, "EntityIndexBuild", "entityIndexBuilds") => self.resolve_entity_index_builds(),
+            (None, "EntityTypeStats", "deploymentStats") => {
+                self.resolve_deployment_stats(field).await
+            }
+            (None, "EntityReplayDiff", "replayDiff") => self.resolve_replay_diff(field).await,
+            (None, "SkippedTrigger", "skippedTriggers") => self.resolve_skipped_triggers(field),
 
             // Resolve fields of `Object` values (e.g. the `chains` field of `ChainIndexingStatus`)
             (value, _, _) => Ok(value.unwrap_or(r::Value::Null)),
@@ -882,13 +1358,67 @@ impl<S: Store> Resolver for IndexNodeResolver<S> {
                 self.resolve_indexing_status_for_version(field, false)
             }
             (None, "subgraphFeatures") => self.resolve_subgraph_features(field).await,
+            (None, "bisectDivergence") => self.resolve_bisect_divergence(field).await,
             (None, "entityChangesInBlock") => self.resolve_entity_changes_in_block(field),
             // The top-level `subgraphVersions` field
             (None, "apiVersions") => self.resolve_api_versions(field),
             (None, "version") => self.version(),
+            // The `createEntityIndex` mutation
+            (None, "createEntityIndex") => self.create_entity_index(field).await,
 
             // Resolve fields of `Object` values (e.g. the `latestBlock` field of `EthereumBlock`)
             (value, _) => Ok(value.unwrap_or(r::Value::Null)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::DeploymentHash;
+
+    use super::{EntityIndexBuild, IndexBuildStatus, IndexBuilds, MAX_INDEX_BUILDS};
+
+    fn build(entity: &str) -> EntityIndexBuild {
+        EntityIndexBuild {
+            deployment: DeploymentHash::new("QmIndexBuilds").unwrap(),
+            entity: entity.to_string(),
+            fields: vec!["name".to_string()],
+            method: "btree".to_string(),
+            status: IndexBuildStatus::Running,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn index_builds_forget_oldest_finished_builds() {
+        let mut builds = IndexBuilds::default();
+        let running = builds.add(build("Running"));
+        let failed = builds.add(build("Failed"));
+        builds.finish(failed, Err("boom".to_string()));
+        for i in 2..MAX_INDEX_BUILDS {
+            let id = builds.add(build(&format!("Done{}", i)));
+            builds.finish(id, Ok(()));
+        }
+        assert_eq!(MAX_INDEX_BUILDS, builds.all().len());
+        let all = builds.all();
+        assert!(matches!(all[1].status, IndexBuildStatus::Failed));
+        assert_eq!(Some("boom".to_string()), all[1].error);
+
+        // The oldest build is still running and stays, the oldest finished
+        // one is evicted
+        builds.add(build("Newest"));
+        let all = builds.all();
+        assert_eq!(MAX_INDEX_BUILDS, all.len());
+        assert_eq!("Running", all[0].entity);
+        assert_eq!("Done2", all[1].entity);
+        assert_eq!("Newest", all[MAX_INDEX_BUILDS - 1].entity);
+
+        // Finishing a build that was evicted does nothing
+        builds.finish(failed, Ok(()));
+        builds.finish(running, Ok(()));
+        assert!(matches!(
+            builds.all()[0].status,
+            IndexBuildStatus::Succeeded
+        ));
+    }
+}
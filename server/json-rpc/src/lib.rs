@@ -1,3 +1,4 @@
+use graph::components::store::DeploymentPriority;
 use graph::prelude::{Value as GraphValue, *};
 use jsonrpsee::core::Error as JsonRpcError;
 use jsonrpsee::http_server::{HttpServerBuilder, HttpServerHandle};
@@ -78,6 +79,11 @@ impl JsonRpcServer {
                 state.resume_handler(params.parse()?).await
             })
             .unwrap();
+        rpc_module
+            .register_async_method("subgraph_set_priority", |params, state| async move {
+                state.set_priority_handler(params.parse()?).await
+            })
+            .unwrap();
 
         let _handle = http_server.start(rpc_module)?;
         Ok(Self { _handle })
@@ -99,6 +105,7 @@ impl<R: SubgraphRegistrar> ServerState<R> {
     const REASSIGN_ERROR: i64 = 3;
     const PAUSE_ERROR: i64 = 4;
     const RESUME_ERROR: i64 = 5;
+    const SET_PRIORITY_ERROR: i64 = 6;
 
     /// Handler for the `subgraph_create` endpoint.
     async fn create_handler(&self, params: SubgraphCreateParams) -> JsonRpcResult<JsonValue> {
@@ -217,6 +224,29 @@ impl<R: SubgraphRegistrar> ServerState<R> {
             )),
         }
     }
+
+    /// Handler for the `subgraph_set_priority` endpoint.
+    async fn set_priority_handler(
+        &self,
+        params: SubgraphSetPriorityParams,
+    ) -> JsonRpcResult<GraphValue> {
+        info!(&self.logger, "Received subgraph_set_priority request"; "params" => format!("{:?}", params));
+
+        match self
+            .registrar
+            .set_subgraph_priority(&params.deployment, params.priority)
+            .await
+        {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(json_rpc_error(
+                &self.logger,
+                "subgraph_set_priority",
+                e,
+                Self::SET_PRIORITY_ERROR,
+                params,
+            )),
+        }
+    }
 }
 
 fn json_rpc_error(
@@ -236,10 +266,17 @@ fn json_rpc_error(
         e.to_string()
     };
 
+    // Give deployers a structured list of everything the node does not
+    // support so that tooling can show it
+    let data = match &e {
+        SubgraphRegistrarError::UnsupportedFeatures(unsupported) => {
+            Some(serde_json::json!({ "unsupported": unsupported }))
+        }
+        _ => None,
+    };
+
     JsonRpcError::Call(CallError::Custom(ErrorObject::owned(
-        code as _,
-        message,
-        None::<String>,
+        code as _, message, data,
     )))
 }
 
@@ -299,3 +336,9 @@ struct SubgraphReassignParams {
 struct SubgraphPauseParams {
     deployment: DeploymentHash,
 }
+
+#[derive(Debug, Deserialize)]
+struct SubgraphSetPriorityParams {
+    deployment: DeploymentHash,
+    priority: DeploymentPriority,
+}
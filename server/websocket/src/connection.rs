@@ -5,6 +5,8 @@ use graph::futures03::sink::SinkExt;
 use graph::futures03::stream::{SplitStream, StreamExt, TryStreamExt};
 use std::collections::HashMap;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::{
     http::Response as WsResponse, http::StatusCode, Error as WsError, Message as WsMessage,
 };
@@ -14,6 +16,63 @@ use uuid::Uuid;
 use graph::futures03::compat::Future01CompatExt;
 use graph::{data::query::QueryTarget, prelude::*};
 
+/// The GraphQL over WebSocket protocol that a client speaks. Clients
+/// choose the protocol with the `Sec-WebSocket-Protocol` header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WsProtocol {
+    /// The protocol of `subscriptions-transport-ws`, which is announced as
+    /// `graphql-ws`
+    Legacy,
+    /// The `graphql-transport-ws` protocol of the `graphql-ws` library
+    GraphQlTransportWs,
+}
+
+impl WsProtocol {
+    /// Pick the protocol from the `Sec-WebSocket-Protocol` header. Clients
+    /// that do not ask for `graphql-transport-ws` get the legacy protocol
+    pub(crate) fn from_header(header: Option<&str>) -> Self {
+        let requested = header
+            .map(|protocols| {
+                protocols
+                    .split(',')
+                    .any(|protocol| protocol.trim() == "graphql-transport-ws")
+            })
+            .unwrap_or(false);
+        if requested {
+            WsProtocol::GraphQlTransportWs
+        } else {
+            WsProtocol::Legacy
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            WsProtocol::Legacy => "graphql-ws",
+            WsProtocol::GraphQlTransportWs => "graphql-transport-ws",
+        }
+    }
+
+    fn data(&self, id: String, payload: Arc<QueryResult>) -> OutgoingMessage {
+        match self {
+            WsProtocol::Legacy => OutgoingMessage::Data { id, payload },
+            WsProtocol::GraphQlTransportWs => OutgoingMessage::Next { id, payload },
+        }
+    }
+
+    fn error(&self, id: String, message: String) -> OutgoingMessage {
+        match self {
+            WsProtocol::Legacy => OutgoingMessage::Error {
+                id,
+                payload: message,
+            },
+            WsProtocol::GraphQlTransportWs => OutgoingMessage::Errors {
+                id,
+                payload: vec![serde_json::json!({ "message": message })],
+            },
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct StartPayload {
@@ -38,6 +97,22 @@ enum IncomingMessage {
     Stop {
         id: String,
     },
+    // Messages of the `graphql-transport-ws` protocol
+    Subscribe {
+        id: String,
+        payload: StartPayload,
+    },
+    Complete {
+        id: String,
+    },
+    Ping {
+        #[allow(dead_code)]
+        payload: Option<serde_json::Value>,
+    },
+    Pong {
+        #[allow(dead_code)]
+        payload: Option<serde_json::Value>,
+    },
 }
 
 impl IncomingMessage {
@@ -67,19 +142,17 @@ enum OutgoingMessage {
     Complete {
         id: String,
     },
-}
-
-impl OutgoingMessage {
-    pub fn from_query_result(id: String, result: Arc<QueryResult>) -> Self {
-        OutgoingMessage::Data {
-            id,
-            payload: result,
-        }
-    }
-
-    pub fn from_error_string(id: String, s: String) -> Self {
-        OutgoingMessage::Error { id, payload: s }
-    }
+    // Messages of the `graphql-transport-ws` protocol
+    Next {
+        id: String,
+        payload: Arc<QueryResult>,
+    },
+    #[serde(rename = "error")]
+    Errors {
+        id: String,
+        payload: Vec<serde_json::Value>,
+    },
+    Pong,
 }
 
 impl From<OutgoingMessage> for WsMessage {
@@ -103,15 +176,28 @@ fn send_message(
 /// Helper function to send error messages.
 fn send_error_string(
     sink: &mpsc::UnboundedSender<WsMessage>,
+    protocol: WsProtocol,
     operation_id: String,
     error: String,
 ) -> Result<(), WsError> {
-    sink.unbounded_send(OutgoingMessage::from_error_string(operation_id, error).into())
-        .map_err(|_| {
-            let mut response = WsResponse::new(None);
-            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-            WsError::Http(response)
-        })
+    send_message(sink, protocol.error(operation_id, error))
+}
+
+/// Close the connection with `code`. This is how the
+/// `graphql-transport-ws` protocol reports errors that are not tied to an
+/// operation
+fn close_connection(
+    sink: &mpsc::UnboundedSender<WsMessage>,
+    code: u16,
+    reason: String,
+) -> Result<(), WsError> {
+    let frame = CloseFrame {
+        code: CloseCode::from(code),
+        reason: reason.into(),
+    };
+    // An error means the client is gone already
+    let _ = sink.unbounded_send(WsMessage::Close(Some(frame)));
+    Err(WsError::ConnectionClosed)
 }
 
 /// Responsible for recording operation ids and stopping them.
@@ -119,13 +205,15 @@ fn send_error_string(
 struct Operations {
     operations: HashMap<String, CancelGuard>,
     msg_sink: mpsc::UnboundedSender<WsMessage>,
+    protocol: WsProtocol,
 }
 
 impl Operations {
-    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
+    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>, protocol: WsProtocol) -> Self {
         Self {
             operations: HashMap::new(),
             msg_sink,
+            protocol,
         }
     }
 
@@ -154,11 +242,21 @@ impl Operations {
             }
             None => send_error_string(
                 &self.msg_sink,
+                self.protocol,
                 operation_id.clone(),
                 format!("Unknown operation ID: {}", operation_id),
             ),
         }
     }
+
+    /// Stop an operation because the client asked us to with a
+    /// `complete` message. Unlike for `stop`, the client does not expect
+    /// a response, and unknown operations are ignored
+    fn complete(&mut self, operation_id: &str) {
+        if let Some(stopper) = self.operations.remove(operation_id) {
+            stopper.cancel();
+        }
+    }
 }
 
 impl Drop for Operations {
@@ -178,6 +276,7 @@ pub struct GraphQlConnection<Q, S> {
     graphql_runner: Arc<Q>,
     stream: WebSocketStream<S>,
     deployment: DeploymentHash,
+    protocol: WsProtocol,
 }
 
 impl<Q, S> GraphQlConnection<Q, S>
@@ -191,6 +290,7 @@ where
         deployment: DeploymentHash,
         stream: WebSocketStream<S>,
         graphql_runner: Arc<Q>,
+        protocol: WsProtocol,
     ) -> Self {
         GraphQlConnection {
             id: Uuid::new_v4().to_string(),
@@ -198,6 +298,7 @@ where
             graphql_runner,
             stream,
             deployment,
+            protocol,
         }
     }
 
@@ -208,8 +309,10 @@ where
         connection_id: String,
         deployment: DeploymentHash,
         graphql_runner: Arc<Q>,
+        protocol: WsProtocol,
     ) -> Result<(), WsError> {
-        let mut operations = Operations::new(msg_sink.clone());
+        let mut operations = Operations::new(msg_sink.clone(), protocol);
+        let mut acknowledged = false;
 
         // Process incoming messages as long as the WebSocket is open
         while let Some(ws_msg) = ws_stream.try_next().await? {
@@ -220,15 +323,35 @@ where
                    "connection" => &connection_id,
                    "msg" => format!("{}", ws_msg).as_str());
 
-            let msg = IncomingMessage::from_ws_message(ws_msg.clone())?;
+            let msg = match IncomingMessage::from_ws_message(ws_msg.clone()) {
+                Ok(msg) => msg,
+                Err(e) if protocol == WsProtocol::GraphQlTransportWs => {
+                    return close_connection(&msg_sink, 4400, e.to_string());
+                }
+                Err(e) => return Err(e),
+            };
 
             debug!(logger, "GraphQL/WebSocket message";
                    "connection" => &connection_id,
                    "msg" => format!("{:?}", msg).as_str());
 
             match msg {
-                // Always accept connection init requests
-                ConnectionInit { payload: _ } => send_message(&msg_sink, ConnectionAck),
+                // Always accept connection init requests, but only once
+                ConnectionInit { payload: _ } => {
+                    if acknowledged && protocol == WsProtocol::GraphQlTransportWs {
+                        return close_connection(
+                            &msg_sink,
+                            4429,
+                            "Too many initialisation requests".to_string(),
+                        );
+                    }
+                    acknowledged = true;
+                    send_message(&msg_sink, ConnectionAck)
+                }
+
+                Ping { payload: _ } => send_message(&msg_sink, Pong),
+
+                Pong { payload: _ } => Ok(()),
 
                 // When receiving a connection termination request
                 ConnectionTerminate => {
@@ -242,12 +365,31 @@ where
                 // When receiving a stop request
                 Stop { id } => operations.stop(id),
 
+                Complete { id } => {
+                    operations.complete(&id);
+                    Ok(())
+                }
+
                 // When receiving a start request
-                Start { id, payload } => {
+                Start { id, payload } | Subscribe { id, payload } => {
+                    if protocol == WsProtocol::GraphQlTransportWs {
+                        if !acknowledged {
+                            return close_connection(&msg_sink, 4401, "Unauthorized".to_string());
+                        }
+                        if operations.contains(&id) {
+                            return close_connection(
+                                &msg_sink,
+                                4409,
+                                format!("Subscriber for {} already exists", id),
+                            );
+                        }
+                    }
+
                     // Respond with a GQL_ERROR if we already have an operation with this ID
                     if operations.contains(&id) {
                         return send_error_string(
                             &msg_sink,
+                            protocol,
                             id.clone(),
                             format!("Operation with ID already started: {}", id),
                         );
@@ -257,6 +399,7 @@ where
                     if operations.operations.len() >= max_ops {
                         return send_error_string(
                             &msg_sink,
+                            protocol,
                             id,
                             format!("Reached the limit of {} operations per connection", max_ops),
                         );
@@ -269,6 +412,7 @@ where
                         Err(e) => {
                             return send_error_string(
                                 &msg_sink,
+                                protocol,
                                 id,
                                 format!("Invalid query: {}: {}", payload.query, e),
                             );
@@ -284,6 +428,7 @@ where
                                 Err(e) => {
                                     return send_error_string(
                                         &msg_sink,
+                                        protocol,
                                         id,
                                         format!("Invalid variables provided: {}", e),
                                     );
@@ -293,6 +438,7 @@ where
                         _ => {
                             return send_error_string(
                                 &msg_sink,
+                                protocol,
                                 id,
                                 "Invalid variables provided (must be an object)".to_string(),
                             );
@@ -328,7 +474,8 @@ where
                                                "id" => &err_id,
                                                "error" => format!("{:?}", e));
 
-                            // Send errors back to the client as GQL_DATA
+                            // Send errors back to the client as GQL_DATA, or
+                            // as an `error` message for `graphql-transport-ws`
                             match e {
                                 SubscriptionError::GraphQLError(e) => {
                                     // Don't bug clients with transient `TooExpensive` errors,
@@ -337,11 +484,24 @@ where
                                         .iter()
                                         .any(|err| matches!(err, QueryExecutionError::TooExpensive))
                                     {
-                                        let result = Arc::new(QueryResult::from(e));
-                                        let msg = OutgoingMessage::from_query_result(
-                                            err_id.clone(),
-                                            result,
-                                        );
+                                        let msg = match protocol {
+                                            WsProtocol::Legacy => protocol.data(
+                                                err_id.clone(),
+                                                Arc::new(QueryResult::from(e)),
+                                            ),
+                                            WsProtocol::GraphQlTransportWs => {
+                                                OutgoingMessage::Errors {
+                                                    id: err_id.clone(),
+                                                    payload: e
+                                                        .into_iter()
+                                                        .map(QueryError::from)
+                                                        .filter_map(|e| {
+                                                            serde_json::to_value(e).ok()
+                                                        })
+                                                        .collect(),
+                                                }
+                                            }
+                                        };
 
                                         // An error means the client closed the websocket, ignore
                                         // and let it be handled in the websocket loop above.
@@ -353,9 +513,7 @@ where
                         .and_then(move |result_stream| {
                             // Send results back to the client as GQL_DATA
                             result_stream
-                                .map(move |result| {
-                                    OutgoingMessage::from_query_result(result_id.clone(), result)
-                                })
+                                .map(move |result| protocol.data(result_id.clone(), result))
                                 .map(WsMessage::from)
                                 .map(Ok)
                                 .compat()
@@ -412,6 +570,7 @@ where
             self.id.clone(),
             self.deployment.clone(),
             self.graphql_runner.clone(),
+            self.protocol,
         );
 
         // Send outgoing messages asynchronously
@@ -434,3 +593,56 @@ where
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::serde_json::{self, json};
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    use super::{IncomingMessage, OutgoingMessage, WsProtocol};
+
+    #[test]
+    fn protocol_from_header() {
+        assert_eq!(WsProtocol::Legacy, WsProtocol::from_header(None));
+        assert_eq!(
+            WsProtocol::Legacy,
+            WsProtocol::from_header(Some("graphql-ws"))
+        );
+        assert_eq!(
+            WsProtocol::GraphQlTransportWs,
+            WsProtocol::from_header(Some("graphql-ws, graphql-transport-ws"))
+        );
+    }
+
+    #[test]
+    fn graphql_transport_ws_messages() {
+        let msg = WsMessage::text(
+            json!({
+                "type": "subscribe",
+                "id": "1",
+                "payload": { "query": "subscription { musicians { id } }" }
+            })
+            .to_string(),
+        );
+        match IncomingMessage::from_ws_message(msg).unwrap() {
+            IncomingMessage::Subscribe { id, .. } => assert_eq!("1", id),
+            msg => panic!("unexpected message {:?}", msg),
+        }
+
+        let msg = WsMessage::text(json!({ "type": "complete", "id": "1" }).to_string());
+        assert!(matches!(
+            IncomingMessage::from_ws_message(msg).unwrap(),
+            IncomingMessage::Complete { .. }
+        ));
+
+        let msg = WsProtocol::GraphQlTransportWs.error("1".to_string(), "boom".to_string());
+        assert_eq!(
+            json!({ "type": "error", "id": "1", "payload": [{ "message": "boom" }] }),
+            serde_json::to_value(&msg).unwrap()
+        );
+        assert_eq!(
+            json!({ "type": "pong" }),
+            serde_json::to_value(&OutgoingMessage::Pong).unwrap()
+        );
+    }
+}
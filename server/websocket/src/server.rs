@@ -1,4 +1,4 @@
-use crate::connection::GraphQlConnection;
+use crate::connection::{GraphQlConnection, WsProtocol};
 use graph::futures01::IntoFuture as _;
 use graph::futures03::compat::Future01CompatExt;
 use graph::futures03::future::FutureExt;
@@ -129,6 +129,9 @@ where
             // Subgraph that the request is resolved to (if any)
             let subgraph_id = Arc::new(Mutex::new(None));
             let accept_subgraph_id = subgraph_id.clone();
+            // The protocol the client asked for
+            let protocol = Arc::new(Mutex::new(WsProtocol::Legacy));
+            let accept_protocol = protocol.clone();
 
             accept_hdr_async(stream, move |request: &Request, mut response: Response<()>| {
                 // Try to obtain the subgraph ID or name from the URL path.
@@ -182,10 +185,18 @@ where
                             .unwrap());
                     }
 
+                let ws_protocol = WsProtocol::from_header(
+                    request
+                        .headers()
+                        .get("Sec-WebSocket-Protocol")
+                        .and_then(|value| value.to_str().ok()),
+                );
+
                 *accept_subgraph_id.lock().unwrap() = Some(state.id);
+                *accept_protocol.lock().unwrap() = ws_protocol;
                 response.headers_mut().insert(
                     "Sec-WebSocket-Protocol",
-                    HeaderValue::from_static("graphql-ws"),
+                    HeaderValue::from_static(ws_protocol.name()),
                 );
                 Ok(response)
             })
@@ -194,6 +205,7 @@ where
                     Ok(ws_stream) => {
                         // Obtain the subgraph ID or name that we resolved the request to
                         let subgraph_id = subgraph_id.lock().unwrap().clone().unwrap();
+                        let protocol = *protocol.lock().unwrap();
 
                         // Spawn a GraphQL over WebSocket connection
                         let service = GraphQlConnection::new(
@@ -201,6 +213,7 @@ where
                             subgraph_id,
                             ws_stream,
                             graphql_runner.clone(),
+                            protocol,
                         );
 
                         graph::spawn_allow_panic(service.into_future().compat());
@@ -0,0 +1,5 @@
+create table if not exists public.persisted_queries(
+  hash       text primary key,
+  query      text not null,
+  created_at timestamptz not null default now()
+);
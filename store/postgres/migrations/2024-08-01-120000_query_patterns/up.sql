@@ -0,0 +1,10 @@
+create table subgraphs.query_patterns(
+  deployment int not null
+             references subgraphs.subgraph_deployment on delete cascade,
+  entity     text not null,
+  filters    text[] not null,
+  sort       text[] not null,
+  count      int8 not null,
+  last_seen  timestamptz not null default now(),
+  primary key(deployment, entity, filters, sort)
+);
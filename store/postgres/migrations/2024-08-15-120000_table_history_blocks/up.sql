@@ -0,0 +1 @@
+alter table subgraphs.table_stats add column history_blocks int;
@@ -0,0 +1 @@
+drop table subgraphs.deferred_index;
@@ -0,0 +1,7 @@
+create table subgraphs.deferred_index(
+  deployment int not null
+             references subgraphs.subgraph_deployment on delete cascade,
+  name       text not null,
+  definition text not null,
+  primary key(deployment, name)
+);
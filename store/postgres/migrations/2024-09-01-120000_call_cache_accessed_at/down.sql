@@ -0,0 +1,19 @@
+do $$
+declare
+    tables cursor for select table_schema
+                        from information_schema.tables
+                       where table_name = 'call_cache'
+                         and table_schema like 'chain%';
+begin
+	for table_record in tables loop
+		execute
+			'drop index if exists '
+			|| table_record.table_schema
+			|| '.call_cache_accessed_at_idx';
+		execute
+			'alter table '
+			|| table_record.table_schema
+			|| '.call_cache drop column if exists accessed_at';
+	end loop;
+end;
+$$;
@@ -0,0 +1,24 @@
+-- The column is nullable and has no default when it is added so that
+-- adding it does not rewrite the call cache; existing calls get an access
+-- time the next time they are used. The index on the column is built
+-- concurrently by the call cache evictor since building it here would
+-- block the call cache for too long
+do $$
+declare
+    tables cursor for select table_schema
+                        from information_schema.tables
+                       where table_name = 'call_cache'
+                         and table_schema like 'chain%';
+begin
+	for table_record in tables loop
+		execute
+			'alter table '
+			|| table_record.table_schema
+			|| '.call_cache add column if not exists accessed_at timestamptz';
+		execute
+			'alter table '
+			|| table_record.table_schema
+			|| '.call_cache alter column accessed_at set default now()';
+	end loop;
+end;
+$$;
@@ -0,0 +1,15 @@
+do $$
+declare
+    tables cursor for select table_schema
+                        from information_schema.tables
+                       where table_name = 'call_cache'
+                         and table_schema like 'chain%';
+begin
+	for table_record in tables loop
+		execute
+			'alter table '
+			|| table_record.table_schema
+			|| '.call_cache add column if not exists revert_reason text';
+	end loop;
+end;
+$$;
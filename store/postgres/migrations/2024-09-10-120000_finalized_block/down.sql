@@ -0,0 +1,3 @@
+alter table public.ethereum_networks
+    drop column if exists finalized_block_hash,
+    drop column if exists finalized_block_number;
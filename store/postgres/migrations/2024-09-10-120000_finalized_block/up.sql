@@ -0,0 +1,3 @@
+alter table public.ethereum_networks
+    add column if not exists finalized_block_hash varchar default null,
+    add column if not exists finalized_block_number int8 default null;
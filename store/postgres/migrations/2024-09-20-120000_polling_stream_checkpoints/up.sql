@@ -0,0 +1,9 @@
+create table if not exists public.polling_stream_checkpoints (
+    network    varchar not null,
+    deployment varchar not null,
+    filter     varchar not null,
+    from_block int4    not null,
+    to_block   int4    not null,
+    blocks     int4[]  not null,
+    primary key (network, deployment)
+);
@@ -0,0 +1,12 @@
+create table subgraphs.subgraph_error_triggers(
+  deployment   int    not null
+               references subgraphs.subgraph_deployment on delete cascade,
+  block_number int    not null,
+  -- position of the trigger among the skipped triggers of the block
+  seq          int    not null,
+  block_hash   bytea  not null,
+  handler      text   not null,
+  trigger      text   not null,
+  message      text   not null,
+  primary key(deployment, block_number, seq)
+);
@@ -0,0 +1,3 @@
+alter table subgraphs.subgraph_deployment_assignment
+  add column priority text not null default 'normal'
+      check (priority in ('low', 'normal', 'high'));
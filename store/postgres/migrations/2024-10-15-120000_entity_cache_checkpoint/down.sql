@@ -0,0 +1 @@
+drop table subgraphs.entity_cache_checkpoint;
@@ -0,0 +1,9 @@
+create table subgraphs.entity_cache_checkpoint(
+  deployment       int    not null
+                   references subgraphs.subgraph_deployment on delete cascade,
+  entity_type      text   not null,
+  causality_region int    not null,
+  -- the ids of the cached entities, formatted as strings
+  ids              text[] not null,
+  primary key(deployment, entity_type, causality_region)
+);
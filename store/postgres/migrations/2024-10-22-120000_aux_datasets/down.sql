@@ -0,0 +1,3 @@
+drop table if exists public.aux_pins;
+drop table if exists public.aux_entries;
+drop table if exists public.aux_datasets;
@@ -0,0 +1,26 @@
+create table if not exists public.aux_datasets(
+  name      text not null,
+  version   int not null,
+  entries   int8 not null,
+  loaded_at timestamptz not null default now(),
+  primary key(name, version)
+);
+
+create table if not exists public.aux_entries(
+  dataset text not null,
+  version int not null,
+  key     text not null,
+  value   text not null,
+  primary key(dataset, version, key),
+  foreign key(dataset, version)
+    references public.aux_datasets(name, version) on delete cascade
+);
+
+-- The version of each dataset that a deployment reads from; it is set
+-- when the deployment first looks something up in the dataset
+create table if not exists public.aux_pins(
+  deployment text not null,
+  dataset    text not null,
+  version    int not null,
+  primary key(deployment, dataset)
+);
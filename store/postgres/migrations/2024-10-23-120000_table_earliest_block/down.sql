@@ -0,0 +1 @@
+alter table subgraphs.table_stats drop column earliest_block;
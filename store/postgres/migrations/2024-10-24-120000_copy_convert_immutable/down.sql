@@ -0,0 +1 @@
+alter table subgraphs.copy_state drop column convert_immutable;
@@ -0,0 +1 @@
+alter table subgraphs.copy_state add column convert_immutable boolean not null default false;
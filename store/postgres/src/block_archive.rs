@@ -0,0 +1,330 @@
+//! Storage of ancient blocks in an object store like S3 or GCS. Blocks
+//! that are more than `GRAPH_STORE_BLOCK_ARCHIVE_DEPTH` blocks behind the
+//! chain head are moved out of the `blocks` table of their chain and into
+//! the object store configured with `GRAPH_STORE_BLOCK_ARCHIVE_URL`.
+//!
+//! Each block is stored as a JSON document at `<prefix>/<chain>/<hash>.json`
+//! so that it can be found by its hash alone. The document contains the
+//! number and parent hash of the block together with its data, exactly as
+//! it was stored in the database. To make it possible to find blocks by
+//! their number, we also write an empty object at
+//! `<prefix>/<chain>/numbers/<number>/<hash>` for each block
+use graph::blockchain::BlockHash;
+use graph::bytes::Bytes;
+use graph::futures03::future::try_join_all;
+use graph::object_store::{self, path::Path, ObjectStore};
+use graph::prelude::{
+    anyhow, serde_json as json, BlockNumber, BlockPtr, Deserialize, Serialize, StoreError,
+};
+use graph::url::Url;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::chain_store::JsonBlock;
+
+#[derive(Serialize, Deserialize)]
+struct ArchivedBlock {
+    number: BlockNumber,
+    parent_hash: String,
+    data: json::Value,
+}
+
+/// A read-mostly block store that keeps blocks in object storage. It
+/// provides the lookups that `ChainStore` needs for blocks that are no
+/// longer in the database
+pub struct ObjectStorageBlockStore {
+    store: Box<dyn ObjectStore>,
+    prefix: Path,
+}
+
+impl ObjectStorageBlockStore {
+    /// Create a block store for `chain` from a URL like
+    /// `s3://bucket/path` or `gs://bucket/path`. Credentials and other
+    /// options are taken from the usual environment variables for the
+    /// respective object store, e.g., `AWS_ACCESS_KEY_ID` or
+    /// `GOOGLE_SERVICE_ACCOUNT`
+    pub fn new(url: &str, chain: &str) -> Result<Self, StoreError> {
+        let url =
+            Url::parse(url).map_err(|e| anyhow!("invalid block archive url `{}`: {}", url, e))?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, prefix) = object_store::parse_url_opts(&url, options)
+            .map_err(|e| anyhow!("can not use block archive at `{}`: {}", url, e))?;
+        Ok(Self {
+            store,
+            prefix: prefix.child(chain),
+        })
+    }
+
+    fn path(&self, hash: &BlockHash) -> Path {
+        self.prefix.child(format!("{}.json", hash.hash_hex()))
+    }
+
+    fn numbers_path(&self, number: BlockNumber) -> Path {
+        self.prefix.child("numbers").child(number.to_string())
+    }
+
+    /// Store `block` in the archive. Blocks without data are not stored
+    /// since there is nothing we could return for them later
+    pub(crate) async fn put(&self, block: &JsonBlock) -> Result<(), StoreError> {
+        let data = match &block.data {
+            Some(data) => data.clone(),
+            None => return Ok(()),
+        };
+        let archived = ArchivedBlock {
+            number: block.ptr.number,
+            parent_hash: block.parent_hash.hash_hex(),
+            data,
+        };
+        let bytes = json::to_vec(&archived)?;
+        self.store
+            .put(&self.path(&block.ptr.hash), Bytes::from(bytes))
+            .await
+            .map_err(|e| anyhow!("failed to archive block {}: {}", block.ptr.hash_hex(), e))?;
+        // Write the number index only after the block itself so that
+        // every hash we find through it can also be looked up
+        let marker = self
+            .numbers_path(block.ptr.number)
+            .child(block.ptr.hash_hex());
+        self.store
+            .put(&marker, Bytes::new())
+            .await
+            .map_err(|e| anyhow!("failed to archive block {}: {}", block.ptr.hash_hex(), e))?;
+        Ok(())
+    }
+
+    /// Look up the block with the given hash. Return `None` if the archive
+    /// does not have that block
+    pub(crate) async fn get(&self, hash: &BlockHash) -> Result<Option<JsonBlock>, StoreError> {
+        let res = match self.store.get(&self.path(hash)).await {
+            Ok(res) => res,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(anyhow!("failed to read archived block {}: {}", hash, e).into()),
+        };
+        let bytes = res
+            .bytes()
+            .await
+            .map_err(|e| anyhow!("failed to read archived block {}: {}", hash, e))?;
+        let archived: ArchivedBlock = json::from_slice(&bytes)?;
+        let parent_hash = BlockHash::from_str(&archived.parent_hash)?;
+        Ok(Some(JsonBlock::new(
+            BlockPtr::new(hash.clone(), archived.number),
+            parent_hash,
+            Some(archived.data),
+        )))
+    }
+
+    /// Look up all the blocks in `hashes`; blocks that the archive does not
+    /// have are silently omitted from the result
+    pub(crate) async fn blocks(&self, hashes: &[BlockHash]) -> Result<Vec<JsonBlock>, StoreError> {
+        let blocks = try_join_all(hashes.iter().map(|hash| self.get(hash))).await?;
+        Ok(blocks.into_iter().flatten().collect())
+    }
+
+    /// Look up the numbers of the blocks in `hashes`; blocks that the
+    /// archive does not have are omitted from the result
+    pub(crate) async fn block_numbers(
+        &self,
+        hashes: &[BlockHash],
+    ) -> Result<HashMap<BlockHash, BlockNumber>, StoreError> {
+        let blocks = self.blocks(hashes).await?;
+        Ok(blocks
+            .into_iter()
+            .map(|block| (block.ptr.hash, block.ptr.number))
+            .collect())
+    }
+
+    /// Return the hashes of all archived blocks with the given number
+    pub(crate) async fn block_hashes_by_number(
+        &self,
+        number: BlockNumber,
+    ) -> Result<Vec<BlockHash>, StoreError> {
+        let list = self
+            .store
+            .list_with_delimiter(Some(&self.numbers_path(number)))
+            .await
+            .map_err(|e| anyhow!("failed to list archived blocks for {}: {}", number, e))?;
+        list.objects
+            .iter()
+            .filter_map(|meta| meta.location.filename())
+            .map(|hash| BlockHash::from_str(hash).map_err(StoreError::from))
+            .collect()
+    }
+
+    /// Walk back `offset` blocks from `block` by following parent hashes
+    /// through the archive, reading exactly one block per step. If `root`
+    /// is given, stop at the block whose parent is `root`. Return `None`
+    /// if a block along the way is not in the archive
+    pub(crate) async fn ancestor_of(
+        &self,
+        mut block: JsonBlock,
+        offset: BlockNumber,
+        root: Option<BlockHash>,
+    ) -> Result<Option<JsonBlock>, StoreError> {
+        for _ in 0..offset {
+            if root.as_ref() == Some(&block.parent_hash) {
+                break;
+            }
+            block = match self.get(&block.parent_hash).await? {
+                Some(block) => block,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(block))
+    }
+
+    /// Like `ancestor_of`, but for a starting block that also needs to be
+    /// read from the archive
+    pub(crate) async fn ancestor_block(
+        &self,
+        hash: &BlockHash,
+        offset: BlockNumber,
+        root: Option<BlockHash>,
+    ) -> Result<Option<JsonBlock>, StoreError> {
+        match self.get(hash).await? {
+            Some(block) => self.ancestor_of(block, offset, root).await,
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::serde_json::json;
+    use graph::tokio::runtime::Runtime;
+
+    use super::*;
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from(vec![n; 32])
+    }
+
+    fn block(n: u8) -> JsonBlock {
+        JsonBlock::new(
+            BlockPtr::new(hash(n), n as BlockNumber),
+            hash(n.saturating_sub(1)),
+            Some(json!({ "block": { "number": n } })),
+        )
+    }
+
+    /// Run `f` against an archive in a fresh temporary directory
+    fn with_archive<F, Fut>(name: &str, f: F)
+    where
+        F: FnOnce(ObjectStorageBlockStore) -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        let dir =
+            std::env::temp_dir().join(format!("block-archive-{}-{}", name, std::process::id()));
+        let url = format!("file://{}", dir.display());
+        let archive = ObjectStorageBlockStore::new(&url, "mainnet").unwrap();
+
+        Runtime::new().unwrap().block_on(f(archive));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn put_and_get() {
+        with_archive("put", |archive| async move {
+            for n in 1..=3 {
+                archive.put(&block(n)).await.unwrap();
+            }
+
+            let found = archive.get(&hash(2)).await.unwrap().unwrap();
+            assert_eq!(2, found.ptr.number);
+            assert_eq!(hash(1), found.parent_hash);
+            assert_eq!(block(2).data, found.data);
+            assert!(archive.get(&hash(7)).await.unwrap().is_none());
+
+            let found = archive.blocks(&[hash(1), hash(7), hash(3)]).await.unwrap();
+            let mut numbers: Vec<_> = found.iter().map(|block| block.ptr.number).collect();
+            numbers.sort();
+            assert_eq!(vec![1, 3], numbers);
+
+            // Blocks without data are not archived
+            let empty = JsonBlock::new(BlockPtr::new(hash(4), 4), hash(3), None);
+            archive.put(&empty).await.unwrap();
+            assert!(archive.get(&hash(4)).await.unwrap().is_none());
+            assert!(archive.block_hashes_by_number(4).await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn block_numbers() {
+        with_archive("numbers", |archive| async move {
+            for n in 1..=3 {
+                archive.put(&block(n)).await.unwrap();
+            }
+            // A second block at height 2 from a different fork
+            let uncle = JsonBlock::new(
+                BlockPtr::new(hash(12), 2),
+                hash(1),
+                Some(json!({ "block": { "number": 2 } })),
+            );
+            archive.put(&uncle).await.unwrap();
+
+            let numbers = archive
+                .block_numbers(&[hash(1), hash(12), hash(7)])
+                .await
+                .unwrap();
+            assert_eq!(2, numbers.len());
+            assert_eq!(Some(&1), numbers.get(&hash(1)));
+            assert_eq!(Some(&2), numbers.get(&hash(12)));
+
+            let mut hashes = archive.block_hashes_by_number(2).await.unwrap();
+            hashes.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(vec![hash(2), hash(12)], hashes);
+            assert_eq!(
+                vec![hash(3)],
+                archive.block_hashes_by_number(3).await.unwrap()
+            );
+            assert!(archive.block_hashes_by_number(7).await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn ancestor_block() {
+        with_archive("ancestor", |archive| async move {
+            for n in 1..=5 {
+                archive.put(&block(n)).await.unwrap();
+            }
+
+            let number = |block: Option<JsonBlock>| block.map(|block| block.ptr.number);
+
+            let ancestor = archive.ancestor_block(&hash(3), 0, None).await.unwrap();
+            assert_eq!(Some(3), number(ancestor));
+            let ancestor = archive.ancestor_block(&hash(3), 2, None).await.unwrap();
+            assert_eq!(Some(1), number(ancestor));
+            let ancestor = archive
+                .ancestor_block(&hash(3), 2, Some(hash(1)))
+                .await
+                .unwrap();
+            assert_eq!(Some(2), number(ancestor));
+            // Block 0 is not in the archive
+            let ancestor = archive.ancestor_block(&hash(3), 3, None).await.unwrap();
+            assert!(ancestor.is_none());
+            // Neither is the starting block
+            let ancestor = archive.ancestor_block(&hash(7), 1, None).await.unwrap();
+            assert!(ancestor.is_none());
+
+            // Starting from a block we already have only reads its
+            // ancestors, even if the starting block is not archived
+            let start = JsonBlock::new(BlockPtr::new(hash(6), 6), hash(5), None);
+            let ancestor = archive.ancestor_of(start.clone(), 3, None).await.unwrap();
+            assert_eq!(Some(3), number(ancestor));
+            let ancestor = archive
+                .ancestor_of(start.clone(), 3, Some(hash(4)))
+                .await
+                .unwrap();
+            assert_eq!(Some(5), number(ancestor));
+            let ancestor = archive.ancestor_of(start, 0, None).await.unwrap();
+            assert_eq!(Some(6), number(ancestor));
+
+            // A gap in the archive ends the walk
+            archive.store.delete(&archive.path(&hash(4))).await.unwrap();
+            let ancestor = archive.ancestor_block(&hash(5), 1, None).await.unwrap();
+            assert!(ancestor.is_none());
+            let ancestor = archive.ancestor_block(&hash(3), 1, None).await.unwrap();
+            assert_eq!(Some(2), number(ancestor));
+        });
+    }
+}
@@ -184,8 +184,9 @@ impl<'a> BlockRangeColumn<'a> {
         out: &mut AstPass<'_, 'b, Pg>,
         filters_by_id: bool,
     ) -> QueryResult<()> {
-        out.unsafe_to_cache_prepared();
-
+        // The SQL we generate only depends on the table and on whether
+        // `block` is `BLOCK_NUMBER_MAX`. It is up to the queries that use
+        // this whether their prepared statement can be cached
         match self {
             BlockRangeColumn::Mutable { table, block, .. } => {
                 self.name(out);
@@ -227,6 +228,35 @@ impl<'a> BlockRangeColumn<'a> {
         }
     }
 
+    /// Output SQL that excludes versions that were written more than the
+    /// time-to-live of the table ago. Since entities with a time-to-live
+    /// only disappear for queries, this must not be used for lookups
+    /// during indexing. Outputs nothing for tables without a time-to-live
+    pub fn not_expired(&self, out: &mut AstPass<Pg>) {
+        let (table, block) = match self {
+            BlockRangeColumn::Mutable { table, block, .. }
+            | BlockRangeColumn::Immutable { table, block, .. } => (table, *block),
+        };
+        let Some(ttl) = table.ttl else {
+            return;
+        };
+        if block == BLOCK_NUMBER_MAX {
+            return;
+        }
+        out.push_sql(" and ");
+        match self {
+            BlockRangeColumn::Mutable { table_prefix, .. } => {
+                out.push_sql("lower(");
+                out.push_sql(table_prefix);
+                out.push_sql(BLOCK_RANGE_COLUMN);
+                out.push_sql(")");
+            }
+            BlockRangeColumn::Immutable { .. } => self.name(out),
+        }
+        out.push_sql(" > ");
+        out.push_sql(&(block - ttl).to_string());
+    }
+
     pub fn column_name(&self) -> &str {
         match self {
             BlockRangeColumn::Mutable { .. } => BLOCK_RANGE_COLUMN,
@@ -19,6 +19,7 @@ use graph::{constraint_violation, prelude::CheapClone};
 use graph::{prelude::StoreError, util::timed_cache::TimedCache};
 
 use crate::{
+    block_archive::ObjectStorageBlockStore,
     chain_head_listener::ChainHeadUpdateSender,
     chain_store::{ChainStoreMetrics, Storage},
     connection_pool::ConnectionPool,
@@ -362,6 +363,12 @@ impl BlockStore {
         );
         let ident = chain.network_identifier()?;
         let logger = self.logger.new(o!("network" => chain.name.clone()));
+        let archive = ENV_VARS
+            .store
+            .block_archive_url
+            .as_deref()
+            .map(|url| ObjectStorageBlockStore::new(url, &chain.name).map(Arc::new))
+            .transpose()?;
         let store = ChainStore::new(
             logger,
             chain.name.clone(),
@@ -371,6 +378,7 @@ impl BlockStore {
             pool,
             ENV_VARS.store.recent_blocks_cache_capacity,
             self.chain_store_metrics.clone(),
+            archive,
         );
         if create {
             store.create(&ident)?;
@@ -498,6 +506,32 @@ impl BlockStore {
         Ok(())
     }
 
+    /// Move blocks that are more than `GRAPH_STORE_BLOCK_ARCHIVE_DEPTH`
+    /// blocks behind the chain head into the block archive for all chains.
+    /// Return the number of blocks that were archived for each chain
+    pub async fn archive_blocks(&self) -> Vec<(String, Result<usize, StoreError>)> {
+        let stores: Vec<_> = self.stores.read().unwrap().values().cloned().collect();
+        let mut res = Vec::new();
+        for store in stores {
+            let archived = store
+                .archive_blocks(ENV_VARS.store.block_archive_depth)
+                .await;
+            res.push((store.chain.clone(), archived));
+        }
+        res
+    }
+
+    /// Evict calls from the call caches of all chains so that each of them
+    /// stays within `GRAPH_STORE_CALL_CACHE_MAX_BYTES`. Return how many
+    /// bytes were evicted for each chain
+    pub fn evict_call_caches(&self, max_bytes: u64) -> Vec<(String, Result<i64, StoreError>)> {
+        let stores: Vec<_> = self.stores.read().unwrap().values().cloned().collect();
+        stores
+            .into_iter()
+            .map(|store| (store.chain.clone(), store.evict_call_cache(max_bytes)))
+            .collect()
+    }
+
     fn truncate_block_caches(&self) -> Result<(), StoreError> {
         for store in self.stores.read().unwrap().values() {
             store.truncate_block_cache()?
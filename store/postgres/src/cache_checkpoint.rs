@@ -0,0 +1,99 @@
+//! Save the keys of the entities in a subgraph's entity cache so that the
+//! subgraph can load them into its cache again when it is restarted. The
+//! keys are kept in the `subgraphs.entity_cache_checkpoint` table in the
+//! deployment's shard.
+//!
+//! Only keys are saved; the entities themselves are loaded from the store
+//! when the checkpoint is restored, so that a checkpoint can never make a
+//! subgraph see entities that differ from what is in the store. Saving a
+//! checkpoint replaces the previous checkpoint of the deployment.
+//!
+//! The checkpoint deliberately leaves out the rest of the in-memory
+//! indexing state: data sources created from templates are already stored
+//! with the deployment and restored from there, and the blocks that the
+//! block stream had buffered are fetched again starting at the
+//! deployment's block pointer, which is the only cursor the block stream
+//! needs
+use std::collections::BTreeMap;
+
+use diesel::{
+    delete, sql_query,
+    sql_types::{Array, Integer, Text},
+    Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use graph::{
+    data_source::CausalityRegion,
+    prelude::StoreError,
+    schema::{EntityKey, InputSchema},
+};
+
+use crate::primary::Site;
+
+table! {
+    subgraphs.entity_cache_checkpoint(deployment, entity_type, causality_region) {
+        deployment -> Integer,
+        entity_type -> Text,
+        causality_region -> Integer,
+        ids -> Array<Text>,
+    }
+}
+
+pub(crate) fn save(
+    conn: &mut PgConnection,
+    site: &Site,
+    keys: &[EntityKey],
+) -> Result<(), StoreError> {
+    use entity_cache_checkpoint as c;
+
+    let mut groups: BTreeMap<(&str, CausalityRegion), Vec<String>> = BTreeMap::new();
+    for key in keys {
+        groups
+            .entry((key.entity_type.as_str(), key.causality_region))
+            .or_default()
+            .push(key.entity_id.to_string());
+    }
+
+    conn.transaction::<_, StoreError, _>(|conn| {
+        delete(c::table.filter(c::deployment.eq(site.id))).execute(conn)?;
+        for ((entity_type, causality_region), ids) in groups {
+            sql_query(
+                "insert into subgraphs.entity_cache_checkpoint\
+                   (deployment, entity_type, causality_region, ids) \
+                 values ($1, $2, $3, $4)",
+            )
+            .bind::<Integer, _>(site.id)
+            .bind::<Text, _>(entity_type)
+            .bind::<Integer, _>(causality_region)
+            .bind::<Array<Text>, _>(ids)
+            .execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+/// Load the keys of the last checkpoint. Keys for entity types that are
+/// not in `schema` or whose ids can not be parsed are ignored
+pub(crate) fn load(
+    conn: &mut PgConnection,
+    site: &Site,
+    schema: &InputSchema,
+) -> Result<Vec<EntityKey>, StoreError> {
+    use entity_cache_checkpoint as c;
+
+    let rows = c::table
+        .filter(c::deployment.eq(site.id))
+        .select((c::entity_type, c::causality_region, c::ids))
+        .load::<(String, CausalityRegion, Vec<String>)>(conn)?;
+
+    let mut keys = Vec::new();
+    for (entity_type, causality_region, ids) in rows {
+        let Ok(entity_type) = schema.entity_type(entity_type.as_str()) else {
+            continue;
+        };
+        keys.extend(
+            ids.into_iter()
+                .filter_map(|id| entity_type.parse_key_in(id, causality_region).ok()),
+        );
+    }
+    Ok(keys)
+}
@@ -6,7 +6,7 @@ use diesel::{
     sql_types::{Array, BigInt, Double, Nullable, Text},
     ExpressionMethods, QueryDsl,
 };
-use graph::components::store::VersionStats;
+use graph::components::store::{EntityIndex, IndexBuildProgress, VersionStats};
 use graph::prelude::BlockNumber;
 use graph::schema::EntityType;
 use itertools::Itertools;
@@ -19,7 +19,7 @@ use std::time::Duration;
 use graph::prelude::anyhow::anyhow;
 use graph::{
     data::subgraph::schema::POI_TABLE,
-    prelude::{lazy_static, StoreError},
+    prelude::{lazy_static, StoreError, ENV_VARS},
 };
 
 use crate::connection_pool::ForeignServer;
@@ -102,6 +102,8 @@ table! {
         table_name -> Text,
         is_account_like -> Nullable<Bool>,
         last_pruned_block -> Nullable<Integer>,
+        history_blocks -> Nullable<Integer>,
+        earliest_block -> Nullable<Integer>,
     }
 }
 
@@ -186,6 +188,16 @@ pub struct Catalog {
     /// Whether the database supports `int4_minmax_multi_ops` etc.
     /// See the [Postgres docs](https://www.postgresql.org/docs/15/brin-builtin-opclasses.html)
     has_minmax_multi_ops: bool,
+
+    /// Whether the PostGIS extension is installed. Spatial filters use it
+    /// to compute distances if it is available
+    pub has_postgis: bool,
+
+    /// Whether tables for mutable entities should be partitioned when they
+    /// are created
+    pub(crate) partition_new_tables: bool,
+    /// The names of the tables that are partitioned by block range
+    pub(crate) partitioned_tables: HashSet<String>,
 }
 
 impl Catalog {
@@ -199,6 +211,8 @@ impl Catalog {
         let text_columns = get_text_columns(conn, &site.namespace)?;
         let use_poi = supports_proof_of_indexing(conn, &site.namespace)?;
         let has_minmax_multi_ops = has_minmax_multi_ops(conn)?;
+        let has_postgis = has_postgis(conn)?;
+        let partitioned_tables = partitioned_tables(conn, &site.namespace)?;
 
         Ok(Catalog {
             site,
@@ -207,6 +221,9 @@ impl Catalog {
             use_bytea_prefix,
             entities_with_causality_region: entities_with_causality_region.into_iter().collect(),
             has_minmax_multi_ops,
+            has_postgis,
+            partition_new_tables: false,
+            partitioned_tables,
         })
     }
 
@@ -217,6 +234,7 @@ impl Catalog {
         entities_with_causality_region: BTreeSet<EntityType>,
     ) -> Result<Self, StoreError> {
         let has_minmax_multi_ops = has_minmax_multi_ops(conn)?;
+        let has_postgis = has_postgis(conn)?;
 
         Ok(Catalog {
             site,
@@ -228,6 +246,9 @@ impl Catalog {
             use_bytea_prefix: true,
             entities_with_causality_region,
             has_minmax_multi_ops,
+            has_postgis,
+            partition_new_tables: ENV_VARS.store.partition_blocks > 0,
+            partitioned_tables: HashSet::new(),
         })
     }
 
@@ -245,9 +266,19 @@ impl Catalog {
             use_bytea_prefix: true,
             entities_with_causality_region,
             has_minmax_multi_ops: false,
+            has_postgis: false,
+            partition_new_tables: false,
+            partitioned_tables: HashSet::new(),
         })
     }
 
+    /// Partition the tables for mutable entities when they are created,
+    /// independently of `GRAPH_STORE_PARTITION_BLOCKS`
+    pub fn with_partitioned_tables(mut self) -> Self {
+        self.partition_new_tables = true;
+        self
+    }
+
     /// Return `true` if `table` exists and contains the given `column` and
     /// if that column is of data type `text`
     pub fn is_existing_text_column(&self, table: &SqlName, column: &SqlName) -> bool {
@@ -301,6 +332,63 @@ fn get_text_columns(
     Ok(map)
 }
 
+/// Return the names of all partitioned tables in `namespace`
+fn partitioned_tables(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+) -> Result<HashSet<String>, StoreError> {
+    const QUERY: &str = "
+        select c.relname::text as table_name
+          from pg_class c, pg_namespace n
+         where c.relnamespace = n.oid
+           and n.nspname = $1
+           and c.relkind = 'p'";
+
+    #[derive(Debug, QueryableByName)]
+    struct Table {
+        #[diesel(sql_type = Text)]
+        pub table_name: String,
+    }
+
+    Ok(diesel::sql_query(QUERY)
+        .bind::<Text, _>(namespace.as_str())
+        .load::<Table>(conn)?
+        .into_iter()
+        .map(|table| table.table_name)
+        .collect())
+}
+
+/// Return the names of the tables in `namespace` that have a child table
+/// whose name is the name of the table followed by `$` and `suffix`
+pub(crate) fn archived_tables(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+    suffix: &str,
+) -> Result<HashSet<String>, StoreError> {
+    const QUERY: &str = "
+        select p.relname::text as table_name
+          from pg_inherits i
+          join pg_class c on c.oid = i.inhrelid
+          join pg_class p on p.oid = i.inhparent
+          join pg_namespace n on n.oid = p.relnamespace
+         where n.nspname = $1
+           and c.relname = p.relname || '$' || $2";
+
+    #[derive(Debug, QueryableByName)]
+    struct Table {
+        #[diesel(sql_type = Text)]
+        pub table_name: String,
+    }
+
+    Ok(diesel::sql_query(QUERY)
+        .bind::<Text, _>(namespace.as_str())
+        .bind::<Text, _>(suffix)
+        .load::<Table>(conn)?
+        .into_iter()
+        .map(|table| table.table_name)
+        .collect())
+}
+
 pub fn table_exists(
     conn: &mut PgConnection,
     namespace: &str,
@@ -469,6 +557,8 @@ pub fn set_account_like(
     Ok(())
 }
 
+/// Copy the table stats of `src` to `dst`. How much history tables keep
+/// is only copied for tables for which `dst` does not have a setting yet
 pub fn copy_account_like(
     conn: &mut PgConnection,
     src: &Site,
@@ -476,10 +566,14 @@ pub fn copy_account_like(
 ) -> Result<usize, StoreError> {
     let src_nsp = ForeignServer::metadata_schema_in(&src.shard, &dst.shard);
     let query = format!(
-        "insert into subgraphs.table_stats(deployment, table_name, is_account_like, last_pruned_block)
-         select $2 as deployment, ts.table_name, ts.is_account_like, ts.last_pruned_block
+        "insert into subgraphs.table_stats(deployment, table_name, is_account_like, last_pruned_block, history_blocks, earliest_block)
+         select $2 as deployment, ts.table_name, ts.is_account_like, ts.last_pruned_block, ts.history_blocks, ts.earliest_block
            from {src_nsp}.table_stats ts
-          where ts.deployment = $1",
+          where ts.deployment = $1
+             on conflict(deployment, table_name)
+             do update set is_account_like = excluded.is_account_like,
+                           last_pruned_block = excluded.last_pruned_block,
+                           earliest_block = excluded.earliest_block",
         src_nsp = src_nsp
     );
     Ok(sql_query(query)
@@ -509,6 +603,87 @@ pub fn set_last_pruned_block(
     Ok(())
 }
 
+/// Return how many blocks of history each table of `site` keeps for the
+/// tables that keep less history than the deployment
+pub fn table_history_blocks(
+    conn: &mut PgConnection,
+    site: &Site,
+) -> Result<HashMap<String, BlockNumber>, StoreError> {
+    use table_stats as ts;
+    let history = ts::table
+        .filter(ts::deployment.eq(site.id))
+        .filter(ts::history_blocks.is_not_null())
+        .select((ts::table_name, ts::history_blocks))
+        .get_results::<(String, Option<BlockNumber>)>(conn)?
+        .into_iter()
+        .filter_map(|(name, history_blocks)| history_blocks.map(|hb| (name, hb)))
+        .collect();
+    Ok(history)
+}
+
+pub fn set_table_history_blocks(
+    conn: &mut PgConnection,
+    site: &Site,
+    table_name: &SqlName,
+    history_blocks: BlockNumber,
+) -> Result<(), StoreError> {
+    use table_stats as ts;
+
+    insert_into(ts::table)
+        .values((
+            ts::deployment.eq(site.id),
+            ts::table_name.eq(table_name.as_str()),
+            ts::history_blocks.eq(history_blocks),
+        ))
+        .on_conflict((ts::deployment, ts::table_name))
+        .do_update()
+        .set(ts::history_blocks.eq(history_blocks))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Return the earliest block for which tables of `site` that keep less
+/// history than the deployment still have data
+pub fn table_earliest_blocks(
+    conn: &mut PgConnection,
+    site: &Site,
+) -> Result<HashMap<String, BlockNumber>, StoreError> {
+    use table_stats as ts;
+    let earliest = ts::table
+        .filter(ts::deployment.eq(site.id))
+        .filter(ts::earliest_block.is_not_null())
+        .select((ts::table_name, ts::earliest_block))
+        .get_results::<(String, Option<BlockNumber>)>(conn)?
+        .into_iter()
+        .filter_map(|(name, earliest_block)| earliest_block.map(|eb| (name, eb)))
+        .collect();
+    Ok(earliest)
+}
+
+/// Record that the table `table_name` does not have data for blocks
+/// before `earliest_block`. This must be called before any data is
+/// removed from the table
+pub fn set_table_earliest_block(
+    conn: &mut PgConnection,
+    site: &Site,
+    table_name: &SqlName,
+    earliest_block: BlockNumber,
+) -> Result<(), StoreError> {
+    use table_stats as ts;
+
+    insert_into(ts::table)
+        .values((
+            ts::deployment.eq(site.id),
+            ts::table_name.eq(table_name.as_str()),
+            ts::earliest_block.eq(earliest_block),
+        ))
+        .on_conflict((ts::deployment, ts::table_name))
+        .do_update()
+        .set(ts::earliest_block.eq(earliest_block))
+        .execute(conn)?;
+    Ok(())
+}
+
 pub(crate) mod table_schema {
     use super::*;
 
@@ -687,6 +862,70 @@ pub(crate) fn indexes_for_table(
 
     Ok(results.into_iter().map(|i| i.def).collect())
 }
+
+/// Return the indexes on `schema_name.table_name` together with whether
+/// they are valid and, for indexes that are being built, the progress of
+/// the build
+pub(crate) fn entity_indexes(
+    conn: &mut PgConnection,
+    schema_name: &str,
+    table_name: &str,
+) -> Result<Vec<EntityIndex>, StoreError> {
+    #[derive(QueryableByName)]
+    struct Index {
+        #[diesel(sql_type = Text)]
+        name: String,
+        #[diesel(sql_type = Text)]
+        definition: String,
+        #[diesel(sql_type = Bool)]
+        valid: bool,
+        #[diesel(sql_type = Nullable<Text>)]
+        phase: Option<String>,
+        #[diesel(sql_type = Nullable<BigInt>)]
+        blocks_done: Option<i64>,
+        #[diesel(sql_type = Nullable<BigInt>)]
+        blocks_total: Option<i64>,
+        #[diesel(sql_type = Nullable<BigInt>)]
+        tuples_done: Option<i64>,
+        #[diesel(sql_type = Nullable<BigInt>)]
+        tuples_total: Option<i64>,
+    }
+
+    let query = "
+        select c.relname::text as name,
+               pg_get_indexdef(i.indexrelid) as definition,
+               i.indisvalid as valid,
+               p.phase, p.blocks_done, p.blocks_total,
+               p.tuples_done, p.tuples_total
+          from pg_index i
+          join pg_class c on c.oid = i.indexrelid
+          join pg_class t on t.oid = i.indrelid
+          join pg_namespace n on n.oid = t.relnamespace
+          left join pg_stat_progress_create_index p on p.index_relid = i.indexrelid
+         where n.nspname = $1
+           and t.relname = $2
+         order by c.relname";
+    let indexes = sql_query(query)
+        .bind::<Text, _>(schema_name)
+        .bind::<Text, _>(table_name)
+        .load::<Index>(conn)?;
+
+    Ok(indexes
+        .into_iter()
+        .map(|index| EntityIndex {
+            name: index.name,
+            definition: index.definition,
+            valid: index.valid,
+            progress: index.phase.map(|phase| IndexBuildProgress {
+                phase,
+                blocks_done: index.blocks_done.unwrap_or(0),
+                blocks_total: index.blocks_total.unwrap_or(0),
+                tuples_done: index.tuples_done.unwrap_or(0),
+                tuples_total: index.tuples_total.unwrap_or(0),
+            }),
+        })
+        .collect())
+}
 pub(crate) fn drop_index(
     conn: &mut PgConnection,
     schema_name: &str,
@@ -765,6 +1004,41 @@ pub fn stats(conn: &mut PgConnection, site: &Site) -> Result<Vec<VersionStats>,
     Ok(stats.into_iter().map(|s| s.into()).collect())
 }
 
+/// Return the size of each table in `namespace` and the total size of its
+/// indexes in bytes, keyed by table name. The table size includes the
+/// table's TOAST data
+pub(crate) fn table_sizes(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+) -> Result<HashMap<String, (i64, i64)>, StoreError> {
+    #[derive(QueryableByName)]
+    struct Size {
+        #[diesel(sql_type = Text)]
+        tablename: String,
+        #[diesel(sql_type = BigInt)]
+        table_size: i64,
+        #[diesel(sql_type = BigInt)]
+        index_size: i64,
+    }
+
+    let query = "
+        select c.relname::text as tablename,
+               pg_table_size(c.oid) as table_size,
+               pg_indexes_size(c.oid) as index_size
+          from pg_class c
+          join pg_namespace n on n.oid = c.relnamespace
+         where n.nspname = $1
+           and c.relkind = 'r'";
+    let sizes = sql_query(query)
+        .bind::<Text, _>(namespace.as_str())
+        .load::<Size>(conn)?;
+
+    Ok(sizes
+        .into_iter()
+        .map(|size| (size.tablename, (size.table_size, size.index_size)))
+        .collect())
+}
+
 /// Return by how much the slowest replica connected to the database `conn`
 /// is lagging. The returned value has millisecond precision. If the
 /// database has no replicas, return `0`
@@ -865,6 +1139,81 @@ pub(crate) fn set_stats_target(
     Ok(())
 }
 
+/// Return the storage parameters that are set for the tables in
+/// `namespace` as `name=value` strings, for tables that have any
+pub(crate) fn storage_params(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+) -> Result<BTreeMap<SqlName, Vec<String>>, StoreError> {
+    #[derive(QueryableByName)]
+    struct TableOptions {
+        #[diesel(sql_type = Text)]
+        name: String,
+        #[diesel(sql_type = Array<Text>)]
+        options: Vec<String>,
+    }
+
+    const QUERY: &str = "select c.relname as name, c.reloptions as options \
+                           from pg_class c, pg_namespace n \
+                          where c.relnamespace = n.oid \
+                            and c.relkind in ('r', 'p') \
+                            and c.reloptions is not null \
+                            and n.nspname = $1";
+
+    let options = sql_query(QUERY)
+        .bind::<Text, _>(namespace.as_str())
+        .get_results::<TableOptions>(conn)?
+        .into_iter()
+        .map(|opts| (SqlName::from(opts.name), opts.options))
+        .collect();
+    Ok(options)
+}
+
+/// Set the storage parameters `params`, given as `(name, value)` pairs, on
+/// `table`. Parameters that are not mentioned are left alone
+pub(crate) fn set_storage_params(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+    table: &SqlName,
+    params: &[(&str, String)],
+) -> Result<(), StoreError> {
+    if params.is_empty() {
+        return Ok(());
+    }
+    let params = params
+        .iter()
+        .map(|(name, value)| format!("{name} = {value}"))
+        .join(", ");
+    let query = format!(
+        "alter table {}.{} set ({})",
+        namespace,
+        table.quoted(),
+        params
+    );
+    conn.batch_execute(&query)?;
+    Ok(())
+}
+
+/// Reset the storage parameters `names` on `table` to their defaults
+pub(crate) fn reset_storage_params(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+    table: &SqlName,
+    names: &[&str],
+) -> Result<(), StoreError> {
+    if names.is_empty() {
+        return Ok(());
+    }
+    let query = format!(
+        "alter table {}.{} reset ({})",
+        namespace,
+        table.quoted(),
+        names.join(", ")
+    );
+    conn.batch_execute(&query)?;
+    Ok(())
+}
+
 /// Return the names of all tables in the `namespace` that need to be
 /// analyzed. Whether a table needs to be analyzed is determined with the
 /// same logic that Postgres' [autovacuum
@@ -896,6 +1245,37 @@ pub(crate) fn needs_autoanalyze(
     Ok(tables)
 }
 
+/// Return Postgres' estimate of the number of rows of each table in
+/// `namespace` as of the last time the table was vacuumed or analyzed
+pub(crate) fn row_estimates(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+) -> Result<HashMap<String, f64>, StoreError> {
+    // `reltuples` is -1 for tables that were never analyzed
+    const QUERY: &str = "select c.relname::text as name, \
+                                greatest(c.reltuples, 0)::float8 as rows \
+                           from pg_class c \
+                           join pg_namespace n on n.oid = c.relnamespace \
+                          where n.nspname = $1 \
+                            and c.relkind in ('r', 'p')";
+
+    #[derive(QueryableByName)]
+    struct Estimate {
+        #[diesel(sql_type = Text)]
+        name: String,
+        #[diesel(sql_type = Double)]
+        rows: f64,
+    }
+
+    let estimates = sql_query(QUERY)
+        .bind::<Text, _>(namespace.as_str())
+        .get_results::<Estimate>(conn)?
+        .into_iter()
+        .map(|estimate| (estimate.name, estimate.rows))
+        .collect();
+    Ok(estimates)
+}
+
 /// Check whether the database for `conn` supports the `minmax_multi_ops`
 /// introduced in Postgres 14
 fn has_minmax_multi_ops(conn: &mut PgConnection) -> Result<bool, StoreError> {
@@ -911,3 +1291,18 @@ fn has_minmax_multi_ops(conn: &mut PgConnection) -> Result<bool, StoreError> {
 
     Ok(sql_query(QUERY).get_result::<Ops>(conn)?.has_ops)
 }
+
+/// Check whether the PostGIS extension is installed in the database for
+/// `conn`
+fn has_postgis(conn: &mut PgConnection) -> Result<bool, StoreError> {
+    const QUERY: &str = "select exists (select 1 from pg_extension \
+                                         where extname = 'postgis') as has_postgis";
+
+    #[derive(Queryable, QueryableByName)]
+    struct Ext {
+        #[diesel(sql_type = Bool)]
+        has_postgis: bool,
+    }
+
+    Ok(sql_query(QUERY).get_result::<Ext>(conn)?.has_postgis)
+}
@@ -6,7 +6,9 @@ use diesel::{insert_into, update};
 use graph::data::store::ethereum::call;
 use graph::derive::CheapClone;
 use graph::env::ENV_VARS;
+use graph::futures03::future::try_join_all;
 use graph::parking_lot::RwLock;
+use graph::prelude::rand::{thread_rng, Rng};
 use graph::prelude::MetricsRegistry;
 use graph::prometheus::{CounterVec, GaugeVec};
 use graph::slog::Logger;
@@ -17,35 +19,39 @@ use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
     iter::FromIterator,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
+use graph::blockchain::polling_block_stream::StreamCheckpoint;
 use graph::blockchain::{Block, BlockHash, ChainIdentifier};
 use graph::cheap_clone::CheapClone;
 use graph::prelude::web3::types::H256;
 use graph::prelude::{
     async_trait, serde_json as json, transaction_receipt::LightTransactionReceipt, BlockNumber,
-    BlockPtr, CachedEthereumCall, CancelableError, ChainStore as ChainStoreTrait, Error,
-    EthereumCallCache, StoreError,
+    BlockPtr, CachedEthereumCall, CancelableError, ChainStore as ChainStoreTrait, DeploymentHash,
+    Error, EthereumCallCache, StoreError,
 };
 use graph::{constraint_violation, ensure};
 
 use self::recent_blocks_cache::RecentBlocksCache;
 use crate::{
-    block_store::ChainStatus, chain_head_listener::ChainHeadUpdateSender,
-    connection_pool::ConnectionPool,
+    block_archive::ObjectStorageBlockStore, block_store::ChainStatus,
+    chain_head_listener::ChainHeadUpdateSender, connection_pool::ConnectionPool,
 };
 
 /// Our own internal notion of a block
 #[derive(Clone, Debug)]
-struct JsonBlock {
-    ptr: BlockPtr,
-    parent_hash: BlockHash,
-    data: Option<json::Value>,
+pub(crate) struct JsonBlock {
+    pub(crate) ptr: BlockPtr,
+    pub(crate) parent_hash: BlockHash,
+    pub(crate) data: Option<json::Value>,
 }
 
 impl JsonBlock {
-    fn new(ptr: BlockPtr, parent_hash: BlockHash, data: Option<json::Value>) -> Self {
+    pub(crate) fn new(ptr: BlockPtr, parent_hash: BlockHash, data: Option<json::Value>) -> Self {
         JsonBlock {
             ptr,
             parent_hash,
@@ -65,6 +71,19 @@ mod public {
             net_version -> Varchar,
             genesis_block_hash -> Varchar,
             head_block_cursor -> Nullable<Varchar>,
+            finalized_block_hash -> Nullable<Varchar>,
+            finalized_block_number -> Nullable<BigInt>,
+        }
+    }
+
+    table! {
+        polling_stream_checkpoints (network, deployment) {
+            network -> Varchar,
+            deployment -> Varchar,
+            filter -> Varchar,
+            from_block -> Integer,
+            to_block -> Integer,
+            blocks -> Array<Integer>,
         }
     }
 }
@@ -89,6 +108,7 @@ mod data {
     };
     use graph::blockchain::{Block, BlockHash};
     use graph::constraint_violation;
+    use graph::data::store::ethereum::call;
     use graph::data::store::scalar::Bytes;
     use graph::prelude::ethabi::ethereum_types::H160;
     use graph::prelude::transaction_receipt::LightTransactionReceipt;
@@ -102,12 +122,21 @@ mod data {
     use std::iter::FromIterator;
     use std::str::FromStr;
 
+    use crate::catalog;
     use crate::transaction_receipt::RawTransactionReceipt;
 
     use super::JsonBlock;
 
     pub(crate) const ETHEREUM_BLOCKS_TABLE_NAME: &str = "public.ethereum_blocks";
 
+    /// Turn a row from the call cache into the return value of the call
+    fn retval(return_value: Vec<u8>, revert_reason: Option<String>) -> call::Retval {
+        match revert_reason {
+            Some(reason) => call::Retval::Reverted(reason),
+            None => call::Retval::Value(Bytes::from(return_value)),
+        }
+    }
+
     pub(crate) const ETHEREUM_CALL_CACHE_TABLE_NAME: &str = "public.eth_call_cache";
 
     mod public {
@@ -255,6 +284,7 @@ mod data {
 
     impl CallCacheTable {
         const TABLE_NAME: &'static str = "call_cache";
+        const ACCESSED_AT_INDEX: &'static str = "call_cache_accessed_at_idx";
 
         fn new(namespace: &str) -> Self {
             CallCacheTable {
@@ -280,6 +310,10 @@ mod data {
             self.table.column::<Bytea, _>("return_value")
         }
 
+        fn revert_reason(&self) -> DynColumn<Nullable<Text>> {
+            self.table.column::<Nullable<Text>, _>("revert_reason")
+        }
+
         fn contract_address(&self) -> DynColumn<Bytea> {
             self.table.column::<Bytea, _>("contract_address")
         }
@@ -384,9 +418,12 @@ mod data {
                   id               bytea not null primary key,
                   return_value     bytea not null,
                   contract_address bytea not null,
-                  block_number     int4 not null
+                  block_number     int4 not null,
+                  accessed_at      timestamptz default now(),
+                  revert_reason    text
                 );
                 create index call_cache_block_number_idx ON {nsp}.call_cache(block_number);
+                create index call_cache_accessed_at_idx ON {nsp}.call_cache(accessed_at nulls first);
 
                 create table {nsp}.call_meta (
                     contract_address bytea not null primary key,
@@ -1123,6 +1160,182 @@ mod data {
             }
         }
 
+        /// Count the blocks with a number below `block`, leaving out the
+        /// genesis block, and the call cache entries for such blocks
+        pub(super) fn count_before(
+            &self,
+            conn: &mut PgConnection,
+            chain: &str,
+            block: BlockNumber,
+        ) -> Result<(usize, usize), Error> {
+            #[derive(QueryableByName)]
+            struct Count {
+                #[diesel(sql_type = BigInt)]
+                count: i64,
+            }
+
+            // The shared call cache does not record which chain an entry
+            // belongs to, and we can therefore only prune private storage
+            let (blocks, calls) = match self {
+                Storage::Shared => {
+                    return Err(constraint_violation!(
+                        "chain {} uses shared storage which can not be pruned",
+                        chain
+                    )
+                    .into())
+                }
+                Storage::Private(Schema {
+                    blocks, call_cache, ..
+                }) => {
+                    let query = format!(
+                        "select count(*) as count from {} where number < $1 and number > 0",
+                        blocks.qname
+                    );
+                    let blocks = sql_query(query)
+                        .bind::<BigInt, _>(block as i64)
+                        .get_result::<Count>(conn)?
+                        .count;
+                    let query = format!(
+                        "select count(*) as count from {} where block_number < $1",
+                        call_cache.qname
+                    );
+                    let calls = sql_query(query)
+                        .bind::<Integer, _>(block)
+                        .get_result::<Count>(conn)?
+                        .count;
+                    (blocks, calls)
+                }
+            };
+            Ok((blocks as usize, calls as usize))
+        }
+
+        /// Delete up to `limit` of the blocks with a number below `block`,
+        /// leaving out the genesis block, and up to `limit` of the call
+        /// cache entries for such blocks. Return how many blocks and call
+        /// cache entries were deleted. Like `count_before`, this only works
+        /// for private storage
+        pub(super) fn delete_batch_before(
+            &self,
+            conn: &mut PgConnection,
+            chain: &str,
+            block: BlockNumber,
+            limit: i64,
+        ) -> Result<(usize, usize), Error> {
+            match self {
+                Storage::Shared => Err(constraint_violation!(
+                    "chain {} uses shared storage which can not be pruned",
+                    chain
+                )
+                .into()),
+                Storage::Private(Schema {
+                    blocks, call_cache, ..
+                }) => {
+                    let query = format!(
+                        "delete from {qname} where hash in \
+                           (select hash from {qname} \
+                             where number < $1 and number > 0 limit $2)",
+                        qname = blocks.qname
+                    );
+                    let blocks = sql_query(query)
+                        .bind::<BigInt, _>(block as i64)
+                        .bind::<BigInt, _>(limit)
+                        .execute(conn)?;
+                    let query = format!(
+                        "delete from {qname} where id in \
+                           (select id from {qname} where block_number < $1 limit $2)",
+                        qname = call_cache.qname
+                    );
+                    let calls = sql_query(query)
+                        .bind::<Integer, _>(block)
+                        .bind::<BigInt, _>(limit)
+                        .execute(conn)?;
+                    Ok((blocks, calls))
+                }
+            }
+        }
+
+        /// Return up to `limit` of the oldest blocks with a number below
+        /// `block`, leaving out the genesis block. The data of the blocks is
+        /// returned exactly as it is stored
+        pub(super) fn blocks_before(
+            &self,
+            conn: &mut PgConnection,
+            chain: &str,
+            block: BlockNumber,
+            limit: i64,
+        ) -> Result<Vec<JsonBlock>, StoreError> {
+            let x = match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select((b::hash, b::number, b::parent_hash, b::data))
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::number.lt(block as i64))
+                        .filter(b::number.gt(0))
+                        .order_by(b::number)
+                        .limit(limit)
+                        .load::<(BlockHash, i64, BlockHash, json::Value)>(conn)
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select((
+                        blocks.hash(),
+                        blocks.number(),
+                        blocks.parent_hash(),
+                        blocks.data(),
+                    ))
+                    .filter(blocks.number().lt(block as i64))
+                    .filter(blocks.number().gt(0))
+                    .order_by(blocks.number())
+                    .limit(limit)
+                    .load::<(BlockHash, i64, BlockHash, json::Value)>(conn),
+            }?;
+            Ok(x.into_iter()
+                .map(|(hash, nr, parent, data)| {
+                    JsonBlock::new(BlockPtr::new(hash, nr as i32), parent, Some(data))
+                })
+                .collect())
+        }
+
+        /// Delete the blocks with the given hashes, but never the genesis
+        /// block
+        pub(super) fn delete_blocks(
+            &self,
+            conn: &mut PgConnection,
+            chain: &str,
+            hashes: &[BlockHash],
+        ) -> Result<usize, StoreError> {
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    let hashes: Vec<String> =
+                        hashes.iter().map(|hash| format!("{hash:x}")).collect();
+
+                    diesel::delete(b::table)
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::hash.eq_any(hashes))
+                        .filter(b::number.gt(0))
+                        .execute(conn)
+                        .map_err(StoreError::from)
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    let query = format!(
+                        "delete from {} where hash = any($1) and number > 0",
+                        blocks.qname
+                    );
+
+                    let hashes: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_slice()).collect();
+
+                    sql_query(query)
+                        .bind::<Array<Bytea>, _>(hashes)
+                        .execute(conn)
+                        .map_err(StoreError::from)
+                }
+            }
+        }
+
         pub(super) fn delete_blocks_by_hash(
             &self,
             conn: &mut PgConnection,
@@ -1166,7 +1379,7 @@ mod data {
             &self,
             conn: &mut PgConnection,
             id: &[u8],
-        ) -> Result<Option<(Bytes, bool)>, Error> {
+        ) -> Result<Option<(call::Retval, bool)>, Error> {
             match self {
                 Storage::Shared => {
                     use public::eth_call_cache as cache;
@@ -1177,6 +1390,7 @@ mod data {
                         .inner_join(meta::table)
                         .select((
                             cache::return_value,
+                            sql::<Nullable<Text>>("null"),
                             sql::<Bool>("CURRENT_DATE > eth_call_meta.accessed_at"),
                         ))
                         .get_result(conn)
@@ -1197,24 +1411,29 @@ mod data {
                     .filter(call_cache.id().eq(id))
                     .select((
                         call_cache.return_value(),
+                        call_cache.revert_reason(),
                         sql::<Bool>(&format!(
                             "CURRENT_DATE > {}.{}",
                             CallMetaTable::TABLE_NAME,
                             CallMetaTable::ACCESSED_AT
                         )),
                     ))
-                    .first::<(Vec<u8>, bool)>(conn)
+                    .first::<(Vec<u8>, Option<String>, bool)>(conn)
                     .optional()
                     .map_err(Error::from),
             }
-            .map(|row| row.map(|(return_value, expired)| (Bytes::from(return_value), expired)))
+            .map(|row| {
+                row.map(|(return_value, revert_reason, expired)| {
+                    (retval(return_value, revert_reason), expired)
+                })
+            })
         }
 
         pub(super) fn get_calls_and_access(
             &self,
             conn: &mut PgConnection,
             ids: &[&[u8]],
-        ) -> Result<Vec<(Vec<u8>, Bytes, bool)>, Error> {
+        ) -> Result<Vec<(Vec<u8>, call::Retval, bool)>, Error> {
             let rows = match self {
                 Storage::Shared => {
                     use public::eth_call_cache as cache;
@@ -1226,6 +1445,7 @@ mod data {
                         .select((
                             cache::id,
                             cache::return_value,
+                            sql::<Nullable<Text>>("null"),
                             sql::<Bool>("CURRENT_DATE > eth_call_meta.accessed_at"),
                         ))
                         .load(conn)
@@ -1246,21 +1466,167 @@ mod data {
                     .select((
                         call_cache.id(),
                         call_cache.return_value(),
+                        call_cache.revert_reason(),
                         sql::<Bool>(&format!(
                             "CURRENT_DATE > {}.{}",
                             CallMetaTable::TABLE_NAME,
                             CallMetaTable::ACCESSED_AT
                         )),
                     ))
-                    .load::<(Vec<u8>, Vec<u8>, bool)>(conn)
+                    .load::<(Vec<u8>, Vec<u8>, Option<String>, bool)>(conn)
                     .map_err(Error::from),
             }?;
             Ok(rows
                 .into_iter()
-                .map(|(id, return_value, expired)| (id, Bytes::from(return_value), expired))
+                .map(|(id, return_value, revert_reason, expired)| {
+                    (id, retval(return_value, revert_reason), expired)
+                })
                 .collect())
         }
 
+        /// Mark the calls with the given ids as accessed just now. The
+        /// shared call cache does not track access times for individual
+        /// calls
+        pub(super) fn touch_calls(
+            &self,
+            conn: &mut PgConnection,
+            ids: &[&[u8]],
+        ) -> Result<(), Error> {
+            match self {
+                Storage::Shared => Ok(()),
+                Storage::Private(Schema { call_cache, .. }) => {
+                    let query = format!(
+                        "update {} set accessed_at = now() where id = any($1)",
+                        call_cache.qname
+                    );
+                    sql_query(query)
+                        .bind::<Array<Bytea>, _>(ids)
+                        .execute(conn)
+                        .map_err(Error::from)
+                        .map(|_| ())
+                }
+            }
+        }
+
+        /// Estimate how many bytes of data and how many rows the call cache
+        /// has, based on the statistics Postgres keeps for the table. Those
+        /// statistics are cheap to get, but only as accurate as the last
+        /// time the table was analyzed. Return `None` for the shared call
+        /// cache
+        pub(super) fn call_cache_size(
+            &self,
+            conn: &mut PgConnection,
+        ) -> Result<Option<(i64, i64)>, Error> {
+            #[derive(QueryableByName)]
+            struct Size {
+                #[diesel(sql_type = BigInt)]
+                bytes: i64,
+                #[diesel(sql_type = BigInt)]
+                rows: i64,
+            }
+
+            match self {
+                Storage::Shared => Ok(None),
+                Storage::Private(Schema { name, .. }) => {
+                    let query = "
+                    select s.n_live_tup * coalesce(
+                             (select sum(avg_width)
+                                from pg_stats
+                               where schemaname = $1
+                                 and tablename = $2), 0)::int8 as bytes,
+                           s.n_live_tup as rows
+                      from pg_stat_user_tables s
+                     where s.schemaname = $1
+                       and s.relname = $2";
+                    let size = sql_query(query)
+                        .bind::<Text, _>(name)
+                        .bind::<Text, _>(CallCacheTable::TABLE_NAME)
+                        .get_result::<Size>(conn)
+                        .optional()?
+                        .map(|size| (size.bytes, size.rows))
+                        .unwrap_or((0, 0));
+                    Ok(Some(size))
+                }
+            }
+        }
+
+        /// Make sure that the call cache has the index that eviction uses
+        /// to find the least recently accessed calls. Call caches that
+        /// existed before calls had an access time do not have it yet; it
+        /// is not created in a migration since building it for a large
+        /// call cache takes a long time. The index is built concurrently,
+        /// and this function must therefore not be called inside a
+        /// transaction
+        pub(super) fn ensure_accessed_at_index(
+            &self,
+            conn: &mut PgConnection,
+        ) -> Result<(), Error> {
+            match self {
+                Storage::Shared => Ok(()),
+                Storage::Private(Schema {
+                    name, call_cache, ..
+                }) => {
+                    let index = CallCacheTable::ACCESSED_AT_INDEX;
+                    if catalog::check_index_is_valid(conn, name, index)? {
+                        return Ok(());
+                    }
+                    // An index that is there but not valid was left behind
+                    // by an earlier attempt that got interrupted
+                    sql_query(format!("drop index concurrently if exists {name}.{index}"))
+                        .execute(conn)?;
+                    sql_query(format!(
+                        "create index concurrently if not exists {index} \
+                             on {}(accessed_at nulls first)",
+                        call_cache.qname
+                    ))
+                    .execute(conn)?;
+                    Ok(())
+                }
+            }
+        }
+
+        /// Delete the `limit` calls that were accessed least recently from
+        /// the call cache. Calls that have not been accessed since access
+        /// times were introduced have no access time and are deleted
+        /// first. Return how many calls were deleted and how many bytes of
+        /// data they had
+        pub(super) fn evict_calls(
+            &self,
+            conn: &mut PgConnection,
+            limit: i64,
+        ) -> Result<(i64, i64), Error> {
+            #[derive(QueryableByName)]
+            struct Evicted {
+                #[diesel(sql_type = BigInt)]
+                rows: i64,
+                #[diesel(sql_type = BigInt)]
+                bytes: i64,
+            }
+
+            match self {
+                Storage::Shared => Ok((0, 0)),
+                Storage::Private(Schema { call_cache, .. }) => {
+                    let query = format!(
+                        "
+                    with evicted as (
+                      delete from {qname}
+                       where id in (select id from {qname}
+                                     order by accessed_at nulls first
+                                     limit $1)
+                      returning octet_length(id) + octet_length(return_value)
+                              + octet_length(contract_address) + 12 as bytes)
+                    select count(*) as rows, coalesce(sum(bytes), 0)::int8 as bytes
+                      from evicted",
+                        qname = call_cache.qname
+                    );
+                    let evicted = sql_query(query)
+                        .bind::<BigInt, _>(limit)
+                        .get_result::<Evicted>(conn)?;
+                    Ok((evicted.rows, evicted.bytes))
+                }
+            }
+        }
+
         pub(super) fn get_calls_in_block(
             &self,
             conn: &mut PgConnection,
@@ -1286,6 +1652,7 @@ mod data {
                         call_cache.contract_address(),
                     ))
                     .filter(call_cache.block_number().eq(block_num as i64))
+                    .filter(call_cache.revert_reason().is_null())
                     .order(call_cache.contract_address())
                     .get_results::<(Vec<u8>, Vec<u8>, Vec<u8>)>(conn)?,
             };
@@ -1376,9 +1743,18 @@ mod data {
             id: &[u8],
             contract_address: &[u8],
             block_number: i32,
-            return_value: &[u8],
+            return_value: &call::Retval,
         ) -> Result<(), Error> {
+            let (return_value, revert_reason) = match return_value {
+                call::Retval::Value(value) => (value.as_slice(), None),
+                call::Retval::Reverted(reason) => (&[][..], Some(reason.as_str())),
+                call::Retval::Null => return Ok(()),
+            };
             let result = match self {
+                Storage::Shared if revert_reason.is_some() => {
+                    // The shared call cache has no place to record reverts
+                    return Ok(());
+                }
                 Storage::Shared => {
                     use public::eth_call_cache as cache;
                     use public::eth_call_meta as meta;
@@ -1425,8 +1801,8 @@ mod data {
                     ..
                 }) => {
                     let query = format!(
-                        "insert into {}(id, contract_address, block_number, return_value) \
-                         values ($1, $2, $3, $4) on conflict do nothing",
+                        "insert into {}(id, contract_address, block_number, return_value, revert_reason) \
+                         values ($1, $2, $3, $4, $5) on conflict do nothing",
                         call_cache.qname
                     );
                     sql_query(query)
@@ -1434,6 +1810,7 @@ mod data {
                         .bind::<Bytea, _>(contract_address)
                         .bind::<Integer, _>(block_number)
                         .bind::<Bytea, _>(return_value)
+                        .bind::<Nullable<Text>, _>(revert_reason)
                         .execute(conn)?;
 
                     // Check whether we need to update `call_meta`. The
@@ -1470,6 +1847,47 @@ mod data {
             result.map(|_| ()).map_err(Error::from)
         }
 
+        /// Put the call cache into the state it is in right after the
+        /// migration that introduced access times: the calls with the given
+        /// `ids` have no access time, and there is no index on access times
+        #[cfg(debug_assertions)]
+        pub(super) fn unset_accessed_at(
+            &self,
+            conn: &mut PgConnection,
+            ids: &[&[u8]],
+        ) -> Result<(), Error> {
+            match self {
+                Storage::Shared => Ok(()),
+                Storage::Private(Schema {
+                    name, call_cache, ..
+                }) => {
+                    let index = CallCacheTable::ACCESSED_AT_INDEX;
+                    sql_query(format!("drop index if exists {name}.{index}")).execute(conn)?;
+                    sql_query(format!(
+                        "update {} set accessed_at = null where id = any($1)",
+                        call_cache.qname
+                    ))
+                    .bind::<Array<Bytea>, _>(ids)
+                    .execute(conn)?;
+                    Ok(())
+                }
+            }
+        }
+
+        /// Return `true` if the call cache has a valid index on access
+        /// times. The shared call cache never has one
+        #[cfg(debug_assertions)]
+        pub(super) fn has_accessed_at_index(&self, conn: &mut PgConnection) -> Result<bool, Error> {
+            match self {
+                Storage::Shared => Ok(false),
+                Storage::Private(Schema { name, .. }) => Ok(catalog::check_index_is_valid(
+                    conn,
+                    name,
+                    CallCacheTable::ACCESSED_AT_INDEX,
+                )?),
+            }
+        }
+
         #[cfg(debug_assertions)]
         // used by `super::set_chain` for test support
         pub(super) fn remove_chain(&self, conn: &mut PgConnection, chain_name: &str) {
@@ -1562,6 +1980,10 @@ pub struct ChainStoreMetrics {
     chain_head_cache_latest_block_num: Box<GaugeVec>,
     chain_head_cache_hits: Box<CounterVec>,
     chain_head_cache_misses: Box<CounterVec>,
+    call_cache_hits: Box<CounterVec>,
+    call_cache_misses: Box<CounterVec>,
+    call_cache_size: Box<GaugeVec>,
+    call_cache_evicted_bytes: Box<CounterVec>,
 }
 
 impl ChainStoreMetrics {
@@ -1603,12 +2025,45 @@ impl ChainStoreMetrics {
             )
             .expect("Can't register the counter");
 
+        let call_cache_hits = registry
+            .new_counter_vec(
+                "call_cache_hits",
+                "Number of eth_calls that were found in the call cache",
+                vec!["network".to_string()],
+            )
+            .expect("Can't register the counter");
+        let call_cache_misses = registry
+            .new_counter_vec(
+                "call_cache_misses",
+                "Number of eth_calls that were not found in the call cache",
+                vec!["network".to_string()],
+            )
+            .expect("Can't register the counter");
+        let call_cache_size = registry
+            .new_gauge_vec(
+                "call_cache_size_bytes",
+                "Estimated size of the data in the call cache",
+                vec!["network".to_string()],
+            )
+            .expect("Can't register the gauge");
+        let call_cache_evicted_bytes = registry
+            .new_counter_vec(
+                "call_cache_evicted_bytes",
+                "Number of bytes of data evicted from the call cache",
+                vec!["network".to_string()],
+            )
+            .expect("Can't register the counter");
+
         Self {
             chain_head_cache_size,
             chain_head_cache_oldest_block_num,
             chain_head_cache_latest_block_num,
             chain_head_cache_hits,
             chain_head_cache_misses,
+            call_cache_hits,
+            call_cache_misses,
+            call_cache_size,
+            call_cache_evicted_bytes,
         }
     }
 
@@ -1648,6 +2103,24 @@ impl ChainStoreMetrics {
             .unwrap()
             .inc_by(misses as f64);
     }
+
+    fn record_call_cache_hit_and_miss(&self, network: &str, hits: usize, misses: usize) {
+        self.call_cache_hits
+            .with_label_values(&[network])
+            .inc_by(hits as f64);
+        self.call_cache_misses
+            .with_label_values(&[network])
+            .inc_by(misses as f64);
+    }
+
+    fn record_call_cache_eviction(&self, network: &str, size: i64, evicted: i64) {
+        self.call_cache_size
+            .with_label_values(&[network])
+            .set((size - evicted).max(0) as f64);
+        self.call_cache_evicted_bytes
+            .with_label_values(&[network])
+            .inc_by(evicted as f64);
+    }
 }
 
 #[derive(Clone, CheapClone)]
@@ -1668,6 +2141,13 @@ pub struct ChainStore {
     // conservative approach is acceptable.
     recent_blocks_cache: RecentBlocksCache,
     lookup_herd: HerdCache<BlocksLookupResult>,
+    /// Where blocks that are too old to be kept in the database are
+    /// stored; `None` if ancient blocks are not archived
+    archive: Option<Arc<ObjectStorageBlockStore>>,
+    metrics: Arc<ChainStoreMetrics>,
+    /// Whether we made sure that the call cache has the index on the
+    /// access time of calls
+    accessed_at_indexed: AtomicBool,
 }
 
 impl ChainStore {
@@ -1680,9 +2160,10 @@ impl ChainStore {
         pool: ConnectionPool,
         recent_blocks_cache_capacity: usize,
         metrics: Arc<ChainStoreMetrics>,
+        archive: Option<Arc<ObjectStorageBlockStore>>,
     ) -> Self {
         let recent_blocks_cache =
-            RecentBlocksCache::new(recent_blocks_cache_capacity, chain.clone(), metrics);
+            RecentBlocksCache::new(recent_blocks_cache_capacity, chain.clone(), metrics.clone());
         let lookup_herd = HerdCache::new(format!("chain_{}_herd_cache", chain));
         ChainStore {
             logger,
@@ -1693,6 +2174,9 @@ impl ChainStore {
             chain_head_update_sender,
             recent_blocks_cache,
             lookup_herd,
+            archive,
+            metrics,
+            accessed_at_indexed: AtomicBool::new(false),
         }
     }
 
@@ -1729,11 +2213,15 @@ impl ChainStore {
 
     pub fn update_name(&self, name: &str) -> Result<(), Error> {
         use public::ethereum_networks as n;
+        use public::polling_stream_checkpoints as c;
         let mut conn = self.get_conn()?;
         conn.transaction(|conn| {
             update(n::table.filter(n::name.eq(&self.chain)))
                 .set(n::name.eq(name))
                 .execute(conn)?;
+            update(c::table.filter(c::network.eq(&self.chain)))
+                .set(c::network.eq(name))
+                .execute(conn)?;
             Ok(())
         })
     }
@@ -1741,11 +2229,13 @@ impl ChainStore {
     pub(crate) fn drop_chain(&self) -> Result<(), Error> {
         use diesel::dsl::delete;
         use public::ethereum_networks as n;
+        use public::polling_stream_checkpoints as c;
 
         let mut conn = self.get_conn()?;
         conn.transaction(|conn| {
             self.storage.drop_storage(conn, &self.chain)?;
 
+            delete(c::table.filter(c::network.eq(&self.chain))).execute(conn)?;
             delete(n::table.filter(n::name.eq(&self.chain))).execute(conn)?;
             Ok(())
         })
@@ -1823,6 +2313,8 @@ impl ChainStore {
                 n::genesis_block_hash.eq(genesis_hash),
                 n::head_block_hash.eq::<Option<&str>>(None),
                 n::head_block_number.eq::<Option<i64>>(None),
+                n::finalized_block_hash.eq::<Option<&str>>(None),
+                n::finalized_block_number.eq::<Option<i64>>(None),
             ))
             .execute(&mut conn)
             .unwrap();
@@ -1860,16 +2352,269 @@ impl ChainStore {
     ) -> Result<Vec<JsonBlock>, StoreError> {
         let store = self.cheap_clone();
         let pool = self.pool.clone();
-        let values = pool
+        let lookup = hashes.clone();
+        let mut values = pool
             .with_conn(move |conn, _| {
                 store
                     .storage
-                    .blocks(conn, &store.chain, &hashes)
+                    .blocks(conn, &store.chain, &lookup)
                     .map_err(CancelableError::from)
             })
             .await?;
+
+        // Blocks that are not in the database might have been moved to
+        // the archive
+        if let Some(archive) = &self.archive {
+            if values.len() < hashes.len() {
+                let missing = hashes
+                    .into_iter()
+                    .filter(|hash| !values.iter().any(|block| &block.ptr.hash == hash))
+                    .collect::<Vec<_>>();
+                let archived = archive.blocks(&missing).await?;
+                values.extend(archived.into_iter().map(|mut block| {
+                    // Same as `coalesce(data -> 'block', data)` in
+                    // `Storage::blocks`
+                    block.data = block
+                        .data
+                        .map(|data| data.get("block").cloned().unwrap_or(data));
+                    block
+                }));
+            }
+        }
         Ok(values)
     }
+
+    /// Remove all blocks with a number below `before`, except for the
+    /// genesis block, and all call cache entries for those blocks. Return
+    /// how many blocks and call cache entries were removed. With `dry_run`,
+    /// only count what would be removed. Only chains with private storage
+    /// can be pruned since the shared call cache is used by all chains
+    pub fn prune(&self, before: BlockNumber, dry_run: bool) -> Result<(usize, usize), StoreError> {
+        // Deleting in batches keeps each statement short so that it does
+        // not hold up block ingestion and eth_calls for a long time
+        const BATCH_SIZE: usize = 10_000;
+
+        if let Storage::Shared = self.storage {
+            return Err(StoreError::Unknown(anyhow::anyhow!(
+                "chain {} uses shared storage; only chains with private storage can be pruned",
+                self.chain
+            )));
+        }
+        let mut conn = self.get_conn()?;
+        if dry_run {
+            return Ok(self.storage.count_before(&mut conn, &self.chain, before)?);
+        }
+        let (mut blocks, mut calls) = (0, 0);
+        loop {
+            let (deleted_blocks, deleted_calls) = self.storage.delete_batch_before(
+                &mut conn,
+                &self.chain,
+                before,
+                BATCH_SIZE as i64,
+            )?;
+            blocks += deleted_blocks;
+            calls += deleted_calls;
+            if deleted_blocks < BATCH_SIZE && deleted_calls < BATCH_SIZE {
+                break;
+            }
+        }
+        self.recent_blocks_cache.clear();
+        Ok((blocks, calls))
+    }
+
+    /// Evict the least recently accessed calls from the call cache until
+    /// the estimated size of its data is at most `max_bytes`. Return how
+    /// many bytes were evicted
+    pub fn evict_call_cache(&self, max_bytes: u64) -> Result<i64, StoreError> {
+        const BATCH_SIZE: i64 = 10_000;
+
+        let mut conn = self.get_conn()?;
+        if !self.accessed_at_indexed.load(Ordering::SeqCst) {
+            self.storage.ensure_accessed_at_index(&mut conn)?;
+            self.accessed_at_indexed.store(true, Ordering::SeqCst);
+        }
+        let (bytes, rows) = match self.storage.call_cache_size(&mut conn)? {
+            Some((bytes, rows)) if rows > 0 => (bytes, rows),
+            _ => return Ok(0),
+        };
+        let excess = bytes - max_bytes as i64;
+        if excess <= 0 {
+            self.metrics
+                .record_call_cache_eviction(&self.chain, bytes, 0);
+            return Ok(0);
+        }
+
+        // Evict roughly as many rows as it takes to get rid of the excess,
+        // based on the average size of a row
+        let avg_row_bytes = (bytes / rows).max(1);
+        let mut to_evict = excess / avg_row_bytes + 1;
+        let mut evicted_bytes = 0;
+        while to_evict > 0 {
+            let (evicted, bytes) = self
+                .storage
+                .evict_calls(&mut conn, to_evict.min(BATCH_SIZE))?;
+            if evicted == 0 {
+                break;
+            }
+            to_evict -= evicted;
+            evicted_bytes += bytes;
+        }
+        self.metrics
+            .record_call_cache_eviction(&self.chain, bytes, evicted_bytes);
+        Ok(evicted_bytes)
+    }
+
+    /// Evict the `limit` calls that were accessed least recently from the
+    /// call cache, regardless of its size. Return how many calls were
+    /// evicted and how many bytes of data they had
+    #[cfg(debug_assertions)]
+    pub fn evict_calls(&self, limit: i64) -> Result<(i64, i64), Error> {
+        let mut conn = self.get_conn()?;
+        self.storage.evict_calls(&mut conn, limit)
+    }
+
+    /// Mark the calls `reqs` at `block` as accessed just now without
+    /// sampling
+    #[cfg(debug_assertions)]
+    pub fn touch_calls_unsampled(
+        &self,
+        reqs: &[call::Request],
+        block: &BlockPtr,
+    ) -> Result<(), Error> {
+        let ids: Vec<_> = reqs
+            .iter()
+            .map(|req| contract_call_id(req, block))
+            .collect();
+        let ids: Vec<_> = ids.iter().map(|id| id.as_slice()).collect();
+        let mut conn = self.get_conn()?;
+        self.storage.touch_calls(&mut conn, &ids)
+    }
+
+    /// Make it look like the calls `reqs` at `block` were cached before
+    /// calls had an access time, and like the call cache was just
+    /// migrated
+    #[cfg(debug_assertions)]
+    pub fn unset_accessed_at(&self, reqs: &[call::Request], block: &BlockPtr) -> Result<(), Error> {
+        let ids: Vec<_> = reqs
+            .iter()
+            .map(|req| contract_call_id(req, block))
+            .collect();
+        let ids: Vec<_> = ids.iter().map(|id| id.as_slice()).collect();
+        let mut conn = self.get_conn()?;
+        self.storage.unset_accessed_at(&mut conn, &ids)?;
+        self.accessed_at_indexed.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    pub fn has_accessed_at_index(&self) -> Result<bool, Error> {
+        let mut conn = self.get_conn()?;
+        self.storage.has_accessed_at_index(&mut conn)
+    }
+
+    /// Record that the calls with the given ids were read from the call
+    /// cache. To keep this cheap, only a sample of the accesses is written
+    /// to the database, and only when the call cache is size-bounded
+    fn touch_calls(&self, conn: &mut PgConnection, ids: &[&[u8]]) -> Result<(), Error> {
+        if ENV_VARS.store.call_cache_max_bytes.is_none() {
+            return Ok(());
+        }
+        let sample = ENV_VARS.store.call_cache_access_sample;
+        let mut rng = thread_rng();
+        let ids: Vec<_> = ids
+            .iter()
+            .filter(|_| rng.gen_bool(sample))
+            .cloned()
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.storage.touch_calls(conn, &ids)
+    }
+
+    /// Move all blocks that are more than `depth` blocks behind the chain
+    /// head into the archive and delete them from the database. Return
+    /// the number of blocks that were archived. Does nothing if no archive
+    /// is configured
+    pub async fn archive_blocks(&self, depth: BlockNumber) -> Result<usize, StoreError> {
+        const BATCH_SIZE: i64 = 1_000;
+
+        let archive = match &self.archive {
+            Some(archive) => archive,
+            None => return Ok(0),
+        };
+        let head = match self.chain_head_block(&self.chain)? {
+            Some(head) => head,
+            None => return Ok(0),
+        };
+        // Never archive blocks that could still be affected by a reorg
+        let before = head - depth.max(ENV_VARS.reorg_threshold);
+        if before <= 0 {
+            return Ok(0);
+        }
+
+        let mut archived = 0;
+        loop {
+            let blocks = {
+                let mut conn = self.get_conn()?;
+                self.storage
+                    .blocks_before(&mut conn, &self.chain, before, BATCH_SIZE)?
+            };
+            if blocks.is_empty() {
+                break;
+            }
+            // Only delete blocks from the database once they are safely
+            // stored in the archive
+            try_join_all(blocks.iter().map(|block| archive.put(block))).await?;
+            let hashes = blocks
+                .into_iter()
+                .map(|block| block.ptr.hash)
+                .collect::<Vec<_>>();
+            let mut conn = self.get_conn()?;
+            archived += self
+                .storage
+                .delete_blocks(&mut conn, &self.chain, &hashes)?;
+            if (hashes.len() as i64) < BATCH_SIZE {
+                break;
+            }
+        }
+        Ok(archived)
+    }
+
+    /// Continue the search for an ancestor in the archive. `ptr` is the
+    /// oldest block we found in the database on the way back from the
+    /// starting block, and `offset` is how many more blocks we need to go
+    /// back from it. Return `None` if the archive can not help
+    async fn archived_ancestor(
+        self: &Arc<Self>,
+        archive: &ObjectStorageBlockStore,
+        ptr: BlockPtr,
+        offset: BlockNumber,
+        root: Option<BlockHash>,
+    ) -> Result<Option<json::Value>, Error> {
+        let store = self.cheap_clone();
+        let hash = ptr.hash.clone();
+        let parent = self
+            .pool
+            .with_conn(move |conn, _| {
+                store
+                    .storage
+                    .block_number(conn, &hash)
+                    .map_err(CancelableError::from)
+            })
+            .await?
+            .and_then(|(_, _, parent)| parent);
+        // We already have the starting block from the database; the walk
+        // through the archive only needs to read its ancestors
+        let block = match parent {
+            Some(parent) => {
+                let start = JsonBlock::new(ptr, parent, None);
+                archive.ancestor_of(start, offset, root).await?
+            }
+            None => None,
+        };
+        Ok(block.and_then(|block| block.data).map(with_block_field))
+    }
 }
 
 #[async_trait]
@@ -2065,6 +2810,131 @@ impl ChainStoreTrait for ChainStore {
         Ok(())
     }
 
+    async fn finalized_block_ptr(self: Arc<Self>) -> Result<Option<BlockPtr>, Error> {
+        use public::ethereum_networks as n;
+
+        let chain = self.chain.clone();
+        let ptr = self
+            .pool
+            .with_conn(move |conn, _| {
+                n::table
+                    .select((n::finalized_block_hash, n::finalized_block_number))
+                    .filter(n::name.eq(&chain))
+                    .first::<(Option<String>, Option<i64>)>(conn)
+                    .optional()
+                    .map_err(|e| CancelableError::from(StoreError::from(e)))
+            })
+            .await?;
+        match ptr {
+            Some((Some(hash), Some(number))) => {
+                let hash: BlockHash = hash.parse()?;
+                Ok(Some(BlockPtr::new(hash, number as BlockNumber)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn set_finalized_block(self: Arc<Self>, ptr: BlockPtr) -> Result<(), Error> {
+        use public::ethereum_networks as n;
+
+        let hash = ptr.hash_hex();
+        let number = ptr.number as i64;
+        let storage = self.storage.clone();
+        let chain = self.chain.clone();
+        self.pool
+            .with_conn(move |conn, _| {
+                conn.transaction(|conn| -> Result<(), StoreError> {
+                    let updated = update(
+                        n::table.filter(n::name.eq(&chain)).filter(
+                            n::finalized_block_number
+                                .is_null()
+                                .or(n::finalized_block_number.lt(number)),
+                        ),
+                    )
+                    .set((
+                        n::finalized_block_hash.eq(&hash),
+                        n::finalized_block_number.eq(number),
+                    ))
+                    .execute(conn)?;
+
+                    // The finalized block can never be reverted; any other
+                    // blocks with the same number are on abandoned forks
+                    if updated > 0 {
+                        storage.confirm_block_hash(conn, &chain, ptr.number, &ptr.hash)?;
+                    }
+                    Ok(())
+                })
+                .map_err(CancelableError::from)
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn stream_checkpoint(
+        self: Arc<Self>,
+        deployment: &DeploymentHash,
+    ) -> Result<Option<StreamCheckpoint>, Error> {
+        use public::polling_stream_checkpoints as c;
+
+        let chain = self.chain.clone();
+        let deployment = deployment.to_string();
+        let checkpoint = self
+            .pool
+            .with_conn(move |conn, _| {
+                c::table
+                    .select((c::filter, c::from_block, c::to_block, c::blocks))
+                    .filter(c::network.eq(&chain))
+                    .filter(c::deployment.eq(&deployment))
+                    .first::<(String, i32, i32, Vec<i32>)>(conn)
+                    .optional()
+                    .map_err(|e| CancelableError::from(StoreError::from(e)))
+            })
+            .await?;
+        Ok(
+            checkpoint.map(|(filter, from, to, blocks)| StreamCheckpoint {
+                filter,
+                from,
+                to,
+                blocks,
+            }),
+        )
+    }
+
+    async fn set_stream_checkpoint(
+        self: Arc<Self>,
+        deployment: &DeploymentHash,
+        checkpoint: StreamCheckpoint,
+    ) -> Result<(), Error> {
+        use public::polling_stream_checkpoints as c;
+
+        let chain = self.chain.clone();
+        let deployment = deployment.to_string();
+        self.pool
+            .with_conn(move |conn, _| {
+                insert_into(c::table)
+                    .values((
+                        c::network.eq(&chain),
+                        c::deployment.eq(&deployment),
+                        c::filter.eq(&checkpoint.filter),
+                        c::from_block.eq(checkpoint.from),
+                        c::to_block.eq(checkpoint.to),
+                        c::blocks.eq(&checkpoint.blocks),
+                    ))
+                    .on_conflict((c::network, c::deployment))
+                    .do_update()
+                    .set((
+                        c::filter.eq(&checkpoint.filter),
+                        c::from_block.eq(checkpoint.from),
+                        c::to_block.eq(checkpoint.to),
+                        c::blocks.eq(&checkpoint.blocks),
+                    ))
+                    .execute(conn)
+                    .map_err(|e| CancelableError::from(StoreError::from(e)))
+            })
+            .await?;
+        Ok(())
+    }
+
     async fn blocks(self: Arc<Self>, hashes: Vec<BlockHash>) -> Result<Vec<json::Value>, Error> {
         if ENV_VARS.store.disable_block_cache_for_lookup {
             let values = self
@@ -2148,17 +3018,41 @@ impl ChainStoreTrait for ChainStore {
 
         let block_ptr_clone = block_ptr.clone();
         let chain_store = self.cheap_clone();
-        Ok(self
+        let root_clone = root.clone();
+        let block = self
             .pool
             .with_conn(move |conn, _| {
                 chain_store
                     .storage
-                    .ancestor_block(conn, block_ptr_clone, offset, root)
+                    .ancestor_block(conn, block_ptr_clone, offset, root_clone)
                     .map_err(StoreError::from)
                     .map_err(CancelableError::from)
             })
-            .await?
-            .map(|b| b.0))
+            .await?;
+
+        let archive = match &self.archive {
+            Some(archive) => archive,
+            None => return Ok(block.map(|b| b.0)),
+        };
+        // The search in the database stops at the oldest block it has; if
+        // that is not old enough, the rest of the blocks might be in the
+        // archive
+        match block {
+            Some((data, ptr)) => {
+                let missing = ptr.number - (block_ptr.number - offset);
+                if missing > 0 {
+                    if let Some(data) = self.archived_ancestor(archive, ptr, missing, root).await? {
+                        return Ok(Some(data));
+                    }
+                }
+                Ok(Some(data))
+            }
+            None => Ok(archive
+                .ancestor_block(&block_ptr.hash, offset, root)
+                .await?
+                .and_then(|block| block.data)
+                .map(with_block_field)),
+        }
     }
 
     fn cleanup_cached_blocks(
@@ -2231,8 +3125,18 @@ impl ChainStoreTrait for ChainStore {
 
     fn block_hashes_by_block_number(&self, number: BlockNumber) -> Result<Vec<BlockHash>, Error> {
         let mut conn = self.get_conn()?;
-        self.storage
-            .block_hashes_by_block_number(&mut conn, &self.chain, number)
+        let hashes = self
+            .storage
+            .block_hashes_by_block_number(&mut conn, &self.chain, number)?;
+        match &self.archive {
+            // This method is synchronous but gets called from async code;
+            // `block_in_place` keeps us from stalling the other tasks on
+            // this worker while we wait for the object store
+            Some(archive) if hashes.is_empty() => Ok(graph::tokio::task::block_in_place(|| {
+                graph::block_on(archive.block_hashes_by_number(number))
+            })?),
+            _ => Ok(hashes),
+        }
     }
 
     fn confirm_block_hash(&self, number: BlockNumber, hash: &BlockHash) -> Result<usize, Error> {
@@ -2245,13 +3149,14 @@ impl ChainStoreTrait for ChainStore {
         &self,
         hash: &BlockHash,
     ) -> Result<Option<(String, BlockNumber, Option<u64>, Option<BlockHash>)>, StoreError> {
-        let hash = hash.clone();
+        let lookup = hash.clone();
         let storage = self.storage.clone();
         let chain = self.chain.clone();
-        self.pool
+        let number = self
+            .pool
             .with_conn(move |conn, _| {
                 storage
-                    .block_number(conn, &hash)
+                    .block_number(conn, &lookup)
                     .map(|opt| {
                         opt.map(|(number, timestamp, parent_hash)| {
                             (chain.clone(), number, timestamp, parent_hash)
@@ -2259,7 +3164,33 @@ impl ChainStoreTrait for ChainStore {
                     })
                     .map_err(|e| e.into())
             })
-            .await
+            .await?;
+
+        match (number, &self.archive) {
+            (None, Some(archive)) => {
+                let block = match archive.get(hash).await? {
+                    Some(block) => block,
+                    None => return Ok(None),
+                };
+                // Same as the timestamp query in `Storage::block_number`
+                let ts = block.data.as_ref().and_then(|data| {
+                    data.get("block")
+                        .unwrap_or(data)
+                        .get("timestamp")
+                        .map(|ts| match ts {
+                            json::Value::String(ts) => ts.clone(),
+                            ts => ts.to_string(),
+                        })
+                });
+                Ok(Some((
+                    self.chain.clone(),
+                    block.ptr.number,
+                    try_parse_timestamp(ts)?,
+                    Some(block.parent_hash),
+                )))
+            }
+            (number, _) => Ok(number),
+        }
     }
 
     async fn block_numbers(
@@ -2271,13 +3202,26 @@ impl ChainStoreTrait for ChainStore {
         }
 
         let storage = self.storage.clone();
-        self.pool
+        let lookup = hashes.clone();
+        let mut numbers = self
+            .pool
             .with_conn(move |conn, _| {
                 storage
-                    .block_numbers(conn, hashes.as_slice())
+                    .block_numbers(conn, lookup.as_slice())
                     .map_err(|e| e.into())
             })
-            .await
+            .await?;
+
+        if let Some(archive) = &self.archive {
+            let missing = hashes
+                .into_iter()
+                .filter(|hash| !numbers.contains_key(hash))
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                numbers.extend(archive.block_numbers(&missing).await?);
+            }
+        }
+        Ok(numbers)
     }
 
     async fn clear_call_cache(&self, from: BlockNumber, to: BlockNumber) -> Result<(), Error> {
@@ -2499,6 +3443,18 @@ mod recent_blocks_cache {
     }
 }
 
+/// We need to deal with chain stores where some entries have a toplevel
+/// 'block' field and others directly contain what would be in the 'block'
+/// field. Make sure the value we return has a 'block' entry
+///
+/// see also 7736e440-4c6b-11ec-8c4d-b42e99f52061
+fn with_block_field(data: json::Value) -> json::Value {
+    match data.get("block") {
+        Some(_) => data,
+        None => json::json!({ "block": data, "transaction_receipts": [] }),
+    }
+}
+
 fn try_parse_timestamp(ts: Option<String>) -> Result<Option<u64>, StoreError> {
     let ts = match ts {
         Some(str) => str,
@@ -2530,21 +3486,27 @@ impl EthereumCallCache for ChainStore {
         let id = contract_call_id(req, &block);
         let conn = &mut *self.get_conn()?;
         let return_value = conn.transaction::<_, Error, _>(|conn| {
-            if let Some((return_value, update_accessed_at)) =
-                self.storage.get_call_and_access(conn, id.as_ref())?
+            if let Some((return_value, update_accessed_at)) = self
+                .storage
+                .get_call_and_access(conn, id.as_ref())?
+                .filter(|(retval, _)| cache_retval(retval))
             {
                 if update_accessed_at {
                     self.storage
                         .update_accessed_at(conn, req.address.as_ref())?;
                 }
+                self.touch_calls(conn, &[id.as_ref()])?;
                 Ok(Some(return_value))
             } else {
                 Ok(None)
             }
         })?;
+        let hits = return_value.is_some() as usize;
+        self.metrics
+            .record_call_cache_hit_and_miss(&self.chain, hits, 1 - hits);
         Ok(return_value.map(|return_value| {
             req.cheap_clone()
-                .response(call::Retval::Value(return_value), call::Source::Store)
+                .response(return_value, call::Source::Store)
         }))
     }
 
@@ -2564,8 +3526,18 @@ impl EthereumCallCache for ChainStore {
         let id_refs: Vec<_> = ids.iter().map(|id| id.as_slice()).collect();
 
         let conn = &mut *self.get_conn()?;
-        let rows = conn
-            .transaction::<_, Error, _>(|conn| self.storage.get_calls_and_access(conn, &id_refs))?;
+        let rows = conn.transaction::<_, Error, _>(|conn| {
+            let mut rows = self.storage.get_calls_and_access(conn, &id_refs)?;
+            rows.retain(|(_, retval, _)| cache_retval(retval));
+            let found: Vec<_> = rows.iter().map(|(id, _, _)| id.as_slice()).collect();
+            self.touch_calls(conn, &found)?;
+            Ok(rows)
+        })?;
+        self.metrics.record_call_cache_hit_and_miss(
+            &self.chain,
+            rows.len(),
+            reqs.len().saturating_sub(rows.len()),
+        );
 
         let mut found: Vec<usize> = Vec::new();
         let mut resps = Vec::new();
@@ -2579,7 +3551,7 @@ impl EthereumCallCache for ChainStore {
             found.push(idx);
             let resp = reqs[idx]
                 .cheap_clone()
-                .response(call::Retval::Value(retval), call::Source::Store);
+                .response(retval, call::Source::Store);
             resps.push(resp);
         }
         let calls = reqs
@@ -2603,13 +3575,9 @@ impl EthereumCallCache for ChainStore {
         block: BlockPtr,
         return_value: call::Retval,
     ) -> Result<(), Error> {
-        let call::Retval::Value(return_value) = return_value else {
-            // We do not want to cache unsuccessful calls as some RPC nodes
-            // have weird behavior near the chain head. The details are lost
-            // to time, but we had issues with some RPC clients in the past
-            // where calls first failed and later succeeded
+        if !cache_retval(&return_value) {
             return Ok(());
-        };
+        }
         let id = contract_call_id(&call, &block);
         let conn = &mut *self.get_conn()?;
         conn.transaction(|conn| {
@@ -2624,6 +3592,35 @@ impl EthereumCallCache for ChainStore {
     }
 }
 
+/// Whether `retval` should be stored in and returned from the call cache.
+/// We do not want to cache unsuccessful calls by default as some RPC nodes
+/// have weird behavior near the chain head. The details are lost to time,
+/// but we had issues with some RPC clients in the past where calls first
+/// failed and later succeeded. Calls that reverted are only cached when
+/// `GRAPH_STORE_CACHE_REVERTED_CALLS` is set
+fn cache_retval(retval: &call::Retval) -> bool {
+    match retval {
+        call::Retval::Value(_) => true,
+        call::Retval::Reverted(_) => cache_reverted_calls(),
+        call::Retval::Null => false,
+    }
+}
+
+#[cfg(debug_assertions)]
+lazy_static::lazy_static! {
+    /// Tests set this to true to cache reverted calls regardless of
+    /// `GRAPH_STORE_CACHE_REVERTED_CALLS`
+    pub static ref CACHE_REVERTED_CALLS: std::sync::Mutex<bool> = std::sync::Mutex::new(false);
+}
+
+fn cache_reverted_calls() -> bool {
+    #[cfg(debug_assertions)]
+    if *CACHE_REVERTED_CALLS.lock().unwrap() {
+        return true;
+    }
+    ENV_VARS.store.cache_reverted_calls
+}
+
 /// The id is the hashed encoded_call + contract_address + block hash to uniquely identify the call.
 /// 256 bits of output, and therefore 128 bits of security against collisions, are needed since this
 /// could be targeted by a birthday attack.
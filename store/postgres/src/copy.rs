@@ -11,9 +11,16 @@
 //! `subgraphs.copy_state` and `subgraphs.copy_table_state` so that a copy
 //! operation can resume after an interruption, for example, because
 //! `graph-node` was restarted while the copy was running.
+//!
+//! Tables are independent of each other, and up to
+//! `GRAPH_STORE_COPY_WORKERS` tables are copied at the same time, each
+//! with its own connection from the fdw pool.
 use std::{
     convert::TryFrom,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
@@ -45,12 +52,16 @@ use crate::{
 use crate::{connection_pool::ConnectionPool, relational::Layout};
 use crate::{relational::Table, relational_queries as rq};
 
-/// The initial batch size for tables that do not have an array column
+/// The largest initial batch size, used for tables with narrow rows
 const INITIAL_BATCH_SIZE: i64 = 10_000;
-/// The initial batch size for tables that do have an array column; those
-/// arrays can be large and large arrays will slow down copying a lot. We
-/// therefore tread lightly in that case
+/// The smallest initial batch size. Tables that have an array column end
+/// up close to this; those arrays can be large and large arrays will slow
+/// down copying a lot. We therefore tread lightly in that case
 const INITIAL_BATCH_SIZE_LIST: i64 = 100;
+/// How many bytes the first batch for a table should copy. Together with
+/// the estimated width of the table's rows, this determines the initial
+/// batch size
+const INITIAL_BATCH_BYTES: i64 = 4 * 1024 * 1024;
 
 const LOG_INTERVAL: Duration = Duration::from_secs(3 * 60);
 
@@ -75,6 +86,7 @@ table! {
         started_at -> Timestamptz,
         finished_at -> Nullable<Timestamptz>,
         cancelled_at -> Nullable<Timestamptz>,
+        convert_immutable -> Bool,
     }
 }
 
@@ -134,11 +146,16 @@ impl CopyState {
 
         let state = match cs::table
             .filter(cs::dst.eq(dst.site.id))
-            .select((cs::src, cs::target_block_hash, cs::target_block_number))
-            .first::<(DeploymentId, Vec<u8>, BlockNumber)>(conn)
+            .select((
+                cs::src,
+                cs::target_block_hash,
+                cs::target_block_number,
+                cs::convert_immutable,
+            ))
+            .first::<(DeploymentId, Vec<u8>, BlockNumber, bool)>(conn)
             .optional()?
         {
-            Some((src_id, hash, number)) => {
+            Some((src_id, hash, number, convert_immutable)) => {
                 let stored_target_block = BlockPtr::from((hash, number));
                 if stored_target_block != target_block {
                     return Err(constraint_violation!(
@@ -159,7 +176,7 @@ impl CopyState {
                         src.site.id
                     ));
                 }
-                Self::load(conn, src, dst, target_block)
+                Self::load(conn, src, dst, target_block, convert_immutable)
             }
             None => Self::create(conn, src, dst, target_block),
         }?;
@@ -172,8 +189,10 @@ impl CopyState {
         src: Arc<Layout>,
         dst: Arc<Layout>,
         target_block: BlockPtr,
+        convert_immutable: bool,
     ) -> Result<CopyState, StoreError> {
-        let tables = TableState::load(conn, src.as_ref(), dst.as_ref())?;
+        let convert_immutable = convert_immutable.then_some(target_block.number);
+        let tables = TableState::load(conn, src.as_ref(), dst.as_ref(), convert_immutable)?;
         Ok(CopyState {
             src,
             dst,
@@ -297,6 +316,77 @@ pub(crate) fn source(
         .map_err(StoreError::from)
 }
 
+/// How far copying data into `dst` has gotten
+pub(crate) struct CopyStatus {
+    /// The number of vids that have been copied so far across all tables
+    pub copied: i64,
+    /// The number of vids that need to be copied across all tables
+    pub total: i64,
+    /// Whether copying has finished or was cancelled; `None` while copying
+    /// is still in progress
+    pub status: Option<Status>,
+}
+
+/// Return how far copying into `dst` has gotten, or `None` if copying has
+/// not started yet
+pub(crate) fn status(
+    conn: &mut PgConnection,
+    dst: &Site,
+) -> Result<Option<CopyStatus>, StoreError> {
+    use copy_state as cs;
+    use copy_table_state as cts;
+
+    let state = cs::table
+        .filter(cs::dst.eq(dst.id))
+        .select((
+            cs::finished_at.is_not_null(),
+            cs::cancelled_at.is_not_null(),
+        ))
+        .get_result::<(bool, bool)>(conn)
+        .optional()?;
+    let (finished, cancelled) = match state {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+    let status = match (finished, cancelled) {
+        (_, true) => Some(Status::Cancelled),
+        (true, false) => Some(Status::Finished),
+        (false, false) => None,
+    };
+
+    let (copied, total) = cts::table
+        .filter(cts::dst.eq(dst.id))
+        .select((cts::next_vid, cts::target_vid))
+        .load::<(i64, i64)>(conn)?
+        .into_iter()
+        .fold((0, 0), |(copied, total), (next_vid, target_vid)| {
+            (copied + next_vid.min(target_vid), total + target_vid)
+        });
+    Ok(Some(CopyStatus {
+        copied,
+        total,
+        status,
+    }))
+}
+
+/// Let the unfinished copy into `dst` turn mutable entity types in the
+/// source into immutable ones even if some of their entities were updated
+/// or deleted. For such entity types, only the version of each entity that
+/// is current at the target block is copied. Return `false` if there is no
+/// unfinished copy into `dst`
+pub(crate) fn convert_immutable(conn: &mut PgConnection, dst: &Site) -> Result<bool, StoreError> {
+    use copy_state as cs;
+
+    let rows = update(
+        cs::table
+            .filter(cs::dst.eq(dst.id))
+            .filter(cs::finished_at.is_null()),
+    )
+    .set(cs::convert_immutable.eq(true))
+    .execute(conn)?;
+    Ok(rows > 0)
+}
+
 /// Track the desired size of a batch in such a way that doing the next
 /// batch gets close to TARGET_DURATION for the time it takes to copy one
 /// batch, but don't step up the size by more than 2x at once
@@ -307,15 +397,44 @@ pub(crate) struct AdaptiveBatchSize {
 
 impl AdaptiveBatchSize {
     pub fn new(table: &Table) -> Self {
-        let size = if table.columns.iter().any(|col| col.is_list()) {
-            INITIAL_BATCH_SIZE_LIST
-        } else {
-            INITIAL_BATCH_SIZE
-        };
+        let size = (INITIAL_BATCH_BYTES / Self::row_width(table))
+            .clamp(INITIAL_BATCH_SIZE_LIST, INITIAL_BATCH_SIZE);
 
         Self { size }
     }
 
+    /// A rough estimate of how many bytes a row of `table` takes up. We
+    /// can't ask the database since the source table of a copy might be a
+    /// foreign table for which the database has no statistics
+    fn row_width(table: &Table) -> i64 {
+        use crate::relational::ColumnType::*;
+
+        // vid and block_range or block$
+        const FIXED_WIDTH: i64 = 24;
+        // Arrays can be large; assume they are
+        const LIST_WIDTH: i64 = 32 * 1024;
+
+        table
+            .columns
+            .iter()
+            .map(|column| {
+                if column.is_list() {
+                    return LIST_WIDTH;
+                }
+                match column.column_type {
+                    Boolean => 1,
+                    Int => 4,
+                    Int8 | Timestamp | Enum(_) => 8,
+                    BigInt | BigDecimal => 32,
+                    Bytes => 48,
+                    String => 64,
+                    TSVector(_) => 512,
+                }
+            })
+            .sum::<i64>()
+            + FIXED_WIDTH
+    }
+
     // adjust batch size by trying to extrapolate in such a way that we
     // get close to TARGET_DURATION for the time it takes to copy one
     // batch, but don't step up batch_size by more than 2x at once
@@ -355,11 +474,20 @@ pub(crate) struct BatchCopy {
     next_vid: i64,
     /// The last `vid` that should be copied
     target_vid: i64,
+    /// When copying from a mutable into an immutable table, only copy the
+    /// versions that are current at this block; see `convert_immutable`
+    convert_immutable: Option<BlockNumber>,
     batch_size: AdaptiveBatchSize,
 }
 
 impl BatchCopy {
-    pub fn new(src: Arc<Table>, dst: Arc<Table>, first_vid: i64, last_vid: i64) -> Self {
+    pub fn new(
+        src: Arc<Table>,
+        dst: Arc<Table>,
+        first_vid: i64,
+        last_vid: i64,
+        convert_immutable: Option<BlockNumber>,
+    ) -> Self {
         let batch_size = AdaptiveBatchSize::new(&dst);
 
         Self {
@@ -367,6 +495,7 @@ impl BatchCopy {
             dst,
             next_vid: first_vid,
             target_vid: last_vid,
+            convert_immutable,
             batch_size,
         }
     }
@@ -379,8 +508,14 @@ impl BatchCopy {
         // Copy all versions with next_vid <= vid <= next_vid + batch_size - 1,
         // but do not go over target_vid
         let last_vid = (self.next_vid + self.batch_size.size - 1).min(self.target_vid);
-        rq::CopyEntityBatchQuery::new(self.dst.as_ref(), &self.src, self.next_vid, last_vid)?
-            .execute(conn)?;
+        rq::CopyEntityBatchQuery::new(
+            self.dst.as_ref(),
+            &self.src,
+            self.next_vid,
+            last_vid,
+            self.convert_immutable,
+        )?
+        .execute(conn)?;
 
         let duration = start.elapsed();
 
@@ -434,7 +569,7 @@ impl TableState {
         .unwrap_or(-1);
 
         Ok(Self {
-            batch: BatchCopy::new(src, dst, 0, target_vid),
+            batch: BatchCopy::new(src, dst, 0, target_vid, None),
             dst_site,
             duration_ms: 0,
         })
@@ -448,6 +583,7 @@ impl TableState {
         conn: &mut PgConnection,
         src_layout: &Layout,
         dst_layout: &Layout,
+        convert_immutable: Option<BlockNumber>,
     ) -> Result<Vec<TableState>, StoreError> {
         use copy_table_state as cts;
 
@@ -500,7 +636,13 @@ impl TableState {
                     );
                     match (src, dst) {
                         (Ok(src), Ok(dst)) => {
-                            let mut batch = BatchCopy::new(src, dst, current_vid, target_vid);
+                            let mut batch = BatchCopy::new(
+                                src,
+                                dst,
+                                current_vid,
+                                target_vid,
+                                convert_immutable,
+                            );
                             let batch_size = AdaptiveBatchSize { size };
 
                             batch.batch_size = batch_size;
@@ -683,10 +825,84 @@ impl<'a> CopyProgress<'a> {
     }
 }
 
+/// Copy the remaining batches of `table`, pausing when replicas lag too
+/// much. Stop early without an error if `stop` is set since that means
+/// that another worker ran into trouble
+fn copy_table(
+    conn: &mut PgConnection,
+    logger: &Logger,
+    table: &mut TableState,
+    progress: &Mutex<CopyProgress>,
+    stop: &AtomicBool,
+) -> Result<Status, StoreError> {
+    while !table.finished() {
+        if stop.load(Ordering::SeqCst) {
+            return Ok(Status::Finished);
+        }
+
+        // It is important that this check happens outside the write
+        // transaction so that we do not hold on to locks acquired
+        // by the check
+        if table.is_cancelled(conn)? {
+            return Ok(Status::Cancelled);
+        }
+
+        // Pause copying if replication is lagging behind to avoid
+        // overloading replicas
+        let mut lag = catalog::replication_lag(conn)?;
+        if lag > MAX_REPLICATION_LAG {
+            loop {
+                info!(logger,
+                     "Replicas are lagging too much; pausing copying for {}s to allow them to catch up",
+                     REPLICATION_SLEEP.as_secs();
+                     "lag_s" => lag.as_secs());
+                std::thread::sleep(REPLICATION_SLEEP);
+                lag = catalog::replication_lag(conn)?;
+                if lag <= ACCEPTABLE_REPLICATION_LAG {
+                    break;
+                }
+            }
+        }
+
+        let status = conn.transaction(|conn| table.copy_batch(conn))?;
+        if status == Status::Cancelled {
+            return Ok(status);
+        }
+        progress.lock().unwrap().update(&table.batch);
+    }
+    progress.lock().unwrap().table_finished(&table.batch);
+    Ok(Status::Finished)
+}
+
+/// Take tables from `queue` and copy them with `copy` until there are no
+/// more tables to copy. If copying a table is cancelled or fails, set
+/// `stop` so that the other workers stop, too
+fn copy_worker<C, T>(
+    conn: &mut C,
+    queue: &Mutex<Vec<T>>,
+    stop: &AtomicBool,
+    mut copy: impl FnMut(&mut C, T) -> Result<Status, StoreError>,
+) -> Result<Status, StoreError> {
+    loop {
+        let table = match queue.lock().unwrap().pop() {
+            Some(table) => table,
+            None => return Ok(Status::Finished),
+        };
+        match copy(conn, table) {
+            Ok(Status::Finished) if !stop.load(Ordering::SeqCst) => continue,
+            res => {
+                stop.store(true, Ordering::SeqCst);
+                return res;
+            }
+        }
+    }
+}
+
 /// A helper for copying subgraphs
 pub struct Connection {
     /// The connection pool for the shard that will contain the destination
     /// of the copy
+    pool: ConnectionPool,
     logger: Logger,
     conn: PooledConnection<ConnectionManager<PgConnection>>,
     src: Arc<Layout>,
@@ -731,6 +947,7 @@ impl Connection {
             false
         })?;
         Ok(Self {
+            pool,
             logger,
             conn,
             src,
@@ -768,48 +985,76 @@ impl Connection {
         let mut state = self.transaction(|conn| CopyState::new(conn, src, dst, target_block))?;
 
         let logger = &self.logger.clone();
-        let mut progress = CopyProgress::new(logger, &state);
+        let progress = CopyProgress::new(logger, &state);
         progress.start();
+        let progress = Mutex::new(progress);
+
+        // Workers beyond the first one need their own connection. We make
+        // do with fewer workers if the fdw pool does not have enough
+        // connections available right now
+        let mut conns = Vec::new();
+        for _ in 1..ENV_VARS.store.copy_workers.max(1) {
+            match self.pool.get_fdw(logger, || true) {
+                Ok(conn) => conns.push(conn),
+                Err(_) => break,
+            }
+        }
+        if !conns.is_empty() {
+            info!(logger, "Copying tables with {} workers", conns.len() + 1);
+        }
 
-        for table in state.tables.iter_mut().filter(|table| !table.finished()) {
-            while !table.finished() {
-                // It is important that this check happens outside the write
-                // transaction so that we do not hold on to locks acquired
-                // by the check
-                if table.is_cancelled(&mut self.conn)? {
-                    return Ok(Status::Cancelled);
-                }
-
-                // Pause copying if replication is lagging behind to avoid
-                // overloading replicas
-                let mut lag = catalog::replication_lag(&mut self.conn)?;
-                if lag > MAX_REPLICATION_LAG {
-                    loop {
-                        info!(&self.logger,
-                             "Replicas are lagging too much; pausing copying for {}s to allow them to catch up",
-                             REPLICATION_SLEEP.as_secs();
-                             "lag_s" => lag.as_secs());
-                        std::thread::sleep(REPLICATION_SLEEP);
-                        lag = catalog::replication_lag(&mut self.conn)?;
-                        if lag <= ACCEPTABLE_REPLICATION_LAG {
-                            break;
-                        }
-                    }
-                }
-
-                let status = self.transaction(|conn| table.copy_batch(conn))?;
-                if status == Status::Cancelled {
-                    return Ok(status);
+        let status = {
+            // Workers take tables from the end of the queue; start with the
+            // tables that have the most data left to copy
+            let mut tables: Vec<_> = state
+                .tables
+                .iter_mut()
+                .filter(|table| !table.finished())
+                .collect();
+            tables.sort_by_key(|table| table.batch.target_vid - table.batch.next_vid);
+            let queue = Mutex::new(tables);
+            let stop = AtomicBool::new(false);
+            let (queue, progress, stop) = (&queue, &progress, &stop);
+
+            let conn = &mut self.conn;
+            let results = std::thread::scope(|scope| {
+                let workers: Vec<_> = conns
+                    .iter_mut()
+                    .map(|conn| {
+                        scope.spawn(move || {
+                            copy_worker(conn, queue, stop, |conn, table| {
+                                copy_table(conn, logger, table, progress, stop)
+                            })
+                        })
+                    })
+                    .collect();
+                let mut results = vec![copy_worker(conn, queue, stop, |conn, table| {
+                    copy_table(conn, logger, table, progress, stop)
+                })];
+                results.extend(workers.into_iter().map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|_| Err(constraint_violation!("a copy worker panicked")))
+                }));
+                results
+            });
+
+            let mut status = Status::Finished;
+            for res in results {
+                if res? == Status::Cancelled {
+                    status = Status::Cancelled;
                 }
-                progress.update(&table.batch);
             }
-            progress.table_finished(&table.batch);
+            status
+        };
+        if status == Status::Cancelled {
+            return Ok(status);
         }
 
         self.copy_private_data_sources(&state)?;
 
         self.transaction(|conn| state.finished(conn))?;
-        progress.finished();
+        progress.into_inner().unwrap().finished();
 
         Ok(Status::Finished)
     }
@@ -850,3 +1095,126 @@ impl Connection {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::sync::atomic::AtomicUsize;
+
+    use graph::prelude::DeploymentHash;
+    use graph::schema::InputSchema;
+
+    use crate::catalog::Catalog;
+    use crate::primary::{make_dummy_site, Namespace};
+    use crate::relational::SqlName;
+
+    use super::*;
+
+    fn layout(gql: &str) -> Layout {
+        let subgraph = DeploymentHash::new("copy").unwrap();
+        let schema = InputSchema::parse_latest(gql, subgraph.clone()).unwrap();
+        let namespace = Namespace::new("sgd0815".to_owned()).unwrap();
+        let site = Arc::new(make_dummy_site(subgraph, namespace, "anet".to_string()));
+        let catalog = Catalog::for_tests(site.clone(), BTreeSet::new()).unwrap();
+        Layout::new(site, &schema, catalog).unwrap()
+    }
+
+    #[test]
+    fn initial_batch_size_depends_on_row_width() {
+        const GQL: &str = "
+            type Narrow @entity { id: Bytes!, count: Int! }
+            type Wide @entity {
+                id: String!, a: String!, b: String!, c: String!, d: String!,
+                e: String!, f: String!, g: String!, h: String!
+            }
+            type Listy @entity { id: Bytes!, values: [BigInt!]! }";
+        let layout = layout(GQL);
+        let size = |name: &str| {
+            let table = layout.table(&SqlName::verbatim(name.to_string())).unwrap();
+            AdaptiveBatchSize::new(table).size
+        };
+
+        let narrow = size("narrow");
+        let wide = size("wide");
+        let listy = size("listy");
+        assert_eq!(INITIAL_BATCH_SIZE, narrow);
+        assert!(wide < narrow);
+        assert!(listy < wide);
+        assert!(listy >= INITIAL_BATCH_SIZE_LIST);
+    }
+
+    #[test]
+    fn workers_copy_each_table_once() {
+        let queue = Mutex::new((0..100).collect::<Vec<usize>>());
+        let stop = AtomicBool::new(false);
+        let copied = Mutex::new(Vec::new());
+
+        let results = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        copy_worker(&mut (), &queue, &stop, |_, table| {
+                            copied.lock().unwrap().push(table);
+                            Ok(Status::Finished)
+                        })
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert!(results
+            .into_iter()
+            .all(|res| res.unwrap() == Status::Finished));
+        assert!(!stop.load(Ordering::SeqCst));
+        let mut copied = copied.into_inner().unwrap();
+        copied.sort();
+        assert_eq!((0..100).collect::<Vec<_>>(), copied);
+    }
+
+    #[test]
+    fn workers_stop_after_error_or_cancel() {
+        // Run two workers one after the other; the first one fails on
+        // `fail_at` and the second one should stop right away
+        fn run(fail_at: usize, outcome: Result<Status, StoreError>) -> (usize, bool) {
+            let queue = Mutex::new((0..100).collect::<Vec<usize>>());
+            let stop = AtomicBool::new(false);
+            let copied = AtomicUsize::new(0);
+            let mut outcome = Some(outcome);
+
+            let res = copy_worker(&mut (), &queue, &stop, |_, table| {
+                if table == fail_at {
+                    return outcome.take().unwrap();
+                }
+                copied.fetch_add(1, Ordering::SeqCst);
+                Ok(Status::Finished)
+            });
+            assert!(stop.load(Ordering::SeqCst));
+            let cancelled = match res {
+                Ok(status) => status == Status::Cancelled,
+                Err(_) => false,
+            };
+
+            // The second worker finishes the table it was working on when
+            // the first one stopped, but does not start another one
+            let res = copy_worker(&mut (), &queue, &stop, |_, _| {
+                copied.fetch_add(1, Ordering::SeqCst);
+                Ok(Status::Finished)
+            });
+            assert!(res.unwrap() == Status::Finished);
+            (copied.load(Ordering::SeqCst), cancelled)
+        }
+
+        // Tables are taken from the end of the queue
+        let (copied, cancelled) = run(90, Ok(Status::Cancelled));
+        assert!(cancelled);
+        assert_eq!(10, copied);
+
+        let (copied, cancelled) = run(50, Err(constraint_violation!("copy failed")));
+        assert!(!cancelled);
+        assert_eq!(50, copied);
+    }
+}
@@ -0,0 +1,128 @@
+//! Defer maintaining indexes that indexing does not need while a
+//! deployment catches up with the chain head. Writes to tables with many
+//! attribute indexes are much slower than writes to tables that only have
+//! the indexes that indexing itself uses; for a deployment that is far
+//! behind the chain head, it is faster overall to drop its attribute
+//! indexes and to recreate them once it gets close to the chain head.
+//!
+//! The definitions of dropped indexes are kept in the
+//! `subgraphs.deferred_index` table in the deployment's shard. An index is
+//! only removed from that table once it has been recreated so that
+//! indexes get restored even if `graph-node` restarts in between
+use diesel::{
+    delete, insert_into, sql_query, Connection, ExpressionMethods, PgConnection, QueryDsl,
+    RunQueryDsl,
+};
+use graph::{
+    constraint_violation,
+    prelude::{info, Logger, StoreError},
+};
+
+use crate::catalog;
+use crate::primary::Site;
+use crate::relational::{index::CreateIndex, Layout};
+
+table! {
+    subgraphs.deferred_index(deployment, name) {
+        deployment -> Integer,
+        name -> Text,
+        definition -> Text,
+    }
+}
+
+/// Drop the non-unique attribute indexes of all tables in `layout` and
+/// remember their definitions. Returns the number of indexes that were
+/// dropped
+pub(crate) fn defer(conn: &mut PgConnection, layout: &Layout) -> Result<usize, StoreError> {
+    use deferred_index as di;
+
+    let nsp = layout.site.namespace.as_str();
+    conn.transaction::<_, StoreError, _>(|conn| {
+        let mut count = 0;
+        for table in layout.tables.values() {
+            for defn in catalog::indexes_for_table(conn, nsp, table.name.as_str())? {
+                let index = CreateIndex::parse(defn.clone());
+                if !index.is_attribute_index() {
+                    continue;
+                }
+                let CreateIndex::Parsed {
+                    unique: false,
+                    name,
+                    ..
+                } = &index
+                else {
+                    continue;
+                };
+
+                insert_into(di::table)
+                    .values((
+                        di::deployment.eq(layout.site.id),
+                        di::name.eq(name),
+                        di::definition.eq(&defn),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                sql_query(format!("drop index {nsp}.{name}")).execute(conn)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    })
+}
+
+/// Return the number of indexes of `site` that `defer` dropped and that
+/// have not been recreated yet
+pub(crate) fn pending(conn: &mut PgConnection, site: &Site) -> Result<i64, StoreError> {
+    use deferred_index as di;
+
+    di::table
+        .filter(di::deployment.eq(site.id))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(StoreError::from)
+}
+
+/// Recreate all indexes of `site` that `defer` dropped. The indexes are
+/// created concurrently, and this function must therefore not be called
+/// inside a transaction
+pub(crate) fn restore(
+    conn: &mut PgConnection,
+    logger: &Logger,
+    site: &Site,
+) -> Result<(), StoreError> {
+    use deferred_index as di;
+
+    let nsp = site.namespace.as_str();
+    let indexes = di::table
+        .filter(di::deployment.eq(site.id))
+        .select((di::name, di::definition))
+        .order_by(di::name)
+        .load::<(String, String)>(conn)?;
+
+    for (name, defn) in indexes {
+        // An index that is there but not valid was left behind by an
+        // earlier attempt that got interrupted
+        if !catalog::check_index_is_valid(conn, nsp, &name)? {
+            sql_query(format!("drop index concurrently if exists {nsp}.{name}")).execute(conn)?;
+            let sql = CreateIndex::parse(defn)
+                .to_sql(true, false)
+                .map_err(|e| StoreError::Unknown(e.into()))?;
+            info!(logger, "Recreating deferred index"; "index" => &name);
+            sql_query(sql).execute(conn)?;
+            if !catalog::check_index_is_valid(conn, nsp, &name)? {
+                return Err(constraint_violation!(
+                    "recreating the deferred index {}.{} did not produce a valid index",
+                    nsp,
+                    name
+                ));
+            }
+        }
+        delete(
+            di::table
+                .filter(di::deployment.eq(site.id))
+                .filter(di::name.eq(&name)),
+        )
+        .execute(conn)?;
+    }
+    Ok(())
+}
@@ -589,6 +589,25 @@ pub fn block_ptr(
     Ok(ptr)
 }
 
+/// Return the number of the latest block that the deployment `site` has
+/// processed, or `None` if it has not processed any blocks yet
+pub fn block_number(
+    conn: &mut PgConnection,
+    site: &Site,
+) -> Result<Option<BlockNumber>, StoreError> {
+    use subgraph_deployment as d;
+
+    d::table
+        .filter(d::id.eq(site.id))
+        .select(sql::<Nullable<Integer>>(
+            "latest_ethereum_block_number::int4",
+        ))
+        .first::<Option<BlockNumber>>(conn)
+        .optional()
+        .map(Option::flatten)
+        .map_err(StoreError::from)
+}
+
 /// Initialize the subgraph's block pointer. If the block pointer in
 /// `latest_ethereum_block` is set already, do nothing. If it is still
 /// `null`, set it to `start_ethereum_block` from `subgraph_manifest`
@@ -817,6 +836,26 @@ pub fn fail(
     Ok(())
 }
 
+/// Remember `ptr` as the last block before the block at which the
+/// deployment failed with a deterministic error. A new deployment can be
+/// grafted onto the failed one at that block
+pub fn set_last_healthy_block(
+    conn: &mut PgConnection,
+    id: &DeploymentHash,
+    ptr: &BlockPtr,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    let number = format!("{}::numeric", ptr.number);
+    update(d::table.filter(d::deployment.eq(id.as_str())))
+        .set((
+            d::last_healthy_ethereum_block_hash.eq(ptr.hash_slice()),
+            d::last_healthy_ethereum_block_number.eq(sql(&number)),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
 pub fn update_non_fatal_errors(
     conn: &mut PgConnection,
     deployment_id: &DeploymentHash,
@@ -993,14 +1032,20 @@ pub(crate) fn revert_subgraph_errors(
 
     // If the deployment is failed in both `failed` and `status` columns,
     // update both values respectively to `false` and `healthy`. Basically
-    // unfail the statuses.
+    // unfail the statuses. The last healthy block only matters while the
+    // deployment is failed
     update(
         d::table
             .filter(d::deployment.eq(id.as_str()))
             .filter(d::failed.eq(true))
             .filter(d::health.eq(SubgraphHealth::Failed)),
     )
-    .set((d::failed.eq(false), d::health.eq(SubgraphHealth::Healthy)))
+    .set((
+        d::failed.eq(false),
+        d::health.eq(SubgraphHealth::Healthy),
+        d::last_healthy_ethereum_block_hash.eq(None::<&[u8]>),
+        d::last_healthy_ethereum_block_number.eq(sql("null")),
+    ))
     .execute(conn)
     .map(|_| ())
     .map_err(StoreError::from)
@@ -1130,6 +1175,10 @@ pub fn create_deployment(
         graft_block,
         debug_fork,
         history_blocks_override,
+        // Stored per table in `table_stats` once the tables exist
+        entity_history_blocks: _,
+        // Set on the tables once they exist
+        entity_storage_params: _,
     } = deployment;
     let earliest_block_number = start_block.as_ref().map(|ptr| ptr.number).unwrap_or(0);
     let entities_with_causality_region = Vec::from_iter(
@@ -1233,6 +1282,16 @@ pub fn set_entity_count(
     Ok(())
 }
 
+/// The earliest block for which `site` has data
+pub fn earliest_block(conn: &mut PgConnection, site: &Site) -> Result<BlockNumber, StoreError> {
+    use subgraph_deployment as d;
+
+    Ok(d::table
+        .filter(d::id.eq(site.id))
+        .select(d::earliest_block_number)
+        .first::<BlockNumber>(conn)?)
+}
+
 /// Set the earliest block of `site` to the larger of `earliest_block` and
 /// the current value. This means that the `earliest_block_number` can never
 /// go backwards, only forward. This is important so that copying into
@@ -8,13 +8,14 @@ use graph::blockchain::block_stream::FirehoseCursor;
 use graph::blockchain::BlockTime;
 use graph::components::store::write::RowGroup;
 use graph::components::store::{
-    Batch, DeploymentLocator, DerivedEntityQuery, PrunePhase, PruneReporter, PruneRequest,
-    PruningStrategy, QueryPermit, StoredDynamicDataSource, VersionStats,
+    Batch, DeploymentLocator, DerivedEntityQuery, EntityIndex, EntityTypeStats, ExportFormat,
+    PrunePhase, PruneReporter, PruneRequest, PruningStrategy, QueryPermit, RollupBackfillReporter,
+    StoredDynamicDataSource, VersionStats,
 };
 use graph::components::versions::VERSIONS;
 use graph::data::query::Trace;
 use graph::data::store::IdList;
-use graph::data::subgraph::{status, SPEC_VERSION_0_0_6};
+use graph::data::subgraph::{status, StorageParams, SPEC_VERSION_0_0_6};
 use graph::data_source::CausalityRegion;
 use graph::derive::CheapClone;
 use graph::futures03::FutureExt;
@@ -32,17 +33,19 @@ use std::convert::Into;
 use std::ops::Bound;
 use std::ops::Deref;
 use std::str::FromStr;
-use std::sync::{atomic::AtomicUsize, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use graph::components::store::EntityCollection;
 use graph::components::subgraph::{ProofOfIndexingFinisher, ProofOfIndexingVersion};
 use graph::constraint_violation;
-use graph::data::subgraph::schema::{DeploymentCreate, SubgraphError};
+use graph::data::subgraph::schema::{DeploymentCreate, SkippedTrigger, SubgraphError};
 use graph::prelude::{
     anyhow, debug, info, o, warn, web3, AttributeNames, BlockNumber, BlockPtr, CheapClone,
-    DeploymentHash, DeploymentState, Entity, EntityQuery, Error, Logger, QueryExecutionError,
-    StopwatchMetrics, StoreError, StoreEvent, UnfailOutcome, Value, ENV_VARS,
+    CounterVec, DeploymentHash, DeploymentState, Entity, EntityAggregate, EntityQuery, Error,
+    HistogramVec, Logger, MetricsRegistry, QueryExecutionError, StopwatchMetrics, StoreError,
+    StoreEvent, UnfailOutcome, Value, ENV_VARS,
 };
 use graph::schema::{ApiSchema, EntityKey, EntityType, InputSchema};
 use web3::types::Address;
@@ -51,10 +54,12 @@ use crate::block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
 use crate::deployment::{self, OnSync};
 use crate::detail::ErrorDetail;
 use crate::dynds::DataSourcesTable;
+use crate::index_advisor::{self, IndexSuggestion, QueryPatterns};
 use crate::primary::DeploymentId;
 use crate::relational::index::{CreateIndex, Method};
 use crate::relational::{Layout, LayoutCache, SqlName, Table};
-use crate::relational_queries::FromEntityData;
+use crate::relational_queries::{FromEntityData, QueryShape};
+use crate::table_churn::{is_churned, TableChurn, Writes};
 use crate::{advisory_lock, catalog, retry};
 use crate::{connection_pool::ConnectionPool, detail};
 use crate::{dynds, primary::Site};
@@ -69,6 +74,46 @@ pub enum ReplicaId {
     ReadOnly(usize),
 }
 
+/// How long we trust what we know about the subgraph head of a deployment
+/// in a read replica before checking it again
+const REPLICA_HEAD_TTL: Duration = Duration::from_secs(5);
+
+/// How long to wait before trying again to recreate deferred indexes after
+/// recreating them failed
+const INDEX_RESTORE_RETRY: Duration = Duration::from_secs(60);
+
+/// The subgraph heads of deployments as one read replica last reported
+/// them. Since replicas lag behind the main database, a replica can only
+/// serve a query at a block once it has replayed the writes for that block
+struct ReplicaHeads {
+    heads: Mutex<HashMap<DeploymentId, (Option<BlockNumber>, Instant)>>,
+}
+
+impl ReplicaHeads {
+    fn new() -> Self {
+        Self {
+            heads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return `true` if we know that the replica has data for `site` up
+    /// to at least `block`
+    fn caught_up(&self, site: &Site, block: BlockNumber) -> bool {
+        let heads = self.heads.lock().unwrap();
+        match heads.get(&site.id) {
+            Some((Some(head), checked_at)) => {
+                *head >= block && checked_at.elapsed() < REPLICA_HEAD_TTL
+            }
+            Some((None, _)) | None => false,
+        }
+    }
+
+    fn set(&self, site: &Site, head: Option<BlockNumber>) {
+        let mut heads = self.heads.lock().unwrap();
+        heads.insert(site.id, (head, Instant::now()));
+    }
+}
+
 /// Commonly needed information about a subgraph that we cache in
 /// `Store.subgraph_cache`. Only immutable subgraph data can be cached this
 /// way as the cache lives for the lifetime of the `Store` object
@@ -95,6 +140,11 @@ pub struct StoreInner {
 
     pool: ConnectionPool,
     read_only_pools: Vec<ConnectionPool>,
+    /// The subgraph heads in each of the `read_only_pools`
+    replica_heads: Vec<ReplicaHeads>,
+    /// The number of queries that were meant to go to a read replica but
+    /// had to use the main database because the replica lagged behind
+    replica_fallbacks: CounterVec,
 
     /// A list of the available replicas set up such that when we run
     /// through the list once, we picked each replica according to its
@@ -113,6 +163,41 @@ pub struct StoreInner {
     pub(crate) layout_cache: LayoutCache,
 
     prune_handles: Mutex<HashMap<DeploymentId, PruneHandle>>,
+
+    /// The deployments for which deferred indexes are being recreated in
+    /// the background (`None`), or for which recreating them failed at
+    /// the given time
+    index_restores: Mutex<HashMap<DeploymentId, Option<Instant>>>,
+
+    /// The latest block at which pruning was started for each deployment.
+    /// Deployments with entity types that keep less history than the
+    /// deployment use this to decide when to prune again since their
+    /// earliest block does not reflect how much history those entity
+    /// types have
+    prune_blocks: Mutex<HashMap<DeploymentId, BlockNumber>>,
+
+    /// The number of lookups of single entities, labelled by whether
+    /// caching their prepared statements was enabled. Diesel does not tell
+    /// us whether a statement actually came from its cache
+    entity_lookups: CounterVec,
+
+    /// How long the queries that we generate for a deployment took to
+    /// execute, labelled by deployment and `QueryShape`
+    query_execution_time: HistogramVec,
+    /// How many rows the queries that we generate for a deployment
+    /// returned, labelled by deployment and `QueryShape`
+    query_rows: CounterVec,
+
+    /// Whether a slow query is being explained in the background. We
+    /// explain at most one query at a time and skip slow queries while
+    /// that is happening
+    explaining: Arc<AtomicBool>,
+
+    /// The query patterns that have not been written to the database yet
+    query_patterns: QueryPatterns,
+
+    /// How many rows indexing wrote to each table, see `table_churn`
+    table_churn: TableChurn,
 }
 
 /// Storage of the data for individual deployments. Each `DeploymentStore`
@@ -133,6 +218,7 @@ impl DeploymentStore {
         pool: ConnectionPool,
         read_only_pools: Vec<ConnectionPool>,
         mut pool_weights: Vec<usize>,
+        registry: Arc<MetricsRegistry>,
     ) -> Self {
         // Create a store-specific logger
         let logger = logger.new(o!("component" => "Store"));
@@ -157,16 +243,55 @@ impl DeploymentStore {
         replica_order.shuffle(&mut rng);
         debug!(logger, "Using postgres host order {:?}", replica_order);
 
+        let replica_heads = read_only_pools
+            .iter()
+            .map(|_| ReplicaHeads::new())
+            .collect();
+
         // Create the store
         let store = StoreInner {
             logger: logger.clone(),
             pool,
             read_only_pools,
+            replica_heads,
+            replica_fallbacks: registry
+                .global_counter_vec(
+                    "store_query_replica_fallbacks",
+                    "Number of queries that used the main database because the read replica lagged behind",
+                    &["shard", "replica"],
+                )
+                .expect("failed to create `store_query_replica_fallbacks` counter"),
             replica_order,
             conn_round_robin_counter: AtomicUsize::new(0),
             subgraph_cache: Mutex::new(LruCache::with_capacity(100)),
             layout_cache: LayoutCache::new(ENV_VARS.store.query_stats_refresh_interval),
             prune_handles: Mutex::new(HashMap::new()),
+            index_restores: Mutex::new(HashMap::new()),
+            prune_blocks: Mutex::new(HashMap::new()),
+            entity_lookups: registry
+                .global_counter_vec(
+                    "store_entity_lookups",
+                    "Number of lookups of single entities by id",
+                    &["shard", "statement_caching"],
+                )
+                .expect("failed to create `store_entity_lookups` counter"),
+            query_execution_time: registry
+                .global_histogram_vec(
+                    "store_query_execution_time",
+                    "Execution time of the queries generated for a deployment in seconds",
+                    &["deployment", "shape"],
+                )
+                .expect("failed to create `store_query_execution_time` histogram"),
+            query_rows: registry
+                .global_counter_vec(
+                    "store_query_rows",
+                    "Number of rows returned by the queries generated for a deployment",
+                    &["deployment", "shape"],
+                )
+                .expect("failed to create `store_query_rows` counter"),
+            explaining: Arc::new(AtomicBool::new(false)),
+            query_patterns: QueryPatterns::new(),
+            table_churn: TableChurn::new(),
         };
 
         DeploymentStore(Arc::new(store))
@@ -197,6 +322,8 @@ impl DeploymentStore {
                 } else {
                     deployment
                 };
+            let entity_history_blocks = deployment.entity_history_blocks.clone();
+            let entity_storage_params = deployment.entity_storage_params.clone();
 
             if replace || !exists {
                 deployment::create_deployment(conn, &site, deployment, exists, replace)?;
@@ -213,6 +340,34 @@ impl DeploymentStore {
                     schema,
                     entities_with_causality_region.into_iter().collect(),
                 )?;
+                for (entity, history_blocks) in &entity_history_blocks {
+                    let table = layout
+                        .tables
+                        .values()
+                        .find(|table| table.object.as_str() == entity.as_str());
+                    if let Some(table) = table {
+                        catalog::set_table_history_blocks(
+                            conn,
+                            &site,
+                            &table.name,
+                            *history_blocks,
+                        )?;
+                    }
+                }
+                for (entity, params) in &entity_storage_params {
+                    let table = layout
+                        .tables
+                        .values()
+                        .find(|table| table.object.as_str() == entity.as_str());
+                    if let Some(table) = table {
+                        catalog::set_storage_params(
+                            conn,
+                            &site.namespace,
+                            &table.name,
+                            &params.options(),
+                        )?;
+                    }
+                }
                 // See if we are grafting and check that the graft is permissible
                 if let Some(base) = graft_base {
                     let errors = layout.can_copy_from(&base);
@@ -270,13 +425,103 @@ impl DeploymentStore {
         site: Arc<Site>,
         query: EntityQuery,
     ) -> Result<(Vec<T>, Trace), QueryExecutionError> {
-        let layout = self.layout(conn, site)?;
+        let layout = self.layout(conn, site.cheap_clone())?;
 
         let logger = query
             .logger
             .cheap_clone()
             .unwrap_or_else(|| self.logger.cheap_clone());
-        layout.query(&logger, conn, query)
+        if ENV_VARS.store.record_query_patterns {
+            self.record_query_pattern(&logger, &site, &query);
+        }
+        // Only hold on to a copy of the query if we might have to explain it
+        let explain = ENV_VARS
+            .store
+            .explain_slow_queries
+            .map(|threshold| (threshold, query.clone()));
+        let shape = QueryShape::from(&query.collection);
+        let start = Instant::now();
+        let res = layout.query(&logger, conn, query);
+        let rows = res.as_ref().map_or(0, |(values, _)| values.len());
+        self.observe_query(&site, shape, start, rows);
+        if let Some((threshold, query)) = explain {
+            let elapsed = start.elapsed();
+            if res.is_ok() && elapsed >= threshold {
+                self.explain_query(&logger, layout, query, elapsed);
+            }
+        }
+        res
+    }
+
+    /// Explain `query`, which took `elapsed` to execute, in the background
+    /// with `Layout::explain_query`. If another query is being explained,
+    /// `query` is not explained
+    fn explain_query(
+        &self,
+        logger: &Logger,
+        layout: Arc<Layout>,
+        query: EntityQuery,
+        elapsed: Duration,
+    ) {
+        if self.explaining.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let explaining = self.explaining.cheap_clone();
+        let pool = self.pool.clone();
+        let logger = logger.cheap_clone();
+        graph::spawn_blocking_allow_panic(move || {
+            match pool.get() {
+                Ok(mut conn) => layout.explain_query(&logger, &mut conn, query, elapsed),
+                Err(e) => {
+                    warn!(logger, "Failed to explain slow query"; "error" => e.to_string())
+                }
+            }
+            explaining.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Record the execution time and number of rows of a query of the
+    /// given `shape` for `site`
+    fn observe_query(&self, site: &Site, shape: QueryShape, start: Instant, rows: usize) {
+        let labels = [site.deployment.as_str(), shape.as_str()];
+        self.query_execution_time
+            .with_label_values(&labels)
+            .observe(start.elapsed().as_secs_f64());
+        self.query_rows
+            .with_label_values(&labels)
+            .inc_by(rows as f64);
+    }
+
+    /// Count the pattern of `query` for the index advisor, and write the
+    /// counts to the database every now and then. The counts are written
+    /// in a background task so that queries never wait for that. Since
+    /// `conn` might be for a replica, they are written with a connection
+    /// from the main pool
+    fn record_query_pattern(&self, logger: &Logger, site: &Site, query: &EntityQuery) {
+        self.query_patterns.record(site, query);
+        if let Some(counts) = self.query_patterns.take_if_due() {
+            let pool = self.pool.clone();
+            let logger = logger.cheap_clone();
+            graph::spawn_blocking_allow_panic(move || {
+                if let Err(e) = pool
+                    .get()
+                    .and_then(|mut conn| index_advisor::flush(&mut conn, counts))
+                {
+                    warn!(logger, "Failed to record query patterns"; "error" => e.to_string());
+                }
+            });
+        }
+    }
+
+    pub(crate) fn execute_aggregate_query(
+        &self,
+        conn: &mut PgConnection,
+        site: Arc<Site>,
+        query: EntityQuery,
+        aggregates: &[EntityAggregate],
+    ) -> Result<(Vec<Value>, Trace), QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+        layout.aggregate(conn, query, aggregates)
     }
 
     fn check_intf_uniqueness(
@@ -314,6 +559,12 @@ impl DeploymentStore {
         for group in groups {
             count += group.entity_count_change();
 
+            if ENV_VARS.store.analyze_churn_ratio > 0.0 {
+                let table = layout.table_for_entity(&group.entity_type)?;
+                self.table_churn
+                    .record(&layout.site, &table.name, group.row_count());
+            }
+
             // Clamp entities before inserting them to avoid having versions
             // with overlapping block ranges
             let section = stopwatch.start_section("apply_entity_modifications_delete");
@@ -397,15 +648,38 @@ impl DeploymentStore {
         self.read_only_pools[idx].get().map_err(Error::from)
     }
 
-    pub(crate) fn get_replica_conn(
+    /// Get a connection for running a query against `site` at `block`. If
+    /// `replica` is a read replica, the query only uses it if the replica
+    /// has replayed the writes for `site` up to `block`, and falls back to
+    /// the main database otherwise so that queries never see data that
+    /// is older than what they asked for
+    pub(crate) fn get_query_conn(
         &self,
         replica: ReplicaId,
+        site: &Site,
+        block: BlockNumber,
     ) -> Result<PooledConnection<ConnectionManager<PgConnection>>, Error> {
-        let conn = match replica {
-            ReplicaId::Main => self.get_conn()?,
-            ReplicaId::ReadOnly(idx) => self.read_only_conn(idx)?,
+        let idx = match replica {
+            ReplicaId::Main => return Ok(self.get_conn()?),
+            ReplicaId::ReadOnly(idx) => idx,
         };
-        Ok(conn)
+        let heads = &self.replica_heads[idx];
+        let mut conn = self.read_only_conn(idx)?;
+        if heads.caught_up(site, block) {
+            return Ok(conn);
+        }
+
+        let head = deployment::block_number(&mut conn, site)?;
+        heads.set(site, head);
+        if head.map_or(false, |head| head >= block) {
+            return Ok(conn);
+        }
+        drop(conn);
+
+        self.replica_fallbacks
+            .with_label_values(&[self.pool.shard.as_str(), &idx.to_string()])
+            .inc();
+        Ok(self.get_conn()?)
     }
 
     pub(crate) async fn query_permit(&self, replica: ReplicaId) -> Result<QueryPermit, StoreError> {
@@ -574,6 +848,23 @@ impl DeploymentStore {
         crate::copy::source(&mut conn, site)
     }
 
+    /// Return how far copying data into `site` has gotten, or `None` if
+    /// copying has not started yet
+    pub(crate) fn copy_status(
+        &self,
+        site: &Site,
+    ) -> Result<Option<crate::copy::CopyStatus>, StoreError> {
+        let mut conn = self.get_conn()?;
+        crate::copy::status(&mut conn, site)
+    }
+
+    /// Let the unfinished copy into `site` convert mutable entity types
+    /// into immutable ones; see `copy::convert_immutable`
+    pub(crate) fn convert_immutable(&self, site: &Site) -> Result<bool, StoreError> {
+        let mut conn = self.get_conn()?;
+        crate::copy::convert_immutable(&mut conn, site)
+    }
+
     // Only used for tests
     #[cfg(debug_assertions)]
     pub(crate) fn drop_deployment_schema(
@@ -616,6 +907,43 @@ impl DeploymentStore {
         .await
     }
 
+    /// Analyze the tables to which indexing wrote so many rows since they
+    /// were last analyzed that their statistics are likely stale, see
+    /// `GRAPH_STORE_ANALYZE_CHURN_RATIO`
+    pub(crate) async fn analyze_churned_tables(&self) -> Result<(), StoreError> {
+        let writes = self.table_churn.take();
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        let store = self.clone();
+        self.with_conn(move |conn, cancel| {
+            let ratio = ENV_VARS.store.analyze_churn_ratio;
+            for Writes { site, rows } in writes {
+                let layout = store.layout(conn, site.cheap_clone())?;
+                let estimates = catalog::row_estimates(conn, &site.namespace)?;
+                for (name, written) in rows {
+                    let estimate = estimates.get(name.as_str()).copied().unwrap_or(0.0);
+                    if !is_churned(written, estimate, ratio) {
+                        // Not enough churn yet, keep counting
+                        store.table_churn.record(&site, &name, written);
+                        continue;
+                    }
+                    cancel.check_cancel()?;
+                    if let Some(table) = layout.tables.values().find(|table| table.name == name) {
+                        if ENV_VARS.store.vacuum_churned_tables {
+                            table.vacuum(conn)?;
+                        } else {
+                            table.analyze(conn)?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
     /// Runs the SQL `ANALYZE` command in a table.
     pub(crate) fn analyze(&self, site: Arc<Site>, entity: Option<&str>) -> Result<(), StoreError> {
         let mut conn = self.get_conn()?;
@@ -631,6 +959,22 @@ impl DeploymentStore {
         Ok(())
     }
 
+    /// Write the state of all entities of type `entity_type` at `block` to
+    /// `writer` and return how many entities were written
+    pub(crate) fn export_entities(
+        &self,
+        site: Arc<Site>,
+        entity_type: &str,
+        block: BlockNumber,
+        format: ExportFormat,
+        writer: &mut dyn std::io::Write,
+    ) -> Result<usize, StoreError> {
+        let mut conn = self.get_conn()?;
+        let layout = self.layout(&mut conn, site)?;
+        let entity_type = layout.input_schema.entity_type(entity_type)?;
+        layout.export_entities(&mut conn, &entity_type, block, format, writer)
+    }
+
     pub(crate) fn stats_targets(
         &self,
         site: Arc<Site>,
@@ -668,6 +1012,33 @@ impl DeploymentStore {
         })
     }
 
+    pub(crate) fn storage_params(
+        &self,
+        site: Arc<Site>,
+    ) -> Result<BTreeMap<SqlName, Vec<String>>, StoreError> {
+        let mut conn = self.get_conn()?;
+        catalog::storage_params(&mut conn, &site.namespace)
+    }
+
+    /// Set the storage parameters `params` on the table for `entity`, and
+    /// reset the parameters in `reset` to their defaults
+    pub(crate) fn set_storage_params(
+        &self,
+        site: Arc<Site>,
+        entity: &str,
+        params: &StorageParams,
+        reset: &[&str],
+    ) -> Result<(), StoreError> {
+        let mut conn = self.get_conn()?;
+        let layout = self.layout(&mut conn, site.clone())?;
+        let table = resolve_table_name(&layout, entity)?;
+
+        conn.transaction(|conn| {
+            catalog::set_storage_params(conn, &site.namespace, &table.name, &params.options())?;
+            catalog::reset_storage_params(conn, &site.namespace, &table.name, reset)
+        })
+    }
+
     /// Runs the SQL `ANALYZE` command in a table, with a shared connection.
     pub(crate) fn analyze_with_conn(
         &self,
@@ -725,6 +1096,87 @@ impl DeploymentStore {
         .await
     }
 
+    /// Drop the attribute indexes of `site` to speed up writes while it
+    /// catches up with the chain head; see `deferred_index`
+    pub(crate) async fn defer_indexes(
+        &self,
+        logger: &Logger,
+        site: Arc<Site>,
+    ) -> Result<(), StoreError> {
+        let store = self.clone();
+        let count = self
+            .with_conn(move |conn, _| {
+                let layout = store.layout(conn, site)?;
+                crate::deferred_index::defer(conn, &layout).map_err(Into::into)
+            })
+            .await?;
+        if count > 0 {
+            info!(logger, "Dropped attribute indexes until the subgraph catches up with the chain head";
+                "indexes" => count);
+        }
+        Ok(())
+    }
+
+    /// Recreate the indexes of `site` that `defer_indexes` dropped. The
+    /// indexes are created in the background; return `true` once all of
+    /// them have been recreated. If recreating them fails, calling this
+    /// again after `INDEX_RESTORE_RETRY` starts over
+    pub(crate) async fn restore_deferred_indexes(
+        self: &Arc<Self>,
+        logger: &Logger,
+        site: Arc<Site>,
+    ) -> Result<bool, StoreError> {
+        match self.index_restores.lock().unwrap().get(&site.id) {
+            // Already restoring indexes for this deployment
+            Some(None) => return Ok(false),
+            Some(Some(failed_at)) if failed_at.elapsed() < INDEX_RESTORE_RETRY => return Ok(false),
+            Some(Some(_)) | None => {}
+        }
+
+        let site2 = site.cheap_clone();
+        let pending = self
+            .with_conn(move |conn, _| {
+                crate::deferred_index::pending(conn, &site2).map_err(Into::into)
+            })
+            .await?;
+        if pending == 0 {
+            self.index_restores.lock().unwrap().remove(&site.id);
+            return Ok(true);
+        }
+        {
+            let mut restores = self.index_restores.lock().unwrap();
+            if let Some(None) = restores.get(&site.id) {
+                return Ok(false);
+            }
+            restores.insert(site.id, None);
+        }
+
+        let store = self.cheap_clone();
+        let logger = logger.cheap_clone();
+        graph::spawn(async move {
+            let id = site.id;
+            let logger2 = logger.cheap_clone();
+            let res = store
+                .with_conn(move |conn, _| {
+                    crate::deferred_index::restore(conn, &logger2, &site).map_err(Into::into)
+                })
+                .await;
+            let mut restores = store.index_restores.lock().unwrap();
+            match res {
+                Ok(()) => {
+                    restores.remove(&id);
+                }
+                Err(e) => {
+                    warn!(logger, "Failed to recreate deferred indexes, will retry";
+                          "error" => e.to_string(),
+                          "retry_s" => INDEX_RESTORE_RETRY.as_secs());
+                    restores.insert(id, Some(Instant::now()));
+                }
+            }
+        });
+        Ok(false)
+    }
+
     /// Returns a list of all existing indexes for the specified Entity table.
     pub(crate) async fn indexes_for_entity(
         &self,
@@ -746,6 +1198,78 @@ impl DeploymentStore {
         .await
     }
 
+    /// Returns the indexes on the table for the specified Entity, with
+    /// their validity and the progress of builds that are underway.
+    pub(crate) async fn entity_indexes(
+        &self,
+        site: Arc<Site>,
+        entity_name: &str,
+    ) -> Result<Vec<EntityIndex>, StoreError> {
+        let store = self.clone();
+        let entity_name = entity_name.to_owned();
+        self.with_conn(move |conn, _| {
+            let schema_name = site.namespace.clone();
+            let layout = store.layout(conn, site)?;
+            let table = resolve_table_name(&layout, &entity_name)?;
+            catalog::entity_indexes(conn, schema_name.as_str(), table.name.as_str())
+                .map_err(Into::into)
+        })
+        .await
+    }
+
+    /// Return the statistics for the tables of all entity types in `site`
+    pub(crate) async fn deployment_stats(
+        &self,
+        site: Arc<Site>,
+    ) -> Result<Vec<EntityTypeStats>, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, _| {
+            let layout = store.layout(conn, site.cheap_clone())?;
+            let mut stats: HashMap<_, _> = catalog::stats(conn, &site)?
+                .into_iter()
+                .map(|stats| (stats.tablename.clone(), stats))
+                .collect();
+            let sizes = catalog::table_sizes(conn, &site.namespace)?;
+
+            let mut stats: Vec<_> = layout
+                .tables
+                .values()
+                .map(|table| {
+                    let name = table.name.as_str();
+                    let version_stats = stats.remove(name);
+                    let (table_size, index_size) = sizes.get(name).copied().unwrap_or((0, 0));
+                    EntityTypeStats {
+                        entity_type: table.object.to_string(),
+                        table: name.to_string(),
+                        entities: version_stats.as_ref().map(|s| s.entities).unwrap_or(0),
+                        versions: version_stats.as_ref().map(|s| s.versions).unwrap_or(0),
+                        table_size,
+                        index_size,
+                        last_pruned_block: version_stats.and_then(|s| s.last_pruned_block),
+                    }
+                })
+                .collect();
+            stats.sort_by(|a, b| a.entity_type.cmp(&b.entity_type));
+            Ok(stats)
+        })
+        .await
+    }
+
+    /// Suggest indexes for the deployment based on the query patterns
+    /// that were recorded for it at least `min_count` times
+    pub(crate) async fn suggest_indexes(
+        &self,
+        site: Arc<Site>,
+        min_count: i64,
+    ) -> Result<Vec<IndexSuggestion>, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, _| {
+            let layout = store.layout(conn, site.cheap_clone())?;
+            index_advisor::suggestions(conn, &site, &layout, min_count).map_err(Into::into)
+        })
+        .await
+    }
+
     /// Drops an index for a given deployment, concurrently.
     pub(crate) async fn drop_index(
         &self,
@@ -818,24 +1342,36 @@ impl DeploymentStore {
             cancel.check_cancel()?;
             let state = deployment::state(&mut conn, site.deployment.clone())?;
 
-            if state.latest_block.number <= req.history_blocks {
-                // We haven't accumulated enough history yet, nothing to prune
-                return Ok(reporter);
-            }
+            // Entity types that keep less history than the deployment need
+            // to be pruned even if the deployment as a whole does not
+            let prunes_tables = layout.min_history_blocks() < layout.history_blocks;
 
-            if state.earliest_block_number > req.earliest_block {
-                // We already have less history than we need (e.g., because
-                // of a manual onetime prune), nothing to prune
-                return Ok(reporter);
-            }
+            let prunes_history = if state.latest_block.number <= layout.min_history_blocks() {
+                // We haven't accumulated enough history yet
+                false
+            } else {
+                // If we already have less history than we need (e.g.,
+                // because of a manual onetime prune), there is nothing to
+                // prune for the deployment as a whole
+                state.earliest_block_number <= req.earliest_block || prunes_tables
+            };
 
-            conn.transaction(|conn| {
-                deployment::set_earliest_block(conn, site.as_ref(), req.earliest_block)
-            })?;
+            if prunes_history {
+                if state.earliest_block_number <= req.earliest_block {
+                    conn.transaction(|conn| {
+                        deployment::set_earliest_block(conn, site.as_ref(), req.earliest_block)
+                    })?;
+                }
 
-            cancel.check_cancel()?;
+                cancel.check_cancel()?;
+
+                layout.prune(&store.logger, reporter.as_mut(), &mut conn, &req, cancel)?;
+            }
 
-            layout.prune(&store.logger, reporter.as_mut(), &mut conn, &req, cancel)?;
+            // Entity types with a time-to-live are pruned no matter how
+            // much history the deployment keeps
+            cancel.check_cancel()?;
+            layout.prune_expired(&store.logger, reporter.as_mut(), &mut conn, &req, cancel)?;
             Ok(reporter)
         }
 
@@ -854,6 +1390,57 @@ impl DeploymentStore {
         })
         .await
     }
+
+    /// Move entity versions whose block range ended at or before `block`
+    /// into archive tables. See `Layout::archive`
+    pub(crate) async fn archive(
+        self: &Arc<Self>,
+        site: Arc<Site>,
+        block: BlockNumber,
+        lz4: bool,
+    ) -> Result<Vec<(String, usize)>, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, cancel| {
+            let layout = store.layout(conn, site.clone())?;
+            // Archiving and pruning both rewrite old versions; use the
+            // same lock so that they never run at the same time
+            if !advisory_lock::try_lock_pruning(conn, &site)? {
+                return Err(CancelableError::from(constraint_violation!(
+                    "sgd{} is being pruned or archived by another process",
+                    site.id
+                )));
+            }
+            let res = layout.archive(&store.logger, conn, block, lz4, cancel);
+            advisory_lock::unlock_pruning(conn, &site)?;
+            res
+        })
+        .await
+    }
+
+    pub(crate) async fn rollup_backfill(
+        self: &Arc<Self>,
+        site: Arc<Site>,
+        batch_size: usize,
+        mut reporter: Box<dyn RollupBackfillReporter>,
+    ) -> Result<usize, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, cancel| {
+            let layout = store.layout(conn, site.clone())?;
+            // Only buckets that the deployment has completely rolled up
+            // already can be missing; later ones are filled by indexing
+            let end = match deployment::block_ptr(conn, &site.deployment)? {
+                Some(head) => layout.block_time(conn, head.number)?,
+                None => None,
+            };
+            match end {
+                Some(end) => {
+                    layout.rollup_backfill(conn, end, batch_size, reporter.as_mut(), cancel)
+                }
+                None => Ok(0),
+            }
+        })
+        .await
+    }
 }
 
 /// Methods that back the trait `graph::components::Store`, but have small
@@ -870,6 +1457,18 @@ impl DeploymentStore {
         .await
     }
 
+    /// Like `block_ptr`, but for callers that can not wait asynchronously,
+    /// like host functions
+    pub(crate) fn block_ptr_sync(&self, site: Arc<Site>) -> Result<Option<BlockPtr>, StoreError> {
+        let mut conn = self.get_conn()?;
+        Self::block_ptr_with_conn(&mut conn, site)
+    }
+
+    pub(crate) fn earliest_block(&self, site: Arc<Site>) -> Result<BlockNumber, StoreError> {
+        let mut conn = self.get_conn()?;
+        deployment::earliest_block(&mut conn, &site)
+    }
+
     pub(crate) async fn block_cursor(&self, site: Arc<Site>) -> Result<FirehoseCursor, StoreError> {
         let site = site.cheap_clone();
 
@@ -1021,8 +1620,19 @@ impl DeploymentStore {
         block: BlockNumber,
     ) -> Result<Option<Entity>, StoreError> {
         let mut conn = self.get_conn()?;
-        let layout = self.layout(&mut conn, site)?;
-        layout.find(&mut conn, key, block)
+        let layout = self.layout(&mut conn, site.cheap_clone())?;
+        let statement_caching = if ENV_VARS.store.cache_prepared_lookups {
+            "enabled"
+        } else {
+            "disabled"
+        };
+        self.entity_lookups
+            .with_label_values(&[self.pool.shard.as_str(), statement_caching])
+            .inc();
+        let start = Instant::now();
+        let entity = layout.find(&mut conn, key, block)?;
+        self.observe_query(&site, QueryShape::Find, start, entity.iter().count());
+        Ok(entity)
     }
 
     /// Retrieve all the entities matching `ids_for_type`, both the type and causality region, from
@@ -1037,9 +1647,31 @@ impl DeploymentStore {
             return Ok(BTreeMap::new());
         }
         let mut conn = self.get_conn()?;
-        let layout = self.layout(&mut conn, site)?;
+        let layout = self.layout(&mut conn, site.cheap_clone())?;
 
-        layout.find_many(&mut conn, ids_for_type, block)
+        let start = Instant::now();
+        let entities = layout.find_many(&mut conn, ids_for_type, block)?;
+        self.observe_query(&site, QueryShape::FindMany, start, entities.len());
+        Ok(entities)
+    }
+
+    pub(crate) async fn find_recent(
+        &self,
+        site: Arc<Site>,
+        entity_type: EntityType,
+        count: usize,
+        block: BlockNumber,
+    ) -> Result<Vec<EntityKey>, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, cancel| {
+            cancel.check_cancel()?;
+            let layout = store.layout(conn, site.cheap_clone())?;
+            let start = Instant::now();
+            let keys = layout.find_recent(conn, &entity_type, count, block)?;
+            store.observe_query(&site, QueryShape::FindRecent, start, keys.len());
+            Ok(keys)
+        })
+        .await
     }
 
     pub(crate) fn get_derived(
@@ -1050,8 +1682,11 @@ impl DeploymentStore {
         excluded_keys: &Vec<EntityKey>,
     ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
         let mut conn = self.get_conn()?;
-        let layout = self.layout(&mut conn, site)?;
-        layout.find_derived(&mut conn, derived_query, block, excluded_keys)
+        let layout = self.layout(&mut conn, site.cheap_clone())?;
+        let start = Instant::now();
+        let entities = layout.find_derived(&mut conn, derived_query, block, excluded_keys)?;
+        self.observe_query(&site, QueryShape::FindDerived, start, entities.len());
+        Ok(entities)
     }
 
     pub(crate) fn get_changes(
@@ -1066,6 +1701,25 @@ impl DeploymentStore {
         Ok(changes)
     }
 
+    pub(crate) async fn get_changes_in_range(
+        &self,
+        site: Arc<Site>,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<(BlockNumber, Vec<EntityOperation>)>, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, cancel| {
+            let layout = store.layout(conn, site)?;
+            let mut changes = Vec::new();
+            for block in from..=to {
+                cancel.check_cancel()?;
+                changes.push((block, layout.find_changes(conn, block)?));
+            }
+            Ok(changes)
+        })
+        .await
+    }
+
     // Only used by tests
     #[cfg(debug_assertions)]
     pub(crate) fn find(
@@ -1103,6 +1757,8 @@ impl DeploymentStore {
                 // Make the changes
                 let layout = self.layout(conn, site.clone())?;
 
+                layout.create_partitions(conn, batch.first_block, batch.block_ptr.number)?;
+
                 let section = stopwatch.start_section("apply_entity_modifications");
                 let count = self.apply_entity_modifications(
                     conn,
@@ -1148,9 +1804,26 @@ impl DeploymentStore {
             })
         })?;
 
-        if batch.block_ptr.number as f64
-            > earliest_block as f64
-                + layout.history_blocks as f64 * ENV_VARS.store.history_slack_factor
+        let slack = ENV_VARS.store.history_slack_factor;
+        let min_history_blocks = layout.min_history_blocks();
+        let last_prune = self.prune_blocks.lock().unwrap().get(&site.id).copied();
+        let entity_prune_due = min_history_blocks < layout.history_blocks
+            && match last_prune {
+                Some(last_prune) => {
+                    batch.block_ptr.number as f64
+                        > last_prune as f64 + min_history_blocks as f64 * (slack - 1.0)
+                }
+                None => batch.block_ptr.number > earliest_block + min_history_blocks,
+            };
+        // Expired versions are deleted whenever the shortest time-to-live
+        // has passed since we last pruned
+        let expire_due = layout.min_ttl().map_or(false, |ttl| {
+            batch.block_ptr.number > last_prune.unwrap_or(earliest_block) + ttl
+        });
+        if entity_prune_due
+            || expire_due
+            || batch.block_ptr.number as f64
+                > earliest_block as f64 + layout.history_blocks as f64 * slack
         {
             // This only measures how long it takes to spawn pruning, not
             // how long pruning itself takes
@@ -1238,6 +1911,10 @@ impl DeploymentStore {
             )?;
 
             let deployment_id = site.id;
+            self.prune_blocks
+                .lock()
+                .unwrap()
+                .insert(deployment_id, latest_block);
             let handle = graph::spawn(run(logger.cheap_clone(), self.clone(), site, req));
             self.prune_handles
                 .lock()
@@ -1408,12 +2085,57 @@ impl DeploymentStore {
         Ok(())
     }
 
+    pub(crate) async fn set_last_healthy_block(
+        &self,
+        id: DeploymentHash,
+        block_ptr: BlockPtr,
+    ) -> Result<(), StoreError> {
+        self.with_conn(move |conn, _| {
+            deployment::set_last_healthy_block(conn, &id, &block_ptr).map_err(Into::into)
+        })
+        .await
+    }
+
+    pub(crate) async fn record_skipped_triggers(
+        &self,
+        site: Arc<Site>,
+        triggers: Vec<SkippedTrigger>,
+    ) -> Result<(), StoreError> {
+        self.with_conn(move |conn, _| {
+            crate::skipped_trigger::insert(conn, &site, &triggers).map_err(Into::into)
+        })
+        .await
+    }
+
+    pub(crate) async fn checkpoint_entity_cache(
+        &self,
+        site: Arc<Site>,
+        keys: Vec<EntityKey>,
+    ) -> Result<(), StoreError> {
+        self.with_conn(move |conn, _| {
+            crate::cache_checkpoint::save(conn, &site, &keys).map_err(Into::into)
+        })
+        .await
+    }
+
+    pub(crate) fn entity_cache_checkpoint(
+        &self,
+        site: Arc<Site>,
+    ) -> Result<Vec<EntityKey>, StoreError> {
+        let mut conn = self.get_conn()?;
+        let layout = self.layout(&mut conn, site.cheap_clone())?;
+        crate::cache_checkpoint::load(&mut conn, &site, &layout.input_schema)
+    }
+
+    pub(crate) fn skipped_triggers(&self, site: &Site) -> Result<Vec<SkippedTrigger>, StoreError> {
+        let mut conn = self.get_conn()?;
+        crate::skipped_trigger::load(&mut conn, site)
+    }
+
     pub(crate) fn replica_for_query(
         &self,
         for_subscription: bool,
     ) -> Result<ReplicaId, StoreError> {
-        use std::sync::atomic::Ordering;
-
         let replica_id = match for_subscription {
             // Pick a weighted ReplicaId. `replica_order` contains a list of
             // replicas with repetitions according to their weight
@@ -2015,3 +2737,53 @@ impl PruneReporter for OngoingPruneReporter {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::DeploymentHash;
+
+    use crate::primary::{make_dummy_site, Namespace};
+
+    use super::*;
+
+    fn site(name: &str, id: i32) -> Site {
+        let mut site = make_dummy_site(
+            DeploymentHash::new(name).unwrap(),
+            Namespace::new(format!("sgd{}", id)).unwrap(),
+            "mainnet".to_string(),
+        );
+        site.id = DeploymentId::from(graph::components::store::DeploymentId::new(id));
+        site
+    }
+
+    #[test]
+    fn replica_heads() {
+        let heads = ReplicaHeads::new();
+        let site1 = site("replica1", 1);
+        let site2 = site("replica2", 2);
+
+        // Nothing is known about the replica yet
+        assert!(!heads.caught_up(&site1, 0));
+
+        // The deployment has not processed any blocks in the replica
+        heads.set(&site1, None);
+        assert!(!heads.caught_up(&site1, 0));
+
+        heads.set(&site1, Some(10));
+        assert!(heads.caught_up(&site1, 0));
+        assert!(heads.caught_up(&site1, 10));
+        assert!(!heads.caught_up(&site1, 11));
+        // Heads are tracked per deployment
+        assert!(!heads.caught_up(&site2, 0));
+
+        // Once the head is too old, we need to check it again
+        if let Some(checked_at) = Instant::now().checked_sub(REPLICA_HEAD_TTL * 2) {
+            heads
+                .heads
+                .lock()
+                .unwrap()
+                .insert(site1.id, (Some(10), checked_at));
+            assert!(!heads.caught_up(&site1, 0));
+        }
+    }
+}
@@ -194,6 +194,8 @@ pub(crate) fn info_from_details(
         earliest_block_number,
         latest_ethereum_block_hash,
         latest_ethereum_block_number,
+        last_healthy_ethereum_block_hash,
+        last_healthy_ethereum_block_number,
         entity_count,
         graft_base: _,
         graft_block_hash: _,
@@ -215,12 +217,19 @@ pub(crate) fn info_from_details(
         latest_ethereum_block_hash,
         latest_ethereum_block_number,
     )?;
+    let last_healthy_block = block(
+        &deployment,
+        "last_healthy_ethereum_block",
+        last_healthy_ethereum_block_hash,
+        last_healthy_ethereum_block_number,
+    )?;
     let health = health.into();
     let chain = status::ChainInfo {
         network: site.network.clone(),
         chain_head_block,
         earliest_block_number,
         latest_block,
+        last_healthy_block,
     };
     let entity_count = entity_count.to_u64().ok_or_else(|| {
         constraint_violation!(
@@ -241,12 +250,14 @@ pub(crate) fn info_from_details(
         synced,
         health,
         paused: None,
+        priority: None,
         fatal_error,
         non_fatal_errors,
         chains: vec![chain],
         entity_count,
         node: None,
         history_blocks: subgraph_history_blocks,
+        handler_stats: vec![],
     })
 }
 
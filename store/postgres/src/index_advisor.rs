@@ -0,0 +1,480 @@
+//! Record which attributes queries filter and order by so that we can
+//! suggest indexes that would help them. Query nodes count these patterns
+//! in memory and periodically add the counts to the
+//! `subgraphs.query_patterns` table in the deployment's shard, where
+//! `graphman index suggest` picks them up
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use diesel::{
+    sql_query,
+    sql_types::{Array, BigInt, Integer, Text},
+    Connection, PgConnection, RunQueryDsl,
+};
+use graph::components::store::{EntityCollection, EntityFilter, EntityLink, EntityOrder};
+use graph::prelude::{EntityQuery, StoreError};
+
+use crate::primary::{DeploymentId, Site};
+use crate::relational::{index::CreateIndex, index::Expr, index::Method, Layout, Table};
+
+/// How often the patterns recorded in memory are written to the database
+const FLUSH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The attributes that queries against one entity type filter and order
+/// by, together with how often such queries were run
+#[derive(Clone, Debug, PartialEq, Eq, Hash, QueryableByName)]
+pub struct QueryPattern {
+    #[diesel(sql_type = Text)]
+    pub entity: String,
+    /// The attributes used in filters, sorted and without duplicates
+    #[diesel(sql_type = Array<Text>)]
+    pub filters: Vec<String>,
+    /// The attributes used for sorting
+    #[diesel(sql_type = Array<Text>)]
+    pub sort: Vec<String>,
+}
+
+impl QueryPattern {
+    /// The patterns for `query`, one for each entity type the query
+    /// touches and each alternative in its filter. An index on several
+    /// attributes does not help a filter that combines them with `or`,
+    /// and each branch of an `or` therefore leads to its own pattern.
+    /// Patterns that existing indexes always cover, like ones that only
+    /// sort by `id`, are left out
+    fn for_query(query: &EntityQuery) -> Vec<QueryPattern> {
+        /// The sets of attributes that the alternatives of `filter` use.
+        /// There are at most `MAX_ALTERNATIVES` of them
+        fn filter_attrs(filter: &EntityFilter) -> Vec<Vec<String>> {
+            use EntityFilter::*;
+
+            const MAX_ALTERNATIVES: usize = 8;
+
+            match filter {
+                And(filters) => filters.iter().fold(vec![vec![]], |alternatives, filter| {
+                    let branches = filter_attrs(filter);
+                    alternatives
+                        .iter()
+                        .flat_map(|attrs| {
+                            branches.iter().map(move |branch| {
+                                attrs.iter().chain(branch.iter()).cloned().collect()
+                            })
+                        })
+                        .take(MAX_ALTERNATIVES)
+                        .collect()
+                }),
+                Or(filters) => filters
+                    .iter()
+                    .flat_map(filter_attrs)
+                    .take(MAX_ALTERNATIVES)
+                    .collect(),
+                Equal(attr, _)
+                | Not(attr, _)
+                | GreaterThan(attr, _)
+                | LessThan(attr, _)
+                | GreaterOrEqual(attr, _)
+                | LessOrEqual(attr, _)
+                | In(attr, _)
+                | NotIn(attr, _)
+                | Contains(attr, _)
+                | ContainsNoCase(attr, _)
+                | NotContains(attr, _)
+                | NotContainsNoCase(attr, _)
+                | StartsWith(attr, _)
+                | StartsWithNoCase(attr, _)
+                | NotStartsWith(attr, _)
+                | NotStartsWithNoCase(attr, _)
+                | EndsWith(attr, _)
+                | EndsWithNoCase(attr, _)
+                | NotEndsWith(attr, _)
+                | NotEndsWithNoCase(attr, _)
+                | MatchesNoCase(attr, _)
+                | Regex(attr, _)
+                | RegexNoCase(attr, _) => vec![vec![attr.clone()]],
+                Spatial(lat, lon, _) => vec![vec![lat.clone(), lon.clone()]],
+                // Fulltext searches have their own index, the other
+                // filters are about other tables or the block range
+                ChangeBlockGte(_) | Child(_) | Fulltext(..) => vec![vec![]],
+            }
+        }
+
+        let alternatives = match &query.filter {
+            Some(filter) => filter_attrs(filter),
+            None => vec![vec![]],
+        };
+        let sort = match &query.order {
+            EntityOrder::Ascending(attr, _) | EntityOrder::Descending(attr, _) => {
+                vec![attr.clone()]
+            }
+            EntityOrder::ChildAscending(_)
+            | EntityOrder::ChildDescending(_)
+            | EntityOrder::Default
+            | EntityOrder::Unordered => vec![],
+        };
+
+        let entities: Vec<_> = match &query.collection {
+            EntityCollection::All(types) => types
+                .iter()
+                .map(|(entity_type, _)| (entity_type.to_string(), None))
+                .collect(),
+            EntityCollection::Window(windows) => windows
+                .iter()
+                .map(|window| {
+                    let attr = match &window.link {
+                        EntityLink::Direct(attr, _) => Some(attr.name().to_string()),
+                        EntityLink::Parent(_, _) => None,
+                    };
+                    (window.child_type.to_string(), attr)
+                })
+                .collect(),
+        };
+
+        let mut patterns = Vec::new();
+        for (entity, window_attr) in entities {
+            for attrs in &alternatives {
+                let mut filters = attrs.clone();
+                filters.extend(window_attr.clone());
+                filters.sort();
+                filters.dedup();
+                let pattern = QueryPattern {
+                    entity: entity.clone(),
+                    filters,
+                    sort: sort.clone(),
+                };
+                if (!pattern.filters.is_empty() || !pattern.sort.is_empty())
+                    && !patterns.contains(&pattern)
+                {
+                    patterns.push(pattern);
+                }
+            }
+        }
+        patterns
+    }
+}
+
+/// Counts of query patterns that have not been written to the database
+/// yet
+pub(crate) struct QueryPatterns {
+    counts: Mutex<(HashMap<(DeploymentId, QueryPattern), i64>, Instant)>,
+}
+
+impl QueryPatterns {
+    pub(crate) fn new() -> Self {
+        Self {
+            counts: Mutex::new((HashMap::new(), Instant::now())),
+        }
+    }
+
+    /// Count the patterns of `query` against the deployment `site`
+    pub(crate) fn record(&self, site: &Site, query: &EntityQuery) {
+        let patterns = QueryPattern::for_query(query);
+        if patterns.is_empty() {
+            return;
+        }
+        let mut counts = self.counts.lock().unwrap();
+        for pattern in patterns {
+            *counts.0.entry((site.id, pattern)).or_default() += 1;
+        }
+    }
+
+    /// If it has been long enough since the last time, take the counts
+    /// that should be written to the database
+    pub(crate) fn take_if_due(&self) -> Option<HashMap<(DeploymentId, QueryPattern), i64>> {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.0.is_empty() || counts.1.elapsed() < FLUSH_INTERVAL {
+            return None;
+        }
+        counts.1 = Instant::now();
+        Some(std::mem::take(&mut counts.0))
+    }
+}
+
+/// Add `counts` to the counts in the database
+pub(crate) fn flush(
+    conn: &mut PgConnection,
+    counts: HashMap<(DeploymentId, QueryPattern), i64>,
+) -> Result<(), StoreError> {
+    const QUERY: &str = "
+        insert into subgraphs.query_patterns(deployment, entity, filters, sort, count)
+        values ($1, $2, $3, $4, $5)
+        on conflict(deployment, entity, filters, sort)
+        do update set count = query_patterns.count + excluded.count,
+                      last_seen = now()";
+
+    conn.transaction::<_, StoreError, _>(|conn| {
+        for ((deployment, pattern), count) in counts {
+            sql_query(QUERY)
+                .bind::<Integer, _>(deployment)
+                .bind::<Text, _>(&pattern.entity)
+                .bind::<Array<Text>, _>(&pattern.filters)
+                .bind::<Array<Text>, _>(&pattern.sort)
+                .bind::<BigInt, _>(count)
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+/// An index that would help queries that were run `count` times
+#[derive(Debug)]
+pub struct IndexSuggestion {
+    pub entity: String,
+    /// The GraphQL names of the fields to index
+    pub fields: Vec<String>,
+    pub method: Method,
+    pub count: i64,
+}
+
+impl fmt::Display for IndexSuggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) using {}",
+            self.entity,
+            self.fields.join(", "),
+            self.method
+        )
+    }
+}
+
+/// Return `true` if one of the `indexes` starts with `columns`
+fn is_covered(indexes: &[CreateIndex], method: &Method, columns: &[&str]) -> bool {
+    indexes.iter().any(|index| match index {
+        CreateIndex::Parsed {
+            method: index_method,
+            columns: index_columns,
+            ..
+        } => {
+            let usable = match method {
+                Method::Gin => index_method == &Method::Gin,
+                _ => index_method != &Method::Brin && index_method != &Method::Gin,
+            };
+            usable
+                && index_columns.len() >= columns.len()
+                && index_columns.iter().zip(columns).all(|(expr, column)| {
+                    matches!(expr, Expr::Column(name) | Expr::Prefix(name, _) if name == column)
+                })
+        }
+        CreateIndex::Unknown { .. } => false,
+    })
+}
+
+/// Suggest indexes for `table` that would help queries with `pattern`
+/// and that are not covered by the `existing` indexes. Scalar attributes
+/// are combined into one btree index, list attributes get a GIN index
+/// each
+fn suggest(
+    table: &Table,
+    pattern: &QueryPattern,
+    count: i64,
+    existing: &[CreateIndex],
+) -> Vec<IndexSuggestion> {
+    let mut scalars = Vec::new();
+    let mut lists = Vec::new();
+    for field in pattern.filters.iter().chain(pattern.sort.iter()) {
+        let Ok(column) = table.column_for_field(field) else {
+            // Queries against interfaces can use attributes that only
+            // some of the implementing types have
+            continue;
+        };
+        if column.is_fulltext() || column.is_primary_key() {
+            continue;
+        }
+        let entry = (field.clone(), column.name.as_str());
+        if column.is_list() {
+            lists.push(entry);
+        } else if !scalars.contains(&entry) {
+            scalars.push(entry);
+        }
+    }
+
+    let mut suggestions = Vec::new();
+    let columns: Vec<_> = scalars.iter().map(|(_, column)| *column).collect();
+    if !columns.is_empty() && !is_covered(existing, &Method::BTree, &columns) {
+        suggestions.push(IndexSuggestion {
+            entity: pattern.entity.clone(),
+            fields: scalars.into_iter().map(|(field, _)| field).collect(),
+            method: Method::BTree,
+            count,
+        });
+    }
+    for (field, column) in lists {
+        if !is_covered(existing, &Method::Gin, &[column]) {
+            suggestions.push(IndexSuggestion {
+                entity: pattern.entity.clone(),
+                fields: vec![field],
+                method: Method::Gin,
+                count,
+            });
+        }
+    }
+    suggestions
+}
+
+/// Suggest indexes for the deployment `site` based on the query patterns
+/// that were recorded for it at least `min_count` times. The suggestions
+/// are sorted by how often they would have helped, most often first
+pub(crate) fn suggestions(
+    conn: &mut PgConnection,
+    site: &Site,
+    layout: &Layout,
+    min_count: i64,
+) -> Result<Vec<IndexSuggestion>, StoreError> {
+    #[derive(QueryableByName)]
+    struct Row {
+        #[diesel(embed)]
+        pattern: QueryPattern,
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+
+    let rows = sql_query(
+        "select entity, filters, sort, count
+           from subgraphs.query_patterns
+          where deployment = $1 and count >= $2",
+    )
+    .bind::<Integer, _>(site.id)
+    .bind::<BigInt, _>(min_count)
+    .load::<Row>(conn)?;
+
+    let mut indexes: HashMap<&str, Vec<CreateIndex>> = HashMap::new();
+    let mut suggestions: Vec<IndexSuggestion> = Vec::new();
+    for Row { pattern, count } in rows {
+        let Some(table) = layout
+            .input_schema
+            .entity_type(&pattern.entity)
+            .ok()
+            .and_then(|entity_type| layout.table_for_entity(&entity_type).ok())
+        else {
+            continue;
+        };
+        if !indexes.contains_key(table.name.as_str()) {
+            let existing = crate::catalog::indexes_for_table(
+                conn,
+                site.namespace.as_str(),
+                table.name.as_str(),
+            )?
+            .into_iter()
+            .map(CreateIndex::parse)
+            .collect();
+            indexes.insert(table.name.as_str(), existing);
+        }
+        for suggestion in suggest(table, &pattern, count, &indexes[table.name.as_str()]) {
+            // Several patterns can lead to the same suggestion
+            match suggestions.iter_mut().find(|other| {
+                other.entity == suggestion.entity
+                    && other.fields == suggestion.fields
+                    && other.method == suggestion.method
+            }) {
+                Some(other) => other.count += suggestion.count,
+                None => suggestions.push(suggestion),
+            }
+        }
+    }
+    suggestions.sort_by(|a, b| b.count.cmp(&a.count));
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::components::store::{ChildMultiplicity, EntityWindow, WindowAttribute};
+    use graph::data::store::{IdList, IdType};
+    use graph::prelude::{AttributeNames, DeploymentHash, Value, ValueType};
+    use graph::schema::InputSchema;
+
+    use super::*;
+
+    const SCHEMA: &str = "
+        type Token @entity { id: ID!, owner: Bytes!, symbol: String!, amount: BigInt! }
+        type Transfer @entity { id: ID!, token: Token!, amount: BigInt! }";
+
+    #[test]
+    fn query_patterns() {
+        let id = DeploymentHash::new("QmPatterns").unwrap();
+        let schema = InputSchema::parse_latest(SCHEMA, id.clone()).unwrap();
+        let token = schema.entity_type("Token").unwrap();
+        let transfer = schema.entity_type("Transfer").unwrap();
+
+        let query = EntityQuery::new(
+            id.clone(),
+            1,
+            EntityCollection::All(vec![(token.clone(), AttributeNames::All)]),
+        )
+        .filter(EntityFilter::And(vec![
+            EntityFilter::Equal("symbol".to_string(), Value::from("GRT")),
+            EntityFilter::Equal("owner".to_string(), Value::from("0x01")),
+            EntityFilter::Not("symbol".to_string(), Value::from("")),
+        ]))
+        .order(EntityOrder::Descending(
+            "amount".to_string(),
+            ValueType::BigInt,
+        ));
+        assert_eq!(
+            vec![QueryPattern {
+                entity: "Token".to_string(),
+                filters: vec!["owner".to_string(), "symbol".to_string()],
+                sort: vec!["amount".to_string()],
+            }],
+            QueryPattern::for_query(&query)
+        );
+
+        // Each branch of an `or` is its own pattern
+        let query = EntityQuery::new(
+            id.clone(),
+            1,
+            EntityCollection::All(vec![(token.clone(), AttributeNames::All)]),
+        )
+        .filter(EntityFilter::And(vec![
+            EntityFilter::Equal("amount".to_string(), Value::from(1)),
+            EntityFilter::Or(vec![
+                EntityFilter::Equal("symbol".to_string(), Value::from("GRT")),
+                EntityFilter::Equal("owner".to_string(), Value::from("0x01")),
+                EntityFilter::Equal("symbol".to_string(), Value::from("ETH")),
+            ]),
+        ]));
+        assert_eq!(
+            vec![
+                QueryPattern {
+                    entity: "Token".to_string(),
+                    filters: vec!["amount".to_string(), "symbol".to_string()],
+                    sort: vec![],
+                },
+                QueryPattern {
+                    entity: "Token".to_string(),
+                    filters: vec!["amount".to_string(), "owner".to_string()],
+                    sort: vec![],
+                }
+            ],
+            QueryPattern::for_query(&query)
+        );
+
+        // Queries for all entities in id order are always covered
+        let query = EntityQuery::new(
+            id.clone(),
+            1,
+            EntityCollection::All(vec![(token, AttributeNames::All)]),
+        );
+        assert!(QueryPattern::for_query(&query).is_empty());
+
+        // Windows filter by the attribute that links them to the parent
+        let window = EntityWindow {
+            child_type: transfer,
+            ids: IdList::new(IdType::String),
+            link: EntityLink::Direct(
+                WindowAttribute::Scalar("token".to_string()),
+                ChildMultiplicity::Many,
+            ),
+            column_names: AttributeNames::All,
+        };
+        let query = EntityQuery::new(id, 1, EntityCollection::Window(vec![window]));
+        assert_eq!(
+            vec![QueryPattern {
+                entity: "Transfer".to_string(),
+                filters: vec!["token".to_string()],
+                sort: vec![],
+            }],
+            QueryPattern::for_query(&query)
+        );
+    }
+}
@@ -6,12 +6,12 @@ use std::time::{Duration, Instant};
 use async_trait::async_trait;
 use diesel::{prelude::RunQueryDsl, sql_query, sql_types::Double};
 
-use graph::prelude::{error, Logger, MetricsRegistry, StoreError, ENV_VARS};
+use graph::prelude::{error, info, Logger, MetricsRegistry, StoreError, ENV_VARS};
 use graph::prometheus::Gauge;
 use graph::util::jobs::{Job, Runner};
 
 use crate::connection_pool::ConnectionPool;
-use crate::{unused, Store, SubgraphStore};
+use crate::{unused, BlockStore, Store, SubgraphStore};
 
 pub fn register(
     runner: &mut Runner,
@@ -27,6 +27,11 @@ pub fn register(
         ONE_MINUTE,
     );
 
+    runner.register(
+        Arc::new(AnalyzeChurnedTablesJob::new(store.subgraph_store())),
+        ONE_MINUTE,
+    );
+
     runner.register(
         Arc::new(NotificationQueueUsage::new(primary_pool, registry)),
         ONE_MINUTE,
@@ -47,6 +52,20 @@ pub fn register(
         Arc::new(RefreshMaterializedView::new(store.subgraph_store())),
         6 * ONE_HOUR,
     );
+
+    if let Some(max_bytes) = ENV_VARS.store.call_cache_max_bytes {
+        runner.register(
+            Arc::new(EvictCallCacheJob::new(store.block_store(), max_bytes)),
+            10 * ONE_MINUTE,
+        );
+    }
+
+    if ENV_VARS.store.block_archive_url.is_some() {
+        runner.register(
+            Arc::new(ArchiveBlocksJob::new(store.block_store())),
+            10 * ONE_MINUTE,
+        );
+    }
 }
 
 /// A job that vacuums `subgraphs.subgraph_deployment`. With a large number
@@ -81,6 +100,37 @@ impl Job for VacuumDeploymentsJob {
     }
 }
 
+/// A job that analyzes entity tables whose statistics are likely stale
+/// because indexing wrote a lot of rows to them since they were last
+/// analyzed. See `GRAPH_STORE_ANALYZE_CHURN_RATIO`
+struct AnalyzeChurnedTablesJob {
+    store: Arc<SubgraphStore>,
+}
+
+impl AnalyzeChurnedTablesJob {
+    fn new(store: Arc<SubgraphStore>) -> AnalyzeChurnedTablesJob {
+        AnalyzeChurnedTablesJob { store }
+    }
+}
+
+#[async_trait]
+impl Job for AnalyzeChurnedTablesJob {
+    fn name(&self) -> &str {
+        "Analyze entity tables with stale statistics"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        for res in self.store.analyze_churned_tables().await {
+            if let Err(e) = res {
+                error!(
+                    logger,
+                    "Analyzing tables with stale statistics failed: {}", e
+                );
+            }
+        }
+    }
+}
+
 struct NotificationQueueUsage {
     primary: ConnectionPool,
     usage_gauge: Box<Gauge>,
@@ -236,3 +286,86 @@ impl Job for UnusedJob {
         }
     }
 }
+
+/// A job that moves ancient blocks from the database into the block
+/// archive. See `GRAPH_STORE_BLOCK_ARCHIVE_URL`
+struct ArchiveBlocksJob {
+    store: Arc<BlockStore>,
+}
+
+impl ArchiveBlocksJob {
+    fn new(store: Arc<BlockStore>) -> ArchiveBlocksJob {
+        ArchiveBlocksJob { store }
+    }
+}
+
+#[async_trait]
+impl Job for ArchiveBlocksJob {
+    fn name(&self) -> &str {
+        "Move ancient blocks into the block archive"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        for (chain, res) in self.store.archive_blocks().await {
+            match res {
+                Ok(0) => { /* nothing to do */ }
+                Ok(count) => {
+                    info!(logger, "Archived blocks"; "network" => chain, "count" => count)
+                }
+                Err(e) => {
+                    error!(logger, "failed to archive blocks";
+                                   "network" => chain,
+                                   "error" => e.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// A job that keeps the call cache of each chain within the size set with
+/// `GRAPH_STORE_CALL_CACHE_MAX_BYTES` by evicting the calls that were
+/// accessed least recently
+struct EvictCallCacheJob {
+    store: Arc<BlockStore>,
+    max_bytes: u64,
+}
+
+impl EvictCallCacheJob {
+    fn new(store: Arc<BlockStore>, max_bytes: u64) -> EvictCallCacheJob {
+        EvictCallCacheJob { store, max_bytes }
+    }
+}
+
+#[async_trait]
+impl Job for EvictCallCacheJob {
+    fn name(&self) -> &str {
+        "Evict least recently used calls from the call cache"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        let store = self.store.clone();
+        let max_bytes = self.max_bytes;
+        let res =
+            graph::spawn_blocking_allow_panic(move || store.evict_call_caches(max_bytes)).await;
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                error!(logger, "call cache eviction panicked"; "error" => e.to_string());
+                return;
+            }
+        };
+        for (chain, res) in res {
+            match res {
+                Ok(0) => { /* nothing to do */ }
+                Ok(bytes) => {
+                    info!(logger, "Evicted calls from the call cache"; "network" => chain, "bytes" => bytes)
+                }
+                Err(e) => {
+                    error!(logger, "failed to evict calls from the call cache";
+                                   "network" => chain,
+                                   "error" => e.to_string());
+                }
+            }
+        }
+    }
+}
@@ -12,19 +12,23 @@ extern crate diesel_migrations;
 extern crate diesel_derive_enum;
 
 mod advisory_lock;
+mod block_archive;
 mod block_range;
 mod block_store;
+mod cache_checkpoint;
 mod catalog;
 mod chain_head_listener;
 mod chain_store;
 pub mod connection_pool;
 mod copy;
+mod deferred_index;
 mod deployment;
 mod deployment_store;
 mod detail;
 mod dynds;
 mod fork;
 mod functions;
+mod index_advisor;
 mod jobs;
 mod notification_listener;
 mod primary;
@@ -32,9 +36,11 @@ pub mod query_store;
 mod relational;
 mod relational_queries;
 mod retry;
+mod skipped_trigger;
 mod store;
 mod store_events;
 mod subgraph_store;
+mod table_churn;
 pub mod transaction_receipt;
 mod writable;
 
@@ -43,6 +49,7 @@ pub mod layout_for_tests {
     pub use crate::block_range::*;
     pub use crate::block_store::FAKE_NETWORK_SHARED;
     pub use crate::catalog::set_account_like;
+    pub use crate::chain_store::CACHE_REVERTED_CALLS;
     pub use crate::primary::{
         make_dummy_site, Connection, Mirror, Namespace, EVENT_TAP, EVENT_TAP_ENABLED,
     };
@@ -63,7 +70,9 @@ pub use self::notification_listener::NotificationSender;
 pub use self::primary::{db_version, UnusedDeployment};
 pub use self::store::Store;
 pub use self::store_events::SubscriptionManager;
-pub use self::subgraph_store::{unused, DeploymentPlacer, Shard, SubgraphStore, PRIMARY_SHARD};
+pub use self::subgraph_store::{
+    unused, DeploymentPlacer, MoveReporter, Shard, SubgraphStore, AUX_DATASET_ENS, PRIMARY_SHARD,
+};
 
 /// This module is only meant to support command line tooling. It must not
 /// be used in 'normal' graph-node code
@@ -73,12 +82,13 @@ pub mod command_support {
         pub use crate::catalog::{account_like, stats};
         pub use crate::copy::{copy_state, copy_table_state};
         pub use crate::primary::{
-            active_copies, deployment_schemas, ens_names, subgraph, subgraph_deployment_assignment,
-            subgraph_version, Site,
+            active_copies, aux_datasets, aux_pins, deployment_schemas, ens_names, subgraph,
+            subgraph_deployment_assignment, subgraph_version, Site,
         };
-        pub use crate::primary::{Connection, Mirror};
+        pub use crate::primary::{AuxDataset, Connection, Mirror};
     }
     pub mod index {
+        pub use crate::index_advisor::IndexSuggestion;
         pub use crate::relational::index::{CreateIndex, Method};
     }
     pub use crate::deployment::{on_sync, OnSync};
@@ -12,7 +12,7 @@ use diesel::{
     connection::SimpleConnection,
     data_types::PgTimestamp,
     deserialize::FromSql,
-    dsl::{exists, not, select},
+    dsl::{count_star, exists, max, not, select},
     pg::Pg,
     serialize::{Output, ToSql},
     sql_types::{Array, BigInt, Bool, Integer, Text},
@@ -40,7 +40,9 @@ use graph::{
     },
 };
 use graph::{
-    components::store::{DeploymentId as GraphDeploymentId, DeploymentSchemaVersion},
+    components::store::{
+        DeploymentId as GraphDeploymentId, DeploymentPriority, DeploymentSchemaVersion,
+    },
     prelude::{chrono, CancelHandle, CancelToken},
 };
 use graph::{data::subgraph::schema::generate_entity_id, prelude::StoreEvent};
@@ -110,6 +112,7 @@ table! {
         node_id -> Text,
         paused_at -> Nullable<Timestamptz>,
         assigned_at -> Nullable<Timestamptz>,
+        priority -> Text,
     }
 }
 
@@ -132,6 +135,40 @@ table! {
     }
 }
 
+table! {
+    public.persisted_queries(hash) {
+        hash -> Text,
+        query -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    public.aux_datasets(name, version) {
+        name -> Text,
+        version -> Integer,
+        entries -> BigInt,
+        loaded_at -> Timestamptz,
+    }
+}
+
+table! {
+    public.aux_entries(dataset, version, key) {
+        dataset -> Text,
+        version -> Integer,
+        key -> Text,
+        value -> Text,
+    }
+}
+
+table! {
+    public.aux_pins(deployment, dataset) {
+        deployment -> Text,
+        dataset -> Text,
+        version -> Integer,
+    }
+}
+
 table! {
     deployment_schemas(id) {
         id -> Integer,
@@ -231,6 +268,18 @@ pub struct UnusedDeployment {
     pub synced: bool,
 }
 
+/// One version of an auxiliary dataset that mappings can read with
+/// `aux.lookup`
+#[derive(Clone, Debug)]
+pub struct AuxDataset {
+    pub name: String,
+    pub version: i32,
+    pub entries: i64,
+    pub loaded_at: chrono::DateTime<chrono::Utc>,
+    /// The number of deployments that read from this version
+    pub deployments: i64,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
 #[diesel(sql_type = Text)]
 /// A namespace (schema) in the database
@@ -630,18 +679,47 @@ mod queries {
         let nodes: HashMap<_, _> = a::table
             .inner_join(ds::table.on(ds::id.eq(a::id)))
             .filter(ds::subgraph.eq_any(ids))
-            .select((ds::subgraph, a::node_id, a::paused_at.is_not_null()))
-            .load::<(String, String, bool)>(conn)?
+            .select((
+                ds::subgraph,
+                a::node_id,
+                a::paused_at.is_not_null(),
+                a::priority,
+            ))
+            .load::<(String, String, bool, String)>(conn)?
             .into_iter()
-            .map(|(subgraph, node, paused)| (subgraph, (node, paused)))
+            .map(|(subgraph, node, paused, priority)| (subgraph, (node, paused, priority)))
             .collect();
         for info in infos {
-            info.node = nodes.get(&info.subgraph).map(|(node, _)| node.clone());
-            info.paused = nodes.get(&info.subgraph).map(|(_, paused)| *paused);
+            let assignment = nodes.get(&info.subgraph);
+            info.node = assignment.map(|(node, _, _)| node.clone());
+            info.paused = assignment.map(|(_, paused, _)| *paused);
+            info.priority = assignment
+                .map(|(_, _, priority)| parse_priority(priority))
+                .transpose()?;
         }
         Ok(())
     }
 
+    fn parse_priority(priority: &str) -> Result<DeploymentPriority, StoreError> {
+        priority
+            .parse()
+            .map_err(|e: String| constraint_violation!("{}", e))
+    }
+
+    /// The priority of the deployment, or `None` if it is not assigned
+    pub(super) fn priority(
+        conn: &mut PgConnection,
+        site: &Site,
+    ) -> Result<Option<DeploymentPriority>, StoreError> {
+        a::table
+            .filter(a::id.eq(site.id))
+            .select(a::priority)
+            .first::<String>(conn)
+            .optional()?
+            .map(|priority| parse_priority(&priority))
+            .transpose()
+    }
+
     pub(super) fn assigned_node(
         conn: &mut PgConnection,
         site: &Site,
@@ -1095,6 +1173,25 @@ impl<'a> Connection<'a> {
         }
     }
 
+    /// Set the priority of the deployment. The new priority takes effect
+    /// when the deployment is started the next time
+    pub fn set_priority(
+        &mut self,
+        site: &Site,
+        priority: DeploymentPriority,
+    ) -> Result<(), StoreError> {
+        use subgraph_deployment_assignment as a;
+
+        let conn = self.conn.as_mut();
+        let updates = update(a::table.filter(a::id.eq(site.id)))
+            .set(a::priority.eq(priority.as_str()))
+            .execute(conn)?;
+        match updates {
+            0 => Err(StoreError::DeploymentNotFound(site.deployment.to_string())),
+            _ => Ok(()),
+        }
+    }
+
     pub fn reassign_subgraph(
         &mut self,
         site: &Site,
@@ -1360,6 +1457,10 @@ impl<'a> Connection<'a> {
         queries::assignment_status(self.conn.as_mut(), site)
     }
 
+    pub fn priority(&mut self, site: &Site) -> Result<Option<DeploymentPriority>, StoreError> {
+        queries::priority(self.conn.as_mut(), site)
+    }
+
     /// Create a copy of the site `src` in the shard `shard`, but mark it as
     /// not active. If there already is a site in `shard`, return that
     /// instead.
@@ -1786,6 +1887,225 @@ impl<'a> Connection<'a> {
             .map_err(|e| anyhow!("error if ens table is empty: {}", e).into())
     }
 
+    pub fn find_persisted_query(&mut self, hash: &str) -> Result<Option<String>, StoreError> {
+        use persisted_queries as pq;
+
+        pq::table
+            .select(pq::query)
+            .find(hash)
+            .get_result::<String>(self.conn.as_mut())
+            .optional()
+            .map_err(|e| anyhow!("error looking up persisted query {}: {}", hash, e).into())
+    }
+
+    /// Remember `query` under `hash` and delete the queries that were
+    /// stored first so that there are never more than `max_queries`
+    pub fn add_persisted_query(
+        &mut self,
+        hash: &str,
+        query: &str,
+        max_queries: usize,
+    ) -> Result<(), StoreError> {
+        use persisted_queries as pq;
+
+        let inserted = insert_into(pq::table)
+            .values((
+                pq::hash.eq(hash),
+                pq::query.eq(query),
+                pq::created_at.eq(sql("now()")),
+            ))
+            .on_conflict_do_nothing()
+            .execute(self.conn.as_mut())?;
+
+        if inserted > 0 {
+            let keep = pq::table
+                .select(pq::hash)
+                .order_by((pq::created_at.desc(), pq::hash))
+                .limit(max_queries as i64);
+            delete(pq::table.filter(not(pq::hash.eq_any(keep)))).execute(self.conn.as_mut())?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the version of the auxiliary dataset `dataset` that
+    /// `deployment` reads from. The first call for a deployment pins it to
+    /// the latest version of the dataset, or to version 0, which has no
+    /// entries, if the dataset has not been loaded yet. Once pinned, the
+    /// deployment sees the same data no matter what is loaded later
+    pub fn pin_aux_dataset(
+        &mut self,
+        deployment: &DeploymentHash,
+        dataset: &str,
+    ) -> Result<i32, StoreError> {
+        use aux_datasets as ad;
+        use aux_pins as ap;
+
+        self.transaction(|conn| {
+            let latest = ad::table
+                .filter(ad::name.eq(dataset))
+                .select(max(ad::version))
+                .get_result::<Option<i32>>(conn)?
+                .unwrap_or(0);
+
+            insert_into(ap::table)
+                .values((
+                    ap::deployment.eq(deployment.as_str()),
+                    ap::dataset.eq(dataset),
+                    ap::version.eq(latest),
+                ))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            ap::table
+                .filter(ap::deployment.eq(deployment.as_str()))
+                .filter(ap::dataset.eq(dataset))
+                .select(ap::version)
+                .get_result::<i32>(conn)
+                .map_err(StoreError::from)
+        })
+    }
+
+    pub fn find_aux_entry(
+        &mut self,
+        dataset: &str,
+        version: i32,
+        key: &str,
+    ) -> Result<Option<String>, StoreError> {
+        use aux_entries as ae;
+
+        ae::table
+            .select(ae::value)
+            .find((dataset, version, key))
+            .get_result::<String>(self.conn.as_mut())
+            .optional()
+            .map_err(|e| {
+                anyhow!(
+                    "error looking up {} in version {} of dataset {}: {}",
+                    key,
+                    version,
+                    dataset,
+                    e
+                )
+                .into()
+            })
+    }
+
+    /// Load `entries` as a new version of the auxiliary dataset `name` and
+    /// return that version
+    pub fn load_aux_dataset(
+        &mut self,
+        name: &str,
+        entries: &[(String, String)],
+    ) -> Result<i32, StoreError> {
+        use aux_datasets as ad;
+        use aux_entries as ae;
+
+        // Stay well below the limit of 65535 bind variables per statement
+        const CHUNK_SIZE: usize = 10_000;
+
+        self.transaction(|conn| {
+            let version = ad::table
+                .filter(ad::name.eq(name))
+                .select(max(ad::version))
+                .get_result::<Option<i32>>(conn)?
+                .unwrap_or(0)
+                + 1;
+
+            insert_into(ad::table)
+                .values((
+                    ad::name.eq(name),
+                    ad::version.eq(version),
+                    ad::entries.eq(entries.len() as i64),
+                    ad::loaded_at.eq(sql("now()")),
+                ))
+                .execute(conn)?;
+
+            for chunk in entries.chunks(CHUNK_SIZE) {
+                let rows: Vec<_> = chunk
+                    .iter()
+                    .map(|(key, value)| {
+                        (
+                            ae::dataset.eq(name),
+                            ae::version.eq(version),
+                            ae::key.eq(key),
+                            ae::value.eq(value),
+                        )
+                    })
+                    .collect();
+                insert_into(ae::table).values(rows).execute(conn)?;
+            }
+            Ok(version)
+        })
+    }
+
+    pub fn aux_datasets(&mut self) -> Result<Vec<AuxDataset>, StoreError> {
+        use aux_datasets as ad;
+        use aux_pins as ap;
+
+        let pins: HashMap<(String, i32), i64> = ap::table
+            .group_by((ap::dataset, ap::version))
+            .select((ap::dataset, ap::version, count_star()))
+            .load::<(String, i32, i64)>(self.conn.as_mut())?
+            .into_iter()
+            .map(|(dataset, version, count)| ((dataset, version), count))
+            .collect();
+
+        let datasets = ad::table
+            .select((ad::name, ad::version, ad::entries, ad::loaded_at))
+            .order_by((ad::name, ad::version))
+            .load::<(String, i32, i64, chrono::DateTime<chrono::Utc>)>(self.conn.as_mut())?
+            .into_iter()
+            .map(|(name, version, entries, loaded_at)| {
+                let deployments = pins.get(&(name.clone(), version)).copied().unwrap_or(0);
+                AuxDataset {
+                    name,
+                    version,
+                    entries,
+                    loaded_at,
+                    deployments,
+                }
+            })
+            .collect();
+        Ok(datasets)
+    }
+
+    /// Remove a version of an auxiliary dataset. Versions that deployments
+    /// read from can not be removed since that would change what these
+    /// deployments see
+    pub fn remove_aux_dataset(&mut self, name: &str, version: i32) -> Result<(), StoreError> {
+        use aux_datasets as ad;
+        use aux_pins as ap;
+
+        self.transaction(|conn| {
+            let pinned = select(exists(
+                ap::table
+                    .filter(ap::dataset.eq(name))
+                    .filter(ap::version.eq(version)),
+            ))
+            .get_result::<bool>(conn)?;
+            if pinned {
+                return Err(anyhow!(
+                    "version {} of dataset {} is in use by deployments and can not be removed",
+                    version,
+                    name
+                )
+                .into());
+            }
+
+            let count = delete(
+                ad::table
+                    .filter(ad::name.eq(name))
+                    .filter(ad::version.eq(version)),
+            )
+            .execute(conn)?;
+            if count == 0 {
+                return Err(anyhow!("dataset {} has no version {}", name, version).into());
+            }
+            Ok(())
+        })
+    }
+
     pub fn record_active_copy(&mut self, src: &Site, dst: &Site) -> Result<(), StoreError> {
         use active_copies as cp;
 
@@ -2008,6 +2328,10 @@ impl Mirror {
         self.read(|conn| queries::assignment_status(conn, site))
     }
 
+    pub fn priority(&self, site: &Site) -> Result<Option<DeploymentPriority>, StoreError> {
+        self.read(|conn| queries::priority(conn, site))
+    }
+
     pub fn find_active_site(&self, subgraph: &DeploymentHash) -> Result<Option<Site>, StoreError> {
         self.read(|conn| queries::find_active_site(conn, subgraph))
     }
@@ -46,7 +46,7 @@ impl QueryStoreTrait for QueryStore {
         let start = Instant::now();
         let mut conn = self
             .store
-            .get_replica_conn(self.replica_id)
+            .get_query_conn(self.replica_id, &self.site, query.block)
             .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
         let wait = start.elapsed();
         self.store
@@ -57,6 +57,26 @@ impl QueryStoreTrait for QueryStore {
             })
     }
 
+    fn aggregate_query_values(
+        &self,
+        query: EntityQuery,
+        aggregates: &[EntityAggregate],
+    ) -> Result<(Vec<Value>, Trace), QueryExecutionError> {
+        assert_eq!(&self.site.deployment, &query.subgraph_id);
+        let start = Instant::now();
+        let mut conn = self
+            .store
+            .get_query_conn(self.replica_id, &self.site, query.block)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        let wait = start.elapsed();
+        self.store
+            .execute_aggregate_query(&mut conn, self.site.clone(), query, aggregates)
+            .map(|(values, mut trace)| {
+                trace.conn_wait(wait);
+                (values, trace)
+            })
+    }
+
     /// Return true if the deployment with the given id is fully synced,
     /// and return false otherwise. Errors from the store are passed back up
     async fn is_deployment_synced(&self) -> Result<bool, Error> {
@@ -7,6 +7,7 @@
 //! The pivotal struct in this module is the `Layout` which handles all the
 //! information about mapping a GraphQL schema to database tables
 
+mod archive;
 mod ddl;
 
 #[cfg(test)]
@@ -14,12 +15,15 @@ mod ddl_tests;
 #[cfg(test)]
 mod query_tests;
 
+mod export;
 pub(crate) mod index;
+mod partition;
 mod prune;
 mod rollup;
 
 use diesel::deserialize::FromSql;
-use diesel::pg::Pg;
+use diesel::pg::{Pg, PgQueryBuilder};
+use diesel::query_builder::{QueryBuilder, QueryFragment};
 use diesel::serialize::{Output, ToSql};
 use diesel::sql_types::Text;
 use diesel::{connection::SimpleConnection, Connection};
@@ -33,14 +37,16 @@ use graph::data::graphql::TypeExt as _;
 use graph::data::query::Trace;
 use graph::data::value::Word;
 use graph::data_source::CausalityRegion;
-use graph::prelude::{q, EntityQuery, StopwatchMetrics, ENV_VARS};
+use graph::prelude::{
+    lazy_static, q, EntityAggregate, EntityCollection, EntityQuery, StopwatchMetrics, Value,
+    ENV_VARS,
+};
 use graph::schema::{
     EntityKey, EntityType, Field, FulltextConfig, FulltextDefinition, InputSchema,
 };
 use graph::slog::warn;
 use inflector::Inflector;
 use itertools::Itertools;
-use lazy_static::lazy_static;
 use std::borrow::Borrow;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::{From, TryFrom};
@@ -56,12 +62,13 @@ use crate::relational_queries::{
 use crate::{
     primary::{Namespace, Site},
     relational_queries::{
-        ClampRangeQuery, EntityData, EntityDeletion, FilterCollection, FilterQuery, FindManyQuery,
-        FindQuery, InsertQuery, RevertClampQuery, RevertRemoveQuery,
+        AggregateQuery, AggregateValues, ClampRangeQuery, EntityData, EntityDeletion, ExplainQuery,
+        FilterCollection, FilterQuery, FindManyQuery, FindQuery, FindRecentQuery, InsertQuery,
+        QueryPlan, RevertClampQuery, RevertRemoveQuery,
     },
 };
 use graph::components::store::DerivedEntityQuery;
-use graph::data::store::{Id, IdList, IdType, BYTES_SCALAR};
+use graph::data::store::{scalar, Id, IdList, IdType, BYTES_SCALAR};
 use graph::data::subgraph::schema::POI_TABLE;
 use graph::prelude::{
     anyhow, info, BlockNumber, DeploymentHash, Entity, EntityChange, EntityOperation, Logger,
@@ -86,11 +93,46 @@ const DELETE_OPERATION_CHUNK_SIZE: usize = 1_000;
 pub const STRING_PREFIX_SIZE: usize = 256;
 pub const BYTE_ARRAY_PREFIX_SIZE: usize = 64;
 
-lazy_static! {
-    static ref STATEMENT_TIMEOUT: Option<String> = ENV_VARS
+/// Set the `statement_timeout` for the current transaction to the smaller
+/// of `GRAPH_SQL_STATEMENT_TIMEOUT` and the time that is left until
+/// `deadline` so that Postgres cancels statements once the GraphQL query
+/// they belong to has timed out. Since the setting is local to the
+/// transaction, it does not leak to other users of the connection
+fn set_statement_timeout(
+    conn: &mut PgConnection,
+    deadline: Option<Instant>,
+) -> Result<(), diesel::result::Error> {
+    let env_timeout = ENV_VARS
         .graphql
         .sql_statement_timeout
-        .map(|duration| format!("set local statement_timeout={}", duration.as_millis()));
+        .filter(|timeout| !timeout.is_zero());
+    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    let timeout = match (env_timeout, remaining) {
+        (Some(env_timeout), Some(remaining)) => Some(env_timeout.min(remaining)),
+        (env_timeout, remaining) => env_timeout.or(remaining),
+    };
+    if let Some(timeout) = timeout {
+        // A timeout of 0 turns the timeout off
+        let millis = timeout.as_millis().max(1);
+        conn.batch_execute(&format!("set local statement_timeout={}", millis))?;
+    }
+    Ok(())
+}
+
+/// Whether `e` happened because Postgres canceled a statement that ran
+/// longer than `statement_timeout`
+fn is_statement_timeout(e: &diesel::result::Error) -> bool {
+    use diesel::result::DatabaseErrorKind;
+    use diesel::result::Error::*;
+
+    matches!(e, DatabaseError(DatabaseErrorKind::Unknown, info)
+        if info.message().contains("statement timeout"))
+}
+
+lazy_static! {
+    /// The hashes of the queries that `Layout::explain_query` has already
+    /// explained
+    static ref EXPLAINED_QUERIES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
 }
 
 /// A string we use as a SQL name for a table or column. The important thing
@@ -370,8 +412,12 @@ impl Layout {
             // predictable
             position: position as u32,
             is_account_like: false,
+            history_blocks: None,
+            earliest_block: None,
             immutable: false,
             has_causality_region: false,
+            partitioned: false,
+            ttl: None,
         }
     }
 
@@ -475,6 +521,26 @@ impl Layout {
             .transpose()
     }
 
+    /// Return the keys of the `count` entities of type `entity_type` that
+    /// were written most recently and are visible at `block`
+    pub fn find_recent(
+        &self,
+        conn: &mut PgConnection,
+        entity_type: &EntityType,
+        count: usize,
+        block: BlockNumber,
+    ) -> Result<Vec<EntityKey>, StoreError> {
+        let table = self.table_for_entity(entity_type)?;
+        FindRecentQuery::new(table.as_ref(), count, block)
+            .load::<EntityData>(conn)?
+            .into_iter()
+            .map(|data| {
+                let entity: Entity = data.deserialize_with_layout(self, None)?;
+                Ok(entity_type.key_in(entity.id(), CausalityRegion::from_entity(&entity)))
+            })
+            .collect()
+    }
+
     // An optimization when looking up multiple entities, it will generate a single sql query using `UNION ALL`.
     pub fn find_many(
         &self,
@@ -644,6 +710,131 @@ impl Layout {
     }
 
     /// order is a tuple (attribute, value_type, direction)
+    /// Check that the tables for the entity types in `collection` still
+    /// have data for `block`. Tables that keep less history than the
+    /// deployment can not answer queries for blocks that were pruned from
+    /// them
+    fn check_history(
+        &self,
+        collection: &EntityCollection,
+        block: BlockNumber,
+    ) -> Result<(), QueryExecutionError> {
+        let entity_types: Vec<_> = match collection {
+            EntityCollection::All(types) => types.iter().map(|(et, _)| et).collect(),
+            EntityCollection::Window(windows) => {
+                windows.iter().map(|window| &window.child_type).collect()
+            }
+        };
+        for entity_type in entity_types {
+            // Unknown entity types are reported when building the query
+            let Ok(table) = self.table_for_entity(entity_type) else {
+                continue;
+            };
+            if let Some(earliest_block) = table.earliest_block {
+                if block < earliest_block {
+                    return Err(QueryExecutionError::HistoryPruned(
+                        entity_type.to_string(),
+                        block,
+                        earliest_block,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `query`, which took `elapsed` to execute, again under `EXPLAIN
+    /// ANALYZE` and log its plan. Queries are identified by a hash of their
+    /// SQL text without the tags and bind variables, and each query is only
+    /// explained once. Since this executes `query` again, it should not be
+    /// called on the path of a request. Failing to explain a query is
+    /// logged but otherwise ignored
+    pub fn explain_query(
+        &self,
+        logger: &Logger,
+        conn: &mut PgConnection,
+        query: EntityQuery,
+        elapsed: Duration,
+    ) {
+        // Limit the number of hashes we remember so that a stream of
+        // different slow queries can not use up memory
+        const MAX_EXPLAINED: usize = 10_000;
+
+        let filter_collection = match FilterCollection::new(
+            self,
+            query.collection,
+            query.filter.as_ref(),
+            query.block,
+        ) {
+            Ok(filter_collection) => filter_collection,
+            Err(e) => {
+                warn!(logger, "Failed to generate SQL for slow query"; "error" => e.to_string());
+                return;
+            }
+        };
+        let query = match FilterQuery::new(
+            &filter_collection,
+            self,
+            query.filter.as_ref(),
+            query.order,
+            query.range,
+            query.after.as_ref(),
+            query.block,
+            query.query_id,
+            &self.site,
+        ) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!(logger, "Failed to generate SQL for slow query"; "error" => e.to_string());
+                return;
+            }
+        };
+
+        let mut builder = PgQueryBuilder::default();
+        if let Err(e) = query.to_sql(&mut builder, &Pg) {
+            warn!(logger, "Failed to generate SQL for slow query"; "error" => e.to_string());
+            return;
+        }
+        let sql = builder.finish();
+        // The tags contain the block and query id which vary from one
+        // execution of the same query to the next
+        let text = match sql.trim_start().strip_prefix("/*") {
+            Some(rest) => rest.split_once("*/").map(|(_, sql)| sql).unwrap_or(rest),
+            None => sql.as_str(),
+        };
+        let hash = blake3::hash(text.trim().as_bytes()).to_hex()[..16].to_string();
+
+        {
+            let mut explained = EXPLAINED_QUERIES.lock().unwrap();
+            if explained.contains(&hash) {
+                return;
+            }
+            if explained.len() >= MAX_EXPLAINED {
+                explained.clear();
+            }
+            explained.insert(hash.clone());
+        }
+
+        let res = conn.transaction(|conn| {
+            set_statement_timeout(conn, None)?;
+            ExplainQuery::new(&query).get_result::<QueryPlan>(conn)
+        });
+        match res {
+            Ok(QueryPlan { plan }) => {
+                warn!(logger, "Slow query plan";
+                      "query_hash" => &hash,
+                      "time_ms" => elapsed.as_millis(),
+                      "query" => text.trim().replace('\n', "\t"),
+                      "plan" => plan)
+            }
+            Err(e) => {
+                warn!(logger, "Failed to explain slow query";
+                      "query_hash" => &hash,
+                      "error" => e.to_string())
+            }
+        }
+    }
+
     pub fn query<T: crate::relational_queries::FromEntityData>(
         &self,
         logger: &Logger,
@@ -692,6 +883,12 @@ impl Layout {
         }
 
         let trace = query.trace;
+        let deadline = query.deadline;
+        if deadline.map_or(false, |deadline| deadline <= Instant::now()) {
+            return Err(QueryExecutionError::Timeout);
+        }
+
+        self.check_history(&query.collection, query.block)?;
 
         let filter_collection =
             FilterCollection::new(self, query.collection, query.filter.as_ref(), query.block)?;
@@ -701,6 +898,7 @@ impl Layout {
             query.filter.as_ref(),
             query.order,
             query.range,
+            query.after.as_ref(),
             query.block,
             query.query_id,
             &self.site,
@@ -711,9 +909,7 @@ impl Layout {
         let start = Instant::now();
         let values = conn
             .transaction(|conn| {
-                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
-                    conn.batch_execute(timeout_sql)?;
-                }
+                set_statement_timeout(conn, deadline)?;
                 query.load::<EntityData>(conn)
             })
             .map_err(|e| {
@@ -737,6 +933,7 @@ impl Layout {
                     {
                         QueryExecutionError::FulltextQueryInvalidSyntax(info.message().to_string())
                     }
+                    _ if is_statement_timeout(&e) => QueryExecutionError::Timeout,
                     _ => QueryExecutionError::ResolveEntitiesError(format!(
                         "{e}, query = {query_text}",
                     )),
@@ -756,6 +953,63 @@ impl Layout {
             .map(|values| (values, trace))
     }
 
+    /// Compute `aggregates` over the entities that `query` matches. Only
+    /// the collection, filter and block of `query` are used
+    pub fn aggregate(
+        &self,
+        conn: &mut PgConnection,
+        query: EntityQuery,
+        aggregates: &[EntityAggregate],
+    ) -> Result<(Vec<Value>, Trace), QueryExecutionError> {
+        fn parse_error(e: impl ToString) -> QueryExecutionError {
+            QueryExecutionError::ResolveEntitiesError(format!(
+                "invalid aggregate value: {}",
+                e.to_string()
+            ))
+        }
+
+        let filter_collection =
+            FilterCollection::new(self, query.collection, query.filter.as_ref(), query.block)?;
+        let agg_query = AggregateQuery::new(&filter_collection, aggregates)?;
+
+        let start = Instant::now();
+        let values = conn
+            .transaction(|conn| {
+                set_statement_timeout(conn, query.deadline)?;
+                agg_query.get_result::<AggregateValues>(conn)
+            })
+            .map_err(|e| {
+                if is_statement_timeout(&e) {
+                    return QueryExecutionError::Timeout;
+                }
+                QueryExecutionError::ResolveEntitiesError(format!(
+                    "{e}, query = {}",
+                    debug_query(&agg_query)
+                ))
+            })?;
+        let trace = if query.trace {
+            let text = debug_query(&agg_query).to_string().replace('\n', "\t");
+            Trace::query(&text, start.elapsed(), 1)
+        } else {
+            Trace::None
+        };
+
+        let values = aggregates
+            .iter()
+            .zip(values.aggregates)
+            .map(|(aggregate, value)| match (aggregate, value) {
+                (_, None) => Ok(Value::Null),
+                (EntityAggregate::Count, Some(count)) => {
+                    count.parse().map(Value::Int8).map_err(parse_error)
+                }
+                (_, Some(value)) => scalar::BigDecimal::from_str(&value)
+                    .map(Value::BigDecimal)
+                    .map_err(parse_error),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((values, trace))
+    }
+
     pub fn update<'a>(
         &'a self,
         conn: &mut PgConnection,
@@ -910,12 +1164,14 @@ impl Layout {
                 .map(|_| EntityChange::Data {
                     subgraph_id: self.site.deployment.clone(),
                     entity_type: table.object.to_string(),
+                    ids: None,
                 });
             changes.extend(deleted);
             // EntityChange for versions that we just updated or inserted
             let set = unclamped.into_iter().map(|_| EntityChange::Data {
                 subgraph_id: self.site.deployment.clone(),
                 entity_type: table.object.to_string(),
+                ids: None,
             });
             changes.extend(set);
         }
@@ -934,6 +1190,7 @@ impl Layout {
     ) -> Result<(), StoreError> {
         crate::dynds::revert(conn, site, block)?;
         crate::deployment::revert_subgraph_errors(conn, &site.deployment, block)?;
+        crate::skipped_trigger::revert(conn, site, block)?;
 
         Ok(())
     }
@@ -946,8 +1203,9 @@ impl Layout {
     }
 
     /// Update the layout with the latest information from the database; an
-    /// update can only change the `is_account_like` flag for tables, the
-    /// layout's site, or the `history_blocks`. If no update is needed, just
+    /// update can only change the `is_account_like` flag, the
+    /// `history_blocks` and the `earliest_block` for tables, the layout's
+    /// site, or the `history_blocks`. If no update is needed, just
     /// return `self`.
     ///
     /// This is tied closely to how the `LayoutCache` works and called from
@@ -960,13 +1218,22 @@ impl Layout {
     ) -> Result<Arc<Self>, StoreError> {
         let account_like = crate::catalog::account_like(conn, &self.site)?;
         let history_blocks = deployment::history_blocks(conn, &self.site)?;
+        let table_history_blocks = crate::catalog::table_history_blocks(conn, &self.site)?;
+        let table_earliest_blocks = crate::catalog::table_earliest_blocks(conn, &self.site)?;
 
         let is_account_like = { |table: &Table| account_like.contains(table.name.as_str()) };
+        let table_history = |table: &Table| table_history_blocks.get(table.name.as_str()).copied();
+        let table_earliest =
+            |table: &Table| table_earliest_blocks.get(table.name.as_str()).copied();
 
         let changed_tables: Vec<_> = self
             .tables
             .values()
-            .filter(|table| table.is_account_like != is_account_like(table.as_ref()))
+            .filter(|table| {
+                table.is_account_like != is_account_like(table.as_ref())
+                    || table.history_blocks != table_history(table.as_ref())
+                    || table.earliest_block != table_earliest(table.as_ref())
+            })
             .collect();
         if changed_tables.is_empty() && site == self.site && history_blocks == self.history_blocks {
             return Ok(self);
@@ -976,6 +1243,8 @@ impl Layout {
         for table in changed_tables.into_iter() {
             let mut table = (*table.as_ref()).clone();
             table.is_account_like = is_account_like(&table);
+            table.history_blocks = table_history(&table);
+            table.earliest_block = table_earliest(&table);
             layout.tables.insert(table.object.clone(), Arc::new(table));
         }
         layout.site = site;
@@ -1451,6 +1720,16 @@ pub struct Table {
     /// entities are updated frequently on average
     pub is_account_like: bool,
 
+    /// How many blocks of history to keep for this table if it keeps less
+    /// history than the deployment as a whole. Pruning removes versions
+    /// that are older than that
+    pub history_blocks: Option<BlockNumber>,
+
+    /// The earliest block for which this table still has data if pruning
+    /// removed more history from it than from the deployment as a whole.
+    /// Queries for earlier blocks fail
+    pub earliest_block: Option<BlockNumber>,
+
     /// The position of this table in all the tables for this layout; this
     /// is really only needed for the tests to make the names of indexes
     /// predictable
@@ -1463,6 +1742,14 @@ pub struct Table {
     /// Whether this table has an explicit `causality_region` column. If `false`, then the column is
     /// not present and the causality region for all rows is implicitly `0` (equivalent to CasualityRegion::ONCHAIN).
     pub(crate) has_causality_region: bool,
+
+    /// Whether this table is partitioned by the lower bound of its block
+    /// range. See `relational/partition.rs`
+    pub(crate) partitioned: bool,
+
+    /// For how many blocks after they were written versions in this table
+    /// are visible to queries, from `@ttl(blocks: ..)`
+    pub(crate) ttl: Option<BlockNumber>,
 }
 
 impl Table {
@@ -1490,6 +1777,12 @@ impl Table {
             .collect::<Result<Vec<Column>, StoreError>>()?;
         let qualified_name = SqlName::qualified_name(&catalog.site.namespace, &table_name);
         let immutable = defn.is_immutable();
+        // Partitioned tables can't have the exclusion constraint that
+        // tables with a causality region need
+        let partitioned = !immutable
+            && !has_causality_region
+            && (catalog.partition_new_tables
+                || catalog.partitioned_tables.contains(table_name.as_str()));
 
         let table = Table {
             object: defn.cheap_clone(),
@@ -1499,10 +1792,14 @@ impl Table {
             // `refresh` after constructing the layout, but that requires a
             // db connection, which we don't have at this point.
             is_account_like: false,
+            history_blocks: None,
+            earliest_block: None,
             columns,
             position,
             immutable,
             has_causality_region,
+            partitioned,
+            ttl: object_type.ttl,
         };
         Ok(table)
     }
@@ -1516,9 +1813,13 @@ impl Table {
             qualified_name: SqlName::qualified_name(namespace, name),
             columns: self.columns.clone(),
             is_account_like: self.is_account_like,
+            history_blocks: self.history_blocks,
+            earliest_block: self.earliest_block,
             position: self.position,
             immutable: self.immutable,
             has_causality_region: self.has_causality_region,
+            partitioned: self.partitioned,
+            ttl: self.ttl,
         };
 
         Arc::new(other)
@@ -1579,6 +1880,13 @@ impl Table {
         Ok(())
     }
 
+    pub(crate) fn vacuum(&self, conn: &mut PgConnection) -> Result<(), StoreError> {
+        let table_name = &self.qualified_name;
+        let sql = format!("vacuum (analyze, skip_locked) {table_name}");
+        sql_query(&sql).execute(conn)?;
+        Ok(())
+    }
+
     pub(crate) fn block_column(&self) -> &SqlName {
         if self.immutable {
             &crate::block_range::BLOCK_COLUMN_SQL
@@ -0,0 +1,200 @@
+//! Versions of mutable entities whose block range was closed a long time
+//! ago are rarely needed, but for deployments that keep all their history
+//! they make up most of the data in a table. Archiving moves such versions
+//! into a companion table `{table}$archive` that inherits from the table.
+//! Since Postgres includes the rows of child tables when reading from a
+//! parent table, queries, reverts, pruning and copying see archived
+//! versions without any changes. The archive table only has the indexes
+//! needed for lookups by id and block, and can store its larger columns
+//! with `lz4` compression
+use std::{collections::HashSet, fmt::Write, time::Instant};
+
+use diesel::{
+    connection::SimpleConnection,
+    sql_query,
+    sql_types::{BigInt, Integer},
+    PgConnection, RunQueryDsl,
+};
+use graph::{
+    prelude::{BlockNumber, CancelHandle, CancelToken, CancelableError, StoreError},
+    slog::{info, Logger},
+};
+
+use crate::{catalog, copy::AdaptiveBatchSize, primary::Namespace};
+
+use super::{ColumnType, Layout, SqlName, Table};
+
+/// The suffix for the name of the archive table of a table
+const ARCHIVE_SUFFIX: &str = "archive";
+
+impl Table {
+    /// The unqualified name of the archive table for this table
+    fn archive_name(&self) -> SqlName {
+        SqlName::verbatim(format!("{}${}", self.name, ARCHIVE_SUFFIX))
+    }
+
+    /// Create the archive table for this table and its indexes if they do
+    /// not exist yet. If `lz4` is `true`, set the compression for columns
+    /// that can hold large values to `lz4`; that requires Postgres 14 or
+    /// later built with `lz4` support
+    fn create_archive(
+        &self,
+        conn: &mut PgConnection,
+        namespace: &Namespace,
+        lz4: bool,
+    ) -> Result<(), StoreError> {
+        let name = self.archive_name();
+        let archive = SqlName::qualified_name(namespace, &name);
+
+        let mut ddl = String::new();
+        writeln!(
+            ddl,
+            "create table if not exists {archive} () inherits ({qname}) with (fillfactor = 100);",
+            qname = self.qualified_name
+        )?;
+        writeln!(
+            ddl,
+            "create index if not exists \"{name}_br\" on {archive} using gist(block_range);"
+        )?;
+        writeln!(
+            ddl,
+            "create index if not exists \"{name}_id\" on {archive} using gist(id, block_range);"
+        )?;
+        writeln!(
+            ddl,
+            "create index if not exists \"{name}_brin\" on {archive} \
+             using brin(lower(block_range), coalesce(upper(block_range), 2147483647), vid);"
+        )?;
+        if lz4 {
+            let compressible = self.columns.iter().filter(|column| {
+                column.is_list()
+                    || matches!(
+                        column.column_type,
+                        ColumnType::String
+                            | ColumnType::Bytes
+                            | ColumnType::BigDecimal
+                            | ColumnType::BigInt
+                            | ColumnType::TSVector(_)
+                    )
+            });
+            for column in compressible {
+                writeln!(
+                    ddl,
+                    "alter table {archive} alter column {column} set compression lz4;",
+                    column = column.name.quoted()
+                )?;
+            }
+        }
+        conn.batch_execute(&ddl)?;
+        Ok(())
+    }
+
+    /// Move all versions in this table whose block range ended at or
+    /// before `block` into the archive table and return how many versions
+    /// were moved
+    fn archive_versions(
+        &self,
+        conn: &mut PgConnection,
+        namespace: &Namespace,
+        block: BlockNumber,
+        cancel: &CancelHandle,
+    ) -> Result<usize, CancelableError<StoreError>> {
+        #[derive(QueryableByName)]
+        struct VidRange {
+            #[diesel(sql_type = BigInt)]
+            min_vid: i64,
+            #[diesel(sql_type = BigInt)]
+            max_vid: i64,
+        }
+
+        let archive = SqlName::qualified_name(namespace, &self.archive_name());
+
+        // Only look at versions that are still in the table itself
+        let VidRange { min_vid, max_vid } = sql_query(format!(
+            "/* controller=archive,block={block} */ \
+             select coalesce(min(vid), 0) as min_vid, \
+                    coalesce(max(vid), -1) as max_vid from only {qname} \
+              where coalesce(upper(block_range), 2147483647) <= $1",
+            qname = self.qualified_name,
+        ))
+        .bind::<Integer, _>(block)
+        .get_result::<VidRange>(conn)?;
+
+        let mut batch_size = AdaptiveBatchSize::new(self);
+        let mut next_vid = min_vid;
+        let mut archived = 0;
+        while next_vid <= max_vid {
+            let start = Instant::now();
+            let rows = sql_query(format!(
+                "/* controller=archive,start_vid={next_vid},batch_size={batch_size} */ \
+                 with moved as ( \
+                   delete from only {qname} \
+                    where coalesce(upper(block_range), 2147483647) <= $1 \
+                      and vid >= $2 and vid < $2 + $3 \
+                   returning *) \
+                 insert into {archive} select * from moved",
+                qname = self.qualified_name,
+                batch_size = batch_size.size
+            ))
+            .bind::<Integer, _>(block)
+            .bind::<BigInt, _>(next_vid)
+            .bind::<BigInt, _>(&batch_size)
+            .execute(conn)?;
+
+            next_vid += batch_size.size;
+            archived += rows;
+
+            batch_size.adapt(start.elapsed());
+            cancel.check_cancel()?;
+        }
+        Ok(archived)
+    }
+}
+
+impl Layout {
+    /// Move all versions of mutable entities whose block range ended at or
+    /// before `block` into the archive tables of their tables, creating
+    /// archive tables as needed. Partitioned tables are not archived since
+    /// pruning them is already cheap. Return the number of versions that
+    /// were archived for each table
+    pub fn archive(
+        &self,
+        logger: &Logger,
+        conn: &mut PgConnection,
+        block: BlockNumber,
+        lz4: bool,
+        cancel: &CancelHandle,
+    ) -> Result<Vec<(String, usize)>, CancelableError<StoreError>> {
+        let mut tables: Vec<_> = self
+            .tables
+            .values()
+            .filter(|table| !table.immutable && !table.partitioned)
+            .collect();
+        tables.sort_by_key(|table| table.name.as_str());
+
+        let mut counts = Vec::new();
+        for table in tables {
+            table.create_archive(conn, &self.site.namespace, lz4)?;
+            let archived = table.archive_versions(conn, &self.site.namespace, block, cancel)?;
+            if archived > 0 {
+                table.analyze(conn)?;
+            }
+            info!(logger, "Archived entity versions";
+                  "table" => table.name.as_str(),
+                  "block" => block,
+                  "versions" => archived);
+            counts.push((table.name.to_string(), archived));
+        }
+        Ok(counts)
+    }
+
+    /// Return the names of the tables in this layout that have an archive
+    /// table. We look at the database rather than the catalog since the
+    /// layout might have been loaded before an archive table was created
+    pub(super) fn archived_tables(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<HashSet<String>, StoreError> {
+        catalog::archived_tables(conn, &self.site.namespace, ARCHIVE_SUFFIX)
+    }
+}
@@ -14,7 +14,7 @@ use crate::relational::{
     VID_COLUMN,
 };
 
-use super::{Catalog, Column, Layout, SqlName, Table};
+use super::{partition::DEFAULT_PARTITION, Catalog, Column, Layout, SqlName, Table};
 
 // In debug builds (for testing etc.) unconditionally create exclusion constraints, in release
 // builds for production, skip them
@@ -127,6 +127,24 @@ impl Table {
                 block = BLOCK_COLUMN,
                 id = self.primary_key().name
             )
+        } else if self.partitioned {
+            // Partitioned tables can't have a primary key on `vid` since
+            // unique indexes must contain the partition key
+            writeln!(
+                out,
+                r#"
+    create table {qname} (
+        {vid}                  bigserial not null,
+        {block_range}          int4range not null,
+        {cols}
+    ) partition by range (lower({block_range}));"#,
+                qname = self.qualified_name,
+                cols = columns_ddl(self)?,
+                vid = VID_COLUMN,
+                block_range = BLOCK_RANGE_COLUMN
+            )?;
+
+            self.exclusion_ddl(out)
         } else {
             writeln!(
                 out,
@@ -146,6 +164,31 @@ impl Table {
         }
     }
 
+    /// For partitioned tables, create the default partition and an index
+    /// on `vid`. The default partition holds versions that do not belong
+    /// into any other partition, for example, the versions copied from
+    /// another deployment; partitions for block ranges are added by
+    /// `Layout::create_partitions` as indexing advances
+    fn create_default_partition(&self, catalog: &Catalog, out: &mut String) -> fmt::Result {
+        if !self.partitioned {
+            return Ok(());
+        }
+
+        writeln!(
+            out,
+            "create table {partition} partition of {qname} default;",
+            partition = self.partition_name(&catalog.site.namespace, DEFAULT_PARTITION),
+            qname = self.qualified_name,
+        )?;
+        writeln!(
+            out,
+            "create index {table_name}_{vid}\n    on {qname}({vid});",
+            table_name = self.name,
+            qname = self.qualified_name,
+            vid = VID_COLUMN
+        )
+    }
+
     fn create_time_travel_indexes(&self, catalog: &Catalog, out: &mut String) -> fmt::Result {
         let (int4, int8) = catalog.minmax_ops();
 
@@ -287,11 +330,14 @@ impl Table {
             let (method, index_expr) =
                 Self::calculate_attr_index_method_and_expression(self.immutable, column);
             // If `create_gin_indexes` is set to false, we don't create
-            // indexes on array attributes. Experience has shown that these
-            // indexes are very expensive to update and can have a very bad
-            // impact on the write performance of the database, but are
-            // hardly ever used or needed by queries.
-            if !column.is_list() || ENV_VARS.store.create_gin_indexes {
+            // indexes on array attributes of mutable tables. Experience has
+            // shown that these indexes are very expensive to update and can
+            // have a very bad impact on the write performance of the
+            // database, but are hardly ever used or needed by queries.
+            // Immutable tables are only ever inserted into, which makes
+            // the indexes cheap enough to always create them so that
+            // `_contains` filters on lists can use them
+            if !column.is_list() || self.immutable || ENV_VARS.store.create_gin_indexes {
                 write!(
                     out,
                     "create index attr_{table_index}_{column_index}_{table_name}_{column_name}\n    on {qname} using {method}({index_expr});\n",
@@ -356,6 +402,7 @@ impl Table {
         out: &mut String,
     ) -> fmt::Result {
         self.create_table(out)?;
+        self.create_default_partition(catalog, out)?;
         self.create_time_travel_indexes(catalog, out)?;
         self.create_attribute_indexes(out)?;
         self.create_aggregate_indexes(schema, out)
@@ -363,8 +410,10 @@ impl Table {
 
     pub fn exclusion_ddl(&self, out: &mut String) -> fmt::Result {
         // Tables with causality regions need to use exclusion constraints for correctness,
-        // to catch violations of write isolation.
-        let as_constraint = self.has_causality_region || CREATE_EXCLUSION_CONSTRAINT;
+        // to catch violations of write isolation. Partitioned tables can't
+        // have exclusion constraints that do not contain the partition key
+        let as_constraint =
+            !self.partitioned && (self.has_causality_region || CREATE_EXCLUSION_CONSTRAINT);
 
         self.exclusion_ddl_inner(out, as_constraint)
     }
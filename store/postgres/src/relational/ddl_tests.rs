@@ -183,6 +183,25 @@ fn generate_ddl() {
     check_eqv(LIFETIME_SQL, &sql);
 }
 
+#[test]
+fn gin_indexes_for_immutable_lists() {
+    const GQL: &str = r#"
+type Tagged @entity(immutable: true) {
+    id: ID!
+    tags: [String!]!
+}
+
+type Mutable @entity {
+    id: ID!
+    tags: [String!]!
+}"#;
+
+    let layout = test_layout(GQL);
+    let sql = layout.as_ddl().expect("Failed to generate DDL");
+    assert!(sql.contains(r#"on "sgd0815"."tagged" using gin("tags")"#));
+    assert!(!sql.contains(r#"on "sgd0815"."mutable" using gin("tags")"#));
+}
+
 #[test]
 fn exlusion_ddl() {
     let layout = test_layout(THING_GQL);
@@ -211,6 +230,37 @@ fn exlusion_ddl() {
     );
 }
 
+#[test]
+fn partitioned_ddl() {
+    let layout = test_layout(THING_GQL);
+    let mut catalog = layout.catalog.clone();
+    catalog.partition_new_tables = true;
+    let layout = Layout::new(layout.site.clone(), &layout.input_schema, catalog)
+        .expect("Failed to construct Layout");
+
+    let thing = layout.table(&SqlName::from("thing")).unwrap();
+    assert!(thing.partitioned);
+    // Tables with a causality region need an exclusion constraint and are
+    // never partitioned
+    let file_thing = layout.table(&SqlName::from("file_thing")).unwrap();
+    assert!(!file_thing.partitioned);
+
+    let mut out = String::new();
+    thing
+        .as_ddl(&layout.input_schema, &layout.catalog, &mut out)
+        .expect("can write DDL");
+    let out = out.split_whitespace().join(" ");
+    for expected in [
+        r#"vid bigserial not null, block_range int4range not null,"#,
+        r#") partition by range (lower(block_range));"#,
+        r#"create index thing_id_block_range_excl on "sgd0815"."thing" using gist (id, block_range);"#,
+        r#"create table "sgd0815"."thing$default" partition of "sgd0815"."thing" default;"#,
+        r#"create index thing_vid on "sgd0815"."thing"(vid);"#,
+    ] {
+        assert!(out.contains(expected), "`{}` is in `{}`", expected, out);
+    }
+}
+
 #[test]
 fn forward_enum() {
     let layout = test_layout(FORWARD_ENUM_GQL);
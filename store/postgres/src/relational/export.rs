@@ -0,0 +1,175 @@
+//! Export the state of all entities in a table at a given block as CSV or
+//! NDJSON. Rows are read through a server-side cursor so that tables with
+//! millions of rows can be exported without holding them all in memory
+use std::io::Write;
+
+use diesel::{connection::SimpleConnection, sql_query, Connection, PgConnection, RunQueryDsl};
+use graph::{
+    anyhow,
+    components::store::{ExportFormat, StoreError},
+    csv,
+    data::value::{Object, Word},
+    prelude::{r, serde_json, BlockNumber, Entity},
+    schema::EntityType,
+};
+
+use crate::{
+    block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN},
+    relational_queries::EntityData,
+};
+
+use super::{Column, Layout, Table, VID_COLUMN};
+
+/// The name of the cursor used for exports
+const EXPORT_CURSOR: &str = "export_entities";
+
+/// How many rows to fetch from the cursor at once
+const EXPORT_BATCH_SIZE: usize = 10_000;
+
+fn write_error(e: impl Into<anyhow::Error>) -> StoreError {
+    StoreError::Unknown(e.into())
+}
+
+impl Table {
+    /// The query for the state of all entities in this table at `block`,
+    /// in the order in which they were written
+    fn export_query(&self, block: BlockNumber) -> String {
+        let visible = if self.immutable {
+            format!("e.\"{}\" <= {}", BLOCK_COLUMN, block)
+        } else {
+            format!("e.\"{}\" @> {}", BLOCK_RANGE_COLUMN, block)
+        };
+        format!(
+            "select '{entity}' as entity, to_jsonb(e.*) as data \
+               from {table} e \
+              where {visible} \
+              order by e.\"{vid}\"",
+            entity = self.object.as_str(),
+            table = self.qualified_name,
+            vid = VID_COLUMN
+        )
+    }
+
+    /// The columns that are exported; fulltext columns are derived from
+    /// other columns and are not part of the entity
+    fn export_columns(&self) -> impl Iterator<Item = &Column> {
+        self.columns.iter().filter(|column| !column.is_fulltext())
+    }
+}
+
+/// Format a single value for a CSV file. Scalars are written as they are,
+/// lists are written as JSON arrays
+fn csv_value(value: r::Value) -> Result<String, StoreError> {
+    match value {
+        r::Value::Null => Ok(String::new()),
+        r::Value::String(s) | r::Value::Enum(s) => Ok(s),
+        r::Value::Int(i) => Ok(i.to_string()),
+        r::Value::Float(f) => Ok(f.to_string()),
+        r::Value::Boolean(b) => Ok(b.to_string()),
+        r::Value::Timestamp(ts) => Ok(ts.as_microseconds_since_epoch().to_string()),
+        value @ (r::Value::List(_) | r::Value::Object(_)) => Ok(serde_json::to_string(&value)?),
+    }
+}
+
+/// Writes entities to a writer in one of the export formats
+enum Exporter<'a> {
+    Csv(csv::Writer<&'a mut dyn Write>),
+    Ndjson(&'a mut dyn Write),
+}
+
+impl<'a> Exporter<'a> {
+    fn new(
+        table: &Table,
+        format: ExportFormat,
+        writer: &'a mut dyn Write,
+    ) -> Result<Self, StoreError> {
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                writer
+                    .write_record(table.export_columns().map(|column| column.field.as_str()))
+                    .map_err(write_error)?;
+                Ok(Exporter::Csv(writer))
+            }
+            ExportFormat::Ndjson => Ok(Exporter::Ndjson(writer)),
+        }
+    }
+
+    fn write(&mut self, table: &Table, entity: &Entity) -> Result<(), StoreError> {
+        let values = table.export_columns().map(|column| {
+            let value = entity
+                .get(column.field.as_str())
+                .cloned()
+                .map(r::Value::from)
+                .unwrap_or(r::Value::Null);
+            (&column.field, value)
+        });
+        match self {
+            Exporter::Csv(writer) => {
+                let record = values
+                    .map(|(_, value)| csv_value(value))
+                    .collect::<Result<Vec<_>, _>>()?;
+                writer.write_record(&record).map_err(write_error)
+            }
+            Exporter::Ndjson(writer) => {
+                let object = r::Value::Object(Object::from_iter(
+                    values.map(|(field, value)| (Word::from(field.as_str()), value)),
+                ));
+                serde_json::to_writer(&mut *writer, &object)?;
+                writer.write_all(b"\n").map_err(write_error)
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), StoreError> {
+        match self {
+            Exporter::Csv(mut writer) => writer.flush().map_err(write_error),
+            Exporter::Ndjson(writer) => writer.flush().map_err(write_error),
+        }
+    }
+}
+
+impl Layout {
+    /// Write the state of all entities of type `entity_type` at `block` to
+    /// `writer` in the given `format` and return how many entities were
+    /// written
+    pub fn export_entities(
+        &self,
+        conn: &mut PgConnection,
+        entity_type: &EntityType,
+        block: BlockNumber,
+        format: ExportFormat,
+        writer: &mut dyn Write,
+    ) -> Result<usize, StoreError> {
+        let table = self.table_for_entity(entity_type)?;
+        let mut exporter = Exporter::new(table, format, writer)?;
+
+        let count = conn.transaction::<_, StoreError, _>(|conn| {
+            // The cursor is closed automatically at the end of the
+            // transaction
+            conn.batch_execute(&format!(
+                "declare {} no scroll cursor for {}",
+                EXPORT_CURSOR,
+                table.export_query(block)
+            ))?;
+            let fetch = format!("fetch forward {} from {}", EXPORT_BATCH_SIZE, EXPORT_CURSOR);
+
+            let mut count = 0;
+            loop {
+                let rows = sql_query(&fetch).load::<EntityData>(conn)?;
+                let done = rows.len() < EXPORT_BATCH_SIZE;
+                for row in rows {
+                    let entity: Entity = row.deserialize_with_layout(self, None)?;
+                    exporter.write(table, &entity)?;
+                    count += 1;
+                }
+                if done {
+                    return Ok(count);
+                }
+            }
+        })?;
+
+        exporter.finish()?;
+        Ok(count)
+    }
+}
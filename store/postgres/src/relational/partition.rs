@@ -0,0 +1,165 @@
+//! Tables for mutable entities can be partitioned by the lower bound of
+//! their block range. Such a table has a default partition and one
+//! partition for each range of blocks; partitions for block ranges are
+//! created as indexing advances. Since a version never moves to another
+//! partition, pruning can drop a partition in its entirety once all the
+//! versions in it were closed before the earliest block the deployment
+//! needs to keep
+use std::fmt::Write;
+
+use diesel::{
+    connection::SimpleConnection,
+    sql_query,
+    sql_types::{Bool, Integer, Text},
+    PgConnection, RunQueryDsl,
+};
+use graph::prelude::{BlockNumber, StoreError, BLOCK_NUMBER_MAX, ENV_VARS};
+
+use crate::{block_range::BLOCK_RANGE_COLUMN, primary::Namespace};
+
+use super::{Layout, SqlName, Table};
+
+/// The suffix for the name of the default partition of a table
+pub(super) const DEFAULT_PARTITION: &str = "default";
+
+/// How many blocks a partition covers when a table does not have any
+/// partitions for block ranges yet and `GRAPH_STORE_PARTITION_BLOCKS` is
+/// not set
+const DEFAULT_PARTITION_BLOCKS: BlockNumber = 1_000_000;
+
+/// A partition of a table for the versions whose block range starts in
+/// `start..end`
+#[derive(QueryableByName)]
+struct Partition {
+    #[diesel(sql_type = Text)]
+    table_name: String,
+    #[diesel(sql_type = Text)]
+    partition_name: String,
+    #[diesel(sql_type = Integer)]
+    start: BlockNumber,
+    #[diesel(sql_type = Integer)]
+    end: BlockNumber,
+}
+
+/// Return all partitions for block ranges of the tables in `namespace`;
+/// default partitions are not included
+fn partitions(
+    conn: &mut PgConnection,
+    namespace: &Namespace,
+) -> Result<Vec<Partition>, StoreError> {
+    const QUERY: &str = r#"
+        select p.relname::text as table_name,
+               c.relname::text as partition_name,
+               m.bounds[1]::int as start,
+               m.bounds[2]::int as "end"
+          from pg_inherits i
+          join pg_class c on c.oid = i.inhrelid
+          join pg_class p on p.oid = i.inhparent
+          join pg_namespace n on n.oid = p.relnamespace,
+               regexp_match(pg_get_expr(c.relpartbound, c.oid),
+                            'FROM \((\d+)\) TO \((\d+)\)') as m(bounds)
+         where n.nspname = $1
+           and m.bounds is not null"#;
+
+    Ok(sql_query(QUERY)
+        .bind::<Text, _>(namespace.as_str())
+        .load::<Partition>(conn)?)
+}
+
+impl Table {
+    /// The qualified name of the partition of this table with the given
+    /// `suffix`
+    pub(super) fn partition_name(&self, namespace: &Namespace, suffix: &str) -> SqlName {
+        let name = SqlName::verbatim(format!("{}${}", self.name, suffix));
+        SqlName::qualified_name(namespace, &name)
+    }
+}
+
+impl Layout {
+    /// Make sure that all partitioned tables have partitions for the
+    /// blocks up to and including `last_block`. If a table does not have
+    /// any partitions for block ranges yet, its first partition starts at
+    /// `first_block`; versions for earlier blocks, for example ones that
+    /// were copied from another deployment, are in the default partition
+    pub fn create_partitions(
+        &self,
+        conn: &mut PgConnection,
+        first_block: BlockNumber,
+        last_block: BlockNumber,
+    ) -> Result<(), StoreError> {
+        if !self.tables.values().any(|table| table.partitioned) {
+            return Ok(());
+        }
+
+        let partitions = partitions(conn, &self.site.namespace)?;
+        let mut ddl = String::new();
+        for table in self.tables.values().filter(|table| table.partitioned) {
+            let last = partitions
+                .iter()
+                .filter(|partition| partition.table_name == table.name.as_str())
+                .max_by_key(|partition| partition.end);
+            let (mut start, size) = match last {
+                Some(partition) => (partition.end, partition.end - partition.start),
+                None if ENV_VARS.store.partition_blocks > 0 => {
+                    (first_block, ENV_VARS.store.partition_blocks)
+                }
+                None => (first_block, DEFAULT_PARTITION_BLOCKS),
+            };
+            while start <= last_block {
+                let end = start.saturating_add(size).min(BLOCK_NUMBER_MAX);
+                writeln!(
+                    ddl,
+                    "create table {partition} partition of {qname} for values from ({start}) to ({end});",
+                    partition = table.partition_name(&self.site.namespace, &format!("p{start}")),
+                    qname = table.qualified_name,
+                )?;
+                start = end;
+            }
+        }
+        if !ddl.is_empty() {
+            conn.batch_execute(&ddl)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the partitions of `table` that only contain versions whose
+    /// block range ends at or before `earliest_block` and return their
+    /// names
+    pub fn drop_pruned_partitions(
+        &self,
+        conn: &mut PgConnection,
+        table: &Table,
+        earliest_block: BlockNumber,
+    ) -> Result<Vec<String>, StoreError> {
+        #[derive(QueryableByName)]
+        struct Needed {
+            #[diesel(sql_type = Bool)]
+            needed: bool,
+        }
+
+        let candidates = partitions(conn, &self.site.namespace)?
+            .into_iter()
+            .filter(|partition| partition.table_name == table.name.as_str())
+            .filter(|partition| partition.end <= earliest_block);
+
+        let mut dropped = Vec::new();
+        for partition in candidates {
+            let qname = SqlName::qualified_name(
+                &self.site.namespace,
+                &SqlName::verbatim(partition.partition_name.clone()),
+            );
+            let Needed { needed } = sql_query(format!(
+                "select exists (select 1 from {qname} \
+                                 where coalesce(upper({BLOCK_RANGE_COLUMN}), {BLOCK_NUMBER_MAX}) > $1) \
+                        as needed"
+            ))
+            .bind::<Integer, _>(earliest_block)
+            .get_result::<Needed>(conn)?;
+            if !needed {
+                conn.batch_execute(&format!("drop table {qname}"))?;
+                dropped.push(partition.partition_name);
+            }
+        }
+        Ok(dropped)
+    }
+}
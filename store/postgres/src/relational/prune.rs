@@ -1,4 +1,4 @@
-use std::{fmt::Write, sync::Arc, time::Instant};
+use std::{collections::HashSet, fmt::Write, sync::Arc, time::Instant};
 
 use diesel::{
     connection::SimpleConnection,
@@ -13,7 +13,7 @@ use graph::{
         BLOCK_NUMBER_MAX,
     },
     schema::InputSchema,
-    slog::{warn, Logger},
+    slog::{info, warn, Logger},
 };
 use itertools::Itertools;
 
@@ -61,6 +61,26 @@ impl Table {
         .get_result::<VidRange>(conn)?;
         Ok((min_vid, max_vid))
     }
+
+    /// Return the first and last vid in this table
+    fn vid_bounds(&self, conn: &mut PgConnection) -> Result<(i64, i64), StoreError> {
+        #[derive(QueryableByName)]
+        struct VidRange {
+            #[diesel(sql_type = BigInt)]
+            min_vid: i64,
+            #[diesel(sql_type = BigInt)]
+            max_vid: i64,
+        }
+
+        let VidRange { min_vid, max_vid } = sql_query(format!(
+            "/* controller=prune */ \
+             select coalesce(min(vid), 0) as min_vid, \
+                    coalesce(max(vid), -1) as max_vid from {src}",
+            src = self.qualified_name,
+        ))
+        .get_result::<VidRange>(conn)?;
+        Ok((min_vid, max_vid))
+    }
 }
 
 /// Utility to copy relevant data out of a source table and into a new
@@ -273,6 +293,14 @@ impl TablePair {
 }
 
 impl Layout {
+    /// The least amount of history that any table in this layout keeps
+    pub(crate) fn min_history_blocks(&self) -> BlockNumber {
+        self.tables
+            .values()
+            .filter_map(|table| table.history_blocks)
+            .fold(self.history_blocks, BlockNumber::min)
+    }
+
     /// Analyze the `tables` and return `VersionStats` for all tables in
     /// this `Layout`
     fn analyze_tables(
@@ -324,12 +352,15 @@ impl Layout {
     }
 
     /// Return all tables and the strategy to prune them withir stats whose ratio of distinct entities
-    /// to versions is less than `prune_ratio`
+    /// to versions is less than `prune_ratio`, together with the request
+    /// for each table. Tables that keep less history than the deployment
+    /// get a request that removes more history than `req`
     fn prunable_tables(
         &self,
         stats: &[VersionStats],
+        archived: &HashSet<String>,
         req: &PruneRequest,
-    ) -> Vec<(&Arc<Table>, PruningStrategy)> {
+    ) -> Vec<(&Arc<Table>, PruningStrategy, PruneRequest)> {
         let mut prunable_tables = self
             .tables
             .values()
@@ -340,12 +371,137 @@ impl Layout {
                     .find(|stats| stats.tablename == table.name.as_str())
                     .map(|stats| (table, stats))
             })
-            .filter_map(|(table, stats)| req.strategy(stats).map(|strat| (table, strat)))
+            .filter_map(|(table, stats)| {
+                let req = match table.history_blocks {
+                    Some(history_blocks) => req.with_history_blocks(history_blocks),
+                    None => *req,
+                };
+                req.strategy(stats).map(|strat| (table, strat, req))
+            })
+            // Partitioned tables shed most of their history by dropping
+            // partitions; deleting the rest is cheaper than rebuilding.
+            // Tables with an archive table can not be replaced by a
+            // rebuilt copy since the archive table inherits from them
+            .map(|(table, strat, req)| {
+                if table.partitioned || archived.contains(table.name.as_str()) {
+                    (table, PruningStrategy::Delete, req)
+                } else {
+                    (table, strat, req)
+                }
+            })
             .collect::<Vec<_>>();
-        prunable_tables.sort_by(|(a, _), (b, _)| a.name.as_str().cmp(b.name.as_str()));
+        prunable_tables.sort_by(|(a, _, _), (b, _, _)| a.name.as_str().cmp(b.name.as_str()));
         prunable_tables
     }
 
+    /// The shortest time-to-live of any table in this layout
+    pub(crate) fn min_ttl(&self) -> Option<BlockNumber> {
+        self.tables.values().filter_map(|table| table.ttl).min()
+    }
+
+    /// Delete versions in tables with a time-to-live that no query can see
+    /// anymore. This is independent of how much history the deployment
+    /// keeps: tables with a time-to-live only keep enough history to
+    /// answer queries for the last `ttl` blocks, and we set the earliest
+    /// block of the table accordingly before deleting anything. Queries at
+    /// a block see a version only if it was written less than the
+    /// time-to-live before that block, and once that is the case for the
+    /// table's earliest block, no query will ever see the version.
+    ///
+    /// Indexing does not use the time-to-live and still sees versions of
+    /// mutable entities that are current, and reverts can make versions
+    /// current again that were closed in a nonfinal block. We therefore
+    /// only delete versions of mutable entities whose block range ended in
+    /// a final block. Immutable entities are never closed, and we delete
+    /// them once they have expired in a final block; handlers can not load
+    /// them anymore after that
+    pub fn prune_expired(
+        &self,
+        logger: &Logger,
+        reporter: &mut dyn PruneReporter,
+        conn: &mut PgConnection,
+        req: &PruneRequest,
+        cancel: &CancelHandle,
+    ) -> Result<(), CancelableError<StoreError>> {
+        let mut tables: Vec<_> = self
+            .tables
+            .values()
+            .filter_map(|table| table.ttl.map(|ttl| (table, ttl)))
+            .collect();
+        tables.sort_by_key(|(table, _)| table.name.as_str());
+
+        let mut expired = Vec::new();
+        for (table, ttl) in tables {
+            let req = req.with_history_blocks(table.history_blocks.map_or(ttl, |hb| hb.min(ttl)));
+            let written_before = req.earliest_block - ttl;
+            if written_before < 0 {
+                continue;
+            }
+
+            reporter.start_table(table.name.as_str());
+            catalog::set_table_earliest_block(conn, &self.site, &table.name, req.earliest_block)?;
+
+            // Versions that are closed before the earliest block are
+            // invisible to queries even if they have not expired yet
+            let (cond, last_block) = if table.immutable {
+                ("block$ <= $1", written_before)
+            } else {
+                (
+                    "coalesce(upper(block_range), 2147483647) <= $2 \
+                     and (lower(block_range) <= $1 or upper(block_range) <= $3)",
+                    req.final_block,
+                )
+            };
+            let (min_vid, max_vid) = table.vid_bounds(conn)?;
+            let mut batch_size = AdaptiveBatchSize::new(table);
+            let mut next_vid = min_vid;
+            let mut deleted = 0;
+            while next_vid <= max_vid {
+                let start = Instant::now();
+                let rows = sql_query(format!(
+                    "/* controller=prune,phase=expire,start_vid={next_vid},batch_size={batch_size} */ \
+                     delete from {qname} \
+                      where {cond} \
+                        and vid >= $4 and vid < $4 + $5",
+                    qname = table.qualified_name,
+                    batch_size = batch_size.size
+                ))
+                .bind::<Integer, _>(written_before)
+                .bind::<Integer, _>(last_block)
+                .bind::<Integer, _>(req.earliest_block)
+                .bind::<BigInt, _>(next_vid)
+                .bind::<BigInt, _>(&batch_size)
+                .execute(conn)?;
+
+                next_vid += batch_size.size;
+                deleted += rows;
+
+                batch_size.adapt(start.elapsed());
+
+                reporter.prune_batch(
+                    table.name.as_str(),
+                    rows,
+                    PrunePhase::Delete,
+                    next_vid > max_vid,
+                );
+                cancel.check_cancel()?;
+            }
+            reporter.finish_table(table.name.as_str());
+            if deleted > 0 {
+                info!(logger, "Deleted expired entity versions";
+                      "table" => table.name.as_str(),
+                      "ttl" => ttl,
+                      "versions" => deleted);
+                expired.push(table);
+            }
+        }
+
+        if !expired.is_empty() {
+            self.analyze_tables(conn, reporter, expired, cancel)?;
+        }
+        Ok(())
+    }
+
     /// Remove all data from the underlying deployment that is not needed to
     /// respond to queries before block `earliest_block`. The `req` is used
     /// to determine which strategy should be used for pruning, rebuild or
@@ -396,7 +552,11 @@ impl Layout {
 
         let stats = self.version_stats(conn, reporter, true, cancel)?;
 
-        let prunable_tables: Vec<_> = self.prunable_tables(&stats, req).into_iter().collect();
+        let archived = self.archived_tables(conn)?;
+        let prunable_tables: Vec<_> = self
+            .prunable_tables(&stats, &archived, req)
+            .into_iter()
+            .collect();
 
         // create a shadow namespace where we will put the copies of our
         // tables, but only create it in the database if we really need it
@@ -413,8 +573,19 @@ impl Layout {
         // that `final_block` is far enough from the subgraph head that it
         // stays final even if a revert happens during this loop, but that
         // is the definition of 'final'
-        for (table, strat) in &prunable_tables {
+        for (table, strat, req) in &prunable_tables {
             reporter.start_table(table.name.as_str());
+            // Queries check the earliest block of tables that keep less
+            // history than the deployment, and it therefore has to be
+            // set before we remove anything
+            if table.history_blocks.is_some() {
+                catalog::set_table_earliest_block(
+                    conn,
+                    &self.site,
+                    &table.name,
+                    req.earliest_block,
+                )?;
+            }
             match strat {
                 PruningStrategy::Rebuild => {
                     if recreate_dst_nsp {
@@ -454,6 +625,16 @@ impl Layout {
                     reporter.finish_switch();
                 }
                 PruningStrategy::Delete => {
+                    if table.partitioned {
+                        let dropped =
+                            self.drop_pruned_partitions(conn, table, req.earliest_block)?;
+                        if !dropped.is_empty() {
+                            info!(logger, "Dropped pruned partitions";
+                                  "table" => table.name.as_str(),
+                                  "partitions" => dropped.join(", "));
+                        }
+                    }
+
                     // Delete all entity versions whose range was closed
                     // before `req.earliest_block`
                     let (min_vid, max_vid) = table.vid_range(conn, 0, req.earliest_block)?;
@@ -494,12 +675,12 @@ impl Layout {
             catalog::drop_schema(conn, dst_nsp.as_str())?;
         }
 
-        for (table, _) in &prunable_tables {
+        for (table, _, req) in &prunable_tables {
             catalog::set_last_pruned_block(conn, &self.site, &table.name, req.earliest_block)?;
         }
 
         // Analyze the new tables
-        let tables = prunable_tables.iter().map(|(table, _)| *table).collect();
+        let tables = prunable_tables.iter().map(|(table, _, _)| *table).collect();
         self.analyze_tables(conn, reporter, tables, cancel)?;
 
         reporter.finish();
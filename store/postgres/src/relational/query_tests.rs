@@ -2,7 +2,11 @@ use std::{collections::BTreeSet, sync::Arc};
 
 use diesel::{debug_query, pg::Pg};
 use graph::{
-    prelude::{r, serde_json as json, DeploymentHash, EntityFilter},
+    components::store::{AttributeNames, EntityOrderByChild, EntityOrderByChildInfo},
+    prelude::{
+        r, serde_json as json, BlockNumber, DeploymentHash, EntityCollection, EntityFilter,
+        EntityOrder, EntityRange, ValueType, BLOCK_NUMBER_MAX,
+    },
     schema::InputSchema,
 };
 
@@ -12,7 +16,7 @@ use crate::{
     relational_queries::FromColumnValue,
 };
 
-use crate::relational_queries::Filter;
+use crate::relational_queries::{Filter, FilterCollection, FilterQuery};
 
 #[test]
 fn gql_value_from_bytes() {
@@ -85,3 +89,168 @@ fn prefix() {
     let filter = EntityFilter::In("address".to_string(), vec!["0xbeef".into()]);
     filter_contains(filter, r#"substring(c."address", 1, 64) in ($1)"#);
 }
+
+#[test]
+fn interface_query_limits_each_implementer() {
+    const SCHEMA: &str = "
+    interface Pet { id: ID!, name: String! }
+    type Cat implements Pet @entity { id: ID!, name: String! }
+    type Dog implements Pet @entity { id: ID!, name: String! }";
+    let layout = test_layout(SCHEMA);
+
+    // The number of times the SQL for querying all pets has `limit 15`
+    let branch_limits = |order: EntityOrder, range: EntityRange| {
+        let entities = ["Cat", "Dog"]
+            .into_iter()
+            .map(|name| {
+                (
+                    layout.input_schema.entity_type(name).unwrap(),
+                    AttributeNames::All,
+                )
+            })
+            .collect();
+        let collection = FilterCollection::new(
+            &layout,
+            EntityCollection::All(entities),
+            None,
+            BLOCK_NUMBER_MAX,
+        )
+        .unwrap();
+        let query = FilterQuery::new(
+            &collection,
+            &layout,
+            None,
+            order,
+            range,
+            None,
+            BLOCK_NUMBER_MAX,
+            None,
+            &layout.site,
+        )
+        .unwrap();
+        debug_query::<Pg, _>(&query)
+            .to_string()
+            .matches("limit 15")
+            .count()
+    };
+    let range = EntityRange {
+        first: Some(10),
+        skip: 5,
+    };
+    let by_name = EntityOrder::Ascending("name".to_string(), ValueType::String);
+
+    // Ordering by columns of the tables gets pushed into each branch
+    assert_eq!(2, branch_limits(by_name.clone(), range.clone()));
+    assert_eq!(2, branch_limits(EntityOrder::Default, range.clone()));
+
+    // Without an order or a limit, the branches are not limited
+    assert_eq!(0, branch_limits(EntityOrder::Unordered, range));
+    let unlimited = EntityRange {
+        first: None,
+        skip: 0,
+    };
+    assert_eq!(0, branch_limits(by_name, unlimited));
+}
+
+#[test]
+fn sort_by_child_attribute() {
+    const SCHEMA: &str = "
+    type Song @entity {
+        id: ID!,
+        cover: Cover,
+        writer: Writer!,
+        stat: Stat @derivedFrom(field: \"song\")
+    }
+    type Cover @entity(immutable: true) { id: ID!, size: Int8! }
+    type Writer @entity { id: ID!, born: Timestamp }
+    type Stat @entity(immutable: true) { id: ID!, song: Song!, played: Int8! }";
+    let layout = test_layout(SCHEMA);
+
+    // The SQL for querying songs ordered by `attr` of the child `child`
+    let child_sql = |child: &str, attr: &str, join: &str, derived: bool| {
+        let song = layout.input_schema.entity_type("Song").unwrap();
+        let collection = FilterCollection::new(
+            &layout,
+            EntityCollection::All(vec![(song, AttributeNames::All)]),
+            None,
+            BLOCK_NUMBER_MAX,
+        )
+        .unwrap();
+        let order = EntityOrder::ChildDescending(EntityOrderByChild::Object(
+            EntityOrderByChildInfo {
+                sort_by_attribute: attr.to_string(),
+                join_attribute: join.to_string(),
+                derived,
+            },
+            layout.input_schema.entity_type(child).unwrap(),
+        ));
+        let query = FilterQuery::new(
+            &collection,
+            &layout,
+            None,
+            order,
+            EntityRange::first(10),
+            None,
+            BLOCK_NUMBER_MAX,
+            None,
+            &layout.site,
+        )
+        .unwrap();
+        debug_query::<Pg, _>(&query).to_string()
+    };
+
+    // Immutable children only have a `block$` column
+    let sql = child_sql("Cover", "size", "cover", false);
+    assert!(sql.contains(r#"cc."id" = c."cover" and cc."block$" <= $"#));
+    assert!(sql.contains(r#"cc."size" desc"#));
+
+    let sql = child_sql("Writer", "born", "writer", false);
+    assert!(sql.contains(r#"cc."id" = c."writer" and cc."block_range" @> $"#));
+    assert!(sql.contains(r#"cc."born" desc"#));
+
+    // Derived immutable children join on the child's column
+    let sql = child_sql("Stat", "played", "song", true);
+    assert!(sql.contains(r#"cc."song" = c."id" and cc."block$" <= $"#));
+    assert!(sql.contains(r#"cc."played" desc"#));
+}
+
+#[test]
+fn ttl_hides_expired_versions() {
+    const SCHEMA: &str = "
+    type Offer @entity @ttl(blocks: 10) { id: ID!, name: String! }
+    type Bid @entity(immutable: true) @ttl(blocks: 10) { id: ID!, name: String! }
+    type Thing @entity { id: ID!, name: String! }";
+    let layout = test_layout(SCHEMA);
+
+    // The SQL for querying all entities of type `name` at `block`
+    let sql = |name: &str, block: BlockNumber| {
+        let entity_type = layout.input_schema.entity_type(name).unwrap();
+        let collection = FilterCollection::new(
+            &layout,
+            EntityCollection::All(vec![(entity_type, AttributeNames::All)]),
+            None,
+            block,
+        )
+        .unwrap();
+        let query = FilterQuery::new(
+            &collection,
+            &layout,
+            None,
+            EntityOrder::Default,
+            EntityRange::first(10),
+            None,
+            block,
+            None,
+            &layout.site,
+        )
+        .unwrap();
+        debug_query::<Pg, _>(&query).to_string()
+    };
+
+    assert!(sql("Offer", 100).contains("and lower(c.block_range) > 90"));
+    assert!(sql("Bid", 100).contains("and c.block$ > 90"));
+    // Tables without a time-to-live and queries for the latest state
+    // without a block constraint are not filtered
+    assert!(!sql("Thing", 100).contains("> 90"));
+    assert!(!sql("Offer", BLOCK_NUMBER_MAX).contains("lower(c.block_range) >"));
+}
@@ -58,20 +58,21 @@ use std::fmt;
 use std::ops::Range;
 use std::sync::Arc;
 
-use diesel::{sql_query, PgConnection, RunQueryDsl as _};
+use diesel::{sql_query, Connection as _, PgConnection, RunQueryDsl as _};
 
-use diesel::sql_types::{Integer, Timestamptz};
+use diesel::sql_types::{BigInt, Integer, Nullable, Timestamptz};
 use graph::blockchain::BlockTime;
-use graph::components::store::{BlockNumber, StoreError};
+use graph::components::store::{BlockNumber, RollupBackfillReporter, StoreError};
 use graph::constraint_violation;
 use graph::data::store::IdType;
+use graph::prelude::{CancelHandle, CancelToken as _, CancelableError};
 use graph::schema::{
     Aggregate, AggregateFn, Aggregation, AggregationInterval, ExprVisitor, VisitExpr,
 };
 use graph::sqlparser::ast as p;
 use graph::sqlparser::parser::ParserError;
 
-use crate::relational::Table;
+use crate::relational::{Layout, Table};
 
 use super::{Column, SqlName};
 
@@ -144,6 +145,8 @@ pub(crate) struct Agg<'a> {
     aggregate: &'a Aggregate,
     src_columns: Vec<&'a str>,
     expr: String,
+    /// The rewritten `weight` expression for `weighted_avg`
+    weight: Option<String>,
     agg_column: &'a Column,
 }
 
@@ -153,12 +156,21 @@ impl<'a> Agg<'a> {
         src_table: &'a Table,
         agg_table: &'a Table,
     ) -> Result<Self, StoreError> {
-        let (expr, src_columns) = rewrite(src_table, &aggregate.arg)?;
+        let (expr, mut src_columns) = rewrite(src_table, &aggregate.arg)?;
+        let weight = match &aggregate.weight {
+            Some(weight) => {
+                let (weight, weight_columns) = rewrite(src_table, weight)?;
+                src_columns.extend(weight_columns);
+                Some(weight)
+            }
+            None => None,
+        };
         let agg_column = agg_table.column_for_field(&aggregate.name)?;
         Ok(Self {
             aggregate,
             src_columns,
             expr,
+            weight,
             agg_column,
         })
     }
@@ -179,6 +191,19 @@ impl<'a> Agg<'a> {
                 write!(w, "arg_max_{}(({}, {time}))", sql_type, src)?
             }
             Count => write!(w, "count(*)")?,
+            CountDistinct => write!(w, "count(distinct {})", src)?,
+            WeightedAvg => {
+                // Validation makes sure that `weighted_avg` always has a
+                // weight. Compute in `numeric` so that integer values and
+                // weights do not lead to integer division, and use 0 when
+                // the weights add up to 0 since aggregate fields are
+                // usually not nullable
+                let weight = self.weight.as_deref().unwrap_or("1");
+                write!(
+                    w,
+                    "coalesce(sum(({src})::numeric * ({weight})::numeric) / nullif(sum(({weight})::numeric), 0), 0)"
+                )?
+            }
         }
         write!(w, " as \"{}\"", self.agg_column.name)
     }
@@ -206,6 +231,11 @@ impl<'a> Agg<'a> {
                 return self.aggregate_over(&name, time, w);
             }
             Count => write!(w, "sum(\"{}\")", self.agg_column.name)?,
+            CountDistinct | WeightedAvg => {
+                // These can not be cumulative, so the previous value is
+                // always `null` and we just pick the value for the bucket
+                write!(w, "max(\"{}\")", self.agg_column.name)?
+            }
         }
         write!(w, " as \"{}\"", self.agg_column.name)
     }
@@ -226,8 +256,10 @@ impl<'a> Agg<'a> {
 #[derive(Debug, Clone)]
 pub(crate) struct Rollup {
     pub(crate) interval: AggregationInterval,
-    #[allow(dead_code)]
     agg_table: Arc<Table>,
+    src_table: SqlName,
+    /// Whether any of the aggregates are cumulative
+    cumulative: bool,
     insert_sql: String,
 }
 
@@ -254,15 +286,104 @@ impl Rollup {
             &dimensions,
             &aggregates,
         );
+        let cumulative = sql.has_cumulative_aggregates();
         let mut insert_sql = String::new();
         sql.insert(&mut insert_sql)?;
         Ok(Self {
             interval,
             agg_table,
+            src_table: src_table.qualified_name.clone(),
+            cumulative,
             insert_sql,
         })
     }
 
+    /// The table into which this rollup writes
+    pub(crate) fn agg_table(&self) -> &Table {
+        &self.agg_table
+    }
+
+    /// Return the buckets that a backfill of this rollup needs to look at:
+    /// all buckets from the one containing the earliest source data up to
+    /// the last one that ends at or before `end`. The buckets are in
+    /// increasing order of their start time
+    pub(crate) fn backfill_buckets(
+        &self,
+        conn: &mut PgConnection,
+        end: BlockTime,
+    ) -> Result<Vec<Range<BlockTime>>, StoreError> {
+        #[derive(QueryableByName)]
+        struct Start {
+            #[diesel(sql_type = Nullable<BigInt>)]
+            secs: Option<i64>,
+        }
+
+        let Start { secs } = sql_query(format!(
+            "select extract(epoch from min(timestamp))::int8 as secs from {}",
+            self.src_table
+        ))
+        .get_result::<Start>(conn)?;
+        let buckets = secs
+            .map(|secs| self.interval.buckets(BlockTime::since_epoch(secs, 0), end))
+            .unwrap_or_default();
+        Ok(buckets)
+    }
+
+    /// Fill `bucket` from the source data unless the aggregation already
+    /// has values for it. The values are marked as belonging to the last
+    /// block that wrote source data in `bucket` so that reverting past
+    /// that block removes them. Return `true` if values were filled in
+    ///
+    /// If the aggregation has cumulative aggregates, a bucket that already
+    /// has values is recomputed, too, since its running totals do not
+    /// include any buckets before it that were filled. The recomputed
+    /// values keep the block of the values they replace
+    pub(crate) fn backfill(
+        &self,
+        conn: &mut PgConnection,
+        bucket: &Range<BlockTime>,
+    ) -> Result<bool, StoreError> {
+        #[derive(QueryableByName)]
+        struct Bucket {
+            #[diesel(sql_type = Nullable<Integer>)]
+            agg_block: Option<BlockNumber>,
+            #[diesel(sql_type = Nullable<Integer>)]
+            src_block: Option<BlockNumber>,
+        }
+
+        let Bucket {
+            agg_block,
+            src_block,
+        } = sql_query(format!(
+            "select (select max(block$) from {agg} where timestamp = $1) as agg_block, \
+                    (select max(block$) from {src} \
+                      where timestamp >= $1 and timestamp < $2) as src_block",
+            agg = self.agg_table.qualified_name,
+            src = self.src_table,
+        ))
+        .bind::<Timestamptz, _>(bucket.start)
+        .bind::<Timestamptz, _>(bucket.end)
+        .get_result::<Bucket>(conn)?;
+
+        match (agg_block, src_block) {
+            (None, Some(block)) => {
+                self.insert(conn, bucket, block)?;
+                Ok(true)
+            }
+            (Some(block), Some(_)) if self.cumulative => {
+                sql_query(format!(
+                    "delete from {} where timestamp = $1",
+                    self.agg_table.qualified_name
+                ))
+                .bind::<Timestamptz, _>(bucket.start)
+                .execute(conn)?;
+                self.insert(conn, bucket, block)?;
+                Ok(false)
+            }
+            (Some(_), _) | (None, None) => Ok(false),
+        }
+    }
+
     pub(crate) fn insert(
         &self,
         conn: &mut PgConnection,
@@ -277,6 +398,59 @@ impl Rollup {
     }
 }
 
+impl Layout {
+    /// Fill the buckets of all aggregations that end at or before `end`
+    /// and that are missing, for example, because the aggregation was
+    /// added to a deployment that was grafted onto one with the
+    /// timeseries, from the data in the timeseries. Each batch of
+    /// `batch_size` buckets is filled in its own transaction. Since
+    /// buckets that already have values are not filled again, an
+    /// interrupted backfill can simply be restarted. Return the number of
+    /// buckets that were filled
+    ///
+    /// For aggregations with cumulative aggregates, buckets that already
+    /// have values are recomputed in order so that their running totals
+    /// include the filled buckets. That happens on every run, so that a
+    /// restart also fixes buckets that an interrupted backfill did not get
+    /// to anymore
+    pub fn rollup_backfill(
+        &self,
+        conn: &mut PgConnection,
+        end: BlockTime,
+        batch_size: usize,
+        reporter: &mut dyn RollupBackfillReporter,
+        cancel: &CancelHandle,
+    ) -> Result<usize, CancelableError<StoreError>> {
+        let batch_size = batch_size.max(1);
+        let mut total = 0;
+        for rollup in &self.rollups {
+            let table = rollup.agg_table().name.as_str();
+            let buckets = rollup.backfill_buckets(conn, end)?;
+            reporter.start_table(table, buckets.len());
+
+            let mut done = 0;
+            let mut filled = 0;
+            for batch in buckets.chunks(batch_size) {
+                filled += conn.transaction(|conn| -> Result<usize, StoreError> {
+                    let mut filled = 0;
+                    for bucket in batch {
+                        if rollup.backfill(conn, bucket)? {
+                            filled += 1;
+                        }
+                    }
+                    Ok(filled)
+                })?;
+                cancel.check_cancel()?;
+                done += batch.len();
+                reporter.backfill_batch(table, done, filled);
+            }
+            reporter.finish_table(table, filled);
+            total += filled;
+        }
+        Ok(total)
+    }
+}
+
 struct RollupSql<'a> {
     interval: AggregationInterval,
     src_table: &'a SqlName,
@@ -592,6 +766,20 @@ mod tests {
         total_count: Int8! @aggregate(fn: "count", cumulative: true)
         total_sum: BigDecimal! @aggregate(fn: "sum", arg: "amount", cumulative: true)
       }
+
+      type Traders @aggregation(intervals: ["day"], source: "Data") {
+        id: Int8!
+        timestamp: Timestamp!
+        tokens: Int8! @aggregate(fn: "count_distinct", arg: "token")
+        vwap: BigDecimal! @aggregate(fn: "weighted_avg", arg: "price", weight: "amount")
+      }
+
+      type Running @aggregation(intervals: ["day"], source: "Data", cumulative: true) {
+        id: Int8!
+        timestamp: Timestamp!
+        total: BigDecimal! @aggregate(fn: "sum", arg: "amount")
+        daily: BigDecimal! @aggregate(fn: "sum", arg: "amount", cumulative: false)
+      }
       "#;
 
         const STATS_HOUR_SQL: &str = r#"\
@@ -664,6 +852,43 @@ mod tests {
         select id, timestamp, $3 as block$, "count", "sum", "total_count", "total_sum" from combined
         "#;
 
+        const TRADERS_SQL: &str = r#"\
+        insert into "sgd007"."traders_day"(id, timestamp, block$, "tokens", "vwap") \
+        select max(id) as id, timestamp, $3, count(distinct "token") as "tokens", \
+               coalesce(sum(("price")::numeric * ("amount")::numeric) / nullif(sum(("amount")::numeric), 0), 0) as "vwap" \
+          from (select id, date_bin('86400s', timestamp, 'epoch'::timestamptz) as timestamp, "amount", "price", "token" \
+                  from "sgd007"."data" \
+                 where "sgd007"."data".timestamp >= $1 \
+                   and "sgd007"."data".timestamp < $2 \
+                 order by "sgd007"."data".timestamp) data \
+         group by timestamp"#;
+
+        const RUNNING_SQL: &str = r#"\
+        with bucket as (
+            select max(id) as id, timestamp, sum("amount") as "total",
+                   sum("amount") as "daily"
+              from (select id, date_bin('86400s', timestamp, 'epoch'::timestamptz) as timestamp, "amount"
+                      from "sgd007"."data"
+                     where "sgd007"."data".timestamp >= $1
+                       and "sgd007"."data".timestamp < $2
+                     order by "sgd007"."data".timestamp) data
+              group by timestamp),
+             prev as (select bucket.id, bucket.timestamp,
+                             prev."total", null::numeric as "daily"
+                        from bucket cross join lateral (
+                             select * from "sgd007"."running_day" prev
+                              where prev.timestamp < $1
+                              order by prev.timestamp desc limit 1) prev),
+             combined as (select id, timestamp,
+                                 sum("total") as "total", sum("daily") as "daily" from (
+                            select *, 1 as seq from prev
+                            union all
+                            select *, 2 as seq from bucket) u
+                          group by id, timestamp)
+        insert into "sgd007"."running_day"(id, timestamp, block$, "total", "daily")
+        select id, timestamp, $3 as block$, "total", "daily" from combined
+        "#;
+
         #[track_caller]
         fn rollup_for<'a>(layout: &'a Layout, table_name: &str) -> &'a Rollup {
             layout
@@ -679,7 +904,7 @@ mod tests {
         let site = Arc::new(make_dummy_site(hash, nsp, "rollup".to_string()));
         let catalog = Catalog::for_tests(site.clone(), BTreeSet::new()).unwrap();
         let layout = Layout::new(site, &schema, catalog).unwrap();
-        assert_eq!(5, layout.rollups.len());
+        assert_eq!(7, layout.rollups.len());
 
         // Intervals are non-decreasing
         assert!(layout.rollups[0].interval <= layout.rollups[1].interval);
@@ -698,5 +923,11 @@ mod tests {
 
         let lifetime = rollup_for(&layout, "lifetime_day");
         check_eqv(LIFETIME_SQL, &lifetime.insert_sql);
+
+        let traders = rollup_for(&layout, "traders_day");
+        check_eqv(TRADERS_SQL, &traders.insert_sql);
+
+        let running = rollup_for(&layout, "running_day");
+        check_eqv(RUNNING_SQL, &running.insert_sql);
     }
 }
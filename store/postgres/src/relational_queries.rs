@@ -10,7 +10,9 @@ use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
 use diesel::query_dsl::RunQueryDsl;
 use diesel::result::{Error as DieselError, QueryResult};
 use diesel::sql_types::Untyped;
-use diesel::sql_types::{Array, BigInt, Binary, Bool, Int8, Integer, Jsonb, Text, Timestamptz};
+use diesel::sql_types::{
+    Array, BigInt, Binary, Bool, Int8, Integer, Jsonb, Nullable, Numeric, Text, Timestamptz,
+};
 use graph::components::store::write::{EntityWrite, RowGroup, WriteChunk};
 use graph::components::store::{Child as StoreChild, DerivedEntityQuery};
 use graph::data::store::{Id, IdType, NULL};
@@ -18,9 +20,10 @@ use graph::data::store::{IdList, IdRef, QueryObject};
 use graph::data::value::{Object, Word};
 use graph::data_source::CausalityRegion;
 use graph::prelude::{
-    anyhow, r, serde_json, BlockNumber, ChildMultiplicity, Entity, EntityCollection, EntityFilter,
-    EntityLink, EntityOrder, EntityOrderByChild, EntityOrderByChildInfo, EntityRange, EntityWindow,
-    ParentLink, QueryExecutionError, StoreError, Value, ENV_VARS,
+    anyhow, r, serde_json, BlockNumber, ChildMultiplicity, Entity, EntityAggregate,
+    EntityCollection, EntityCursor, EntityFilter, EntityLink, EntityOrder, EntityOrderByChild,
+    EntityOrderByChildInfo, EntityRange, EntityWindow, FulltextOptions, ParentLink,
+    QueryExecutionError, SpatialArea, StoreError, Value, ENV_VARS,
 };
 use graph::schema::{EntityKey, EntityType, FulltextAlgorithm, FulltextConfig, InputSchema};
 use graph::{components::store::AttributeNames, data::store::scalar};
@@ -649,7 +652,7 @@ impl std::fmt::Display for SqlValue<'_> {
 
 /// A `QueryValue` makes it possible to bind a `Value` into a SQL query
 /// using the metadata from Column
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct QueryValue<'a> {
     value: SqlValue<'a>,
     column_type: &'a ColumnType,
@@ -884,6 +887,14 @@ impl ContainsOp {
             NotLike | NotILike => true,
         }
     }
+
+    fn nocase(&self) -> bool {
+        use ContainsOp::*;
+        match self {
+            Like | NotLike => false,
+            ILike | NotILike => true,
+        }
+    }
 }
 
 impl QueryFragment<Pg> for ContainsOp {
@@ -1148,6 +1159,10 @@ pub struct QueryChild<'a> {
     child_filter: Filter<'a>,
     derived: bool,
     br_column: BlockRangeColumn<'a>,
+    /// The qualifiers for the parent and the child table. Child filters
+    /// can be nested, and each level needs its own table alias
+    parent_qual: ColumnQual,
+    child_qual: ColumnQual,
 }
 
 impl<'a> QueryChild<'a> {
@@ -1156,8 +1171,9 @@ impl<'a> QueryChild<'a> {
         parent_table: &'a Table,
         child: &'a StoreChild,
         block: BlockNumber,
+        parent_qual: ColumnQual,
     ) -> Result<Self, StoreError> {
-        const CHILD_PREFIX: &str = "i.";
+        let child_qual = parent_qual.child();
 
         let StoreChild {
             attr,
@@ -1184,8 +1200,8 @@ impl<'a> QueryChild<'a> {
                 child_table.primary_key(),
             )
         };
-        let br_column = BlockRangeColumn::new(child_table, CHILD_PREFIX, block);
-        let child_filter = Filter::new(layout, child_table, filter, block, ColumnQual::Child)?;
+        let br_column = BlockRangeColumn::new(child_table, child_qual.prefix(), block);
+        let child_filter = Filter::new(layout, child_table, filter, block, child_qual)?;
 
         Ok(Self {
             parent_column,
@@ -1194,6 +1210,8 @@ impl<'a> QueryChild<'a> {
             child_filter,
             derived,
             br_column,
+            parent_qual,
+            child_qual,
         })
     }
 }
@@ -1209,15 +1227,18 @@ impl<'a> QueryFragment<Pg> for QueryChild<'a> {
             child_filter,
             derived,
             br_column,
+            parent_qual,
+            child_qual,
         } = self;
         let derived = *derived;
 
-        let child_prefix = "i.";
-        let parent_prefix = "c.";
+        let child_prefix = child_qual.prefix();
+        let parent_prefix = parent_qual.prefix();
 
         out.push_sql("exists (select 1 from ");
         out.push_sql(child_table.qualified_name.as_str());
-        out.push_sql(" as i");
+        out.push_sql(" as ");
+        out.push_sql(child_qual.alias());
 
         out.push_sql(" where ");
 
@@ -1266,6 +1287,7 @@ impl<'a> QueryFragment<Pg> for QueryChild<'a> {
 
         // Match by block
         br_column.contains(&mut out, is_type_c_or_d)?;
+        br_column.not_expired(&mut out);
 
         out.push_sql(" and ");
 
@@ -1277,44 +1299,66 @@ impl<'a> QueryFragment<Pg> for QueryChild<'a> {
     }
 }
 
+/// The table aliases for the tables in nested child filters. The first
+/// level of child filters uses `i`, the next one `i2` etc. The length of
+/// this list limits how deeply child filters can be nested
+const CHILD_ALIASES: [(&str, &str); 3] = [("i", "i."), ("i2", "i2."), ("i3", "i3.")];
+
 /// The qualifier for a column to indicate whether we use the main table or
-/// a child table
+/// a child table. Child tables are numbered by how deeply the child filter
+/// that uses them is nested, starting at 1
 #[derive(Copy, Clone, Debug)]
 enum ColumnQual {
     Main,
-    Child,
+    Child(usize),
 }
 
 impl ColumnQual {
     fn with<'a>(&self, column: &'a Column) -> QualColumn<'a> {
         match self {
             ColumnQual::Main => QualColumn::Main(column),
-            ColumnQual::Child => QualColumn::Child(column),
+            ColumnQual::Child(level) => QualColumn::Child(column, *level),
         }
     }
 
     /// Return `true` if we allow a nested child filter. That's allowed as
-    /// long as we are filtering the main table
+    /// long as we have a table alias for the next level of nesting
     fn allow_child(&self) -> bool {
         match self {
             ColumnQual::Main => true,
-            ColumnQual::Child => false,
+            ColumnQual::Child(level) => *level < CHILD_ALIASES.len(),
+        }
+    }
+
+    /// The qualifier for a child filter nested in a filter that uses `self`
+    fn child(&self) -> ColumnQual {
+        match self {
+            ColumnQual::Main => ColumnQual::Child(1),
+            ColumnQual::Child(level) => ColumnQual::Child(level + 1),
+        }
+    }
+
+    fn alias(&self) -> &'static str {
+        match self {
+            ColumnQual::Main => "c",
+            ColumnQual::Child(level) => CHILD_ALIASES[level - 1].0,
         }
     }
 
     fn prefix(&self) -> &'static str {
         match self {
             ColumnQual::Main => "c.",
-            ColumnQual::Child => "i.",
+            ColumnQual::Child(level) => CHILD_ALIASES[level - 1].1,
         }
     }
 }
 
-/// A qualified column name. This is either `c.{column}` or `i.{column}`
+/// A qualified column name. This is either `c.{column}` or, for the tables
+/// of child filters, `i.{column}`, `i2.{column}` etc.
 #[derive(Debug)]
 pub enum QualColumn<'a> {
     Main(&'a Column),
-    Child(&'a Column),
+    Child(&'a Column, usize),
 }
 impl QualColumn<'_> {
     fn column_type(&self) -> &ColumnType {
@@ -1323,25 +1367,22 @@ impl QualColumn<'_> {
 
     fn prefix(&self) -> &str {
         match self {
-            QualColumn::Main(_) => "c.",
-            QualColumn::Child(_) => "i.",
+            QualColumn::Main(_) => ColumnQual::Main.prefix(),
+            QualColumn::Child(_, level) => ColumnQual::Child(*level).prefix(),
         }
     }
 
     fn column(&self) -> &Column {
         match self {
             QualColumn::Main(column) => column,
-            QualColumn::Child(column) => column,
+            QualColumn::Child(column, _) => column,
         }
     }
 }
 
 impl std::fmt::Display for QualColumn<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            QualColumn::Main(column) => write!(f, "{}", column.name),
-            QualColumn::Child(column) => write!(f, "{}", column.name),
-        }
+        write!(f, "{}", self.column().name)
     }
 }
 
@@ -1370,15 +1411,30 @@ pub enum Filter<'a> {
         op: ContainsOp,
         pattern: QueryValue<'a>,
     },
-    StartsOrEndsWith {
+    /// Compare `column` to `pattern` with a `like` or regular expression
+    /// operator `op`
+    Pattern {
         column: QualColumn<'a>,
         op: &'static str,
         pattern: String,
     },
     ChangeBlockGte(BlockRangeColumn<'a>),
     Child(Box<QueryChild<'a>>),
-    /// The value is never null for fulltext queries
-    Fulltext(QualColumn<'a>, QueryValue<'a>),
+    /// Match the tsvector `column` against the tsquery `query`
+    Fulltext {
+        column: QualColumn<'a>,
+        query: &'a str,
+        options: &'a FulltextOptions,
+    },
+    /// Check that the point with coordinates `latitude` and `longitude`
+    /// lies within `area`. Distances are computed with PostGIS if the
+    /// database has it, and with the haversine formula otherwise
+    Spatial {
+        latitude: QualColumn<'a>,
+        longitude: QualColumn<'a>,
+        area: &'a SpatialArea,
+        postgis: bool,
+    },
 }
 
 impl<'a> Filter<'a> {
@@ -1391,6 +1447,25 @@ impl<'a> Filter<'a> {
         Self::new(layout, table, filter, block, ColumnQual::Main)
     }
 
+    /// The fulltext search whose matches should be highlighted in the
+    /// results. The search is either this filter or the first filter of a
+    /// conjunction since that is how the `text` and `where` arguments of a
+    /// query are combined
+    fn highlight(&self) -> Option<(&Column, &'a str, &'a FulltextOptions)> {
+        let filter = match self {
+            Filter::And(filters) => filters.first()?,
+            filter => filter,
+        };
+        match filter {
+            Filter::Fulltext {
+                column,
+                query,
+                options,
+            } if options.highlight => Some((column.column(), *query, *options)),
+            _ => None,
+        }
+    }
+
     fn new(
         layout: &'a Layout,
         table: &'a Table,
@@ -1427,7 +1502,7 @@ impl<'a> Filter<'a> {
                     } else {
                         format!("%{}", s)
                     };
-                    Ok(Filter::StartsOrEndsWith {
+                    Ok(Filter::Pattern {
                         column,
                         op,
                         pattern,
@@ -1524,6 +1599,27 @@ impl<'a> Filter<'a> {
             })
         }
 
+        fn matches<'s>(
+            qual: ColumnQual,
+            table: &'s Table,
+            attr: &String,
+            value: &Value,
+            op: &'static str,
+        ) -> Result<Filter<'s>, StoreError> {
+            let column = table.column_for_field(attr)?;
+            match (value, &column.column_type) {
+                (Value::String(s), ColumnType::String) => Ok(Filter::Pattern {
+                    column: qual.with(column),
+                    op,
+                    pattern: s.clone(),
+                }),
+                _ => Err(StoreError::UnsupportedFilter(
+                    op.trim().to_owned(),
+                    value.to_string(),
+                )),
+            }
+        }
+
         use Comparison as C;
         use ContainsOp as K;
         use EntityFilter::*;
@@ -1586,6 +1682,9 @@ impl<'a> Filter<'a> {
             NotEndsWithNoCase(attr, value) => {
                 starts_or_ends_with(qual, table, attr, value, " not ilike ", false)
             }
+            MatchesNoCase(attr, value) => matches(qual, table, attr, value, " ilike "),
+            Regex(attr, value) => matches(qual, table, attr, value, " ~ "),
+            RegexNoCase(attr, value) => matches(qual, table, attr, value, " ~* "),
 
             ChangeBlockGte(num) => Ok(F::ChangeBlockGte(BlockRangeColumn::new(
                 table,
@@ -1599,18 +1698,40 @@ impl<'a> Filter<'a> {
                         filter.to_string(),
                     ));
                 }
-                let child = QueryChild::new(layout, table, child, block)?;
+                let child = QueryChild::new(layout, table, child, block, qual)?;
                 Ok(F::Child(Box::new(child)))
             }
-            Fulltext(attr, value) => {
-                let (column, value) = column_and_value(qual, table, attr, value)?;
-                if value.is_null() {
-                    return Err(StoreError::UnsupportedFilter(
+            Fulltext(attr, value, options) => {
+                let column = table.column_for_field(attr)?;
+                match value {
+                    Value::String(query) if column.is_fulltext() => Ok(F::Fulltext {
+                        column: qual.with(column),
+                        query,
+                        options,
+                    }),
+                    _ => Err(StoreError::UnsupportedFilter(
                         "fulltext".to_owned(),
                         value.to_string(),
-                    ));
+                    )),
                 }
-                Ok(F::Fulltext(column, value))
+            }
+            Spatial(latitude, longitude, area) => {
+                let coordinate = |attr: &String| -> Result<QualColumn<'a>, StoreError> {
+                    let column = table.column_for_field(attr)?;
+                    if column.column_type != ColumnType::BigDecimal || column.is_list() {
+                        return Err(StoreError::UnsupportedFilter(
+                            "spatial".to_owned(),
+                            attr.to_string(),
+                        ));
+                    }
+                    Ok(qual.with(column))
+                };
+                Ok(F::Spatial {
+                    latitude: coordinate(latitude)?,
+                    longitude: coordinate(longitude)?,
+                    area,
+                    postgis: layout.catalog.has_postgis,
+                })
             }
         }
     }
@@ -1661,13 +1782,101 @@ impl<'a> Filter<'a> {
 
     fn fulltext<'b>(
         column: &'b QualColumn,
-        qv: &'b QueryValue,
+        query: &'b str,
+        options: &'b FulltextOptions,
         mut out: AstPass<'_, 'b, Pg>,
     ) -> QueryResult<()> {
-        assert!(!qv.is_null());
         column.walk_ast(out.reborrow())?;
         out.push_sql(Comparison::Match.as_str());
-        qv.walk_ast(out)
+        push_tsquery(query, options, &mut out)
+    }
+
+    fn spatial<'b>(
+        latitude: &'b QualColumn,
+        longitude: &'b QualColumn,
+        area: &'b SpatialArea,
+        postgis: bool,
+        mut out: AstPass<'_, 'b, Pg>,
+    ) -> QueryResult<()> {
+        /// The mean radius of the earth in meters
+        const EARTH_RADIUS: &str = "6371008.8";
+
+        match area {
+            SpatialArea::Box {
+                min_latitude,
+                min_longitude,
+                max_latitude,
+                max_longitude,
+            } => {
+                // If the box crosses the antimeridian, the longitude can
+                // be on either side of it
+                let lon_op = if min_longitude > max_longitude {
+                    " or "
+                } else {
+                    " and "
+                };
+                out.push_sql("(");
+                latitude.walk_ast(out.reborrow())?;
+                out.push_sql(" >= ");
+                out.push_bind_param::<Numeric, _>(min_latitude)?;
+                out.push_sql(" and ");
+                latitude.walk_ast(out.reborrow())?;
+                out.push_sql(" <= ");
+                out.push_bind_param::<Numeric, _>(max_latitude)?;
+                out.push_sql(" and (");
+                longitude.walk_ast(out.reborrow())?;
+                out.push_sql(" >= ");
+                out.push_bind_param::<Numeric, _>(min_longitude)?;
+                out.push_sql(lon_op);
+                longitude.walk_ast(out.reborrow())?;
+                out.push_sql(" <= ");
+                out.push_bind_param::<Numeric, _>(max_longitude)?;
+                out.push_sql("))");
+            }
+            SpatialArea::Radius {
+                latitude: center_lat,
+                longitude: center_lon,
+                meters,
+            } if postgis => {
+                out.push_sql("st_dwithin(st_makepoint(");
+                longitude.walk_ast(out.reborrow())?;
+                out.push_sql("::float8, ");
+                latitude.walk_ast(out.reborrow())?;
+                out.push_sql("::float8)::geography, st_makepoint(");
+                out.push_bind_param::<Numeric, _>(center_lon)?;
+                out.push_sql("::float8, ");
+                out.push_bind_param::<Numeric, _>(center_lat)?;
+                out.push_sql("::float8)::geography, ");
+                out.push_bind_param::<Numeric, _>(meters)?;
+                out.push_sql("::float8)");
+            }
+            SpatialArea::Radius {
+                latitude: center_lat,
+                longitude: center_lon,
+                meters,
+            } => {
+                // The haversine formula for the great-circle distance;
+                // `least` guards against rounding errors pushing the
+                // argument of `asin` past 1
+                out.push_sql("2 * ");
+                out.push_sql(EARTH_RADIUS);
+                out.push_sql(" * asin(least(1, sqrt(power(sin(radians(");
+                latitude.walk_ast(out.reborrow())?;
+                out.push_sql(" - ");
+                out.push_bind_param::<Numeric, _>(center_lat)?;
+                out.push_sql(") / 2), 2) + cos(radians(");
+                latitude.walk_ast(out.reborrow())?;
+                out.push_sql(")) * cos(radians(");
+                out.push_bind_param::<Numeric, _>(center_lat)?;
+                out.push_sql(")) * power(sin(radians(");
+                longitude.walk_ast(out.reborrow())?;
+                out.push_sql(" - ");
+                out.push_bind_param::<Numeric, _>(center_lon)?;
+                out.push_sql(") / 2), 2)))) <= ");
+                out.push_bind_param::<Numeric, _>(meters)?;
+            }
+        }
+        Ok(())
     }
 
     fn contains<'b>(
@@ -1704,6 +1913,24 @@ impl<'a> Filter<'a> {
                     out.push_sql(") > 0");
                 }
             }
+            SqlValue::List(_) if op.nocase() && column.column_type() == &ColumnType::String => {
+                // Compare the lowercased elements of both arrays. That
+                // can not use an index on the column
+                if op.negated() {
+                    out.push_sql(" not ");
+                }
+                out.push_sql("array(select lower(e) from unnest(");
+                column.walk_ast(out.reborrow())?;
+                out.push_sql(") e)");
+                if op.negated() {
+                    out.push_sql(" && ");
+                } else {
+                    out.push_sql(" @> ");
+                }
+                out.push_sql("array(select lower(e) from unnest(");
+                qv.walk_ast(out.reborrow())?;
+                out.push_sql(") e)");
+            }
             SqlValue::List(_) | SqlValue::Numerics(_) => {
                 if op.negated() {
                     out.push_sql(" not ");
@@ -1834,7 +2061,17 @@ impl<'a> fmt::Display for Filter<'a> {
             Or(fs) => {
                 write!(f, "{}", fs.iter().map(|f| f.to_string()).join(" or "))
             }
-            Fulltext(a, v) => write!(f, "{a} = {v}"),
+            Fulltext {
+                column,
+                query,
+                options: _,
+            } => write!(f, "{column} = {query}"),
+            Spatial {
+                latitude,
+                longitude,
+                area,
+                postgis: _,
+            } => write!(f, "({latitude}, {longitude}) within {area}"),
             PrefixCmp(PrefixComparison {
                 op,
                 kind: _,
@@ -1861,7 +2098,7 @@ impl<'a> fmt::Display for Filter<'a> {
                 };
                 write!(f, "{column} {neg}~ *{pattern}*{case}")
             }
-            StartsOrEndsWith {
+            Pattern {
                 column,
                 op,
                 pattern,
@@ -1896,10 +2133,20 @@ impl<'a> QueryFragment<Pg> for Filter<'a> {
             } => Self::contains(column, op, pattern, out)?,
             PrefixCmp(pc) => pc.walk_ast(out)?,
             Cmp(column, op, value) => Self::cmp(column, value, *op, out)?,
-            Fulltext(column, value) => Self::fulltext(column, value, out)?,
+            Fulltext {
+                column,
+                query,
+                options,
+            } => Self::fulltext(column, query, options, out)?,
+            Spatial {
+                latitude,
+                longitude,
+                area,
+                postgis,
+            } => Self::spatial(latitude, longitude, area, *postgis, out)?,
             In(attr, values) => Self::in_array(attr, values, false, out)?,
             NotIn(attr, values) => Self::in_array(attr, values, true, out)?,
-            StartsOrEndsWith {
+            Pattern {
                 column,
                 op,
                 pattern,
@@ -1937,7 +2184,13 @@ impl<'a> FindQuery<'a> {
 
 impl<'a> QueryFragment<Pg> for FindQuery<'a> {
     fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
-        out.unsafe_to_cache_prepared();
+        // The id, causality region and block are all bind variables, so
+        // that the SQL text only depends on the table. Diesel uses the SQL
+        // text as the key for its cache of prepared statements, and there
+        // is therefore at most a handful of statements per table
+        if !ENV_VARS.store.cache_prepared_lookups {
+            out.unsafe_to_cache_prepared();
+        }
 
         // Generate
         //    select '..' as entity, to_jsonb(e.*) as data
@@ -1971,6 +2224,58 @@ impl<'a> Query for FindQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FindQuery<'a> {}
 
+/// A query that returns the `count` entities of a table that were written
+/// most recently and are visible at `block`
+#[derive(Debug)]
+pub struct FindRecentQuery<'a> {
+    table: &'a Table,
+    count: i64,
+    br_column: BlockRangeColumn<'a>,
+}
+
+impl<'a> FindRecentQuery<'a> {
+    pub fn new(table: &'a Table, count: usize, block: BlockNumber) -> Self {
+        let br_column = BlockRangeColumn::new(table, "e.", block);
+        Self {
+            table,
+            count: count as i64,
+            br_column,
+        }
+    }
+}
+
+impl<'a> QueryFragment<Pg> for FindRecentQuery<'a> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Generate
+        //    select '..' as entity, to_jsonb(e.*) as data
+        //      from schema.table e where {br_column contains block}
+        //     order by e.vid desc limit $1
+        out.push_sql("select ");
+        out.push_bind_param::<Text, _>(self.table.object.as_str())?;
+        out.push_sql(" as entity, to_jsonb(e.*) as data\n");
+        out.push_sql("  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" e\n where ");
+        self.br_column.contains(&mut out, false)?;
+        out.push_sql("\n order by e.vid desc limit ");
+        out.push_bind_param::<BigInt, _>(&self.count)
+    }
+}
+
+impl<'a> QueryId for FindRecentQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> Query for FindRecentQuery<'a> {
+    type SqlType = Untyped;
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for FindRecentQuery<'a> {}
+
 /// Builds a query over a given set of [`Table`]s in an attempt to find updated
 /// and/or newly inserted entities at a given block number; i.e. such that the
 /// block range's lower bound is equal to said block number.
@@ -2571,9 +2876,22 @@ struct ParentLimit<'a> {
     /// Limit children by sorting and picking top n
     sort_key: SortKey<'a>,
     range: FilterRange,
+    /// Only return children that come after a cursor. For windows, the
+    /// cursor is applied to the children of each parent separately, just
+    /// like `range`
+    keyset: Option<Keyset<'a>>,
 }
 
 impl<'a> ParentLimit<'a> {
+    /// Add ` and {keyset}` if the query has a cursor
+    fn after(&'a self, out: &mut AstPass<'_, 'a, Pg>) -> QueryResult<()> {
+        if let Some(keyset) = &self.keyset {
+            out.push_sql(" and ");
+            keyset.walk_ast(out.reborrow())?;
+        }
+        Ok(())
+    }
+
     fn filter(&self, is_outer: bool, out: &mut AstPass<'_, 'a, Pg>) {
         if is_outer {
             out.push_sql(" and q.id = p.id")
@@ -2701,11 +3019,13 @@ impl<'a> FilterWindow<'a> {
         out.push_sql(self.table.qualified_name.as_str());
         out.push_sql(" c where ");
         self.br_column.contains(out, false)?;
+        self.br_column.not_expired(out);
         limit.filter(is_outer, out);
         out.push_sql(" and p.id = any(c.");
         out.push_identifier(column.name.as_str())?;
         out.push_sql(")");
         self.and_filter(out)?;
+        limit.after(out)?;
         limit.restrict(is_outer, out)?;
         out.push_sql(") c");
         Ok(())
@@ -2737,6 +3057,7 @@ impl<'a> FilterWindow<'a> {
         out.push_sql(self.table.qualified_name.as_str());
         out.push_sql(" c where ");
         self.br_column.contains(out, false)?;
+        self.br_column.not_expired(out);
         limit.filter(is_outer, out);
         out.push_sql(" and c.");
         out.push_identifier(column.name.as_str())?;
@@ -2780,10 +3101,12 @@ impl<'a> FilterWindow<'a> {
         out.push_sql(self.table.qualified_name.as_str());
         out.push_sql(" c where ");
         self.br_column.contains(out, false)?;
+        self.br_column.not_expired(out);
         limit.filter(is_outer, out);
         out.push_sql(" and p.id = c.");
         out.push_identifier(column.name.as_str())?;
         self.and_filter(out)?;
+        limit.after(out)?;
         limit.restrict(is_outer, out)?;
         out.push_sql(") c");
         Ok(())
@@ -2810,6 +3133,7 @@ impl<'a> FilterWindow<'a> {
         out.push_sql(self.table.qualified_name.as_str());
         out.push_sql(" c where ");
         self.br_column.contains(out, false)?;
+        self.br_column.not_expired(out);
         limit.filter(is_outer, out);
         out.push_sql(" and p.id = c.");
         out.push_identifier(column.name.as_str())?;
@@ -2864,9 +3188,11 @@ impl<'a> FilterWindow<'a> {
             out.push_sql(self.table.qualified_name.as_str());
             out.push_sql(" c where ");
             self.br_column.contains(out, true)?;
+            self.br_column.not_expired(out);
             limit.filter(is_outer, out);
             out.push_sql(" and c.id = any(p.child_ids)");
             self.and_filter(out)?;
+            limit.after(out)?;
             limit.restrict(is_outer, out)?;
             out.push_sql(") c");
         } else {
@@ -2906,6 +3232,7 @@ impl<'a> FilterWindow<'a> {
         out.push_sql(self.table.qualified_name.as_str());
         out.push_sql(" c where ");
         self.br_column.contains(out, true)?;
+        self.br_column.not_expired(out);
         limit.filter(is_outer, out);
 
         // Include a constraint on the child IDs as a set if the size of the set
@@ -3013,6 +3340,63 @@ impl<'a> WholeTable<'a> {
     }
 }
 
+/// The different kinds of queries that we generate. They are used to label
+/// queries in SQL comments and in metrics so that the time spent in the
+/// database can be broken down by what kind of query caused it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QueryShape {
+    /// Look up a single entity by id
+    Find,
+    /// Look up entities of several types by their ids
+    FindMany,
+    /// Look up entities through a derived field
+    FindDerived,
+    /// Look up the most recently written entities of a type
+    FindRecent,
+    /// A GraphQL query over one table
+    Filter,
+    /// A GraphQL query over several tables, i.e., an interface
+    FilterUnion,
+    /// A GraphQL query for the children of a single type of parents
+    Window,
+    /// A GraphQL query for the children of several types of parents
+    WindowUnion,
+}
+
+impl QueryShape {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryShape::Find => "find",
+            QueryShape::FindMany => "find_many",
+            QueryShape::FindDerived => "find_derived",
+            QueryShape::FindRecent => "find_recent",
+            QueryShape::Filter => "filter",
+            QueryShape::FilterUnion => "filter_union",
+            QueryShape::Window => "window",
+            QueryShape::WindowUnion => "window_union",
+        }
+    }
+}
+
+impl From<&EntityCollection> for QueryShape {
+    /// The shape of the query that `FilterCollection::new` and
+    /// `FilterQuery` will generate for `collection`
+    fn from(collection: &EntityCollection) -> Self {
+        match collection {
+            EntityCollection::All(entities) if entities.len() == 1 => QueryShape::Filter,
+            EntityCollection::All(_) => QueryShape::FilterUnion,
+            EntityCollection::Window(windows) if windows.len() == 1 => QueryShape::Window,
+            EntityCollection::Window(_) => QueryShape::WindowUnion,
+        }
+    }
+}
+
+impl fmt::Display for QueryShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// This is a parallel to `EntityCollection`, but with entity type names
 /// and filters translated in a form ready for SQL generation
 #[derive(Debug)]
@@ -3149,6 +3533,15 @@ impl<'a> FilterCollection<'a> {
         }
     }
 
+    fn shape(&self) -> QueryShape {
+        match self {
+            FilterCollection::All(entities) if entities.len() == 1 => QueryShape::Filter,
+            FilterCollection::All(_) => QueryShape::FilterUnion,
+            FilterCollection::SingleWindow(_) => QueryShape::Window,
+            FilterCollection::MultiWindow(_, _) => QueryShape::WindowUnion,
+        }
+    }
+
     fn first_table(&self) -> Option<&Table> {
         match self {
             FilterCollection::All(entities) => entities.first().map(|wh| wh.table),
@@ -3266,7 +3659,8 @@ pub enum SortKey<'a> {
     /// Order by some other column; `column` will never be `id`
     Key {
         column: &'a Column,
-        value: Option<&'a str>,
+        /// The query and options for fulltext columns
+        value: Option<(&'a str, &'a FulltextOptions)>,
         direction: &'static str,
     },
     /// Order by some other column; `column` will never be `id`
@@ -3410,9 +3804,10 @@ impl<'a> SortKey<'a> {
         fn sort_key_from_value<'a>(
             column: &'a Column,
             value: &'a Value,
+            options: &'a FulltextOptions,
             direction: &'static str,
         ) -> Result<SortKey<'a>, QueryExecutionError> {
-            let sort_value = value.as_str();
+            let sort_value = value.as_str().map(|query| (query, options));
 
             Ok(SortKey::Key {
                 column,
@@ -3431,12 +3826,12 @@ impl<'a> SortKey<'a> {
             let column = table.column_for_field(&attribute)?;
             if column.is_fulltext() {
                 match filter {
-                    Some(EntityFilter::Fulltext(_, value)) => {
-                        sort_key_from_value(column, value, direction)
+                    Some(EntityFilter::Fulltext(_, value, options)) => {
+                        sort_key_from_value(column, value, options, direction)
                     }
                     Some(EntityFilter::And(vec)) => match vec.first() {
-                        Some(EntityFilter::Fulltext(_, value)) => {
-                            sort_key_from_value(column, value, direction)
+                        Some(EntityFilter::Fulltext(_, value, options)) => {
+                            sort_key_from_value(column, value, options, direction)
                         }
                         _ => unreachable!(),
                     },
@@ -3722,6 +4117,17 @@ impl<'a> SortKey<'a> {
         }
     }
 
+    /// Whether the `order by` for this sort key can be used in each branch
+    /// of a `union all` over several tables. That is only possible if the
+    /// order is on columns of the tables themselves
+    fn can_push_down(&self) -> bool {
+        match self {
+            SortKey::IdAsc(_) | SortKey::IdDesc(_) => true,
+            SortKey::Key { column, value, .. } => value.is_none() && !column.is_fulltext(),
+            SortKey::None | SortKey::ChildKey(_) => false,
+        }
+    }
+
     /// Generate selecting the sort key if it is needed
     fn select(
         &self,
@@ -4003,7 +4409,7 @@ impl<'a> SortKey<'a> {
     ///   [name direction,] id
     fn sort_expr<'b>(
         column: &Column,
-        value: &'b Option<&str>,
+        value: &'b Option<(&str, &FulltextOptions)>,
         direction: &str,
         column_prefix: Option<&str>,
         rest_prefix: Option<&str>,
@@ -4026,7 +4432,8 @@ impl<'a> SortKey<'a> {
 
         match &column.column_type {
             ColumnType::TSVector(config) => {
-                let algorithm = match config.algorithm {
+                let (query, options) = value.unwrap();
+                let algorithm = match options.rank_by.as_ref().unwrap_or(&config.algorithm) {
                     FulltextAlgorithm::Rank => "ts_rank(",
                     FulltextAlgorithm::ProximityRank => "ts_rank_cd(",
                 };
@@ -4039,10 +4446,9 @@ impl<'a> SortKey<'a> {
                     out.push_identifier(name)?;
                 }
 
-                out.push_sql(", to_tsquery(");
-
-                out.push_bind_param::<Text, _>(value.unwrap())?;
-                out.push_sql("))");
+                out.push_sql(", ");
+                push_tsquery(query, options, out)?;
+                out.push_sql(")");
             }
             _ => {
                 if use_sort_key_alias {
@@ -4211,11 +4617,18 @@ impl<'a> SortKey<'a> {
                 out.push_identifier(parent_column.name.as_str())?;
             }
 
+            // Immutable children, for example timeseries, only have a
+            // `block$` column
             out.push_sql(" and ");
             out.push_sql(prefix);
             out.push_sql(".");
-            out.push_identifier(BLOCK_RANGE_COLUMN)?;
-            out.push_sql(" @> ");
+            if child_table.immutable {
+                out.push_identifier(BLOCK_COLUMN)?;
+                out.push_sql(" <= ");
+            } else {
+                out.push_identifier(BLOCK_RANGE_COLUMN)?;
+                out.push_sql(" @> ");
+            }
             out.push_bind_param::<Integer, _>(block)?;
             out.push_sql(") ");
 
@@ -4275,6 +4688,140 @@ impl<'a> SortKey<'a> {
     }
 }
 
+/// Restrict the rows of a query to those that come after an
+/// `EntityCursor` in the order given by the query's sort key. Since we
+/// always order by `id` as the last sort column, comparing the sort column
+/// and `id` with the values from the cursor is enough to find where to
+/// continue
+#[derive(Debug, Clone)]
+pub struct Keyset<'a> {
+    id: QueryValue<'a>,
+    /// The column the query is ordered by and the cursor's value for it;
+    /// `None` if the query is ordered by `id`
+    key: Option<(&'a Column, QueryValue<'a>)>,
+    direction: &'static str,
+}
+
+impl<'a> Keyset<'a> {
+    fn new(
+        cursor: &'a EntityCursor,
+        sort_key: &SortKey<'a>,
+        collection: &'a FilterCollection<'a>,
+    ) -> Result<Self, QueryExecutionError> {
+        fn parse_error(e: DieselError) -> QueryExecutionError {
+            QueryExecutionError::ValueParseError("after".to_string(), e.to_string())
+        }
+
+        // All tables in a collection use the same type for their ids
+        let table = match collection {
+            FilterCollection::All(tables) if !tables.is_empty() => tables[0].table,
+            FilterCollection::SingleWindow(window) => window.table,
+            FilterCollection::MultiWindow(windows, _) if !windows.is_empty() => windows[0].table,
+            _ => {
+                return Err(QueryExecutionError::NotSupported(
+                    "cursors can not be used for empty collections".to_string(),
+                ))
+            }
+        };
+        let id =
+            QueryValue::new(&cursor.id, &table.primary_key().column_type).map_err(parse_error)?;
+
+        match (sort_key, &cursor.value) {
+            (SortKey::IdAsc(_), None) => Ok(Keyset {
+                id,
+                key: None,
+                direction: ASC,
+            }),
+            (SortKey::IdDesc(_), None) => Ok(Keyset {
+                id,
+                key: None,
+                direction: DESC,
+            }),
+            (
+                SortKey::Key {
+                    column,
+                    value: None,
+                    direction,
+                },
+                Some(value),
+            ) if !column.is_fulltext() => {
+                let value = QueryValue::new(value, &column.column_type).map_err(parse_error)?;
+                Ok(Keyset {
+                    id,
+                    key: Some((*column, value)),
+                    direction: *direction,
+                })
+            }
+            _ => Err(QueryExecutionError::NotSupported(
+                "cursors can only be used when ordering by `id` or by an attribute of \
+                 the entity, and the cursor must have been created for that order"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+impl<'a> QueryFragment<Pg> for Keyset<'a> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        fn push_column(name: &str, out: &mut AstPass<Pg>) -> QueryResult<()> {
+            out.push_sql("c.");
+            out.push_identifier(name)
+        }
+
+        out.unsafe_to_cache_prepared();
+
+        let op = if self.direction == ASC { " > " } else { " < " };
+        match &self.key {
+            None => {
+                // c.id > $id
+                push_column(PRIMARY_KEY_COLUMN, &mut out)?;
+                out.push_sql(op);
+                self.id.walk_ast(out.reborrow())?;
+            }
+            Some((column, value)) if value.is_null() => {
+                // Postgres sorts nulls last for `asc` and first for `desc`
+                //   asc:  (c.col is null and c.id > $id)
+                //   desc: (c.col is not null or c.id < $id)
+                out.push_sql("(");
+                push_column(column.name.as_str(), &mut out)?;
+                if self.direction == ASC {
+                    out.push_sql(" is null and ");
+                } else {
+                    out.push_sql(" is not null or ");
+                }
+                push_column(PRIMARY_KEY_COLUMN, &mut out)?;
+                out.push_sql(op);
+                self.id.walk_ast(out.reborrow())?;
+                out.push_sql(")");
+            }
+            Some((column, value)) => {
+                //   (c.col > $value or (c.col = $value and c.id > $id)
+                //    [or c.col is null])
+                out.push_sql("(");
+                push_column(column.name.as_str(), &mut out)?;
+                out.push_sql(op);
+                value.walk_ast(out.reborrow())?;
+                out.push_sql(" or (");
+                push_column(column.name.as_str(), &mut out)?;
+                out.push_sql(" = ");
+                value.walk_ast(out.reborrow())?;
+                out.push_sql(" and ");
+                push_column(PRIMARY_KEY_COLUMN, &mut out)?;
+                out.push_sql(op);
+                self.id.walk_ast(out.reborrow())?;
+                out.push_sql(")");
+                if self.direction == ASC {
+                    out.push_sql(" or ");
+                    push_column(column.name.as_str(), &mut out)?;
+                    out.push_sql(" is null");
+                }
+                out.push_sql(")");
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Generate `[limit {first}] [offset {skip}]
 #[derive(Debug, Clone)]
 pub struct FilterRange(EntityRange);
@@ -4295,6 +4842,15 @@ impl fmt::Display for FilterRange {
     }
 }
 
+impl FilterRange {
+    /// The number of rows that each part of a query that combines several
+    /// tables needs to produce at most so that the combined query has all
+    /// the rows it needs; `None` if the query is not limited
+    fn branch_limit(&self) -> Option<u64> {
+        self.0.first.map(|first| first as u64 + self.0.skip as u64)
+    }
+}
+
 impl QueryFragment<Pg> for FilterRange {
     fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
         let range = &self.0;
@@ -4345,13 +4901,21 @@ impl<'a> FilterQuery<'a> {
         filter: Option<&'a EntityFilter>,
         order: EntityOrder,
         range: EntityRange,
+        after: Option<&'a EntityCursor>,
         block: BlockNumber,
         query_id: Option<String>,
         site: &'a Site,
     ) -> Result<Self, QueryExecutionError> {
         let sort_key = SortKey::new(order, collection, filter, block, layout)?;
+        let keyset = after
+            .map(|cursor| Keyset::new(cursor, &sort_key, collection))
+            .transpose()?;
         let range = FilterRange(range);
-        let limit = ParentLimit { sort_key, range };
+        let limit = ParentLimit {
+            sort_key,
+            range,
+            keyset,
+        };
 
         Ok(FilterQuery {
             collection,
@@ -4386,10 +4950,12 @@ impl<'a> FilterQuery<'a> {
         };
 
         wh.br_column.contains(out, filters_by_id)?;
+        wh.br_column.not_expired(out);
         if let Some(filter) = &wh.filter {
             out.push_sql(" and ");
             filter.walk_ast(out.reborrow())?;
         }
+        self.limit.after(out)?;
         out.push_sql("\n");
         Ok(())
     }
@@ -4400,6 +4966,68 @@ impl<'a> FilterQuery<'a> {
         out.push_sql("' as entity, to_jsonb(c.*) as data");
     }
 
+    /// Like `select_entity_and_data`, but overwrite the selected fields
+    /// that are included in the fulltext `column` with
+    ///   ts_headline({language}, c.{field}, to_tsquery({language}, $query))
+    fn select_highlighted_entity_and_data<'b>(
+        table: &'b Table,
+        column_names: &AttributeNames,
+        column: &'b Column,
+        query: &'b str,
+        options: &'b FulltextOptions,
+        out: &mut AstPass<'_, 'b, Pg>,
+    ) -> QueryResult<()> {
+        let language = match (&options.language, &column.column_type) {
+            (Some(language), _) => language,
+            (None, ColumnType::TSVector(config)) => &config.language,
+            (None, _) => {
+                return Err(constraint_violation!(
+                    "fulltext search on non-fulltext column {}",
+                    column.name
+                ))
+            }
+        };
+        let options = FulltextOptions {
+            language: Some(language.clone()),
+            ..options.clone()
+        };
+        let fields = column
+            .fulltext_fields
+            .iter()
+            .flatten()
+            .filter(|field| match column_names {
+                AttributeNames::All => true,
+                AttributeNames::Select(names) => names.contains(*field),
+            })
+            .filter_map(|field| table.column_for_field(field).ok())
+            .sorted_by(|a, b| a.name.as_str().cmp(b.name.as_str()))
+            .collect::<Vec<_>>();
+
+        out.push_sql("select '");
+        out.push_sql(table.object.as_str());
+        out.push_sql("' as entity, to_jsonb(c.*)");
+        if !fields.is_empty() {
+            out.push_sql(" || jsonb_build_object(");
+            for (i, field) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push_sql(", ");
+                }
+                out.push_sql("'");
+                out.push_sql(field.name.as_str());
+                out.push_sql("', ts_headline(");
+                out.push_sql(language.as_sql());
+                out.push_sql(", c.");
+                out.push_identifier(field.name.as_str())?;
+                out.push_sql(", ");
+                push_tsquery(query, &options, out)?;
+                out.push_sql(")");
+            }
+            out.push_sql(")");
+        }
+        out.push_sql(" as data");
+        Ok(())
+    }
+
     /// Only one table/filter pair, and no window
     ///
     /// The generated query makes sure we only convert the rows we actually
@@ -4412,12 +5040,26 @@ impl<'a> FilterQuery<'a> {
     ///         where block_range @> $block
     ///           and filter
     ///         order by .. limit .. skip ..) c
+    ///
+    /// For fulltext searches with highlighting, the values of the fields
+    /// included in the search are replaced in `data` with the output of
+    /// `ts_headline`
     fn query_no_window_one_entity<'b>(
         &'b self,
         wh: &'b WholeTable<'a>,
         out: &mut AstPass<'_, 'b, Pg>,
     ) -> QueryResult<()> {
-        Self::select_entity_and_data(wh.table, out);
+        match wh.filter.as_ref().and_then(Filter::highlight) {
+            Some((column, query, options)) => Self::select_highlighted_entity_and_data(
+                wh.table,
+                &wh.column_names,
+                column,
+                query,
+                options,
+                out,
+            )?,
+            None => Self::select_entity_and_data(wh.table, out),
+        }
         out.push_sql(" from (select ");
         write_column_names(&wh.column_names, wh.table, Some("c."), out)?;
         self.filtered_rows(wh, out)?;
@@ -4481,6 +5123,18 @@ impl<'a> FilterQuery<'a> {
         //  union all
         //  ...
         //  order by c.{sort_key}
+        //
+        // If the query is limited and ordered by columns of the tables, each
+        // part of the union is ordered and limited to `n + m` rows
+        // itself. That lets Postgres use the indexes of each table for the
+        // order and merge the sorted parts, stopping once it has enough
+        // rows, rather than sorting all matching rows from all tables
+
+        let branch_limit = self
+            .limit
+            .range
+            .branch_limit()
+            .filter(|_| self.limit.sort_key.can_push_down());
 
         // Step 1: build matches CTE
         out.push_sql("with matches as (");
@@ -4488,6 +5142,9 @@ impl<'a> FilterQuery<'a> {
             if i > 0 {
                 out.push_sql("\nunion all\n");
             }
+            if branch_limit.is_some() {
+                out.push_sql("(");
+            }
             // select '..' as entity,
             //        c.id,
             //        c.vid,
@@ -4499,6 +5156,13 @@ impl<'a> FilterQuery<'a> {
                 .sort_key
                 .select(out, SelectStatementLevel::InnerStatement)?; // here
             self.filtered_rows(wh, out)?;
+            if let Some(branch_limit) = branch_limit {
+                out.push_sql(" ");
+                self.limit.sort_key.order_by(out, false)?;
+                out.push_sql("\n limit ");
+                out.push_sql(&branch_limit.to_string());
+                out.push_sql(")");
+            }
         }
         out.push_sql("\n ");
         self.limit.sort_key.order_by(out, true)?;
@@ -4624,17 +5288,20 @@ impl<'a> QueryFragment<Pg> for FilterQuery<'a> {
         }
 
         // Tag the query with various information to make connecting it to
-        // the GraphQL query it came from easier. The names of the tags are
-        // chosen so that GCP's Query Insights will recognize them
+        // the deployment and the GraphQL query it came from easier. The
+        // names of the tags are chosen so that GCP's Query Insights will
+        // recognize them
+        out.push_sql("/* controller='");
+        out.push_sql(self.collection.shape().as_str());
+        out.push_sql("',application='");
+        out.push_sql(self.site.namespace.as_str());
         if let Some(qid) = &self.query_id {
-            out.push_sql("/* controller='filter',application='");
-            out.push_sql(self.site.namespace.as_str());
             out.push_sql("',route='");
             out.push_sql(qid);
             out.push_sql("',action='");
             out.push_sql(&self.block.to_string());
-            out.push_sql("' */\n");
         }
+        out.push_sql("' */\n");
         // We generate four different kinds of queries, depending on whether
         // we need to window and whether we query just one or multiple entity
         // types/windows; the most complex situation is windowing with multiple
@@ -4674,6 +5341,169 @@ impl<'a> Query for FilterQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FilterQuery<'a> {}
 
+/// The plan that `ExplainQuery` returns, formatted as JSON
+#[derive(QueryableByName)]
+pub struct QueryPlan {
+    #[diesel(sql_type = Text, column_name = "QUERY PLAN")]
+    pub plan: String,
+}
+
+/// Run `query` under `EXPLAIN ANALYZE` to find out how Postgres executed
+/// it. Note that this executes `query` again
+#[derive(Debug)]
+pub struct ExplainQuery<'a, Q> {
+    query: &'a Q,
+}
+
+impl<'a, Q> ExplainQuery<'a, Q> {
+    pub fn new(query: &'a Q) -> Self {
+        ExplainQuery { query }
+    }
+}
+
+impl<'a, Q: QueryFragment<Pg>> QueryFragment<Pg> for ExplainQuery<'a, Q> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("explain (analyze, buffers, format json) ");
+        self.query.walk_ast(out)
+    }
+}
+
+impl<'a, Q> QueryId for ExplainQuery<'a, Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a, Q> Query for ExplainQuery<'a, Q> {
+    type SqlType = Untyped;
+}
+
+impl<'a, Q, Conn> RunQueryDsl<Conn> for ExplainQuery<'a, Q> {}
+
+/// The result of an `AggregateQuery`. Each aggregate is returned as text
+/// so that the query has the same result type no matter which columns are
+/// aggregated
+#[derive(QueryableByName)]
+pub struct AggregateValues {
+    #[diesel(sql_type = Array<Nullable<Text>>)]
+    pub aggregates: Vec<Option<String>>,
+}
+
+/// Compute aggregates like `count` and `sum` over all the entities of one
+/// type that match a filter
+#[derive(Debug)]
+pub struct AggregateQuery<'a> {
+    wh: &'a WholeTable<'a>,
+    /// The SQL aggregate function and the column it is applied to; the
+    /// column is `None` for `count(*)`
+    aggregates: Vec<(&'static str, Option<&'a Column>)>,
+}
+
+impl<'a> AggregateQuery<'a> {
+    pub fn new(
+        collection: &'a FilterCollection<'a>,
+        aggregates: &[EntityAggregate],
+    ) -> Result<Self, QueryExecutionError> {
+        let wh = match collection {
+            FilterCollection::All(tables) if tables.len() == 1 => &tables[0],
+            _ => {
+                return Err(QueryExecutionError::NotSupported(
+                    "aggregates can only be computed for toplevel collections of one \
+                     entity type"
+                        .to_string(),
+                ))
+            }
+        };
+
+        let aggregates = aggregates
+            .iter()
+            .map(|aggregate| {
+                let func = match aggregate {
+                    EntityAggregate::Count => "count",
+                    EntityAggregate::Sum(_) => "sum",
+                    EntityAggregate::Avg(_) => "avg",
+                    EntityAggregate::Min(_) => "min",
+                    EntityAggregate::Max(_) => "max",
+                };
+                let column = match aggregate.attribute() {
+                    None => None,
+                    Some(attr) => {
+                        let column = wh.table.column_for_field(attr)?;
+                        let numeric = matches!(
+                            column.column_type,
+                            ColumnType::BigDecimal
+                                | ColumnType::BigInt
+                                | ColumnType::Int
+                                | ColumnType::Int8
+                        );
+                        if column.is_list() || !numeric {
+                            return Err(QueryExecutionError::NotSupported(format!(
+                                "can not compute `{}` of `{}` since it is not a numeric \
+                                 attribute",
+                                func, attr
+                            )));
+                        }
+                        Some(column)
+                    }
+                };
+                Ok((func, column))
+            })
+            .collect::<Result<Vec<_>, QueryExecutionError>>()?;
+
+        Ok(AggregateQuery { wh, aggregates })
+    }
+}
+
+impl<'a> QueryFragment<Pg> for AggregateQuery<'a> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        // select array[count(*)::text, sum(c.col)::text, ..] as aggregates
+        //   from table c
+        //  where block_range @> $block
+        //    and filter
+        out.unsafe_to_cache_prepared();
+
+        out.push_sql("select array[");
+        for (i, (func, column)) in self.aggregates.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            out.push_sql(func);
+            out.push_sql("(");
+            match column {
+                None => out.push_sql("*"),
+                Some(column) => {
+                    out.push_sql("c.");
+                    out.push_identifier(column.name.as_str())?;
+                }
+            }
+            out.push_sql(")::text");
+        }
+        out.push_sql("]::text[] as aggregates\n  from ");
+        out.push_sql(self.wh.table.qualified_name.as_str());
+        out.push_sql(" c\n where ");
+        self.wh.br_column.contains(&mut out, false)?;
+        self.wh.br_column.not_expired(&mut out);
+        if let Some(filter) = &self.wh.filter {
+            out.push_sql(" and ");
+            filter.walk_ast(out.reborrow())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for AggregateQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> Query for AggregateQuery<'a> {
+    type SqlType = Untyped;
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for AggregateQuery<'a> {}
+
 /// Reduce the upper bound of the current entry's block range to `block` as
 /// long as that does not result in an empty block range
 #[derive(Debug)]
@@ -4888,6 +5718,11 @@ pub struct CopyEntityBatchQuery<'a> {
     columns: Vec<&'a Column>,
     first_vid: i64,
     last_vid: i64,
+    // When converting a mutable table into an immutable one, only copy
+    // the versions that are current at this block. If it is `None`,
+    // copying fails if the source contains entities that were updated or
+    // deleted
+    convert_immutable: Option<BlockNumber>,
 }
 
 impl<'a> CopyEntityBatchQuery<'a> {
@@ -4896,6 +5731,7 @@ impl<'a> CopyEntityBatchQuery<'a> {
         src: &'a Table,
         first_vid: i64,
         last_vid: i64,
+        convert_immutable: Option<BlockNumber>,
     ) -> Result<Self, StoreError> {
         let mut columns = Vec::new();
         for dcol in &dst.columns {
@@ -4922,8 +5758,19 @@ impl<'a> CopyEntityBatchQuery<'a> {
             columns,
             first_vid,
             last_vid,
+            convert_immutable,
         })
     }
+
+    /// The block at which we take the current versions of a mutable table
+    /// to turn them into entities in an immutable table
+    fn converts_to_immutable(&self) -> Option<&BlockNumber> {
+        if !self.src.immutable && self.dst.immutable {
+            self.convert_immutable.as_ref()
+        } else {
+            None
+        }
+    }
 }
 
 impl<'a> QueryFragment<Pg> for CopyEntityBatchQuery<'a> {
@@ -4970,7 +5817,8 @@ impl<'a> QueryFragment<Pg> for CopyEntityBatchQuery<'a> {
         // Try to convert back and forth between mutable and immutable,
         // though that can go wrong during the actual copying when going
         // from mutable to immutable if any entity has ever been updated or
-        // deleted
+        // deleted, unless we were told to convert such entities by only
+        // copying the version that is current at a given block
         match (self.src.immutable, self.dst.immutable) {
             (true, true) => out.push_sql(BLOCK_COLUMN),
             (true, false) => {
@@ -4978,6 +5826,11 @@ impl<'a> QueryFragment<Pg> for CopyEntityBatchQuery<'a> {
                 out.push_sql(BLOCK_COLUMN);
                 out.push_sql(", null)");
             }
+            (false, true) if self.convert_immutable.is_some() => {
+                out.push_sql("lower(");
+                out.push_sql(BLOCK_RANGE_COLUMN);
+                out.push_sql(")");
+            }
             (false, true) => {
                 // If this entity was mutated, we will find one version
                 // where the upper end of the block range will be finite.
@@ -4988,7 +5841,7 @@ impl<'a> QueryFragment<Pg> for CopyEntityBatchQuery<'a> {
                     r#"
                 case when upper_inf({BLOCK_RANGE_COLUMN})
                      then lower({BLOCK_RANGE_COLUMN})
-                     else length(raise_exception_bytea('table {} for entity type {} can not be made immutable since it contains at least one mutated entity. vid = ' || vid)) end
+                     else length(raise_exception_bytea('table {} for entity type {} can not be made immutable since it contains at least one mutated entity; use `graphman copy convert-immutable` to only copy the current versions of its entities. vid = ' || vid)) end
                 "#,
                     self.src.qualified_name,
                     self.src.object.as_str()
@@ -5021,6 +5874,12 @@ impl<'a> QueryFragment<Pg> for CopyEntityBatchQuery<'a> {
         out.push_bind_param::<BigInt, _>(&self.first_vid)?;
         out.push_sql(" and vid <= ");
         out.push_bind_param::<BigInt, _>(&self.last_vid)?;
+        if let Some(block) = self.converts_to_immutable() {
+            out.push_sql(" and ");
+            out.push_sql(BLOCK_RANGE_COLUMN);
+            out.push_sql(" @> ");
+            out.push_bind_param::<Integer, _>(block)?;
+        }
         Ok(())
     }
 }
@@ -5041,6 +5900,23 @@ pub struct CopyVid {
     pub vid: i64,
 }
 
+/// Generate `to_tsquery([{language}, ]$query)`, using the language from
+/// `options` if there is one and the database's default otherwise
+fn push_tsquery<'b>(
+    query: &'b str,
+    options: &FulltextOptions,
+    out: &mut AstPass<'_, 'b, Pg>,
+) -> QueryResult<()> {
+    out.push_sql("to_tsquery(");
+    if let Some(language) = &options.language {
+        out.push_sql(language.as_sql());
+        out.push_sql(", ");
+    }
+    out.push_bind_param::<Text, _>(query)?;
+    out.push_sql(")");
+    Ok(())
+}
+
 fn write_column_names(
     column_names: &AttributeNames,
     table: &Table,
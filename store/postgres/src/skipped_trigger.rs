@@ -0,0 +1,122 @@
+//! Record triggers whose handler failed with a deterministic error that
+//! the handler's `errorPolicy: skip` turned into a record instead of a
+//! subgraph error. The records are kept in the
+//! `subgraphs.subgraph_error_triggers` table in the deployment's shard.
+//!
+//! Triggers are identified by their block and their position among the
+//! skipped triggers of that block. Recording the triggers for a block
+//! replaces whatever was recorded for that block number before, so that
+//! processing a block again after a restart, possibly on a different
+//! fork, does not leave stale records behind
+use std::collections::HashMap;
+
+use diesel::{
+    delete, insert_into, Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use graph::{
+    data::subgraph::schema::SkippedTrigger,
+    prelude::{BlockNumber, BlockPtr, StoreError},
+};
+
+use crate::primary::Site;
+
+table! {
+    subgraphs.subgraph_error_triggers(deployment, block_number, seq) {
+        deployment -> Integer,
+        block_number -> Integer,
+        seq -> Integer,
+        block_hash -> Binary,
+        handler -> Text,
+        trigger -> Text,
+        message -> Text,
+    }
+}
+
+pub(crate) fn insert(
+    conn: &mut PgConnection,
+    site: &Site,
+    triggers: &[SkippedTrigger],
+) -> Result<(), StoreError> {
+    use subgraph_error_triggers as t;
+
+    if triggers.is_empty() {
+        return Ok(());
+    }
+
+    let mut seqs: HashMap<BlockNumber, i32> = HashMap::new();
+    let rows: Vec<_> = triggers
+        .iter()
+        .map(|skipped| {
+            let seq = seqs.entry(skipped.block_ptr.number).or_insert(0);
+            let row = (
+                t::deployment.eq(site.id),
+                t::block_number.eq(skipped.block_ptr.number),
+                t::seq.eq(*seq),
+                t::block_hash.eq(skipped.block_ptr.hash_slice()),
+                t::handler.eq(&skipped.handler),
+                t::trigger.eq(&skipped.trigger),
+                t::message.eq(&skipped.message),
+            );
+            *seq += 1;
+            row
+        })
+        .collect();
+
+    let blocks: Vec<_> = seqs.into_keys().collect();
+    conn.transaction::<_, StoreError, _>(|conn| {
+        delete(
+            t::table
+                .filter(t::deployment.eq(site.id))
+                .filter(t::block_number.eq_any(&blocks)),
+        )
+        .execute(conn)?;
+        insert_into(t::table).values(rows).execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Remove the records for `block` and all later blocks
+pub(crate) fn revert(
+    conn: &mut PgConnection,
+    site: &Site,
+    block: BlockNumber,
+) -> Result<(), StoreError> {
+    use subgraph_error_triggers as t;
+
+    delete(
+        t::table
+            .filter(t::deployment.eq(site.id))
+            .filter(t::block_number.ge(block)),
+    )
+    .execute(conn)?;
+    Ok(())
+}
+
+pub(crate) fn load(
+    conn: &mut PgConnection,
+    site: &Site,
+) -> Result<Vec<SkippedTrigger>, StoreError> {
+    use subgraph_error_triggers as t;
+
+    let rows = t::table
+        .filter(t::deployment.eq(site.id))
+        .select((
+            t::block_number,
+            t::block_hash,
+            t::handler,
+            t::trigger,
+            t::message,
+        ))
+        .order_by((t::block_number, t::seq))
+        .load::<(BlockNumber, Vec<u8>, String, String, String)>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(number, hash, handler, trigger, message)| SkippedTrigger {
+            block_ptr: BlockPtr::new(hash.into(), number),
+            handler,
+            trigger,
+            message,
+        })
+        .collect())
+}
@@ -9,7 +9,10 @@ use std::{
     collections::{BTreeMap, HashMap},
     sync::{atomic::AtomicU8, Arc, Mutex},
 };
-use std::{iter::FromIterator, time::Duration};
+use std::{
+    iter::FromIterator,
+    time::{Duration, Instant},
+};
 
 use graph::futures03::future::join_all;
 use graph::{
@@ -17,21 +20,27 @@ use graph::{
     components::{
         server::index_node::VersionInfo,
         store::{
-            self, BlockPtrForNumber, BlockStore, DeploymentLocator, EnsLookup as EnsLookupTrait,
-            PruneReporter, PruneRequest, SubgraphFork,
+            self, AuxLookup as AuxLookupTrait, BlockPtrForNumber, BlockStore, DeploymentLocator,
+            DeploymentPriority, DeploymentReader as DeploymentReaderTrait,
+            EnsLookup as EnsLookupTrait, ExportFormat,
+            PersistedQueryStore as PersistedQueryStoreTrait, PruneReporter, PruneRequest,
+            RollupBackfillReporter, SubgraphFork,
         },
     },
     constraint_violation,
     data::query::QueryTarget,
-    data::subgraph::{schema::DeploymentCreate, status, DeploymentFeatures},
+    data::subgraph::{
+        schema::{DeploymentCreate, SkippedTrigger},
+        status, DeploymentFeatures, StorageParams,
+    },
     prelude::{
         anyhow, lazy_static, o, web3::types::Address, ApiVersion, BlockNumber, BlockPtr,
-        ChainStore, DeploymentHash, EntityOperation, Logger, MetricsRegistry, NodeId,
+        ChainStore, DeploymentHash, Entity, EntityOperation, Logger, MetricsRegistry, NodeId,
         PartialBlockPtr, StoreError, SubgraphDeploymentEntity, SubgraphName,
         SubgraphStore as SubgraphStoreTrait, SubgraphVersionSwitchingMode,
     },
-    prelude::{CancelableError, StoreEvent},
-    schema::{ApiSchema, InputSchema},
+    prelude::{CancelableError, StoreEvent, ENV_VARS},
+    schema::{ApiSchema, EntityKey, InputSchema},
     url::Url,
     util::timed_cache::TimedCache,
 };
@@ -50,7 +59,9 @@ use crate::{
     detail::DeploymentDetail,
     primary::UnusedDeployment,
 };
-use crate::{fork, relational::index::CreateIndex, relational::SqlName};
+use crate::{
+    fork, index_advisor::IndexSuggestion, relational::index::CreateIndex, relational::SqlName,
+};
 
 /// The name of a database shard; valid names must match `[a-z0-9_]+`
 #[derive(Clone, Debug, Eq, PartialEq, Hash, AsExpression, FromSqlRow)]
@@ -147,6 +158,104 @@ pub mod unused {
     }
 }
 
+/// How often `SubgraphStore.move_deployment` checks on the progress of the
+/// copy it is moving a deployment to
+const MOVE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What `catch_up` learns about the copy of a deployment each time it
+/// checks on it
+struct CatchUp {
+    src_head: BlockNumber,
+    dst_head: Option<BlockNumber>,
+    /// Whether the copy has failed
+    failed: bool,
+}
+
+/// Wait until the copy `dst` of `src` is at most `max_lag` blocks behind
+/// `src`, calling `check` every `poll_interval` to find out where they
+/// are. Fail if the copy fails, or if it has not caught up after `timeout`
+async fn catch_up(
+    src: &DeploymentLocator,
+    dst: &DeploymentLocator,
+    max_lag: BlockNumber,
+    timeout: Option<Duration>,
+    poll_interval: Duration,
+    mut check: impl FnMut() -> CatchUp,
+) -> Result<(), StoreError> {
+    let start = Instant::now();
+    loop {
+        let CatchUp {
+            src_head,
+            dst_head,
+            failed,
+        } = check();
+        if dst_head.map_or(false, |dst_head| dst_head >= src_head - max_lag) {
+            return Ok(());
+        }
+        if failed {
+            return Err(StoreError::Unknown(anyhow!(
+                "the copy {} of {} failed while catching up",
+                dst,
+                src
+            )));
+        }
+        if let Some(timeout) = timeout {
+            if start.elapsed() >= timeout {
+                return Err(StoreError::Unknown(anyhow!(
+                    "the copy {} of {} did not catch up within {}s",
+                    dst,
+                    src,
+                    timeout.as_secs()
+                )));
+            }
+        }
+        graph::tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// If `res` is an error, call `resume` to let the source of a move index
+/// again before returning the error
+fn resume_on_error<T>(
+    res: Result<T, StoreError>,
+    resume: impl FnOnce() -> Result<(), StoreError>,
+) -> Result<T, StoreError> {
+    match res {
+        Ok(t) => Ok(t),
+        Err(e) => match resume() {
+            Ok(()) => Err(e),
+            Err(e2) => Err(StoreError::Unknown(anyhow!(
+                "{}; resuming the source of the move also failed: {}",
+                e,
+                e2
+            ))),
+        },
+    }
+}
+
+/// Callbacks for `SubgraphStore.move_deployment` so that callers can report
+/// progress of moving a deployment to users
+#[allow(unused_variables)]
+pub trait MoveReporter: Send + 'static {
+    /// The copy `dst` of `src` has been created and assigned to the same
+    /// node as `src`
+    fn copy_created(&mut self, src: &DeploymentLocator, dst: &DeploymentLocator) {}
+
+    /// Copying data into the new deployment has copied `copied` out of
+    /// `total` rows
+    fn copy_progress(&mut self, copied: i64, total: i64) {}
+    fn copy_finished(&mut self) {}
+
+    /// The new deployment has indexed up to `dst_head` while the source is
+    /// at `src_head`
+    fn catching_up(&mut self, src_head: BlockNumber, dst_head: Option<BlockNumber>) {}
+
+    /// The source has been paused so that the new deployment can catch up
+    /// with it completely
+    fn source_paused(&mut self, src: &DeploymentLocator) {}
+    fn activated(&mut self, dst: &DeploymentLocator) {}
+    fn source_removed(&mut self, src: &DeploymentLocator) {}
+}
+
 /// Multiplex store operations on subgraphs and deployments between a
 /// primary and any number of additional storage shards. The primary
 /// contains information about named subgraphs, and how the underlying
@@ -266,6 +375,138 @@ impl SubgraphStore {
     pub fn for_site(&self, site: &Site) -> Result<&Arc<DeploymentStore>, StoreError> {
         self.inner.for_site(site)
     }
+
+    /// Move the active deployment `src` to `shard` without interrupting
+    /// queries or indexing for more than a short time. We first copy `src`
+    /// as of `block` into `shard`; the copy is assigned to the same node as
+    /// `src` and indexes alongside it. Once the copy is within `max_lag`
+    /// blocks of `src`, we pause `src` to let the copy catch up
+    /// completely, activate the copy so queries use it, and finally
+    /// remove `src`. If the copy fails, or does not catch up within
+    /// `timeout` once `src` is paused, `src` is resumed and the copy is
+    /// left in place for inspection. Returns the locator of the new
+    /// deployment
+    pub async fn move_deployment(
+        &self,
+        src: &DeploymentLocator,
+        shard: Shard,
+        block: BlockPtr,
+        max_lag: BlockNumber,
+        timeout: Duration,
+        reporter: &mut dyn MoveReporter,
+    ) -> Result<DeploymentLocator, StoreError> {
+        let src_site = self.find_site(src.id.into())?;
+        if src_site.shard == shard {
+            return Err(StoreError::Unknown(anyhow!(
+                "deployment {} is already in shard {}",
+                src,
+                shard
+            )));
+        }
+        if !src_site.active {
+            return Err(StoreError::Unknown(anyhow!(
+                "deployment {} is not the active copy of {} and can not be moved",
+                src,
+                src_site.deployment
+            )));
+        }
+        let node = self.assigned_node(src)?.ok_or_else(|| {
+            StoreError::Unknown(anyhow!(
+                "deployment {} is not assigned to a node and can not be moved",
+                src
+            ))
+        })?;
+
+        let block_number = block.number;
+        let dst = self.copy_deployment(src, shard, node, block, OnSync::None)?;
+        reporter.copy_created(src, &dst);
+
+        // Wait for the node to copy the data of `src`
+        let dst_site = self.find_site(dst.id.into())?;
+        loop {
+            let status = self.for_site(dst_site.as_ref())?.copy_status(&dst_site)?;
+            match status.and_then(|status| {
+                reporter.copy_progress(status.copied, status.total);
+                status.status
+            }) {
+                Some(crate::copy::Status::Finished) => break,
+                Some(crate::copy::Status::Cancelled) => {
+                    return Err(StoreError::Unknown(anyhow!(
+                        "copying {} to {} was cancelled",
+                        src,
+                        dst
+                    )));
+                }
+                None => {}
+            }
+            if self.status_for_id(dst.id).health.is_failed() {
+                return Err(StoreError::Unknown(anyhow!(
+                    "the copy {} of {} failed before copying finished",
+                    dst,
+                    src
+                )));
+            }
+            graph::tokio::time::sleep(MOVE_POLL_INTERVAL).await;
+        }
+        reporter.copy_finished();
+
+        // Let the copy index until it is close to `src`
+        let heads = |reporter: &mut dyn MoveReporter| {
+            let (src_head, dst_head) = (self.latest_block(src), self.latest_block(&dst));
+            let src_head = src_head.unwrap_or(block_number);
+            reporter.catching_up(src_head, dst_head);
+            CatchUp {
+                src_head,
+                dst_head,
+                failed: self.status_for_id(dst.id).health.is_failed(),
+            }
+        };
+        catch_up(src, &dst, max_lag, None, MOVE_POLL_INTERVAL, || {
+            heads(&mut *reporter)
+        })
+        .await?;
+
+        // Stop writes to `src` and let the copy catch up completely. If
+        // anything goes wrong while `src` is paused, resume it so that it
+        // keeps indexing
+        self.pause_subgraph(src)?;
+        reporter.source_paused(src);
+        let switched = async {
+            catch_up(src, &dst, 0, Some(timeout), MOVE_POLL_INTERVAL, || {
+                heads(&mut *reporter)
+            })
+            .await?;
+            self.activate(&dst)
+        }
+        .await;
+        resume_on_error(switched, || self.resume_subgraph(src))?;
+        reporter.activated(&dst);
+
+        // Stop indexing `src` and give the node some time to notice that
+        // before we remove it
+        let unassigned = self.primary_conn().and_then(|mut pconn| {
+            pconn.transaction(|conn| -> Result<_, StoreError> {
+                let mut pconn = primary::Connection::new(conn);
+                let changes = pconn.unassign_subgraph(src_site.as_ref())?;
+                pconn.send_store_event(&self.sender, &StoreEvent::new(changes))
+            })
+        });
+        resume_on_error(unassigned, || self.resume_subgraph(src))?;
+        graph::tokio::time::sleep(MOVE_POLL_INTERVAL).await;
+        self.remove_deployment(src_site.id)?;
+        reporter.source_removed(src);
+
+        Ok(dst)
+    }
+
+    /// The number of the latest block that `loc` has indexed
+    fn latest_block(&self, loc: &DeploymentLocator) -> Option<BlockNumber> {
+        self.status_for_id(loc.id)
+            .chains
+            .first()
+            .and_then(|chain| chain.latest_block.as_ref())
+            .map(|block| block.number())
+    }
 }
 
 impl std::ops::Deref for SubgraphStore {
@@ -333,6 +574,7 @@ impl SubgraphStoreInner {
                         main_pool,
                         read_only_pools,
                         weights,
+                        registry.cheap_clone(),
                     )),
                 )
             },
@@ -604,6 +846,23 @@ impl SubgraphStoreInner {
         Ok(site.as_ref().into())
     }
 
+    /// Let the copy into `deployment`, e.g., because it is a graft, turn
+    /// entity types that are mutable in the source into immutable ones
+    /// even if some of their entities were updated or deleted. Only the
+    /// version of each entity that is current at the graft block is
+    /// copied. The copy must have been started but not finished yet
+    pub fn convert_immutable(&self, deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(site.as_ref())?;
+        if !store.convert_immutable(site.as_ref())? {
+            return Err(StoreError::Unknown(anyhow!(
+                "there is no unfinished copy into {}",
+                deployment
+            )));
+        }
+        Ok(())
+    }
+
     pub fn copy_deployment(
         &self,
         src: &DeploymentLocator,
@@ -651,6 +910,10 @@ impl SubgraphStoreInner {
             graft_block: Some(block),
             debug_fork: deployment.debug_fork,
             history_blocks_override: None,
+            // Copied along with the other table stats
+            entity_history_blocks: BTreeMap::new(),
+            // Storage parameters are not copied
+            entity_storage_params: BTreeMap::new(),
         };
 
         let graft_base = self.layout(&src.deployment)?;
@@ -698,6 +961,17 @@ impl SubgraphStoreInner {
         Ok(())
     }
 
+    /// Remove the assignment of `deployment` so that no node indexes it
+    pub fn unassign_subgraph(&self, deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let mut pconn = self.primary_conn()?;
+        pconn.transaction(|conn| -> Result<_, StoreError> {
+            let mut pconn = primary::Connection::new(conn);
+            let changes = pconn.unassign_subgraph(site.as_ref())?;
+            pconn.send_store_event(&self.sender, &StoreEvent::new(changes))
+        })
+    }
+
     // Only for tests to simplify their handling of test fixtures, so that
     // tests can reset the block pointer of a subgraph by recreating it
     #[cfg(debug_assertions)]
@@ -979,6 +1253,15 @@ impl SubgraphStoreInner {
         join_all(self.stores.values().map(|store| store.vacuum())).await
     }
 
+    pub(crate) async fn analyze_churned_tables(&self) -> Vec<Result<(), StoreError>> {
+        join_all(
+            self.stores
+                .values()
+                .map(|store| store.analyze_churned_tables()),
+        )
+        .await
+    }
+
     pub fn rewind(&self, id: DeploymentHash, block_ptr_to: BlockPtr) -> Result<(), StoreError> {
         let (store, site) = self.store(&id)?;
         let event = store.rewind(site, block_ptr_to)?;
@@ -1100,6 +1383,21 @@ impl SubgraphStoreInner {
         store.analyze(site, entity_name)
     }
 
+    /// Write the state of all entities of type `entity_type` in
+    /// `deployment` at `block` to `writer` in the given `format`. Returns
+    /// the number of entities that were written
+    pub fn export_entities(
+        &self,
+        deployment: &DeploymentLocator,
+        entity_type: &str,
+        block: BlockNumber,
+        format: ExportFormat,
+        writer: &mut dyn std::io::Write,
+    ) -> Result<usize, StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.export_entities(site, entity_type, block, format, writer)
+    }
+
     /// Return the statistics targets for all tables of `deployment`. The
     /// first return value is the default target, and the second value maps
     /// the name of each table to a map of column name to its statistics
@@ -1112,6 +1410,29 @@ impl SubgraphStoreInner {
         store.stats_targets(site)
     }
 
+    /// Return the storage parameters that are set for the tables of
+    /// `deployment` as `name=value` strings
+    pub fn storage_params(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<BTreeMap<SqlName, Vec<String>>, StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.storage_params(site)
+    }
+
+    /// Set the storage parameters `params` on the table for `entity` in
+    /// `deployment`, and reset the parameters named in `reset`
+    pub fn set_storage_params(
+        &self,
+        deployment: &DeploymentLocator,
+        entity: &str,
+        params: &StorageParams,
+        reset: &[&str],
+    ) -> Result<(), StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.set_storage_params(site, entity, params, reset)
+    }
+
     /// Set the statistics target for columns `columns` in `deployment`. If
     /// `entity` is `Some`, only set it for the table for that entity, if it
     /// is `None`, set it for all tables in the deployment.
@@ -1149,6 +1470,15 @@ impl SubgraphStoreInner {
         store.indexes_for_entity(site, entity_name).await
     }
 
+    pub async fn suggest_indexes(
+        &self,
+        deployment: &DeploymentLocator,
+        min_count: i64,
+    ) -> Result<Vec<IndexSuggestion>, StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.suggest_indexes(site, min_count).await
+    }
+
     pub async fn drop_index_for_deployment(
         &self,
         deployment: &DeploymentLocator,
@@ -1189,6 +1519,36 @@ impl SubgraphStoreInner {
         store.prune(reporter, site, req).await
     }
 
+    /// Move the versions of entities in `deployment` whose block range
+    /// ended at or before `block` into archive tables where they take up
+    /// less space. Queries continue to see archived versions
+    pub async fn archive(
+        &self,
+        deployment: &DeploymentLocator,
+        block: BlockNumber,
+        lz4: bool,
+    ) -> Result<Vec<(String, usize)>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(&site)?;
+
+        store.archive(site, block, lz4).await
+    }
+
+    /// Fill the buckets of aggregations in `deployment` that are missing
+    /// from the data in their timeseries in batches of `batch_size`
+    /// buckets. Return the number of buckets that were filled
+    pub async fn rollup_backfill(
+        &self,
+        deployment: &DeploymentLocator,
+        batch_size: usize,
+        reporter: Box<dyn RollupBackfillReporter>,
+    ) -> Result<usize, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(&site)?;
+
+        store.rollup_backfill(site, batch_size, reporter).await
+    }
+
     pub fn set_history_blocks(
         &self,
         deployment: &DeploymentLocator,
@@ -1273,12 +1633,138 @@ impl EnsLookupTrait for EnsLookup {
     }
 }
 
+/// The name under which the ENS rainbow table is available to `aux.lookup`
+pub const AUX_DATASET_ENS: &str = "ens";
+
+/// Looks up keys in the auxiliary datasets in the primary. The dataset
+/// `ens` is the ENS rainbow table, which is not versioned; all other
+/// datasets are read at the version the deployment is pinned to
+struct AuxLookup {
+    primary: ConnectionPool,
+    // Cache the pinned versions; once a deployment is pinned to a version
+    // of a dataset, that never changes
+    pins: Mutex<HashMap<(DeploymentHash, String), i32>>,
+}
+
+impl AuxLookup {
+    fn new(primary: ConnectionPool) -> Self {
+        Self {
+            primary,
+            pins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn version(&self, deployment: &DeploymentHash, dataset: &str) -> Result<i32, StoreError> {
+        let key = (deployment.clone(), dataset.to_string());
+        if let Some(version) = self.pins.lock().unwrap().get(&key) {
+            return Ok(*version);
+        }
+
+        let conn = self.primary.get()?;
+        let version = primary::Connection::new(conn).pin_aux_dataset(deployment, dataset)?;
+        self.pins.lock().unwrap().insert(key, version);
+        Ok(version)
+    }
+}
+
+impl AuxLookupTrait for AuxLookup {
+    fn lookup(
+        &self,
+        deployment: &DeploymentHash,
+        dataset: &str,
+        key: &str,
+    ) -> Result<Option<String>, StoreError> {
+        if dataset == AUX_DATASET_ENS {
+            let conn = self.primary.get()?;
+            return primary::Connection::new(conn).find_ens_name(key);
+        }
+
+        let version = self.version(deployment, dataset)?;
+        if version == 0 {
+            return Ok(None);
+        }
+        let conn = self.primary.get()?;
+        primary::Connection::new(conn).find_aux_entry(dataset, version, key)
+    }
+}
+
+/// Reads the entities of other deployments for the `store.loadFrom` host
+/// function
+struct DeploymentReader {
+    inner: Arc<SubgraphStoreInner>,
+}
+
+impl DeploymentReaderTrait for DeploymentReader {
+    fn head(&self, deployment: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError> {
+        let (store, site) = self.inner.store(deployment)?;
+        store.block_ptr_sync(site)
+    }
+
+    fn earliest_block(&self, deployment: &DeploymentHash) -> Result<BlockNumber, StoreError> {
+        let (store, site) = self.inner.store(deployment)?;
+        store.earliest_block(site)
+    }
+
+    fn input_schema(&self, deployment: &DeploymentHash) -> Result<InputSchema, StoreError> {
+        let (store, site) = self.inner.store(deployment)?;
+        let layout = store.find_layout(site)?;
+        Ok(layout.input_schema.cheap_clone())
+    }
+
+    fn get(
+        &self,
+        deployment: &DeploymentHash,
+        key: &EntityKey,
+        block: BlockNumber,
+    ) -> Result<Option<Entity>, StoreError> {
+        let (store, site) = self.inner.store(deployment)?;
+        store.get(site, key, block)
+    }
+}
+
+/// Stores the query texts of persisted queries in the primary
+struct PersistedQueryStore {
+    primary: ConnectionPool,
+}
+
+impl PersistedQueryStoreTrait for PersistedQueryStore {
+    fn find_query(&self, hash: &str) -> Result<Option<String>, StoreError> {
+        let conn = self.primary.get()?;
+        primary::Connection::new(conn).find_persisted_query(hash)
+    }
+
+    fn add_query(&self, hash: &str, query: &str) -> Result<(), StoreError> {
+        let conn = self.primary.get()?;
+        primary::Connection::new(conn).add_persisted_query(
+            hash,
+            query,
+            ENV_VARS.graphql.persisted_queries_in_db_max,
+        )
+    }
+}
+
 #[async_trait::async_trait]
 impl SubgraphStoreTrait for SubgraphStore {
     fn ens_lookup(&self) -> Arc<dyn EnsLookupTrait> {
         Arc::new(EnsLookup::new(self.mirror.primary().clone()))
     }
 
+    fn aux_lookup(&self) -> Arc<dyn AuxLookupTrait> {
+        Arc::new(AuxLookup::new(self.mirror.primary().clone()))
+    }
+
+    fn deployment_reader(&self) -> Arc<dyn DeploymentReaderTrait> {
+        Arc::new(DeploymentReader {
+            inner: self.inner.cheap_clone(),
+        })
+    }
+
+    fn persisted_query_store(&self) -> Arc<dyn PersistedQueryStoreTrait> {
+        Arc::new(PersistedQueryStore {
+            primary: self.mirror.primary().clone(),
+        })
+    }
+
     // FIXME: This method should not get a node_id
     fn create_subgraph_deployment(
         &self,
@@ -1359,6 +1845,21 @@ impl SubgraphStoreTrait for SubgraphStore {
         })
     }
 
+    fn set_priority(
+        &self,
+        deployment: &DeploymentLocator,
+        priority: DeploymentPriority,
+    ) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let mut pconn = self.primary_conn()?;
+        pconn.set_priority(site.as_ref(), priority)
+    }
+
+    fn priority(&self, deployment: &DeploymentLocator) -> Result<DeploymentPriority, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        Ok(self.mirror.priority(site.as_ref())?.unwrap_or_default())
+    }
+
     fn assigned_node(&self, deployment: &DeploymentLocator) -> Result<Option<NodeId>, StoreError> {
         let site = self.find_site(deployment.id.into())?;
         self.mirror.assigned_node(site.as_ref())
@@ -1418,6 +1919,26 @@ impl SubgraphStoreTrait for SubgraphStore {
         Ok(changes)
     }
 
+    async fn deployment_entity_changes(
+        &self,
+        deployment: &DeploymentLocator,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<(BlockNumber, Vec<EntityOperation>)>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(site.as_ref())?;
+        store.get_changes_in_range(site, from, to).await
+    }
+
+    fn skipped_triggers(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Vec<SkippedTrigger>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(site.as_ref())?;
+        store.skipped_triggers(&site)
+    }
+
     fn input_schema(&self, id: &DeploymentHash) -> Result<InputSchema, StoreError> {
         let (store, site) = self.store(id)?;
         let layout = store.find_layout(site)?;
@@ -1574,4 +2095,143 @@ impl SubgraphStoreTrait for SubgraphStore {
         let info = store.subgraph_info(site)?;
         Ok(info.instrument)
     }
+
+    async fn create_entity_index(
+        &self,
+        id: &DeploymentHash,
+        entity: &str,
+        fields: Vec<String>,
+        method: &str,
+    ) -> Result<(), StoreError> {
+        let method = method
+            .parse::<Method>()
+            .map_err(|()| StoreError::Unknown(anyhow!("unknown index method `{}`", method)))?;
+        let (store, site) = self.store(id)?;
+        store
+            .create_manual_index(site, entity, fields, method, None)
+            .await
+    }
+
+    async fn entity_indexes(
+        &self,
+        id: &DeploymentHash,
+        entity: &str,
+    ) -> Result<Vec<store::EntityIndex>, StoreError> {
+        let (store, site) = self.store(id)?;
+        store.entity_indexes(site, entity).await
+    }
+
+    async fn deployment_stats(
+        &self,
+        id: &DeploymentHash,
+    ) -> Result<Vec<store::EntityTypeStats>, StoreError> {
+        let (store, site) = self.store(id)?;
+        store.deployment_stats(site).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use graph::components::store::DeploymentId as GraphDeploymentId;
+    use graph::tokio::runtime::Runtime;
+
+    use super::*;
+
+    fn locator(id: i32) -> DeploymentLocator {
+        DeploymentLocator::new(
+            GraphDeploymentId::new(id),
+            DeploymentHash::new("move").unwrap(),
+        )
+    }
+
+    fn wait(
+        max_lag: BlockNumber,
+        timeout: Option<Duration>,
+        mut heads: Vec<(BlockNumber, Option<BlockNumber>, bool)>,
+    ) -> (Result<(), StoreError>, usize) {
+        let checks = Cell::new(0);
+        let res = Runtime::new().unwrap().block_on(catch_up(
+            &locator(1),
+            &locator(2),
+            max_lag,
+            timeout,
+            Duration::from_millis(1),
+            || {
+                checks.set(checks.get() + 1);
+                let (src_head, dst_head, failed) = if heads.len() > 1 {
+                    heads.remove(0)
+                } else {
+                    heads[0]
+                };
+                CatchUp {
+                    src_head,
+                    dst_head,
+                    failed,
+                }
+            },
+        ));
+        (res, checks.get())
+    }
+
+    #[test]
+    fn catch_up_waits_for_copy() {
+        let heads = vec![
+            (100, None, false),
+            (100, Some(80), false),
+            (102, Some(92), false),
+        ];
+        let (res, checks) = wait(10, None, heads);
+        assert!(res.is_ok());
+        assert_eq!(3, checks);
+
+        let heads = vec![(100, Some(99), false), (100, Some(100), false)];
+        let (res, checks) = wait(0, Some(Duration::from_secs(60)), heads);
+        assert!(res.is_ok());
+        assert_eq!(2, checks);
+    }
+
+    #[test]
+    fn catch_up_stops_when_copy_fails() {
+        let heads = vec![(100, Some(50), false), (100, Some(60), true)];
+        let (res, checks) = wait(10, None, heads);
+        assert!(res.unwrap_err().to_string().contains("failed"));
+        assert_eq!(2, checks);
+    }
+
+    #[test]
+    fn catch_up_times_out() {
+        let heads = vec![(100, Some(50), false)];
+        let (res, checks) = wait(0, Some(Duration::from_millis(20)), heads);
+        assert!(res.unwrap_err().to_string().contains("did not catch up"));
+        assert!(checks > 1);
+    }
+
+    #[test]
+    fn resume_source_on_error() {
+        let resumed = Cell::new(false);
+        let resume = || {
+            resumed.set(true);
+            Ok(())
+        };
+        assert_eq!(7, resume_on_error(Ok(7), resume).unwrap());
+        assert!(!resumed.get());
+
+        let res: Result<(), _> = resume_on_error(
+            Err(StoreError::Unknown(anyhow!("activation failed"))),
+            resume,
+        );
+        assert!(res.unwrap_err().to_string().contains("activation failed"));
+        assert!(resumed.get());
+
+        // If resuming fails, too, both errors are reported
+        let res: Result<(), _> = resume_on_error(
+            Err(StoreError::Unknown(anyhow!("activation failed"))),
+            || Err(StoreError::Unknown(anyhow!("resume failed"))),
+        );
+        let msg = res.unwrap_err().to_string();
+        assert!(msg.contains("activation failed"));
+        assert!(msg.contains("resume failed"));
+    }
 }
@@ -0,0 +1,101 @@
+//! Track how many rows indexing writes to each entity table so that tables
+//! whose statistics are stale can be analyzed without waiting for
+//! autovacuum. With a large number of subgraphs, autovacuum often gets to
+//! a table long after its statistics stopped reflecting its contents, and
+//! the planner, working from a bad estimate of the number of rows, then
+//! picks sequential scans for queries on `block_range`
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::primary::{DeploymentId, Site};
+use crate::relational::SqlName;
+
+/// The rows written to the tables of one deployment
+pub(crate) struct Writes {
+    pub site: Arc<Site>,
+    pub rows: HashMap<SqlName, usize>,
+}
+
+/// Counts of the rows written to each table since it was last analyzed,
+/// kept in memory. Since the counts only serve as a heuristic, they are
+/// not persisted and start over when the node restarts
+pub(crate) struct TableChurn {
+    writes: Mutex<HashMap<DeploymentId, Writes>>,
+}
+
+impl TableChurn {
+    pub fn new() -> Self {
+        Self {
+            writes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, site: &Arc<Site>, table: &SqlName, rows: usize) {
+        let mut writes = self.writes.lock().unwrap();
+        let writes = writes.entry(site.id).or_insert_with(|| Writes {
+            site: site.clone(),
+            rows: HashMap::new(),
+        });
+        *writes.rows.entry(table.clone()).or_default() += rows;
+    }
+
+    /// Remove and return all recorded writes
+    pub fn take(&self) -> Vec<Writes> {
+        let mut writes = self.writes.lock().unwrap();
+        writes.drain().map(|(_, writes)| writes).collect()
+    }
+}
+
+/// Whether `written` rows are enough churn for a table with an estimated
+/// `estimate` rows to need analyzing. A `ratio` of 0 turns analyzing off
+pub(crate) fn is_churned(written: usize, estimate: f64, ratio: f64) -> bool {
+    ratio > 0.0 && written as f64 >= ratio * estimate
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::DeploymentHash;
+
+    use crate::primary::{make_dummy_site, Namespace};
+    use crate::relational::SqlName;
+
+    use super::*;
+
+    fn site(name: &str) -> Arc<Site> {
+        Arc::new(make_dummy_site(
+            DeploymentHash::new(name).unwrap(),
+            Namespace::new("sgd1".to_string()).unwrap(),
+            "mainnet".to_string(),
+        ))
+    }
+
+    #[test]
+    fn record_and_take() {
+        let churn = TableChurn::new();
+        let site = site("churn");
+        let thing = SqlName::verbatim("thing".to_string());
+        let other = SqlName::verbatim("other".to_string());
+
+        churn.record(&site, &thing, 3);
+        churn.record(&site, &thing, 4);
+        churn.record(&site, &other, 1);
+
+        let writes = churn.take();
+        assert_eq!(1, writes.len());
+        assert_eq!(Some(&7), writes[0].rows.get(&thing));
+        assert_eq!(Some(&1), writes[0].rows.get(&other));
+
+        // Taking drains the recorded writes
+        assert!(churn.take().is_empty());
+    }
+
+    #[test]
+    fn churn_ratio() {
+        assert!(!is_churned(5, 100.0, 0.1));
+        assert!(is_churned(10, 100.0, 0.1));
+        assert!(is_churned(1, 0.0, 0.1));
+        // A ratio of 0 is the default and turns analyzing off
+        assert!(!is_churned(1_000, 100.0, 0.0));
+        assert!(!is_churned(1, 0.0, 0.0));
+    }
+}
@@ -25,7 +25,7 @@ use graph::util::bounded_queue::BoundedQueue;
 use graph::{
     cheap_clone::CheapClone,
     components::store::{self, write::EntityOp, WritableStore as WritableStoreTrait},
-    data::subgraph::schema::SubgraphError,
+    data::subgraph::schema::{SkippedTrigger, SubgraphError},
     prelude::{
         BlockPtr, DeploymentHash, EntityModification, Error, Logger, StopwatchMetrics, StoreError,
         StoreEvent, UnfailOutcome, ENV_VARS,
@@ -287,6 +287,46 @@ impl SyncStore {
         .await
     }
 
+    async fn set_last_healthy_block(&self, block_ptr: BlockPtr) -> Result<(), StoreError> {
+        retry::forever_async(&self.logger, "set_last_healthy_block", || {
+            let block_ptr = block_ptr.clone();
+            async {
+                self.writable
+                    .set_last_healthy_block(self.site.deployment.clone(), block_ptr)
+                    .await
+            }
+        })
+        .await
+    }
+
+    async fn checkpoint_entity_cache(&self, keys: Vec<EntityKey>) -> Result<(), StoreError> {
+        // Checkpoints are only a hint, and retrying would hold up shutting
+        // down the subgraph; the next checkpoint replaces this one anyway
+        self.writable
+            .checkpoint_entity_cache(self.site.clone(), keys)
+            .await
+    }
+
+    fn entity_cache_checkpoint(&self) -> Result<Vec<EntityKey>, StoreError> {
+        self.writable
+            .entity_cache_checkpoint(self.site.cheap_clone())
+    }
+
+    async fn record_skipped_triggers(
+        &self,
+        triggers: Vec<SkippedTrigger>,
+    ) -> Result<(), StoreError> {
+        retry::forever_async(&self.logger, "record_skipped_triggers", || {
+            let triggers = triggers.clone();
+            async {
+                self.writable
+                    .record_skipped_triggers(self.site.clone(), triggers)
+                    .await
+            }
+        })
+        .await
+    }
+
     async fn supports_proof_of_indexing(&self) -> Result<bool, StoreError> {
         retry::forever_async(&self.logger, "supports_proof_of_indexing", || async {
             self.writable
@@ -358,6 +398,20 @@ impl SyncStore {
         })
     }
 
+    async fn find_recent(
+        &self,
+        entity_type: &EntityType,
+        count: usize,
+        block: BlockNumber,
+    ) -> Result<Vec<EntityKey>, StoreError> {
+        retry::forever_async(&self.logger, "find_recent", || async {
+            self.writable
+                .find_recent(self.site.cheap_clone(), entity_type.clone(), count, block)
+                .await
+        })
+        .await
+    }
+
     async fn is_deployment_synced(&self) -> Result<bool, StoreError> {
         retry::forever_async(&self.logger, "is_deployment_synced", || async {
             self.writable
@@ -367,6 +421,21 @@ impl SyncStore {
         .await
     }
 
+    async fn defer_indexes(&self) -> Result<(), StoreError> {
+        retry::forever_async(&self.logger, "defer_indexes", || async {
+            self.writable
+                .defer_indexes(&self.logger, self.site.clone())
+                .await
+        })
+        .await
+    }
+
+    async fn restore_deferred_indexes(&self) -> Result<bool, StoreError> {
+        self.writable
+            .restore_deferred_indexes(&self.logger, self.site.clone())
+            .await
+    }
+
     fn unassign_subgraph(&self, site: &Site) -> Result<(), StoreError> {
         retry::forever(&self.logger, "unassign_subgraph", || {
             let mut pconn = self.store.primary_conn()?;
@@ -1635,6 +1704,17 @@ impl WritableStoreTrait for WritableStore {
         self.store.fail_subgraph(error).await
     }
 
+    async fn set_last_healthy_block(&self, block_ptr: BlockPtr) -> Result<(), StoreError> {
+        self.store.set_last_healthy_block(block_ptr).await
+    }
+
+    async fn record_skipped_triggers(
+        &self,
+        triggers: Vec<SkippedTrigger>,
+    ) -> Result<(), StoreError> {
+        self.store.record_skipped_triggers(triggers).await
+    }
+
     async fn supports_proof_of_indexing(&self) -> Result<bool, StoreError> {
         self.store.supports_proof_of_indexing().await
     }
@@ -1707,6 +1787,14 @@ impl WritableStoreTrait for WritableStore {
         self.store.unassign_subgraph(&self.store.site)
     }
 
+    async fn defer_indexes(&self) -> Result<(), StoreError> {
+        self.store.defer_indexes().await
+    }
+
+    async fn restore_deferred_indexes(&self) -> Result<bool, StoreError> {
+        self.store.restore_deferred_indexes().await
+    }
+
     async fn load_dynamic_data_sources(
         &self,
         manifest_idx_and_name: Vec<(u32, String)>,
@@ -1716,6 +1804,24 @@ impl WritableStoreTrait for WritableStore {
             .await
     }
 
+    async fn recent_entity_keys(
+        &self,
+        entity_type: &EntityType,
+        count: usize,
+    ) -> Result<Vec<EntityKey>, StoreError> {
+        self.store
+            .find_recent(entity_type, count, BLOCK_NUMBER_MAX)
+            .await
+    }
+
+    async fn checkpoint_entity_cache(&self, keys: Vec<EntityKey>) -> Result<(), StoreError> {
+        self.store.checkpoint_entity_cache(keys).await
+    }
+
+    fn entity_cache_checkpoint(&self) -> Result<Vec<EntityKey>, StoreError> {
+        self.store.entity_cache_checkpoint()
+    }
+
     async fn causality_region_curr_val(&self) -> Result<Option<CausalityRegion>, StoreError> {
         // It should be empty when we call this, but just in case.
         self.writer.flush().await?;
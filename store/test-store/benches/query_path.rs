@@ -0,0 +1,60 @@
+//! Benchmarks for the query path, from parsing a GraphQL query to
+//! generating and running SQL in `relational_queries`. Use `--save-baseline`
+//! and `--baseline` to compare a change against the code before it; see
+//! the README for details.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use graph::data::query::QueryTarget;
+use graph::prelude::{q, DeploymentHash, Query, SubgraphStore as _};
+use graph_node::manager::commands::bench::fixtures::{self, QUERIES};
+use test_store::{
+    create_test_subgraph, execute_subgraph_query, insert_entities, STORE_RUNTIME, SUBGRAPH_STORE,
+};
+
+const DEFAULT_SIZE: usize = 1000;
+
+/// Create the benchmark subgraph and load the dataset into it. The
+/// deployment is recreated every time so that changes to the fixtures take
+/// effect
+fn setup() -> DeploymentHash {
+    // Cached results would hide the time spent in the store. This needs to
+    // happen before anything looks at `ENV_VARS`
+    std::env::set_var("GRAPH_QUERY_CACHE_BLOCKS", "0");
+    std::env::set_var("GRAPH_QUERY_LFU_CACHE_SHARDS", "0");
+
+    let size = std::env::var("GRAPH_BENCH_SIZE")
+        .ok()
+        .map(|size| size.parse().expect("GRAPH_BENCH_SIZE must be a number"))
+        .unwrap_or(DEFAULT_SIZE);
+    let id = DeploymentHash::new(format!("queryPathBench{}", size)).unwrap();
+
+    STORE_RUNTIME.handle().block_on(async {
+        let deployment = create_test_subgraph(&id, fixtures::SCHEMA).await;
+        let schema = SUBGRAPH_STORE.input_schema(&id).unwrap();
+        insert_entities(&deployment, fixtures::dataset(&schema, size))
+            .await
+            .unwrap();
+    });
+    id
+}
+
+fn query_path(c: &mut Criterion) {
+    let id = setup();
+    let target = QueryTarget::Deployment(id, Default::default());
+
+    let mut group = c.benchmark_group("query_path");
+    for query in &QUERIES {
+        let document = q::parse_query(query.text).unwrap().into_static();
+        group.bench_function(query.name, |b| {
+            b.to_async(&*STORE_RUNTIME).iter(|| async {
+                let query = Query::new(document.clone(), None, false);
+                let res = execute_subgraph_query(query, target.clone()).await;
+                assert!(!res.has_errors(), "query failed: {:?}", res.errors());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, query_path);
+criterion_main!(benches);
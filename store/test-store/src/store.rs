@@ -416,6 +416,22 @@ pub fn insert_ens_name(hash: &str, name: &str) {
         .unwrap();
 }
 
+/// Remove all versions of the auxiliary dataset `name` and what
+/// deployments they are pinned to
+pub fn remove_aux_dataset(name: &str) {
+    use diesel::prelude::*;
+    use graph_store_postgres::command_support::catalog::{aux_datasets, aux_pins};
+
+    let mut conn = PRIMARY_POOL.get().unwrap();
+
+    diesel::delete(aux_pins::table.filter(aux_pins::dataset.eq(name)))
+        .execute(&mut conn)
+        .unwrap();
+    diesel::delete(aux_datasets::table.filter(aux_datasets::name.eq(name)))
+        .execute(&mut conn)
+        .unwrap();
+}
+
 /// Insert the given entities and wait until all writes have been processed.
 /// The inserts all happen at `GENESIS_PTR`, i.e., block 0
 pub async fn insert_entities(
@@ -535,7 +551,7 @@ async fn execute_subgraph_query_internal(
         .unwrap();
     let state = store.deployment_state().await.unwrap();
     let by_block_constraint =
-        return_err!(StoreResolver::locate_blocks(store.as_ref(), &state, &query).await);
+        return_err!(StoreResolver::locate_blocks(store.as_ref(), &state, &query, None).await);
     for (ptr, (selection_set, error_policy)) in by_block_constraint {
         let logger = logger.clone();
         let resolver = return_err!(
@@ -616,7 +632,7 @@ fn build_store() -> (Arc<Store>, ConnectionPool, Config, Arc<SubscriptionManager
 
     let config = Config::load(&LOGGER, &opt)
         .unwrap_or_else(|_| panic!("config is not valid (file={:?})", &opt.config));
-    let registry = Arc::new(MetricsRegistry::mock());
+    let registry = METRICS_REGISTRY.clone();
     std::thread::spawn(move || {
         STORE_RUNTIME.handle().block_on(async {
             let builder = StoreBuilder::new(&LOGGER, &NODE_ID, &config, None, registry).await;
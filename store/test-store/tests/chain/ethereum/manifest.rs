@@ -10,8 +10,9 @@ use graph::data::store::scalar::Bytes;
 use graph::data::store::Value;
 use graph::data::subgraph::schema::SubgraphError;
 use graph::data::subgraph::{
-    Prune, LATEST_VERSION, SPEC_VERSION_0_0_4, SPEC_VERSION_0_0_7, SPEC_VERSION_0_0_8,
-    SPEC_VERSION_0_0_9, SPEC_VERSION_1_0_0, SPEC_VERSION_1_2_0,
+    HandlerLimits, Prune, LATEST_VERSION, SPEC_VERSION_0_0_4, SPEC_VERSION_0_0_7,
+    SPEC_VERSION_0_0_8, SPEC_VERSION_0_0_9, SPEC_VERSION_1_0_0, SPEC_VERSION_1_2_0,
+    SPEC_VERSION_1_5_0,
 };
 use graph::data_source::offchain::OffchainDataSourceKind;
 use graph::data_source::DataSourceTemplate;
@@ -26,6 +27,7 @@ use graph::{
     blockchain::NodeCapabilities as _,
     components::link_resolver::{JsonValueStream, LinkResolver as LinkResolverTrait},
     data::subgraph::SubgraphFeature,
+    runtime::gas::DEFAULT_FUEL_PER_HANDLER,
 };
 
 use graph::semver::Version;
@@ -70,6 +72,19 @@ impl LinkResolverTrait for TextResolver {
             .map(Clone::clone)
     }
 
+    async fn cat_bounded(
+        &self,
+        _logger: &Logger,
+        _link: &Link,
+        _max_bytes: usize,
+    ) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        unimplemented!()
+    }
+
+    async fn exists(&self, _logger: &Logger, _link: &Link) -> Result<bool, anyhow::Error> {
+        unimplemented!()
+    }
+
     async fn get_block(&self, _logger: &Logger, _link: &Link) -> Result<Vec<u8>, anyhow::Error> {
         unimplemented!()
     }
@@ -87,6 +102,15 @@ async fn resolve_manifest(
     text: &str,
     max_spec_version: Version,
 ) -> SubgraphManifest<graph_chain_ethereum::Chain> {
+    try_resolve_manifest(text, max_spec_version)
+        .await
+        .expect("Parsing simple manifest works")
+}
+
+async fn try_resolve_manifest(
+    text: &str,
+    max_spec_version: Version,
+) -> Result<SubgraphManifest<graph_chain_ethereum::Chain>, anyhow::Error> {
     let mut resolver = TextResolver::default();
     let id = DeploymentHash::new("Qmmanifest").unwrap();
 
@@ -99,9 +123,7 @@ async fn resolve_manifest(
     let resolver: Arc<dyn LinkResolverTrait> = Arc::new(resolver);
 
     let raw = serde_yaml::from_str(text).unwrap();
-    SubgraphManifest::resolve_from_raw(id, raw, &resolver, &LOGGER, max_spec_version)
-        .await
-        .expect("Parsing simple manifest works")
+    Ok(SubgraphManifest::resolve_from_raw(id, raw, &resolver, &LOGGER, max_spec_version).await?)
 }
 
 async fn resolve_unvalidated(text: &str) -> UnvalidatedSubgraphManifest<Chain> {
@@ -245,6 +267,199 @@ indexerHints:
     assert_eq!(manifest.history_blocks(), BLOCK_NUMBER_MAX);
 }
 
+#[tokio::test]
+async fn parse_indexer_hints_warmup() {
+    const YAML: &str = "
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 1.0.0
+indexerHints:
+  warmup:
+    - Token
+    - Pool
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_1_0_0).await;
+
+    assert_eq!(
+        Some(&["Token".to_string(), "Pool".to_string()][..]),
+        manifest.warmup_entity_types()
+    );
+    assert_eq!(manifest.history_blocks(), BLOCK_NUMBER_MAX);
+}
+
+#[tokio::test]
+async fn parse_indexer_hints_prune_by_entity() {
+    const YAML: &str = "
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 1.0.0
+indexerHints:
+  prune: 1000
+  pruneByEntity:
+    Thing: 100
+    TestEntity: never
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_1_0_0).await;
+
+    // The deployment keeps the longest history of any entity type
+    assert_eq!(manifest.history_blocks(), BLOCK_NUMBER_MAX);
+    assert_eq!(
+        vec![("Thing".to_string(), 100)],
+        manifest
+            .entity_history_blocks()
+            .into_iter()
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn parse_indexer_hints_storage_by_entity() {
+    const YAML: &str = "
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 1.0.0
+indexerHints:
+  storageByEntity:
+    Thing:
+      fillfactor: 80
+      autovacuumVacuumScaleFactor: 0.05
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_1_0_0).await;
+
+    let params = manifest.entity_storage_params();
+    assert_eq!(vec!["Thing"], params.keys().collect::<Vec<_>>());
+    assert_eq!(
+        vec![
+            ("fillfactor", "80".to_string()),
+            ("autovacuum_vacuum_scale_factor", "0.05".to_string())
+        ],
+        params["Thing"].options()
+    );
+}
+
+#[tokio::test]
+async fn parse_indexer_hints_handler_limits() {
+    const YAML: &str = "
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 1.0.0
+indexerHints:
+  handlerLimits:
+    handleTransfer:
+      timeout: 10
+      gas: 1000000
+    handleSwap:
+      gas: 5000
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_1_0_0).await;
+
+    let limits = manifest.handler_limits();
+    assert_eq!(
+        vec!["handleSwap", "handleTransfer"],
+        limits.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some(Duration::from_secs(10)),
+        limits["handleTransfer"].timeout()
+    );
+    assert_eq!(Some(1000000), limits["handleTransfer"].gas);
+    assert_eq!(None, limits["handleSwap"].timeout());
+    assert!(limits.values().all(|limits| limits.validate().is_ok()));
+
+    let zero_timeout = HandlerLimits {
+        timeout: Some(0),
+        gas: None,
+    };
+    assert!(zero_timeout.validate().is_err());
+}
+
+#[tokio::test]
+async fn parse_indexer_hints_error_policy() {
+    const YAML: &str = "
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 1.0.0
+indexerHints:
+  errorPolicy:
+    handleTransfer: fail
+    handleMetadata: skip
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_1_0_0).await;
+
+    assert_eq!(
+        vec!["handleMetadata"],
+        manifest.skip_error_handlers().iter().collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn parse_indexer_hints_fuel() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      abi: Factory
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      blockHandlers:
+        - handler: handleBlock
+schema:
+  file:
+    /: /ipfs/Qmschema
+";
+
+    // Handlers of subgraphs with older spec versions are not metered
+    let yaml = format!("{YAML}specVersion: 1.0.0\n");
+    let manifest = resolve_manifest(&yaml, SPEC_VERSION_1_5_0).await;
+    assert_eq!(None, manifest.fuel());
+
+    let yaml = format!("{YAML}specVersion: 1.0.0\nindexerHints:\n  fuel: 1000000\n");
+    let err = try_resolve_manifest(&yaml, SPEC_VERSION_1_5_0)
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("`indexerHints.fuel` is not supported prior to 1.5.0"),
+        "{}",
+        err
+    );
+
+    let yaml = format!("{YAML}specVersion: 1.5.0\n");
+    let manifest = resolve_manifest(&yaml, SPEC_VERSION_1_5_0).await;
+    assert_eq!(Some(DEFAULT_FUEL_PER_HANDLER), manifest.fuel());
+
+    let yaml = format!("{YAML}specVersion: 1.5.0\nindexerHints:\n  fuel: 1000000\n");
+    let manifest = resolve_manifest(&yaml, SPEC_VERSION_1_5_0).await;
+    assert_eq!(Some(1000000), manifest.fuel());
+}
+
 #[test]
 fn graft_failed_subgraph() {
     const YAML: &str = "
@@ -986,6 +1201,54 @@ specVersion: 0.0.2
     assert_eq!(true, required_capabilities.traces);
 }
 
+#[tokio::test]
+async fn parse_transfer_handlers() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Vault
+    network: mainnet
+    source:
+      address: \"0x0000000000000000000000000000000000000001\"
+      abi: Vault
+      startBlock: 9562480
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.9
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Vault
+          file:
+            /: /ipfs/Qmabi
+      transferHandlers:
+        - handler: handleTransfer
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 1.2.0
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_1_2_0).await;
+    let onchain_data_sources = manifest
+        .data_sources
+        .iter()
+        .filter_map(|ds| ds.as_onchain().cloned())
+        .collect::<Vec<_>>();
+    let required_capabilities = NodeCapabilities::from_data_sources(&onchain_data_sources);
+
+    let data_source = onchain_data_sources.first().unwrap();
+    assert_eq!(1, data_source.mapping.transfer_handlers.len());
+    assert_eq!(
+        "handleTransfer",
+        data_source.mapping.transfer_handlers[0].handler
+    );
+    assert_eq!(true, required_capabilities.traces);
+}
+
 #[test]
 fn undeclared_grafting_feature_causes_feature_validation_error() {
     const YAML: &str = "
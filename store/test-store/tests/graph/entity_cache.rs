@@ -4,8 +4,10 @@ use graph::components::store::{
     DeploymentCursorTracker, DerivedEntityQuery, GetScope, LoadRelatedRequest, ReadStore,
     StoredDynamicDataSource, WritableStore,
 };
-use graph::data::store::Id;
-use graph::data::subgraph::schema::{DeploymentCreate, SubgraphError, SubgraphHealth};
+use graph::data::store::{Id, IdType};
+use graph::data::subgraph::schema::{
+    DeploymentCreate, SkippedTrigger, SubgraphError, SubgraphHealth,
+};
 use graph::data_source::CausalityRegion;
 use graph::schema::{EntityKey, EntityType, InputSchema};
 use graph::{
@@ -16,9 +18,10 @@ use graph::{entity, prelude::*};
 use hex_literal::hex;
 
 use graph::semver::Version;
+use graph::util::lfu_cache::LfuCache;
 use lazy_static::lazy_static;
 use slog::Logger;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::marker::PhantomData;
 use std::sync::Arc;
 use web3::types::H256;
@@ -121,6 +124,14 @@ impl WritableStore for MockStore {
         unimplemented!()
     }
 
+    async fn set_last_healthy_block(&self, _: BlockPtr) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    async fn record_skipped_triggers(&self, _: Vec<SkippedTrigger>) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
     async fn supports_proof_of_indexing(&self) -> Result<bool, StoreError> {
         unimplemented!()
     }
@@ -149,6 +160,14 @@ impl WritableStore for MockStore {
         unimplemented!()
     }
 
+    async fn defer_indexes(&self) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    async fn restore_deferred_indexes(&self) -> Result<bool, StoreError> {
+        unimplemented!()
+    }
+
     async fn load_dynamic_data_sources(
         &self,
         _manifest_idx_and_name: Vec<(u32, String)>,
@@ -176,6 +195,22 @@ impl WritableStore for MockStore {
         unimplemented!()
     }
 
+    async fn checkpoint_entity_cache(&self, _: Vec<EntityKey>) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn entity_cache_checkpoint(&self) -> Result<Vec<EntityKey>, StoreError> {
+        unimplemented!()
+    }
+
+    async fn recent_entity_keys(
+        &self,
+        _: &EntityType,
+        _: usize,
+    ) -> Result<Vec<EntityKey>, StoreError> {
+        unimplemented!()
+    }
+
     async fn restart(self: Arc<Self>) -> Result<Option<Arc<dyn WritableStore>>, StoreError> {
         unimplemented!()
     }
@@ -309,6 +344,79 @@ fn consecutive_modifications() {
     );
 }
 
+#[test]
+fn forked_block_states() {
+    let store = {
+        let entities = vec![entity! { SCHEMA => id: "mogwai", name: "Mogwai" }];
+        MockStore::new(entity_version_map("Band", entities))
+    };
+    let mut state = BlockState::new(store, LfuCache::default());
+
+    let mogwai_key = make_band_key("mogwai");
+    let sigurros_key = make_band_key("sigurros");
+    let mogwai_data = entity! { SCHEMA => id: "mogwai", founded: 1995 };
+    state
+        .entity_cache
+        .set(mogwai_key.clone(), mogwai_data)
+        .unwrap();
+
+    let mut forks = state.forks(3);
+    let mut generator = forks.pop().unwrap();
+    let mut writer = forks.pop().unwrap();
+    let mut reader = forks.pop().unwrap();
+
+    // Reads in a fork see the changes made before the fork
+    let mogwai = reader
+        .entity_cache
+        .get(&mogwai_key, GetScope::Store)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        mogwai.as_ref(),
+        &entity! { SCHEMA => id: "mogwai", name: "Mogwai", founded: 1995 }
+    );
+
+    let sigurros_data = entity! { SCHEMA => id: "sigurros", name: "Sigur Ros" };
+    writer
+        .entity_cache
+        .set(sigurros_key.clone(), sigurros_data.clone())
+        .unwrap();
+
+    let reads = reader.entity_cache.footprint();
+    let writes = writer.entity_cache.footprint();
+    assert_eq!(writes.writes, HashSet::from([sigurros_key.clone()]));
+    assert!(!reads.conflicts_with(&writes.writes));
+    assert!(reads.conflicts_with(&HashSet::from([mogwai_key.clone()])));
+
+    // Generated ids depend on the order of triggers
+    generator
+        .entity_cache
+        .generate_id(IdType::Bytes, 1)
+        .unwrap();
+    assert!(generator
+        .entity_cache
+        .footprint()
+        .conflicts_with(&HashSet::new()));
+
+    // Merging a fork only applies the changes made in it
+    reader.entity_cache.detach();
+    writer.entity_cache.detach();
+    state.extend(reader);
+    state.extend(writer);
+    let result = state.entity_cache.as_modifications(0);
+    assert_eq!(
+        sort_by_entity_key(result.unwrap().modifications),
+        sort_by_entity_key(vec![
+            EntityModification::overwrite(
+                mogwai_key,
+                entity! { SCHEMA => id: "mogwai", name: "Mogwai", founded: 1995 },
+                0
+            ),
+            EntityModification::insert(sigurros_key, sigurros_data, 0)
+        ])
+    );
+}
+
 const ACCOUNT_GQL: &str = "
     type Account @entity {
         id: ID!
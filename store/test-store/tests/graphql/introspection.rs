@@ -7,10 +7,10 @@ use graph::components::store::QueryPermit;
 use graph::data::graphql::{object_value, ObjectOrInterface};
 use graph::data::query::Trace;
 use graph::prelude::{
-    async_trait, o, q, r, s, serde_json, slog, tokio, DeploymentHash, Logger, Query,
+    async_trait, o, q, r, s, serde_json, slog, tokio, DeploymentHash, Logger, Query, QueryError,
     QueryExecutionError, QueryResult,
 };
-use graph::schema::{ApiSchema, InputSchema};
+use graph::schema::{ApiSchema, InputSchema, Schema};
 
 use graph_graphql::prelude::{
     a, execute_query, ExecutionContext, Query as PreparedQuery, QueryExecutionOptions, Resolver,
@@ -960,3 +960,113 @@ async fn introspection_possible_types() {
         )])
     )
 }
+
+#[test]
+fn introspection_only_deployments_reject_data_queries() {
+    let schema = mock_schema();
+    let logger = Logger::root(slog::Discard, o!());
+    let check = |query: &str, only: &[&str]| {
+        let query = Query::new(q::parse_query(query).unwrap().into_static(), None, false);
+        let only: Vec<_> = only.iter().map(|id| id.to_string()).collect();
+        PreparedQuery::new(
+            &logger,
+            schema.clone(),
+            None,
+            query,
+            None,
+            100,
+            graphql_metrics(),
+        )
+        .unwrap()
+        .check_introspection_only(&only)
+    };
+
+    const INTROSPECTION: &str = "{ __typename __schema { queryType { name } } }";
+    const DATA: &str = "{ users { id } }";
+    const MIXED: &str = "{ __type(name: \"User\") { name } users { id } }";
+
+    // Deployments that are not listed accept any query
+    assert!(check(DATA, &[]).is_ok());
+    assert!(check(MIXED, &["otherschema"]).is_ok());
+
+    // Listed deployments only accept introspection
+    assert!(check(INTROSPECTION, &["mockschema"]).is_ok());
+    for query in [DATA, MIXED] {
+        match check(query, &["otherschema", "mockschema"]) {
+            Err(QueryExecutionError::IntrospectionOnly(id)) => assert_eq!("mockschema", id),
+            res => panic!("query `{}` was not rejected: {:?}", query, res),
+        }
+    }
+}
+
+/// A schema that, like the one for the index node, is not a subgraph
+/// schema and has a `Mutation` type
+fn mutation_schema() -> Arc<ApiSchema> {
+    let document = s::parse_schema(
+        "type Query { version: String }
+         type Mutation { echo(text: String!): String }",
+    )
+    .unwrap();
+    let id = DeploymentHash::new("mutationschema").unwrap();
+    Arc::new(ApiSchema::from_graphql_schema(Schema::new(id, document).unwrap()).unwrap())
+}
+
+#[tokio::test]
+async fn executes_mutations_if_schema_has_mutation_type() {
+    let result = introspection_query(mutation_schema(), "mutation { echo(text: \"hi\") }").await;
+    assert!(!result.has_errors(), "{:#?}", result);
+    assert_eq!(
+        object_value(vec![("echo", r::Value::Null)]),
+        result.to_result().unwrap().unwrap()
+    );
+
+    // Fields of the `Mutation` type can only be used in mutations
+    let result = introspection_query(mutation_schema(), "{ echo(text: \"hi\") }").await;
+    assert!(result.has_errors());
+
+    // Subgraph schemas do not have a `Mutation` type
+    let result = introspection_query(mock_schema(), "mutation { echo(text: \"hi\") }").await;
+    match result.to_result() {
+        Err(errors) => match &errors[0] {
+            QueryError::ExecutionError(QueryExecutionError::NotSupported(msg)) => {
+                assert_eq!("Mutations are not supported", msg)
+            }
+            e => panic!("unexpected error: {:?}", e),
+        },
+        Ok(data) => panic!("mutation was not rejected: {:?}", data),
+    }
+}
+
+#[tokio::test]
+async fn introspection_mutation_type() {
+    const QUERY: &str = "{ __schema { mutationType { name } } }";
+
+    let response = introspection_query(mutation_schema(), QUERY)
+        .await
+        .to_result()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        object_value(vec![(
+            "__schema",
+            object_value(vec![(
+                "mutationType",
+                object_value(vec![("name", r::Value::String("Mutation".to_string()))])
+            )])
+        )]),
+        response
+    );
+
+    let response = introspection_query(mock_schema(), QUERY)
+        .await
+        .to_result()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        object_value(vec![(
+            "__schema",
+            object_value(vec![("mutationType", r::Value::Null)])
+        )]),
+        response
+    );
+}
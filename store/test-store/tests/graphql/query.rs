@@ -24,7 +24,7 @@ use graph::{
     data::graphql::{object, object_value},
     data::subgraph::schema::SubgraphError,
     data::{
-        query::{QueryResults, QueryTarget},
+        query::{QueryCostModel, QueryResults, QueryTarget},
         subgraph::SubgraphFeature,
     },
     prelude::{
@@ -284,6 +284,13 @@ fn test_schema(id: DeploymentHash, id_type: IdType) -> InputSchema {
         media: [Media!]!
         release: Release! @derivedFrom(field: \"songs\")
         stats: [SongStat!]! @derivedFrom(field: \"id\")
+        cover: Cover @derivedFrom(field: \"song\")
+    }
+
+    type Cover @entity(immutable: true) {
+        id: ID!
+        size: Int8!
+        song: Song!
     }
 
     type SongStat @entity {
@@ -502,6 +509,13 @@ async fn insert_test_entities(
                 entity! { is => id: s[2], played: 15 },
             ],
         ),
+        (
+            "Cover",
+            vec![
+                entity! { is => id: "c1", size: 300i64, song: s[1] },
+                entity! { is => id: "c2", size: 100i64, song: s[2] },
+            ],
+        ),
         (
             "BandReview",
             vec![
@@ -572,13 +586,19 @@ async fn insert_test_entities(
     ];
     let entities0 = insert_ops(&manifest.schema, entities0);
 
-    let entities1 = vec![(
-        "Musician",
-        vec![
-            entity! { is => id: "m3", name: "Tom", mainBand: "b2", bands: vec!["b1", "b2"], favoriteCount: 5, birthDate: timestamp.clone() },
-            entity! { is => id: "m4", name: "Valerie", bands: Vec::<String>::new(), favoriteCount: 20, birthDate: timestamp.clone() },
-        ],
-    )];
+    let entities1 = vec![
+        (
+            "Musician",
+            vec![
+                entity! { is => id: "m3", name: "Tom", mainBand: "b2", bands: vec!["b1", "b2"], favoriteCount: 5, birthDate: timestamp.clone() },
+                entity! { is => id: "m4", name: "Valerie", bands: Vec::<String>::new(), favoriteCount: 20, birthDate: timestamp.clone() },
+            ],
+        ),
+        (
+            "Cover",
+            vec![entity! { is => id: "c3", size: 200i64, song: s[3] }],
+        ),
+    ];
     let entities1 = insert_ops(&manifest.schema, entities1);
 
     insert_at(entities0, &deployment, BLOCKS[0].clone()).await;
@@ -612,6 +632,7 @@ async fn execute_query_document_with_variables(
         SUBSCRIPTION_MANAGER.clone(),
         LOAD_MANAGER.clone(),
         METRICS_REGISTRY.clone(),
+        None,
     ));
     let target = QueryTarget::Deployment(id.clone(), Default::default());
     let query = Query::new(query, variables, false);
@@ -723,6 +744,7 @@ where
                     SUBSCRIPTION_MANAGER.clone(),
                     LOAD_MANAGER.clone(),
                     METRICS_REGISTRY.clone(),
+                    None,
                 ));
                 let target = QueryTarget::Deployment(id.clone(), Default::default());
                 let query = Query::new(query, variables, false);
@@ -1007,6 +1029,28 @@ fn can_query_with_fulltext_search_filter() {
     })
 }
 
+#[test]
+fn can_query_with_fulltext_search_options() {
+    const QUERY: &str = "
+    query {
+        bandReviewSearch(text: \"musicians\", rankBy: RANK, language: en, highlight: true) {
+            id
+            body
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            bandReviewSearch: vec![
+                object! { id: "r1", body: "Bad <b>musicians</b>" },
+                object! { id: "r5", body: "Very Bad <b>musicians</b>" },
+            ]
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
 #[test]
 fn can_query_with_sorting_by_child_entity() {
     const QUERY: &str = "
@@ -1139,6 +1183,88 @@ fn can_query_with_sorting_by_child_entity_id() {
     })
 }
 
+#[test]
+fn can_query_with_sorting_by_child_entity_int8() {
+    const QUERY: &str = "
+    query {
+        desc: songs(first: 100, orderBy: writtenBy__favoriteCount, orderDirection: desc) {
+            title
+            writtenBy { name favoriteCount }
+        }
+        asc: songs(first: 100, orderBy: writtenBy__favoriteCount, orderDirection: asc) {
+            title
+            writtenBy { name favoriteCount }
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            desc: vec![
+                object! { title: "Rock Tune",   writtenBy: object! { name: "Lisa", favoriteCount: "100" } },
+                object! { title: "Pop Tune",    writtenBy: object! { name: "John", favoriteCount: "10" } },
+                object! { title: "Cheesy Tune", writtenBy: object! { name: "John", favoriteCount: "10" } },
+                object! { title: "Folk Tune",   writtenBy: object! { name: "Tom",  favoriteCount: "5" } },
+                ],
+            asc: vec![
+                object! { title: "Folk Tune",   writtenBy: object! { name: "Tom",  favoriteCount: "5" } },
+                object! { title: "Cheesy Tune", writtenBy: object! { name: "John", favoriteCount: "10" } },
+                object! { title: "Pop Tune",    writtenBy: object! { name: "John", favoriteCount: "10" } },
+                object! { title: "Rock Tune",   writtenBy: object! { name: "Lisa", favoriteCount: "100" } },
+                ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_sorting_by_immutable_derived_child_entity() {
+    const QUERY: &str = "
+    query {
+        desc: songs(first: 100, orderBy: cover__size, orderDirection: desc) {
+            title
+            cover { size }
+        }
+        asc: songs(first: 100, orderBy: cover__size, orderDirection: asc) {
+            title
+            cover { size }
+        }
+        old: songs(first: 100, orderBy: cover__size, orderDirection: asc, block: { number: 0 }) {
+            title
+            cover { size }
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        // Songs without a cover sort first for `desc` and last for `asc`;
+        // the cover for 'Pop Tune' was only added at block 1
+        let exp = object! {
+            desc: vec![
+                object! { title: "Folk Tune",   cover: r::Value::Null },
+                object! { title: "Cheesy Tune", cover: object! { size: "300" } },
+                object! { title: "Pop Tune",    cover: object! { size: "200" } },
+                object! { title: "Rock Tune",   cover: object! { size: "100" } },
+                ],
+            asc: vec![
+                object! { title: "Rock Tune",   cover: object! { size: "100" } },
+                object! { title: "Pop Tune",    cover: object! { size: "200" } },
+                object! { title: "Cheesy Tune", cover: object! { size: "300" } },
+                object! { title: "Folk Tune",   cover: r::Value::Null },
+                ],
+            old: vec![
+                object! { title: "Rock Tune",   cover: object! { size: "100" } },
+                object! { title: "Cheesy Tune", cover: object! { size: "300" } },
+                object! { title: "Pop Tune",    cover: r::Value::Null },
+                object! { title: "Folk Tune",   cover: r::Value::Null },
+                ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
 #[test]
 fn can_query_with_sorting_by_derived_child_entity_id() {
     const QUERY: &str = "
@@ -1799,6 +1925,95 @@ fn include_directive_works_with_query_variables() {
     });
 }
 
+#[test]
+fn conditional_fragment_spreads_at_block() {
+    const QUERY: &str = "
+    query musicians($block: Bytes!, $bands: Boolean!, $noSongs: Boolean!) {
+      musicians(block: { hash: $block }, first: 100, orderBy: id) {
+        id
+        ...bandInfo @include(if: $bands)
+      }
+    }
+
+    fragment bandInfo on Musician {
+      bands(first: 100, orderBy: id) {
+        id
+        ...songInfo @skip(if: $noSongs)
+      }
+    }
+
+    fragment songInfo on Band {
+      originalSongs(first: 100, orderBy: sid) { sid }
+    }
+";
+
+    let block = BLOCKS[0].hash.to_string();
+
+    let vars = object! { block: block.clone(), bands: false, noSongs: true };
+    run_query((QUERY, vars), |result, _| {
+        let exp = object! {
+            musicians: vec![ object! { id: "m1" }, object! { id: "m2" } ]
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    });
+
+    let vars = object! { block: block.clone(), bands: true, noSongs: true };
+    run_query((QUERY, vars), |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { id: "m1", bands: vec![ object! { id: "b1" }, object! { id: "b2" } ] },
+                object! { id: "m2", bands: vec![ object! { id: "b1" } ] },
+            ]
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    });
+
+    let vars = object! { block: block, bands: true, noSongs: false };
+    run_query((QUERY, vars), |result, _| {
+        let b1 = object! {
+            id: "b1",
+            originalSongs: vec![ object! { sid: "s1" }, object! { sid: "s2" } ]
+        };
+        let b2 = object! {
+            id: "b2",
+            originalSongs: vec![
+                object! { sid: "s1" }, object! { sid: "s3" }, object! { sid: "s4" }
+            ]
+        };
+        let exp = object! {
+            musicians: vec![
+                object! { id: "m1", bands: vec![ b1.clone(), b2 ] },
+                object! { id: "m2", bands: vec![ b1 ] },
+            ]
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    });
+}
+
+#[test]
+fn skipped_fragment_spread_does_not_hide_later_spread() {
+    const QUERY: &str = "
+    query {
+      musicians(first: 100, orderBy: id, where: { id: \"m1\" }) {
+        id
+        ...musicianName @skip(if: true)
+        ...musicianName
+      }
+    }
+
+    fragment musicianName on Musician { name }
+";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! { musicians: vec![ object! { id: "m1", name: "John" } ] };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    });
+}
+
 #[test]
 fn query_complexity() {
     const QUERY1: &str = "query {
@@ -2202,6 +2417,60 @@ fn can_use_nested_filter() {
     })
 }
 
+#[test]
+fn can_filter_nested_child_collections() {
+    // Filters on nested collections are part of the query that loads the
+    // children for all parents at once; check that they work for derived
+    // fields and for lists stored in the parent, and that they can use
+    // child filters themselves
+    const QUERY: &str = "
+    query {
+        musicians(orderBy: id) {
+            name
+            writtenSongs(orderBy: sid, where: { title_contains: \"Tune\", sid_not: \"s1\" }) { sid }
+            bands(orderBy: id, where: { name_starts_with: \"The A\" }) {
+                id
+                originalSongs(orderBy: sid, where: { writtenBy_: { name: \"John\" } }) { sid }
+            }
+        }
+    }
+    ";
+
+    run_query(QUERY, |result, _| {
+        let b2 = object! {
+            id: "b2",
+            originalSongs: vec![ object! { sid: "s1" }, object! { sid: "s3" } ]
+        };
+        let exp = object! {
+            musicians: vec![
+                object! {
+                    name: "John",
+                    writtenSongs: vec![ object! { sid: "s3" } ],
+                    bands: vec![ b2.clone() ]
+                },
+                object! {
+                    name: "Lisa",
+                    writtenSongs: vec![ object! { sid: "s2" } ],
+                    bands: Vec::<r::Value>::new(),
+                },
+                object! {
+                    name: "Tom",
+                    writtenSongs: vec![ object! { sid: "s4" } ],
+                    bands: vec![ b2 ]
+                },
+                object! {
+                    name: "Valerie",
+                    writtenSongs: Vec::<r::Value>::new(),
+                    bands: Vec::<r::Value>::new(),
+                }
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
 // see: graphql-bug-compat
 #[test]
 fn ignores_invalid_field_arguments() {
@@ -2649,6 +2918,80 @@ fn can_query_meta() {
         };
         assert_eq!(extract_data!(result), Some(exp));
     });
+
+    // indexing status; nothing was reverted or pruned, and all blocks are
+    // still within the reorg threshold
+    const QUERY6: &str = "query { _meta { indexingStatus { reorgCount maxReorgDepth \
+                          firstNonFinalBlock earliestBlock __typename } } }";
+    run_query(QUERY6, |result, _| {
+        let exp = object! {
+            _meta: object! {
+                indexingStatus: object! {
+                    reorgCount: 0,
+                    maxReorgDepth: 0,
+                    firstNonFinalBlock: 0,
+                    earliestBlock: 0,
+                    __typename: "_IndexingStatus_"
+                },
+            },
+        };
+        assert_eq!(extract_data!(result), Some(exp));
+    });
+}
+
+#[test]
+fn can_query_meta_indexing_status() {
+    const QUERY: &str = "query { _meta { indexingStatus { reorgCount maxReorgDepth \
+                         firstNonFinalBlock earliestBlock } } }";
+
+    fn status(reorgs: i32, depth: i32, first_non_final: i32, earliest: i32) -> Option<r::Value> {
+        Some(object! {
+            _meta: object! {
+                indexingStatus: object! {
+                    reorgCount: reorgs,
+                    maxReorgDepth: depth,
+                    firstNonFinalBlock: first_non_final,
+                    earliestBlock: earliest,
+                },
+            },
+        })
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup(
+            store.as_ref(),
+            "graphqlMetaIndexingStatus",
+            BTreeSet::new(),
+            IdType::String,
+        )
+        .await;
+
+        // Reverting a block is a reorg of depth 1
+        revert_block(&STORE, &deployment, &BLOCKS[1]).await;
+        let result = execute_query(&deployment, QUERY).await;
+        assert_eq!(status(1, 1, 0, 0), extract_data!(result));
+
+        // Pretend that the head of the deployment is past the reorg
+        // threshold, and that blocks before block 5 were pruned; see
+        // c435c25decbc4ad7bbbadf8e0ced0ff2
+        let mut state = deployment_state(STORE.as_ref(), &deployment.hash).await;
+        let threshold = graph::env::ENV_VARS.reorg_threshold;
+        state.latest_block = BlockPtr::new(BLOCKS[1].hash.clone(), threshold + 10);
+        for (earliest, first_non_final) in [(5, 11), (20, 20)] {
+            state.earliest_block_number = earliest;
+            *graph_graphql::test_support::INITIAL_DEPLOYMENT_STATE_FOR_TESTS
+                .lock()
+                .unwrap() = Some(state.clone());
+            let result = execute_query(&deployment, QUERY).await;
+            *graph_graphql::test_support::INITIAL_DEPLOYMENT_STATE_FOR_TESTS
+                .lock()
+                .unwrap() = None;
+            assert_eq!(
+                status(1, 1, first_non_final, earliest),
+                extract_data!(result)
+            );
+        }
+    })
 }
 
 #[test]
@@ -2998,6 +3341,89 @@ fn can_query_with_or_implicit_and_filter() {
     })
 }
 
+#[test]
+fn can_query_with_or_filter_on_child_entities() {
+    const QUERY: &str = "
+    query {
+        musicians(
+          orderBy: id,
+          where: { or: [{ mainBand_: { name: \"The Amateurs\" } }, { name: \"Lisa\" }] }
+        ) {
+          name
+          id
+        }
+      }
+    ";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "Lisa", id: "m2" },
+                object! { name: "Tom", id: "m3" },
+            ],
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_nested_child_filters() {
+    const QUERY: &str = "
+    query {
+        songs(
+          orderBy: sid,
+          where: {
+            writtenBy_: { or: [{ mainBand_: { name: \"The Amateurs\" } }, { name: \"Lisa\" }] }
+          }
+        ) {
+          sid
+        }
+      }
+    ";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            songs: vec![
+                object! { sid: "s2" },
+                object! { sid: "s4" },
+            ],
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_case_insensitive_and_regex_filters() {
+    const QUERY: &str = "
+    query {
+        imatches: musicians(orderBy: id, where: { name_imatches: \"%O%\" }) { id }
+        regex: songs(orderBy: sid, where: { title_regex: \"^(Rock|Pop) \" }) { sid }
+        iregex: songs(orderBy: sid, where: { title_iregex: \"^c|^f\" }) { sid }
+      }
+    ";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            imatches: vec![
+                object! { id: "m1" },
+                object! { id: "m3" },
+            ],
+            regex: vec![
+                object! { sid: "s2" },
+                object! { sid: "s3" },
+            ],
+            iregex: vec![
+                object! { sid: "s1" },
+                object! { sid: "s4" },
+            ],
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
 #[test]
 fn trace_works() {
     const QUERY1: &str = "query { musicians(first: 100) { name } }";
@@ -3056,6 +3482,68 @@ fn trace_works() {
     })
 }
 
+#[test]
+fn apollo_tracing_lists_resolvers() {
+    const QUERY: &str = "query {
+        musicians(first: 2, orderBy: id) {
+            name
+            bands(first: 2, orderBy: id) { name }
+        }
+    }";
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+        let query = Query::new(q::parse_query(QUERY).unwrap().into_static(), None, true);
+        let result = execute_subgraph_query(
+            query,
+            QueryTarget::Deployment(deployment.hash.clone(), Default::default()),
+        )
+        .await;
+        assert!(!result.has_errors());
+
+        let tracing = result.trace.apollo_tracing(
+            std::time::SystemTime::now(),
+            Duration::from_millis(1),
+            Duration::from_millis(10),
+        );
+        let tracing = serde_json::to_value(&tracing).unwrap();
+        assert_eq!(1, tracing["version"]);
+        assert_eq!(10_000_000, tracing["duration"]);
+        assert_eq!(1_000_000, tracing["parsing"]["duration"]);
+        assert_eq!(2, tracing["database"]["statements"]);
+
+        let resolvers = tracing["execution"]["resolvers"].as_array().unwrap();
+        let fields: Vec<_> = resolvers
+            .iter()
+            .map(|resolver| {
+                (
+                    resolver["path"].clone(),
+                    resolver["parentType"].as_str().unwrap().to_string(),
+                    resolver["returnType"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    serde_json::json!(["musicians"]),
+                    "Query".to_string(),
+                    "[Musician!]!".to_string()
+                ),
+                (
+                    serde_json::json!(["musicians", "bands"]),
+                    "Musician".to_string(),
+                    "[Band!]!".to_string()
+                ),
+            ],
+            fields
+        );
+        for resolver in resolvers {
+            assert!(resolver["startOffset"].as_i64().unwrap() >= 1_000_000);
+        }
+    })
+}
+
 /// Check that various comparisons against `id` work as expected. This also
 /// serves as a test that they work for `String` as well as `Bytes` fields
 /// in general
@@ -3168,3 +3656,171 @@ fn simple_aggregation() {
         assert_eq!(data, exp);
     })
 }
+
+#[test]
+fn deferred_fragments_are_delivered_incrementally() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 1, orderBy: id) { id }
+        ... @defer(label: \"bands\") {
+            bands(first: 1, orderBy: id) { id }
+        }
+    }";
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+        let runner = Arc::new(GraphQlRunner::new(
+            &LOGGER,
+            STORE.clone(),
+            SUBSCRIPTION_MANAGER.clone(),
+            LOAD_MANAGER.clone(),
+            METRICS_REGISTRY.clone(),
+            None,
+        ));
+        let target = QueryTarget::Deployment(deployment.hash.clone(), Default::default());
+        let query = || Query::new(q::parse_query(QUERY).unwrap().into_static(), None, false);
+        let result = runner.clone().run_query(query(), target.clone()).await;
+
+        assert!(result.is_incremental());
+        let initial = extract_data!(result.first().unwrap().duplicate()).unwrap();
+        assert_eq!(initial, object! { musicians: vec![object! { id: "m1" }] });
+
+        // Clients that do not accept incremental delivery get one response
+        let full = serde_json::to_string(&result).unwrap();
+        assert_eq!(
+            full,
+            r#"{"data":{"musicians":[{"id":"m1"}],"bands":[{"id":"b1"}]}}"#
+        );
+
+        let query = query().with_incremental_delivery(true);
+        let result = runner.run_query(query, target).await;
+        let body = result.into_multipart_body().collect::<Vec<_>>().await;
+        let body = String::from_utf8(body.concat()).unwrap();
+        assert!(body.contains(r#"{"data":{"musicians":[{"id":"m1"}]},"hasNext":true}"#));
+        assert!(body.contains(
+            r#"{"incremental":[{"data":{"bands":[{"id":"b1"}]},"path":[],"label":"bands"}],"hasNext":false}"#
+        ));
+    })
+}
+
+#[test]
+fn nested_lists_are_streamed() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 2, orderBy: id) {
+            id
+            bands(orderBy: id) @stream(initialCount: 1, label: \"bands\") { id }
+        }
+    }";
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+        let runner = Arc::new(GraphQlRunner::new(
+            &LOGGER,
+            STORE.clone(),
+            SUBSCRIPTION_MANAGER.clone(),
+            LOAD_MANAGER.clone(),
+            METRICS_REGISTRY.clone(),
+            None,
+        ));
+        let target = QueryTarget::Deployment(deployment.hash.clone(), Default::default());
+        let query = Query::new(q::parse_query(QUERY).unwrap().into_static(), None, false)
+            .with_incremental_delivery(true);
+        let result = runner.clone().run_query(query, target.clone()).await;
+        assert!(result.is_incremental());
+
+        // Only the initial items are resolved for the initial payload
+        let initial = extract_data!(result.first().unwrap().duplicate()).unwrap();
+        assert_eq!(
+            initial,
+            object! { musicians: vec![
+                object! { id: "m1", bands: vec![object! { id: "b1" }] },
+                object! { id: "m2", bands: vec![object! { id: "b1" }] },
+            ]}
+        );
+
+        let body = result.into_multipart_body().collect::<Vec<_>>().await;
+        let body = String::from_utf8(body.concat()).unwrap();
+        assert!(body.contains(
+            r#"{"data":{"musicians":[{"id":"m1","bands":[{"id":"b1"}]},{"id":"m2","bands":[{"id":"b1"}]}]},"hasNext":true}"#
+        ));
+        assert!(body.contains(
+            r#"{"incremental":[{"items":[{"id":"b2"}],"path":["musicians",0,"bands",1],"label":"bands"}],"hasNext":false}"#
+        ));
+
+        // A top-level list with an `initialCount` of 0 sends all its items
+        // after the initial payload
+        const TOP_LEVEL: &str = "
+        query {
+            musicians(first: 3, orderBy: id) @stream(initialCount: 0) { id }
+        }";
+        let query = Query::new(
+            q::parse_query(TOP_LEVEL).unwrap().into_static(),
+            None,
+            false,
+        )
+        .with_incremental_delivery(true);
+        let result = runner.run_query(query, target).await;
+        let body = result.into_multipart_body().collect::<Vec<_>>().await;
+        let body = String::from_utf8(body.concat()).unwrap();
+        assert!(body.contains(r#"{"data":{"musicians":[]},"hasNext":true}"#));
+        assert!(body.contains(
+            r#"{"incremental":[{"items":[{"id":"m1"},{"id":"m2"},{"id":"m3"}],"path":["musicians",0]}],"hasNext":false}"#
+        ));
+    })
+}
+
+#[test]
+fn query_cost_is_checked_against_budget() {
+    // The cost is `(1 + 1 + (3 + 1) * 5) * 10 = 220`
+    const QUERY: &str = "
+    query {
+        musicians(first: 10, orderBy: id) {
+            name
+            bands(first: 5, orderBy: id) { name }
+        }
+    }";
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+        let run = |config: String| {
+            let model = QueryCostModel::from_str(&config).unwrap();
+            let runner = Arc::new(GraphQlRunner::new(
+                &LOGGER,
+                STORE.clone(),
+                SUBSCRIPTION_MANAGER.clone(),
+                LOAD_MANAGER.clone(),
+                METRICS_REGISTRY.clone(),
+                Some(Arc::new(model)),
+            ));
+            let target = QueryTarget::Deployment(deployment.hash.clone(), Default::default());
+            let query = Query::new(q::parse_query(QUERY).unwrap().into_static(), None, false);
+            runner.run_query(query, target)
+        };
+
+        let weights = "
+        scalar_weight = 1
+
+        [[weight]]
+        field = \"Musician.bands\"
+        weight = 3
+        ";
+
+        // The query is exactly at the budget
+        let result = run(format!("max_cost = 220\n{}", weights)).await;
+        assert!(!result.has_errors());
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(json.contains(r#""extensions":{"cost":{"cost":220,"budget":220}}"#));
+
+        // The budget for the deployment takes precedence
+        let result = run(format!(
+            "max_cost = 220\n{}\n[[deployment]]\nhash = \"{}\"\nmax_cost = 219\n",
+            weights, deployment.hash
+        ))
+        .await;
+        match &result.errors()[0] {
+            QueryError::ExecutionError(QueryExecutionError::CostExceeded(220, 219)) => (),
+            e => panic!("did not catch query cost: {:?}", e),
+        }
+    })
+}
@@ -7,8 +7,10 @@ use graph::{
         metrics::stopwatch::StopwatchMetrics,
         store::{
             AttributeNames, BlockNumber, DeploymentLocator, EntityCache, EntityCollection,
-            EntityOperation, EntityQuery, ReadStore, StoreError, SubgraphStore as _, WritableStore,
+            EntityOperation, EntityQuery, ReadStore, RollupBackfillReporter, StoreError,
+            SubgraphStore as _, WritableStore,
         },
+        subgraph::PoICausalityRegion,
     },
     data::{
         store::{
@@ -22,7 +24,10 @@ use graph::{
     schema::InputSchema,
 };
 use graph_store_postgres::{Store as DieselStore, SubgraphStore};
-use test_store::{create_test_subgraph, run_test_sequentially, BLOCKS, LOGGER, METRICS_REGISTRY};
+use test_store::{
+    create_subgraph, create_test_subgraph, run_test_sequentially, BLOCKS, LOGGER, METRICS_REGISTRY,
+    NETWORK_NAME,
+};
 
 const SCHEMA: &str = r#"
 type Data @entity(timeseries: true) {
@@ -51,6 +56,36 @@ type Data @entity(timeseries: true) {
     timestamp: Timestamp!
     max: BigDecimal! @aggregate(fn: "max", arg: "price")
   }
+
+  type Trades @aggregation(intervals: ["hour"], source: "Data") {
+    id: Int8!
+    timestamp: Timestamp!
+    first: BigDecimal! @aggregate(fn: "first", arg: "price")
+    last: BigDecimal! @aggregate(fn: "last", arg: "price")
+    tokens: Int8! @aggregate(fn: "count_distinct", arg: "token")
+    vwap: BigDecimal! @aggregate(fn: "weighted_avg", arg: "price", weight: "amount")
+  }
+
+  type Running @aggregation(intervals: ["hour"], source: "Data", cumulative: true) {
+    id: Int8!
+    timestamp: Timestamp!
+    token: Bytes!
+    total: BigDecimal! @aggregate(fn: "sum", arg: "amount")
+    count: Int8! @aggregate(fn: "count")
+    hourly: BigDecimal! @aggregate(fn: "sum", arg: "amount", cumulative: false)
+  }
+  "#;
+
+/// A deployment that only has the timeseries and that deployments with
+/// `SCHEMA` can be grafted onto
+const BASE_SCHEMA: &str = r#"
+type Data @entity(timeseries: true) {
+    id: Int8!
+    timestamp: Timestamp!
+    token: Bytes!
+    price: BigDecimal!
+    amount: BigDecimal!
+  }
   "#;
 
 fn minutes(n: u32) -> BlockTime {
@@ -86,6 +121,16 @@ pub async fn insert(
         })
         .collect();
 
+    transact(store, deployment, block_ptr_to, block_time, ops).await
+}
+
+async fn transact(
+    store: &Arc<dyn WritableStore>,
+    deployment: &DeploymentLocator,
+    block_ptr_to: BlockPtr,
+    block_time: BlockTime,
+    ops: Vec<EntityOperation>,
+) -> Result<(), StoreError> {
     let mut entity_cache = EntityCache::new(Arc::new(store.clone()));
     entity_cache.append(ops);
     let mods = entity_cache
@@ -198,6 +243,23 @@ fn stats_hour(schema: &InputSchema) -> Vec<Vec<Entity>> {
     vec![vec![], vec![], block2, block3]
 }
 
+fn running_hour(schema: &InputSchema) -> Vec<Entity> {
+    let ts0 = BlockTime::since_epoch(0, 0);
+    let ts1 = BlockTime::since_epoch(3600, 0);
+    // `total` and `count` are running totals for each token while
+    // `hourly` only covers each hour
+    vec![
+        entity! { schema => id: 11i64, timestamp: ts0, token: TOKEN1.clone(),
+        total: bd(12), count: 2i64, hourly: bd(12) },
+        entity! { schema => id: 12i64, timestamp: ts0, token: TOKEN2.clone(),
+        total: bd(21), count: 2i64, hourly: bd(21) },
+        entity! { schema => id: 21i64, timestamp: ts1, token: TOKEN1.clone(),
+        total: bd(42), count: 3i64, hourly: bd(30) },
+        entity! { schema => id: 22i64, timestamp: ts1, token: TOKEN2.clone(),
+        total: bd(24), count: 3i64, hourly: bd(3) },
+    ]
+}
+
 struct TestEnv {
     store: Arc<DieselStore>,
     writable: Arc<dyn WritableStore>,
@@ -301,3 +363,174 @@ fn simple() {
         }
     })
 }
+
+#[test]
+fn first_last_count_distinct_weighted_avg() {
+    run_test(|env| async move {
+        let act = env.all_entities("Trades_hour", BLOCKS[3].number);
+        assert_eq!(2, act.len());
+
+        // Trades_hour aggregation over BLOCKS[0..=1]; the prices are
+        // weighted by the amounts so that vwap = 55 / 33
+        let hour0 = &act[0];
+        let ts0 = Value::from(BlockTime::since_epoch(0, 0));
+        assert_eq!(Some(&ts0), hour0.get("timestamp"));
+        assert_eq!(Some(&bd(1)), hour0.get("first"));
+        assert_eq!(Some(&bd(2)), hour0.get("last"));
+        assert_eq!(Some(&Value::Int8(2)), hour0.get("tokens"));
+        match hour0.get("vwap") {
+            Some(Value::BigDecimal(vwap)) => assert!(
+                vwap.to_string().starts_with("1.666666666"),
+                "vwap for hour 0 is {}",
+                vwap
+            ),
+            v => panic!("unexpected vwap for hour 0: {:?}", v),
+        }
+
+        // Trades_hour aggregation over BLOCKS[2]
+        let hour1 = &act[1];
+        let ts1 = Value::from(BlockTime::since_epoch(3600, 0));
+        assert_eq!(Some(&ts1), hour1.get("timestamp"));
+        assert_eq!(Some(&bd(3)), hour1.get("first"));
+        assert_eq!(Some(&bd(3)), hour1.get("last"));
+        assert_eq!(Some(&Value::Int8(2)), hour1.get("tokens"));
+        assert_eq!(Some(&bd(3)), hour1.get("vwap"));
+    })
+}
+
+#[test]
+fn cumulative_running_totals() {
+    run_test(|env| async move {
+        let exp = running_hour(&env.writable.input_schema());
+        let act = env.all_entities("Running_hour", BLOCKS[3].number);
+        let diff = entity_diff(&exp, &act).unwrap();
+        if !diff.is_empty() {
+            panic!("running totals differ:\n{}", diff);
+        }
+        assert_eq!(exp, act);
+    })
+}
+
+struct NoopReporter;
+
+impl RollupBackfillReporter for NoopReporter {}
+
+/// Transact the test data for BLOCKS[2] and BLOCKS[3], together with the
+/// PoI entities that record the block times
+async fn insert_later_test_data(store: &Arc<dyn WritableStore>, deployment: &DeploymentLocator) {
+    let schema = ReadStore::input_schema(store);
+    let data_type = schema.entity_type("Data").unwrap();
+    let poi_id = PoICausalityRegion::from_network(NETWORK_NAME);
+    let poi_key = schema.poi_type().parse_key(poi_id.as_str()).unwrap();
+    let digest: Bytes = "0x00".parse().unwrap();
+
+    for (i, token1, token2) in [
+        (2, (21i64, 3, 30), (22i64, 3, 3)),
+        (3, (31i64, 4, 4), (32i64, 4, 40)),
+    ] {
+        let ts64 = TIMES[i];
+        let mut ops: Vec<_> = vec![
+            entity! { schema => id: token1.0, timestamp: ts64, token: TOKEN1.clone(), price: bd(token1.1), amount: bd(token1.2) },
+            entity! { schema => id: token2.0, timestamp: ts64, token: TOKEN2.clone(), price: bd(token2.1), amount: bd(token2.2) },
+        ]
+        .into_iter()
+        .map(|data| EntityOperation::Set {
+            key: data_type.key(data.id()),
+            data,
+        })
+        .collect();
+        let poi = entity! { schema => id: poi_id.as_str(), digest: digest.clone(),
+        blockTime: TIMES[i].as_secs_since_epoch() };
+        ops.push(EntityOperation::Set {
+            key: poi_key.clone(),
+            data: poi,
+        });
+        transact(store, deployment, BLOCKS[i].clone(), TIMES[i], ops)
+            .await
+            .unwrap();
+    }
+    store.flush().await.unwrap();
+}
+
+#[test]
+fn rollup_backfill() {
+    #[track_caller]
+    fn check(env: &TestEnv, entity_type: &str, exp: &[Entity]) {
+        let act = env.all_entities(entity_type, BLOCKS[3].number);
+        let diff = entity_diff(exp, &act).unwrap();
+        if !diff.is_empty() {
+            panic!("entities for {} differ:\n{}", entity_type, diff);
+        }
+        assert_eq!(
+            exp,
+            act.as_slice(),
+            "entities for {} are the same",
+            entity_type
+        );
+    }
+
+    run_test_sequentially(|store| async move {
+        let subgraph_store = store.subgraph_store();
+        remove_test_data(subgraph_store.clone());
+
+        let base_hash = DeploymentHash::new("rollupBase").unwrap();
+        let base = create_test_subgraph(&base_hash, BASE_SCHEMA).await;
+        let base_writable = subgraph_store
+            .clone()
+            .writable(LOGGER.clone(), base.id, Arc::new(Vec::new()))
+            .await
+            .expect("we can get a writable store");
+        insert_test_data(base_writable, base).await;
+
+        // Graft onto the base after hour 0 so that the aggregations for
+        // hour 0 are missing and the ones for hour 1 are only rolled up
+        // from the data in the grafted deployment
+        let hash = DeploymentHash::new("rollupGrafted").unwrap();
+        let loc = create_subgraph(&hash, SCHEMA, Some((base_hash, BLOCKS[1].clone())))
+            .await
+            .unwrap();
+        let writable = subgraph_store
+            .clone()
+            .writable(LOGGER.clone(), loc.id, Arc::new(Vec::new()))
+            .await
+            .expect("we can get a writable store");
+        insert_later_test_data(&writable, &loc).await;
+
+        let env = TestEnv {
+            store: store.clone(),
+            writable: writable.clone(),
+            deployment: loc.clone(),
+        };
+        let schema = env.writable.input_schema();
+
+        // Hour 1 was rolled up without hour 0, so its running totals only
+        // cover hour 1
+        let act = env.all_entities("Stats_hour", BLOCKS[3].number);
+        assert_eq!(2, act.len());
+        assert_eq!(Some(&bd(90)), act[0].get("totalValue"));
+        assert_eq!(Some(&bd(9)), act[1].get("totalValue"));
+
+        // Fills hour 0 of each hourly aggregation and recomputes the
+        // running totals for hour 1
+        let filled = subgraph_store
+            .rollup_backfill(&loc, 1, Box::new(NoopReporter))
+            .await
+            .unwrap();
+        assert_eq!(4, filled);
+        check(&env, "Stats_hour", &stats_hour(&schema)[3]);
+        check(&env, "Running_hour", &running_hour(&schema));
+        check(&env, "Stats_day", &[]);
+
+        // Running the backfill again does not fill anything and leaves the
+        // aggregations unchanged
+        let filled = subgraph_store
+            .rollup_backfill(&loc, 1, Box::new(NoopReporter))
+            .await
+            .unwrap();
+        assert_eq!(0, filled);
+        check(&env, "Stats_hour", &stats_hour(&schema)[3]);
+        check(&env, "Running_hour", &running_hour(&schema));
+
+        writable.flush().await.unwrap();
+    })
+}
@@ -1,6 +1,7 @@
 //! Test ChainStore implementation of Store, in particular, how
 //! the chain head pointer gets updated in various situations
 
+use graph::blockchain::polling_block_stream::StreamCheckpoint;
 use graph::blockchain::{BlockHash, BlockPtr};
 use graph::data::store::ethereum::call;
 use graph::data::store::scalar::Bytes;
@@ -16,8 +17,9 @@ use graph::prelude::{BlockNumber, QueryStoreManager, QueryTarget};
 use graph::{cheap_clone::CheapClone, prelude::web3::types::H160};
 use graph::{components::store::BlockStore as _, prelude::DeploymentHash};
 use graph::{components::store::ChainStore as _, prelude::EthereumCallCache as _};
+use graph_store_postgres::layout_for_tests::{CACHE_REVERTED_CALLS, FAKE_NETWORK_SHARED};
+use graph_store_postgres::ChainStore as DieselChainStore;
 use graph_store_postgres::Store as DieselStore;
-use graph_store_postgres::{layout_for_tests::FAKE_NETWORK_SHARED, ChainStore as DieselChainStore};
 
 use test_store::block_store::{
     FakeBlock, FakeBlockList, BLOCK_FIVE, BLOCK_FIVE_AFTER_SKIP, BLOCK_FOUR,
@@ -292,6 +294,91 @@ fn block_hashes_by_number() {
     })
 }
 
+#[test]
+fn finalized_block() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_TWO_NO_PARENT,
+        &*BLOCK_THREE,
+    ];
+    run_test(chain, move |store, _| {
+        let finalized = executor::block_on(store.cheap_clone().finalized_block_ptr())?;
+        assert_eq!(None, finalized);
+
+        executor::block_on(
+            store
+                .cheap_clone()
+                .set_finalized_block(BLOCK_TWO.block_ptr()),
+        )?;
+        let finalized = executor::block_on(store.cheap_clone().finalized_block_ptr())?;
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), finalized);
+
+        // Other blocks with the same number are removed
+        let hashes = store.block_hashes_by_block_number(2).unwrap();
+        assert_eq!(vec![BLOCK_TWO.block_hash()], hashes);
+
+        // The finalized block never goes backwards
+        executor::block_on(
+            store
+                .cheap_clone()
+                .set_finalized_block(BLOCK_ONE.block_ptr()),
+        )?;
+        let finalized = executor::block_on(store.cheap_clone().finalized_block_ptr())?;
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), finalized);
+
+        executor::block_on(
+            store
+                .cheap_clone()
+                .set_finalized_block(BLOCK_THREE.block_ptr()),
+        )?;
+        let finalized = executor::block_on(store.cheap_clone().finalized_block_ptr())?;
+        assert_eq!(Some(BLOCK_THREE.block_ptr()), finalized);
+        Ok(())
+    })
+}
+
+#[test]
+fn stream_checkpoint() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE];
+    run_test(chain, move |store, _| {
+        let deployment = DeploymentHash::new("QmStreamCheckpoint").unwrap();
+        let checkpoint = executor::block_on(store.cheap_clone().stream_checkpoint(&deployment))?;
+        assert_eq!(None, checkpoint);
+
+        let first = StreamCheckpoint {
+            filter: "abc".to_string(),
+            from: 1,
+            to: 100,
+            blocks: vec![10, 100],
+        };
+        executor::block_on(
+            store
+                .cheap_clone()
+                .set_stream_checkpoint(&deployment, first.clone()),
+        )?;
+        let checkpoint = executor::block_on(store.cheap_clone().stream_checkpoint(&deployment))?;
+        assert_eq!(Some(first), checkpoint);
+
+        // A new checkpoint replaces the previous one
+        let second = StreamCheckpoint {
+            filter: "abc".to_string(),
+            from: 101,
+            to: 200,
+            blocks: vec![200],
+        };
+        executor::block_on(
+            store
+                .cheap_clone()
+                .set_stream_checkpoint(&deployment, second.clone()),
+        )?;
+        let checkpoint = executor::block_on(store.cheap_clone().stream_checkpoint(&deployment))?;
+        assert_eq!(Some(second), checkpoint);
+        Ok(())
+    })
+}
+
 #[track_caller]
 fn check_ancestor(
     store: &Arc<DieselChainStore>,
@@ -480,6 +567,243 @@ fn eth_call_cache() {
     })
 }
 
+#[test]
+fn eth_call_cache_reverted() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        let logger = LOGGER.cheap_clone();
+        let address = H160::from_low_u64_be(1);
+        let call = call::Request::new(address, vec![1, 2, 3], 0);
+        let other = call::Request::new(address, vec![4, 5, 6], 0);
+        let reverted = call::Retval::Reverted("execution reverted: paused".to_string());
+        let value = call::Retval::Value(Bytes::from(vec![7, 8, 9]));
+
+        // By default, reverted calls are not cached
+        store.set_call(
+            &logger,
+            call.cheap_clone(),
+            BLOCK_ONE.block_ptr(),
+            reverted.clone(),
+        )?;
+        assert_eq!(None, store.get_call(&call, BLOCK_ONE.block_ptr())?);
+
+        *CACHE_REVERTED_CALLS.lock().unwrap() = true;
+        let res = (|| -> Result<(), Error> {
+            store.set_call(
+                &logger,
+                call.cheap_clone(),
+                BLOCK_TWO.block_ptr(),
+                reverted.clone(),
+            )?;
+            store.set_call(
+                &logger,
+                other.cheap_clone(),
+                BLOCK_TWO.block_ptr(),
+                value.clone(),
+            )?;
+
+            let ret = store.get_call(&call, BLOCK_TWO.block_ptr())?;
+            let (found, missing) = store.get_calls(
+                &[call.cheap_clone(), other.cheap_clone()],
+                BLOCK_TWO.block_ptr(),
+            )?;
+            if store.chain == FAKE_NETWORK_SHARED {
+                // The shared call cache has no place to record reverts
+                assert_eq!(None, ret);
+                assert_eq!(1, found.len());
+                assert_eq!(vec![call.cheap_clone()], missing);
+            } else {
+                assert_eq!(Some(reverted.clone()), ret.map(|resp| resp.retval));
+                let mut retvals: Vec<_> = found.into_iter().map(|resp| resp.retval).collect();
+                retvals.sort_by_key(|retval| matches!(retval, call::Retval::Value(_)));
+                assert_eq!(vec![reverted.clone(), value.clone()], retvals);
+                assert!(missing.is_empty());
+            }
+
+            // Reverted calls are never handed out as calls in a block
+            let in_block = store.get_calls_in_block(BLOCK_TWO.block_ptr())?;
+            assert_eq!(1, in_block.len());
+            Ok(())
+        })();
+        *CACHE_REVERTED_CALLS.lock().unwrap() = false;
+        res?;
+
+        // With the setting off again, reverts that were cached earlier are
+        // ignored
+        assert_eq!(None, store.get_call(&call, BLOCK_TWO.block_ptr())?);
+        let (found, missing) = store.get_calls(&[call.cheap_clone()], BLOCK_TWO.block_ptr())?;
+        assert!(found.is_empty());
+        assert_eq!(vec![call], missing);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn eth_call_cache_eviction() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE];
+
+    // Only private call caches track when calls were accessed
+    run_test_sequentially(|store| async move {
+        block_store::set_chain(chain, NETWORK_NAME).await;
+        let chain_store = store
+            .block_store()
+            .chain_store(NETWORK_NAME)
+            .expect("chain store");
+        let logger = LOGGER.cheap_clone();
+        let block = BLOCK_ONE.block_ptr();
+
+        let address = H160::from_low_u64_be(1);
+        let reqs: Vec<_> = (0..3u8)
+            .map(|i| call::Request::new(address, vec![i], 0))
+            .collect();
+        // Each call is written in its own transaction and therefore gets
+        // a later access time than the ones before it
+        for (i, req) in reqs.iter().enumerate() {
+            chain_store
+                .set_call(
+                    &logger,
+                    req.cheap_clone(),
+                    block.clone(),
+                    call::Retval::Value(Bytes::from(vec![i as u8; 4])),
+                )
+                .unwrap();
+        }
+        let cached =
+            |req: &call::Request| chain_store.get_call(req, block.clone()).unwrap().is_some();
+
+        // Touching the oldest call makes it the most recently used one
+        chain_store
+            .touch_calls_unsampled(&reqs[0..1], &block)
+            .unwrap();
+
+        // Nothing is evicted while the call caches are within budget
+        for (chain, res) in store.block_store().evict_call_caches(u64::MAX) {
+            assert_eq!(0, res.unwrap(), "nothing evicted for {}", chain);
+        }
+        assert!(reqs.iter().all(|req| cached(req)));
+
+        // Calls are evicted in the order in which they were last accessed
+        let (rows, bytes) = chain_store.evict_calls(1).unwrap();
+        assert_eq!(1, rows);
+        assert!(bytes > 0);
+        assert!(cached(&reqs[0]));
+        assert!(!cached(&reqs[1]));
+        assert!(cached(&reqs[2]));
+
+        let (rows, _) = chain_store.evict_calls(1).unwrap();
+        assert_eq!(1, rows);
+        assert!(cached(&reqs[0]));
+        assert!(!cached(&reqs[2]));
+
+        let (rows, _) = chain_store.evict_calls(5).unwrap();
+        assert_eq!(1, rows);
+        assert!(!cached(&reqs[0]));
+        assert_eq!((0, 0), chain_store.evict_calls(5).unwrap());
+
+        // Calls that were cached before calls had an access time are
+        // evicted first, and the evictor builds the missing index
+        for (i, req) in reqs.iter().enumerate() {
+            chain_store
+                .set_call(
+                    &logger,
+                    req.cheap_clone(),
+                    block.clone(),
+                    call::Retval::Value(Bytes::from(vec![i as u8; 4])),
+                )
+                .unwrap();
+        }
+        chain_store.unset_accessed_at(&reqs[2..], &block).unwrap();
+        assert!(!chain_store.has_accessed_at_index().unwrap());
+        for (chain, res) in store.block_store().evict_call_caches(u64::MAX) {
+            assert_eq!(0, res.unwrap(), "nothing evicted for {}", chain);
+        }
+        assert!(chain_store.has_accessed_at_index().unwrap());
+
+        let (rows, _) = chain_store.evict_calls(1).unwrap();
+        assert_eq!(1, rows);
+        assert!(cached(&reqs[0]));
+        assert!(cached(&reqs[1]));
+        assert!(!cached(&reqs[2]));
+        chain_store.evict_calls(5).unwrap();
+    });
+}
+
+#[test]
+fn prune_chain() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO, &*BLOCK_THREE];
+
+    async fn has_block(chain_store: &Arc<DieselChainStore>, block: &FakeBlock) -> bool {
+        !chain_store
+            .cheap_clone()
+            .blocks(vec![block.block_hash()])
+            .await
+            .unwrap()
+            .is_empty()
+    }
+
+    run_test_sequentially(|store| async move {
+        block_store::set_chain(chain, NETWORK_NAME).await;
+        let chain_store = store
+            .block_store()
+            .chain_store(NETWORK_NAME)
+            .expect("chain store");
+        let logger = LOGGER.cheap_clone();
+
+        let address = H160::from_low_u64_be(1);
+        let calls: Vec<_> = [&*BLOCK_ONE, &*BLOCK_TWO, &*BLOCK_THREE]
+            .into_iter()
+            .enumerate()
+            .map(|(i, block)| (call::Request::new(address, vec![i as u8], 0), block))
+            .collect();
+        for (req, block) in &calls {
+            chain_store
+                .set_call(
+                    &logger,
+                    req.cheap_clone(),
+                    block.block_ptr(),
+                    call::Retval::Value(Bytes::from(vec![1u8; 4])),
+                )
+                .unwrap();
+        }
+        let cached = |(req, block): &(call::Request, &FakeBlock)| {
+            chain_store
+                .get_call(req, block.block_ptr())
+                .unwrap()
+                .is_some()
+        };
+
+        // A dry run only counts what would be removed
+        for _ in 0..2 {
+            assert_eq!((2, 2), chain_store.prune(3, true).unwrap());
+        }
+        assert!(has_block(&chain_store, &BLOCK_ONE).await);
+        assert!(has_block(&chain_store, &BLOCK_TWO).await);
+        assert!(calls.iter().all(cached));
+
+        // Blocks and calls before the given block are removed, but the
+        // genesis block is always kept
+        assert_eq!((2, 2), chain_store.prune(3, false).unwrap());
+        assert!(has_block(&chain_store, &GENESIS_BLOCK).await);
+        assert!(!has_block(&chain_store, &BLOCK_ONE).await);
+        assert!(!has_block(&chain_store, &BLOCK_TWO).await);
+        assert!(has_block(&chain_store, &BLOCK_THREE).await);
+        assert!(!cached(&calls[0]));
+        assert!(!cached(&calls[1]));
+        assert!(cached(&calls[2]));
+        assert_eq!((0, 0), chain_store.prune(3, false).unwrap());
+
+        // Chains with shared storage can not be pruned
+        let shared = store
+            .block_store()
+            .chain_store(FAKE_NETWORK_SHARED)
+            .expect("chain store");
+        assert!(shared.prune(3, true).is_err());
+        assert!(shared.prune(3, false).is_err());
+    });
+}
+
 #[test]
 /// Tests only query correctness. No data is involved.
 fn test_transaction_receipts_in_block_function() {
@@ -1,5 +1,5 @@
 use graph::blockchain::block_stream::FirehoseCursor;
-use graph::schema::InputSchema;
+use graph::schema::{EntityType, InputSchema};
 use graph_store_postgres::command_support::OnSync;
 use lazy_static::lazy_static;
 use std::{marker::PhantomData, str::FromStr};
@@ -412,6 +412,72 @@ fn graft() {
     })
 }
 
+#[test]
+fn graft_converts_mutated_entities() {
+    run_test(|store, _| async move {
+        const SUBGRAPH: &str = "grafted_converted";
+
+        // Grafting immutably onto block 2 fails since entity 3 was
+        // updated at block 2 and its old version ends there
+        let subgraph_id = DeploymentHash::new(SUBGRAPH).unwrap();
+        let err = create_grafted_subgraph(
+            &subgraph_id,
+            GRAFT_IMMUTABLE_GQL,
+            TEST_SUBGRAPH_ID.as_str(),
+            BLOCKS[2].clone(),
+        )
+        .await
+        .expect_err("grafting onto block 2 fails");
+        assert!(err.to_string().contains("convert-immutable"));
+
+        // Once we allow converting mutated entities, starting the graft
+        // again copies the versions that are current at block 2
+        let deployment = store.locators(SUBGRAPH)?.pop().unwrap();
+        store.convert_immutable(&deployment)?;
+        store
+            .cheap_clone()
+            .writable(LOGGER.clone(), deployment.id, Arc::new(Vec::new()))
+            .await?
+            .start_subgraph_deployment(&LOGGER)
+            .await?;
+
+        let (entities, ids) = find_entities(store.as_ref(), &deployment);
+        let ids_str = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["3", "1", "2"], ids_str);
+        let shaq = entities.first().unwrap().clone();
+        assert_eq!(Some(&Value::from("teeko@email.com")), shaq.get("email"));
+
+        // The converted entity only exists from the block at which its
+        // current version was written
+        let query = |block| {
+            EntityQuery::new(
+                deployment.hash.clone(),
+                block,
+                EntityCollection::All(vec![(
+                    TEST_SUBGRAPH_SCHEMA.entity_type(USER).unwrap(),
+                    AttributeNames::All,
+                )]),
+            )
+        };
+        let ids = |block| {
+            let mut ids = store
+                .find(query(block))
+                .unwrap()
+                .iter()
+                .map(|entity| entity.id().to_string())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(vec!["1", "2"], ids(1));
+        assert_eq!(vec!["1", "2", "3"], ids(2));
+
+        // Marking a copy that has finished is an error
+        assert!(store.convert_immutable(&deployment).is_err());
+        Ok(())
+    })
+}
+
 fn other_shard(
     store: &DieselSubgraphStore,
     src: &DeploymentLocator,
@@ -546,6 +612,68 @@ fn on_sync() {
     })
 }
 
+// Test that promoting a warm standby activates and resumes it, and pauses
+// the previously active copy. This test will only do something if the test
+// configuration uses at least two shards
+#[test]
+fn promote_standby() {
+    use graph_node::manager::commands::copy::promote;
+
+    run_test(|store, src| async move {
+        if let Some(dst_shard) = other_shard(&store, &src)? {
+            let dst = store.copy_deployment(
+                &src,
+                dst_shard.clone(),
+                NODE_ID.clone(),
+                BLOCKS[1].clone(),
+                OnSync::None,
+            )?;
+
+            // Perform the copy and pause the standby
+            store
+                .cheap_clone()
+                .writable(LOGGER.clone(), dst.id, Arc::new(Vec::new()))
+                .await?
+                .start_subgraph_deployment(&LOGGER)
+                .await?;
+            store.pause_subgraph(&dst)?;
+
+            let promote = |max_lag, force| {
+                promote(
+                    store.cheap_clone(),
+                    src.hash.to_string(),
+                    dst_shard.to_string(),
+                    max_lag,
+                    force,
+                )
+            };
+            let is_paused = |loc: &DeploymentLocator| {
+                store
+                    .assignment_status(loc)
+                    .unwrap()
+                    .map(|(_, paused)| paused)
+            };
+            let active = || store.active_locator(src.hash.as_str()).unwrap().unwrap().id;
+
+            // The standby is behind the active copy, and promoting it
+            // without allowing for that changes nothing
+            assert!(promote(0, false).is_err());
+            assert_eq!(src.id, active());
+            assert_eq!(Some(false), is_paused(&src));
+            assert_eq!(Some(true), is_paused(&dst));
+
+            promote(10, false).unwrap();
+            assert_eq!(dst.id, active());
+            assert_eq!(Some(true), is_paused(&src));
+            assert_eq!(Some(false), is_paused(&dst));
+
+            // The standby is now the active copy
+            assert!(promote(10, true).is_err());
+        }
+        Ok(())
+    })
+}
+
 #[test]
 fn prune() {
     struct Progress;
@@ -663,3 +791,85 @@ fn prune() {
         })
     }
 }
+
+#[test]
+fn prune_expired() {
+    const TTL_GQL: &str = "
+    type Offer @entity @ttl(blocks: 2) { id: ID!, name: String! }
+    type Bid @entity(immutable: true) @ttl(blocks: 2) { id: ID!, name: String! }";
+
+    struct Progress;
+    impl PruneReporter for Progress {}
+
+    fn ids_at(
+        store: &DieselSubgraphStore,
+        deployment: &DeploymentLocator,
+        entity_type: &EntityType,
+        block: BlockNumber,
+    ) -> Vec<String> {
+        let query = EntityQuery::new(
+            deployment.hash.clone(),
+            block,
+            EntityCollection::All(vec![(entity_type.clone(), AttributeNames::All)]),
+        );
+        store
+            .find(query)
+            .unwrap()
+            .into_iter()
+            .map(|entity| entity.id().to_string())
+            .collect()
+    }
+
+    run_test(|store, _| async move {
+        let subgraph_id = DeploymentHash::new("ttl").unwrap();
+        let schema = InputSchema::parse_latest(TTL_GQL, subgraph_id.clone()).unwrap();
+        let offer = schema.entity_type("Offer").unwrap();
+        let bid = schema.entity_type("Bid").unwrap();
+        let set = |entity_type: &EntityType, id: &str, name: &str| EntityOperation::Set {
+            key: entity_type.parse_key(id).unwrap(),
+            data: entity! { schema => id: id, name: name },
+        };
+
+        let deployment = create_test_subgraph(&subgraph_id, TTL_GQL).await;
+        let ops = vec![
+            set(&offer, "o1", "Offer 1"),
+            set(&offer, "o2", "Offer 2"),
+            set(&bid, "b1", "Bid 1"),
+        ];
+        transact_and_wait(&store, &deployment, BLOCKS[0].clone(), ops).await?;
+        let ops = vec![set(&offer, "o1", "Offer 1b")];
+        transact_and_wait(&store, &deployment, BLOCKS[1].clone(), ops).await?;
+        let ops = vec![set(&bid, "b2", "Bid 2")];
+        transact_and_wait(&store, &deployment, BLOCKS[5].clone(), ops).await?;
+        transact_and_wait(&store, &deployment, BLOCKS[6].clone(), vec![]).await?;
+
+        // Queries only see versions written less than 2 blocks before the
+        // block they query
+        assert_eq!(vec!["o1", "o2"], ids_at(&store, &deployment, &offer, 0));
+        assert_eq!(vec!["o1"], ids_at(&store, &deployment, &offer, 2));
+        assert!(ids_at(&store, &deployment, &offer, 6).is_empty());
+        assert_eq!(vec!["b2"], ids_at(&store, &deployment, &bid, 6));
+
+        // Pruning with a reorg threshold of 1 deletes expired versions
+        // even though the deployment keeps all its history. The tables
+        // keep history from block 4 on, and versions written at or before
+        // block 2 expire, unless they are still current for mutable
+        // entities
+        let req = PruneRequest::new(&deployment, BLOCK_NUMBER_MAX, 1, 0, 6)?;
+        store
+            .prune(Box::new(Progress), &deployment, req)
+            .await
+            .expect("pruning works");
+
+        // Without a block constraint, the time-to-live is not applied
+        assert_eq!(
+            vec!["o1", "o2"],
+            ids_at(&store, &deployment, &offer, BLOCK_NUMBER_MAX)
+        );
+        assert_eq!(
+            vec!["b2"],
+            ids_at(&store, &deployment, &bid, BLOCK_NUMBER_MAX)
+        );
+        Ok(())
+    })
+}
@@ -2,14 +2,17 @@
 use diesel::connection::SimpleConnection as _;
 use diesel::pg::PgConnection;
 use graph::components::store::write::{EntityModification, RowGroup};
+use graph::components::store::ExportFormat;
+use graph::csv;
 use graph::data::store::scalar;
 use graph::entity;
 use graph::prelude::{
-    o, slog, tokio, web3::types::H256, DeploymentHash, Entity, EntityCollection, EntityFilter,
-    EntityOrder, EntityQuery, Logger, StopwatchMetrics, Value, ValueType, BLOCK_NUMBER_MAX,
+    o, serde_json, slog, tokio, web3::types::H256, DeploymentHash, Entity, EntityAggregate,
+    EntityCollection, EntityCursor, EntityFilter, EntityOrder, EntityQuery, FulltextOptions,
+    Logger, QueryExecutionError, SpatialArea, StopwatchMetrics, Value, ValueType, BLOCK_NUMBER_MAX,
 };
 use graph::prelude::{BlockNumber, MetricsRegistry};
-use graph::schema::{EntityKey, EntityType, InputSchema};
+use graph::schema::{EntityKey, EntityType, FulltextAlgorithm, FulltextLanguage, InputSchema};
 use graph_store_postgres::layout_for_tests::set_account_like;
 use graph_store_postgres::layout_for_tests::LayoutCache;
 use graph_store_postgres::layout_for_tests::SqlName;
@@ -20,7 +23,7 @@ use std::panic;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use graph::{
     components::store::AttributeNames,
@@ -28,7 +31,7 @@ use graph::{
 };
 use graph_store_postgres::{
     layout_for_tests::make_dummy_site,
-    layout_for_tests::{Layout, Namespace, STRING_PREFIX_SIZE},
+    layout_for_tests::{Catalog, Layout, Namespace, STRING_PREFIX_SIZE},
 };
 
 use test_store::*;
@@ -148,6 +151,12 @@ const THINGS_GQL: &str = r#"
         test: String
     }
 
+    type Place @entity @spatial(latitude: "lat", longitude: "lon") {
+        id: ID!,
+        lat: BigDecimal!,
+        lon: BigDecimal!
+    }
+
     interface BytePet {
         id: Bytes!,
         name: String!
@@ -218,6 +227,7 @@ lazy_static! {
     static ref CAT_TYPE: EntityType = THINGS_SCHEMA.entity_type("Cat").unwrap();
     static ref FERRET_TYPE: EntityType = THINGS_SCHEMA.entity_type("Ferret").unwrap();
     static ref MINK_TYPE: EntityType = THINGS_SCHEMA.entity_type("Mink").unwrap();
+    static ref PLACE_TYPE: EntityType = THINGS_SCHEMA.entity_type("Place").unwrap();
     static ref CHAIR_TYPE: EntityType = THINGS_SCHEMA.entity_type("Chair").unwrap();
     static ref NULLABLE_STRINGS_TYPE: EntityType =
         THINGS_SCHEMA.entity_type("NullableStrings").unwrap();
@@ -481,6 +491,26 @@ fn create_schema(conn: &mut PgConnection) -> Layout {
         .expect("Failed to create relational schema")
 }
 
+/// Like `create_schema`, but partition the tables for mutable entities
+fn create_partitioned_schema(conn: &mut PgConnection) -> Layout {
+    let schema = InputSchema::parse_latest(THINGS_GQL, THINGS_SUBGRAPH_ID.clone()).unwrap();
+    let site = Arc::new(make_dummy_site(
+        THINGS_SUBGRAPH_ID.clone(),
+        NAMESPACE.clone(),
+        NETWORK_NAME.to_string(),
+    ));
+    let query = format!("create schema {}", NAMESPACE.as_str());
+    conn.batch_execute(&query).unwrap();
+
+    let catalog = Catalog::for_creation(conn, site.clone(), BTreeSet::new())
+        .unwrap()
+        .with_partitioned_tables();
+    let layout = Layout::new(site, &schema, catalog).unwrap();
+    conn.batch_execute(&layout.as_ddl().unwrap())
+        .expect("Failed to create partitioned schema");
+    layout
+}
+
 fn scrub(entity: &Entity) -> Entity {
     let mut scrubbed = entity.clone();
     scrubbed.remove_null_fields();
@@ -1137,11 +1167,181 @@ fn check_fulltext_search_syntax_error() {
             user_query().filter(EntityFilter::Fulltext(
                 "userSearch".into(),
                 "Jono 'a".into(),
+                Default::default(),
             )),
         );
     });
 }
 
+#[test]
+fn check_fulltext_options() {
+    run_test(move |mut conn, layout| {
+        fn search(query: &str, options: FulltextOptions) -> EntityQuery {
+            user_query().filter(EntityFilter::Fulltext(
+                "userSearch".into(),
+                query.into(),
+                options,
+            ))
+        }
+
+        let ranked = FulltextOptions {
+            rank_by: Some(FulltextAlgorithm::ProximityRank),
+            language: Some(FulltextLanguage::English),
+            highlight: false,
+        };
+        let checker = QueryChecker::new(&mut conn, layout)
+            .check(vec!["3"], search("Shaq:*", ranked.clone()))
+            .check(vec!["3"], search("Shaq:*", ranked).desc("userSearch"));
+
+        let highlighted = FulltextOptions {
+            highlight: true,
+            ..Default::default()
+        };
+        let mut query = search("Shaq:*", highlighted);
+        query.block = BLOCK_NUMBER_MAX;
+        let users = layout
+            .query::<Entity>(&LOGGER, checker.conn, query)
+            .expect("highlighted fulltext search succeeds")
+            .0;
+        assert_eq!(1, users.len());
+        assert_eq!(
+            Some(&Value::from("<b>Shaqueeena</b>")),
+            users[0].get("name")
+        );
+        assert_eq!(Some(&Value::from("teeko@email.com")), users[0].get("email"));
+    });
+}
+
+#[test]
+fn export_entities() {
+    run_test(move |conn, layout| {
+        let mut out = Vec::new();
+        let count = layout
+            .export_entities(
+                conn,
+                &*USER_TYPE,
+                BLOCK_NUMBER_MAX,
+                ExportFormat::Csv,
+                &mut out,
+            )
+            .expect("CSV export succeeds");
+        assert_eq!(3, count);
+        let mut reader = csv::Reader::from_reader(out.as_slice());
+        let header = reader.headers().unwrap().clone();
+        let column = |name: &str| header.iter().position(|col| col == name).unwrap();
+        assert!(!header.iter().any(|col| col == "userSearch"));
+        let users: Vec<_> = reader.records().map(|user| user.unwrap()).collect();
+        assert_eq!(3, users.len());
+        assert_eq!("Cindini", &users[1][column("name")]);
+        assert_eq!(r#"["beer","wine"]"#, &users[1][column("drinks")]);
+        assert_eq!("", &users[2][column("favorite_color")]);
+
+        let mut ndjson = Vec::new();
+        let count = layout
+            .export_entities(conn, &*USER_TYPE, 0, ExportFormat::Ndjson, &mut ndjson)
+            .expect("NDJSON export succeeds");
+        assert_eq!(3, count);
+        let users: Vec<serde_json::Value> = String::from_utf8(ndjson)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!("Shaqueeena", users[2]["name"]);
+        assert_eq!(serde_json::json!(["coffee", "tea"]), users[2]["drinks"]);
+        assert_eq!(serde_json::Value::Null, users[2]["favorite_color"]);
+    });
+}
+
+#[test]
+fn query_past_deadline_times_out() {
+    run_test(move |conn, layout| {
+        let mut query = user_query();
+        query.block = BLOCK_NUMBER_MAX;
+        query.deadline = Some(Instant::now());
+        let res = layout.query::<Entity>(&LOGGER, conn, query);
+        assert!(matches!(res, Err(QueryExecutionError::Timeout)));
+
+        let mut query = user_query();
+        query.block = BLOCK_NUMBER_MAX;
+        query.deadline = Some(Instant::now() + Duration::from_secs(60));
+        let users = layout
+            .query::<Entity>(&LOGGER, conn, query)
+            .expect("query before the deadline succeeds")
+            .0;
+        assert_eq!(3, users.len());
+    });
+}
+
+#[test]
+fn query_before_table_earliest_block_fails() {
+    run_test(move |conn, layout| {
+        insert_pets(conn, layout);
+
+        // Pretend that the history of cats was pruned to block 5
+        let mut layout = layout.clone();
+        let mut cat = layout.table_for_entity(&CAT_TYPE).unwrap().as_ref().clone();
+        cat.earliest_block = Some(5);
+        layout.tables.insert(CAT_TYPE.clone(), Arc::new(cat));
+
+        let query = |entity_type: &EntityType, block| {
+            EntityQuery::new(
+                THINGS_SUBGRAPH_ID.clone(),
+                block,
+                EntityCollection::All(vec![(entity_type.clone(), AttributeNames::All)]),
+            )
+        };
+
+        match layout.query::<Entity>(&LOGGER, conn, query(&*CAT_TYPE, 4)) {
+            Err(QueryExecutionError::HistoryPruned(entity, 4, 5)) => assert_eq!("Cat", entity),
+            res => panic!(
+                "query before the earliest block was not rejected: {:?}",
+                res
+            ),
+        }
+
+        let (cats, _) = layout
+            .query::<Entity>(&LOGGER, conn, query(&*CAT_TYPE, 5))
+            .expect("query at the earliest block succeeds");
+        assert_eq!(1, cats.len());
+
+        // Other entity types still have their whole history
+        let (dogs, _) = layout
+            .query::<Entity>(&LOGGER, conn, query(&*DOG_TYPE, 0))
+            .expect("query for entity type with full history succeeds");
+        assert_eq!(1, dogs.len());
+    });
+}
+
+#[test]
+fn interface_query_with_limit() {
+    run_test(move |conn, layout| {
+        insert_pets(conn, layout);
+        insert_pet(conn, layout, &*CAT_TYPE, "tom", "Tom", 0);
+        insert_pet(conn, layout, &*DOG_TYPE, "odie", "Odie", 0);
+        insert_pet(conn, layout, &*DOG_TYPE, "snoopy", "Snoopy", 0);
+
+        let mut names = |order: EntityOrder, first: u32, skip: u32| {
+            let query = query(&[&*CAT_TYPE, &*DOG_TYPE])
+                .order(order)
+                .first(first)
+                .skip(skip);
+            let (pets, _) = layout
+                .query::<Entity>(&LOGGER, conn, query)
+                .expect("interface query succeeds");
+            pets.into_iter()
+                .map(|pet| pet.get("name").unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let asc = EntityOrder::Ascending("name".to_string(), ValueType::String);
+        let desc = EntityOrder::Descending("name".to_string(), ValueType::String);
+        assert_eq!(vec!["Odie", "Pluto"], names(asc.clone(), 2, 1));
+        assert_eq!(vec!["Tom", "Snoopy", "Pluto"], names(desc, 3, 0));
+        assert_eq!(vec!["Snoopy", "Tom"], names(asc, 10, 3));
+        assert_eq!(vec!["Garfield", "Odie"], names(EntityOrder::Default, 2, 0));
+    });
+}
+
 #[test]
 fn check_block_finds() {
     run_test(move |mut conn, layout| {
@@ -1204,13 +1404,18 @@ fn check_find() {
         let checker = checker
             .check(
                 vec!["3"],
-                user_query().filter(EntityFilter::Fulltext("userSearch".into(), "Shaq:*".into())),
+                user_query().filter(EntityFilter::Fulltext(
+                    "userSearch".into(),
+                    "Shaq:*".into(),
+                    Default::default(),
+                )),
             )
             .check(
                 vec!["1"],
                 user_query().filter(EntityFilter::Fulltext(
                     "userSearch".into(),
                     "Jono & achangedemail@email.com".into(),
+                    Default::default(),
                 )),
             );
         // Test with a second fulltext search; we had a bug that caused only
@@ -1221,6 +1426,7 @@ fn check_find() {
                 user_query().filter(EntityFilter::Fulltext(
                     "userSearch2".into(),
                     "Shaq:*".into(),
+                    Default::default(),
                 )),
             )
             .check(
@@ -1228,6 +1434,7 @@ fn check_find() {
                 user_query().filter(EntityFilter::Fulltext(
                     "userSearch2".into(),
                     "Jono & achangedemail@email.com".into(),
+                    Default::default(),
                 )),
             );
 
@@ -1264,6 +1471,34 @@ fn check_find() {
                 )),
             );
 
+        // list contains, ignoring case
+        let checker = checker
+            .check(
+                vec!["2"],
+                user_query().filter(EntityFilter::ContainsNoCase(
+                    "drinks".into(),
+                    vec!["BEER"].into(),
+                )),
+            )
+            .check(
+                vec!["3"],
+                user_query().filter(EntityFilter::ContainsNoCase(
+                    "drinks".into(),
+                    vec!["Tea", "coffee"].into(),
+                )),
+            )
+            .check(
+                vec![],
+                user_query().filter(EntityFilter::Contains("drinks".into(), vec!["BEER"].into())),
+            )
+            .check(
+                vec!["3"],
+                user_query().filter(EntityFilter::NotContainsNoCase(
+                    "drinks".into(),
+                    vec!["Beer"].into(),
+                )),
+            );
+
         // string attributes
         let checker = checker
             .check(
@@ -1679,6 +1914,176 @@ fn check_find() {
     })
 }
 
+#[test]
+fn check_find_after_cursor() {
+    fn cursor(id: &str, value: Option<Value>) -> EntityCursor {
+        EntityCursor {
+            id: Value::from(id),
+            value,
+        }
+    }
+
+    run_test(move |mut conn, layout| {
+        QueryChecker::new(&mut conn, layout)
+            .check(vec!["2", "3"], user_query().after(cursor("1", None)))
+            .check(
+                vec!["2", "1"],
+                user_query().desc("id").after(cursor("3", None)),
+            )
+            .check(vec![], user_query().after(cursor("3", None)))
+            // Cindini, Johnton, Shaqueeena
+            .check(
+                vec!["1", "3"],
+                user_query()
+                    .asc("name")
+                    .after(cursor("2", Some(Value::from("Cindini")))),
+            )
+            .check(
+                vec!["2"],
+                user_query()
+                    .desc("name")
+                    .after(cursor("1", Some(Value::from("Johnton")))),
+            )
+            // Postgres sorts nulls last for `asc`
+            .check(
+                vec!["1", "3"],
+                user_query()
+                    .asc("favorite_color")
+                    .after(cursor("2", Some(Value::from("red")))),
+            )
+            .check(
+                vec![],
+                user_query()
+                    .asc("favorite_color")
+                    .after(cursor("3", Some(Value::Null))),
+            )
+            // and first for `desc`
+            .check(
+                vec!["1", "2"],
+                user_query()
+                    .desc("favorite_color")
+                    .after(cursor("3", Some(Value::Null))),
+            )
+            .check(
+                vec!["2"],
+                user_query()
+                    .desc("favorite_color")
+                    .after(cursor("1", Some(Value::from("yellow")))),
+            );
+    })
+}
+
+#[test]
+fn check_aggregate() {
+    use EntityAggregate::*;
+
+    fn decimal(value: i32) -> Value {
+        Value::BigDecimal(scalar::BigDecimal::from(value))
+    }
+
+    run_test(move |conn, layout| {
+        let aggregates = vec![
+            Count,
+            Sum("age".to_string()),
+            Avg("age".to_string()),
+            Min("visits".to_string()),
+            Max("visits".to_string()),
+        ];
+        let (values, _) = layout
+            .aggregate(conn, user_query(), &aggregates)
+            .expect("aggregating users works");
+        assert_eq!(
+            vec![
+                Value::Int8(3),
+                decimal(138),
+                decimal(46),
+                decimal(22),
+                decimal(60)
+            ],
+            values
+        );
+
+        let query = user_query().filter(EntityFilter::Equal("coffee".into(), false.into()));
+        let (values, _) = layout
+            .aggregate(conn, query, &aggregates[0..2])
+            .expect("aggregating filtered users works");
+        assert_eq!(vec![Value::Int8(2), decimal(95)], values);
+
+        // Aggregates over no entities are null, except for the count
+        let query = user_query().filter(EntityFilter::Equal("name".into(), "Nobody".into()));
+        let (values, _) = layout
+            .aggregate(conn, query, &aggregates[0..2])
+            .expect("aggregating no users works");
+        assert_eq!(vec![Value::Int8(0), Value::Null], values);
+
+        layout
+            .aggregate(conn, user_query(), &[Sum("name".to_string())])
+            .expect_err("names can not be summed");
+    })
+}
+
+#[test]
+fn check_spatial_filters() {
+    fn place(layout: &Layout, id: &str, lat: &str, lon: &str) -> Entity {
+        entity! { layout.input_schema =>
+            id: id,
+            lat: BigDecimal::from_str(lat).unwrap(),
+            lon: BigDecimal::from_str(lon).unwrap(),
+        }
+    }
+
+    fn within(area: SpatialArea) -> EntityQuery {
+        query(&[&*PLACE_TYPE])
+            .filter(EntityFilter::Spatial("lat".into(), "lon".into(), area))
+            .asc("id")
+    }
+
+    fn area_box(min: (&str, &str), max: (&str, &str)) -> SpatialArea {
+        SpatialArea::Box {
+            min_latitude: BigDecimal::from_str(min.0).unwrap(),
+            min_longitude: BigDecimal::from_str(min.1).unwrap(),
+            max_latitude: BigDecimal::from_str(max.0).unwrap(),
+            max_longitude: BigDecimal::from_str(max.1).unwrap(),
+        }
+    }
+
+    fn radius(lat: &str, lon: &str, meters: i32) -> SpatialArea {
+        SpatialArea::Radius {
+            latitude: BigDecimal::from_str(lat).unwrap(),
+            longitude: BigDecimal::from_str(lon).unwrap(),
+            meters: BigDecimal::from(meters),
+        }
+    }
+
+    run_test(|conn, layout| {
+        let places = vec![
+            place(layout, "berlin", "52.52", "13.405"),
+            place(layout, "fiji", "-17.7134", "178.065"),
+            place(layout, "paris", "48.8566", "2.3522"),
+            place(layout, "samoa", "-13.759", "-172.1046"),
+        ];
+        insert_entity(conn, layout, &*PLACE_TYPE, places);
+
+        QueryChecker::new(conn, layout)
+            .check(
+                vec!["berlin", "paris"],
+                within(area_box(("45", "0"), ("55", "15"))),
+            )
+            // The box crosses the antimeridian
+            .check(
+                vec!["fiji", "samoa"],
+                within(area_box(("-20", "170"), ("-10", "-170"))),
+            )
+            .check(vec![], within(area_box(("-20", "-170"), ("-10", "170"))))
+            // Berlin and Paris are about 880km apart
+            .check(vec!["berlin"], within(radius("52.52", "13.405", 500_000)))
+            .check(
+                vec!["berlin", "paris"],
+                within(radius("52.52", "13.405", 900_000)),
+            );
+    })
+}
+
 // We call our test strings aN so that
 //   aN = "a" * (STRING_PREFIX_SIZE - 2 + N)
 // chosen so that they straddle the boundary between strings that fit into
@@ -1861,3 +2266,160 @@ fn check_filters() {
             .check(vec![], filter_block_gte(BLOCK_NUMBER_MAX));
     });
 }
+
+#[test]
+fn create_and_drop_partitions() {
+    // Partitions cover 1M blocks since `GRAPH_STORE_PARTITION_BLOCKS` is
+    // not set
+    const M: BlockNumber = 1_000_000;
+
+    fn partitions(conn: &mut PgConnection) -> Vec<String> {
+        use diesel::RunQueryDsl;
+
+        #[derive(diesel::QueryableByName)]
+        struct Partition {
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            name: String,
+        }
+
+        diesel::sql_query(format!(
+            "select c.relname::text as name
+               from pg_inherits i
+               join pg_class c on c.oid = i.inhrelid
+               join pg_class p on p.oid = i.inhparent
+               join pg_namespace n on n.oid = p.relnamespace
+              where n.nspname = '{}' and p.relname = 'cat'
+              order by c.relname",
+            NAMESPACE.as_str()
+        ))
+        .load::<Partition>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|p| p.name)
+        .collect()
+    }
+
+    run_test_with_conn(|conn| {
+        remove_schema(conn);
+        let layout = create_partitioned_schema(conn);
+        let cat = layout.table(&SqlName::from("cat")).unwrap();
+        assert_eq!(vec!["cat$default"], partitions(conn));
+
+        // Partitions are created up to the last block, and creating them
+        // again for the same blocks does nothing
+        layout.create_partitions(conn, 0, M + 5).unwrap();
+        layout.create_partitions(conn, 0, M + 5).unwrap();
+        assert_eq!(
+            vec!["cat$default", "cat$p0", "cat$p1000000"],
+            partitions(conn)
+        );
+        layout.create_partitions(conn, 0, 2 * M).unwrap();
+        assert_eq!(
+            vec!["cat$default", "cat$p0", "cat$p1000000", "cat$p2000000"],
+            partitions(conn)
+        );
+
+        // Both cats start out in `cat$p0`; updating them closes those
+        // versions and puts the current ones into `cat$p1000000`
+        insert_pet(conn, &layout, &*CAT_TYPE, "garfield", "Garfield", 0);
+        insert_pet(conn, &layout, &*CAT_TYPE, "pluto", "Pluto", 1);
+        let garfield = entity! { layout.input_schema => id: "garfield", name: "Garfield" };
+        update_entity_at(conn, &layout, &*CAT_TYPE, vec![garfield], M + 2);
+        let pluto = entity! { layout.input_schema => id: "pluto", name: "Pluto" };
+        update_entity_at(conn, &layout, &*CAT_TYPE, vec![pluto], M + 3);
+
+        // Nothing can be dropped while versions in `cat$p0` are needed
+        let dropped = layout.drop_pruned_partitions(conn, cat, M + 1).unwrap();
+        assert!(dropped.is_empty());
+        assert_eq!(4, partitions(conn).len());
+
+        // Once all versions in `cat$p0` are closed, it gets dropped, but
+        // `cat$p1000000` still has current versions
+        let dropped = layout.drop_pruned_partitions(conn, cat, 2 * M).unwrap();
+        assert_eq!(vec!["cat$p0"], dropped);
+        assert_eq!(
+            vec!["cat$default", "cat$p1000000", "cat$p2000000"],
+            partitions(conn)
+        );
+        assert!(layout
+            .find(
+                conn,
+                &CAT_TYPE.parse_key("garfield").unwrap(),
+                BLOCK_NUMBER_MAX
+            )
+            .unwrap()
+            .is_some());
+    });
+}
+
+#[test]
+fn archive_keeps_history_queryable() {
+    use diesel::RunQueryDsl;
+    use graph::prelude::CancelGuard;
+
+    #[derive(diesel::QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+
+    fn count(conn: &mut PgConnection, table: &str) -> i64 {
+        diesel::sql_query(format!(
+            "select count(*) as count from only {}.\"{}\"",
+            NAMESPACE.as_str(),
+            table
+        ))
+        .get_result::<Count>(conn)
+        .unwrap()
+        .count
+    }
+
+    fn name_at(conn: &mut PgConnection, layout: &Layout, block: BlockNumber) -> Option<Value> {
+        layout
+            .find(conn, &CAT_TYPE.parse_key("garfield").unwrap(), block)
+            .unwrap()
+            .and_then(|cat| cat.get("name").cloned())
+    }
+
+    run_test(|conn, layout| {
+        insert_pets(conn, layout);
+        for (name, block) in [("Garfield 2", 3), ("Garfield 3", 6)] {
+            let garfield = entity! { layout.input_schema => id: "garfield", name: name };
+            update_entity_at(conn, layout, &*CAT_TYPE, vec![garfield], block);
+        }
+        let cancel = CancelGuard::new();
+
+        // Only the version that ended at block 3 is old enough
+        let counts = layout
+            .archive(&LOGGER, conn, 5, false, &cancel.handle())
+            .unwrap();
+        let archived = |counts: &[(String, usize)], table: &str| {
+            counts
+                .iter()
+                .find(|(name, _)| name == table)
+                .map(|(_, count)| *count)
+        };
+        assert_eq!(Some(1), archived(&counts, "cat"));
+        assert_eq!(Some(0), archived(&counts, "dog"));
+        assert_eq!(2, count(conn, "cat"));
+        assert_eq!(1, count(conn, "cat$archive"));
+
+        // Archiving again does not move anything
+        let counts = layout
+            .archive(&LOGGER, conn, 5, false, &cancel.handle())
+            .unwrap();
+        assert_eq!(Some(0), archived(&counts, "cat"));
+
+        // Queries see archived versions
+        let name = |name: &str| Some(Value::from(name));
+        assert_eq!(name("Garfield"), name_at(conn, layout, 1));
+        assert_eq!(name("Garfield 2"), name_at(conn, layout, 4));
+        assert_eq!(name("Garfield 3"), name_at(conn, layout, BLOCK_NUMBER_MAX));
+
+        // Reverting reopens the archived version
+        layout.revert_block(conn, 2).unwrap();
+        assert_eq!(name("Garfield"), name_at(conn, layout, BLOCK_NUMBER_MAX));
+        assert_eq!(0, count(conn, "cat"));
+        assert_eq!(1, count(conn, "cat$archive"));
+    });
+}
@@ -17,8 +17,8 @@ use graph::data::store::scalar::{BigDecimal, BigInt};
 use graph::data::store::IdList;
 use graph::prelude::{
     o, slog, web3::types::H256, AttributeNames, ChildMultiplicity, DeploymentHash, Entity,
-    EntityCollection, EntityLink, EntityWindow, Logger, ParentLink, StopwatchMetrics,
-    WindowAttribute, BLOCK_NUMBER_MAX,
+    EntityCollection, EntityCursor, EntityLink, EntityWindow, Logger, ParentLink, StopwatchMetrics,
+    Value, WindowAttribute, BLOCK_NUMBER_MAX,
 };
 use graph_store_postgres::{
     layout_for_tests::make_dummy_site,
@@ -545,3 +545,73 @@ fn query() {
         assert_eq!(vec![ROOT, ROOT], things);
     });
 }
+
+#[test]
+fn query_windows_after_cursor() {
+    fn fetch_after(
+        conn: &mut PgConnection,
+        layout: &Layout,
+        coll: EntityCollection,
+        after: &str,
+    ) -> Vec<String> {
+        let id = DeploymentHash::new("QmXW3qvxV7zXnwRntpj7yoK8HZVtaraZ67uMqaLRvXdxha").unwrap();
+        let cursor = EntityCursor {
+            id: Value::Bytes(scalar::Bytes::from_str(after).unwrap()),
+            value: None,
+        };
+        let query = EntityQuery::new(id, BLOCK_NUMBER_MAX, coll)
+            .first(10)
+            .after(cursor);
+        layout
+            .query::<Entity>(&LOGGER, conn, query)
+            .map(|(entities, _)| entities)
+            .expect("the query succeeds")
+            .into_iter()
+            .map(|e| e.id().to_string())
+            .collect::<Vec<_>>()
+    }
+
+    run_test(|mut conn, layout| {
+        make_thing_tree(&mut conn, layout);
+
+        // EntityCollection::Window, type B, many
+        //   things(where: { parent: [ROOT] }) { id }
+        let coll = EntityCollection::Window(vec![EntityWindow {
+            child_type: THING_TYPE.clone(),
+            ids: THING_TYPE.parse_ids(vec![ROOT]).unwrap(),
+            link: EntityLink::Direct(
+                WindowAttribute::Scalar("parent".to_string()),
+                ChildMultiplicity::Many,
+            ),
+            column_names: AttributeNames::All,
+        }]);
+        let things = fetch_after(&mut conn, layout, coll, CHILD1);
+        assert_eq!(vec![CHILD2], things);
+
+        // EntityCollection::Window, type C
+        //   things { children { id } }
+        let coll = EntityCollection::Window(vec![EntityWindow {
+            child_type: THING_TYPE.clone(),
+            ids: THING_TYPE.parse_ids(vec![ROOT]).unwrap(),
+            link: EntityLink::Parent(
+                THING_TYPE.clone(),
+                ParentLink::List(vec![THING_TYPE.parse_ids(vec![CHILD1, CHILD2]).unwrap()]),
+            ),
+            column_names: AttributeNames::All,
+        }]);
+        let things = fetch_after(&mut conn, layout, coll, CHILD1);
+        assert_eq!(vec![CHILD2], things);
+
+        let coll = EntityCollection::Window(vec![EntityWindow {
+            child_type: THING_TYPE.clone(),
+            ids: THING_TYPE.parse_ids(vec![ROOT]).unwrap(),
+            link: EntityLink::Direct(
+                WindowAttribute::Scalar("parent".to_string()),
+                ChildMultiplicity::Many,
+            ),
+            column_names: AttributeNames::All,
+        }]);
+        let things = fetch_after(&mut conn, layout, coll, CHILD2);
+        assert!(things.is_empty());
+    });
+}
@@ -384,6 +384,43 @@ fn get_entity_3() {
     })
 }
 
+#[test]
+fn store_queries_are_recorded_per_deployment() {
+    run_test(|store, _, deployment| async move {
+        let rows = METRICS_REGISTRY
+            .global_counter_vec(
+                "store_query_rows",
+                "Number of rows returned by the queries generated for a deployment",
+                &["deployment", "shape"],
+            )
+            .unwrap();
+        let times = METRICS_REGISTRY
+            .global_histogram_vec(
+                "store_query_execution_time",
+                "Execution time of the queries generated for a deployment in seconds",
+                &["deployment", "shape"],
+            )
+            .unwrap();
+        let labels = [deployment.hash.as_str(), "filter"];
+        let rows_before = rows.with_label_values(&labels).get();
+        let count_before = times.with_label_values(&labels).get_sample_count();
+
+        let users = store
+            .subgraph_store()
+            .find(user_query())
+            .expect("store.find failed to execute query");
+
+        assert_eq!(
+            rows_before + users.len() as f64,
+            rows.with_label_values(&labels).get()
+        );
+        assert_eq!(
+            count_before + 1,
+            times.with_label_values(&labels).get_sample_count()
+        );
+    })
+}
+
 #[test]
 fn insert_entity() {
     run_test(|store, writable, deployment| async move {
@@ -900,10 +937,13 @@ fn find() {
     });
 }
 
+/// The change reverting a block causes; it does not list the ids of the
+/// entities that were reverted
 fn make_entity_change(entity_type: &EntityType) -> EntityChange {
     EntityChange::Data {
         subgraph_id: TEST_SUBGRAPH_ID.clone(),
         entity_type: entity_type.to_string(),
+        ids: None,
     }
 }
 
@@ -1130,6 +1170,7 @@ fn mock_data_source() -> graph_chain_ethereum::DataSource {
             abis: vec![],
             event_handlers: vec![],
             call_handlers: vec![],
+            transfer_handlers: vec![],
             block_handlers: vec![],
             link: Link {
                 link: "link".to_owned(),
@@ -1240,6 +1281,7 @@ fn revert_block_with_dynamic_data_source_operations() {
                 vec![EntityChange::Data {
                     subgraph_id: DeploymentHash::new("testsubgraph").unwrap(),
                     entity_type: USER_TYPE.to_string(),
+                    ids: None,
                 }]
                 .into_iter(),
             ),
@@ -1332,27 +1374,18 @@ fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
         .unwrap();
 
         // We're expecting two events to be written to the subscription stream
+        let ids = || vec!["1".to_string(), "2".to_string()];
         let expected = vec![
-            StoreEvent::new(vec![
-                EntityChange::Data {
-                    subgraph_id: subgraph_id.clone(),
-                    entity_type: USER_TYPE.to_string(),
-                },
-                EntityChange::Data {
-                    subgraph_id: subgraph_id.clone(),
-                    entity_type: USER_TYPE.to_string(),
-                },
-            ]),
-            StoreEvent::new(vec![
-                EntityChange::Data {
-                    subgraph_id: subgraph_id.clone(),
-                    entity_type: USER_TYPE.to_string(),
-                },
-                EntityChange::Data {
-                    subgraph_id: subgraph_id.clone(),
-                    entity_type: USER_TYPE.to_string(),
-                },
-            ]),
+            StoreEvent::new(vec![EntityChange::for_ids(
+                subgraph_id.clone(),
+                USER_TYPE.to_string(),
+                ids(),
+            )]),
+            StoreEvent::new(vec![EntityChange::for_ids(
+                subgraph_id.clone(),
+                USER_TYPE.to_string(),
+                ids(),
+            )]),
         ];
 
         check_events(subscription, expected).await
@@ -1397,7 +1430,11 @@ fn throttle_subscription_delivers() {
         .await
         .unwrap();
 
-        let expected = StoreEvent::new(vec![make_entity_change(&*USER_TYPE)]);
+        let expected = StoreEvent::new(vec![EntityChange::for_ids(
+            TEST_SUBGRAPH_ID.clone(),
+            USER_TYPE.to_string(),
+            vec!["4".to_string()],
+        )]);
 
         check_events(subscription, vec![expected]).await
     })
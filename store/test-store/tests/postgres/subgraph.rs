@@ -2,7 +2,7 @@ use graph::futures03;
 use graph::{
     components::{
         server::index_node::VersionInfo,
-        store::{DeploymentId, DeploymentLocator, StatusStore},
+        store::{DeploymentId, DeploymentLocator, DeploymentPriority, StatusStore},
     },
     data::query::QueryTarget,
     data::subgraph::{schema::SubgraphHealth, SubgraphFeature},
@@ -10,7 +10,6 @@ use graph::{
         schema::{DeploymentCreate, SubgraphError},
         DeploymentFeatures,
     },
-    prelude::BlockPtr,
     prelude::EntityChange,
     prelude::EntityChangeOperation,
     prelude::QueryStoreManager,
@@ -19,6 +18,7 @@ use graph::{
     prelude::SubgraphName,
     prelude::SubgraphVersionSwitchingMode,
     prelude::UnfailOutcome,
+    prelude::{BlockNumber, BlockPtr},
     prelude::{CheapClone, DeploymentHash, NodeId, SubgraphStore as _},
     schema::InputSchema,
     semver::Version,
@@ -140,6 +140,69 @@ fn reassign_subgraph() {
     })
 }
 
+#[test]
+fn deployment_priority() {
+    const NAME: &str = "deploymentPriority";
+
+    async fn setup() -> DeploymentLocator {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        create_test_subgraph(&id, SUBGRAPH_GQL).await
+    }
+
+    fn status_priority(store: &Store) -> Option<DeploymentPriority> {
+        use graph::data::subgraph::status;
+
+        store
+            .status(status::Filter::Deployments(vec![NAME.to_string()]))
+            .unwrap()
+            .pop()
+            .and_then(|info| info.priority)
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup().await;
+        let subgraph_store = store.subgraph_store();
+
+        // Deployments start with normal priority
+        assert_eq!(
+            DeploymentPriority::Normal,
+            subgraph_store.priority(&deployment).unwrap()
+        );
+        assert_eq!(Some(DeploymentPriority::Normal), status_priority(&store));
+
+        subgraph_store
+            .set_priority(&deployment, DeploymentPriority::High)
+            .unwrap();
+        assert_eq!(
+            DeploymentPriority::High,
+            subgraph_store.priority(&deployment).unwrap()
+        );
+        assert_eq!(Some(DeploymentPriority::High), status_priority(&store));
+
+        // Moving the deployment to another node keeps its priority
+        let node = NodeId::new("left").unwrap();
+        subgraph_store
+            .reassign_subgraph(&deployment, &node)
+            .unwrap();
+        assert_eq!(
+            DeploymentPriority::High,
+            subgraph_store.priority(&deployment).unwrap()
+        );
+
+        // Unassigned deployments have normal priority and can't be changed
+        subgraph_store.unassign_subgraph(&deployment).unwrap();
+        assert_eq!(
+            DeploymentPriority::Normal,
+            subgraph_store.priority(&deployment).unwrap()
+        );
+        assert_eq!(None, status_priority(&store));
+        assert!(subgraph_store
+            .set_priority(&deployment, DeploymentPriority::Low)
+            .is_err());
+    })
+}
+
 #[test]
 fn create_subgraph() {
     const SUBGRAPH_NAME: &str = "create/subgraph";
@@ -572,6 +635,127 @@ fn subgraph_features() {
     })
 }
 
+#[test]
+fn blocks_needed_for_chain_pruning() {
+    use graph_node::manager::commands::chain::{needed_blocks, prune_before};
+    use test_store::block_store::{BLOCK_FIVE, BLOCK_FOUR, BLOCK_THREE, BLOCK_TWO};
+
+    /// Create a deployment without starting it and record `handler_kinds`
+    /// as its features if they are given
+    fn create(
+        name: &str,
+        start_block: Option<BlockPtr>,
+        handler_kinds: Option<&[&str]>,
+    ) -> DeploymentLocator {
+        let id = DeploymentHash::new(name).unwrap();
+        let schema = InputSchema::parse_latest(SUBGRAPH_GQL, id.clone()).unwrap();
+        let manifest = SubgraphManifest::<graph_chain_ethereum::Chain> {
+            id: id.clone(),
+            spec_version: Version::new(1, 0, 0),
+            features: Default::default(),
+            description: None,
+            repository: None,
+            schema: schema.clone(),
+            data_sources: vec![],
+            graft: None,
+            templates: vec![],
+            chain: PhantomData,
+            indexer_hints: None,
+        };
+        let deployment = DeploymentCreate::new(String::new(), &manifest, start_block);
+        let loc = SUBGRAPH_STORE
+            .create_deployment_replace(
+                SubgraphName::new(name).unwrap(),
+                &schema,
+                deployment,
+                NODE_ID.clone(),
+                NETWORK_NAME.to_string(),
+                SubgraphVersionSwitchingMode::Instant,
+            )
+            .unwrap();
+        if let Some(handler_kinds) = handler_kinds {
+            SUBGRAPH_STORE
+                .create_subgraph_features(DeploymentFeatures {
+                    id: id.to_string(),
+                    spec_version: "1.0.0".to_string(),
+                    api_version: None,
+                    features: vec![],
+                    data_source_kinds: vec!["ethereum/contract".to_string()],
+                    network: NETWORK_NAME.to_string(),
+                    handler_kinds: handler_kinds.iter().map(|kind| kind.to_string()).collect(),
+                    has_declared_calls: false,
+                    has_bytes_as_ids: false,
+                    has_aggregations: false,
+                    immutable_entities: vec![],
+                })
+                .unwrap();
+        }
+        loc
+    }
+
+    async fn start(loc: &DeploymentLocator, block: BlockPtr) {
+        SUBGRAPH_STORE
+            .cheap_clone()
+            .writable(LOGGER.clone(), loc.id, Arc::new(Vec::new()))
+            .await
+            .unwrap()
+            .start_subgraph_deployment(&LOGGER)
+            .await
+            .unwrap();
+        transact_and_wait(&SUBGRAPH_STORE, loc, block, vec![])
+            .await
+            .unwrap();
+    }
+
+    async fn needed(loc: &DeploymentLocator) -> (bool, BlockNumber) {
+        let site = primary_connection()
+            .locate_site(loc.clone())
+            .unwrap()
+            .unwrap();
+        needed_blocks(&SUBGRAPH_STORE, Arc::new(site))
+            .await
+            .unwrap()
+    }
+
+    run_test_sequentially(|_store| async move {
+        remove_subgraphs();
+
+        // A deployment with block handlers needs every block it has data for
+        let every_block = create(
+            "everyBlock",
+            Some(BLOCK_TWO.block_ptr()),
+            Some(&["event", "block"]),
+        );
+        start(&every_block, BLOCK_FIVE.block_ptr()).await;
+        assert_eq!((true, 2), needed(&every_block).await);
+
+        // A deployment with only event handlers needs its latest block
+        let events = create("eventsOnly", Some(BLOCK_TWO.block_ptr()), Some(&["event"]));
+        start(&events, BLOCK_FOUR.block_ptr()).await;
+        assert_eq!((false, 4), needed(&events).await);
+
+        // Without recorded features, a deployment might need every block
+        let unknown = create("unknownFeatures", Some(BLOCK_THREE.block_ptr()), None);
+        start(&unknown, BLOCK_FIVE.block_ptr()).await;
+        assert_eq!((true, 3), needed(&unknown).await);
+
+        // A deployment that has not started needs its start block, and
+        // every block if it does not have one
+        let unstarted = create("unstarted", Some(BLOCK_THREE.block_ptr()), Some(&["event"]));
+        assert_eq!((false, 3), needed(&unstarted).await);
+        let no_start = create("noStartBlock", None, Some(&["event"]));
+        assert_eq!((false, 0), needed(&no_start).await);
+
+        // `keep` blocks before the oldest needed block are kept, and the
+        // genesis block is never removed
+        assert_eq!(Some(7), prune_before(10, [], 3));
+        assert_eq!(Some(4), prune_before(10, [8, 6], 2));
+        assert_eq!(Some(2), prune_before(10, [4, 12], 2));
+        assert_eq!(None, prune_before(10, [3, 8], 2));
+        assert_eq!(None, prune_before(10, [0], 0));
+    })
+}
+
 #[test]
 fn subgraph_error() {
     test_store::run_test_sequentially(|store| async move {
@@ -756,6 +940,18 @@ fn fail_unfail_deterministic_error() {
         create_test_subgraph(&id, SUBGRAPH_GQL).await
     }
 
+    fn last_healthy_block(store: &Store) -> Option<BlockPtr> {
+        use graph::data::subgraph::status;
+
+        store
+            .status(status::Filter::Deployments(vec![NAME.to_string()]))
+            .unwrap()
+            .pop()
+            .and_then(|info| info.chains.into_iter().next())
+            .and_then(|chain| chain.last_healthy_block)
+            .map(|block| block.to_ptr())
+    }
+
     run_test_sequentially(|store| async move {
         let deployment = setup().await;
 
@@ -817,8 +1013,14 @@ fn fail_unfail_deterministic_error() {
             .await
             .expect("can get writable");
 
-        // Fail the subgraph with a deterministic error.
+        // Fail the subgraph with a deterministic error, and remember the
+        // block before the failed block as the last healthy one
         writable.fail_subgraph(error).await.unwrap();
+        writable
+            .set_last_healthy_block(BLOCKS[0].clone())
+            .await
+            .unwrap();
+        assert_eq!(Some(BLOCKS[0].clone()), last_healthy_block(&store));
 
         // Now we have a fatal error because the subgraph failed.
         let state = query_store.deployment_state().await.unwrap();
@@ -842,6 +1044,8 @@ fn fail_unfail_deterministic_error() {
         assert_eq!(NAME, vi.deployment_id.as_str());
         assert_eq!(false, vi.failed);
         assert_eq!(Some(0), vi.latest_ethereum_block_number);
+        // The last healthy block only matters while the subgraph is failed
+        assert_eq!(None, last_healthy_block(&store));
 
         test_store::remove_subgraphs();
     })
@@ -1197,3 +1401,60 @@ fn fail_unfail_non_deterministic_error_noop() {
         test_store::remove_subgraphs();
     })
 }
+
+#[test]
+fn aux_lookup_pins_versions() {
+    const DATASET: &str = "tokens";
+
+    fn load(value: &str) -> i32 {
+        primary_connection()
+            .load_aux_dataset(DATASET, &[("dai".to_string(), value.to_string())])
+            .unwrap()
+    }
+
+    run_test_sequentially(|store| async move {
+        test_store::remove_aux_dataset(DATASET);
+        let early = DeploymentHash::new("auxEarly").unwrap();
+        let late = DeploymentHash::new("auxLate").unwrap();
+        let never = DeploymentHash::new("auxNever").unwrap();
+
+        // Before the dataset is loaded, lookups find nothing, and keep
+        // finding nothing for that deployment
+        let lookup = store.subgraph_store().aux_lookup();
+        assert_eq!(None, lookup.lookup(&never, DATASET, "dai").unwrap());
+
+        assert_eq!(1, load("Dai Stablecoin"));
+        let value = lookup.lookup(&early, DATASET, "dai").unwrap();
+        assert_eq!(Some("Dai Stablecoin".to_string()), value);
+        assert_eq!(None, lookup.lookup(&early, DATASET, "usdc").unwrap());
+
+        // Loading a new version does not change what deployments that
+        // already use the dataset see, even with a fresh lookup
+        assert_eq!(2, load("Dai"));
+        let lookup = store.subgraph_store().aux_lookup();
+        let value = lookup.lookup(&early, DATASET, "dai").unwrap();
+        assert_eq!(Some("Dai Stablecoin".to_string()), value);
+        assert_eq!(None, lookup.lookup(&never, DATASET, "dai").unwrap());
+        let value = lookup.lookup(&late, DATASET, "dai").unwrap();
+        assert_eq!(Some("Dai".to_string()), value);
+
+        // Pinned versions can not be removed
+        let mut primary = primary_connection();
+        assert!(primary.remove_aux_dataset(DATASET, 1).is_err());
+        let datasets = primary.aux_datasets().unwrap();
+        let versions: Vec<_> = datasets
+            .iter()
+            .filter(|ds| ds.name == DATASET)
+            .map(|ds| (ds.version, ds.entries, ds.deployments))
+            .collect();
+        assert_eq!(vec![(1, 1, 1), (2, 1, 1)], versions);
+
+        // The ENS rainbow table is available as the `ens` dataset
+        let hash = "0x7f0c1b04d1a4926f9c635a030eeb611d4c26e5e73291b32a1c7a4ac56935b5b3";
+        test_store::insert_ens_name(hash, "dealdrafts");
+        let value = lookup.lookup(&early, "ens", hash).unwrap();
+        assert_eq!(Some("dealdrafts".to_string()), value);
+
+        test_store::remove_aux_dataset(DATASET);
+    })
+}
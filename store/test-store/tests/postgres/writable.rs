@@ -286,3 +286,130 @@ fn restart() {
         writable.flush().await.unwrap();
     })
 }
+
+#[test]
+fn recent_entity_keys() {
+    run_test(|store, writable, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+
+        for (block, id) in [(1, "1"), (2, "2"), (3, "3"), (4, "1")] {
+            let entity_op = EntityOperation::Set {
+                key: count_key(id),
+                data: entity! { TEST_SUBGRAPH_SCHEMA => id: id, count: block as i32 },
+            };
+            transact_entity_operations(
+                &subgraph_store,
+                &deployment,
+                block_pointer(block),
+                vec![entity_op],
+            )
+            .await
+            .unwrap();
+        }
+        writable.flush().await.unwrap();
+
+        // The update of `1` in block 4 makes it the most recent entity
+        let keys = writable.recent_entity_keys(&COUNTER_TYPE, 2).await.unwrap();
+        assert_eq!(vec![count_key("1"), count_key("3")], keys);
+
+        let keys = writable
+            .recent_entity_keys(&COUNTER_TYPE, 10)
+            .await
+            .unwrap();
+        assert_eq!(3, keys.len());
+    })
+}
+
+#[test]
+fn entity_cache_checkpoint() {
+    run_test(|_, writable, _| async move {
+        assert!(writable.entity_cache_checkpoint().unwrap().is_empty());
+
+        let keys = vec![count_key("1"), count_key("2")];
+        writable
+            .checkpoint_entity_cache(keys.clone())
+            .await
+            .unwrap();
+        let mut restored = writable.entity_cache_checkpoint().unwrap();
+        restored.sort();
+        assert_eq!(keys, restored);
+
+        // A new checkpoint replaces the previous one
+        writable
+            .checkpoint_entity_cache(vec![count_key("3")])
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![count_key("3")],
+            writable.entity_cache_checkpoint().unwrap()
+        );
+    })
+}
+
+async fn index_count(store: &DieselSubgraphStore, deployment: &DeploymentLocator) -> usize {
+    store
+        .indexes_for_entity(deployment, COUNTER)
+        .await
+        .unwrap()
+        .len()
+}
+
+#[test]
+fn defer_and_restore_indexes() {
+    run_test(|store, writable, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        let before = index_count(&subgraph_store, &deployment).await;
+
+        writable.defer_indexes().await.unwrap();
+        assert!(index_count(&subgraph_store, &deployment).await < before);
+
+        // Indexes are recreated in the background; we are only told that
+        // they have all been recreated once that is actually the case
+        let mut restored = false;
+        for _ in 0..100 {
+            if writable.restore_deferred_indexes().await.unwrap() {
+                restored = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert!(restored);
+        assert_eq!(before, index_count(&subgraph_store, &deployment).await);
+
+        // Once everything is restored, there is nothing left to do
+        assert!(writable.restore_deferred_indexes().await.unwrap());
+    })
+}
+
+#[test]
+fn deployment_stats() {
+    run_test(|store, _writable, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        // Three versions of the same entity
+        for count in 1..=3 {
+            insert_count(&subgraph_store, &deployment, count).await;
+        }
+        flush(&deployment).await.unwrap();
+        // The counts are estimates that are only updated by `analyze`
+        subgraph_store.analyze(&deployment, Some(COUNTER)).unwrap();
+
+        let stats = subgraph_store
+            .deployment_stats(&deployment.hash)
+            .await
+            .unwrap();
+        assert!(stats
+            .windows(2)
+            .all(|w| w[0].entity_type <= w[1].entity_type));
+
+        let counter = stats
+            .iter()
+            .find(|s| s.entity_type == COUNTER)
+            .expect("there are stats for Counter");
+        assert_eq!("counter", counter.table);
+        assert_eq!(1, counter.entities);
+        assert_eq!(3, counter.versions);
+        assert!(counter.table_size > 0);
+        assert!(counter.index_size > 0);
+        assert_eq!(None, counter.last_pruned_block);
+    })
+}
@@ -45,7 +45,7 @@ use graph::schema::InputSchema;
 use graph_chain_ethereum::chain::RuntimeAdapterBuilder;
 use graph_chain_ethereum::network::EthereumNetworkAdapters;
 use graph_chain_ethereum::Chain;
-use graph_core::polling_monitor::{arweave_service, ipfs_service};
+use graph_core::polling_monitor::{arweave_service, https_service, ipfs_service};
 use graph_core::{
     SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider, SubgraphInstanceManager,
     SubgraphRegistrar as IpfsSubgraphRegistrar, SubgraphTriggerProcessor,
@@ -468,10 +468,11 @@ pub async fn setup<C: Blockchain>(
         Default::default(),
     ));
     let ipfs_service = ipfs_service(
-        ipfs.cheap_clone(),
+        vec![ipfs.cheap_clone()],
         env_vars.mappings.max_ipfs_file_bytes,
         env_vars.mappings.ipfs_timeout,
         env_vars.mappings.ipfs_request_limit,
+        None,
     );
 
     let arweave_resolver = Arc::new(ArweaveClient::default());
@@ -483,6 +484,11 @@ pub async fn setup<C: Blockchain>(
             n => FileSizeLimit::MaxBytes(n as u64),
         },
     );
+    let https_service = https_service(
+        env_vars.mappings.max_ipfs_file_bytes,
+        env_vars.mappings.ipfs_timeout,
+        env_vars.mappings.ipfs_request_limit,
+    );
     let sg_count = Arc::new(SubgraphCountMetric::new(mock_registry.cheap_clone()));
 
     let blockchain_map = Arc::new(blockchain_map);
@@ -496,6 +502,7 @@ pub async fn setup<C: Blockchain>(
         link_resolver.cheap_clone(),
         ipfs_service,
         arweave_service,
+        https_service,
         static_filters,
     );
 
@@ -508,6 +515,7 @@ pub async fn setup<C: Blockchain>(
         subscription_manager.clone(),
         Arc::new(load_manager),
         mock_registry.clone(),
+        None,
     ));
 
     let indexing_status_service = Arc::new(IndexNodeService::new(
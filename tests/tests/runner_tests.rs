@@ -485,6 +485,105 @@ async fn derived_loaders() {
     );
 }
 
+/// Index `blocks` with the subgraph in `test_info` once with the triggers
+/// of a block running one after the other, and once with them running
+/// concurrently, and check that both runs produce the same proofs of
+/// indexing and the same result for `query`
+async fn check_concurrent_triggers(
+    stores: &Stores,
+    test_info: &TestInfo,
+    blocks: Vec<BlockWithTriggers<graph_chain_ethereum::Chain>>,
+    query: &str,
+) {
+    let stop_block = blocks.last().unwrap().block.ptr();
+    let ptrs: Vec<_> = blocks.iter().map(|block| block.block.ptr()).collect();
+    let chain = chain(&test_info.test_name, blocks, stores, None).await;
+
+    let mut runs = Vec::new();
+    for concurrency in [1, 4] {
+        let mut env_vars = EnvVars::default();
+        env_vars.mappings.trigger_concurrency = concurrency;
+        let ctx = fixture::setup(test_info, stores, &chain, None, Some(env_vars)).await;
+        ctx.start_and_sync_to(stop_block.clone()).await;
+        ctx.provider.stop(ctx.deployment.clone()).await.unwrap();
+
+        let mut pois = Vec::new();
+        for ptr in &ptrs {
+            let poi = ctx
+                .store
+                .get_proof_of_indexing(&ctx.deployment.hash, &None, ptr.clone())
+                .await
+                .unwrap();
+            pois.push(poi);
+        }
+        let result = ctx.query(query).await.unwrap();
+        runs.push((pois, result));
+    }
+
+    let (concurrent_pois, concurrent_result) = runs.pop().unwrap();
+    let (sequential_pois, sequential_result) = runs.pop().unwrap();
+    assert!(sequential_pois.iter().all(|poi| poi.is_some()));
+    assert_eq!(sequential_pois, concurrent_pois);
+    assert_eq!(sequential_result, concurrent_result);
+}
+
+#[tokio::test]
+async fn concurrent_triggers_without_conflicts() {
+    let RunnerTestRecipe { stores, test_info } =
+        RunnerTestRecipe::new("concurrent_triggers_without_conflicts", "end-block").await;
+
+    // The handlers for the block and its events change different entities
+    // without reading any; the event handlers all write the same entity
+    let blocks = {
+        let block_0 = genesis();
+        let mut block_1 = empty_block(block_0.ptr(), test_ptr(1));
+        push_test_log(&mut block_1, "1_0");
+        push_test_log(&mut block_1, "1_1");
+        push_test_log(&mut block_1, "1_2");
+        let mut block_2 = empty_block(block_1.ptr(), test_ptr(2));
+        push_test_log(&mut block_2, "2_0");
+        push_test_log(&mut block_2, "2_1");
+        vec![block_0, block_1, block_2]
+    };
+
+    check_concurrent_triggers(
+        &stores,
+        &test_info,
+        blocks,
+        "{ testEventEntities(orderBy: id) { id command blockNumber } \
+           blocks(orderBy: number) { number hash } }",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn concurrent_triggers_with_conflicts() {
+    let RunnerTestRecipe { stores, test_info } =
+        RunnerTestRecipe::new("concurrent_triggers_with_conflicts", "derived-loaders").await;
+
+    // The handler for `1_1` reads and changes the entities that the handler
+    // for `1_0` created, and both load derived fields; the mappings assert
+    // that they see the changes of the handlers before them
+    let blocks = {
+        let block_0 = genesis();
+        let mut block_1 = empty_block(block_0.ptr(), test_ptr(1));
+        push_test_log(&mut block_1, "1_0");
+        push_test_log(&mut block_1, "1_1");
+        let mut block_2 = empty_block(block_1.ptr(), test_ptr(2));
+        push_test_log(&mut block_2, "2_0");
+        vec![block_0, block_1, block_2]
+    };
+
+    check_concurrent_triggers(
+        &stores,
+        &test_info,
+        blocks,
+        "{ testResults(orderBy: id) { id barDerived { id value value2 } \
+           bBarDerived { id value value2 } } }",
+    )
+    .await;
+}
+
 // This PR https://github.com/graphprotocol/graph-node/pull/4787
 // changed the way TriggerFilters were built
 // A bug was introduced in the PR which resulted in filters for substreams not being included